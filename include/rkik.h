@@ -0,0 +1,175 @@
+#ifndef RKIK_H
+#define RKIK_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Results of a comparison, one per target in input order.
+typedef struct RkikCompare RkikCompare;
+
+// Outcome of one probe: a measurement or an error.
+typedef struct RkikResult RkikResult;
+
+// Probe settings, see [`QueryOptions`]. Start from [`rkik_options_default`].
+typedef struct RkikOptions {
+  uint32_t timeout_ms;
+  bool ipv6_only;
+  bool nts;
+  uint16_t nts_port;
+  // NTP port used when a target names none.
+  uint16_t port;
+  uint32_t retries;
+} RkikOptions;
+
+// Default probe settings: 5 s timeout, IPv4, plain NTP, ports 123 and 4460, no retry.
+struct RkikOptions rkik_options_default(void);
+
+// Query one target (`host`, `host:port`, `nts://host`...).
+//
+// Never returns NULL; failures are reported by [`rkik_result_error`].
+//
+// # Safety
+//
+// `target` must be a NUL-terminated string; `options` NULL or a valid
+// pointer.
+struct RkikResult *rkik_query(const char *target, const struct RkikOptions *options);
+
+// Query `count` targets concurrently.
+//
+// Never returns NULL; when fewer than two targets answer the comparison
+// has no entries and [`rkik_compare_error`] says why.
+//
+// # Safety
+//
+// `targets` must point to `count` NUL-terminated strings; `options` NULL
+// or a valid pointer.
+struct RkikCompare *rkik_compare(const char *const *targets,
+                                 size_t count,
+                                 const struct RkikOptions *options);
+
+// Number of entries of a comparison.
+//
+// # Safety
+//
+// `cmp` must be NULL or returned by [`rkik_compare`] and not yet freed.
+size_t rkik_compare_len(const struct RkikCompare *cmp);
+
+// Entry `index` of a comparison, borrowed: do not free it. NULL when out of range.
+//
+// # Safety
+//
+// `cmp` must be NULL or returned by [`rkik_compare`] and not yet freed.
+const struct RkikResult *rkik_compare_get(const struct RkikCompare *cmp, size_t index);
+
+// Why the whole comparison failed, or NULL.
+//
+// # Safety
+//
+// `cmp` must be NULL or returned by [`rkik_compare`] and not yet freed.
+const char *rkik_compare_error(const struct RkikCompare *cmp);
+
+// Release a comparison and all its entries.
+//
+// # Safety
+//
+// `cmp` must be NULL or returned by [`rkik_compare`], and is invalid afterwards.
+void rkik_compare_free(struct RkikCompare *cmp);
+
+// True when the probe succeeded.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+bool rkik_result_ok(const struct RkikResult *r);
+
+// Error message of a failed probe (`"<target> - <kind>: <message>"`), or NULL.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+const char *rkik_result_error(const struct RkikResult *r);
+
+// Stable error kind of a failed probe (`"timeout"`, `"dns"`...), or NULL.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+const char *rkik_result_error_kind(const struct RkikResult *r);
+
+// Exit code the rkik CLI would use for this failure, 0 on success.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+int32_t rkik_result_exit_code(const struct RkikResult *r);
+
+// Target as given to [`rkik_query`] or [`rkik_compare`].
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+const char *rkik_result_target(const struct RkikResult *r);
+
+// Address that answered, empty on failure.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+const char *rkik_result_ip(const struct RkikResult *r);
+
+// Clock offset in ms, NaN on failure.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+double rkik_result_offset_ms(const struct RkikResult *r);
+
+// Round-trip delay in ms, NaN on failure.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+double rkik_result_rtt_ms(const struct RkikResult *r);
+
+// Stratum of the server, 0 on failure.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+uint8_t rkik_result_stratum(const struct RkikResult *r);
+
+// Reference identifier, empty on failure.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+const char *rkik_result_ref_id(const struct RkikResult *r);
+
+// Unix time of the measurement, 0 on failure.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+int64_t rkik_result_timestamp(const struct RkikResult *r);
+
+// True when the answer was authenticated with NTS.
+//
+// # Safety
+//
+// `r` must be NULL or a live result.
+bool rkik_result_authenticated(const struct RkikResult *r);
+
+// Release a result returned by [`rkik_query`]; entries of a comparison are
+// released with it instead.
+//
+// # Safety
+//
+// `r` must be NULL or returned by [`rkik_query`], and is invalid afterwards.
+void rkik_result_free(struct RkikResult *r);
+
+#endif  /* RKIK_H */
@@ -0,0 +1,602 @@
+//! Client for chronyd's command protocol, the one `chronyc tracking` and
+//! `chronyc sources` speak, to read what the resident chronyd believes.
+//!
+//! chronyd answers on its Unix socket (`/run/chrony/chronyd.sock`, open to
+//! root and the chrony group) and, for monitoring commands, on UDP
+//! `127.0.0.1:323`. [`ChronyClient::new`] tries them in that order.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "json")]
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+pub use crate::domain::ntp::Leap;
+use crate::error::RkikError;
+
+const PROTO_VERSION: u8 = 6;
+const PKT_TYPE_REQUEST: u8 = 1;
+const PKT_TYPE_REPLY: u8 = 2;
+const REQ_N_SOURCES: u16 = 14;
+const REQ_SOURCE_DATA: u16 = 15;
+const REQ_TRACKING: u16 = 33;
+const RPY_N_SOURCES: u16 = 2;
+const RPY_SOURCE_DATA: u16 = 3;
+const RPY_TRACKING: u16 = 5;
+const REQUEST_HEADER: usize = 20;
+const REPLY_HEADER: usize = 28;
+const TRACKING_LEN: usize = 76;
+const SOURCE_DATA_LEN: usize = 48;
+const IPADDR_INET4: u16 = 1;
+const IPADDR_INET6: u16 = 2;
+/// `tv_sec_high` of a timestamp that fits in 32 bits.
+const TV_NOHIGHSEC: u32 = 0x7fff_ffff;
+
+/// Where chronyd listens for commands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    #[cfg(unix)]
+    Unix(PathBuf),
+    Udp(SocketAddr),
+}
+
+/// chronyd's `tracking` report: its reference and its estimate of the
+/// system clock error.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Tracking {
+    /// Reference address, or refclock name such as `GPS`.
+    pub reference: String,
+    pub ref_id: String,
+    pub stratum: u16,
+    pub leap: Leap,
+    /// When chronyd last updated the clock from its reference.
+    pub ref_time: DateTime<Utc>,
+    /// True time minus system time as chronyd estimates it (ms): positive
+    /// when the system clock is behind, the sign rkik uses for server offsets.
+    pub offset_ms: f64,
+    /// System clock offset at the last update (ms, positive when it was behind).
+    pub last_offset_ms: f64,
+    pub rms_offset_ms: f64,
+    /// Frequency error being corrected (ppm, positive when the clock runs fast).
+    pub freq_ppm: f64,
+    pub resid_freq_ppm: f64,
+    pub skew_ppm: f64,
+    pub root_delay_ms: f64,
+    pub root_dispersion_ms: f64,
+    pub update_interval_s: f64,
+}
+
+/// One line of chronyd's `sources` report.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Source {
+    /// Server address, or refclock name.
+    pub address: String,
+    pub mode: SourceMode,
+    pub state: SourceState,
+    pub stratum: u16,
+    /// log2 of the polling interval in seconds.
+    pub poll: i16,
+    /// Reachability register of the last 8 polls.
+    pub reach: u8,
+    /// Seconds since the last sample.
+    pub last_rx_s: u32,
+    /// Source minus system clock at the last sample, after chronyd's
+    /// corrections since then (ms, positive when the source is ahead).
+    pub offset_ms: f64,
+    /// The same, as measured.
+    pub measured_offset_ms: f64,
+    /// Error bound of the measurement (ms).
+    pub error_ms: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum SourceMode {
+    Server,
+    Peer,
+    Refclock,
+}
+
+/// chronyd's selection state of a source (`chronyc sources` S column).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum SourceState {
+    Selected,
+    NonSelectable,
+    Falseticker,
+    Jittery,
+    Unselected,
+    Selectable,
+}
+
+impl SourceState {
+    /// The `chronyc sources` symbol.
+    pub fn symbol(self) -> char {
+        match self {
+            SourceState::Selected => '*',
+            SourceState::NonSelectable => '?',
+            SourceState::Falseticker => 'x',
+            SourceState::Jittery => '~',
+            SourceState::Unselected => '-',
+            SourceState::Selectable => '+',
+        }
+    }
+}
+
+/// Sends monitoring requests to chronyd.
+#[derive(Debug, Clone)]
+pub struct ChronyClient {
+    endpoints: Vec<Endpoint>,
+    timeout: Duration,
+}
+
+impl Default for ChronyClient {
+    fn default() -> Self {
+        let mut endpoints = Vec::new();
+        #[cfg(unix)]
+        endpoints.extend(
+            ["/run/chrony/chronyd.sock", "/var/run/chrony/chronyd.sock"]
+                .into_iter()
+                .map(|p| Endpoint::Unix(p.into())),
+        );
+        endpoints.push(Endpoint::Udp((Ipv4Addr::LOCALHOST, 323).into()));
+        endpoints.push(Endpoint::Udp((Ipv6Addr::LOCALHOST, 323).into()));
+        Self {
+            endpoints,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ChronyClient {
+    /// Client for the local chronyd, over its Unix socket when readable, else UDP.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Client for chronyd at `endpoint` only.
+    pub fn with_endpoint(endpoint: Endpoint) -> Self {
+        Self {
+            endpoints: vec![endpoint],
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait for each reply (default 1 s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn tracking(&self) -> Result<Tracking, RkikError> {
+        let data = self
+            .request(REQ_TRACKING, &[], RPY_TRACKING, TRACKING_LEN)
+            .await?;
+        Ok(parse_tracking(&data))
+    }
+
+    /// Every source chronyd knows, in its own order.
+    pub async fn sources(&self) -> Result<Vec<Source>, RkikError> {
+        let n = self.request(REQ_N_SOURCES, &[], RPY_N_SOURCES, 4).await?;
+        let n = u32::from_be_bytes([n[0], n[1], n[2], n[3]]);
+        let mut sources = Vec::with_capacity(n as usize);
+        for index in 0..n {
+            let data = self
+                .request(
+                    REQ_SOURCE_DATA,
+                    &index.to_be_bytes(),
+                    RPY_SOURCE_DATA,
+                    SOURCE_DATA_LEN,
+                )
+                .await?;
+            sources.push(parse_source(&data));
+        }
+        Ok(sources)
+    }
+
+    /// Send `command` and return the reply data, trying each endpoint until
+    /// one answers.
+    async fn request(
+        &self,
+        command: u16,
+        body: &[u8],
+        reply: u16,
+        reply_len: usize,
+    ) -> Result<Vec<u8>, RkikError> {
+        let sequence: u32 = rand_sequence();
+        // chronyd ignores requests shorter than their reply.
+        let mut packet = vec![0u8; (REQUEST_HEADER + body.len()).max(REPLY_HEADER + reply_len)];
+        packet[0] = PROTO_VERSION;
+        packet[1] = PKT_TYPE_REQUEST;
+        packet[4..6].copy_from_slice(&command.to_be_bytes());
+        packet[8..12].copy_from_slice(&sequence.to_be_bytes());
+        packet[REQUEST_HEADER..REQUEST_HEADER + body.len()].copy_from_slice(body);
+
+        let mut last_err = RkikError::Network("no chronyd endpoint configured".into());
+        for endpoint in &self.endpoints {
+            let answer = tokio::time::timeout(self.timeout, exchange(endpoint, &packet)).await;
+            let bytes = match answer {
+                Ok(Ok(bytes)) => bytes,
+                Ok(Err(e)) => {
+                    last_err = e;
+                    continue;
+                }
+                Err(_) => {
+                    last_err = RkikError::Timeout;
+                    continue;
+                }
+            };
+            return check_reply(&bytes, command, sequence, reply, reply_len);
+        }
+        Err(match last_err {
+            RkikError::Timeout => RkikError::Timeout,
+            e => RkikError::Network(format!("chronyd not reachable: {}", e.message())),
+        })
+    }
+}
+
+async fn exchange(endpoint: &Endpoint, packet: &[u8]) -> Result<Vec<u8>, RkikError> {
+    let io = |e: std::io::Error| RkikError::Network(e.to_string());
+    let mut buf = vec![0u8; 1024];
+    let len = match endpoint {
+        #[cfg(unix)]
+        Endpoint::Unix(path) => {
+            let client = UnixClient::bind(path).map_err(io)?;
+            client.socket.connect(path).map_err(io)?;
+            client.socket.send(packet).await.map_err(io)?;
+            client.socket.recv(&mut buf).await.map_err(io)?
+        }
+        Endpoint::Udp(addr) => {
+            let bind: SocketAddr = match addr {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let socket = UdpSocket::bind(bind).await.map_err(io)?;
+            socket.connect(addr).await.map_err(io)?;
+            socket.send(packet).await.map_err(io)?;
+            socket.recv(&mut buf).await.map_err(io)?
+        }
+    };
+    buf.truncate(len);
+    Ok(buf)
+}
+
+/// A datagram socket bound next to chronyd's, which replies to the
+/// client's address (as `chronyc` does); removed on drop.
+#[cfg(unix)]
+struct UnixClient {
+    socket: tokio::net::UnixDatagram,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixClient {
+    fn bind(server: &Path) -> std::io::Result<Self> {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = server.parent().unwrap_or(Path::new("/"));
+        let path = dir.join(format!(
+            "rkik.{}.{}.sock",
+            std::process::id(),
+            rand_sequence()
+        ));
+        let socket = tokio::net::UnixDatagram::bind(&path)?;
+        let client = Self { socket, path };
+        // chronyd runs unprivileged and must be able to write to it.
+        std::fs::set_permissions(&client.path, std::fs::Permissions::from_mode(0o666))?;
+        Ok(client)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixClient {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn check_reply(
+    bytes: &[u8],
+    command: u16,
+    sequence: u32,
+    reply: u16,
+    reply_len: usize,
+) -> Result<Vec<u8>, RkikError> {
+    let bad = |what: &str| RkikError::Protocol(format!("invalid chronyd reply: {what}"));
+    if bytes.len() < REPLY_HEADER {
+        return Err(bad("too short"));
+    }
+    let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+    if bytes[1] != PKT_TYPE_REPLY
+        || u16_at(4) != command
+        || u32::from_be_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]) != sequence
+    {
+        return Err(bad("does not match the request"));
+    }
+    if bytes[0] != PROTO_VERSION {
+        return Err(RkikError::Protocol(format!(
+            "chronyd speaks command protocol version {}, rkik version {PROTO_VERSION}",
+            bytes[0]
+        )));
+    }
+    match u16_at(8) {
+        0 => {}
+        2 => return Err(RkikError::Protocol("chronyd: not authorised".into())),
+        status => {
+            return Err(RkikError::Protocol(format!(
+                "chronyd rejected the request (status {status})"
+            )));
+        }
+    }
+    if u16_at(6) != reply || bytes.len() < REPLY_HEADER + reply_len {
+        return Err(bad("unexpected reply type"));
+    }
+    Ok(bytes[REPLY_HEADER..REPLY_HEADER + reply_len].to_vec())
+}
+
+pub(crate) fn rand_sequence() -> u32 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut h = std::collections::hash_map::RandomState::new().build_hasher();
+    h.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos()),
+    );
+    h.finish() as u32
+}
+
+fn u16_at(data: &[u8], i: usize) -> u16 {
+    u16::from_be_bytes([data[i], data[i + 1]])
+}
+
+fn u32_at(data: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]])
+}
+
+/// chronyd's 32-bit float: 7-bit exponent, 25-bit coefficient, both signed.
+fn float_at(data: &[u8], i: usize) -> f64 {
+    let x = u32_at(data, i);
+    let mut exp = (x >> 25) as i32;
+    if exp >= 1 << 6 {
+        exp -= 1 << 7;
+    }
+    let mut coef = (x & ((1 << 25) - 1)) as i32;
+    if coef >= 1 << 24 {
+        coef -= 1 << 25;
+    }
+    coef as f64 * 2f64.powi(exp - 25)
+}
+
+/// `IPAddr`: 16 address bytes, family, padding.
+fn ip_at(data: &[u8], i: usize) -> Option<IpAddr> {
+    match u16_at(data, i + 16) {
+        IPADDR_INET4 => Some(IpAddr::from([
+            data[i],
+            data[i + 1],
+            data[i + 2],
+            data[i + 3],
+        ])),
+        IPADDR_INET6 => {
+            let octets: [u8; 16] = data[i..i + 16].try_into().ok()?;
+            Some(IpAddr::from(octets))
+        }
+        _ => None,
+    }
+}
+
+/// A reference id as chronyc prints names for it: ASCII when printable, else hex.
+fn refid_name(id: u32) -> String {
+    let bytes = id.to_be_bytes();
+    let text: String = bytes
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| b as char)
+        .collect();
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_graphic()) {
+        text
+    } else {
+        format!("{id:08X}")
+    }
+}
+
+fn parse_tracking(d: &[u8]) -> Tracking {
+    let ref_id = u32_at(d, 0);
+    let secs = match (u32_at(d, 28), u32_at(d, 32)) {
+        (TV_NOHIGHSEC, low) => low as i64,
+        (high, low) => ((high as i64) << 32) | low as i64,
+    };
+    Tracking {
+        reference: ip_at(d, 4).map_or_else(|| refid_name(ref_id), |ip| ip.to_string()),
+        ref_id: format!("{ref_id:08X}"),
+        stratum: u16_at(d, 24),
+        leap: match u16_at(d, 26) {
+            0 => Leap::Normal,
+            1 => Leap::InsertSecond,
+            2 => Leap::DeleteSecond,
+            _ => Leap::Unsynchronised,
+        },
+        ref_time: DateTime::from_timestamp(secs, u32_at(d, 36)).unwrap_or_default(),
+        offset_ms: float_at(d, 40) * 1000.0,
+        last_offset_ms: -float_at(d, 44) * 1000.0,
+        rms_offset_ms: float_at(d, 48) * 1000.0,
+        freq_ppm: -float_at(d, 52),
+        resid_freq_ppm: float_at(d, 56),
+        skew_ppm: float_at(d, 60),
+        root_delay_ms: float_at(d, 64) * 1000.0,
+        root_dispersion_ms: float_at(d, 68) * 1000.0,
+        update_interval_s: float_at(d, 72),
+    }
+}
+
+fn parse_source(d: &[u8]) -> Source {
+    let mode = match u16_at(d, 26) {
+        0 => SourceMode::Server,
+        1 => SourceMode::Peer,
+        _ => SourceMode::Refclock,
+    };
+    let address = match (mode, ip_at(d, 0)) {
+        (SourceMode::Refclock, _) | (_, None) => refid_name(u32_at(d, 0)),
+        (_, Some(ip)) => ip.to_string(),
+    };
+    Source {
+        address,
+        mode,
+        state: match u16_at(d, 24) {
+            0 => SourceState::Selected,
+            1 => SourceState::NonSelectable,
+            2 => SourceState::Falseticker,
+            3 => SourceState::Jittery,
+            4 => SourceState::Unselected,
+            _ => SourceState::Selectable,
+        },
+        stratum: u16_at(d, 22),
+        poll: u16_at(d, 20) as i16,
+        reach: u16_at(d, 30) as u8,
+        last_rx_s: u32_at(d, 32),
+        // chronyd counts local minus source; rkik counts source minus local.
+        offset_ms: -float_at(d, 40) * 1000.0,
+        measured_offset_ms: -float_at(d, 36) * 1000.0,
+        error_ms: float_at(d, 44) * 1000.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// chronyd's `UTI_FloatHostToNetwork`.
+    fn float(x: f64) -> [u8; 4] {
+        if x == 0.0 {
+            return [0; 4];
+        }
+        let mut exp = x.abs().log2() as i32 + 1;
+        let mut coef = (x.abs() * 2f64.powi(25 - exp) + 0.5) as i64;
+        while coef > (1 << 24) - 1 {
+            coef >>= 1;
+            exp += 1;
+        }
+        if x < 0.0 {
+            coef = -coef;
+        }
+        ((((exp as u32) & 0x7f) << 25) | (coef as u32 & 0x1ff_ffff)).to_be_bytes()
+    }
+
+    fn tracking_data() -> Vec<u8> {
+        let mut d = vec![0u8; TRACKING_LEN];
+        d[0..4].copy_from_slice(&[192, 0, 2, 1]);
+        d[4..8].copy_from_slice(&[192, 0, 2, 1]);
+        d[20..22].copy_from_slice(&IPADDR_INET4.to_be_bytes());
+        d[24..26].copy_from_slice(&3u16.to_be_bytes());
+        d[28..32].copy_from_slice(&TV_NOHIGHSEC.to_be_bytes());
+        d[32..36].copy_from_slice(&1_700_000_000u32.to_be_bytes());
+        d[40..44].copy_from_slice(&float(0.000_25));
+        d[44..48].copy_from_slice(&float(-0.000_1));
+        d[52..56].copy_from_slice(&float(12.5));
+        d[64..68].copy_from_slice(&float(0.01));
+        d[72..76].copy_from_slice(&float(64.0));
+        d
+    }
+
+    fn source_data(index: u32) -> Vec<u8> {
+        let mut d = vec![0u8; SOURCE_DATA_LEN];
+        if index == 0 {
+            d[0..4].copy_from_slice(&[192, 0, 2, 1]);
+            d[16..18].copy_from_slice(&IPADDR_INET4.to_be_bytes());
+            d[24..26].copy_from_slice(&0u16.to_be_bytes());
+        } else {
+            d[0..4].copy_from_slice(b"GPS\0");
+            d[16..18].copy_from_slice(&IPADDR_INET4.to_be_bytes());
+            d[24..26].copy_from_slice(&1u16.to_be_bytes());
+            d[26..28].copy_from_slice(&2u16.to_be_bytes());
+        }
+        d[20..22].copy_from_slice(&6i16.to_be_bytes());
+        d[22..24].copy_from_slice(&2u16.to_be_bytes());
+        d[30..32].copy_from_slice(&0o377u16.to_be_bytes());
+        d[32..36].copy_from_slice(&34u32.to_be_bytes());
+        d[36..40].copy_from_slice(&float(0.002));
+        d[40..44].copy_from_slice(&float(0.001_5));
+        d[44..48].copy_from_slice(&float(0.000_5));
+        d
+    }
+
+    /// Answer chronyd requests on a local UDP socket.
+    async fn fake_chronyd() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                let req = &buf[..len];
+                let command = u16_at(req, 4);
+                let (reply, data) = match command {
+                    REQ_TRACKING => (RPY_TRACKING, tracking_data()),
+                    REQ_N_SOURCES => (RPY_N_SOURCES, 2u32.to_be_bytes().to_vec()),
+                    REQ_SOURCE_DATA => (RPY_SOURCE_DATA, source_data(u32_at(req, 20))),
+                    _ => continue,
+                };
+                if len < REPLY_HEADER + data.len() {
+                    continue;
+                }
+                let mut out = vec![0u8; REPLY_HEADER];
+                out[0] = PROTO_VERSION;
+                out[1] = PKT_TYPE_REPLY;
+                out[4..6].copy_from_slice(&command.to_be_bytes());
+                out[6..8].copy_from_slice(&reply.to_be_bytes());
+                out[16..20].copy_from_slice(&req[8..12]);
+                out.extend_from_slice(&data);
+                socket.send_to(&out, peer).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn floats_decode_like_chronyc() {
+        for x in [0.0, 1.0, -1.0, 0.000_123_4, -42.5, 1e-9] {
+            let got = float_at(&float(x), 0);
+            assert!((got - x).abs() <= x.abs() * 1e-7, "{x} decoded as {got}");
+        }
+    }
+
+    #[tokio::test]
+    async fn tracking_and_sources_from_chronyd() {
+        let client = ChronyClient::with_endpoint(Endpoint::Udp(fake_chronyd().await))
+            .timeout(Duration::from_secs(2));
+
+        let t = client.tracking().await.unwrap();
+        assert_eq!(t.reference, "192.0.2.1");
+        assert_eq!(t.ref_id, "C0000201");
+        assert_eq!((t.stratum, t.leap), (3, Leap::Normal));
+        assert_eq!(t.ref_time.timestamp(), 1_700_000_000);
+        // 0.25 ms of correction pending: the system clock is behind.
+        assert!((t.offset_ms - 0.25).abs() < 1e-6);
+        assert!((t.last_offset_ms - 0.1).abs() < 1e-6);
+        assert!((t.freq_ppm + 12.5).abs() < 1e-6);
+        assert!((t.root_delay_ms - 10.0).abs() < 1e-6);
+
+        let sources = client.sources().await.unwrap();
+        assert_eq!(sources.len(), 2);
+        let ntp = &sources[0];
+        assert_eq!(ntp.address, "192.0.2.1");
+        assert_eq!((ntp.state.symbol(), ntp.mode), ('*', SourceMode::Server));
+        assert_eq!((ntp.poll, ntp.reach, ntp.last_rx_s), (6, 0xff, 34));
+        assert!((ntp.offset_ms + 1.5).abs() < 1e-6);
+        assert!((ntp.measured_offset_ms + 2.0).abs() < 1e-6);
+        assert!((ntp.error_ms - 0.5).abs() < 1e-6);
+        assert_eq!(sources[1].address, "GPS");
+        assert_eq!(sources[1].mode, SourceMode::Refclock);
+    }
+
+    #[tokio::test]
+    async fn silent_endpoint_times_out() {
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = ChronyClient::with_endpoint(Endpoint::Udp(silent.local_addr().unwrap()))
+            .timeout(Duration::from_millis(50));
+        assert!(matches!(client.tracking().await, Err(RkikError::Timeout)));
+    }
+}
@@ -0,0 +1,285 @@
+//! One UDP socket for the NTP requests of a whole survey.
+//!
+//! [`UdpTransport`](super::transport::UdpTransport) connects a socket per
+//! exchange, so a compare round over thousands of pool servers holds
+//! thousands of descriptors. [`SharedSocket`] sends every request from a
+//! single unconnected socket per address family and hands each reply to
+//! the exchange that waits for it, matched by source address and origin
+//! timestamp. Replies matching no outstanding request are dropped and
+//! recorded like the spoofed-looking ones of
+//! [`ntp_client::exchange`](super::ntp_client::exchange).
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::channel::oneshot;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+use super::ntp_client::{self, Exchange, RejectReason, io_error};
+use super::ntp_packet::{NtpPacket, NtpTimestamp};
+use super::pcap;
+use super::reuse;
+use super::transport::{NtpSample, NtpTransport};
+use crate::error::RkikError;
+
+/// An outstanding request: the server and the transmit timestamp its reply
+/// must echo.
+type Key = (IpAddr, u16, NtpTimestamp);
+type Waiters = Mutex<HashMap<Key, oneshot::Sender<(NtpPacket, DateTime<Utc>)>>>;
+
+/// Pause after an unexpected receive error before reading again.
+const RECEIVE_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Sends all requests from one socket per address family, bound on first
+/// use and closed when this is dropped.
+#[derive(Debug, Default)]
+pub struct SharedSocket {
+    v4: Mutex<Option<Arc<Demux>>>,
+    v6: Mutex<Option<Arc<Demux>>>,
+}
+
+#[derive(Debug)]
+struct Demux {
+    socket: Arc<UdpSocket>,
+    local: SocketAddr,
+    waiters: Arc<Waiters>,
+    receiver: JoinHandle<()>,
+}
+
+impl Drop for Demux {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+impl SharedSocket {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send one client request to `addr` and wait for its reply, giving up
+    /// after `timeout`. Replies are validated as by
+    /// [`ntp_client::exchange`](super::ntp_client::exchange).
+    pub async fn exchange(
+        &self,
+        addr: SocketAddr,
+        timeout: Duration,
+    ) -> Result<Exchange, RkikError> {
+        let setup = std::time::Instant::now();
+        let demux = self.demux(addr.is_ipv6())?;
+        let setup_ms = setup.elapsed().as_secs_f64() * 1000.0;
+        let (tx, rx) = oneshot::channel();
+        let sent = Utc::now();
+        let mut request = NtpPacket::client(sent);
+        let key = {
+            let mut waiters = demux.waiters.lock().unwrap_or_else(|e| e.into_inner());
+            // Two requests to one server in the same instant must still be
+            // told apart: nudge the timestamp by its smallest unit.
+            while waiters.contains_key(&(addr.ip(), addr.port(), request.transmit)) {
+                request.transmit = NtpTimestamp(request.transmit.0.wrapping_add(1));
+            }
+            let key = (addr.ip(), addr.port(), request.transmit);
+            waiters.insert(key, tx);
+            key
+        };
+        let _waiting = Waiting {
+            waiters: &demux.waiters,
+            key,
+        };
+        let bytes = request.to_bytes();
+        demux.socket.send_to(&bytes, addr).await.map_err(io_error)?;
+        pcap::capture(sent, demux.local, addr, &bytes);
+        tracing::trace!(server = %addr, len = bytes.len(), packet = ?request, "request sent on the shared socket");
+
+        let (reply, received) = match tokio::time::timeout(timeout, rx).await {
+            Err(_) => return Err(RkikError::Timeout),
+            Ok(Err(_)) => return Err(RkikError::Network("shared socket closed".into())),
+            Ok(Ok(reply)) => reply,
+        };
+        tracing::trace!(server = %addr, packet = ?reply, "reply accepted");
+        ntp_client::check_reply(&reply)?;
+        Ok(Exchange {
+            local: demux.local,
+            server: addr,
+            request,
+            reply,
+            sent,
+            received,
+            rejected: 0,
+            setup_ms,
+        })
+    }
+
+    /// The socket of the family of the server, bound on first use.
+    fn demux(&self, ipv6: bool) -> Result<Arc<Demux>, RkikError> {
+        let slot = if ipv6 { &self.v6 } else { &self.v4 };
+        let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(demux) = slot.as_ref() {
+            return Ok(demux.clone());
+        }
+        let bind: SocketAddr = if ipv6 {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        } else {
+            ([0, 0, 0, 0], 0).into()
+        };
+        let socket = std::net::UdpSocket::bind(bind).map_err(io_error)?;
+        socket.set_nonblocking(true).map_err(io_error)?;
+        let socket = Arc::new(UdpSocket::from_std(socket).map_err(io_error)?);
+        let local = socket.local_addr().map_err(io_error)?;
+        reuse::SOCKETS_OPENED.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(%local, "shared UDP socket bound");
+        let waiters = Arc::new(Waiters::default());
+        let receiver = tokio::spawn(receive(socket.clone(), local, waiters.clone()));
+        let demux = Arc::new(Demux {
+            socket,
+            local,
+            waiters,
+            receiver,
+        });
+        *slot = Some(demux.clone());
+        Ok(demux)
+    }
+}
+
+impl NtpTransport for SharedSocket {
+    async fn query(
+        &self,
+        addr: SocketAddr,
+        _ipv6: bool,
+        timeout: Duration,
+    ) -> Result<NtpSample, RkikError> {
+        self.exchange(addr, timeout).await.map(NtpSample::from)
+    }
+}
+
+/// Forgets a request once its exchange ends, answered or not.
+struct Waiting<'a> {
+    waiters: &'a Waiters,
+    key: Key,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.waiters
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.key);
+    }
+}
+
+/// Hand every datagram on `socket` to the exchange it answers.
+async fn receive(socket: Arc<UdpSocket>, local: SocketAddr, waiters: Arc<Waiters>) {
+    let mut buf = [0u8; 1024];
+    loop {
+        let (len, from) = match socket.recv_from(&mut buf).await {
+            Ok(r) => r,
+            // An earlier request bounced (ICMP unreachable on some
+            // platforms); its exchange simply times out.
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::ConnectionRefused
+                ) =>
+            {
+                tracing::debug!(error = %e, "shared socket receive error");
+                continue;
+            }
+            // Anything else may well repeat at once: pause instead of spinning.
+            Err(e) => {
+                tracing::warn!(error = %e, "shared socket receive error");
+                tokio::time::sleep(RECEIVE_BACKOFF).await;
+                continue;
+            }
+        };
+        let received = Utc::now();
+        pcap::capture(received, from, local, &buf[..len]);
+        tracing::trace!(%from, len, "datagram received");
+        let from = canonical(from);
+        let Ok(reply) = NtpPacket::from_bytes(&buf[..len]) else {
+            tracing::debug!(%from, len, "not an NTP packet");
+            continue;
+        };
+        let mut waiters = waiters.lock().unwrap_or_else(|e| e.into_inner());
+        match waiters.remove(&(from.ip(), from.port(), reply.origin)) {
+            Some(tx) => {
+                tx.send((reply, received)).ok();
+            }
+            None => {
+                let asked = waiters
+                    .keys()
+                    .any(|(ip, port, _)| (*ip, *port) == (from.ip(), from.port()));
+                let reason = if asked {
+                    RejectReason::OriginMismatch
+                } else {
+                    RejectReason::WrongSource
+                };
+                ntp_client::reject(from, from, reason);
+            }
+        }
+    }
+}
+
+/// `from` as requests name it: IPv4-mapped IPv6 sources as plain IPv4.
+fn canonical(from: SocketAddr) -> SocketAddr {
+    match from {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(v4) => (v4, v6.port()).into(),
+            None => from,
+        },
+        SocketAddr::V4(_) => from,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::serve::{ServeOptions, bind, serve};
+    use tokio_util::sync::CancellationToken;
+
+    #[tokio::test]
+    async fn one_socket_answers_many_servers() {
+        let cancel = CancellationToken::new();
+        let mut servers = Vec::new();
+        for offset in [100.0, -100.0, 300.0] {
+            let socket = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+            servers.push((socket.local_addr().unwrap(), offset));
+            let options = ServeOptions::new().offset_ms(offset);
+            tokio::spawn(serve(socket, options, cancel.clone()));
+        }
+        let shared = SharedSocket::new();
+        let timeout = Duration::from_secs(2);
+        let exchanges = futures::future::join_all(
+            servers
+                .iter()
+                .flat_map(|(addr, _)| [*addr, *addr])
+                .map(|addr| shared.exchange(addr, timeout)),
+        )
+        .await;
+        let local = exchanges[0].as_ref().unwrap().local;
+        for (ex, (addr, offset)) in exchanges.iter().zip(servers.iter().flat_map(|s| [s, s])) {
+            let ex = ex.as_ref().unwrap();
+            assert_eq!((ex.server, ex.local), (*addr, local));
+            assert!((ex.offset_ms() - offset).abs() < 50.0, "{}", ex.offset_ms());
+        }
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn silent_servers_time_out() {
+        let socket = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let shared = SharedSocket::new();
+        let err = shared
+            .exchange(addr, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RkikError::Timeout));
+        let demux = shared.demux(false).unwrap();
+        assert!(demux.waiters.lock().unwrap().is_empty());
+    }
+}
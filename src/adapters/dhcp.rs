@@ -0,0 +1,163 @@
+//! NTP servers handed out by DHCP (option 42, DHCPv6 option 56), read from
+//! the lease files of the usual Linux DHCP clients.
+
+use std::path::{Path, PathBuf};
+
+/// NTP servers found in a lease, and the file they came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpServers {
+    pub source: PathBuf,
+    pub servers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum LeaseFormat {
+    Dhclient,
+    Networkd,
+    NetworkManager,
+}
+
+/// Directories holding lease files, and how their files are laid out.
+const LEASE_DIRS: [(&str, LeaseFormat); 5] = [
+    ("/run/systemd/netif/leases", LeaseFormat::Networkd),
+    ("/run/NetworkManager/devices", LeaseFormat::NetworkManager),
+    ("/var/lib/NetworkManager", LeaseFormat::Dhclient),
+    ("/var/lib/dhcp", LeaseFormat::Dhclient),
+    ("/var/lib/dhclient", LeaseFormat::Dhclient),
+];
+
+/// NTP servers from the most recently written lease that has any.
+///
+/// Looks at systemd-networkd state, NetworkManager device state and
+/// dhclient lease files (including NetworkManager's dhclient leases).
+/// Always `None` outside Linux.
+pub fn ntp_servers() -> Option<DhcpServers> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let mut leases: Vec<(std::time::SystemTime, PathBuf, LeaseFormat)> = Vec::new();
+    for (dir, format) in LEASE_DIRS {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if matches!(format, LeaseFormat::Dhclient) && !is_dhclient_lease(&path) {
+                continue;
+            }
+            if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                leases.push((modified, path, format));
+            }
+        }
+    }
+    leases.sort_by_key(|(modified, ..)| std::cmp::Reverse(*modified));
+    leases.into_iter().find_map(|(_, path, format)| {
+        let text = std::fs::read_to_string(&path).ok()?;
+        let servers = match format {
+            LeaseFormat::Dhclient => parse_dhclient(&text),
+            LeaseFormat::Networkd => parse_networkd(&text),
+            LeaseFormat::NetworkManager => parse_networkmanager(&text),
+        };
+        (!servers.is_empty()).then_some(DhcpServers {
+            source: path,
+            servers,
+        })
+    })
+}
+
+fn is_dhclient_lease(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "lease" || ext == "leases")
+}
+
+/// `option ntp-servers a, b;` (or `dhcp6.sntp-servers`) of the last `lease { }` block.
+fn parse_dhclient(text: &str) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let start = lines
+        .iter()
+        .rposition(|l| l.starts_with("lease") && l.ends_with('{'))
+        .unwrap_or(0);
+    let mut servers = Vec::new();
+    for line in &lines[start..] {
+        let Some(rest) = line
+            .strip_prefix("option ntp-servers ")
+            .or_else(|| line.strip_prefix("option dhcp6.sntp-servers "))
+        else {
+            continue;
+        };
+        push_list(&mut servers, rest.trim_end_matches(';'));
+    }
+    servers
+}
+
+/// `NTP=a b` in a systemd-networkd lease.
+fn parse_networkd(text: &str) -> Vec<String> {
+    let mut servers = Vec::new();
+    for line in text.lines() {
+        if let Some(rest) = line.trim().strip_prefix("NTP=") {
+            push_list(&mut servers, rest);
+        }
+    }
+    servers
+}
+
+/// `ntp_servers=a b` in the `[dhcp4]`/`[dhcp6]` sections of a NetworkManager device state file.
+fn parse_networkmanager(text: &str) -> Vec<String> {
+    let mut servers = Vec::new();
+    let mut in_dhcp = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_dhcp = line == "[dhcp4]" || line == "[dhcp6]";
+            continue;
+        }
+        if !in_dhcp {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=')
+            && matches!(
+                key.trim(),
+                "ntp_servers" | "option_ntp_servers" | "dhcp6_sntp_servers"
+            )
+        {
+            push_list(&mut servers, value);
+        }
+    }
+    servers
+}
+
+/// Append the comma or space separated servers of `list`, skipping duplicates.
+fn push_list(servers: &mut Vec<String>, list: &str) {
+    for server in list
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(|s| s.trim_matches('"'))
+        .filter(|s| !s.is_empty())
+    {
+        if !servers.iter().any(|s| s == server) {
+            servers.push(server.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dhclient_uses_the_last_lease() {
+        let text = "lease {\n  interface \"eth0\";\n  option ntp-servers 192.0.2.1;\n}\n\
+                    lease {\n  interface \"eth0\";\n  option ntp-servers 192.0.2.5, 192.0.2.6;\n  option dhcp-lease-time 600;\n  renew 4 2026/10/15 10:00:00;\n}\n";
+        assert_eq!(parse_dhclient(text), ["192.0.2.5", "192.0.2.6"]);
+        assert!(parse_dhclient("lease {\n  option routers 192.0.2.254;\n}\n").is_empty());
+    }
+
+    #[test]
+    fn networkd_and_networkmanager_state() {
+        let networkd =
+            "# This is private data. Do not parse.\nADDRESS=192.0.2.10\nNTP=192.0.2.1 192.0.2.2\n";
+        assert_eq!(parse_networkd(networkd), ["192.0.2.1", "192.0.2.2"]);
+
+        let nm = "[device]\nnm-managed=true\n[dhcp4]\nntp_servers=192.0.2.3 192.0.2.3\n[other]\nntp_servers=192.0.2.9\n";
+        assert_eq!(parse_networkmanager(nm), ["192.0.2.3"]);
+    }
+}
@@ -0,0 +1,55 @@
+//! Offline ASN and country lookups in MaxMind databases (GeoLite2 / GeoIP2 `.mmdb`).
+
+use std::net::IpAddr;
+use std::path::Path;
+
+use maxminddb::{Reader, geoip2};
+
+use crate::error::RkikError;
+
+/// An opened `.mmdb` file.
+pub struct GeoIpDb {
+    reader: Reader<Vec<u8>>,
+}
+
+/// What a database knows about one address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeoIpRecord {
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+    pub country: Option<String>,
+}
+
+impl GeoIpDb {
+    /// Load a database into memory. ASN, country and city databases are accepted.
+    pub fn open(path: &Path) -> Result<Self, RkikError> {
+        let reader = Reader::open_readfile(path)
+            .map_err(|e| RkikError::Other(format!("cannot open '{}': {e}", path.display())))?;
+        Ok(Self { reader })
+    }
+
+    /// Database edition, e.g. `GeoLite2-ASN`.
+    pub fn kind(&self) -> &str {
+        &self.reader.metadata().database_type
+    }
+
+    /// Look `ip` up; an address outside the database yields an empty record.
+    pub fn lookup(&self, ip: IpAddr) -> Result<GeoIpRecord, RkikError> {
+        let err = |e: maxminddb::MaxMindDbError| RkikError::Other(format!("GeoIP lookup: {e}"));
+        let result = self.reader.lookup(ip).map_err(err)?;
+        let mut record = GeoIpRecord::default();
+        if self.kind().contains("ASN") {
+            if let Some(asn) = result.decode::<geoip2::Asn>().map_err(err)? {
+                record.asn = asn.autonomous_system_number;
+                record.as_org = asn.autonomous_system_organization.map(str::to_string);
+            }
+        } else if let Some(country) = result.decode::<geoip2::Country>().map_err(err)? {
+            record.country = country
+                .country
+                .iso_code
+                .or(country.registered_country.iso_code)
+                .map(str::to_string);
+        }
+        Ok(record)
+    }
+}
@@ -0,0 +1,335 @@
+//! Clock offset from the `Date` header of an HTTP(S) response.
+//!
+//! A fallback for networks where UDP 123 is blocked. The header only has a
+//! one-second resolution, so a sample is good to about half a second plus
+//! half the round trip: enough to spot a clock that is minutes off, not to
+//! judge an NTP server.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "json")]
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::adapters::resolver;
+use crate::error::RkikError;
+
+/// The `Date` header is truncated to the second.
+const DATE_RESOLUTION_MS: f64 = 1000.0;
+/// Response headers larger than this are not worth waiting for.
+const MAX_HEAD: usize = 16 * 1024;
+
+/// An `http://` or `https://` URL; a bare host means `https://host/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl FromStr for HttpUrl {
+    type Err = RkikError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tls, rest) = match s.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") => (true, rest),
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => (false, rest),
+            Some((scheme, _)) => {
+                return Err(RkikError::Other(format!(
+                    "unsupported scheme '{scheme}' (use http or https)"
+                )));
+            }
+            None => (true, s),
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('/') => (&rest[..i], rest[i..].to_string()),
+            Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+            None => (rest, "/".to_string()),
+        };
+        let default_port = if tls { 443 } else { 80 };
+        let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+            let (host, tail) = v6
+                .split_once(']')
+                .ok_or_else(|| RkikError::Other(format!("invalid URL '{s}'")))?;
+            let port = match tail.strip_prefix(':') {
+                Some(p) => p.parse().ok(),
+                None if tail.is_empty() => Some(default_port),
+                None => None,
+            };
+            (host, port)
+        } else {
+            match authority.rsplit_once(':') {
+                Some((host, p)) => (host, p.parse().ok()),
+                None => (authority, Some(default_port)),
+            }
+        };
+        let port = port.ok_or_else(|| RkikError::Other(format!("invalid port in URL '{s}'")))?;
+        if host.is_empty() {
+            return Err(RkikError::Other(format!("no host in URL '{s}'")));
+        }
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+impl fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = if self.tls { "https" } else { "http" };
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => write!(f, "{scheme}://{host}{}", self.path),
+            _ => write!(f, "{scheme}://{host}:{}{}", self.port, self.path),
+        }
+    }
+}
+
+/// One offset measurement against an HTTP server's clock.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct HttpDateSample {
+    pub url: String,
+    pub ip: IpAddr,
+    pub port: u16,
+    /// HTTP status of the response; any status carries a usable `Date`.
+    pub status: u16,
+    /// Value of the `Date` header.
+    pub server_date: DateTime<Utc>,
+    /// Local time when the request was sent.
+    pub local: DateTime<Utc>,
+    /// Server clock minus local clock, taking the middle of the second the
+    /// header names, at the middle of the round trip.
+    pub offset_ms: f64,
+    /// Round trip of the request alone, after the TCP and TLS handshakes.
+    pub rtt_ms: f64,
+    /// Half the header resolution plus half the round trip: the true offset
+    /// lies within `offset_ms ± accuracy_ms`.
+    pub accuracy_ms: f64,
+}
+
+/// Send a `HEAD` request to `url` and derive the clock offset from the
+/// response's `Date` header.
+pub async fn probe(
+    url: &HttpUrl,
+    timeout: Duration,
+    ipv6_only: bool,
+) -> Result<HttpDateSample, RkikError> {
+    tokio::time::timeout(timeout, probe_inner(url, ipv6_only))
+        .await
+        .map_err(|_| RkikError::Timeout)?
+        .map_err(|e| e.with_target(url.to_string()))
+}
+
+async fn probe_inner(url: &HttpUrl, ipv6_only: bool) -> Result<HttpDateSample, RkikError> {
+    let ip = resolver::lookup(&url.host, ipv6_only).await?.ip;
+    let tcp = TcpStream::connect((ip, url.port)).await.map_err(io_error)?;
+    tracing::debug!(url = %url, %ip, "TCP connected");
+    let request = format!(
+        "HEAD {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: rkik/{}\r\nConnection: close\r\n\r\n",
+        url.path,
+        url.host,
+        env!("CARGO_PKG_VERSION")
+    );
+    let (sent, received, head) = if url.tls {
+        exchange(tls::connect(tcp, &url.host).await?, &request).await?
+    } else {
+        exchange(tcp, &request).await?
+    };
+    let (status, date) = parse_head(&head)?;
+    tracing::debug!(url = %url, status, %date, "HTTP response");
+    Ok(sample(url, ip, status, date, sent, received))
+}
+
+/// Write `request` and read the response head, timing the exchange.
+async fn exchange<S>(
+    mut stream: S,
+    request: &str,
+) -> Result<(DateTime<Utc>, DateTime<Utc>, String), RkikError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let sent = Utc::now();
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(io_error)?;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 2048];
+    let mut received = None;
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await.map_err(io_error)?;
+        if n == 0 {
+            break;
+        }
+        received.get_or_insert_with(Utc::now);
+        head.extend_from_slice(&buf[..n]);
+        if head.len() > MAX_HEAD {
+            return Err(RkikError::Protocol("response headers too large".into()));
+        }
+    }
+    let received = received
+        .ok_or_else(|| RkikError::Protocol("connection closed without a response".into()))?;
+    Ok((sent, received, String::from_utf8_lossy(&head).into_owned()))
+}
+
+/// Status code and `Date` of a response head.
+fn parse_head(head: &str) -> Result<(u16, DateTime<Utc>), RkikError> {
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .filter(|l| l.starts_with("HTTP/"))
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| RkikError::Protocol("not an HTTP response".into()))?;
+    let date = lines
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("date"))
+        .map(|(_, value)| value.trim())
+        .ok_or_else(|| RkikError::Protocol("response has no Date header".into()))?;
+    let date = DateTime::parse_from_rfc2822(date)
+        .map_err(|e| RkikError::Protocol(format!("invalid Date header '{date}': {e}")))?;
+    Ok((status, date.with_timezone(&Utc)))
+}
+
+fn sample(
+    url: &HttpUrl,
+    ip: IpAddr,
+    status: u16,
+    server_date: DateTime<Utc>,
+    sent: DateTime<Utc>,
+    received: DateTime<Utc>,
+) -> HttpDateSample {
+    let ms = |d: chrono::TimeDelta| d.num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.0;
+    let rtt_ms = ms(received - sent).max(0.0);
+    let server_ms = ms(server_date - sent) + DATE_RESOLUTION_MS / 2.0;
+    HttpDateSample {
+        url: url.to_string(),
+        ip,
+        port: url.port,
+        status,
+        server_date,
+        local: sent,
+        offset_ms: server_ms - rtt_ms / 2.0,
+        rtt_ms,
+        accuracy_ms: (DATE_RESOLUTION_MS + rtt_ms) / 2.0,
+    }
+}
+
+fn io_error(e: std::io::Error) -> RkikError {
+    match e.kind() {
+        std::io::ErrorKind::ConnectionRefused => RkikError::Refused(e.to_string()),
+        _ => RkikError::Network(e.to_string()),
+    }
+}
+
+#[cfg(feature = "https")]
+mod tls {
+    use std::sync::Arc;
+
+    use tokio::net::TcpStream;
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::client::TlsStream;
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore, crypto};
+
+    use crate::error::RkikError;
+
+    pub async fn connect(tcp: TcpStream, host: &str) -> Result<TlsStream<TcpStream>, RkikError> {
+        let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        // Explicit provider: other dependencies may enable a second one.
+        let config =
+            ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|e| RkikError::Other(e.to_string()))?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|e| RkikError::Other(format!("invalid TLS server name '{host}': {e}")))?;
+        TlsConnector::from(Arc::new(config))
+            .connect(name, tcp)
+            .await
+            .map_err(|e| RkikError::Network(format!("TLS handshake failed: {e}")))
+    }
+}
+
+#[cfg(not(feature = "https"))]
+mod tls {
+    use tokio::net::TcpStream;
+
+    use crate::error::RkikError;
+
+    pub async fn connect(_tcp: TcpStream, _host: &str) -> Result<TcpStream, RkikError> {
+        Err(RkikError::Other(
+            "https URLs need the https feature; use http:// instead".into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn parses_urls_and_bare_hosts() {
+        let url: HttpUrl = "example.com".parse().unwrap();
+        assert_eq!((url.tls, url.port, url.path.as_str()), (true, 443, "/"));
+        let url: HttpUrl = "http://[::1]:8080/health?x=1".parse().unwrap();
+        assert_eq!(url.host, "::1");
+        assert_eq!((url.tls, url.port), (false, 8080));
+        assert_eq!(url.to_string(), "http://[::1]:8080/health?x=1");
+        assert_eq!(
+            "https://example.com?q".parse::<HttpUrl>().unwrap().path,
+            "/?q"
+        );
+        assert!("ftp://example.com".parse::<HttpUrl>().is_err());
+        assert!("http://example.com:http".parse::<HttpUrl>().is_err());
+    }
+
+    #[test]
+    fn head_needs_a_status_and_a_date() {
+        let (status, date) = parse_head(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: /\r\ndate: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(status, 301);
+        assert_eq!(date.to_rfc3339(), "1994-11-06T08:49:37+00:00");
+        assert!(parse_head("HTTP/1.1 200 OK\r\n\r\n").is_err());
+        assert!(parse_head("SSH-2.0-OpenSSH\r\n\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn offset_is_measured_against_the_date_header() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = conn.read(&mut buf).await;
+            let ahead = Utc::now() + chrono::TimeDelta::seconds(30);
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\nDate: {}\r\n\r\n",
+                ahead.format("%a, %d %b %Y %H:%M:%S GMT")
+            );
+            conn.write_all(reply.as_bytes()).await.unwrap();
+        });
+        let url: HttpUrl = format!("http://127.0.0.1:{port}/").parse().unwrap();
+        let s = probe(&url, Duration::from_secs(2), false).await.unwrap();
+        assert_eq!(s.status, 200);
+        assert!((s.offset_ms - 30_000.0).abs() <= s.accuracy_ms, "{s:?}");
+        assert!(s.accuracy_ms >= 500.0);
+    }
+}
@@ -0,0 +1,203 @@
+//! Kernel clock discipline state, read with `adjtimex(2)` in read-only mode
+//! (no privileges needed). Reading needs Linux and the `sync` feature.
+#![cfg_attr(not(all(feature = "sync", target_os = "linux")), allow(dead_code))]
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use crate::error::RkikError;
+
+/// `STA_*` status bits, as `adjtimex --print` names them.
+const FLAGS: [(i32, &str); 16] = [
+    (0x0001, "PLL"),
+    (0x0002, "PPSFREQ"),
+    (0x0004, "PPSTIME"),
+    (0x0008, "FLL"),
+    (0x0010, "INS"),
+    (0x0020, "DEL"),
+    (0x0040, "UNSYNC"),
+    (0x0080, "FREQHOLD"),
+    (0x0100, "PPSSIGNAL"),
+    (0x0200, "PPSJITTER"),
+    (0x0400, "PPSWANDER"),
+    (0x0800, "PPSERROR"),
+    (0x1000, "CLOCKERR"),
+    (0x2000, "NANO"),
+    (0x4000, "MODE"),
+    (0x8000, "CLK"),
+];
+const STA_UNSYNC: i32 = 0x0040;
+const STA_NANO: i32 = 0x2000;
+
+/// The kernel's clock discipline variables.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct KernelClock {
+    /// Clock state returned by `adjtimex`.
+    pub state: ClockState,
+    /// False when `STA_UNSYNC` is set or the state is `TIME_ERROR`.
+    pub synchronised: bool,
+    /// Frequency correction applied to the clock (ppm).
+    pub freq_ppm: f64,
+    /// Offset the kernel PLL is still slewing out (ms, positive when the
+    /// clock is behind).
+    pub offset_ms: f64,
+    /// Estimated error reported by the daemon (ms).
+    pub est_error_ms: f64,
+    /// Maximum error, growing while nothing disciplines the clock (ms).
+    pub max_error_ms: f64,
+    /// Names of the `STA_*` bits set.
+    pub flags: Vec<&'static str>,
+    /// TAI minus UTC (s); 0 when no daemon has set it.
+    pub tai_offset_s: i32,
+    /// PLL time constant.
+    pub time_constant: i64,
+}
+
+/// `adjtimex` return value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum ClockState {
+    Ok,
+    InsertLeap,
+    DeleteLeap,
+    LeapInProgress,
+    LeapOccurred,
+    Error,
+}
+
+impl ClockState {
+    /// The kernel's name for the state.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ClockState::Ok => "TIME_OK",
+            ClockState::InsertLeap => "TIME_INS",
+            ClockState::DeleteLeap => "TIME_DEL",
+            ClockState::LeapInProgress => "TIME_OOP",
+            ClockState::LeapOccurred => "TIME_WAIT",
+            ClockState::Error => "TIME_ERROR",
+        }
+    }
+}
+
+/// The `struct timex` fields rkik reports.
+struct Raw {
+    state: i32,
+    status: i32,
+    offset: i64,
+    freq: i64,
+    maxerror: i64,
+    esterror: i64,
+    tai: i32,
+    constant: i64,
+}
+
+impl KernelClock {
+    fn from_raw(raw: Raw) -> Self {
+        let state = match raw.state {
+            0 => ClockState::Ok,
+            1 => ClockState::InsertLeap,
+            2 => ClockState::DeleteLeap,
+            3 => ClockState::LeapInProgress,
+            4 => ClockState::LeapOccurred,
+            _ => ClockState::Error,
+        };
+        // `offset` is in ns with STA_NANO, else µs; `freq` is ppm with a
+        // 16-bit fraction.
+        let offset_ms = if raw.status & STA_NANO != 0 {
+            raw.offset as f64 / 1e6
+        } else {
+            raw.offset as f64 / 1e3
+        };
+        KernelClock {
+            state,
+            synchronised: raw.status & STA_UNSYNC == 0 && state != ClockState::Error,
+            freq_ppm: raw.freq as f64 / 65_536.0,
+            offset_ms,
+            est_error_ms: raw.esterror as f64 / 1e3,
+            max_error_ms: raw.maxerror as f64 / 1e3,
+            flags: FLAGS
+                .iter()
+                .filter(|(bit, _)| raw.status & bit != 0)
+                .map(|&(_, name)| name)
+                .collect(),
+            tai_offset_s: raw.tai,
+            time_constant: raw.constant,
+        }
+    }
+}
+
+/// Read the kernel clock discipline state.
+#[cfg(all(feature = "sync", target_os = "linux"))]
+pub fn read() -> Result<KernelClock, RkikError> {
+    // SAFETY: timex is plain data; modes = 0 makes adjtimex read-only.
+    let mut tx: libc::timex = unsafe { std::mem::zeroed() };
+    let state = unsafe { libc::adjtimex(&mut tx) };
+    if state < 0 {
+        return Err(RkikError::Io(std::io::Error::last_os_error()));
+    }
+    Ok(KernelClock::from_raw(Raw {
+        state,
+        status: tx.status,
+        offset: tx.offset as i64,
+        freq: tx.freq as i64,
+        maxerror: tx.maxerror as i64,
+        esterror: tx.esterror as i64,
+        tai: tx.tai,
+        constant: tx.constant as i64,
+    }))
+}
+
+/// Read the kernel clock discipline state.
+#[cfg(not(all(feature = "sync", target_os = "linux")))]
+pub fn read() -> Result<KernelClock, RkikError> {
+    Err(RkikError::Other(
+        "kernel clock status needs Linux and the sync feature".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timex_fields_are_scaled_and_named() {
+        let clock = KernelClock::from_raw(Raw {
+            state: 0,
+            status: 0x2001,
+            offset: -250_000,
+            freq: 12 * 65_536 + 32_768,
+            maxerror: 16_000,
+            esterror: 500,
+            tai: 37,
+            constant: 6,
+        });
+        assert_eq!(clock.state.as_str(), "TIME_OK");
+        assert!(clock.synchronised);
+        assert_eq!(clock.flags, ["PLL", "NANO"]);
+        assert_eq!(clock.offset_ms, -0.25);
+        assert_eq!(clock.freq_ppm, 12.5);
+        assert_eq!((clock.est_error_ms, clock.max_error_ms), (0.5, 16.0));
+
+        let unsynced = KernelClock::from_raw(Raw {
+            state: 5,
+            status: STA_UNSYNC,
+            offset: 1_000,
+            freq: 0,
+            maxerror: 0,
+            esterror: 0,
+            tai: 0,
+            constant: 0,
+        });
+        assert_eq!(unsynced.state, ClockState::Error);
+        assert!(!unsynced.synchronised);
+        assert_eq!(unsynced.offset_ms, 1.0);
+    }
+
+    #[cfg(all(feature = "sync", target_os = "linux"))]
+    #[test]
+    fn reads_the_running_kernel() {
+        let clock = read().unwrap();
+        assert!(clock.max_error_ms >= 0.0);
+    }
+}
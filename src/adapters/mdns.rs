@@ -0,0 +1,176 @@
+//! One-shot multicast DNS browsing (RFC 6762 §5.1, DNS-SD RFC 6763).
+//!
+//! The query is sent from an ephemeral port, so responders answer by unicast
+//! and no multicast group membership (or port 5353) is needed. IPv4 only.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use hickory_proto::op::{Message, MessageType, Query};
+use hickory_proto::rr::{Name, RData, Record, RecordType};
+use tokio::net::UdpSocket;
+
+use crate::error::RkikError;
+
+const MDNS_GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+
+/// A DNS-SD service instance found on the local link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instance {
+    /// Service type it was found under, e.g. `_ntp._udp.local`.
+    pub service: String,
+    /// Instance name, e.g. `Lab GPS clock._ntp._udp.local`.
+    pub name: String,
+    /// Target host of the SRV record, e.g. `gps1.local`.
+    pub host: String,
+    pub port: u16,
+    pub priority: u16,
+    pub weight: u16,
+    /// Addresses announced for `host` in the same responses.
+    pub addrs: Vec<IpAddr>,
+}
+
+/// Ask the local link for `services` (e.g. `_ntp._udp.local`) and collect
+/// the instances announced within `window`.
+///
+/// Instances whose SRV record never arrives are dropped; a silent network
+/// yields an empty list, not an error.
+pub async fn browse(services: &[&str], window: Duration) -> Result<Vec<Instance>, RkikError> {
+    let mut query = Message::new();
+    query.set_message_type(MessageType::Query);
+    for service in services {
+        let name = Name::from_ascii(format!("{}.", service.trim_end_matches('.')))
+            .map_err(|e| RkikError::Other(format!("invalid service '{service}': {e}")))?;
+        query.add_query(Query::query(name, RecordType::PTR));
+    }
+    let packet = query
+        .to_vec()
+        .map_err(|e| RkikError::Other(e.to_string()))?;
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| RkikError::Network(e.to_string()))?;
+    socket
+        .send_to(&packet, MDNS_GROUP)
+        .await
+        .map_err(|e| RkikError::Network(format!("mDNS query failed: {e}")))?;
+
+    let mut records = Vec::new();
+    let mut buf = vec![0u8; 9000];
+    let deadline = tokio::time::Instant::now() + window;
+    while let Ok(Ok((len, _))) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await
+    {
+        if let Ok(msg) = Message::from_vec(&buf[..len])
+            && msg.message_type() == MessageType::Response
+        {
+            records.extend(msg.answers().iter().cloned());
+            records.extend(msg.additionals().iter().cloned());
+        }
+    }
+    Ok(collect(services, &records))
+}
+
+/// Join PTR, SRV and address records into instances of `services`.
+fn collect(services: &[&str], records: &[Record]) -> Vec<Instance> {
+    let key = |name: &Name| name.to_ascii().trim_end_matches('.').to_ascii_lowercase();
+    let mut srv = HashMap::new();
+    let mut addrs: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    let mut ptrs = Vec::new();
+    for record in records {
+        match record.data() {
+            RData::PTR(ptr) => ptrs.push((key(record.name()), ptr.0.clone())),
+            RData::SRV(s) => {
+                srv.insert(key(record.name()), s.clone());
+            }
+            RData::A(a) => addrs
+                .entry(key(record.name()))
+                .or_default()
+                .push(a.0.into()),
+            RData::AAAA(a) => addrs
+                .entry(key(record.name()))
+                .or_default()
+                .push(a.0.into()),
+            _ => {}
+        }
+    }
+
+    let mut found: Vec<Instance> = Vec::new();
+    for (service, instance) in ptrs {
+        let wanted = services
+            .iter()
+            .any(|s| s.trim_end_matches('.').eq_ignore_ascii_case(&service));
+        let name = text(&instance);
+        if !wanted || found.iter().any(|f| f.name.eq_ignore_ascii_case(&name)) {
+            continue;
+        }
+        let Some(s) = srv.get(&key(&instance)) else {
+            continue;
+        };
+        let mut host_addrs = addrs.get(&key(s.target())).cloned().unwrap_or_default();
+        host_addrs.dedup();
+        found.push(Instance {
+            service,
+            name,
+            host: text(s.target()),
+            port: s.port(),
+            priority: s.priority(),
+            weight: s.weight(),
+            addrs: host_addrs,
+        });
+    }
+    found
+}
+
+/// DNS-SD names are raw UTF-8 labels ("Lab GPS clock"), not IDNA host names.
+fn text(name: &Name) -> String {
+    name.iter()
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::rr::rdata::{A, PTR, SRV};
+
+    fn name(s: &str) -> Name {
+        Name::from_ascii(s).unwrap()
+    }
+
+    #[test]
+    fn joins_ptr_srv_and_address_records() {
+        let instance = Name::from_labels([&b"Lab GPS"[..], b"_ntp", b"_udp", b"local"]).unwrap();
+        let records = vec![
+            Record::from_rdata(
+                name("_ntp._udp.local."),
+                120,
+                RData::PTR(PTR(instance.clone())),
+            ),
+            Record::from_rdata(
+                instance,
+                120,
+                RData::SRV(SRV::new(0, 5, 123, name("gps1.local."))),
+            ),
+            Record::from_rdata(name("gps1.local."), 120, RData::A(A::new(192, 0, 2, 7))),
+            // Orphan PTR without SRV, and an unrelated service.
+            Record::from_rdata(
+                name("_ntp._udp.local."),
+                120,
+                RData::PTR(PTR(name("gone._ntp._udp.local."))),
+            ),
+            Record::from_rdata(
+                name("_http._tcp.local."),
+                120,
+                RData::PTR(PTR(name("web._http._tcp.local."))),
+            ),
+        ];
+        let found = collect(&["_ntp._udp.local", "_ptp._udp.local"], &records);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "Lab GPS._ntp._udp.local");
+        assert_eq!(found[0].host, "gps1.local");
+        assert_eq!(found[0].port, 123);
+        assert_eq!(found[0].addrs, vec![IpAddr::from([192, 0, 2, 7])]);
+    }
+}
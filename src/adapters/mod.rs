@@ -1,3 +1,24 @@
+pub mod chrony;
+pub mod demux;
+pub mod dhcp;
+#[cfg(feature = "enrich")]
+pub mod geoip;
+pub mod http_date;
+pub mod kernel;
+pub mod mdns;
 pub mod ntp_client;
+pub mod ntp_packet;
+pub mod ntpd;
 pub mod nts_client;
+#[cfg(feature = "nts")]
+pub mod nts_ke;
+pub mod pcap;
 pub mod resolver;
+pub mod reuse;
+#[cfg(feature = "json")]
+pub mod session;
+#[cfg(feature = "sqlite")]
+pub mod store;
+pub mod traceroute;
+pub mod transport;
+pub mod w32time;
@@ -1,28 +1,644 @@
-use std::net::{IpAddr, Ipv6Addr};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
-use rsntp::{AsyncSntpClient, Config, SynchronizationResult};
+use chrono::{DateTime, Datelike, Utc};
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
 
+use super::ntp_packet::{
+    MODE_BROADCAST, MODE_SERVER, NtpPacket, NtpTimestamp, millis_between, short_ms,
+};
+use super::pcap;
+use super::reuse;
+use crate::domain::ntp::{Health, HealthIssue, RawTimestamps, Timing};
 use crate::error::RkikError;
 
-/// Query an NTP server asynchronously and return the synchronization result.
-pub async fn query(
-    ip: IpAddr,
+/// One client/server exchange, with the packets as they went over the wire.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub local: SocketAddr,
+    pub server: SocketAddr,
+    pub request: NtpPacket,
+    pub reply: NtpPacket,
+    /// Local clock when the request was sent (T1).
+    pub sent: DateTime<Utc>,
+    /// Local clock when the reply arrived (T4).
+    pub received: DateTime<Utc>,
+    /// Spoofed-looking replies dropped before this one.
+    pub rejected: u32,
+    /// Time spent getting a socket ready before sending (ms).
+    pub setup_ms: f64,
+}
+
+impl Exchange {
+    /// Server clock minus local clock, from the four timestamps.
+    pub fn offset_ms(&self) -> f64 {
+        let t2 = self.reply.receive.to_datetime();
+        let t3 = self.reply.transmit.to_datetime();
+        (millis_between(self.sent, t2) + millis_between(self.received, t3)) / 2.0
+    }
+
+    /// Round trip, less the time the server held the request.
+    pub fn rtt_ms(&self) -> f64 {
+        let t2 = self.reply.receive.to_datetime();
+        let t3 = self.reply.transmit.to_datetime();
+        millis_between(self.sent, self.received) - millis_between(t2, t3)
+    }
+
+    /// T1 to T4 and the time the server held the request; `dns_ms` is
+    /// left to the caller.
+    pub fn timing(&self) -> Timing {
+        let t2 = self.reply.receive.to_datetime();
+        let t3 = self.reply.transmit.to_datetime();
+        Timing {
+            dns_ms: 0.0,
+            socket_setup_ms: self.setup_ms,
+            t1: self.sent,
+            t2,
+            t3,
+            t4: self.received,
+            processing_ms: millis_between(t2, t3),
+            raw: RawTimestamps {
+                origin: self.reply.origin.0,
+                receive: self.reply.receive.0,
+                transmit: self.reply.transmit.0,
+                destination: NtpTimestamp::from_datetime(self.received).0,
+            },
+        }
+    }
+
+    /// Bound on the error of [`offset_ms`](Self::offset_ms), RFC 5905's
+    /// root distance seen from here: half the round trip (the path may be
+    /// fully asymmetric), the server's own distance to its reference (half
+    /// its root delay plus its root dispersion) and its clock precision.
+    pub fn uncertainty_ms(&self) -> f64 {
+        let reply = &self.reply;
+        self.rtt_ms().max(0.0) / 2.0
+            + short_ms(reply.root_delay) / 2.0
+            + short_ms(reply.root_dispersion)
+            + 2f64.powi(reply.precision.into()) * 1000.0
+    }
+
+    /// Time since the server last set its clock from its reference, at
+    /// the time of the reply (s); `None` when it never did.
+    pub fn reference_age_s(&self) -> Option<f64> {
+        let reply = &self.reply;
+        (!reply.reference.is_zero()).then(|| {
+            millis_between(reply.reference.to_datetime(), reply.transmit.to_datetime()) / 1000.0
+        })
+    }
+
+    pub fn ref_id(&self) -> String {
+        self.reply.ref_id_string(self.server.is_ipv6())
+    }
+
+    /// Sanity checks on the reply: an impossible year, stratum 0 without a
+    /// Kiss-o'-Death code, a missing or day-old reference timestamp, or an
+    /// oversized root dispersion.
+    pub fn health(&self) -> Health {
+        let reply = &self.reply;
+        let mut issues = Vec::new();
+        let year = reply.transmit.to_datetime().year();
+        let local = self.received.year();
+        let sane = EARLIEST_YEAR..=LATEST_YEAR;
+        if !sane.contains(&year) || (sane.contains(&local) && (year - local).abs() > 1) {
+            issues.push(HealthIssue::BogusYear { year });
+        }
+        if reply.stratum == 0 {
+            issues.push(HealthIssue::StratumZero);
+        } else if reply.leap != 3 {
+            // An unsynchronised server has no reference to speak of.
+            match self.reference_age_s() {
+                None => issues.push(HealthIssue::NoReference),
+                Some(age) if age / 3600.0 > MAX_REFERENCE_AGE_H => {
+                    issues.push(HealthIssue::StaleReference {
+                        hours: age / 3600.0,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        let ms = short_ms(reply.root_dispersion);
+        if ms > MAX_DISPERSION_MS {
+            issues.push(HealthIssue::HighDispersion { ms });
+        }
+        Health::from_issues(issues)
+    }
+}
+
+/// Years outside this range are bogus whatever the local clock says.
+const EARLIEST_YEAR: i32 = 2024;
+const LATEST_YEAR: i32 = 2100;
+/// A server that has not heard from its reference for a day is neglected.
+const MAX_REFERENCE_AGE_H: f64 = 24.0;
+/// Root dispersion above this is no longer synchronised time (ms).
+const MAX_DISPERSION_MS: f64 = 1000.0;
+
+/// Kiss-o'-Death codes are four uppercase ASCII letters (RFC 5905 7.4).
+fn is_kiss_code(ref_id: [u8; 4]) -> bool {
+    ref_id.iter().all(u8::is_ascii_uppercase)
+}
+
+/// Send one client request to `addr` and validate the reply, giving up
+/// after `timeout`. Both packets go to the [`pcap`] capture when one runs,
+/// including replies that fail validation.
+///
+/// Replies from another address, that are not NTP packets, or whose origin
+/// timestamp does not echo the request's transmit timestamp, are dropped
+/// as spoofed-looking and the wait goes on; they are recorded for
+/// [`take_rejected`]. When only such replies came, the exchange fails with
+/// a protocol error saying so.
+///
+/// A link-local IPv6 `addr` must carry its scope id, which selects the
+/// interface the request leaves on.
+pub async fn exchange(
+    addr: SocketAddr,
     ipv6: bool,
     timeout: Duration,
-    port: u16,
-) -> Result<SynchronizationResult, RkikError> {
-    let cfg = if ipv6 {
-        Config::default().bind_address((Ipv6Addr::UNSPECIFIED, 0).into())
+) -> Result<Exchange, RkikError> {
+    let deadline = Instant::now() + timeout;
+    tokio::time::timeout_at(deadline, exchange_inner(addr, ipv6, deadline))
+        .await
+        .map_err(|_| RkikError::Timeout)?
+}
+
+async fn exchange_inner(
+    addr: SocketAddr,
+    ipv6: bool,
+    deadline: Instant,
+) -> Result<Exchange, RkikError> {
+    let setup = Instant::now();
+    let socket = socket_for(addr, ipv6).await?;
+    let setup_ms = setup.elapsed().as_secs_f64() * 1000.0;
+    let exchange = exchange_on(&socket, addr, deadline).await?;
+    keep(addr, socket);
+    Ok(Exchange {
+        setup_ms,
+        ..exchange
+    })
+}
+
+async fn exchange_on(
+    socket: &UdpSocket,
+    addr: SocketAddr,
+    deadline: Instant,
+) -> Result<Exchange, RkikError> {
+    let local = socket.local_addr().map_err(io_error)?;
+    let sent = Utc::now();
+    let request = NtpPacket::client(sent);
+    let bytes = request.to_bytes();
+    socket.send(&bytes).await.map_err(io_error)?;
+    pcap::capture(sent, local, addr, &bytes);
+    tracing::trace!(server = %addr, len = bytes.len(), packet = ?request, "request sent");
+
+    let mut rejected = 0;
+    let mut buf = [0u8; 1024];
+    let (reply, received) = loop {
+        let recv = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await;
+        let Ok(recv) = recv else {
+            if rejected > 0 {
+                return Err(RkikError::Protocol(format!(
+                    "no genuine reply: dropped {rejected} that did not match the request (possible spoofing)"
+                )));
+            }
+            return Err(RkikError::Timeout);
+        };
+        let (len, from) = recv.map_err(io_error)?;
+        let received = Utc::now();
+        pcap::capture(received, from, local, &buf[..len]);
+        tracing::trace!(%from, len, "datagram received");
+        if (from.ip(), from.port()) != (addr.ip(), addr.port()) {
+            reject(addr, from, RejectReason::WrongSource);
+            rejected += 1;
+            continue;
+        }
+        let Ok(reply) = NtpPacket::from_bytes(&buf[..len]) else {
+            reject(addr, from, RejectReason::Malformed);
+            rejected += 1;
+            continue;
+        };
+        // Checked before anything else, Kiss-o'-Death included: a forged
+        // KoD could otherwise silence a server.
+        if reply.origin != request.transmit {
+            reject(addr, from, RejectReason::OriginMismatch);
+            rejected += 1;
+            continue;
+        }
+        break (reply, received);
+    };
+    tracing::trace!(server = %addr, packet = ?reply, "reply accepted");
+    check_reply(&reply)?;
+    Ok(Exchange {
+        local,
+        server: addr,
+        request,
+        reply,
+        sent,
+        received,
+        rejected,
+        setup_ms: 0.0,
+    })
+}
+
+/// Refuse a reply that answers the request but carries no time: a
+/// Kiss-o'-Death, no transmit timestamp, or a mode other than server.
+pub(crate) fn check_reply(reply: &NtpPacket) -> Result<(), RkikError> {
+    // Any other stratum 0 reply is flagged by `Exchange::health`.
+    if reply.stratum == 0 && is_kiss_code(reply.ref_id) {
+        return Err(RkikError::kiss_of_death_code(&reply.ref_id_string(false)));
+    }
+    if reply.transmit.is_zero() {
+        return Err(RkikError::Protocol(
+            "reply has no transmit timestamp".into(),
+        ));
+    }
+    if !matches!(reply.mode, MODE_SERVER | MODE_BROADCAST) {
+        return Err(RkikError::Protocol(format!(
+            "unexpected NTP mode {} in reply",
+            reply.mode
+        )));
+    }
+    Ok(())
+}
+
+/// Connected sockets kept by a [`reuse::ReuseScope`], per server.
+static KEPT: Mutex<Option<HashMap<SocketAddr, UdpSocket>>> = Mutex::new(None);
+
+/// The socket kept for `addr`, else a new one connected to it.
+async fn socket_for(addr: SocketAddr, ipv6: bool) -> Result<UdpSocket, RkikError> {
+    if reuse::active() {
+        let kept = KEPT
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut()
+            .and_then(|kept| kept.remove(&addr));
+        if let Some(socket) = kept {
+            // Whatever is queued answers nothing we are waiting for.
+            let mut buf = [0u8; 1024];
+            while socket.try_recv(&mut buf).is_ok() {}
+            reuse::SOCKETS_REUSED.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!(local = ?socket.local_addr().ok(), server = %addr, "UDP socket reused");
+            return Ok(socket);
+        }
+    }
+    let bind: SocketAddr = if ipv6 {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
     } else {
-        Config::default().bind_address(([0, 0, 0, 0], 0).into())
+        ([0, 0, 0, 0], 0).into()
     };
-    let client = AsyncSntpClient::with_config(cfg);
-    // rsntp does not expose explicit timeout; rely on tokio timeout
-    let addr = format!("{}:{}", ip, port);
-    let fut = client.synchronize(addr);
-    let res = tokio::time::timeout(timeout, fut)
-        .await
-        .map_err(|_| RkikError::Network("timeout".into()))??;
-    Ok(res)
+    let socket = UdpSocket::bind(bind).await.map_err(io_error)?;
+    socket.connect(addr).await.map_err(io_error)?;
+    reuse::SOCKETS_OPENED.fetch_add(1, Ordering::Relaxed);
+    tracing::debug!(local = ?socket.local_addr().ok(), server = %addr, "UDP socket connected");
+    Ok(socket)
+}
+
+/// Keep `socket` for the next exchange with `addr`, if a scope wants it.
+fn keep(addr: SocketAddr, socket: UdpSocket) {
+    if reuse::active() {
+        KEPT.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(HashMap::new)
+            .insert(addr, socket);
+    }
+}
+
+/// Close every kept socket; called when the last scope ends.
+pub(crate) fn close_kept() {
+    KEPT.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Why a reply was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// It came from another address than the one queried.
+    WrongSource,
+    /// Its origin timestamp is not the transmit timestamp of the request.
+    OriginMismatch,
+    /// It is not an NTP packet.
+    Malformed,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RejectReason::WrongSource => "it came from another address",
+            RejectReason::OriginMismatch => "its origin timestamp does not echo the request",
+            RejectReason::Malformed => "it is not an NTP packet",
+        })
+    }
+}
+
+/// A reply [`exchange`] dropped as spoofed-looking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rejected {
+    /// The server that was queried.
+    pub server: SocketAddr,
+    /// Where the reply came from.
+    pub from: SocketAddr,
+    pub reason: RejectReason,
+}
+
+/// Rejections kept for [`take_rejected`]; older ones are dropped past this.
+const MAX_KEPT: usize = 64;
+
+static REJECTED: Mutex<Vec<Rejected>> = Mutex::new(Vec::new());
+static REJECTED_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn reject(server: SocketAddr, from: SocketAddr, reason: RejectReason) {
+    tracing::debug!(%server, %from, %reason, "reply dropped");
+    REJECTED_TOTAL.fetch_add(1, Ordering::Relaxed);
+    let mut kept = REJECTED.lock().unwrap_or_else(|e| e.into_inner());
+    if kept.len() == MAX_KEPT {
+        kept.remove(0);
+    }
+    kept.push(Rejected {
+        server,
+        from,
+        reason,
+    });
+}
+
+/// Replies dropped as spoofed-looking since the last call (at most the 64
+/// most recent).
+pub fn take_rejected() -> Vec<Rejected> {
+    std::mem::take(&mut *REJECTED.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Replies dropped as spoofed-looking since the process started.
+pub fn rejected_total() -> u64 {
+    REJECTED_TOTAL.load(Ordering::Relaxed)
+}
+
+pub(crate) fn io_error(e: std::io::Error) -> RkikError {
+    match e.kind() {
+        std::io::ErrorKind::ConnectionRefused => RkikError::Refused(e.to_string()),
+        _ => RkikError::Network(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::ntp_packet::{NtpPacket, NtpTimestamp};
+    use crate::domain::ntp::HealthVerdict;
+
+    /// A loopback server answering one request with each packet `reply`
+    /// makes from it, in order.
+    async fn server(reply: fn(&NtpPacket) -> Vec<NtpPacket>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+            let request = NtpPacket::from_bytes(&buf[..len]).unwrap();
+            for packet in reply(&request) {
+                socket.send_to(&packet.to_bytes(), from).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    fn answer(request: &NtpPacket) -> NtpPacket {
+        let mut packet = request.clone();
+        packet.mode = MODE_SERVER;
+        packet.stratum = 2;
+        packet.origin = request.transmit;
+        packet.receive = request.transmit;
+        packet
+    }
+
+    #[tokio::test]
+    async fn spoofed_looking_replies_are_dropped() {
+        let addr = server(|request| {
+            // A forged Kiss-o'-Death and a stale reply come first.
+            let mut kod = answer(request);
+            kod.stratum = 0;
+            kod.ref_id = *b"DENY";
+            kod.origin = NtpTimestamp(request.transmit.0 ^ 1);
+            let mut stale = answer(request);
+            stale.origin = NtpTimestamp(0);
+            vec![kod, stale, answer(request)]
+        })
+        .await;
+        let ex = exchange(addr, false, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(ex.rejected, 2);
+        let rejected: Vec<Rejected> = take_rejected()
+            .into_iter()
+            .filter(|r| r.server == addr)
+            .collect();
+        assert_eq!(rejected.len(), 2);
+        assert_eq!(rejected[0].reason, RejectReason::OriginMismatch);
+        assert!(rejected_total() >= 2);
+    }
+
+    #[tokio::test]
+    async fn garbage_before_the_reply_is_dropped() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+            let request = NtpPacket::from_bytes(&buf[..len]).unwrap();
+            socket.send_to(b"garbage", from).await.unwrap();
+            socket
+                .send_to(&answer(&request).to_bytes(), from)
+                .await
+                .unwrap();
+        });
+        let ex = exchange(addr, false, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(ex.rejected, 1);
+        let rejected: Vec<Rejected> = take_rejected()
+            .into_iter()
+            .filter(|r| r.server == addr)
+            .collect();
+        assert_eq!(rejected[0].reason, RejectReason::Malformed);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn scope_id_is_kept_on_the_way_out() {
+        let socket = UdpSocket::bind("[::1]:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+            let request = NtpPacket::from_bytes(&buf[..len]).unwrap();
+            socket
+                .send_to(&answer(&request).to_bytes(), from)
+                .await
+                .unwrap();
+        });
+        let scope = crate::adapters::resolver::scope_id("lo").unwrap();
+        let addr = std::net::SocketAddrV6::new(Ipv6Addr::LOCALHOST, port, 0, scope).into();
+        // Replies come back without the scope id; they must still match.
+        let ex = exchange(addr, true, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(ex.rejected, 0);
+    }
+
+    #[tokio::test]
+    async fn scoped_exchanges_share_one_socket() {
+        use crate::services::serve::{ServeOptions, bind, serve};
+        let socket = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let cancel = tokio_util::sync::CancellationToken::new();
+        tokio::spawn(serve(socket, ServeOptions::new(), cancel.clone()));
+
+        let before = reuse::stats();
+        let scope = reuse::keep_open();
+        let mut ports = Vec::new();
+        for _ in 0..3 {
+            let ex = exchange(addr, false, Duration::from_secs(2)).await.unwrap();
+            ports.push(ex.local.port());
+        }
+        drop(scope);
+        cancel.cancel();
+
+        assert!(ports.iter().all(|p| *p == ports[0]), "{ports:?}");
+        let stats = reuse::stats();
+        assert!(stats.sockets_reused >= before.sockets_reused + 2);
+        assert!(stats.sockets_opened > before.sockets_opened);
+    }
+
+    #[test]
+    fn misbehaving_servers_are_flagged() {
+        let sent = Utc::now();
+        let request = NtpPacket::client(sent);
+        let mut healthy = answer(&request);
+        healthy.reference = NtpTimestamp::from_datetime(sent - chrono::TimeDelta::minutes(5));
+        let ex = |reply: NtpPacket| Exchange {
+            local: "127.0.0.1:50000".parse().unwrap(),
+            server: "127.0.0.1:123".parse().unwrap(),
+            request: request.clone(),
+            reply,
+            sent,
+            received: sent,
+            rejected: 0,
+            setup_ms: 0.0,
+        };
+        assert_eq!(ex(healthy.clone()).health(), Health::default());
+
+        let mut neglected = healthy.clone();
+        neglected.reference = NtpTimestamp::from_datetime(sent - chrono::TimeDelta::hours(30));
+        neglected.root_dispersion = 2 << 16;
+        let health = ex(neglected).health();
+        assert_eq!(health.verdict, HealthVerdict::Suspect);
+        assert_eq!(
+            health.issues,
+            [
+                HealthIssue::StaleReference { hours: 30.0 },
+                HealthIssue::HighDispersion { ms: 2000.0 }
+            ]
+        );
+
+        let mut bogus = healthy.clone();
+        bogus.stratum = 0;
+        bogus.ref_id = [0; 4];
+        bogus.transmit = NtpTimestamp::from_datetime("1999-12-31T23:59:59Z".parse().unwrap());
+        let health = ex(bogus).health();
+        assert_eq!(health.verdict, HealthVerdict::Bogus);
+        assert_eq!(
+            health.issues,
+            [
+                HealthIssue::BogusYear { year: 1999 },
+                HealthIssue::StratumZero
+            ]
+        );
+
+        let mut unsynced = healthy;
+        unsynced.leap = 3;
+        unsynced.reference = NtpTimestamp(0);
+        assert_eq!(ex(unsynced).health(), Health::default());
+    }
+
+    #[test]
+    fn uncertainty_is_the_root_distance_from_here() {
+        let sent = Utc::now();
+        let request = NtpPacket::client(sent);
+        let mut reply = answer(&request);
+        reply.receive = NtpTimestamp::from_datetime(sent + chrono::TimeDelta::milliseconds(5));
+        reply.transmit = reply.receive;
+        reply.root_delay = 1 << 13; // 125 ms
+        reply.root_dispersion = 1 << 12; // 62.5 ms
+        reply.precision = -10; // ~1 ms
+        let ex = Exchange {
+            local: "127.0.0.1:50000".parse().unwrap(),
+            server: "127.0.0.1:123".parse().unwrap(),
+            request,
+            reply,
+            sent,
+            received: sent + chrono::TimeDelta::milliseconds(10),
+            rejected: 0,
+            setup_ms: 0.0,
+        };
+        let expected = 5.0 + 62.5 + 62.5 + 0.9765625;
+        assert!(
+            (ex.uncertainty_ms() - expected).abs() < 1e-6,
+            "{}",
+            ex.uncertainty_ms()
+        );
+    }
+
+    #[tokio::test]
+    async fn timing_follows_the_four_timestamps() {
+        let addr = server(|request| {
+            let mut reply = answer(request);
+            let t2 = request.transmit.to_datetime();
+            reply.transmit = NtpTimestamp::from_datetime(t2 + chrono::TimeDelta::milliseconds(3));
+            vec![reply]
+        })
+        .await;
+        let ex = exchange(addr, false, Duration::from_secs(2)).await.unwrap();
+        let timing = ex.timing();
+        assert_eq!((timing.t1, timing.t4), (ex.sent, ex.received));
+        assert!((timing.processing_ms - 3.0).abs() < 1e-3, "{timing:?}");
+        assert!(timing.socket_setup_ms > 0.0);
+        assert_eq!(timing.raw.origin, ex.request.transmit.0);
+        assert_eq!(NtpTimestamp(timing.raw.transmit).to_datetime(), timing.t3);
+        assert!((ex.rtt_ms() - (millis_between(timing.t1, timing.t4) - 3.0)).abs() < 1e-3);
+    }
+
+    #[tokio::test]
+    async fn stratum_zero_needs_a_kiss_code() {
+        let addr = server(|request| {
+            let mut kod = answer(request);
+            kod.stratum = 0;
+            kod.ref_id = *b"RATE";
+            vec![kod]
+        })
+        .await;
+        let err = exchange(addr, false, Duration::from_secs(2))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RkikError::RateLimited(_)));
+
+        let addr = server(|request| {
+            let mut zero = answer(request);
+            zero.stratum = 0;
+            zero.ref_id = [0; 4];
+            vec![zero]
+        })
+        .await;
+        let ex = exchange(addr, false, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(ex.health().verdict, HealthVerdict::Bogus);
+    }
+
+    #[tokio::test]
+    async fn only_spoofed_replies_fail_the_exchange() {
+        let addr = server(|request| {
+            let mut forged = answer(request);
+            forged.origin = NtpTimestamp(request.transmit.0 + 1);
+            vec![forged]
+        })
+        .await;
+        let err = exchange(addr, false, Duration::from_millis(300))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RkikError::Protocol(_)));
+        assert!(err.to_string().contains("possible spoofing"), "{err}");
+    }
 }
@@ -0,0 +1,196 @@
+//! NTPv4 packet encoding and decoding (RFC 5905, header only).
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::error::RkikError;
+
+/// Seconds between the NTP era 0 epoch (1900) and the Unix epoch.
+const UNIX_OFFSET: i64 = 2_208_988_800;
+
+pub const MODE_CLIENT: u8 = 3;
+pub const MODE_SERVER: u8 = 4;
+pub const MODE_BROADCAST: u8 = 5;
+
+/// 64-bit NTP timestamp: seconds since 1900 and a 32-bit fraction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NtpTimestamp(pub u64);
+
+impl NtpTimestamp {
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn from_datetime(t: DateTime<Utc>) -> Self {
+        // Truncating to 32 bits wraps into era 1 from 2036 on.
+        let secs = (t.timestamp() + UNIX_OFFSET) as u32 as u64;
+        let frac = ((t.timestamp_subsec_nanos() as u64) << 32) / 1_000_000_000;
+        NtpTimestamp((secs << 32) | frac)
+    }
+
+    /// The instant this timestamp stands for, read as era 0 (1968–2036)
+    /// or, with the top bit clear, era 1 (2036–2104).
+    pub fn to_datetime(self) -> DateTime<Utc> {
+        let mut secs = (self.0 >> 32) as i64;
+        if secs & 0x8000_0000 == 0 {
+            secs += 1 << 32;
+        }
+        let nanos = (((self.0 & 0xffff_ffff) * 1_000_000_000) >> 32) as u32;
+        DateTime::from_timestamp(secs - UNIX_OFFSET, nanos).unwrap_or_default()
+    }
+}
+
+/// The 48-byte NTP header; extension fields and MACs are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NtpPacket {
+    pub leap: u8,
+    pub version: u8,
+    pub mode: u8,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: i8,
+    /// NTP short format (16.16 seconds).
+    pub root_delay: u32,
+    /// NTP short format (16.16 seconds).
+    pub root_dispersion: u32,
+    pub ref_id: [u8; 4],
+    pub reference: NtpTimestamp,
+    pub origin: NtpTimestamp,
+    pub receive: NtpTimestamp,
+    pub transmit: NtpTimestamp,
+}
+
+impl NtpPacket {
+    pub const LEN: usize = 48;
+
+    /// A version 4 client request sent at `transmit`.
+    pub fn client(transmit: DateTime<Utc>) -> Self {
+        NtpPacket {
+            leap: 0,
+            version: 4,
+            mode: MODE_CLIENT,
+            stratum: 0,
+            poll: 0,
+            precision: 0,
+            root_delay: 0,
+            root_dispersion: 0,
+            ref_id: [0; 4],
+            reference: NtpTimestamp::default(),
+            origin: NtpTimestamp::default(),
+            receive: NtpTimestamp::default(),
+            transmit: NtpTimestamp::from_datetime(transmit),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut out = [0u8; Self::LEN];
+        out[0] = (self.leap & 0x3) << 6 | (self.version & 0x7) << 3 | (self.mode & 0x7);
+        out[1] = self.stratum;
+        out[2] = self.poll as u8;
+        out[3] = self.precision as u8;
+        out[4..8].copy_from_slice(&self.root_delay.to_be_bytes());
+        out[8..12].copy_from_slice(&self.root_dispersion.to_be_bytes());
+        out[12..16].copy_from_slice(&self.ref_id);
+        out[16..24].copy_from_slice(&self.reference.0.to_be_bytes());
+        out[24..32].copy_from_slice(&self.origin.0.to_be_bytes());
+        out[32..40].copy_from_slice(&self.receive.0.to_be_bytes());
+        out[40..48].copy_from_slice(&self.transmit.0.to_be_bytes());
+        out
+    }
+
+    /// Decode a version 3 or 4 header.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, RkikError> {
+        let Some(data) = data.first_chunk::<{ Self::LEN }>() else {
+            return Err(RkikError::Protocol(format!(
+                "packet is too short ({} bytes)",
+                data.len()
+            )));
+        };
+        let version = (data[0] >> 3) & 0x7;
+        if !matches!(version, 3 | 4) {
+            return Err(RkikError::Protocol(format!(
+                "unsupported NTP version {version}"
+            )));
+        }
+        let u32_at =
+            |i: usize| u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let ts_at = |i: usize| NtpTimestamp((u32_at(i) as u64) << 32 | u32_at(i + 4) as u64);
+        Ok(NtpPacket {
+            leap: data[0] >> 6,
+            version,
+            mode: data[0] & 0x7,
+            stratum: data[1],
+            poll: data[2] as i8,
+            precision: data[3] as i8,
+            root_delay: u32_at(4),
+            root_dispersion: u32_at(8),
+            ref_id: [data[12], data[13], data[14], data[15]],
+            reference: ts_at(16),
+            origin: ts_at(24),
+            receive: ts_at(32),
+            transmit: ts_at(40),
+        })
+    }
+
+    /// The reference id as rkik prints it: ASCII for stratum 0 (the
+    /// Kiss-o'-Death code) and 1, else the upstream IPv4 address or, for
+    /// servers reached over IPv6, the hash of its address.
+    pub fn ref_id_string(&self, ipv6: bool) -> String {
+        match self.stratum {
+            0 | 1 => self
+                .ref_id
+                .iter()
+                .take_while(|&&b| b != 0)
+                .map(|&b| if b.is_ascii() { b as char } else { '?' })
+                .collect(),
+            _ if ipv6 => format!("{:#X}", u32::from_be_bytes(self.ref_id)),
+            _ => std::net::Ipv4Addr::from(self.ref_id).to_string(),
+        }
+    }
+}
+
+/// Milliseconds in an NTP short (16.16 fixed point seconds), the format
+/// of the root delay and root dispersion.
+pub(crate) fn short_ms(v: u32) -> f64 {
+    v as f64 / 65_536.0 * 1000.0
+}
+
+/// Milliseconds from `from` to `to`.
+pub(crate) fn millis_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    let delta: TimeDelta = to - from;
+    delta
+        .num_nanoseconds()
+        .map_or(delta.num_milliseconds() as f64, |ns| ns as f64 / 1e6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_and_timestamps_round_trip() {
+        let at = DateTime::parse_from_rfc3339("2025-06-01T12:00:00.25Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let ts = NtpTimestamp::from_datetime(at);
+        assert_eq!(ts.0 >> 32, 3_957_768_000);
+        assert_eq!(ts.to_datetime(), at);
+        // Era 1: seconds wrapped past 2036 come back after it.
+        let later = DateTime::parse_from_rfc3339("2040-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(NtpTimestamp::from_datetime(later).to_datetime(), later);
+
+        let mut reply = NtpPacket::client(at);
+        reply.mode = MODE_SERVER;
+        reply.stratum = 2;
+        reply.ref_id = [192, 0, 2, 9];
+        reply.origin = ts;
+        let decoded = NtpPacket::from_bytes(&reply.to_bytes()).unwrap();
+        assert_eq!(decoded, reply);
+        assert_eq!(decoded.ref_id_string(false), "192.0.2.9");
+        assert_eq!(decoded.ref_id_string(true), "0xC0000209");
+
+        assert!(NtpPacket::from_bytes(&[0x23; 20]).is_err());
+        assert!(NtpPacket::from_bytes(&[0x13; 48]).is_err());
+    }
+}
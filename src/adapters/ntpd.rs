@@ -0,0 +1,431 @@
+//! Client for ntpd's mode 6 control protocol (RFC 9327), the one `ntpq`
+//! speaks, to read a classic ntpd's system variables and peer list.
+//!
+//! Only the read-only `READSTAT` and `READVAR` operations are used; ntpd
+//! serves them without authentication to the local host.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+use super::chrony::{Leap, rand_sequence};
+use crate::error::RkikError;
+
+/// Version 2, mode 6: what `ntpq` sends.
+const VN_MODE: u8 = 2 << 3 | 6;
+const OP_READSTAT: u8 = 1;
+const OP_READVAR: u8 = 2;
+const RESPONSE: u8 = 0x80;
+const ERROR: u8 = 0x40;
+const MORE: u8 = 0x20;
+const HEADER: usize = 12;
+
+/// ntpd's system variables (association 0).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct SystemVars {
+    pub version: Option<String>,
+    pub leap: Leap,
+    pub stratum: u8,
+    pub ref_id: String,
+    /// Association id of the system peer, if one is selected.
+    pub sys_peer: Option<u16>,
+    /// Combined offset of the selected peers (ms, positive when the system
+    /// clock is behind them).
+    pub offset_ms: f64,
+    /// Frequency correction (ppm).
+    pub freq_ppm: f64,
+    pub jitter_ms: f64,
+    pub wander_ppm: f64,
+    pub root_delay_ms: f64,
+    pub root_dispersion_ms: f64,
+    /// log2 of the system poll interval in seconds.
+    pub poll: i8,
+}
+
+/// One association of ntpd's peer list (`ntpq -p` line).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Peer {
+    pub assoc_id: u16,
+    pub address: String,
+    /// Configured name, for pool and DNS associations.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub host: Option<String>,
+    pub ref_id: String,
+    pub stratum: u8,
+    pub selection: Selection,
+    /// Reachability register of the last 8 polls.
+    pub reach: u8,
+    /// log2 of the polling interval in seconds.
+    pub poll: i8,
+    pub delay_ms: f64,
+    /// Peer minus system clock (ms, positive when the peer is ahead).
+    pub offset_ms: f64,
+    pub jitter_ms: f64,
+}
+
+/// Clock selection status of a peer (`ntpq -p` tally code).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum Selection {
+    Reject,
+    Falseticker,
+    Excess,
+    Outlier,
+    Candidate,
+    Backup,
+    SysPeer,
+    PpsPeer,
+}
+
+impl Selection {
+    fn from_status(status: u16) -> Self {
+        match (status >> 8) & 0x7 {
+            0 => Selection::Reject,
+            1 => Selection::Falseticker,
+            2 => Selection::Excess,
+            3 => Selection::Outlier,
+            4 => Selection::Candidate,
+            5 => Selection::Backup,
+            6 => Selection::SysPeer,
+            _ => Selection::PpsPeer,
+        }
+    }
+
+    /// The `ntpq -p` tally code.
+    pub fn symbol(self) -> char {
+        match self {
+            Selection::Reject => ' ',
+            Selection::Falseticker => 'x',
+            Selection::Excess => '.',
+            Selection::Outlier => '-',
+            Selection::Candidate => '+',
+            Selection::Backup => '#',
+            Selection::SysPeer => '*',
+            Selection::PpsPeer => 'o',
+        }
+    }
+}
+
+/// Sends mode 6 queries to ntpd.
+#[derive(Debug, Clone)]
+pub struct NtpdClient {
+    addr: SocketAddr,
+    timeout: Duration,
+}
+
+impl Default for NtpdClient {
+    fn default() -> Self {
+        Self {
+            addr: (Ipv4Addr::LOCALHOST, 123).into(),
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl NtpdClient {
+    /// Client for the ntpd on `127.0.0.1:123`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Client for the ntpd at `addr`.
+    pub fn with_addr(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait for each complete response (default 1 s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn system(&self) -> Result<SystemVars, RkikError> {
+        let vars = parse_vars(&self.request(OP_READVAR, 0).await?);
+        let float = |name: &str| vars.get(name).and_then(|v| v.parse().ok()).unwrap_or(0.0);
+        Ok(SystemVars {
+            version: vars.get("version").cloned(),
+            leap: match vars.get("leap").and_then(|v| v.parse::<u8>().ok()) {
+                Some(0) => Leap::Normal,
+                Some(1) => Leap::InsertSecond,
+                Some(2) => Leap::DeleteSecond,
+                _ => Leap::Unsynchronised,
+            },
+            stratum: vars
+                .get("stratum")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(16),
+            ref_id: vars.get("refid").cloned().unwrap_or_default(),
+            sys_peer: vars
+                .get("peer")
+                .and_then(|v| v.parse().ok())
+                .filter(|&id| id != 0),
+            offset_ms: float("offset"),
+            freq_ppm: float("frequency"),
+            jitter_ms: float("sys_jitter"),
+            wander_ppm: float("clk_wander"),
+            root_delay_ms: float("rootdelay"),
+            root_dispersion_ms: float("rootdisp"),
+            poll: vars.get("tc").and_then(|v| v.parse().ok()).unwrap_or(0),
+        })
+    }
+
+    /// Every association, in ntpd's order.
+    pub async fn peers(&self) -> Result<Vec<Peer>, RkikError> {
+        let status = self.request(OP_READSTAT, 0).await?;
+        let mut peers = Vec::with_capacity(status.len() / 4);
+        for pair in status.chunks_exact(4) {
+            let assoc_id = u16::from_be_bytes([pair[0], pair[1]]);
+            let selection = Selection::from_status(u16::from_be_bytes([pair[2], pair[3]]));
+            let vars = parse_vars(&self.request(OP_READVAR, assoc_id).await?);
+            let float = |name: &str| vars.get(name).and_then(|v| v.parse().ok()).unwrap_or(0.0);
+            peers.push(Peer {
+                assoc_id,
+                address: vars.get("srcadr").cloned().unwrap_or_default(),
+                host: vars.get("srchost").cloned(),
+                ref_id: vars.get("refid").cloned().unwrap_or_default(),
+                stratum: vars
+                    .get("stratum")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(16),
+                selection,
+                reach: vars
+                    .get("reach")
+                    .and_then(|v| u8::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+                    .unwrap_or(0),
+                poll: vars.get("hpoll").and_then(|v| v.parse().ok()).unwrap_or(0),
+                delay_ms: float("delay"),
+                offset_ms: float("offset"),
+                jitter_ms: float("jitter"),
+            });
+        }
+        Ok(peers)
+    }
+
+    /// Send one request and reassemble the data of its response fragments.
+    async fn request(&self, opcode: u8, assoc_id: u16) -> Result<Vec<u8>, RkikError> {
+        let io = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::ConnectionRefused => RkikError::Refused(e.to_string()),
+            _ => RkikError::Network(e.to_string()),
+        };
+        let bind: SocketAddr = match self.addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(bind).await.map_err(io)?;
+        socket.connect(self.addr).await.map_err(io)?;
+
+        let sequence = rand_sequence() as u16;
+        let mut packet = [0u8; HEADER];
+        packet[0] = VN_MODE;
+        packet[1] = opcode;
+        packet[2..4].copy_from_slice(&sequence.to_be_bytes());
+        packet[6..8].copy_from_slice(&assoc_id.to_be_bytes());
+        socket.send(&packet).await.map_err(io)?;
+
+        let collect = async {
+            let mut fragments: Vec<(usize, Vec<u8>)> = Vec::new();
+            let mut end = None;
+            let mut buf = [0u8; 2048];
+            loop {
+                let len = socket.recv(&mut buf).await.map_err(io)?;
+                let Some((offset, data, more)) = fragment(&buf[..len], opcode, sequence)? else {
+                    continue;
+                };
+                if !more {
+                    end = Some(offset + data.len());
+                }
+                fragments.push((offset, data));
+                if let Some(end) = end
+                    && let Some(data) = reassemble(&mut fragments, end)
+                {
+                    return Ok(data);
+                }
+            }
+        };
+        tokio::time::timeout(self.timeout, collect)
+            .await
+            .map_err(|_| RkikError::Timeout)?
+    }
+}
+
+/// Check a response fragment and return its offset, data and more bit;
+/// `None` for datagrams that answer another request.
+fn fragment(
+    bytes: &[u8],
+    opcode: u8,
+    sequence: u16,
+) -> Result<Option<(usize, Vec<u8>, bool)>, RkikError> {
+    if bytes.len() < HEADER
+        || bytes[0] & 0x7 != 6
+        || bytes[1] & RESPONSE == 0
+        || bytes[1] & 0x1f != opcode
+        || u16::from_be_bytes([bytes[2], bytes[3]]) != sequence
+    {
+        return Ok(None);
+    }
+    if bytes[1] & ERROR != 0 {
+        let reason = match bytes[4] {
+            1 => "authentication failure",
+            2 => "invalid message",
+            3 => "unsupported operation",
+            4 => "unknown association",
+            5 => "unknown variable",
+            6 => "invalid variable value",
+            7 => "administratively prohibited",
+            _ => "unspecified error",
+        };
+        return Err(RkikError::Protocol(format!("ntpd: {reason}")));
+    }
+    let offset = u16::from_be_bytes([bytes[8], bytes[9]]) as usize;
+    let count = u16::from_be_bytes([bytes[10], bytes[11]]) as usize;
+    let data = bytes
+        .get(HEADER..HEADER + count)
+        .ok_or_else(|| RkikError::Protocol("ntpd: truncated response".into()))?;
+    Ok(Some((offset, data.to_vec(), bytes[1] & MORE != 0)))
+}
+
+/// The response data once `fragments` cover `0..end` without gaps.
+fn reassemble(fragments: &mut [(usize, Vec<u8>)], end: usize) -> Option<Vec<u8>> {
+    fragments.sort_by_key(|(offset, _)| *offset);
+    let mut data = Vec::with_capacity(end);
+    for (offset, chunk) in fragments.iter() {
+        if *offset > data.len() {
+            return None;
+        }
+        let skip = data.len() - offset;
+        data.extend(chunk.iter().skip(skip));
+    }
+    (data.len() >= end).then(|| {
+        data.truncate(end);
+        data
+    })
+}
+
+/// Split `name=value, name="quoted, value"` text into a map.
+fn parse_vars(data: &[u8]) -> HashMap<String, String> {
+    let text = String::from_utf8_lossy(data);
+    let mut vars = HashMap::new();
+    let mut item = String::new();
+    let mut quoted = false;
+    for c in text.chars().chain(std::iter::once(',')) {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                if let Some((name, value)) = item.split_once('=') {
+                    vars.insert(name.trim().to_string(), value.trim().to_string());
+                }
+                item.clear();
+            }
+            '\r' | '\n' if !quoted => {}
+            c => item.push(c),
+        }
+    }
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYSTEM: &str = "version=\"ntpd 4.2.8p15@1.3728-o, built\", leap=0, stratum=2,\r\n\
+        refid=192.0.2.1, peer=41234, tc=6, offset=0.125, frequency=-12.345,\r\n\
+        sys_jitter=0.050, clk_wander=0.002, rootdelay=10.5, rootdisp=20.25";
+
+    /// Answer mode 6 requests on a local UDP socket, splitting readvar
+    /// responses into 40-byte fragments.
+    async fn fake_ntpd() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                let req = buf[..len].to_vec();
+                let assoc = u16::from_be_bytes([req[6], req[7]]);
+                let data: Vec<u8> = match (req[1], assoc) {
+                    (OP_READSTAT, _) => [41234u16, 0x9614, 41235, 0x9424]
+                        .iter()
+                        .flat_map(|w| w.to_be_bytes())
+                        .collect(),
+                    (OP_READVAR, 0) => SYSTEM.into(),
+                    (OP_READVAR, 41234) => "srcadr=192.0.2.1, srcport=123, stratum=1, refid=GPS, reach=0xff, hpoll=6, delay=1.5, offset=0.2, jitter=0.03".into(),
+                    (OP_READVAR, 41235) => "srcadr=192.0.2.2, srchost=\"pool.example\", stratum=2, refid=192.0.2.9, reach=0x3f, hpoll=7, delay=3.0, offset=-0.4, jitter=0.1".into(),
+                    _ => {
+                        let mut out = req[..HEADER].to_vec();
+                        out[1] |= RESPONSE | ERROR;
+                        out[4] = 4;
+                        socket.send_to(&out, peer).await.unwrap();
+                        continue;
+                    }
+                };
+                // Send the last fragment first: order must not matter.
+                let chunks: Vec<_> = data.chunks(40).enumerate().collect();
+                for (i, chunk) in chunks.iter().rev() {
+                    let mut out = req[..HEADER].to_vec();
+                    out[1] |= RESPONSE;
+                    if *i + 1 < chunks.len() {
+                        out[1] |= MORE;
+                    }
+                    out[8..10].copy_from_slice(&((i * 40) as u16).to_be_bytes());
+                    out[10..12].copy_from_slice(&(chunk.len() as u16).to_be_bytes());
+                    out.extend_from_slice(chunk);
+                    out.resize(out.len().next_multiple_of(4), 0);
+                    socket.send_to(&out, peer).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn variables_keep_quoted_commas() {
+        let vars = parse_vars(SYSTEM.as_bytes());
+        assert_eq!(vars["version"], "ntpd 4.2.8p15@1.3728-o, built");
+        assert_eq!(vars["rootdisp"], "20.25");
+        assert_eq!(vars.len(), 12);
+    }
+
+    #[tokio::test]
+    async fn system_and_peers_from_fragmented_responses() {
+        let client = NtpdClient::with_addr(fake_ntpd().await).timeout(Duration::from_secs(2));
+
+        let system = client.system().await.unwrap();
+        assert_eq!(
+            system.version.as_deref(),
+            Some("ntpd 4.2.8p15@1.3728-o, built")
+        );
+        assert_eq!((system.leap, system.stratum), (Leap::Normal, 2));
+        assert_eq!(
+            (system.ref_id.as_str(), system.sys_peer),
+            ("192.0.2.1", Some(41234))
+        );
+        assert_eq!((system.offset_ms, system.freq_ppm), (0.125, -12.345));
+        assert_eq!(system.root_dispersion_ms, 20.25);
+
+        let peers = client.peers().await.unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].selection.symbol(), '*');
+        assert_eq!(
+            (peers[0].address.as_str(), peers[0].reach),
+            ("192.0.2.1", 0xff)
+        );
+        assert_eq!(peers[1].selection, Selection::Candidate);
+        assert_eq!(peers[1].host.as_deref(), Some("pool.example"));
+        assert_eq!((peers[1].poll, peers[1].offset_ms), (7, -0.4));
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_reported() {
+        let client = NtpdClient::with_addr(fake_ntpd().await);
+        let err = client.request(OP_READVAR, 7).await.unwrap_err();
+        assert!(err.to_string().contains("unknown association"), "{err}");
+    }
+}
@@ -2,19 +2,27 @@
 
 #[cfg(feature = "nts")]
 use rkik_nts::{NtsClient, NtsClientConfig, error::Error as NtsLibError};
+#[cfg(feature = "nts")]
+use std::collections::HashMap;
+#[cfg(feature = "nts")]
+use std::sync::Mutex;
+#[cfg(feature = "nts")]
+use std::sync::atomic::Ordering;
 
 use chrono::{DateTime, Utc};
 use std::time::Duration;
 
+#[cfg(feature = "nts")]
+use super::reuse;
 use crate::error::RkikError;
 
 #[cfg(feature = "json")]
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Machine-readable NTS validation error kinds.
 /// Stable taxonomy for programmatic consumption.
 #[derive(Debug, Clone, PartialEq, Eq)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 #[serde(rename_all = "snake_case")]
 pub enum NtsErrorKind {
     /// NTS-KE handshake failed (TLS or protocol error)
@@ -92,7 +100,7 @@ impl std::fmt::Display for NtsErrorKind {
 
 /// Structured NTS error with machine-readable kind and human-readable message.
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct NtsError {
     /// Machine-readable error classification
     pub kind: NtsErrorKind,
@@ -118,7 +126,7 @@ impl std::fmt::Display for NtsError {
 /// NTS validation outcome for successful probes.
 /// Captures whether NTS validation succeeded or failed after NTS-KE.
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct NtsValidationOutcome {
     /// Whether the response was cryptographically authenticated
     pub authenticated: bool,
@@ -145,7 +153,7 @@ impl NtsValidationOutcome {
 
 /// Result of an NTS time query containing all relevant timing and authentication data.
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct NtsTimeResult {
     /// The network time received from the NTS server
     pub network_time: DateTime<Utc>,
@@ -165,7 +173,7 @@ pub struct NtsTimeResult {
 
 /// NTS-KE (Key Exchange) diagnostic data
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct NtsKeData {
     /// Duration of the NTS-KE handshake (TLS + key exchange)
     pub ke_duration_ms: f64,
@@ -184,7 +192,7 @@ pub struct NtsKeData {
 
 /// TLS Certificate information from NTS-KE handshake
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct CertificateInfo {
     /// Subject of the certificate (CN, O, etc.)
     pub subject: String,
@@ -208,6 +216,16 @@ pub struct CertificateInfo {
     pub is_self_signed: bool,
 }
 
+/// Security-critical kinds become [`RkikError::AuthFailed`], the rest [`RkikError::Nts`].
+#[cfg(feature = "nts")]
+fn nts_failure(message: String, kind: &NtsErrorKind) -> RkikError {
+    if kind.plugin_exit_code() == 2 {
+        RkikError::AuthFailed(message)
+    } else {
+        RkikError::Nts(message)
+    }
+}
+
 #[cfg(feature = "nts")]
 fn map_nts_error(err: &NtsLibError) -> NtsErrorKind {
     match err {
@@ -259,33 +277,41 @@ pub async fn query_nts(
     nts_ke_port: Option<u16>,
     timeout: Duration,
 ) -> Result<NtsTimeResult, RkikError> {
-    // Configure NTS client
-    let mut config = NtsClientConfig::new(server);
-
-    if let Some(port) = nts_ke_port {
-        config = config.with_port(port);
-    }
-
-    config = config.with_timeout(timeout);
-
-    // Create and connect NTS client
-    let mut client = NtsClient::new(config);
-
-    // Perform NTS-KE handshake
-    client.connect().await.map_err(|e| {
-        let kind = map_nts_error(&e);
-        RkikError::Nts(format!("NTS-KE failed: {} [{}]", e, kind))
-    })?;
-
-    // Get authenticated time
-    let time_snapshot = client.get_time().await.map_err(|e| {
-        let kind = map_nts_error(&e);
-        RkikError::Nts(format!("NTS time query failed: {} [{}]", e, kind))
-    })?;
+    let port = nts_ke_port.unwrap_or(4460);
+    let key = (server.to_string(), port);
+    let kept = if reuse::active() {
+        take_kept(&key).filter(|c| c.cookie_count() > 0)
+    } else {
+        None
+    };
+    let (client, time_snapshot) = match kept {
+        Some(mut client) => match client.get_time().await {
+            Ok(snapshot) => {
+                reuse::NTS_SESSIONS_REUSED.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!(
+                    server,
+                    cookies = client.cookie_count(),
+                    "NTS session reused"
+                );
+                (client, snapshot)
+            }
+            // Keys rotated or the session went stale: start over.
+            Err(e) => {
+                tracing::debug!(server, error = %e, "kept NTS session failed");
+                fresh_time(server, nts_ke_port, timeout).await?
+            }
+        },
+        None => fresh_time(server, nts_ke_port, timeout).await?,
+    };
+    tracing::debug!(
+        server,
+        authenticated = time_snapshot.authenticated,
+        "NTS-protected NTP reply"
+    );
 
     // Check if response is authenticated - reject unauthenticated responses after NTS-KE
     if !time_snapshot.authenticated {
-        return Err(RkikError::Nts(format!(
+        return Err(RkikError::AuthFailed(format!(
             "NTS validation failed: server returned unauthenticated response after NTS-KE [{}]",
             NtsErrorKind::UnauthenticatedResponse
         )));
@@ -330,12 +356,14 @@ pub async fn query_nts(
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_nanos() as i128;
-    // Positive = server ahead of local (local clock is slow) — same convention as rsntp/NTP path.
+    // Positive = server ahead of local (local clock is slow) — same convention as the NTP path.
     let offset_ms = (net_ns - sys_ns) as f64 / 1_000_000.0;
 
     // Convert round_trip_delay from Duration to milliseconds
     let rtt_ms = time_snapshot.round_trip_delay.as_secs_f64() * 1000.0;
 
+    keep(key, client);
+
     // Convert to our result format
     Ok(NtsTimeResult {
         network_time,
@@ -348,6 +376,83 @@ pub async fn query_nts(
     })
 }
 
+/// A new session: NTS-KE handshake, then one authenticated query.
+#[cfg(feature = "nts")]
+async fn fresh_time(
+    server: &str,
+    nts_ke_port: Option<u16>,
+    timeout: Duration,
+) -> Result<(NtsClient, rkik_nts::TimeSnapshot), RkikError> {
+    // Configure NTS client
+    let mut config = NtsClientConfig::new(server);
+
+    if let Some(port) = nts_ke_port {
+        config = config.with_port(port);
+    }
+
+    config = config.with_timeout(timeout);
+
+    // Create and connect NTS client
+    let mut client = NtsClient::new(config);
+
+    // Perform NTS-KE handshake
+    tracing::debug!(
+        server,
+        port = nts_ke_port.unwrap_or(4460),
+        "NTS-KE handshake"
+    );
+    reuse::NTS_HANDSHAKES.fetch_add(1, Ordering::Relaxed);
+    client.connect().await.map_err(|e| {
+        let kind = map_nts_error(&e);
+        nts_failure(format!("NTS-KE failed: {} [{}]", e, kind), &kind)
+    })?;
+    if let Some(ke) = client.nts_ke_info() {
+        tracing::debug!(
+            server,
+            ntp_server = %ke.ntp_server,
+            aead = %ke.aead_algorithm,
+            cookies = ke.initial_cookie_count,
+            ke_ms = ke.ke_duration.as_secs_f64() * 1000.0,
+            "NTS-KE complete"
+        );
+    }
+
+    // Get authenticated time
+    let time_snapshot = client.get_time().await.map_err(|e| {
+        let kind = map_nts_error(&e);
+        nts_failure(format!("NTS time query failed: {} [{}]", e, kind), &kind)
+    })?;
+    Ok((client, time_snapshot))
+}
+
+/// NTS sessions kept by a [`reuse::ReuseScope`], per NTS-KE server and port.
+#[cfg(feature = "nts")]
+static KEPT: Mutex<Option<HashMap<(String, u16), NtsClient>>> = Mutex::new(None);
+
+#[cfg(feature = "nts")]
+fn take_kept(key: &(String, u16)) -> Option<NtsClient> {
+    KEPT.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_mut()
+        .and_then(|kept| kept.remove(key))
+}
+
+#[cfg(feature = "nts")]
+fn keep(key: (String, u16), client: NtsClient) {
+    if reuse::active() {
+        KEPT.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(HashMap::new)
+            .insert(key, client);
+    }
+}
+
+/// Close every kept session; called when the last scope ends.
+pub(crate) fn close_kept() {
+    #[cfg(feature = "nts")]
+    KEPT.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
 /// Stub function when NTS feature is disabled
 #[cfg(not(feature = "nts"))]
 pub async fn query_nts(
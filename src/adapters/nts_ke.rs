@@ -0,0 +1,290 @@
+//! A timed NTS-KE handshake (RFC 8915 §4), for diagnostics.
+//!
+//! [`nts_client`](super::nts_client) leaves the key exchange to `rkik-nts`,
+//! which does not say how the TLS session went. This module runs the same
+//! exchange itself: TLS 1.3 with ALPN `ntske/1`, an NTPv4 and
+//! AES-SIV-CMAC-256 request, the server's records up to End of Message. It
+//! times each stage and reports what TLS negotiated. No keys are exported
+//! and the cookies are thrown away.
+//!
+//! Every handshake of the process shares one TLS client configuration and
+//! so one session cache: the first handshake with a server is full, the
+//! next ones resume with the ticket it sent, as a long-running client
+//! would. rustls keeps tickets in memory only, so a new process starts
+//! cold.
+
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, HandshakeKind, RootCertStore, crypto, version};
+
+use crate::adapters::resolver;
+use crate::error::RkikError;
+
+/// ALPN protocol of NTS-KE.
+pub const ALPN: &[u8] = b"ntske/1";
+
+const END_OF_MESSAGE: u16 = 0;
+const NEXT_PROTOCOL: u16 = 1;
+const ERROR: u16 = 2;
+const AEAD_ALGORITHM: u16 = 4;
+const NEW_COOKIE: u16 = 5;
+const CRITICAL: u16 = 0x8000;
+/// AEAD_AES_SIV_CMAC_256, the algorithm every NTS server supports.
+const AES_SIV_CMAC_256: u16 = 15;
+/// Responses larger than this are not NTS-KE.
+const MAX_RESPONSE: usize = 64 * 1024;
+
+/// How one NTS-KE handshake went, stage by stage.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct KeHandshake {
+    pub ip: IpAddr,
+    pub port: u16,
+    /// Negotiated TLS version (`TLSv1_3`).
+    pub tls_version: String,
+    /// Negotiated cipher suite (`TLS13_AES_256_GCM_SHA384`).
+    pub cipher_suite: String,
+    /// Negotiated ALPN protocol, `ntske/1` for a compliant server.
+    pub alpn: Option<String>,
+    /// The TLS session was resumed from a ticket of an earlier handshake.
+    pub resumed: bool,
+    /// The server asked for another key share (HelloRetryRequest), which
+    /// costs a round trip.
+    pub hello_retry: bool,
+    /// Name resolution (ms).
+    pub dns_ms: f64,
+    /// TCP connection (ms).
+    pub tcp_ms: f64,
+    /// TLS handshake (ms).
+    pub tls_ms: f64,
+    /// NTS-KE request up to the server's End of Message (ms).
+    pub ke_ms: f64,
+    /// All of the above (ms).
+    pub total_ms: f64,
+    /// AEAD algorithm the server chose (IANA number, 15 for AES-SIV-CMAC-256).
+    pub aead_algorithm: Option<u16>,
+    pub cookie_count: usize,
+}
+
+/// Run one NTS-KE exchange with `host` on `port`, within `timeout`.
+pub async fn handshake(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    ipv6_only: bool,
+) -> Result<KeHandshake, RkikError> {
+    tokio::time::timeout(timeout, handshake_inner(host, port, ipv6_only))
+        .await
+        .map_err(|_| RkikError::Timeout)?
+        .map_err(|e| e.with_target(host))
+}
+
+async fn handshake_inner(host: &str, port: u16, ipv6_only: bool) -> Result<KeHandshake, RkikError> {
+    let ms = |since: Instant| since.elapsed().as_secs_f64() * 1000.0;
+    let started = Instant::now();
+    let ip = resolver::lookup(host, ipv6_only).await?.ip;
+    let dns_ms = ms(started);
+
+    let connecting = Instant::now();
+    let tcp = TcpStream::connect((ip, port))
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::ConnectionRefused => RkikError::Refused(e.to_string()),
+            _ => RkikError::Network(e.to_string()),
+        })?;
+    let tcp_ms = ms(connecting);
+
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|e| RkikError::Other(format!("invalid TLS server name '{host}': {e}")))?;
+    let securing = Instant::now();
+    let mut tls = TlsConnector::from(config()?)
+        .connect(name, tcp)
+        .await
+        .map_err(|e| RkikError::Nts(format!("NTS-KE TLS handshake failed: {e}")))?;
+    let tls_ms = ms(securing);
+    let session = tls.get_ref().1;
+    let tls_version = session
+        .protocol_version()
+        .map_or_else(|| "unknown".into(), |v| format!("{v:?}"));
+    let cipher_suite = session
+        .negotiated_cipher_suite()
+        .map_or_else(|| "unknown".into(), |s| format!("{:?}", s.suite()));
+    let alpn = session
+        .alpn_protocol()
+        .map(|p| String::from_utf8_lossy(p).into_owned());
+    let kind = session.handshake_kind();
+
+    let exchanging = Instant::now();
+    tls.write_all(&request()).await.map_err(io_error)?;
+    let records = read_response(&mut tls).await?;
+    let ke_ms = ms(exchanging);
+    // Best effort: the measurement is complete either way.
+    let _ = tls.shutdown().await;
+    let (aead_algorithm, cookie_count) = summarize(&records)?;
+
+    Ok(KeHandshake {
+        ip,
+        port,
+        tls_version,
+        cipher_suite,
+        alpn,
+        resumed: kind == Some(HandshakeKind::Resumed),
+        hello_retry: kind == Some(HandshakeKind::FullWithHelloRetryRequest),
+        dns_ms,
+        tcp_ms,
+        tls_ms,
+        ke_ms,
+        total_ms: ms(started),
+        aead_algorithm,
+        cookie_count,
+    })
+}
+
+/// The TLS configuration of every handshake, and with it the session cache.
+fn config() -> Result<Arc<ClientConfig>, RkikError> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    if let Some(config) = CONFIG.get() {
+        return Ok(Arc::clone(config));
+    }
+    let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    // Explicit provider: other dependencies may enable a second one.
+    let mut config =
+        ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+            .with_protocol_versions(&[&version::TLS13])
+            .map_err(|e| RkikError::Other(e.to_string()))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    config.alpn_protocols = vec![ALPN.to_vec()];
+    Ok(Arc::clone(CONFIG.get_or_init(|| Arc::new(config))))
+}
+
+/// Encode one NTS-KE record.
+fn record(critical: bool, kind: u16, body: &[u8]) -> Vec<u8> {
+    let kind = if critical { kind | CRITICAL } else { kind };
+    let mut out = Vec::with_capacity(4 + body.len());
+    out.extend_from_slice(&kind.to_be_bytes());
+    out.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+/// NTPv4 with AES-SIV-CMAC-256, as clients ask for it.
+fn request() -> Vec<u8> {
+    let mut out = record(true, NEXT_PROTOCOL, &0u16.to_be_bytes());
+    out.extend(record(
+        true,
+        AEAD_ALGORITHM,
+        &AES_SIV_CMAC_256.to_be_bytes(),
+    ));
+    out.extend(record(true, END_OF_MESSAGE, &[]));
+    out
+}
+
+/// Read records, type without the critical bit and body, up to End of Message.
+async fn read_response<S>(stream: &mut S) -> Result<Vec<(u16, Vec<u8>)>, RkikError>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut records = Vec::new();
+    let mut read = 0;
+    loop {
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).await.map_err(|e| {
+            RkikError::Nts(format!("NTS-KE response ended before End of Message: {e}"))
+        })?;
+        let kind = u16::from_be_bytes([header[0], header[1]]) & !CRITICAL;
+        let len = usize::from(u16::from_be_bytes([header[2], header[3]]));
+        read += 4 + len;
+        if read > MAX_RESPONSE {
+            return Err(RkikError::Nts("NTS-KE response too large".into()));
+        }
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await.map_err(io_error)?;
+        if kind == END_OF_MESSAGE {
+            return Ok(records);
+        }
+        records.push((kind, body));
+    }
+}
+
+/// The AEAD algorithm and the number of cookies of a response, or the
+/// error it carries.
+fn summarize(records: &[(u16, Vec<u8>)]) -> Result<(Option<u16>, usize), RkikError> {
+    let u16_body = |body: &[u8]| (body.len() >= 2).then(|| u16::from_be_bytes([body[0], body[1]]));
+    if let Some((_, body)) = records.iter().find(|(kind, _)| *kind == ERROR) {
+        let code = u16_body(body).map_or_else(|| "?".into(), |c| c.to_string());
+        return Err(RkikError::Nts(format!(
+            "NTS-KE server returned error {code}"
+        )));
+    }
+    let aead = records
+        .iter()
+        .find(|(kind, _)| *kind == AEAD_ALGORITHM)
+        .and_then(|(_, body)| u16_body(body));
+    let cookies = records
+        .iter()
+        .filter(|(kind, _)| *kind == NEW_COOKIE)
+        .count();
+    Ok((aead, cookies))
+}
+
+fn io_error(e: std::io::Error) -> RkikError {
+    RkikError::Network(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_asks_for_ntpv4_and_aes_siv() {
+        assert_eq!(
+            request(),
+            [
+                0x80, 0x01, 0x00, 0x02, 0x00, 0x00, // Next Protocol: NTPv4
+                0x80, 0x04, 0x00, 0x02, 0x00, 0x0F, // AEAD: AES-SIV-CMAC-256
+                0x80, 0x00, 0x00, 0x00, // End of Message
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn response_records_are_read_up_to_end_of_message() {
+        let mut response = record(true, NEXT_PROTOCOL, &[0, 0]);
+        response.extend(record(true, AEAD_ALGORITHM, &[0, 15]));
+        for _ in 0..8 {
+            response.extend(record(false, NEW_COOKIE, &[0xAB; 100]));
+        }
+        response.extend(record(true, END_OF_MESSAGE, &[]));
+        response.extend(b"trailing");
+        let records = read_response(&mut response.as_slice()).await.unwrap();
+        assert_eq!(summarize(&records).unwrap(), (Some(15), 8));
+
+        let refused = record(true, ERROR, &[0, 1]);
+        let truncated = read_response(&mut refused.as_slice()).await;
+        assert!(truncated.is_err());
+        assert!(summarize(&[(ERROR, vec![0, 1])]).is_err());
+    }
+
+    #[tokio::test]
+    async fn a_server_without_tls_fails_the_handshake() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let _ = conn.write_all(b"SSH-2.0-OpenSSH\r\n").await;
+        });
+        let err = handshake("127.0.0.1", port, Duration::from_secs(2), false)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), "nts", "{err}");
+    }
+}
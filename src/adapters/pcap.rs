@@ -0,0 +1,216 @@
+//! Packet capture of the NTP exchanges rkik makes, in pcap format.
+//!
+//! The capture holds the UDP payloads exactly as sent and received, wrapped
+//! in reconstructed IPv4/IPv6 and UDP headers (link type `RAW`) and stamped
+//! with nanosecond capture times, so Wireshark and tcpdump decode them as
+//! NTP. Only plain NTP goes through [`ntp_client::exchange`](super::ntp_client::exchange);
+//! NTS exchanges happen inside the NTS client and are not captured.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+/// Nanosecond-resolution pcap magic.
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+/// `LINKTYPE_RAW`: packets start with the IP header.
+const LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65_535;
+const UDP: u8 = 17;
+
+/// Writes UDP datagrams to a pcap stream.
+pub struct PcapWriter<W: Write> {
+    out: W,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    /// Create (or truncate) the capture file at `path`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Start a capture on `out` by writing the pcap file header.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&MAGIC_NANOS.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        out.write_all(&header)?;
+        out.flush()?;
+        Ok(Self { out })
+    }
+
+    /// Append the datagram `payload` from `src` to `dst`, seen at `at`, and flush it.
+    pub fn write_udp(
+        &mut self,
+        at: DateTime<Utc>,
+        src: SocketAddr,
+        dst: SocketAddr,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let packet = ip_udp_packet(src, dst, payload)?;
+        let len = packet.len() as u32;
+        let secs = u32::try_from(at.timestamp()).unwrap_or(0);
+        self.out.write_all(&secs.to_le_bytes())?;
+        self.out
+            .write_all(&at.timestamp_subsec_nanos().to_le_bytes())?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(&packet)?;
+        self.out.flush()
+    }
+}
+
+fn ip_udp_packet(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let udp_len = u16::try_from(8 + payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "datagram too large"))?;
+    let mut udp = Vec::with_capacity(udp_len as usize);
+    udp.extend_from_slice(&src.port().to_be_bytes());
+    udp.extend_from_slice(&dst.port().to_be_bytes());
+    udp.extend_from_slice(&udp_len.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]);
+    udp.extend_from_slice(payload);
+
+    let mut packet = Vec::with_capacity(40 + udp.len());
+    let pseudo = match (src.ip(), dst.ip()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            let total = 20 + udp_len;
+            let mut ip = [0u8; 20];
+            ip[0] = 0x45;
+            ip[2..4].copy_from_slice(&total.to_be_bytes());
+            ip[6] = 0x40; // don't fragment
+            ip[8] = 64;
+            ip[9] = UDP;
+            ip[12..16].copy_from_slice(&s.octets());
+            ip[16..20].copy_from_slice(&d.octets());
+            let sum = checksum(&[&ip]);
+            ip[10..12].copy_from_slice(&sum.to_be_bytes());
+            packet.extend_from_slice(&ip);
+            [
+                &s.octets()[..],
+                &d.octets()[..],
+                &[0, UDP],
+                &udp_len.to_be_bytes(),
+            ]
+            .concat()
+        }
+        (IpAddr::V6(s), IpAddr::V6(d)) => {
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            packet.extend_from_slice(&udp_len.to_be_bytes());
+            packet.extend_from_slice(&[UDP, 64]);
+            packet.extend_from_slice(&s.octets());
+            packet.extend_from_slice(&d.octets());
+            [
+                &s.octets()[..],
+                &d.octets()[..],
+                &(udp_len as u32).to_be_bytes(),
+                &[0, 0, 0, UDP],
+            ]
+            .concat()
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "source and destination address families differ",
+            ));
+        }
+    };
+    let sum = match checksum(&[&pseudo, &udp]) {
+        0 => 0xffff,
+        sum => sum,
+    };
+    udp[6..8].copy_from_slice(&sum.to_be_bytes());
+    packet.extend_from_slice(&udp);
+    Ok(packet)
+}
+
+/// Internet checksum (RFC 1071) over the concatenation of `parts`, each of
+/// even length except possibly the last.
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    for part in parts {
+        for pair in part.chunks(2) {
+            let word = u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]);
+            sum += word as u32;
+        }
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+static CAPTURE: Mutex<Option<PcapWriter<BufWriter<File>>>> = Mutex::new(None);
+
+/// Capture every NTP exchange of this process to a new pcap file at `path`,
+/// replacing any capture in progress.
+pub fn start_capture(path: &Path) -> io::Result<()> {
+    let writer = PcapWriter::create(path)?;
+    *CAPTURE.lock().unwrap_or_else(|e| e.into_inner()) = Some(writer);
+    Ok(())
+}
+
+/// Stop capturing and close the file.
+pub fn stop_capture() {
+    CAPTURE.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Add one datagram to the capture, if one is running. A write error ends
+/// the capture, with a warning, rather than the probe.
+pub(crate) fn capture(at: DateTime<Utc>, src: SocketAddr, dst: SocketAddr, payload: &[u8]) {
+    let mut guard = CAPTURE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(writer) = guard.as_mut()
+        && let Err(e) = writer.write_udp(at, src, dst, payload)
+    {
+        tracing::warn!(error = %e, "packet capture stopped");
+        *guard = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datagrams_get_valid_ip_and_udp_headers() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        let at = DateTime::from_timestamp(1_700_000_000, 123_456_789).unwrap();
+        let client: SocketAddr = "192.0.2.10:40000".parse().unwrap();
+        let server: SocketAddr = "192.0.2.1:123".parse().unwrap();
+        writer.write_udp(at, client, server, &[0x23; 48]).unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:123".parse().unwrap();
+        let v6_client: SocketAddr = "[2001:db8::2]:40000".parse().unwrap();
+        writer.write_udp(at, v6, v6_client, &[0x24; 48]).unwrap();
+        assert!(writer.write_udp(at, client, v6, &[]).is_err());
+
+        let bytes = writer.out;
+        assert_eq!(&bytes[..4], &MAGIC_NANOS.to_le_bytes());
+        assert_eq!(&bytes[20..24], &LINKTYPE_RAW.to_le_bytes());
+
+        // First record: 16-byte header, then IPv4 (20) + UDP (8) + 48.
+        let rec = &bytes[24..];
+        assert_eq!(&rec[4..8], &123_456_789u32.to_le_bytes());
+        assert_eq!(&rec[8..12], &76u32.to_le_bytes());
+        let ip = &rec[16..36];
+        assert_eq!(checksum(&[ip]), 0);
+        let udp = &rec[36..92];
+        assert_eq!(&udp[2..4], &123u16.to_be_bytes());
+        let pseudo = [&[192, 0, 2, 10, 192, 0, 2, 1, 0, UDP][..], &[0, 56]].concat();
+        assert_eq!(checksum(&[&pseudo, udp]), 0);
+
+        // Second record: IPv6 (40) + UDP (8) + 48.
+        let rec = &rec[92..];
+        assert_eq!(&rec[8..12], &96u32.to_le_bytes());
+        assert_eq!(rec[16] >> 4, 6);
+        assert_eq!(rec.len(), 16 + 96);
+    }
+}
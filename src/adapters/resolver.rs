@@ -1,40 +1,370 @@
+use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
 
+use hickory_resolver::TokioResolver;
+use hickory_resolver::config::LookupIpStrategy;
+#[cfg(feature = "dnssec")]
+use hickory_resolver::proto::{dnssec::Proof, rr::RData};
+
+use crate::domain::ntp::Resolution;
 use crate::error::RkikError;
 
 /// Resolve the IP address for a host name according to IPv4/IPv6 mode.
 pub fn resolve_ip(target: &str, ipv6_only: bool) -> Result<IpAddr, RkikError> {
+    resolve_system(target, ipv6_only).map(|r| r.ip)
+}
+
+fn resolve_system(target: &str, ipv6_only: bool) -> Result<Resolved, RkikError> {
     let port = 123;
-    let addrs: Vec<SocketAddr> = (target, port)
+    let mut addresses: Vec<IpAddr> = Vec::new();
+    for addr in (target, port)
         .to_socket_addrs()
         .map_err(|e| RkikError::Dns(format!("{}", e)))?
-        .collect();
-
-    let filtered: Vec<IpAddr> = if ipv6_only {
-        addrs
-            .iter()
-            .map(|a| a.ip())
-            .filter(|ip| ip.is_ipv6())
-            .collect()
+    {
+        if !addresses.contains(&addr.ip()) {
+            addresses.push(addr.ip());
+        }
+    }
+    let ip = pick(addresses.iter().copied(), ipv6_only, None)
+        .ok_or_else(|| not_found(target, ipv6_only))?;
+    tracing::debug!(host = target, %ip, addresses = addresses.len(), "resolved by the system resolver");
+    Ok(Resolved {
+        ip,
+        dnssec: false,
+        source: Resolution::System,
+        addresses,
+    })
+}
+
+/// Interface index for the zone of a link-local IPv6 address.
+///
+/// Accepts a numeric index (`3`) or an interface name (`eth0`), which the
+/// system resolver maps to its index.
+pub fn scope_id(zone: &str) -> Result<u32, RkikError> {
+    if let Ok(index) = zone.parse::<u32>() {
+        return Ok(index);
+    }
+    let unknown = || RkikError::Dns(format!("unknown network interface '{zone}'"));
+    let addrs = (format!("fe80::1%{zone}"), 123)
+        .to_socket_addrs()
+        .map_err(|_| unknown())?;
+    addrs
+        .filter_map(|a| match a {
+            SocketAddr::V6(v6) if v6.scope_id() != 0 => Some(v6.scope_id()),
+            _ => None,
+        })
+        .next()
+        .ok_or_else(unknown)
+}
+
+static CACHE_ENABLED: AtomicBool = AtomicBool::new(true);
+static REQUIRE_DNSSEC: AtomicBool = AtomicBool::new(false);
+static CACHE: OnceLock<Mutex<HashMap<(String, bool), Cached>>> = OnceLock::new();
+
+/// Address chosen for a target by [`lookup`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub ip: IpAddr,
+    /// The address record was DNSSEC-validated (see [`set_require_dnssec`]).
+    pub dnssec: bool,
+    /// Where the answer came from.
+    pub source: Resolution,
+    /// Every address of the answer, `ip` included.
+    pub addresses: Vec<IpAddr>,
+}
+
+/// A resolved address, valid until the shortest TTL of its record set runs out.
+struct Cached {
+    resolved: Resolved,
+    valid_until: Instant,
+}
+
+/// Enable or disable the answer cache used by [`lookup`] (enabled by default).
+pub fn set_cache_enabled(enabled: bool) {
+    CACHE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Make [`lookup`] accept only DNSSEC-validated answers (feature `dnssec`).
+///
+/// Names are then resolved with a validating resolver built on the system
+/// configuration; unsigned zones, bogus signatures and unreachable
+/// DNSSEC records all fail with [`RkikError::Dns`]. IP literals need no
+/// resolution and are accepted as is.
+#[cfg(feature = "dnssec")]
+pub fn set_require_dnssec(required: bool) {
+    REQUIRE_DNSSEC.store(required, Ordering::Relaxed);
+}
+
+/// Resolve `host` like [`resolve_ip`], reusing the previous answer while its DNS TTL lasts.
+///
+/// When the TTL expires and the record still contains the address used so
+/// far, that address is kept, so repeated probes stay on the same server of
+/// a round-robin name. With the cache disabled, and for names the DNS
+/// cannot answer (`/etc/nsswitch.conf` sources, unreadable resolver
+/// configuration), the call goes through [`resolve_ip`] uncached — unless
+/// DNSSEC is required, in which case these are errors.
+pub async fn lookup(host: &str, ipv6_only: bool) -> Result<Resolved, RkikError> {
+    let dnssec = REQUIRE_DNSSEC.load(Ordering::Relaxed);
+    let cached = CACHE_ENABLED.load(Ordering::Relaxed);
+    let system = || resolve_system(host, ipv6_only);
+    if host.parse::<IpAddr>().is_ok() {
+        return system().map(|r| Resolved {
+            source: Resolution::Literal,
+            ..r
+        });
+    }
+    if !(cached || dnssec) {
+        return system();
+    }
+    let cache = CACHE.get_or_init(Default::default);
+    let key = (host.to_ascii_lowercase(), ipv6_only);
+    let mut previous = None;
+    if cached {
+        let cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = cache.get(&key) {
+            if entry.valid_until > Instant::now() && (entry.resolved.dnssec || !dnssec) {
+                tracing::debug!(host, ip = %entry.resolved.ip, "DNS answer from cache");
+                return Ok(Resolved {
+                    source: Resolution::Cache,
+                    ..entry.resolved.clone()
+                });
+            }
+            previous = Some(entry.resolved.ip);
+        }
+    }
+
+    // The resolver is built per lookup: it spawns its connections on the
+    // current runtime, and misses only happen once per TTL.
+    let mut builder = match TokioResolver::builder_tokio() {
+        Ok(builder) => builder,
+        Err(e) if dnssec => {
+            return Err(RkikError::Dns(format!(
+                "cannot read the resolver configuration: {e}"
+            )));
+        }
+        Err(_) => return system(),
+    };
+    let options = builder.options_mut();
+    options.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+    #[cfg(feature = "dnssec")]
+    {
+        options.validate = dnssec;
+    }
+    let answer = match builder.build().lookup_ip(host).await {
+        Ok(answer) => answer,
+        Err(e) if dnssec => {
+            return Err(RkikError::Dns(format!(
+                "DNSSEC lookup of '{host}' failed: {e}"
+            )));
+        }
+        Err(_) => return system(),
+    };
+    #[cfg(feature = "dnssec")]
+    let addrs = if dnssec {
+        secure_addrs(answer.as_lookup()).map_err(|proof| {
+            RkikError::Dns(format!(
+                "DNSSEC validation failed for '{host}': answer is {}",
+                proof.to_string().to_lowercase()
+            ))
+        })?
     } else {
-        let mut v4 = vec![];
-        let mut v6 = vec![];
-        for a in addrs {
-            let ip = a.ip();
-            if ip.is_ipv4() {
-                v4.push(ip);
-            } else {
-                v6.push(ip);
+        answer.iter().collect()
+    };
+    #[cfg(not(feature = "dnssec"))]
+    let addrs: Vec<IpAddr> = answer.iter().collect();
+    tracing::debug!(host, ?addrs, dnssec, "DNS answer");
+    let ip = pick(addrs.iter().copied(), ipv6_only, previous)
+        .ok_or_else(|| not_found(host, ipv6_only))?;
+    let resolved = Resolved {
+        ip,
+        dnssec,
+        source: Resolution::Dns,
+        addresses: addrs,
+    };
+    if cached {
+        cache.lock().unwrap_or_else(|e| e.into_inner()).insert(
+            key,
+            Cached {
+                resolved: resolved.clone(),
+                valid_until: answer.valid_until(),
+            },
+        );
+    }
+    Ok(resolved)
+}
+
+/// Every address `host` resolves to (A and AAAA records), bypassing the
+/// cache; IP literals are returned as is.
+pub async fn lookup_all(host: &str) -> Result<Vec<IpAddr>, RkikError> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+    let system = || {
+        let mut ips: Vec<IpAddr> = Vec::new();
+        for addr in (host, 123)
+            .to_socket_addrs()
+            .map_err(|e| RkikError::Dns(format!("{}", e)))?
+        {
+            if !ips.contains(&addr.ip()) {
+                ips.push(addr.ip());
             }
         }
-        v4.into_iter().chain(v6).collect()
+        Ok(ips)
     };
+    let Ok(mut builder) = TokioResolver::builder_tokio() else {
+        return system();
+    };
+    builder.options_mut().ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+    match builder.build().lookup_ip(host).await {
+        Ok(answer) => Ok(answer.iter().collect()),
+        Err(_) => system(),
+    }
+}
 
-    filtered.into_iter().next().ok_or_else(|| {
-        if ipv6_only {
-            RkikError::Dns(format!("No IPv6 address found for '{}'", target))
+/// Addresses of `lookup` proven Secure, or the weakest proof found if none is.
+#[cfg(feature = "dnssec")]
+fn secure_addrs(lookup: &hickory_resolver::lookup::Lookup) -> Result<Vec<IpAddr>, Proof> {
+    let mut secure = Vec::new();
+    let mut weakest: Option<Proof> = None;
+    for record in lookup.dnssec_iter() {
+        let (proof, data) = record.into_parts();
+        let ip = match data {
+            RData::A(a) => IpAddr::V4(a.0),
+            RData::AAAA(a) => IpAddr::V6(a.0),
+            _ => continue,
+        };
+        if proof == Proof::Secure {
+            secure.push(ip);
         } else {
-            RkikError::Dns(format!("No IP address found for '{}'", target))
+            weakest = Some(weakest.map_or(proof, |w| w.min(proof)));
         }
-    })
+    }
+    if secure.is_empty() {
+        Err(weakest.unwrap_or_default())
+    } else {
+        Ok(secure)
+    }
+}
+
+/// One SRV record: a host offering the service and its selection parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub host: String,
+    pub port: u16,
+    pub priority: u16,
+    pub weight: u16,
+}
+
+/// Look up the SRV records of `name` (e.g. `_ntp._udp.example.com`).
+///
+/// A name without SRV records yields an empty list, as does a record whose
+/// target is `.` ("service decidedly not available", RFC 2782).
+pub async fn lookup_srv(name: &str) -> Result<Vec<SrvRecord>, RkikError> {
+    let builder = TokioResolver::builder_tokio()
+        .map_err(|e| RkikError::Dns(format!("cannot read the resolver configuration: {e}")))?;
+    let answer = match builder.build().srv_lookup(name).await {
+        Ok(answer) => answer,
+        Err(e) if e.is_no_records_found() || e.is_nx_domain() => return Ok(Vec::new()),
+        Err(e) => return Err(RkikError::Dns(e.to_string())),
+    };
+    Ok(answer
+        .iter()
+        .filter(|srv| !srv.target().is_root())
+        .map(|srv| SrvRecord {
+            host: srv.target().to_utf8().trim_end_matches('.').to_string(),
+            port: srv.port(),
+            priority: srv.priority(),
+            weight: srv.weight(),
+        })
+        .collect())
+}
+
+/// PTR name of `ip`, or `None` if the address has no reverse record.
+pub async fn reverse_lookup(ip: IpAddr) -> Result<Option<String>, RkikError> {
+    let builder = TokioResolver::builder_tokio()
+        .map_err(|e| RkikError::Dns(format!("cannot read the resolver configuration: {e}")))?;
+    let answer = match builder.build().reverse_lookup(ip).await {
+        Ok(answer) => answer,
+        Err(e) if e.is_no_records_found() || e.is_nx_domain() => return Ok(None),
+        Err(e) => return Err(RkikError::Dns(e.to_string())),
+    };
+    Ok(answer
+        .iter()
+        .next()
+        .map(|ptr| ptr.0.to_utf8().trim_end_matches('.').to_string()))
+}
+
+/// First IPv4 address, else first IPv6 (only IPv6 if `ipv6_only`);
+/// `keep` wins if it is still among the candidates.
+fn pick(
+    addrs: impl Iterator<Item = IpAddr>,
+    ipv6_only: bool,
+    keep: Option<IpAddr>,
+) -> Option<IpAddr> {
+    let mut v4 = vec![];
+    let mut v6 = vec![];
+    for ip in addrs {
+        if ip.is_ipv4() {
+            if !ipv6_only {
+                v4.push(ip);
+            }
+        } else {
+            v6.push(ip);
+        }
+    }
+    if let Some(ip) = keep
+        && (v4.contains(&ip) || v6.contains(&ip))
+    {
+        return Some(ip);
+    }
+    v4.into_iter().chain(v6).next()
+}
+
+fn not_found(target: &str, ipv6_only: bool) -> RkikError {
+    if ipv6_only {
+        RkikError::Dns(format!("No IPv6 address found for '{}'", target))
+    } else {
+        RkikError::Dns(format!("No IP address found for '{}'", target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_prefers_ipv4_and_keeps_previous_address() {
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        let addrs = [v6, a, b];
+        assert_eq!(pick(addrs.into_iter(), false, None), Some(a));
+        assert_eq!(pick(addrs.into_iter(), true, None), Some(v6));
+        assert_eq!(pick(addrs.into_iter(), false, Some(b)), Some(b));
+        // A previous address that left the record set is replaced.
+        let gone: IpAddr = "192.0.2.9".parse().unwrap();
+        assert_eq!(pick(addrs.into_iter(), false, Some(gone)), Some(a));
+        assert_eq!(pick(addrs.into_iter(), true, Some(a)), Some(v6));
+    }
+
+    #[test]
+    fn scope_id_accepts_indexes_and_interface_names() {
+        assert_eq!(scope_id("7").unwrap(), 7);
+        if cfg!(target_os = "linux") {
+            assert!(scope_id("lo").is_ok());
+        }
+        assert!(scope_id("no-such-if0").is_err());
+    }
+
+    #[tokio::test]
+    async fn lookup_passes_literals_through() {
+        let resolved = lookup("2001:db8::5", false).await.unwrap();
+        assert_eq!(resolved.ip, "2001:db8::5".parse::<IpAddr>().unwrap());
+        assert!(!resolved.dnssec);
+        assert_eq!(resolved.source, Resolution::Literal);
+        assert_eq!(resolved.addresses, [resolved.ip]);
+    }
 }
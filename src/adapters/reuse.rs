@@ -0,0 +1,76 @@
+//! Keeping UDP sockets and NTS sessions open across the probes of a loop.
+//!
+//! By default every NTP exchange binds a fresh ephemeral port and every NTS
+//! query runs its own NTS-KE handshake. While a [`ReuseScope`] is alive,
+//! [`ntp_client::exchange`](super::ntp_client::exchange) keeps the connected
+//! socket of each server for the next exchange with it, and
+//! [`nts_client::query_nts`](super::nts_client::query_nts) keeps the session
+//! (keys and cookies) of each NTS server. Repeated probes then leave from
+//! one source port, as interleaved mode (RFC 5905 9.2) requires, and NTS
+//! loops do one handshake instead of one per probe.
+//!
+//! A socket whose exchange failed or timed out is closed rather than kept,
+//! so a late reply cannot be mistaken for the answer to the next request.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+static SCOPES: AtomicUsize = AtomicUsize::new(0);
+pub(crate) static SOCKETS_OPENED: AtomicU64 = AtomicU64::new(0);
+pub(crate) static SOCKETS_REUSED: AtomicU64 = AtomicU64::new(0);
+pub(crate) static NTS_HANDSHAKES: AtomicU64 = AtomicU64::new(0);
+pub(crate) static NTS_SESSIONS_REUSED: AtomicU64 = AtomicU64::new(0);
+
+/// Sockets and sessions are kept while this is alive; dropping the last
+/// scope closes them.
+#[must_use = "sockets are only kept while the scope is alive"]
+#[derive(Debug)]
+pub struct ReuseScope {
+    _private: (),
+}
+
+/// Keep sockets and NTS sessions open until the returned scope is dropped.
+/// Scopes nest: everything is closed when the last one goes.
+pub fn keep_open() -> ReuseScope {
+    SCOPES.fetch_add(1, Ordering::Relaxed);
+    ReuseScope { _private: () }
+}
+
+impl Drop for ReuseScope {
+    fn drop(&mut self) {
+        if SCOPES.fetch_sub(1, Ordering::Relaxed) == 1 {
+            super::ntp_client::close_kept();
+            super::nts_client::close_kept();
+        }
+    }
+}
+
+/// Whether a [`ReuseScope`] is alive.
+pub(crate) fn active() -> bool {
+    SCOPES.load(Ordering::Relaxed) > 0
+}
+
+/// Counters since the process started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct ReuseStats {
+    /// UDP sockets bound for NTP exchanges.
+    pub sockets_opened: u64,
+    /// NTP exchanges that went out on a kept socket.
+    pub sockets_reused: u64,
+    /// NTS-KE handshakes performed.
+    pub nts_handshakes: u64,
+    /// NTS queries answered on a kept session, without a handshake.
+    pub nts_sessions_reused: u64,
+}
+
+pub fn stats() -> ReuseStats {
+    ReuseStats {
+        sockets_opened: SOCKETS_OPENED.load(Ordering::Relaxed),
+        sockets_reused: SOCKETS_REUSED.load(Ordering::Relaxed),
+        nts_handshakes: NTS_HANDSHAKES.load(Ordering::Relaxed),
+        nts_sessions_reused: NTS_SESSIONS_REUSED.load(Ordering::Relaxed),
+    }
+}
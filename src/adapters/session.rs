@@ -0,0 +1,179 @@
+//! Session files written by `rkik --record` and read back by `rkik replay`.
+//!
+//! A session starts with the 8-byte magic `RKIKSES1`, followed by one frame
+//! per [`Record`]: a little-endian `u32` length, then the record as JSON.
+//! Frames are flushed as they are written, so a session interrupted by
+//! Ctrl+C or a crash stays readable up to its last complete frame.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::ntp::ProbeResult;
+use crate::error::RkikError;
+
+/// Leading bytes of every session file; the digit is the format version.
+pub const MAGIC: &[u8; 8] = b"RKIKSES1";
+
+/// Frames larger than this are treated as corruption rather than allocated.
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// One recorded event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    /// A successful probe, offsets relative to the local clock.
+    Probe(Box<ProbeResult>),
+    /// A probe that failed, with the stable [`RkikError::kind`].
+    Failure {
+        target: String,
+        kind: String,
+        message: String,
+        at: DateTime<Utc>,
+    },
+}
+
+impl Record {
+    /// Failure record for `err`, naming the target it carries or else `target`.
+    pub fn failure(target: &str, err: &RkikError) -> Self {
+        Record::Failure {
+            target: err.target().unwrap_or(target).to_string(),
+            kind: err.kind().to_string(),
+            message: err.message(),
+            at: Utc::now(),
+        }
+    }
+}
+
+/// Appends records to a session.
+pub struct SessionWriter<W: Write> {
+    out: W,
+}
+
+impl SessionWriter<BufWriter<File>> {
+    /// Create (or truncate) the session file at `path`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> SessionWriter<W> {
+    /// Start a session on `out` by writing the magic.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.flush()?;
+        Ok(Self { out })
+    }
+
+    /// Append one record and flush it.
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        let payload = serde_json::to_vec(record)?;
+        let len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too large"))?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(&payload)?;
+        self.out.flush()
+    }
+}
+
+/// Read every record of a session.
+///
+/// A truncated last frame, left by an interrupted recording, is ignored;
+/// a wrong magic or an undecodable frame is an error.
+pub fn read_session(mut input: impl Read) -> Result<Vec<Record>, RkikError> {
+    let corrupt = |what: String| RkikError::Other(format!("not a valid rkik session: {what}"));
+    let mut magic = [0u8; 8];
+    input
+        .read_exact(&mut magic)
+        .map_err(|_| corrupt("file too short".into()))?;
+    if &magic != MAGIC {
+        return Err(corrupt("unknown header".into()));
+    }
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+
+    let mut records = Vec::new();
+    let mut rest = data.as_slice();
+    while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+        let len = u32::from_le_bytes(*len) as usize;
+        if len > MAX_FRAME {
+            return Err(corrupt(format!(
+                "frame {} is {len} bytes",
+                records.len() + 1
+            )));
+        }
+        let Some((payload, tail)) = tail.split_at_checked(len) else {
+            break;
+        };
+        let record = serde_json::from_slice(payload)
+            .map_err(|e| corrupt(format!("frame {}: {e}", records.len() + 1)))?;
+        records.push(record);
+        rest = tail;
+    }
+    Ok(records)
+}
+
+/// Read the session file at `path`.
+pub fn open_session(path: &Path) -> Result<Vec<Record>, RkikError> {
+    read_session(File::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::{Leap, Target};
+
+    fn probe() -> ProbeResult {
+        let utc = Utc::now();
+        ProbeResult {
+            target: Target::new("time.example", "192.0.2.1".parse().unwrap(), 123),
+            offset_ms: 1.25,
+            rtt_ms: 9.5,
+            stratum: 2,
+            ref_id: "192.0.2.9".into(),
+            utc,
+            local: utc.into(),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn records_round_trip_and_truncated_tail_is_ignored() {
+        let mut writer = SessionWriter::new(Vec::new()).unwrap();
+        writer.write(&Record::Probe(Box::new(probe()))).unwrap();
+        let err = RkikError::Timeout.with_target("192.0.2.2");
+        writer.write(&Record::failure("ignored", &err)).unwrap();
+        let mut bytes = writer.out;
+
+        let records = read_session(bytes.as_slice()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(matches!(&records[0], Record::Probe(p) if p.offset_ms == 1.25 && p.stratum == 2));
+        assert!(
+            matches!(&records[1], Record::Failure { target, kind, .. } if target == "192.0.2.2" && kind == "timeout")
+        );
+
+        // A frame cut short by an interrupted recording.
+        bytes.extend_from_slice(&100u32.to_le_bytes());
+        bytes.extend_from_slice(b"{\"type\"");
+        assert_eq!(read_session(bytes.as_slice()).unwrap().len(), 2);
+
+        assert!(read_session(&b"RKIKSES9"[..]).is_err());
+    }
+}
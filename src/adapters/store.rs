@@ -0,0 +1,223 @@
+//! Probe history in SQLite, written by `rkik --store` and read back by
+//! `rkik history`.
+//!
+//! Every probe and failure is one row of the `probes` table, keyed by
+//! server name and time, in the same shape as a [`session`](super::session)
+//! record.
+
+use std::path::Path;
+
+use chrono::{DateTime, Local, Utc};
+use rusqlite::{Connection, params};
+
+use super::session::Record;
+use crate::domain::ntp::{Leap, ProbeResult, Target};
+use crate::error::RkikError;
+
+/// Schema version kept in `PRAGMA user_version`.
+const SCHEMA_VERSION: i32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS probes (
+    id            INTEGER PRIMARY KEY,
+    at_ms         INTEGER NOT NULL,
+    server        TEXT NOT NULL,
+    ip            TEXT,
+    port          INTEGER,
+    offset_ms     REAL,
+    rtt_ms        REAL,
+    stratum       INTEGER,
+    ref_id        TEXT,
+    authenticated INTEGER,
+    dnssec        INTEGER,
+    error_kind    TEXT,
+    error         TEXT
+);
+CREATE INDEX IF NOT EXISTS probes_server_at ON probes (server, at_ms);
+";
+
+/// An open history database.
+pub struct ProbeStore {
+    conn: Connection,
+}
+
+impl ProbeStore {
+    /// Open the database at `path`, creating it and its table if needed.
+    pub fn open(path: &Path) -> Result<Self, RkikError> {
+        let conn = Connection::open(path).map_err(store_error)?;
+        let version: i32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(store_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(RkikError::Other(format!(
+                "{}: history schema {version} is newer than this rkik supports ({SCHEMA_VERSION})",
+                path.display()
+            )));
+        }
+        conn.execute_batch(SCHEMA).map_err(store_error)?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)
+            .map_err(store_error)?;
+        Ok(Self { conn })
+    }
+
+    /// Append one probe or failure.
+    pub fn insert(&self, record: &Record) -> Result<(), RkikError> {
+        match record {
+            Record::Probe(r) => self.conn.execute(
+                "INSERT INTO probes (at_ms, server, ip, port, offset_ms, rtt_ms, stratum, ref_id, authenticated, dnssec)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    r.utc.timestamp_millis(),
+                    r.target.name,
+                    r.target.ip.to_string(),
+                    r.target.port,
+                    r.offset_ms,
+                    r.rtt_ms,
+                    r.stratum,
+                    r.ref_id,
+                    r.authenticated,
+                    r.dnssec,
+                ],
+            ),
+            Record::Failure {
+                target,
+                kind,
+                message,
+                at,
+            } => self.conn.execute(
+                "INSERT INTO probes (at_ms, server, error_kind, error) VALUES (?1, ?2, ?3, ?4)",
+                params![at.timestamp_millis(), target, kind, message],
+            ),
+        }
+        .map(|_| ())
+        .map_err(store_error)
+    }
+
+    /// Rows since `since`, oldest first, for `server` or every server.
+    pub fn history(
+        &self,
+        server: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Record>, RkikError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT at_ms, server, ip, port, offset_ms, rtt_ms, stratum, ref_id, authenticated, dnssec, error_kind, error
+                 FROM probes WHERE at_ms >= ?1 AND (?2 IS NULL OR server = ?2) ORDER BY at_ms, id",
+            )
+            .map_err(store_error)?;
+        let rows = stmt
+            .query_map(params![since.timestamp_millis(), server], |row| {
+                let at = DateTime::from_timestamp_millis(row.get(0)?).unwrap_or_default();
+                let server: String = row.get(1)?;
+                if let Some(kind) = row.get::<_, Option<String>>(10)? {
+                    return Ok(Record::Failure {
+                        target: server,
+                        kind,
+                        message: row.get::<_, Option<String>>(11)?.unwrap_or_default(),
+                        at,
+                    });
+                }
+                let ip: String = row.get(2)?;
+                Ok(Record::Probe(Box::new(ProbeResult {
+                    target: Target::new(
+                        server,
+                        ip.parse().unwrap_or(std::net::Ipv4Addr::UNSPECIFIED.into()),
+                        row.get(3)?,
+                    ),
+                    offset_ms: row.get(4)?,
+                    rtt_ms: row.get(5)?,
+                    stratum: row.get(6)?,
+                    ref_id: row.get(7)?,
+                    utc: at,
+                    local: DateTime::<Local>::from(at),
+                    timestamp: at.timestamp(),
+                    authenticated: row.get(8)?,
+                    dnssec: row.get(9)?,
+                    leap: Leap::Normal,
+                    health: Default::default(),
+                    pause_ms: None,
+                    origin: None,
+                    timing: None,
+                    uncertainty_ms: None,
+                    clock_step_ms: None,
+                    reference_age_s: None,
+                    #[cfg(feature = "nts")]
+                    nts_ke_data: None,
+                    #[cfg(feature = "nts")]
+                    nts_validation: None,
+                })))
+            })
+            .map_err(store_error)?;
+        rows.collect::<Result<_, _>>().map_err(store_error)
+    }
+}
+
+fn store_error(e: rusqlite::Error) -> RkikError {
+    RkikError::Other(format!("history database: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(name: &str, at: DateTime<Utc>) -> ProbeResult {
+        ProbeResult {
+            target: Target::new(name, "192.0.2.1".parse().unwrap(), 123),
+            offset_ms: 1.5,
+            rtt_ms: 8.0,
+            stratum: 2,
+            ref_id: "192.0.2.9".into(),
+            utc: at,
+            local: at.into(),
+            timestamp: at.timestamp(),
+            authenticated: false,
+            dnssec: true,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn history_filters_by_server_and_time() {
+        let dir = std::env::temp_dir().join(format!("rkik-store-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.db");
+        let _ = std::fs::remove_file(&path);
+
+        let now = Utc::now();
+        let old = now - chrono::Duration::hours(48);
+        let store = ProbeStore::open(&path).unwrap();
+        store
+            .insert(&Record::Probe(Box::new(probe("a", old))))
+            .unwrap();
+        store
+            .insert(&Record::Probe(Box::new(probe("a", now))))
+            .unwrap();
+        store
+            .insert(&Record::Probe(Box::new(probe("b", now))))
+            .unwrap();
+        let err = RkikError::Timeout.with_target("a");
+        store.insert(&Record::failure("a", &err)).unwrap();
+        drop(store);
+
+        let store = ProbeStore::open(&path).unwrap();
+        let since = now - chrono::Duration::hours(24);
+        let rows = store.history(Some("a"), since).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(&rows[0], Record::Probe(p) if p.dnssec && p.stratum == 2));
+        assert!(matches!(&rows[1], Record::Failure { kind, .. } if kind == "timeout"));
+        assert_eq!(store.history(None, old).unwrap().len(), 4);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
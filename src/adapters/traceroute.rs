@@ -0,0 +1,310 @@
+//! UDP path probing toward a time server, as `tracepath` does: NTP requests
+//! sent with increasing TTLs, the routers' ICMP errors read back through
+//! `IP_RECVERR` (no privileges needed). Probing needs Linux and the `sync`
+//! feature.
+#![cfg_attr(not(all(feature = "sync", target_os = "linux")), allow(dead_code))]
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use crate::error::RkikError;
+
+/// ICMP "time exceeded" (IPv4) and "hop limit exceeded" (IPv6) types.
+const ICMP_TIME_EXCEEDED: u8 = 11;
+const ICMP6_TIME_EXCEEDED: u8 = 3;
+
+/// How [`trace`] probes.
+#[derive(Debug, Clone, Copy)]
+pub struct TraceOptions {
+    /// Highest TTL tried (default 30).
+    pub max_hops: u8,
+    /// Wait for each hop (default 1 s).
+    pub timeout: Duration,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self {
+            max_hops: 30,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// What came back for one TTL.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct TraceHop {
+    pub ttl: u8,
+    /// The router or server that answered; `None` when nothing did.
+    pub address: Option<IpAddr>,
+    pub rtt_ms: Option<f64>,
+    /// True for the last hop, when the probe reached the server's host:
+    /// an NTP reply or an ICMP error other than "time exceeded".
+    pub reached: bool,
+}
+
+/// What an ICMP error reported on a probe means for the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Icmp {
+    /// A router on the way dropped the probe at TTL 0.
+    TimeExceeded,
+    /// The probe got no further: port unreachable from the server's host,
+    /// or a router rejecting it.
+    Unreachable,
+}
+
+fn classify(ipv6: bool, icmp_type: u8) -> Icmp {
+    let exceeded = if ipv6 {
+        ICMP6_TIME_EXCEEDED
+    } else {
+        ICMP_TIME_EXCEEDED
+    };
+    if icmp_type == exceeded {
+        Icmp::TimeExceeded
+    } else {
+        Icmp::Unreachable
+    }
+}
+
+/// Trace the path to `addr`, one probe per TTL, until it is reached or
+/// `max_hops` is exhausted.
+#[cfg(all(feature = "sync", target_os = "linux"))]
+pub async fn trace(addr: SocketAddr, options: TraceOptions) -> Result<Vec<TraceHop>, RkikError> {
+    tokio::task::spawn_blocking(move || {
+        let mut hops = Vec::new();
+        for ttl in 1..=options.max_hops.max(1) {
+            let hop = linux::probe(addr, ttl, options.timeout)?;
+            let reached = hop.reached;
+            hops.push(hop);
+            if reached {
+                break;
+            }
+        }
+        Ok(hops)
+    })
+    .await
+    .map_err(|e| RkikError::Other(e.to_string()))?
+}
+
+/// Trace the path to `addr`, one probe per TTL, until it is reached or
+/// `max_hops` is exhausted.
+#[cfg(not(all(feature = "sync", target_os = "linux")))]
+pub async fn trace(_addr: SocketAddr, _options: TraceOptions) -> Result<Vec<TraceHop>, RkikError> {
+    Err(RkikError::Other(
+        "path probing needs Linux and the sync feature".into(),
+    ))
+}
+
+#[cfg(all(feature = "sync", target_os = "linux"))]
+mod linux {
+    use std::io;
+    use std::mem::{size_of, zeroed};
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+    use std::os::fd::AsRawFd;
+    use std::time::{Duration, Instant};
+
+    use chrono::Utc;
+
+    use super::{Icmp, TraceHop, classify};
+    use crate::adapters::ntp_packet::NtpPacket;
+    use crate::error::RkikError;
+
+    pub(super) fn probe(
+        addr: SocketAddr,
+        ttl: u8,
+        timeout: Duration,
+    ) -> Result<TraceHop, RkikError> {
+        // A socket per hop, so a late error from a previous TTL is not
+        // taken for this one.
+        let bind: SocketAddr = if addr.is_ipv6() {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(addr)?;
+        let fd = socket.as_raw_fd();
+        let (level, recverr, hops) = if addr.is_ipv6() {
+            (
+                libc::IPPROTO_IPV6,
+                libc::IPV6_RECVERR,
+                libc::IPV6_UNICAST_HOPS,
+            )
+        } else {
+            (libc::IPPROTO_IP, libc::IP_RECVERR, libc::IP_TTL)
+        };
+        set_int(fd, level, recverr, 1)?;
+        set_int(fd, level, hops, ttl as libc::c_int)?;
+
+        let sent = Instant::now();
+        socket.send(&NtpPacket::client(Utc::now()).to_bytes())?;
+        let mut hop = TraceHop {
+            ttl,
+            address: None,
+            rtt_ms: None,
+            reached: false,
+        };
+        let deadline = sent + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(hop);
+            }
+            let mut pfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: one valid pollfd.
+            let n = unsafe { libc::poll(&mut pfd, 1, left.as_millis().max(1) as libc::c_int) };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err.into());
+            }
+            if n == 0 {
+                return Ok(hop);
+            }
+            let rtt_ms = Some(sent.elapsed().as_secs_f64() * 1000.0);
+            if pfd.revents & libc::POLLERR != 0 {
+                let Some((from, icmp)) = read_error(fd, addr.is_ipv6())? else {
+                    continue;
+                };
+                hop.address = Some(from);
+                hop.rtt_ms = rtt_ms;
+                hop.reached = icmp == Icmp::Unreachable;
+                return Ok(hop);
+            }
+            let mut buf = [0u8; 1024];
+            if socket.recv(&mut buf).is_ok() {
+                hop.address = Some(addr.ip());
+                hop.rtt_ms = rtt_ms;
+                hop.reached = true;
+                return Ok(hop);
+            }
+        }
+    }
+
+    fn set_int(
+        fd: libc::c_int,
+        level: libc::c_int,
+        name: libc::c_int,
+        value: libc::c_int,
+    ) -> io::Result<()> {
+        // SAFETY: a c_int option value of the right size.
+        let rc = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                (&value as *const libc::c_int).cast(),
+                size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Read one ICMP error from the socket's error queue: who sent it and
+    /// what it says.
+    fn read_error(
+        fd: libc::c_int,
+        ipv6: bool,
+    ) -> Result<Option<(std::net::IpAddr, Icmp)>, RkikError> {
+        let mut data = [0u8; 1024];
+        let mut control = [0u8; 512];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        // SAFETY: msghdr is plain data; the buffers outlive the call.
+        let mut msg: libc::msghdr = unsafe { zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control.len();
+        let n = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_ERRQUEUE) };
+        if n < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // SAFETY: walking the control messages recvmsg filled in.
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+            let is_err = (header.cmsg_level == libc::IPPROTO_IP
+                && header.cmsg_type == libc::IP_RECVERR)
+                || (header.cmsg_level == libc::IPPROTO_IPV6
+                    && header.cmsg_type == libc::IPV6_RECVERR);
+            if is_err {
+                let ee = unsafe { libc::CMSG_DATA(cmsg) } as *const libc::sock_extended_err;
+                let ee_val = unsafe { ee.read_unaligned() };
+                let icmp = ee_val.ee_origin == libc::SO_EE_ORIGIN_ICMP
+                    || ee_val.ee_origin == libc::SO_EE_ORIGIN_ICMP6;
+                if icmp {
+                    let offender = unsafe { libc::SO_EE_OFFENDER(ee) };
+                    if let Some(from) = unsafe { sockaddr_ip(offender) } {
+                        return Ok(Some((from, classify(ipv6, ee_val.ee_type))));
+                    }
+                }
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+        Ok(None)
+    }
+
+    /// # Safety
+    /// `sa` must point to a sockaddr_in or sockaddr_in6.
+    unsafe fn sockaddr_ip(sa: *const libc::sockaddr) -> Option<std::net::IpAddr> {
+        match unsafe { (*sa).sa_family } as libc::c_int {
+            libc::AF_INET => {
+                let sin = unsafe { (sa as *const libc::sockaddr_in).read_unaligned() };
+                Some(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)).into())
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { (sa as *const libc::sockaddr_in6).read_unaligned() };
+                Some(Ipv6Addr::from(sin6.sin6_addr.s6_addr).into())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icmp_errors_are_classified() {
+        assert_eq!(classify(false, 11), Icmp::TimeExceeded);
+        assert_eq!(classify(true, 3), Icmp::TimeExceeded);
+        // Destination unreachable: port (v4 type 3) and port (v6 type 1).
+        assert_eq!(classify(false, 3), Icmp::Unreachable);
+        assert_eq!(classify(true, 1), Icmp::Unreachable);
+    }
+
+    #[cfg(all(feature = "sync", target_os = "linux"))]
+    #[tokio::test]
+    async fn loopback_is_reached_at_the_first_hop() {
+        // Nothing listens on the loopback's discard port: the host answers
+        // with port unreachable.
+        let options = TraceOptions {
+            max_hops: 3,
+            timeout: Duration::from_millis(500),
+        };
+        let hops = trace("127.0.0.1:9".parse().unwrap(), options)
+            .await
+            .unwrap();
+        assert_eq!(hops.len(), 1);
+        assert!(hops[0].reached);
+        assert_eq!(hops[0].address, Some("127.0.0.1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,156 @@
+//! The NTP exchange behind [`query_via`](crate::services::query::query_via),
+//! as a trait so services can run against other implementations.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::ntp_client::{self, Exchange};
+use crate::domain::ntp::{Health, Leap, Timing};
+use crate::error::RkikError;
+
+/// What one NTP request/response exchange measured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NtpSample {
+    pub offset_ms: f64,
+    pub rtt_ms: f64,
+    pub stratum: u8,
+    pub ref_id: String,
+    pub leap: Leap,
+    /// Sanity checks on the reply.
+    pub health: Health,
+    /// Server time at the moment of the measurement.
+    pub utc: DateTime<Utc>,
+    /// T1 to T4 and the time spent around them, when measured.
+    pub timing: Option<Timing>,
+    /// Bound on the error of `offset_ms`, when the reply carries one.
+    pub uncertainty_ms: Option<f64>,
+    /// Time since the server last heard from its reference (s).
+    pub reference_age_s: Option<f64>,
+}
+
+/// Sends one NTP request to `addr` and measures the answer.
+///
+/// Implementations must give up after `timeout` with
+/// [`RkikError::Timeout`], which retries treat as transient. A link-local
+/// IPv6 `addr` carries its scope id, which must select the interface the
+/// request leaves on.
+pub trait NtpTransport: Send + Sync {
+    fn query(
+        &self,
+        addr: SocketAddr,
+        ipv6: bool,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<NtpSample, RkikError>> + Send;
+}
+
+/// Plain UDP through [`ntp_client::exchange`]; what [`query`](crate::services::query::query) uses.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UdpTransport;
+
+impl NtpTransport for UdpTransport {
+    async fn query(
+        &self,
+        addr: SocketAddr,
+        ipv6: bool,
+        timeout: Duration,
+    ) -> Result<NtpSample, RkikError> {
+        ntp_client::exchange(addr, ipv6, timeout)
+            .await
+            .map(NtpSample::from)
+    }
+}
+
+impl From<Exchange> for NtpSample {
+    fn from(ex: Exchange) -> Self {
+        let offset_ms = ex.offset_ms();
+        NtpSample {
+            offset_ms,
+            rtt_ms: ex.rtt_ms(),
+            stratum: ex.reply.stratum,
+            ref_id: ex.ref_id(),
+            leap: Leap::from_indicator(ex.reply.leap),
+            health: ex.health(),
+            utc: ex.received + TimeDelta::nanoseconds((offset_ms * 1e6) as i64),
+            timing: Some(ex.timing()),
+            uncertainty_ms: Some(ex.uncertainty_ms()),
+            reference_age_s: ex.reference_age_s(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::MockTransport;
+
+#[cfg(any(test, feature = "test-util"))]
+mod mock {
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Scripted replies (feature `test-util`), answered at once.
+    ///
+    /// Queued replies for an address are used first, in order, then its
+    /// standing reply; an address with neither times out. Every request is
+    /// recorded for [`requests`](MockTransport::requests).
+    #[derive(Debug, Default)]
+    pub struct MockTransport {
+        queued: Mutex<HashMap<SocketAddr, VecDeque<Result<NtpSample, RkikError>>>>,
+        standing: HashMap<SocketAddr, NtpSample>,
+        requests: Mutex<Vec<SocketAddr>>,
+    }
+
+    impl MockTransport {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Answer every request to `addr` with `sample`.
+        pub fn with_sample(mut self, addr: SocketAddr, sample: NtpSample) -> Self {
+            self.standing.insert(addr, sample);
+            self
+        }
+
+        /// Answer the next request to `addr` with `reply`.
+        pub fn push(&self, addr: SocketAddr, reply: Result<NtpSample, RkikError>) {
+            self.queued
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(addr)
+                .or_default()
+                .push_back(reply);
+        }
+
+        /// Addresses queried so far, in order.
+        pub fn requests(&self) -> Vec<SocketAddr> {
+            self.requests
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone()
+        }
+    }
+
+    impl NtpTransport for MockTransport {
+        async fn query(
+            &self,
+            addr: SocketAddr,
+            _ipv6: bool,
+            _timeout: Duration,
+        ) -> Result<NtpSample, RkikError> {
+            self.requests
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(addr);
+            let queued = self
+                .queued
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get_mut(&addr)
+                .and_then(VecDeque::pop_front);
+            queued.unwrap_or_else(|| self.standing.get(&addr).cloned().ok_or(RkikError::Timeout))
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! State of the Windows Time service (W32Time), from `w32tm /query /status
+//! /verbose` and `GetSystemTimeAdjustment`. Only available on Windows.
+#![cfg_attr(not(windows), allow(dead_code))]
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use super::chrony::Leap;
+use crate::error::RkikError;
+
+/// What W32Time reports about its synchronization.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct W32TimeStatus {
+    pub leap: Leap,
+    pub stratum: u8,
+    /// Configured time source, without its `,0x9` flags.
+    pub source: String,
+    /// Reference: the source IP when W32Time names one, else the raw id.
+    pub ref_id: String,
+    /// As printed by `w32tm`, in the host's locale.
+    pub last_sync: Option<String>,
+    /// Offset still being corrected, as `w32tm` reports it (ms).
+    pub phase_offset_ms: Option<f64>,
+    pub root_delay_ms: Option<f64>,
+    pub root_dispersion_ms: Option<f64>,
+    /// log2 of the poll interval in seconds.
+    pub poll: Option<i8>,
+    pub adjustment: Option<TimeAdjustment>,
+}
+
+/// `GetSystemTimeAdjustment`: how much the clock advances per tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct TimeAdjustment {
+    /// 100 ns units added to the clock per tick.
+    pub adjustment: u32,
+    /// Nominal tick length (100 ns units).
+    pub increment: u32,
+    /// True when Windows does not apply `adjustment`.
+    pub disabled: bool,
+}
+
+impl TimeAdjustment {
+    /// Frequency correction implied by `adjustment` (ppm, positive when
+    /// the clock is sped up).
+    pub fn freq_ppm(&self) -> f64 {
+        if self.disabled || self.increment == 0 {
+            return 0.0;
+        }
+        (self.adjustment as f64 - self.increment as f64) / self.increment as f64 * 1e6
+    }
+}
+
+/// Query W32Time through `w32tm`; fails when the service is stopped.
+#[cfg(windows)]
+pub fn status() -> Result<W32TimeStatus, RkikError> {
+    let out = std::process::Command::new("w32tm")
+        .args(["/query", "/status", "/verbose"])
+        .output()?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    if !out.status.success() {
+        return Err(RkikError::Other(format!("w32tm: {}", text.trim())));
+    }
+    let mut status = parse_status(&text)?;
+    status.adjustment = system_time_adjustment();
+    Ok(status)
+}
+
+/// Query W32Time through `w32tm`; fails when the service is stopped.
+#[cfg(not(windows))]
+pub fn status() -> Result<W32TimeStatus, RkikError> {
+    Err(RkikError::Other(
+        "W32Time is only available on Windows".into(),
+    ))
+}
+
+#[cfg(windows)]
+fn system_time_adjustment() -> Option<TimeAdjustment> {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetSystemTimeAdjustment(
+            adjustment: *mut u32,
+            increment: *mut u32,
+            disabled: *mut i32,
+        ) -> i32;
+    }
+    let (mut adjustment, mut increment, mut disabled) = (0u32, 0u32, 0i32);
+    // SAFETY: three valid out-pointers, as the API requires.
+    let ok = unsafe { GetSystemTimeAdjustment(&mut adjustment, &mut increment, &mut disabled) };
+    (ok != 0).then_some(TimeAdjustment {
+        adjustment,
+        increment,
+        disabled: disabled != 0,
+    })
+}
+
+/// Parse the English `w32tm /query /status /verbose` report.
+fn parse_status(text: &str) -> Result<W32TimeStatus, RkikError> {
+    let field = |name: &str| {
+        text.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim())
+        })
+    };
+    // "0.0312500s" -> 31.25 ms
+    let seconds = |name: &str| {
+        field(name)
+            .and_then(|v| v.trim_end_matches('s').parse::<f64>().ok())
+            .map(|s| s * 1000.0)
+    };
+    let leading_int = |v: &str| {
+        let end = v
+            .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+            .unwrap_or(v.len());
+        v[..end].parse::<i64>().ok()
+    };
+    let stratum = field("Stratum")
+        .and_then(leading_int)
+        .ok_or_else(|| RkikError::Protocol("w32tm: no Stratum in status".into()))?;
+    let ref_id = field("ReferenceId").map_or(String::new(), |v| match v.split_once("source IP:") {
+        Some((_, ip)) => ip.trim().trim_end_matches(')').trim().to_string(),
+        None => v.split_whitespace().next().unwrap_or_default().to_string(),
+    });
+    Ok(W32TimeStatus {
+        leap: match field("Leap Indicator").and_then(leading_int) {
+            Some(0) => Leap::Normal,
+            Some(1) => Leap::InsertSecond,
+            Some(2) => Leap::DeleteSecond,
+            _ => Leap::Unsynchronised,
+        },
+        stratum: u8::try_from(stratum).unwrap_or(16),
+        source: field("Source")
+            .map(|s| s.split(',').next().unwrap_or(s).to_string())
+            .unwrap_or_default(),
+        ref_id,
+        last_sync: field("Last Successful Sync Time").map(str::to_string),
+        phase_offset_ms: seconds("Phase Offset"),
+        root_delay_ms: seconds("Root Delay"),
+        root_dispersion_ms: seconds("Root Dispersion"),
+        poll: field("Poll Interval")
+            .and_then(leading_int)
+            .and_then(|p| i8::try_from(p).ok()),
+        adjustment: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "Leap Indicator: 0(no warning)\r\n\
+        Stratum: 4 (secondary reference - syncd by (S)NTP)\r\n\
+        Precision: -23 (119.209ns per tick)\r\n\
+        Root Delay: 0.0312500s\r\n\
+        Root Dispersion: 7.8012107s\r\n\
+        ReferenceId: 0xC0000201 (source IP:  192.0.2.1)\r\n\
+        Last Successful Sync Time: 10/15/2026 9:12:03 AM\r\n\
+        Source: time.windows.com,0x9\r\n\
+        Poll Interval: 10 (1024s)\r\n\
+        \r\n\
+        Phase Offset: -0.0001234s\r\n\
+        ClockRate: 0.0156250s\r\n";
+
+    #[test]
+    fn status_report_is_parsed() {
+        let s = parse_status(STATUS).unwrap();
+        assert_eq!((s.leap, s.stratum), (Leap::Normal, 4));
+        assert_eq!(s.source, "time.windows.com");
+        assert_eq!(s.ref_id, "192.0.2.1");
+        assert_eq!(s.last_sync.as_deref(), Some("10/15/2026 9:12:03 AM"));
+        assert_eq!(s.root_delay_ms, Some(31.25));
+        assert!((s.phase_offset_ms.unwrap() + 0.1234).abs() < 1e-9);
+        assert_eq!(s.poll, Some(10));
+        assert!(parse_status("The service has not been started.").is_err());
+    }
+
+    #[test]
+    fn adjustment_gives_frequency() {
+        let adj = TimeAdjustment {
+            adjustment: 156_251,
+            increment: 156_250,
+            disabled: false,
+        };
+        assert!((adj.freq_ppm() - 6.4).abs() < 1e-9);
+        let off = TimeAdjustment {
+            disabled: true,
+            ..adj
+        };
+        assert_eq!(off.freq_ppm(), 0.0);
+    }
+}
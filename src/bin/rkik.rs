@@ -1,13 +1,86 @@
+#[path = "rkik/alerts.rs"]
+mod alerts;
 #[path = "rkik/config_store.rs"]
 mod config_store;
+#[cfg(unix)]
+#[path = "rkik/control.rs"]
+mod control;
+#[path = "rkik/daemon.rs"]
+mod daemon;
+#[cfg(all(feature = "dbus", target_os = "linux"))]
+#[path = "rkik/dbus.rs"]
+mod dbus;
+#[path = "rkik/duration.rs"]
+mod duration;
+#[path = "rkik/exporter.rs"]
+mod exporter;
+#[cfg(feature = "sqlite")]
+#[path = "rkik/history.rs"]
+mod history;
 #[path = "rkik/legacy.rs"]
 mod legacy;
+#[path = "rkik/local.rs"]
+mod local;
+#[path = "rkik/logging.rs"]
+mod logging;
+#[cfg(feature = "otel")]
+#[path = "rkik/otel.rs"]
+mod otel;
+#[path = "rkik/output_file.rs"]
+mod output_file;
+#[path = "rkik/plugin.rs"]
+mod plugin;
+#[path = "rkik/progress.rs"]
+mod progress;
+#[path = "rkik/recommend.rs"]
+mod recommend;
+#[cfg(feature = "json")]
+#[path = "rkik/record.rs"]
+mod record;
+#[cfg(feature = "json")]
+#[path = "rkik/replay.rs"]
+mod replay;
+#[path = "rkik/serve.rs"]
+mod serve;
+#[cfg(feature = "sqlite")]
+#[path = "rkik/store.rs"]
+mod store;
+#[path = "rkik/systemd.rs"]
+mod systemd;
+#[path = "rkik/targets_file.rs"]
+mod targets_file;
+#[path = "rkik/threshold.rs"]
+mod threshold;
+#[cfg(feature = "json")]
+#[path = "rkik/zabbix.rs"]
+mod zabbix;
 
-use clap::{Args as ClapArgs, CommandFactory, Parser, Subcommand, ValueEnum};
-use config_store::{ConfigError, ConfigStore, Defaults, PresetRecord};
+use clap::{ArgAction, Args as ClapArgs, CommandFactory, Parser, Subcommand, ValueEnum};
+use config_store::{ConfigError, ConfigStore, Defaults, PresetRecord, Thresholds};
 use legacy::{LegacyArgs, OutputFormat};
+use logging::LogTarget;
+use output_file::RotatePolicy;
+use plugin::PluginFormat;
+use rkik::RkikError;
+use rkik::adapters::http_date::{self, HttpUrl};
+use rkik::fmt::fields::Field;
+use rkik::fmt::units::TimeUnit;
+use rkik::services::asymmetry::{self, AsymmetryOptions};
+use rkik::services::clock_check::{self, ClockCheckOptions};
+use rkik::services::compare::LOCAL_TARGET;
+#[cfg(feature = "nts")]
+use rkik::services::nts_ke::{self, NtsKeOptions};
+use rkik::services::query::Scheme;
+use rkik::services::recommend::{Candidate, DEFAULT_COUNT};
+use rkik::services::survey::{self, SurveyOptions};
+use rkik::services::{diag, discover};
+use std::collections::HashMap;
 use std::env;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::process::{self, Command as ProcessCommand};
+use threshold::Threshold;
 
 #[derive(Parser, Debug)]
 #[command(name = "rkik")]
@@ -20,6 +93,10 @@ use std::process::{self, Command as ProcessCommand};
 ))]
 #[command(about = "Rusty Klock Inspection Kit - NTP Query and Compare Tool")]
 struct Cli {
+    /// Configuration profile to apply (default: $RKIK_PROFILE)
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -35,6 +112,37 @@ enum Command {
     Sync(SyncCommand),
     /// Diagnostic helpers for a single target
     Diag(DiagCommand),
+    /// List the NTP/NTS servers a domain advertises in SRV records
+    Discover(DiscoverCommand),
+    /// Estimate the clock offset from an HTTP(S) server's Date header, where UDP 123 is blocked
+    Http(HttpCommand),
+    /// Collect the members of an NTP Pool zone, probe each of them and rank them
+    Survey(SurveyCommand),
+    /// Probe a site over several paths (IPv4, IPv6, neighbouring servers)
+    /// and bound the true offset despite asymmetric delays
+    Asymmetry(AsymmetryCommand),
+    /// Measure the local clock's frequency error against a server and watch
+    /// the realtime clock for backward steps
+    CheckClock(CheckClockCommand),
+    /// Time NTS-KE handshakes, cold then resumed, and show what TLS negotiated
+    #[cfg(feature = "nts")]
+    NtsKe(NtsKeCommand),
+    /// Show what the local chronyd or ntpd believes: its clock state and sources
+    Local(LocalCommand),
+    /// Re-render a session recorded with --record, without network access
+    #[cfg(feature = "json")]
+    Replay(ReplayCommand),
+    /// Query the probes accumulated with --store
+    #[cfg(feature = "sqlite")]
+    History(HistoryCommand),
+    /// Serve Prometheus metrics for a set of targets
+    Exporter(ExporterCommand),
+    /// Monitor a configured set of targets continuously
+    Daemon(DaemonCommand),
+    /// Answer NTP requests with an injected offset, jitter and loss (lab use only)
+    Serve(ServeCommand),
+    /// Query or reconfigure a running daemon
+    Ctl(CtlCommand),
     /// Inspect or update rkik configuration
     #[command(subcommand)]
     Config(ConfigCommand),
@@ -49,12 +157,36 @@ struct ProbeOptions {
     #[arg(short = 'c', long, value_name = "COUNT")]
     count: Option<u32>,
 
-    /// Interval between requests (s)
-    #[arg(short = 'i', long, value_name = "SECONDS")]
-    interval: Option<f64>,
+    /// Interval between requests (s), or `auto` to adapt it to the jitter
+    #[arg(short = 'i', long, value_name = "DURATION", value_parser = legacy::parse_interval)]
+    interval: Option<legacy::Interval>,
+
+    /// Give up with exit code 5 once a target fails this many times in a row
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_failures: Option<u32>,
+
+    /// Give up at the first failure (same as --max-failures 1)
+    #[arg(long, conflicts_with = "max_failures")]
+    fail_fast: bool,
+
+    /// Stop after this long (e.g. 90s, 30m, 1h); --count then caps the number of samples
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_positive_seconds)]
+    duration: Option<f64>,
+
+    /// Lengthen each pause by a random share of the interval, up to this much (e.g. 10%)
+    #[arg(long, value_name = "PERCENT", value_parser = legacy::parse_jitter)]
+    interval_jitter: Option<f64>,
+
+    /// Never query a server more often than every DURATION, whatever --interval says
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_seconds)]
+    min_interval: Option<f64>,
+
+    /// Allow intervals under 1 s against pool.ntp.org
+    #[arg(long)]
+    allow_fast_pool: bool,
 
     /// Timeout per request (s)
-    #[arg(long, value_name = "SECONDS")]
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_seconds)]
     timeout: Option<f64>,
 
     /// Run until Ctrl+C
@@ -64,13 +196,39 @@ struct ProbeOptions {
     /// Force IPv6 resolution
     #[arg(short = '6', long)]
     ipv6: bool,
+
+    /// Discard samples whose RTT exceeds FACTOR times the lowest one before averaging
+    #[arg(long, value_name = "FACTOR", value_parser = legacy::parse_rtt_filter)]
+    rtt_filter: Option<f64>,
+
+    /// Re-resolve the host name on every probe instead of caching it for its DNS TTL
+    #[arg(long)]
+    no_dns_cache: bool,
+
+    /// Treat answers from unsynchronized servers (leap indicator 3, stratum 0 or 16, stale reference) as failures, with exit code 6
+    #[arg(long)]
+    require_sync: bool,
+
+    /// With --require-sync, the oldest reference accepted (default 1 day)
+    #[arg(long, value_name = "DURATION", requires = "require_sync", value_parser = duration::parse_positive_seconds)]
+    max_reference_age: Option<f64>,
+
+    /// Only accept DNSSEC-validated answers when resolving host names
+    #[cfg(feature = "dnssec")]
+    #[arg(long)]
+    dnssec: bool,
 }
 
 #[derive(ClapArgs, Debug, Clone, Default)]
 struct OutputOptions {
-    /// Verbose / human-friendly output
-    #[arg(short = 'v', long)]
-    verbose: bool,
+    /// Verbose / human-friendly output; -vv and -vvv add DNS, socket, packet and NTS diagnostics on stderr
+    #[arg(short = 'v', long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// MaxMind database (ASN, country or city) used to label responders in verbose output
+    #[cfg(feature = "enrich")]
+    #[arg(long, value_name = "PATH", requires = "verbose")]
+    geoip_db: Vec<PathBuf>,
 
     /// Output format
     #[arg(short = 'f', long, value_enum)]
@@ -84,6 +242,14 @@ struct OutputOptions {
     #[arg(short = 'S', long)]
     short: bool,
 
+    /// With --count or --infinite, print only the final statistics
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// Print nothing at all; only the exit code tells the outcome
+    #[arg(long, conflicts_with_all = ["quiet", "verbose"])]
+    silent: bool,
+
     /// Pretty-print JSON
     #[arg(short = 'p', long)]
     pretty: bool,
@@ -91,6 +257,74 @@ struct OutputOptions {
     /// Disable colors
     #[arg(long = "no-color", alias = "nocolor")]
     no_color: bool,
+
+    /// Restrict json/csv/simple output to these columns (e.g. offset,rtt,stratum)
+    #[arg(long, value_delimiter = ',', value_name = "FIELDS")]
+    fields: Vec<Field>,
+
+    /// Unit for offsets and delays: s, ms, us, ns or auto
+    #[arg(long, value_name = "UNIT")]
+    unit: Option<TimeUnit>,
+
+    /// Write results to this file instead of stdout
+    #[arg(short = 'o', long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Rotate the output file by size (e.g. 10MB) or period (hourly, daily)
+    #[arg(long, requires = "output", value_name = "POLICY")]
+    rotate: Option<RotatePolicy>,
+
+    /// Number of rotated output files to keep
+    #[arg(long, requires = "output", value_name = "N")]
+    rotate_keep: Option<usize>,
+
+    /// Push offset/rtt items to a Zabbix server or proxy (host[:port])
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "SERVER", value_parser = zabbix::ZabbixServer::parse, requires = "zabbix_host")]
+    zabbix: Option<zabbix::ZabbixServer>,
+
+    /// Monitored host name the Zabbix items belong to
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "HOST", requires = "zabbix")]
+    zabbix_host: Option<String>,
+
+    /// Emit structured probe logs to stderr, syslog or journald
+    #[arg(long, value_enum, value_name = "TARGET")]
+    log_target: Option<LogTarget>,
+
+    /// Write diagnostics (debug level, trace with -vvv) to this file instead of stderr
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Record every probe to this session file, for `rkik replay`
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    /// Persist every probe to a history database (sqlite://PATH), for `rkik history`
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "URL", value_parser = store::parse_url)]
+    store: Option<PathBuf>,
+
+    /// Capture the NTP packets exchanged to this pcap file
+    #[arg(long, value_name = "PATH")]
+    pcap: Option<PathBuf>,
+
+    #[cfg(feature = "otel")]
+    #[command(flatten)]
+    otel: OtelOptions,
+}
+
+#[cfg(feature = "otel")]
+#[derive(ClapArgs, Debug, Clone, Default)]
+struct OtelOptions {
+    /// Push results to an OTLP/HTTP collector (e.g. http://localhost:4318)
+    #[arg(long, value_name = "URL", value_parser = otel::Endpoint::parse)]
+    otlp_endpoint: Option<otel::Endpoint>,
+
+    /// Extra OTLP resource attribute (KEY=VALUE, repeatable)
+    #[arg(long = "otlp-attribute", requires = "otlp_endpoint", value_name = "KEY=VALUE", value_parser = otel::parse_attribute)]
+    otlp_attributes: Vec<(String, String)>,
 }
 
 #[derive(ClapArgs, Debug, Clone, Default)]
@@ -99,13 +333,37 @@ struct PluginOptions {
     #[arg(long)]
     plugin: bool,
 
-    /// Warning threshold (ms)
+    /// Warning threshold (ms or Nagios range, e.g. 50, 10:20, @0:5), for --plugin or --highlight
+    #[arg(long, allow_hyphen_values = true, value_name = "WARN")]
+    warning: Option<Threshold>,
+
+    /// Critical threshold (ms or Nagios range), for --plugin or --highlight
+    #[arg(long, allow_hyphen_values = true, value_name = "CRIT")]
+    critical: Option<Threshold>,
+
+    /// Warning threshold on RTT (ms), for --plugin or --highlight
+    #[arg(long, value_name = "WARN")]
+    warning_rtt: Option<Threshold>,
+
+    /// Critical threshold on RTT (ms), for --plugin or --highlight
+    #[arg(long, value_name = "CRIT")]
+    critical_rtt: Option<Threshold>,
+
+    /// Color offsets and RTTs in text output by the thresholds above
+    #[arg(long)]
+    highlight: bool,
+
+    /// Warn when the server stratum is above this value
+    #[arg(long, requires = "plugin", value_name = "N")]
+    max_stratum: Option<u8>,
+
+    /// Warning threshold on offset jitter (ms, needs --count >= 2)
     #[arg(long, requires = "plugin", value_name = "WARN")]
-    warning: Option<f64>,
+    warning_jitter: Option<Threshold>,
 
-    /// Critical threshold (ms)
-    #[arg(long, requires = "plugin", value_name = "CRIT")]
-    critical: Option<f64>,
+    /// Plugin output dialect
+    #[arg(long, value_enum, requires = "plugin", value_name = "FORMAT")]
+    plugin_format: Option<PluginFormat>,
 }
 
 #[cfg(feature = "nts")]
@@ -125,64 +383,660 @@ struct NtpCommand {
     #[command(flatten)]
     common: ProbeOptions,
 
-    #[command(flatten)]
-    output: OutputOptions,
+    #[command(flatten)]
+    output: OutputOptions,
+
+    #[command(flatten)]
+    plugin: PluginOptions,
+
+    #[cfg(feature = "nts")]
+    #[command(flatten)]
+    nts: NtsOptions,
+
+    /// Exit with code 4 if the average offset exceeds this (ms)
+    #[arg(long, value_name = "MS")]
+    assert_max_offset: Option<f64>,
+
+    /// Exit with code 4 if a server or the kernel advertises a leap second
+    /// state that contradicts the IERS leap second table
+    #[arg(long)]
+    assert_leap: bool,
+
+    /// IERS leap-seconds.list to check --assert-leap against
+    #[arg(long, requires = "assert_leap", value_name = "PATH")]
+    leap_file: Option<PathBuf>,
+
+    /// Probe the target on each of these ports (e.g. 123,1123), compared side by side
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u16).range(1..), value_name = "PORT,...")]
+    ports: Option<Vec<u16>>,
+
+    /// Target host (hostname or IP)
+    #[arg(value_name = "TARGET")]
+    target: Option<String>,
+}
+
+#[derive(ClapArgs, Debug, Clone, Default)]
+struct CompareCommand {
+    #[command(flatten)]
+    common: ProbeOptions,
+
+    #[command(flatten)]
+    output: OutputOptions,
+
+    #[command(flatten)]
+    plugin: PluginOptions,
+
+    #[cfg(feature = "nts")]
+    #[command(flatten)]
+    nts: NtsOptions,
+
+    /// Express offsets relative to this server instead of the local clock
+    #[arg(long, value_name = "SERVER")]
+    reference: Option<String>,
+
+    /// Add the local clock ("local", offset 0) to the compared servers
+    #[arg(long)]
+    include_local: bool,
+
+    /// Print the matrix of pairwise offset differences
+    #[arg(long)]
+    matrix: bool,
+
+    /// Flag matrix pairs whose offsets differ by more than this (ms)
+    #[arg(long, requires = "matrix", value_name = "MS")]
+    max_drift: Option<f64>,
+
+    /// Exit with code 4 if a server's average offset exceeds this (ms)
+    #[arg(long, value_name = "MS")]
+    assert_max_offset: Option<f64>,
+
+    /// Exit with code 4 if the servers' average offsets differ by more than this (ms)
+    #[arg(long, value_name = "MS")]
+    assert_max_drift: Option<f64>,
+
+    /// Exit with code 4 if a server or the kernel advertises a leap second
+    /// state that contradicts the IERS leap second table
+    #[arg(long)]
+    assert_leap: bool,
+
+    /// IERS leap-seconds.list for --assert-leap and leap smear detection
+    #[arg(long, value_name = "PATH")]
+    leap_file: Option<PathBuf>,
+
+    /// Warn about servers known to smear leap seconds, not only those
+    /// caught smearing around a leap second
+    #[arg(long)]
+    detect_smear: bool,
+
+    /// Read additional servers from this file, one per line ("-" for stdin)
+    #[arg(long, value_name = "PATH")]
+    targets_file: Option<PathBuf>,
+
+    /// Query at most this many servers at once
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u16).range(1..), value_name = "N")]
+    concurrency: u16,
+
+    /// Wait at least this long between two queries (ms)
+    #[arg(long, value_name = "MS")]
+    stagger: Option<f64>,
+
+    /// Send every request from one UDP socket instead of one per server,
+    /// for surveys of hundreds or thousands of servers
+    #[arg(long)]
+    shared_socket: bool,
+
+    /// Rank the servers by quality (RTT, jitter, stratum, agreement) and
+    /// print the best N (default 4)
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "4", value_parser = clap::value_parser!(u16).range(1..))]
+    recommend: Option<u16>,
+
+    /// Also print the recommended servers as a chrony.conf or ntp.conf block
+    #[arg(long, value_enum, value_name = "DIALECT")]
+    emit_config: Option<recommend::EmitConfig>,
+
+    /// With --count or --infinite, print an event when a server drifts more
+    /// than this far from the median of the group, and when it comes back (ms)
+    #[arg(long, value_name = "MS")]
+    diverge_threshold: Option<f64>,
+
+    /// Servers to compare
+    #[arg(value_name = "TARGET", num_args = 1..)]
+    targets: Vec<String>,
+}
+
+#[cfg(feature = "sync")]
+#[derive(ClapArgs, Debug, Clone, Default)]
+struct SyncCommand {
+    #[command(flatten)]
+    common: ProbeOptions,
+
+    #[command(flatten)]
+    output: OutputOptions,
+
+    /// Skip actually setting the time
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+
+    /// Target to synchronize with
+    #[arg(value_name = "TARGET")]
+    target: String,
+}
+
+#[derive(ClapArgs, Debug, Clone, Default)]
+struct DiagCommand {
+    /// Timeout per request (s)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_seconds)]
+    timeout: Option<f64>,
+
+    /// Only diagnose the target's IPv6 addresses
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// NTS-KE port probed for NTS support
+    #[arg(long, default_value_t = 4460)]
+    nts_port: u16,
+
+    /// Back-to-back queries sent to test rate limiting (0 to skip)
+    #[arg(long, default_value_t = 4, value_name = "N")]
+    burst: u8,
+
+    /// Trace the UDP path to the server (Linux)
+    #[arg(long)]
+    trace: bool,
+
+    /// Highest TTL tried by --trace
+    #[arg(long, default_value_t = 30, requires = "trace", value_name = "N")]
+    max_hops: u8,
+
+    /// Output JSON
+    #[arg(short = 'j', long)]
+    json: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+
+    /// Target to diagnose
+    #[arg(value_name = "TARGET")]
+    target: String,
+}
+
+#[derive(ClapArgs, Debug, Clone, Default)]
+struct HttpCommand {
+    /// Timeout for the whole request, handshakes included
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_positive_seconds)]
+    timeout: Option<f64>,
+
+    /// Only connect over IPv6
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Output JSON
+    #[arg(short = 'j', long)]
+    json: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+
+    /// URL to query; a bare host means https://HOST/
+    #[arg(value_name = "URL")]
+    url: String,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct SurveyCommand {
+    /// Pool zone, prefixed to the pool name (e.g. europe, fr, 2.debian)
+    #[arg(long, value_name = "ZONE")]
+    zone: Option<String>,
+
+    /// Probes of each member
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..), value_name = "N")]
+    samples: u32,
+
+    /// DNS queries of the pool name and of each numbered name (0. to 3.)
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..), value_name = "N")]
+    rounds: u32,
+
+    /// Pause between two probes of a member (at least 1 s)
+    #[arg(short = 'i', long, value_name = "DURATION", default_value_t = 2.0, value_parser = duration::parse_seconds)]
+    interval: f64,
+
+    /// Timeout per request (s)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_positive_seconds)]
+    timeout: Option<f64>,
+
+    /// Survey IPv6 members instead of IPv4 ones
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Probe at most this many members at once
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..), value_name = "N")]
+    concurrency: u16,
+
+    /// Wait at least this long between two member launches (ms)
+    #[arg(long, value_name = "MS")]
+    stagger: Option<f64>,
+
+    /// Rank the members by quality (RTT, jitter, stratum, agreement) and
+    /// print the best N (default 4)
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "4", value_parser = clap::value_parser!(u16).range(1..))]
+    recommend: Option<u16>,
+
+    /// Also print the recommended members as a chrony.conf or ntp.conf block
+    #[arg(long, value_enum, value_name = "DIALECT")]
+    emit_config: Option<recommend::EmitConfig>,
+
+    /// MaxMind database (ASN, country or city) used to fill the ASN column
+    #[cfg(feature = "enrich")]
+    #[arg(long, value_name = "PATH")]
+    geoip_db: Vec<PathBuf>,
+
+    /// Output JSON
+    #[arg(short = 'j', long)]
+    json: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+
+    /// Pool name
+    #[arg(value_name = "POOL", default_value = "pool.ntp.org")]
+    pool: String,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct AsymmetryCommand {
+    /// Probes of each path; the fastest one is kept
+    #[arg(short = 'c', long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..), value_name = "N")]
+    count: u32,
+
+    /// Pause between two probes of a path
+    #[arg(short = 'i', long, value_name = "DURATION", default_value_t = 1.0, value_parser = duration::parse_seconds)]
+    interval: f64,
+
+    /// Timeout per request (s)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_positive_seconds)]
+    timeout: Option<f64>,
+
+    /// Only probe the IPv6 addresses of each server
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Output JSON
+    #[arg(short = 'j', long)]
+    json: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+
+    /// Servers of the site; names are probed over IPv4 and IPv6
+    #[arg(value_name = "TARGET", required = true)]
+    targets: Vec<String>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct CheckClockCommand {
+    /// Time between the two probes; longer gives a tighter frequency
+    #[arg(short = 'w', long, value_name = "DURATION", default_value_t = 10.0, value_parser = duration::parse_positive_seconds)]
+    window: f64,
+
+    /// Timeout per request (s)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_positive_seconds)]
+    timeout: Option<f64>,
+
+    /// Use IPv6
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Output JSON
+    #[arg(short = 'j', long)]
+    json: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+
+    /// Server to check against
+    #[arg(value_name = "TARGET")]
+    target: String,
+}
+
+#[cfg(feature = "nts")]
+#[derive(ClapArgs, Debug, Clone)]
+struct NtsKeCommand {
+    /// Handshakes in a row; the first is full, the next ones resume its session
+    #[arg(short = 'c', long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..), value_name = "N")]
+    count: u16,
+
+    /// NTS-KE port
+    #[arg(long, default_value_t = 4460)]
+    nts_port: u16,
+
+    /// Timeout per handshake (s)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_positive_seconds)]
+    timeout: Option<f64>,
+
+    /// Use IPv6
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Output JSON
+    #[arg(short = 'j', long)]
+    json: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+
+    /// NTS-KE server (host, host:port or nts://host)
+    #[arg(value_name = "TARGET")]
+    target: String,
+}
+
+#[derive(ClapArgs, Debug, Clone, Default)]
+struct DiscoverCommand {
+    #[command(flatten)]
+    common: ProbeOptions,
+
+    #[command(flatten)]
+    output: OutputOptions,
+
+    /// Probe every advertised server after listing them
+    #[arg(long)]
+    probe: bool,
+
+    /// Browse the local link for _ntp._udp and _ptp._udp services via mDNS
+    #[arg(long, conflicts_with = "domain")]
+    local: bool,
+
+    /// How long to collect mDNS answers (s, default 2)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_seconds)]
+    wait: Option<f64>,
+
+    /// Domain whose _ntske._tcp and _ntp._udp SRV records are looked up
+    #[arg(value_name = "DOMAIN", required_unless_present = "local")]
+    domain: Option<String>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct LocalCommand {
+    /// chronyd command socket: a Unix socket path or HOST:PORT
+    /// (default: /run/chrony/chronyd.sock, then 127.0.0.1:323)
+    #[arg(long, value_name = "SOCKET", value_parser = local::parse_endpoint)]
+    chronyd: Option<rkik::adapters::chrony::Endpoint>,
+
+    /// Ask the ntpd at HOST:PORT over mode 6 instead (default when chronyd
+    /// does not answer: 127.0.0.1:123)
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "chronyd")]
+    ntpd: Option<SocketAddr>,
+
+    /// Add the kernel clock discipline state (adjtimex): frequency, errors, STA_* flags, TAI offset
+    #[arg(long)]
+    kernel: bool,
+
+    /// Timeout per request (s)
+    #[arg(long, value_name = "DURATION", default_value_t = 1.0, value_parser = duration::parse_seconds)]
+    timeout: f64,
+
+    /// Output JSON
+    #[arg(short = 'j', long)]
+    json: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+}
+
+#[cfg(feature = "json")]
+#[derive(ClapArgs, Debug, Clone)]
+struct ReplayCommand {
+    /// What to print: the probes as they were shown, JSON, or per-server statistics
+    #[arg(short = 'f', long, value_enum, default_value_t = replay::ReplayFormat::Text)]
+    format: replay::ReplayFormat,
+
+    /// Verbose / human-friendly output
+    #[arg(short = 'v', long)]
+    verbose: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+
+    /// Compare FILE against this earlier session: per-server offset and RTT shifts
+    #[arg(long, value_name = "BEFORE")]
+    diff: Option<PathBuf>,
+
+    /// Session file written by --record
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(ClapArgs, Debug, Clone)]
+struct HistoryCommand {
+    /// History database, as given to --store
+    #[arg(long, value_name = "URL", value_parser = store::parse_url)]
+    store: PathBuf,
+
+    /// Oldest probes to include: an age (30m, 24h, 7d) or an RFC 3339 time
+    #[arg(long, value_name = "WHEN", default_value = "24h", value_parser = history::parse_since)]
+    since: chrono::DateTime<chrono::Utc>,
+
+    /// Print offset, availability and drift statistics per server instead of every probe
+    #[arg(long)]
+    stats: bool,
+
+    /// Print the probes as JSON
+    #[arg(short = 'j', long, conflicts_with = "stats")]
+    json: bool,
+
+    /// Verbose / human-friendly output
+    #[arg(short = 'v', long)]
+    verbose: bool,
+
+    /// Pretty-print JSON
+    #[arg(short = 'p', long)]
+    pretty: bool,
+
+    /// Disable colors
+    #[arg(long = "no-color", alias = "nocolor")]
+    no_color: bool,
+
+    /// Only this server (as it was named when probed); all servers by default
+    #[arg(value_name = "SERVER")]
+    server: Option<String>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct ExporterCommand {
+    /// Address to serve /metrics on
+    #[arg(long, default_value = "0.0.0.0:9123", value_name = "ADDR")]
+    listen: SocketAddr,
+
+    /// Targets to probe (comma separated or repeated)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        required_unless_present = "targets_file",
+        value_name = "TARGETS"
+    )]
+    targets: Vec<String>,
+
+    /// Read additional targets from this file, one per line ("-" for stdin)
+    #[arg(long, value_name = "PATH")]
+    targets_file: Option<PathBuf>,
+
+    /// Interval between probe rounds (s)
+    #[arg(short = 'i', long, default_value_t = 15.0, value_name = "DURATION", value_parser = duration::parse_seconds)]
+    interval: f64,
+
+    /// Timeout per request (s)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_seconds)]
+    timeout: Option<f64>,
+
+    /// Probes kept per target for the rkik_window_* gauges
+    #[arg(long, default_value_t = rkik::history::DEFAULT_CAPACITY as u16, value_parser = clap::value_parser!(u16).range(1..), value_name = "N")]
+    window: u16,
 
-    #[command(flatten)]
-    plugin: PluginOptions,
+    /// Force IPv6 resolution
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Emit structured probe logs to stderr, syslog or journald
+    #[arg(long, value_enum, value_name = "TARGET")]
+    log_target: Option<LogTarget>,
 
     #[cfg(feature = "nts")]
     #[command(flatten)]
     nts: NtsOptions,
-
-    /// Target host (hostname or IP)
-    #[arg(value_name = "TARGET")]
-    target: Option<String>,
 }
 
-#[derive(ClapArgs, Debug, Clone, Default)]
-struct CompareCommand {
-    #[command(flatten)]
-    common: ProbeOptions,
+#[derive(ClapArgs, Debug, Clone)]
+struct ServeCommand {
+    /// UDP port to listen on
+    #[arg(long, default_value_t = 123)]
+    port: u16,
 
-    #[command(flatten)]
-    output: OutputOptions,
+    /// Address to listen on; pass 0.0.0.0 or :: to serve other machines
+    #[arg(long, default_value = "127.0.0.1", value_name = "ADDR")]
+    bind: std::net::IpAddr,
 
-    #[cfg(feature = "nts")]
-    #[command(flatten)]
-    nts: NtsOptions,
+    /// Shift every reply by this much (e.g. 250ms, -2s)
+    #[arg(long, default_value = "0", value_name = "DURATION", allow_hyphen_values = true, value_parser = duration::parse_signed_seconds)]
+    offset: f64,
 
-    /// Servers to compare
-    #[arg(value_name = "TARGET", num_args = 2..)]
-    targets: Vec<String>,
+    /// Shift each reply further by a random amount up to this, either way
+    #[arg(long, default_value = "0", value_name = "DURATION", value_parser = duration::parse_seconds)]
+    jitter: f64,
+
+    /// Drop this share of requests unanswered (e.g. 10%)
+    #[arg(long, default_value = "0", value_name = "SHARE", value_parser = serve::parse_loss)]
+    loss: f64,
+
+    /// Stratum advertised in replies
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=15))]
+    stratum: u8,
+
+    /// Leap indicator advertised in replies (0 none, 1 insert, 2 delete, 3 unsynchronised)
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3))]
+    leap: u8,
 }
 
-#[cfg(feature = "sync")]
-#[derive(ClapArgs, Debug, Clone, Default)]
-struct SyncCommand {
-    #[command(flatten)]
-    common: ProbeOptions,
+#[derive(ClapArgs, Debug, Clone)]
+struct DaemonCommand {
+    /// Daemon configuration file (TOML)
+    #[arg(long, value_name = "FILE")]
+    config: PathBuf,
 
-    #[command(flatten)]
-    output: OutputOptions,
+    /// Override the log target from the configuration file
+    #[arg(long, value_enum, value_name = "TARGET")]
+    log_target: Option<LogTarget>,
+}
 
-    /// Skip actually setting the time
-    #[arg(long = "dry-run")]
-    dry_run: bool,
+#[derive(ClapArgs, Debug, Clone)]
+struct CtlCommand {
+    /// Daemon control socket (default: $XDG_RUNTIME_DIR/rkik.sock)
+    #[arg(long, global = true, value_name = "PATH")]
+    socket: Option<PathBuf>,
 
-    /// Target to synchronize with
-    #[arg(value_name = "TARGET")]
-    target: String,
+    #[command(subcommand)]
+    action: CtlAction,
 }
 
-#[derive(ClapArgs, Debug, Clone, Default)]
-struct DiagCommand {
-    #[command(flatten)]
-    common: ProbeOptions,
+#[derive(Subcommand, Debug, Clone)]
+enum CtlAction {
+    /// Show the state and last probe of every target
+    Status,
+    /// Show rolling-window statistics
+    Stats {
+        /// Only this target
+        server: Option<String>,
+    },
+    /// Start monitoring a target
+    AddTarget(AddTargetArgs),
+    /// Stop monitoring a target
+    RemoveTarget {
+        /// Target to remove
+        server: String,
+    },
+    /// Re-read the daemon configuration file (same as SIGHUP)
+    Reload,
+}
 
-    /// Target to diagnose
-    #[arg(value_name = "TARGET")]
-    target: String,
+#[derive(ClapArgs, Debug, Clone)]
+struct AddTargetArgs {
+    /// Server to probe
+    server: String,
+
+    /// Interval between probes (s), defaults to the daemon interval
+    #[arg(short = 'i', long, value_name = "DURATION", value_parser = duration::parse_seconds)]
+    interval: Option<f64>,
+
+    /// Timeout per request (s)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_seconds)]
+    timeout: Option<f64>,
+
+    /// Force IPv6 resolution
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Probe with Network Time Security
+    #[arg(long)]
+    nts: bool,
+
+    /// NTS-KE port
+    #[arg(long, value_name = "PORT")]
+    nts_port: Option<u16>,
+
+    /// Offset warning threshold (ms or Nagios range)
+    #[arg(long, value_name = "THRESHOLD", allow_hyphen_values = true)]
+    warning: Option<String>,
+
+    /// Offset critical threshold (ms or Nagios range)
+    #[arg(long, value_name = "THRESHOLD", allow_hyphen_values = true)]
+    critical: Option<String>,
+
+    /// RTT warning threshold (ms or Nagios range)
+    #[arg(long, value_name = "THRESHOLD", allow_hyphen_values = true)]
+    warning_rtt: Option<String>,
+
+    /// RTT critical threshold (ms or Nagios range)
+    #[arg(long, value_name = "THRESHOLD", allow_hyphen_values = true)]
+    critical_rtt: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -207,6 +1061,8 @@ enum ConfigCommand {
         #[arg(value_enum)]
         key: ConfigKey,
     },
+    /// Check the configuration file for errors
+    Validate,
 }
 
 #[derive(Subcommand, Debug)]
@@ -216,6 +1072,9 @@ enum PresetCommand {
     /// Store a preset from trailing arguments (use -- to separate)
     Add {
         name: String,
+        /// Start from the arguments of this preset (repeatable)
+        #[arg(long, value_name = "PRESET")]
+        extends: Vec<String>,
         #[arg(trailing_var_arg = true, value_name = "ARGS")]
         args: Vec<String>,
     },
@@ -224,7 +1083,12 @@ enum PresetCommand {
     /// Show stored arguments
     Show { name: String },
     /// Execute a preset by spawning rkik with the stored arguments
-    Run { name: String },
+    Run {
+        name: String,
+        /// Value for a {KEY} placeholder of the preset (repeatable)
+        #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_placeholder_value)]
+        set: Vec<(String, String)>,
+    },
 }
 
 #[derive(ValueEnum, Clone, Debug)]
@@ -235,6 +1099,8 @@ enum ConfigKey {
     Format,
     #[value(alias = "default-ipv6")]
     Ipv6Only,
+    /// Targets probed by a bare `rkik`
+    DefaultTargets,
 }
 
 enum Mode {
@@ -257,13 +1123,50 @@ async fn main() {
             legacy::run(args, true).await;
         }
         Mode::Modern => {
-            let mut config = load_config();
             let cli = Cli::parse();
+            let mut config = match &cli.command {
+                // `config validate` reports load errors itself.
+                Some(Command::Config(ConfigCommand::Validate)) => {
+                    ConfigStore::load().unwrap_or_else(|_| ConfigStore::empty())
+                }
+                _ => load_config(),
+            };
+            let profile = cli
+                .profile
+                .clone()
+                .or_else(|| env::var("RKIK_PROFILE").ok().filter(|p| !p.is_empty()));
+            if let Some(profile) = profile
+                && !matches!(cli.command, Some(Command::Config(ConfigCommand::Validate)))
+            {
+                // Updates may create the profile they are written to.
+                let create = matches!(
+                    cli.command,
+                    Some(Command::Config(
+                        ConfigCommand::Set { .. } | ConfigCommand::Clear { .. }
+                    )) | Some(Command::Preset(PresetCommand::Add { .. }))
+                );
+                if let Err(err) = config.select_profile(&profile, create) {
+                    eprintln!("Error: {}", err);
+                    process::exit(2);
+                }
+            }
             if let Some(cmd) = cli.command {
                 if let Err(err) = dispatch_command(cmd, &mut config).await {
                     eprintln!("Error: {}", err);
                     process::exit(1);
                 }
+            } else if let Some(targets) = config.defaults().default_targets.clone() {
+                let cmd = match default_target_command(&targets) {
+                    Ok(cmd) => cmd,
+                    Err(err) => {
+                        eprintln!("Error: defaults.default_targets: {}", err);
+                        process::exit(2);
+                    }
+                };
+                if let Err(err) = dispatch_command(cmd, &mut config).await {
+                    eprintln!("Error: {}", err);
+                    process::exit(1);
+                }
             } else if let Err(err) = print_help_for(&[]) {
                 eprintln!("Error: {}", err);
                 process::exit(2);
@@ -272,28 +1175,119 @@ async fn main() {
     }
 }
 
+/// `rkik ntp` for a single default target, `rkik compare` for several or a group.
+fn default_target_command(targets: &[String]) -> Result<Command, String> {
+    let keyword = match targets {
+        [target] if !target.starts_with('@') => "ntp",
+        _ => "compare",
+    };
+    let argv = ["rkik", keyword]
+        .into_iter()
+        .map(String::from)
+        .chain(targets.iter().cloned());
+    let cli = Cli::try_parse_from(argv).map_err(|e| {
+        let message = e.to_string();
+        let first = message.lines().next().unwrap_or_default();
+        first.trim_start_matches("error: ").to_string()
+    })?;
+    cli.command.ok_or_else(|| "no command".to_string())
+}
+
 async fn dispatch_command(cmd: Command, config: &mut ConfigStore) -> Result<(), String> {
     match cmd {
         Command::Ntp(opts) => {
-            let legacy_args = build_ntp_args(opts, config.defaults())?;
+            let explicit_timeout = opts.common.timeout.is_some();
+            let mut legacy_args = build_ntp_args(opts, config.defaults())?;
+            apply_target_overrides(&mut legacy_args, config, explicit_timeout);
+            apply_config_thresholds(&mut legacy_args, config.thresholds());
             legacy::run(legacy_args, false).await;
         }
         Command::Compare(opts) => {
-            if opts.targets.len() < 2 {
-                return Err("Provide at least two targets to compare".into());
-            }
-            let legacy_args = build_compare_args(opts, config.defaults())?;
+            let explicit_timeout = opts.common.timeout.is_some();
+            let mut legacy_args = build_compare_args(opts, config)?;
+            apply_target_overrides(&mut legacy_args, config, explicit_timeout);
+            apply_config_thresholds(&mut legacy_args, config.thresholds());
             legacy::run(legacy_args, false).await;
         }
         #[cfg(feature = "sync")]
         Command::Sync(opts) => {
-            let legacy_args = build_sync_args(opts, config.defaults())?;
+            let explicit_timeout = opts.common.timeout.is_some();
+            let mut legacy_args = build_sync_args(opts, config.defaults())?;
+            apply_target_overrides(&mut legacy_args, config, explicit_timeout);
             legacy::run(legacy_args, false).await;
         }
-        Command::Diag(opts) => {
-            let legacy_args = build_diag_args(opts, config.defaults());
-            legacy::run(legacy_args, false).await;
+        Command::Diag(opts) => handle_diag(opts, config).await?,
+        Command::Discover(opts) => handle_discover(opts, config.defaults()).await?,
+        Command::Http(opts) => handle_http(opts, config.defaults()).await?,
+        Command::Survey(cmd) => handle_survey(cmd, config.defaults()).await?,
+        Command::Asymmetry(cmd) => handle_asymmetry(cmd, config.defaults()).await?,
+        Command::CheckClock(cmd) => handle_check_clock(cmd, config.defaults()).await?,
+        #[cfg(feature = "nts")]
+        Command::NtsKe(cmd) => handle_nts_ke(cmd, config.defaults()).await?,
+        Command::Local(cmd) => {
+            if !(cmd.timeout.is_finite() && cmd.timeout > 0.0) {
+                return Err("--timeout must be a positive number of seconds".into());
+            }
+            let timeout = std::time::Duration::from_secs_f64(cmd.timeout);
+            let daemon = match (cmd.chronyd, cmd.ntpd) {
+                (Some(endpoint), _) => local::Daemon::Chronyd(endpoint),
+                (None, Some(addr)) => local::Daemon::Ntpd(addr),
+                (None, None) => local::Daemon::Auto,
+            };
+            local::run(
+                daemon,
+                timeout,
+                cmd.kernel,
+                cmd.json,
+                cmd.pretty,
+                cmd.no_color,
+            )
+            .await?
+        }
+        #[cfg(feature = "json")]
+        Command::Replay(cmd) => match &cmd.diff {
+            Some(before) => replay::diff(before, &cmd.file, cmd.format, cmd.pretty, cmd.no_color)?,
+            None => replay::run(&cmd.file, cmd.format, cmd.verbose, cmd.pretty, cmd.no_color)?,
+        },
+        #[cfg(feature = "sqlite")]
+        Command::History(cmd) => {
+            let format = match (cmd.stats, cmd.json) {
+                (true, _) => replay::ReplayFormat::Report,
+                (false, true) => replay::ReplayFormat::Json,
+                (false, false) => replay::ReplayFormat::Text,
+            };
+            history::run(
+                &cmd.store,
+                cmd.server.as_deref(),
+                cmd.since,
+                format,
+                cmd.verbose,
+                cmd.pretty,
+                cmd.no_color,
+            )?
+        }
+        Command::Exporter(opts) => {
+            logging::init(opts.log_target, &logging::Diagnostics::default())?;
+            exporter::run(build_exporter_config(opts, config)?).await?;
+        }
+        Command::Daemon(opts) => {
+            let cfg = daemon::DaemonConfig::load(&opts.config)?;
+            logging::init(
+                Some(opts.log_target.unwrap_or(cfg.log_target)),
+                &logging::Diagnostics::default(),
+            )?;
+            daemon::run(cfg, opts.config).await?;
+        }
+        Command::Serve(cmd) => {
+            let options = rkik::services::serve::ServeOptions::new()
+                .offset_ms(cmd.offset * 1000.0)
+                .jitter_ms(cmd.jitter * 1000.0)
+                .loss(cmd.loss)
+                .stratum(cmd.stratum)
+                .leap(cmd.leap);
+            serve::run(SocketAddr::new(cmd.bind, cmd.port), options).await?;
         }
+        Command::Ctl(cmd) => handle_ctl(cmd).await?,
         Command::Config(cmd) => handle_config(cmd, config)?,
         Command::Preset(cmd) => handle_preset(cmd, config)?,
     }
@@ -301,12 +1295,15 @@ async fn dispatch_command(cmd: Command, config: &mut ConfigStore) -> Result<(),
 }
 
 fn build_ntp_args(cmd: NtpCommand, defaults: &Defaults) -> Result<LegacyArgs, String> {
-    let mut args = LegacyArgs::default();
-    if let Some(target) = cmd.target {
-        args.target = Some(target);
-    } else {
-        return Err("Provide a target (e.g. rkik ntp pool.ntp.org)".into());
-    }
+    let mut args = LegacyArgs {
+        assert_max_offset: cmd.assert_max_offset,
+        assert_leap: cmd.assert_leap,
+        leap_file: cmd.leap_file,
+        ..LegacyArgs::default()
+    };
+    // Without a target, the servers from the DHCP lease are used (see legacy::run).
+    args.target = cmd.target;
+    args.ports = cmd.ports;
     apply_probe_options(&mut args, &cmd.common, defaults);
     apply_output_options(&mut args, &cmd.output, defaults)?;
     apply_plugin_options(&mut args, &cmd.plugin);
@@ -318,14 +1315,47 @@ fn build_ntp_args(cmd: NtpCommand, defaults: &Defaults) -> Result<LegacyArgs, St
     Ok(args)
 }
 
-fn build_compare_args(cmd: CompareCommand, defaults: &Defaults) -> Result<LegacyArgs, String> {
-    if cmd.targets.len() < 2 {
-        return Err("Comparison requires at least two targets".into());
+fn build_compare_args(cmd: CompareCommand, config: &ConfigStore) -> Result<LegacyArgs, String> {
+    let defaults = config.defaults();
+    let mut targets = cmd.targets;
+    if let Some(path) = &cmd.targets_file {
+        targets.extend(targets_file::read(path)?);
     }
-    let mut args = LegacyArgs::default();
-    args.compare = Some(cmd.targets);
+    let mut targets = config.expand_groups(targets).map_err(|e| e.to_string())?;
+    if let Some(reference) = &cmd.reference
+        && !targets.contains(reference)
+    {
+        targets.insert(0, reference.clone());
+    }
+    if cmd.include_local && !targets.iter().any(|t| t == LOCAL_TARGET) {
+        targets.push(LOCAL_TARGET.to_string());
+    }
+    if targets.len() == 1 {
+        return Err("Provide at least two targets to compare".into());
+    }
+    let mut args = LegacyArgs {
+        // No targets at all: fall back to the DHCP-provided servers.
+        compare: (!targets.is_empty()).then_some(targets),
+        reference: cmd.reference,
+        include_local: cmd.include_local,
+        matrix: cmd.matrix,
+        max_drift: cmd.max_drift,
+        assert_max_offset: cmd.assert_max_offset,
+        assert_max_drift: cmd.assert_max_drift,
+        assert_leap: cmd.assert_leap,
+        leap_file: cmd.leap_file,
+        detect_smear: cmd.detect_smear,
+        concurrency: cmd.concurrency,
+        stagger: cmd.stagger,
+        shared_socket: cmd.shared_socket,
+        recommend: cmd.recommend,
+        emit_config: cmd.emit_config,
+        diverge_threshold: cmd.diverge_threshold,
+        ..LegacyArgs::default()
+    };
     apply_probe_options(&mut args, &cmd.common, defaults);
     apply_output_options(&mut args, &cmd.output, defaults)?;
+    apply_plugin_options(&mut args, &cmd.plugin);
     #[cfg(feature = "nts")]
     {
         args.nts = cmd.nts.nts;
@@ -336,37 +1366,443 @@ fn build_compare_args(cmd: CompareCommand, defaults: &Defaults) -> Result<Legacy
 
 #[cfg(feature = "sync")]
 fn build_sync_args(cmd: SyncCommand, defaults: &Defaults) -> Result<LegacyArgs, String> {
-    let mut args = LegacyArgs::default();
-    args.target = Some(cmd.target);
-    args.sync = true;
-    args.dry_run = cmd.dry_run;
+    let mut args = LegacyArgs {
+        target: Some(cmd.target),
+        sync: true,
+        dry_run: cmd.dry_run,
+        ..LegacyArgs::default()
+    };
     apply_probe_options(&mut args, &cmd.common, defaults);
     apply_output_options(&mut args, &cmd.output, defaults)?;
     Ok(args)
 }
 
-fn build_diag_args(cmd: DiagCommand, defaults: &Defaults) -> LegacyArgs {
+async fn handle_diag(cmd: DiagCommand, config: &ConfigStore) -> Result<(), String> {
+    let defaults = config.defaults();
+    let mut target = cmd.target;
+    let mut timeout = cmd.timeout.or(defaults.timeout).unwrap_or(5.0);
+    if let Some(o) = config.target_override(&target) {
+        target = o.apply(&target);
+        if let (Some(t), None) = (o.timeout, cmd.timeout) {
+            timeout = t;
+        }
+    }
+    if !(timeout.is_finite() && timeout > 0.0) {
+        return Err("--timeout must be a positive number of seconds".into());
+    }
+    let options = diag::DiagOptions::new()
+        .timeout(std::time::Duration::from_secs_f64(timeout))
+        .ipv6_only(cmd.ipv6 || defaults.ipv6_only.unwrap_or(false))
+        .nts_port(cmd.nts_port)
+        .burst(cmd.burst)
+        .max_hops(if cmd.trace { cmd.max_hops } else { 0 });
+    console::set_colors_enabled(
+        !cmd.json
+            && !cmd.no_color
+            && std::io::stdout().is_terminal()
+            && env::var_os("NO_COLOR").is_none(),
+    );
+    let diagnosis = diag::diagnose(&target, &options).await;
+    if !cmd.json {
+        print!("{}", rkik::fmt::text::render_diagnosis(&diagnosis));
+    } else {
+        #[cfg(feature = "json")]
+        println!(
+            "{}",
+            rkik::fmt::json::diagnosis_to_json(&diagnosis, cmd.pretty).map_err(|e| e.to_string())?
+        );
+        #[cfg(not(feature = "json"))]
+        return Err("JSON output requires the json feature".into());
+    }
+    if diagnosis.verdict == diag::Status::Fail {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+async fn handle_http(cmd: HttpCommand, defaults: &Defaults) -> Result<(), String> {
+    let url: HttpUrl = cmd.url.parse().map_err(|e: RkikError| e.to_string())?;
+    let timeout = cmd.timeout.or(defaults.timeout).unwrap_or(5.0);
+    let ipv6_only = cmd.ipv6 || defaults.ipv6_only.unwrap_or(false);
+    console::set_colors_enabled(
+        !cmd.json
+            && !cmd.no_color
+            && std::io::stdout().is_terminal()
+            && env::var_os("NO_COLOR").is_none(),
+    );
+    let sample = match http_date::probe(
+        &url,
+        std::time::Duration::from_secs_f64(timeout),
+        ipv6_only,
+    )
+    .await
+    {
+        Ok(sample) => sample,
+        Err(err) => {
+            #[cfg(feature = "json")]
+            if cmd.json {
+                let doc = rkik::fmt::json::error_to_json(&err, cmd.pretty)
+                    .unwrap_or_else(|_| format!("Error: {}", err));
+                eprintln!("{}", doc);
+                process::exit(err.exit_code());
+            }
+            eprintln!("{}", console::style(format!("Error: {}", err)).red());
+            process::exit(err.exit_code());
+        }
+    };
+    if !cmd.json {
+        print!("{}", rkik::fmt::text::render_http_date(&sample));
+    } else {
+        #[cfg(feature = "json")]
+        println!(
+            "{}",
+            rkik::fmt::json::http_date_to_json(&sample, cmd.pretty).map_err(|e| e.to_string())?
+        );
+        #[cfg(not(feature = "json"))]
+        return Err("JSON output requires the json feature".into());
+    }
+    Ok(())
+}
+
+async fn handle_survey(cmd: SurveyCommand, defaults: &Defaults) -> Result<(), String> {
+    let timeout = cmd.timeout.or(defaults.timeout).unwrap_or(2.0);
+    let query = rkik::services::query::QueryOptions::new()
+        .timeout(std::time::Duration::from_secs_f64(timeout))
+        .ipv6_only(cmd.ipv6 || defaults.ipv6_only.unwrap_or(false))
+        .concurrency(cmd.concurrency.into())
+        .stagger(std::time::Duration::from_secs_f64(
+            cmd.stagger.unwrap_or(0.0).max(0.0) / 1000.0,
+        ));
+    let mut options = SurveyOptions::new()
+        .samples(cmd.samples)
+        .rounds(cmd.rounds)
+        .interval(std::time::Duration::from_secs_f64(cmd.interval))
+        .query(query);
+    if let Some(zone) = &cmd.zone {
+        options = options.zone(zone);
+    }
+    #[cfg(feature = "enrich")]
+    if !cmd.geoip_db.is_empty() {
+        rkik::services::enrich::set_geoip_databases(&cmd.geoip_db).map_err(|e| e.to_string())?;
+        options = options.enrich(true);
+    }
+    console::set_colors_enabled(
+        !cmd.json
+            && !cmd.no_color
+            && std::io::stdout().is_terminal()
+            && env::var_os("NO_COLOR").is_none(),
+    );
+    let result = match survey::survey(&cmd.pool, &options).await {
+        Ok(result) => result,
+        Err(err) => {
+            #[cfg(feature = "json")]
+            if cmd.json {
+                let doc = rkik::fmt::json::error_to_json(&err, cmd.pretty)
+                    .unwrap_or_else(|_| format!("Error: {}", err));
+                eprintln!("{}", doc);
+                process::exit(err.exit_code());
+            }
+            eprintln!("{}", console::style(format!("Error: {}", err)).red());
+            process::exit(err.exit_code());
+        }
+    };
+    if !cmd.json {
+        print!("{}", rkik::fmt::text::render_survey(&result));
+    } else {
+        #[cfg(feature = "json")]
+        println!(
+            "{}",
+            rkik::fmt::json::survey_to_json(&result, cmd.pretty).map_err(|e| e.to_string())?
+        );
+        #[cfg(not(feature = "json"))]
+        return Err("JSON output requires the json feature".into());
+    }
+    if cmd.recommend.is_some() || cmd.emit_config.is_some() {
+        let candidates: Vec<Candidate> = result
+            .members
+            .iter()
+            .filter_map(Candidate::from_member)
+            .collect();
+        let count = cmd.recommend.map_or(DEFAULT_COUNT, usize::from);
+        print!(
+            "{}",
+            recommend::report(&candidates, count, cmd.emit_config, cmd.json, cmd.pretty)?
+        );
+    }
+    Ok(())
+}
+
+async fn handle_asymmetry(cmd: AsymmetryCommand, defaults: &Defaults) -> Result<(), String> {
+    let timeout = cmd.timeout.or(defaults.timeout).unwrap_or(2.0);
+    let query = rkik::services::query::QueryOptions::new()
+        .timeout(std::time::Duration::from_secs_f64(timeout))
+        .ipv6_only(cmd.ipv6 || defaults.ipv6_only.unwrap_or(false));
+    let options = AsymmetryOptions::new()
+        .samples(cmd.count)
+        .interval(std::time::Duration::from_secs_f64(cmd.interval))
+        .query(query);
+    console::set_colors_enabled(
+        !cmd.json
+            && !cmd.no_color
+            && std::io::stdout().is_terminal()
+            && env::var_os("NO_COLOR").is_none(),
+    );
+    let result = match asymmetry::analyse(&cmd.targets, &options).await {
+        Ok(result) => result,
+        Err(err) => {
+            #[cfg(feature = "json")]
+            if cmd.json {
+                let doc = rkik::fmt::json::error_to_json(&err, cmd.pretty)
+                    .unwrap_or_else(|_| format!("Error: {}", err));
+                eprintln!("{}", doc);
+                process::exit(err.exit_code());
+            }
+            eprintln!("{}", console::style(format!("Error: {}", err)).red());
+            process::exit(err.exit_code());
+        }
+    };
+    if !cmd.json {
+        print!("{}", rkik::fmt::text::render_asymmetry(&result));
+        return Ok(());
+    }
+    #[cfg(feature = "json")]
+    {
+        println!(
+            "{}",
+            rkik::fmt::json::asymmetry_to_json(&result, cmd.pretty).map_err(|e| e.to_string())?
+        );
+        Ok(())
+    }
+    #[cfg(not(feature = "json"))]
+    Err("JSON output requires the json feature".into())
+}
+
+async fn handle_check_clock(cmd: CheckClockCommand, defaults: &Defaults) -> Result<(), String> {
+    let timeout = cmd.timeout.or(defaults.timeout).unwrap_or(2.0);
+    let query = rkik::services::query::QueryOptions::new()
+        .timeout(std::time::Duration::from_secs_f64(timeout))
+        .ipv6_only(cmd.ipv6 || defaults.ipv6_only.unwrap_or(false));
+    let options = ClockCheckOptions::new()
+        .window(std::time::Duration::from_secs_f64(cmd.window))
+        .query(query);
+    console::set_colors_enabled(
+        !cmd.json
+            && !cmd.no_color
+            && std::io::stdout().is_terminal()
+            && env::var_os("NO_COLOR").is_none(),
+    );
+    let result = match clock_check::check(&cmd.target, &options).await {
+        Ok(result) => result,
+        Err(err) => {
+            #[cfg(feature = "json")]
+            if cmd.json {
+                let doc = rkik::fmt::json::error_to_json(&err, cmd.pretty)
+                    .unwrap_or_else(|_| format!("Error: {}", err));
+                eprintln!("{}", doc);
+                process::exit(err.exit_code());
+            }
+            eprintln!("{}", console::style(format!("Error: {}", err)).red());
+            process::exit(err.exit_code());
+        }
+    };
+    if !cmd.json {
+        print!("{}", rkik::fmt::text::render_clock_check(&result));
+        return Ok(());
+    }
+    #[cfg(feature = "json")]
+    {
+        println!(
+            "{}",
+            rkik::fmt::json::clock_check_to_json(&result, cmd.pretty).map_err(|e| e.to_string())?
+        );
+        Ok(())
+    }
+    #[cfg(not(feature = "json"))]
+    Err("JSON output requires the json feature".into())
+}
+
+#[cfg(feature = "nts")]
+async fn handle_nts_ke(cmd: NtsKeCommand, defaults: &Defaults) -> Result<(), String> {
+    let timeout = cmd.timeout.or(defaults.timeout).unwrap_or(5.0);
+    let options = NtsKeOptions::new()
+        .count(cmd.count.into())
+        .port(cmd.nts_port)
+        .timeout(std::time::Duration::from_secs_f64(timeout))
+        .ipv6_only(cmd.ipv6 || defaults.ipv6_only.unwrap_or(false));
+    console::set_colors_enabled(
+        !cmd.json
+            && !cmd.no_color
+            && std::io::stdout().is_terminal()
+            && env::var_os("NO_COLOR").is_none(),
+    );
+    let report = match nts_ke::measure(&cmd.target, &options).await {
+        Ok(report) => report,
+        Err(err) => {
+            #[cfg(feature = "json")]
+            if cmd.json {
+                let doc = rkik::fmt::json::error_to_json(&err, cmd.pretty)
+                    .unwrap_or_else(|_| format!("Error: {}", err));
+                eprintln!("{}", doc);
+                process::exit(err.exit_code());
+            }
+            eprintln!("{}", console::style(format!("Error: {}", err)).red());
+            process::exit(err.exit_code());
+        }
+    };
+    if !cmd.json {
+        print!("{}", rkik::fmt::text::render_nts_ke(&report));
+        return Ok(());
+    }
+    #[cfg(feature = "json")]
+    {
+        println!(
+            "{}",
+            rkik::fmt::json::nts_ke_to_json(&report, cmd.pretty).map_err(|e| e.to_string())?
+        );
+        Ok(())
+    }
+    #[cfg(not(feature = "json"))]
+    Err("JSON output requires the json feature".into())
+}
+
+async fn handle_discover(cmd: DiscoverCommand, defaults: &Defaults) -> Result<(), String> {
+    let (domain, servers) = match &cmd.domain {
+        Some(_) if cmd.wait.is_some() => return Err("--wait only applies to --local".into()),
+        Some(domain) => (domain.as_str(), discover::discover(domain).await),
+        None => {
+            let wait = cmd.wait.unwrap_or(2.0);
+            if !(wait.is_finite() && wait > 0.0) {
+                return Err("--wait must be a positive number of seconds".into());
+            }
+            let window = std::time::Duration::from_secs_f64(wait);
+            ("local", discover::discover_local(window).await)
+        }
+    };
+    let servers = servers.map_err(|e| e.to_string())?;
     let mut args = LegacyArgs::default();
-    args.target = Some(cmd.target);
-    args.verbose = true;
-    args.count = 1;
-    args.interval = cmd.common.interval.unwrap_or(1.0);
-    args.timeout = cmd.common.timeout.or(defaults.timeout).unwrap_or(5.0);
-    args.ipv6 = cmd.common.ipv6 || defaults.ipv6_only.unwrap_or(false);
-    args.format = OutputFormat::Text;
-    args.pretty = false;
-    args.infinite = false;
-    args.plugin = false;
-    args.no_color = false;
-    args
+    apply_probe_options(&mut args, &cmd.common, defaults);
+    apply_output_options(&mut args, &cmd.output, defaults)?;
+    match args.format {
+        OutputFormat::Json | OutputFormat::JsonShort => println!(
+            "{}",
+            rkik::fmt::json::discovery_to_json(domain, &servers, args.pretty)
+                .map_err(|e| e.to_string())?
+        ),
+        _ => print!("{}", rkik::fmt::text::render_discovery(domain, &servers)),
+    }
+    // PTP sources are listed but cannot be probed by this build.
+    let mut targets: Vec<String> = servers
+        .iter()
+        .filter(|s| s.protocol != Scheme::Ptp)
+        .map(|s| s.target())
+        .collect();
+    if !cmd.probe || targets.is_empty() {
+        return Ok(());
+    }
+    if targets.len() == 1 {
+        args.target = targets.pop();
+    } else {
+        args.compare = Some(targets);
+    }
+    legacy::run(args, false).await;
+    Ok(())
+}
+
+fn build_exporter_config(
+    cmd: ExporterCommand,
+    config: &ConfigStore,
+) -> Result<exporter::ExporterConfig, String> {
+    let defaults = config.defaults();
+    if !(cmd.interval.is_finite() && cmd.interval > 0.0) {
+        return Err("--interval must be a positive number of seconds".into());
+    }
+    #[cfg(feature = "nts")]
+    let (use_nts, nts_port) = (cmd.nts.nts, cmd.nts.nts_port);
+    #[cfg(not(feature = "nts"))]
+    let (use_nts, nts_port) = (false, 4460u16);
+    let mut targets = cmd.targets;
+    if let Some(path) = &cmd.targets_file {
+        targets.extend(targets_file::read(path)?);
+    }
+    let targets = config.expand_groups(targets).map_err(|e| e.to_string())?;
+    let mut target_timeouts = HashMap::new();
+    let targets = targets
+        .into_iter()
+        .map(|target| {
+            let Some(o) = config.target_override(&target) else {
+                return target;
+            };
+            let target = o.apply(&target);
+            if let (Some(t), None) = (o.timeout, cmd.timeout) {
+                target_timeouts.insert(target.clone(), std::time::Duration::from_secs_f64(t));
+            }
+            target
+        })
+        .collect();
+    Ok(exporter::ExporterConfig {
+        listen: cmd.listen,
+        targets,
+        target_timeouts,
+        interval: std::time::Duration::from_secs_f64(cmd.interval),
+        timeout: std::time::Duration::from_secs_f64(
+            cmd.timeout.or(defaults.timeout).unwrap_or(5.0),
+        ),
+        ipv6: cmd.ipv6 || defaults.ipv6_only.unwrap_or(false),
+        use_nts,
+        nts_port,
+        window: cmd.window.into(),
+    })
+}
+
+/// Merge the `[targets."<name>"]` options of the config file into `args`.
+///
+/// The protocol becomes a `scheme://` prefix on the target; a configured
+/// timeout applies unless `--timeout` was given.
+fn apply_target_overrides(args: &mut LegacyArgs, config: &ConfigStore, explicit_timeout: bool) {
+    if let Some(target) = &args.target
+        && let Some(o) = config.target_override(target)
+    {
+        args.target = Some(o.apply(target));
+        if let (Some(t), false) = (o.timeout, explicit_timeout) {
+            args.timeout = t;
+        }
+    }
+    if let Some(reference) = &args.reference
+        && let Some(o) = config.target_override(reference)
+    {
+        args.reference = Some(o.apply(reference));
+    }
+    for target in args.compare.iter_mut().flatten() {
+        let Some(o) = config.target_override(target) else {
+            continue;
+        };
+        *target = o.apply(target);
+        if let (Some(t), false) = (o.timeout, explicit_timeout) {
+            args.target_timeouts.insert(target.clone(), t);
+        }
+    }
 }
 
 fn apply_probe_options(args: &mut LegacyArgs, opts: &ProbeOptions, defaults: &Defaults) {
     args.count = opts.count.unwrap_or(1);
-    args.interval = opts.interval.unwrap_or(1.0);
+    args.interval = opts.interval.unwrap_or(legacy::Interval::Seconds(1.0));
+    args.duration = opts.duration;
+    args.max_failures = opts.max_failures;
+    args.fail_fast = opts.fail_fast;
+    args.interval_jitter = opts.interval_jitter;
+    args.min_interval = opts.min_interval;
+    args.allow_fast_pool = opts.allow_fast_pool;
     args.timeout = opts.timeout.or(defaults.timeout).unwrap_or(5.0);
     args.infinite = opts.infinite;
     args.ipv6 = opts.ipv6 || defaults.ipv6_only.unwrap_or(false);
+    args.rtt_filter = opts.rtt_filter;
+    args.no_dns_cache = opts.no_dns_cache;
+    args.require_sync = opts.require_sync;
+    args.max_reference_age = opts.max_reference_age;
+    #[cfg(feature = "dnssec")]
+    {
+        args.dnssec = opts.dnssec;
+    }
 }
 
 fn apply_output_options(
@@ -375,13 +1811,42 @@ fn apply_output_options(
     defaults: &Defaults,
 ) -> Result<(), String> {
     args.verbose = opts.verbose;
+    #[cfg(feature = "enrich")]
+    {
+        args.geoip_db = opts.geoip_db.clone();
+    }
     args.pretty = opts.pretty;
+    args.quiet = opts.quiet;
+    args.silent = opts.silent;
     args.no_color = opts.no_color;
+    args.fields = opts.fields.clone();
+    args.unit = opts.unit.unwrap_or_default();
+    args.output = opts.output.clone();
+    args.rotate = opts.rotate;
+    args.rotate_keep = opts.rotate_keep.unwrap_or(5);
+    args.log_target = opts.log_target;
+    args.log_file = opts.log_file.clone();
+    args.pcap = opts.pcap.clone();
+    #[cfg(feature = "sqlite")]
+    {
+        args.store = opts.store.clone();
+    }
+    #[cfg(feature = "json")]
+    {
+        args.zabbix = opts.zabbix.clone();
+        args.zabbix_host = opts.zabbix_host.clone();
+        args.record = opts.record.clone();
+    }
+    #[cfg(feature = "otel")]
+    {
+        args.otlp_endpoint = opts.otel.otlp_endpoint.clone();
+        args.otlp_attributes = opts.otel.otlp_attributes.clone();
+    }
     let mut format = opts.format.clone();
-    if format.is_none() {
-        if let Some(cfg_fmt) = parse_default_format(defaults)? {
-            format = Some(cfg_fmt);
-        }
+    if format.is_none()
+        && let Some(cfg_fmt) = parse_default_format(defaults)?
+    {
+        format = Some(cfg_fmt);
     }
     let mut format = format.unwrap_or(OutputFormat::Text);
     if opts.json {
@@ -397,13 +1862,62 @@ fn apply_plugin_options(args: &mut LegacyArgs, opts: &PluginOptions) {
     args.plugin = opts.plugin;
     args.warning = opts.warning;
     args.critical = opts.critical;
+    args.warning_rtt = opts.warning_rtt;
+    args.critical_rtt = opts.critical_rtt;
+    args.max_stratum = opts.max_stratum;
+    args.highlight = opts.highlight;
+    args.warning_jitter = opts.warning_jitter;
+    args.plugin_format = opts.plugin_format.unwrap_or_default();
+}
+
+/// Plugin and highlight thresholds from the config file, where the command line gives none.
+fn apply_config_thresholds(args: &mut LegacyArgs, thresholds: &Thresholds) {
+    if !args.plugin && !args.highlight {
+        return;
+    }
+    args.warning = args.warning.or(thresholds.warning);
+    args.critical = args.critical.or(thresholds.critical);
+    args.warning_rtt = args.warning_rtt.or(thresholds.warning_rtt);
+    args.critical_rtt = args.critical_rtt.or(thresholds.critical_rtt);
+}
+
+fn check_default_format(raw: &str) -> Result<(), String> {
+    OutputFormat::from_str(raw, false).map(drop).map_err(|_| {
+        format!("unknown format '{raw}' (use text, json, json-short, simple, csv or markdown)")
+    })
+}
+
+/// Dry-parse preset arguments the way `rkik preset run` would execute them.
+fn check_preset_args(args: &[String]) -> Result<(), String> {
+    let argv = std::iter::once("rkik".to_string()).chain(args.iter().cloned());
+    let parsed = if args.first().is_some_and(|a| is_new_keyword(a)) {
+        Cli::try_parse_from(argv).map(drop)
+    } else {
+        LegacyArgs::try_parse_from(argv).map(drop)
+    };
+    match parsed {
+        Ok(()) => Ok(()),
+        Err(e)
+            if matches!(
+                e.kind(),
+                clap::error::ErrorKind::DisplayHelp | clap::error::ErrorKind::DisplayVersion
+            ) =>
+        {
+            Ok(())
+        }
+        Err(e) => {
+            let message = e.to_string();
+            let first = message.lines().next().unwrap_or_default();
+            Err(first.trim_start_matches("error: ").to_string())
+        }
+    }
 }
 
 fn parse_default_format(defaults: &Defaults) -> Result<Option<OutputFormat>, String> {
     if let Some(raw) = defaults.format.as_deref() {
         OutputFormat::from_str(raw, false).map(Some).map_err(|_| {
             format!(
-                "Invalid default format '{}' in rkik config. Use text, json, json-short, or simple.",
+                "Invalid default format '{}' in rkik config. Use text, json, json-short, simple, csv, or markdown.",
                 raw
             )
         })
@@ -431,6 +1945,35 @@ fn handle_config(cmd: ConfigCommand, config: &mut ConfigStore) -> Result<(), Str
                     .map(|v| v.to_string())
                     .unwrap_or_else(|| "<unset>".into())
             );
+            println!(
+                "default_targets = {}",
+                display_opt_list(defaults.default_targets.as_deref())
+            );
+            let thresholds = config.thresholds();
+            for (key, threshold) in [
+                ("warning", thresholds.warning),
+                ("critical", thresholds.critical),
+                ("warning_rtt", thresholds.warning_rtt),
+                ("critical_rtt", thresholds.critical_rtt),
+            ] {
+                if let Some(threshold) = threshold {
+                    println!("thresholds.{} = {}", key, threshold.perfdata(TimeUnit::Ms));
+                }
+            }
+            let mut groups: Vec<_> = config.groups().iter().collect();
+            groups.sort();
+            for (name, servers) in groups {
+                println!("groups.{} = {}", name, servers.join(", "));
+            }
+            let mut profiles: Vec<&String> = config.data.profiles.keys().collect();
+            profiles.sort();
+            if !profiles.is_empty() {
+                let names: Vec<&str> = profiles.iter().map(|p| p.as_str()).collect();
+                println!("profiles = {}", names.join(", "));
+            }
+            if let Some(profile) = config.profile() {
+                println!("profile = {}", profile);
+            }
         }
         ConfigCommand::Get { key } => match key {
             ConfigKey::Timeout => println!("{}", display_opt_float(config.defaults().timeout)),
@@ -446,6 +1989,10 @@ fn handle_config(cmd: ConfigCommand, config: &mut ConfigStore) -> Result<(), Str
                     .map(|v| v.to_string())
                     .unwrap_or_else(|| "<unset>".into())
             ),
+            ConfigKey::DefaultTargets => println!(
+                "{}",
+                display_opt_list(config.defaults().default_targets.as_deref())
+            ),
         },
         ConfigCommand::Set { key, value } => {
             apply_config_value(config, key, Some(value))?;
@@ -455,10 +2002,88 @@ fn handle_config(cmd: ConfigCommand, config: &mut ConfigStore) -> Result<(), Str
             apply_config_value(config, key, None)?;
             persist_config(config)?;
         }
+        ConfigCommand::Validate => {
+            let path = config.path();
+            if !path.exists() {
+                println!("{}: no configuration file, defaults apply", path.display());
+                return Ok(());
+            }
+            let problems = config_store::validate(path, check_default_format, check_preset_args)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("{}: {}", path.display(), problem);
+                }
+                return Err(format!("{} problem(s) found", problems.len()));
+            }
+            println!("{}: OK", path.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn handle_ctl(cmd: CtlCommand) -> Result<(), String> {
+    use toml::Value;
+
+    let mut req = toml::map::Map::new();
+    let mut set = |key: &str, value: Value| {
+        req.insert(key.to_string(), value);
+    };
+    match cmd.action {
+        CtlAction::Status => set("command", "status".into()),
+        CtlAction::Reload => set("command", "reload".into()),
+        CtlAction::Stats { server } => {
+            set("command", "stats".into());
+            if let Some(server) = server {
+                set("server", server.into());
+            }
+        }
+        CtlAction::RemoveTarget { server } => {
+            set("command", "remove-target".into());
+            set("server", server.into());
+        }
+        CtlAction::AddTarget(t) => {
+            set("command", "add-target".into());
+            set("server", t.server.into());
+            if let Some(v) = t.interval {
+                set("interval", v.into());
+            }
+            if let Some(v) = t.timeout {
+                set("timeout", v.into());
+            }
+            if t.ipv6 {
+                set("ipv6", true.into());
+            }
+            if t.nts {
+                set("protocol", "nts".into());
+            }
+            if let Some(port) = t.nts_port {
+                set("nts_port", i64::from(port).into());
+            }
+            for (key, value) in [
+                ("warning", t.warning),
+                ("critical", t.critical),
+                ("warning_rtt", t.warning_rtt),
+                ("critical_rtt", t.critical_rtt),
+            ] {
+                if let Some(v) = value {
+                    set(key, v.into());
+                }
+            }
+        }
     }
+    let socket = cmd.socket.unwrap_or_else(daemon::default_control_socket);
+    let reply = control::request(&socket, &req).await?;
+    print!("{reply}");
     Ok(())
 }
 
+#[cfg(not(unix))]
+async fn handle_ctl(_cmd: CtlCommand) -> Result<(), String> {
+    Err("rkik ctl requires a Unix control socket".into())
+}
+
 fn handle_preset(cmd: PresetCommand, config: &mut ConfigStore) -> Result<(), String> {
     match cmd {
         PresetCommand::List => {
@@ -470,11 +2095,17 @@ fn handle_preset(cmd: PresetCommand, config: &mut ConfigStore) -> Result<(), Str
                 }
             }
         }
-        PresetCommand::Add { name, args } => {
-            if args.is_empty() {
-                return Err("Provide arguments after --".into());
+        PresetCommand::Add {
+            name,
+            extends,
+            args,
+        } => {
+            if args.is_empty() && extends.is_empty() {
+                return Err("Provide arguments after -- or a preset to --extends".into());
             }
-            config.add_preset(name.clone(), args);
+            config.add_preset(name.clone(), args, extends);
+            // Reject unknown bases and cycles before saving.
+            config.preset_args(&name).map_err(|e| e.to_string())?;
             persist_config(config)?;
             println!("Preset '{name}' stored");
         }
@@ -487,14 +2118,19 @@ fn handle_preset(cmd: PresetCommand, config: &mut ConfigStore) -> Result<(), Str
             }
         }
         PresetCommand::Show { name } => match config.preset(&name) {
-            Some(PresetRecord { args }) => println!("{}", args.join(" ")),
+            Some(PresetRecord { args, extends }) => {
+                if !extends.is_empty() {
+                    println!("extends: {}", extends.join(", "));
+                }
+                println!("{}", args.join(" "));
+            }
             None => return Err(format!("Preset '{name}' not found")),
         },
-        PresetCommand::Run { name } => {
-            let preset = config
-                .preset(&name)
-                .ok_or_else(|| format!("Preset '{name}' not found"))?;
-            run_preset(preset)?;
+        PresetCommand::Run { name, set } => {
+            let args = config.preset_args(&name).map_err(|e| e.to_string())?;
+            let args = config_store::fill_placeholders(&args, &set)
+                .map_err(|e| format!("Preset '{name}': {e}"))?;
+            run_preset(&args, config.profile())?;
             return Ok(());
         }
     }
@@ -504,7 +2140,18 @@ fn handle_preset(cmd: PresetCommand, config: &mut ConfigStore) -> Result<(), Str
 fn detect_mode() -> Mode {
     let mut args = env::args_os();
     args.next(); // skip binary
-    match args.next() {
+    let mut first = args.next();
+    // `--profile NAME` may precede the command.
+    while let Some(arg) = &first {
+        let arg = arg.to_string_lossy();
+        if arg == "--profile" {
+            args.next();
+        } else if !arg.starts_with("--profile=") {
+            break;
+        }
+        first = args.next();
+    }
+    match first {
         None => Mode::Modern,
         Some(first) => {
             let first_str = first.to_string_lossy();
@@ -525,7 +2172,28 @@ fn detect_mode() -> Mode {
 }
 
 fn is_new_keyword(s: &str) -> bool {
-    matches!(s, "ntp" | "compare" | "sync" | "diag" | "config" | "preset")
+    matches!(
+        s,
+        "ntp"
+            | "compare"
+            | "sync"
+            | "diag"
+            | "discover"
+            | "http"
+            | "survey"
+            | "asymmetry"
+            | "check-clock"
+            | "nts-ke"
+            | "local"
+            | "replay"
+            | "history"
+            | "exporter"
+            | "daemon"
+            | "serve"
+            | "ctl"
+            | "config"
+            | "preset"
+    )
 }
 
 fn load_config() -> ConfigStore {
@@ -567,10 +2235,7 @@ fn apply_config_value(
         ConfigKey::Timeout => {
             let parsed = value
                 .as_deref()
-                .map(|v| {
-                    v.parse::<f64>()
-                        .map_err(|_| format!("Invalid timeout: {v}"))
-                })
+                .map(|v| duration::parse_seconds(v).map_err(|e| format!("Invalid timeout: {e}")))
                 .transpose()?;
             config.update_timeout(parsed);
         }
@@ -581,7 +2246,8 @@ fn apply_config_value(
                     OutputFormat::from_str(v, false)
                         .map(|fmt| fmt.as_str().to_string())
                         .map_err(|_| {
-                            "Unknown format. Use text, json, json-short, or simple.".to_string()
+                            "Unknown format. Use text, json, json-short, simple, csv, or markdown."
+                                .to_string()
                         })
                 })
                 .transpose()?;
@@ -594,6 +2260,24 @@ fn apply_config_value(
                 .transpose()?;
             config.update_ipv6(parsed);
         }
+        ConfigKey::DefaultTargets => {
+            let parsed = match value.as_deref().map(str::trim) {
+                None | Some("false") => None,
+                Some("true") => Some(vec![config_store::DEFAULT_POOL.to_string()]),
+                Some(list) => {
+                    let targets: Vec<String> = list
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    if targets.is_empty() {
+                        return Err("Provide at least one target, or true for pool.ntp.org".into());
+                    }
+                    Some(targets)
+                }
+            };
+            config.update_default_targets(parsed);
+        }
     }
     Ok(())
 }
@@ -606,18 +2290,32 @@ fn persist_config(config: &ConfigStore) -> Result<(), String> {
     })
 }
 
-fn run_preset(preset: &PresetRecord) -> Result<(), String> {
-    if preset.args.is_empty() {
+fn run_preset(args: &[String], profile: Option<&str>) -> Result<(), String> {
+    if args.is_empty() {
         return Err("Preset is empty".into());
     }
     let exe = env::current_exe().map_err(|e| e.to_string())?;
     let status = ProcessCommand::new(exe)
-        .args(&preset.args)
+        .args(args)
+        .envs(profile.map(|p| ("RKIK_PROFILE", p)))
         .status()
         .map_err(|e| e.to_string())?;
     process::exit(status.code().unwrap_or(1));
 }
 
+fn parse_placeholder_value(raw: &str) -> Result<(String, String), String> {
+    match raw.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{raw}'")),
+    }
+}
+
+fn display_opt_list(value: Option<&[String]>) -> String {
+    value
+        .map(|v| v.join(", "))
+        .unwrap_or_else(|| "<unset>".into())
+}
+
 fn display_opt_float(value: Option<f64>) -> String {
     value
         .map(|v| format!("{:.3}", v))
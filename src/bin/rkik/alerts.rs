@@ -0,0 +1,761 @@
+//! Alerting rules of `rkik daemon`.
+//!
+//! A rule compares one metric of the targets matching its glob with a
+//! bound, after every probe. It fires once the comparison has held for
+//! `for`, and resolves only when the metric crosses back over `clear`, so
+//! a value hovering around the bound does not flap. Both transitions are
+//! logged, and posted to a webhook or published on an MQTT broker when the
+//! rule says so.
+
+use std::collections::HashMap;
+#[cfg(feature = "json")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use rkik::adapters::http_date::HttpUrl;
+use rkik::stats::Stats;
+#[cfg(feature = "json")]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "json")]
+use tokio::net::TcpStream;
+use toml::Value;
+
+use crate::daemon::{Table, seconds};
+
+/// Time allowed to deliver a webhook or MQTT message.
+#[cfg(feature = "json")]
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+const MQTT_PORT: u16 = 1883;
+
+/// What a rule looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Absolute average offset over the window (ms).
+    Offset,
+    /// Largest absolute offset in the window (ms).
+    OffsetMax,
+    /// Average round-trip delay over the window (ms).
+    Rtt,
+    /// RFC 5905 peer jitter over the window (ms).
+    Jitter,
+    /// Stratum of the last answer.
+    Stratum,
+    /// Probes failed in a row.
+    Failures,
+}
+
+impl Metric {
+    const NAMES: [(&str, Metric); 6] = [
+        ("offset", Metric::Offset),
+        ("offset_max", Metric::OffsetMax),
+        ("rtt", Metric::Rtt),
+        ("jitter", Metric::Jitter),
+        ("stratum", Metric::Stratum),
+        ("failures", Metric::Failures),
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, m)| *m == self)
+            .map_or("", |(name, _)| name)
+    }
+
+    fn value(self, window: &Window) -> Option<f64> {
+        let stats = window.stats.as_ref();
+        match self {
+            Metric::Offset => stats.map(|s| s.offset_avg.abs()),
+            Metric::OffsetMax => stats.map(|s| s.offset_min.abs().max(s.offset_max.abs())),
+            Metric::Rtt => stats.map(|s| s.rtt_avg),
+            Metric::Jitter => stats.and_then(|s| s.jitter),
+            Metric::Stratum => window.stratum.map(f64::from),
+            Metric::Failures => Some(f64::from(window.failures)),
+        }
+    }
+}
+
+/// How the metric is compared with the bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparator {
+    Above,
+    AtLeast,
+    Below,
+    AtMost,
+}
+
+impl Comparator {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            ">" => Some(Self::Above),
+            ">=" => Some(Self::AtLeast),
+            "<" => Some(Self::Below),
+            "<=" => Some(Self::AtMost),
+            _ => None,
+        }
+    }
+
+    fn holds(self, value: f64, bound: f64) -> bool {
+        match self {
+            Self::Above => value > bound,
+            Self::AtLeast => value >= bound,
+            Self::Below => value < bound,
+            Self::AtMost => value <= bound,
+        }
+    }
+
+    fn rising(self) -> bool {
+        matches!(self, Self::Above | Self::AtLeast)
+    }
+}
+
+/// Where transitions go besides the daemon's log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Log,
+    /// JSON `POST` to an `http://` URL.
+    Webhook(HttpUrl),
+    /// JSON message published with QoS 0.
+    Mqtt {
+        host: String,
+        port: u16,
+        topic: String,
+    },
+}
+
+/// One `[[rule]]` of the daemon configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    /// Glob over target names (`*` and `?`).
+    pub target: String,
+    pub metric: Metric,
+    pub op: Comparator,
+    pub value: f64,
+    /// Bound the metric must cross back over to resolve (default `value`).
+    pub clear: f64,
+    /// How long the comparison must hold before the rule fires.
+    pub hold: Duration,
+    /// Most recent part of the rolling window the statistics cover
+    /// (default: all of it).
+    pub window: Option<Duration>,
+    pub action: Action,
+}
+
+/// Build a rule from a `[[rule]]` table.
+pub fn parse_rule(t: &Table) -> Result<Rule, String> {
+    let name = t
+        .get("name")
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or("missing `name`")?
+        .to_string();
+    let ctx = format!("rule '{name}' ");
+    let key = |k: &str| format!("{ctx}{k}");
+    let text = |k: &str| match t.get(k) {
+        None => Ok(None),
+        Some(v) => v
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| format!("{} must be a string", key(k))),
+    };
+    let number = |k: &str| match t.get(k) {
+        None => Ok(None),
+        Some(v) => v
+            .as_float()
+            .or_else(|| v.as_integer().map(|n| n as f64))
+            .filter(|n| n.is_finite())
+            .map(Some)
+            .ok_or_else(|| format!("{} must be a number", key(k))),
+    };
+
+    let target = text("target")?.unwrap_or("*").to_string();
+    let metric = text("metric")?.ok_or_else(|| format!("{ctx}is missing `metric`"))?;
+    let metric = Metric::NAMES
+        .iter()
+        .find(|(n, _)| *n == metric)
+        .map(|(_, m)| *m)
+        .ok_or_else(|| {
+            let names: Vec<_> = Metric::NAMES.iter().map(|(n, _)| *n).collect();
+            format!("{} must be one of {}", key("metric"), names.join(", "))
+        })?;
+    let op = match text("op")? {
+        None => Comparator::Above,
+        Some(s) => Comparator::parse(s)
+            .ok_or_else(|| format!("{} must be one of >, >=, <, <=", key("op")))?,
+    };
+    let value = number("value")?.ok_or_else(|| format!("{ctx}is missing `value`"))?;
+    let clear = number("clear")?.unwrap_or(value);
+    if (op.rising() && clear > value) || (!op.rising() && clear < value) {
+        let side = if op.rising() { "above" } else { "below" };
+        return Err(format!("{} cannot be {side} `value`", key("clear")));
+    }
+    let hold = Duration::from_secs_f64(seconds(t, "for", &ctx)?.unwrap_or(0.0));
+    let window = seconds(t, "window", &ctx)?.map(Duration::from_secs_f64);
+
+    let action = match text("action")?.unwrap_or("log") {
+        "log" => Action::Log,
+        "webhook" => {
+            let url = text("url")?.ok_or_else(|| format!("{ctx}is missing `url`"))?;
+            let url: HttpUrl = url.parse().map_err(|e| format!("{}: {e}", key("url")))?;
+            // A bare host parses as https.
+            if url.tls {
+                return Err(format!(
+                    "{} must be an http:// URL (use a local relay for TLS)",
+                    key("url")
+                ));
+            }
+            Action::Webhook(url)
+        }
+        "mqtt" => {
+            let broker = text("broker")?.ok_or_else(|| format!("{ctx}is missing `broker`"))?;
+            let (host, port) = match broker.rsplit_once(':') {
+                Some((host, port)) if !host.contains(':') || host.ends_with(']') => (
+                    host.trim_start_matches('[').trim_end_matches(']'),
+                    port.parse()
+                        .map_err(|_| format!("{} has an invalid port", key("broker")))?,
+                ),
+                _ => (broker, MQTT_PORT),
+            };
+            Action::Mqtt {
+                host: host.to_string(),
+                port,
+                topic: text("topic")?.unwrap_or("rkik/alerts").to_string(),
+            }
+        }
+        _ => {
+            return Err(format!(
+                "{} must be \"log\", \"webhook\" or \"mqtt\"",
+                key("action")
+            ));
+        }
+    };
+    #[cfg(not(feature = "json"))]
+    if action != Action::Log {
+        return Err(format!(
+            "{}: webhook and MQTT actions need the `json` feature",
+            key("action")
+        ));
+    }
+
+    Ok(Rule {
+        name,
+        target,
+        metric,
+        op,
+        value,
+        clear,
+        hold,
+        window,
+        action,
+    })
+}
+
+/// `*` matches any run of characters, `?` any single one.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    // Position after the last `*` and the text position it was tried at.
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi + 1, ti));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match star {
+                Some((after, tried)) => {
+                    pi = after;
+                    ti = tried + 1;
+                    star = Some((after, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// What the rules see of one target after a probe.
+pub struct Window {
+    pub stats: Option<Stats>,
+    pub stratum: Option<u8>,
+    pub failures: u32,
+}
+
+/// A rule starting or ceasing to fire for a target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub rule: String,
+    pub target: String,
+    pub metric: Metric,
+    pub value: f64,
+    /// `value` of the rule when firing, `clear` when resolved.
+    pub threshold: f64,
+    pub firing: bool,
+    pub action: Action,
+}
+
+#[derive(Debug, Default)]
+struct RuleState {
+    /// Since when the comparison holds.
+    pending: Option<Instant>,
+    firing: bool,
+    /// Last value of the metric.
+    value: f64,
+}
+
+/// The rules and where each stands for each target.
+#[derive(Debug, Default)]
+pub struct Alerts {
+    rules: Vec<Rule>,
+    /// Keyed by rule name and target.
+    states: HashMap<(String, String), RuleState>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            states: HashMap::new(),
+        }
+    }
+
+    /// Replace the rules; rules that stay unchanged keep their state.
+    /// Returns a resolved alert for each target a removed or changed rule
+    /// was firing for.
+    pub fn set_rules(&mut self, rules: Vec<Rule>) -> Vec<Alert> {
+        let kept: Vec<String> = self
+            .rules
+            .iter()
+            .filter(|r| rules.contains(r))
+            .map(|r| r.name.clone())
+            .collect();
+        let resolved = self.discard(|(name, _)| !kept.contains(name));
+        self.rules = rules;
+        resolved
+    }
+
+    /// Drop the state of a target that is no longer monitored, resolving
+    /// the rules firing for it.
+    pub fn forget(&mut self, target: &str) -> Vec<Alert> {
+        self.discard(|(_, t)| t == target)
+    }
+
+    /// Drop the states matching `drop`, with a resolved alert for each
+    /// one that was firing: receivers would otherwise never see it end.
+    fn discard(&mut self, drop: impl Fn(&(String, String)) -> bool) -> Vec<Alert> {
+        let mut resolved = Vec::new();
+        self.states.retain(|key, state| {
+            if !drop(key) {
+                return true;
+            }
+            let rule = self.rules.iter().find(|r| r.name == key.0);
+            if let Some(rule) = rule.filter(|_| state.firing) {
+                resolved.push(Alert {
+                    rule: rule.name.clone(),
+                    target: key.1.clone(),
+                    metric: rule.metric,
+                    value: state.value,
+                    threshold: rule.clear,
+                    firing: false,
+                    action: rule.action.clone(),
+                });
+            }
+            false
+        });
+        resolved
+    }
+
+    /// Apply every rule matching `target` at `now`; `window` gives what
+    /// the rule sees over its span. Returns the transitions.
+    pub fn evaluate(
+        &mut self,
+        target: &str,
+        window: impl Fn(Option<Duration>) -> Window,
+        now: Instant,
+    ) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for rule in self.rules.iter().filter(|r| glob(&r.target, target)) {
+            // Without a value (no answer yet, no jitter) nothing changes.
+            let Some(value) = rule.metric.value(&window(rule.window)) else {
+                continue;
+            };
+            let state = self
+                .states
+                .entry((rule.name.clone(), target.to_string()))
+                .or_default();
+            state.value = value;
+            let alert = |firing, threshold| Alert {
+                rule: rule.name.clone(),
+                target: target.to_string(),
+                metric: rule.metric,
+                value,
+                threshold,
+                firing,
+                action: rule.action.clone(),
+            };
+            if state.firing {
+                if !rule.op.holds(value, rule.clear) {
+                    *state = RuleState {
+                        value,
+                        ..RuleState::default()
+                    };
+                    alerts.push(alert(false, rule.clear));
+                }
+            } else if rule.op.holds(value, rule.value) {
+                let since = *state.pending.get_or_insert(now);
+                if now.duration_since(since) >= rule.hold {
+                    state.firing = true;
+                    alerts.push(alert(true, rule.value));
+                }
+            } else {
+                state.pending = None;
+            }
+        }
+        alerts
+    }
+}
+
+/// Log `alert`, then hand it to its action in the background.
+pub fn dispatch(alert: Alert) {
+    let Alert {
+        rule,
+        target,
+        value,
+        threshold,
+        ..
+    } = &alert;
+    let metric = alert.metric.as_str();
+    if alert.firing {
+        tracing::warn!(%rule, server = %target, metric, value, threshold, "alert firing");
+    } else {
+        tracing::info!(%rule, server = %target, metric, value, threshold, "alert resolved");
+    }
+    #[cfg(feature = "json")]
+    if alert.action != Action::Log {
+        tokio::spawn(async move {
+            let sent = tokio::time::timeout(SEND_TIMEOUT, send(&alert)).await;
+            if let Err(e) = sent.unwrap_or_else(|_| Err("timed out".into())) {
+                tracing::warn!(rule = %alert.rule, error = %e, "alert action failed");
+            }
+        });
+    }
+}
+
+#[cfg(feature = "json")]
+fn payload(alert: &Alert) -> String {
+    serde_json::json!({
+        "rule": alert.rule,
+        "target": alert.target,
+        "state": if alert.firing { "firing" } else { "resolved" },
+        "metric": alert.metric.as_str(),
+        "value": alert.value,
+        "threshold": alert.threshold,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    })
+    .to_string()
+}
+
+#[cfg(feature = "json")]
+async fn send(alert: &Alert) -> Result<(), String> {
+    let body = payload(alert);
+    match &alert.action {
+        Action::Log => Ok(()),
+        Action::Webhook(url) => post(url, &body).await,
+        Action::Mqtt { host, port, topic } => publish(host, *port, topic, body.as_bytes()).await,
+    }
+}
+
+#[cfg(feature = "json")]
+async fn post(url: &HttpUrl, body: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port))
+        .await
+        .map_err(|e| format!("{url}: {e}"))?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: rkik/{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        url.path,
+        host_header(url),
+        env!("CARGO_PKG_VERSION"),
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .await
+        .map_err(|e| e.to_string())?;
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(format!("{url} replied '{}'", status.trim())),
+    }
+}
+
+/// `Host` header of a request to `url`: bracketed IPv6 literal, and the
+/// port unless it is 80.
+#[cfg(feature = "json")]
+fn host_header(url: &HttpUrl) -> String {
+    let host = if url.host.contains(':') {
+        format!("[{}]", url.host)
+    } else {
+        url.host.clone()
+    };
+    match url.port {
+        80 => host,
+        port => format!("{host}:{port}"),
+    }
+}
+
+/// Publish `payload` on `topic` with QoS 0 over MQTT 3.1.1, in a
+/// connection of its own.
+#[cfg(feature = "json")]
+async fn publish(host: &str, port: u16, topic: &str, payload: &[u8]) -> Result<(), String> {
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| format!("{host}:{port}: {e}"))?;
+    // Brokers drop the older of two connections with the same id, and
+    // transitions of one tick are sent concurrently.
+    static CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+    let client_id = format!(
+        "rkik-{}-{}",
+        std::process::id(),
+        CONNECTIONS.fetch_add(1, Ordering::Relaxed)
+    );
+    let mut connect = mqtt_string("MQTT");
+    // Level 4 (3.1.1), clean session, 60 s keep-alive.
+    connect.extend_from_slice(&[4, 0x02, 0, 60]);
+    connect.extend(mqtt_string(&client_id));
+    stream
+        .write_all(&mqtt_packet(0x10, &connect))
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut connack = [0u8; 4];
+    stream
+        .read_exact(&mut connack)
+        .await
+        .map_err(|e| format!("{host}:{port}: no CONNACK: {e}"))?;
+    if connack[0] != 0x20 || connack[3] != 0 {
+        return Err(format!(
+            "{host}:{port}: connection refused (code {})",
+            connack[3]
+        ));
+    }
+    let mut publish = mqtt_string(topic);
+    publish.extend_from_slice(payload);
+    stream
+        .write_all(&mqtt_packet(0x30, &publish))
+        .await
+        .map_err(|e| e.to_string())?;
+    stream
+        .write_all(&mqtt_packet(0xE0, &[]))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Fixed header (type and flags, remaining length) followed by `body`.
+#[cfg(feature = "json")]
+fn mqtt_packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![kind];
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        out.push(if len > 0 { byte | 0x80 } else { byte });
+        if len == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+#[cfg(feature = "json")]
+fn mqtt_string(s: &str) -> Vec<u8> {
+    let mut out = (s.len() as u16).to_be_bytes().to_vec();
+    out.extend_from_slice(s.as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(toml: &str) -> Result<Rule, String> {
+        let table: Table = toml.parse::<Table>().map_err(|e| e.to_string())?;
+        parse_rule(&table)
+    }
+
+    fn offset(ms: f64) -> impl Fn(Option<Duration>) -> Window {
+        move |_| Window {
+            stats: Some(Stats {
+                offset_avg: ms,
+                ..rkik::stats::compute_stats(&[])
+            }),
+            stratum: Some(2),
+            failures: 0,
+        }
+    }
+
+    #[test]
+    fn globs_match_target_names() {
+        assert!(glob("*", "time.example"));
+        assert!(glob("*.pool.ntp.org", "0.pool.ntp.org"));
+        assert!(glob("ntp?.lan", "ntp1.lan"));
+        assert!(glob("a*b*c", "aXbYbZc"));
+        assert!(!glob("*.pool.ntp.org", "time.example"));
+        assert!(!glob("ntp?.lan", "ntp10.lan"));
+    }
+
+    #[test]
+    fn parses_rules_and_rejects_inconsistent_ones() {
+        let r = rule(
+            "name = \"drift\"\ntarget = \"*.lan\"\nmetric = \"offset\"\nop = \">=\"\n\
+             value = 100\nclear = 80\nfor = \"2m\"\nwindow = \"5m\"\n\
+             action = \"mqtt\"\nbroker = \"mqtt.lan\"\n",
+        )
+        .unwrap();
+        assert_eq!((r.op, r.value, r.clear), (Comparator::AtLeast, 100.0, 80.0));
+        assert_eq!(r.hold, Duration::from_secs(120));
+        assert_eq!(r.window, Some(Duration::from_secs(300)));
+        assert_eq!(
+            r.action,
+            Action::Mqtt {
+                host: "mqtt.lan".into(),
+                port: 1883,
+                topic: "rkik/alerts".into()
+            }
+        );
+        let hook = rule("name = \"h\"\nmetric = \"rtt\"\nvalue = 50\naction = \"webhook\"\nurl = \"http://hooks.lan:8080/rkik\"\n").unwrap();
+        assert_eq!((hook.target.as_str(), hook.clear), ("*", 50.0));
+
+        for (toml, expected) in [
+            ("metric = \"offset\"\nvalue = 1\n", "missing `name`"),
+            (
+                "name = \"x\"\nmetric = \"skew\"\nvalue = 1\n",
+                "metric must be one of",
+            ),
+            (
+                "name = \"x\"\nmetric = \"offset\"\nvalue = 1\nclear = 2\n",
+                "clear cannot be above",
+            ),
+            (
+                "name = \"x\"\nmetric = \"stratum\"\nop = \"<\"\nvalue = 2\nclear = 1\n",
+                "clear cannot be below",
+            ),
+            (
+                "name = \"x\"\nmetric = \"offset\"\nvalue = 1\naction = \"webhook\"\nurl = \"https://hooks.example\"\n",
+                "must be an http:// URL",
+            ),
+        ] {
+            let err = rule(toml).unwrap_err();
+            assert!(err.contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn rules_fire_after_their_delay_and_resolve_past_clear() {
+        let mut alerts = Alerts::new(vec![
+            rule("name = \"offset\"\nmetric = \"offset\"\nvalue = 100\nclear = 80\nfor = 60\n")
+                .unwrap(),
+        ]);
+        let t0 = Instant::now();
+        let at = |s| t0 + Duration::from_secs(s);
+
+        assert!(alerts.evaluate("a", offset(150.0), at(0)).is_empty());
+        // The condition must hold all along: a dip restarts the delay.
+        assert!(alerts.evaluate("a", offset(50.0), at(30)).is_empty());
+        assert!(alerts.evaluate("a", offset(150.0), at(60)).is_empty());
+        let fired = alerts.evaluate("a", offset(-150.0), at(120));
+        assert_eq!(fired.len(), 1);
+        assert!(fired[0].firing);
+        assert_eq!((fired[0].value, fired[0].threshold), (150.0, 100.0));
+
+        // Between clear and value: still firing.
+        assert!(alerts.evaluate("a", offset(90.0), at(180)).is_empty());
+        let resolved = alerts.evaluate("a", offset(70.0), at(240));
+        assert_eq!(resolved.len(), 1);
+        assert!(!resolved[0].firing);
+        assert_eq!(resolved[0].threshold, 80.0);
+
+        // Targets are tracked apart; unchanged rules keep their state.
+        alerts.evaluate("b", offset(150.0), at(0));
+        assert!(alerts.set_rules(alerts.rules.clone()).is_empty());
+        assert_eq!(alerts.evaluate("b", offset(150.0), at(60)).len(), 1);
+    }
+
+    #[test]
+    fn dropped_states_resolve_what_was_firing() {
+        let offset_rule = rule("name = \"offset\"\nmetric = \"offset\"\nvalue = 100\n").unwrap();
+        let mut alerts = Alerts::new(vec![offset_rule.clone()]);
+        let now = Instant::now();
+        for target in ["a", "b", "c"] {
+            alerts.evaluate(target, offset(150.0), now);
+        }
+        alerts.evaluate("c", offset(10.0), now);
+
+        let gone = alerts.forget("a");
+        assert_eq!(gone.len(), 1);
+        assert_eq!((gone[0].target.as_str(), gone[0].firing), ("a", false));
+        assert_eq!((gone[0].value, gone[0].threshold), (150.0, 100.0));
+        assert!(alerts.forget("c").is_empty());
+
+        let changed = Rule {
+            value: 200.0,
+            clear: 200.0,
+            ..offset_rule
+        };
+        let resolved = alerts.set_rules(vec![changed]);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(
+            (resolved[0].target.as_str(), resolved[0].threshold),
+            ("b", 100.0)
+        );
+        assert!(alerts.states.is_empty());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn host_header_keeps_the_port_and_brackets() {
+        let header = |url: &str| host_header(&url.parse().unwrap());
+        assert_eq!(header("http://alerts.lan:8080/rkik"), "alerts.lan:8080");
+        assert_eq!(header("http://alerts.lan/rkik"), "alerts.lan");
+        assert_eq!(header("http://[::1]:8080/"), "[::1]:8080");
+        assert_eq!(header("http://[2001:db8::1]/"), "[2001:db8::1]");
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn mqtt_messages_are_published_after_connack() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut connect = [0u8; 2];
+            conn.read_exact(&mut connect).await.unwrap();
+            let mut rest = vec![0u8; connect[1] as usize];
+            conn.read_exact(&mut rest).await.unwrap();
+            assert_eq!((connect[0], &rest[..6]), (0x10, &b"\0\x04MQTT"[..]));
+            conn.write_all(&[0x20, 0x02, 0, 0]).await.unwrap();
+            let mut received = Vec::new();
+            conn.read_to_end(&mut received).await.unwrap();
+            received
+        });
+        publish("127.0.0.1", port, "rkik/alerts", b"{}")
+            .await
+            .unwrap();
+        let received = broker.await.unwrap();
+        assert_eq!(
+            received,
+            [&[0x30, 15, 0, 11][..], b"rkik/alerts{}", &[0xE0, 0]].concat()
+        );
+    }
+}
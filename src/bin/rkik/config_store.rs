@@ -6,6 +6,13 @@ use std::path::{Path, PathBuf};
 
 use toml::Value;
 
+use rkik::fmt::units::TimeUnit;
+
+use crate::threshold::Threshold;
+
+/// Probed by a bare `rkik` when `default_targets = true`.
+pub const DEFAULT_POOL: &str = "pool.ntp.org";
+
 #[derive(Debug)]
 pub enum ConfigError {
     Io(io::Error),
@@ -46,22 +53,99 @@ pub struct Defaults {
     pub timeout: Option<f64>,
     pub format: Option<String>,
     pub ipv6_only: Option<bool>,
+    /// Targets probed by a bare `rkik` (`default_targets = true` means pool.ntp.org).
+    pub default_targets: Option<Vec<String>>,
+}
+
+/// Plugin thresholds used when the command line gives none (`[thresholds]`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Thresholds {
+    pub warning: Option<Threshold>,
+    pub critical: Option<Threshold>,
+    pub warning_rtt: Option<Threshold>,
+    pub critical_rtt: Option<Threshold>,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct PresetRecord {
     pub args: Vec<String>,
+    /// Presets whose arguments come first (`extends = ["base"]`).
+    pub extends: Vec<String>,
+}
+
+/// Options applied whenever a target is used (`[targets."gm1.lab"]`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TargetOverride {
+    pub timeout: Option<f64>,
+    pub nts: Option<bool>,
+    /// `ntp`, `nts` or `ptp`.
+    pub protocol: Option<String>,
+    /// PTP domain number (only with `protocol = "ptp"`).
+    pub domain: Option<u8>,
+}
+
+impl TargetOverride {
+    /// `target` with the protocol of this override as a `scheme://` prefix.
+    ///
+    /// A target that already names its scheme is left as written.
+    pub fn apply(&self, target: &str) -> String {
+        if target.contains("://") {
+            return target.to_string();
+        }
+        let scheme = match (self.protocol.as_deref(), self.nts) {
+            (Some(protocol), _) => protocol,
+            (None, Some(true)) => "nts",
+            (None, Some(false)) => "ntp",
+            (None, None) => return target.to_string(),
+        };
+        match self.domain {
+            Some(domain) => format!("{scheme}://{target}?domain={domain}"),
+            None => format!("{scheme}://{target}"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct ConfigData {
     pub defaults: Defaults,
     pub presets: HashMap<String, PresetRecord>,
+    /// Named server lists, referenced as `@name` in target lists.
+    pub groups: HashMap<String, Vec<String>>,
+    /// Per-target options, keyed by the target as written on the command line.
+    pub targets: HashMap<String, TargetOverride>,
+    pub thresholds: Thresholds,
+    /// Named overlays of the sections above (`[profiles.prod]`), selected
+    /// with `--profile` or `RKIK_PROFILE`.
+    pub profiles: HashMap<String, ConfigData>,
+}
+
+impl ConfigData {
+    /// Layer `profile` over `self`: its defaults and thresholds win field by
+    /// field, its groups, presets and targets win by name.
+    fn overlay(&mut self, profile: &ConfigData) {
+        let (d, p) = (&mut self.defaults, &profile.defaults);
+        d.timeout = p.timeout.or(d.timeout);
+        d.format = p.format.clone().or(d.format.take());
+        d.ipv6_only = p.ipv6_only.or(d.ipv6_only);
+        d.default_targets = p.default_targets.clone().or(d.default_targets.take());
+        let (t, p) = (&mut self.thresholds, &profile.thresholds);
+        t.warning = p.warning.or(t.warning);
+        t.critical = p.critical.or(t.critical);
+        t.warning_rtt = p.warning_rtt.or(t.warning_rtt);
+        t.critical_rtt = p.critical_rtt.or(t.critical_rtt);
+        self.presets.extend(profile.presets.clone());
+        self.groups.extend(profile.groups.clone());
+        self.targets.extend(profile.targets.clone());
+    }
 }
 
 pub struct ConfigStore {
     path: PathBuf,
+    /// The file as written: top-level sections and profiles.
     pub data: ConfigData,
+    profile: Option<String>,
+    /// `data` with the selected profile applied.
+    active: ConfigData,
 }
 
 impl ConfigStore {
@@ -70,13 +154,74 @@ impl ConfigStore {
         if !path.exists() {
             return Ok(Self {
                 path,
-                data: ConfigData::default(),
+                ..Self::empty()
             });
         }
         let content = fs::read_to_string(&path)?;
         let parsed: Value = content.parse::<Value>()?;
         let data = parse_value(parsed)?;
-        Ok(Self { path, data })
+        Ok(Self {
+            path,
+            active: data.clone(),
+            data,
+            profile: None,
+        })
+    }
+
+    /// Apply profile `name` on top of the top-level sections.
+    ///
+    /// Later updates (`config set`, `preset add`) go to the profile. An
+    /// unknown profile is an error unless `create` is set.
+    pub fn select_profile(&mut self, name: &str, create: bool) -> Result<(), ConfigError> {
+        if !self.data.profiles.contains_key(name) {
+            if !create {
+                let mut known: Vec<&String> = self.data.profiles.keys().collect();
+                known.sort();
+                let known = known
+                    .iter()
+                    .map(|k| k.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(ConfigError::Invalid(if known.is_empty() {
+                    format!("unknown profile '{name}' (no profiles configured)")
+                } else {
+                    format!("unknown profile '{name}' (known: {known})")
+                }));
+            }
+            self.data
+                .profiles
+                .insert(name.to_string(), ConfigData::default());
+        }
+        self.profile = Some(name.to_string());
+        self.refresh();
+        Ok(())
+    }
+
+    /// The selected profile, if any.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Recompute the active view after `data` changed.
+    fn refresh(&mut self) {
+        let mut active = self.data.clone();
+        active.profiles.clear();
+        if let Some(profile) = self
+            .profile
+            .as_ref()
+            .and_then(|p| self.data.profiles.get(p))
+        {
+            active.overlay(profile);
+        }
+        self.active = active;
+    }
+
+    /// The layer updates are written to: the selected profile or the top level.
+    fn layer_mut(&mut self) -> &mut ConfigData {
+        match &self.profile {
+            Some(name) => self.data.profiles.entry(name.clone()).or_default(),
+            None => &mut self.data,
+        }
     }
 
     pub fn path(&self) -> &Path {
@@ -87,77 +232,433 @@ impl ConfigStore {
         if let Some(parent) = self.path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let mut root = Value::Table(Default::default());
-        if let Value::Table(table) = &mut root {
-            if let Some(defaults_table) = defaults_to_toml(&self.data.defaults) {
-                table.insert("defaults".into(), Value::Table(defaults_table));
-            }
-            if !self.data.presets.is_empty() {
-                let mut presets = toml::map::Map::new();
-                for (name, preset) in &self.data.presets {
-                    let value = Value::Array(
-                        preset
-                            .args
-                            .iter()
-                            .map(|s| Value::String(s.clone()))
-                            .collect(),
-                    );
-                    let mut preset_table = toml::map::Map::new();
-                    preset_table.insert("args".into(), value);
-                    presets.insert(name.clone(), Value::Table(preset_table));
-                }
-                table.insert("presets".into(), Value::Table(presets));
-            }
-        }
+        let root = Value::Table(data_to_toml(&self.data));
         let serialized = toml::to_string_pretty(&root)?;
         fs::write(&self.path, serialized)?;
         Ok(())
     }
 
     pub fn defaults(&self) -> &Defaults {
-        &self.data.defaults
+        &self.active.defaults
+    }
+
+    pub fn thresholds(&self) -> &Thresholds {
+        &self.active.thresholds
     }
 
     pub fn update_timeout(&mut self, value: Option<f64>) {
-        self.data.defaults.timeout = value;
+        self.layer_mut().defaults.timeout = value;
+        self.refresh();
     }
 
     pub fn update_format(&mut self, value: Option<String>) {
-        self.data.defaults.format = value;
+        self.layer_mut().defaults.format = value;
+        self.refresh();
     }
 
     pub fn update_ipv6(&mut self, value: Option<bool>) {
-        self.data.defaults.ipv6_only = value;
+        self.layer_mut().defaults.ipv6_only = value;
+        self.refresh();
+    }
+
+    pub fn update_default_targets(&mut self, value: Option<Vec<String>>) {
+        self.layer_mut().defaults.default_targets = value;
+        self.refresh();
     }
 
-    pub fn add_preset(&mut self, name: String, args: Vec<String>) {
-        self.data.presets.insert(name, PresetRecord { args });
+    pub fn add_preset(&mut self, name: String, args: Vec<String>, extends: Vec<String>) {
+        self.layer_mut()
+            .presets
+            .insert(name, PresetRecord { args, extends });
+        self.refresh();
     }
 
     pub fn remove_preset(&mut self, name: &str) -> bool {
-        self.data.presets.remove(name).is_some()
+        let removed = self.layer_mut().presets.remove(name).is_some();
+        self.refresh();
+        removed
     }
 
     pub fn presets(&self) -> &HashMap<String, PresetRecord> {
-        &self.data.presets
+        &self.active.presets
     }
 
     pub fn preset(&self, name: &str) -> Option<&PresetRecord> {
-        self.data.presets.get(name)
+        self.active.presets.get(name)
+    }
+
+    /// Full argument list of preset `name`: the arguments of the presets it
+    /// extends (recursively, in order), then its own.
+    pub fn preset_args(&self, name: &str) -> Result<Vec<String>, ConfigError> {
+        self.collect_preset(name, &mut Vec::new())
+    }
+
+    fn collect_preset(
+        &self,
+        name: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<Vec<String>, ConfigError> {
+        let preset = self
+            .preset(name)
+            .ok_or_else(|| ConfigError::Invalid(format!("Preset '{name}' not found")))?;
+        if stack.iter().any(|p| p == name) {
+            return Err(ConfigError::Invalid(format!(
+                "Preset '{name}' extends itself"
+            )));
+        }
+        stack.push(name.to_string());
+        let mut args = Vec::new();
+        for base in &preset.extends {
+            args.extend(self.collect_preset(base, stack)?);
+        }
+        stack.pop();
+        args.extend(preset.args.iter().cloned());
+        Ok(args)
+    }
+
+    pub fn groups(&self) -> &HashMap<String, Vec<String>> {
+        &self.active.groups
+    }
+
+    /// Replace each `@name` in `targets` with the servers of group `name`.
+    ///
+    /// Groups may list other groups; duplicates are dropped, keeping the
+    /// first occurrence.
+    pub fn expand_groups(&self, targets: Vec<String>) -> Result<Vec<String>, ConfigError> {
+        let mut out = Vec::new();
+        for target in targets {
+            self.expand_into(target, &mut Vec::new(), &mut out)?;
+        }
+        Ok(out)
+    }
+
+    fn expand_into(
+        &self,
+        target: String,
+        stack: &mut Vec<String>,
+        out: &mut Vec<String>,
+    ) -> Result<(), ConfigError> {
+        let Some(name) = target.strip_prefix('@') else {
+            if !out.contains(&target) {
+                out.push(target);
+            }
+            return Ok(());
+        };
+        let servers = self
+            .active
+            .groups
+            .get(name)
+            .ok_or_else(|| ConfigError::Invalid(format!("unknown group '{target}'")))?;
+        if stack.iter().any(|g| g == name) {
+            return Err(ConfigError::Invalid(format!(
+                "group '{target}' includes itself"
+            )));
+        }
+        stack.push(name.to_string());
+        for server in servers {
+            self.expand_into(server.clone(), stack, out)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    /// Overrides configured for `target` (host names match case-insensitively).
+    pub fn target_override(&self, target: &str) -> Option<&TargetOverride> {
+        self.active.targets.get(target).or_else(|| {
+            self.active
+                .targets
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(target))
+                .map(|(_, o)| o)
+        })
     }
 
     pub fn empty() -> Self {
         Self {
             path: default_path(),
             data: ConfigData::default(),
+            profile: None,
+            active: ConfigData::default(),
+        }
+    }
+}
+
+/// Names of the `{name}` placeholders in `arg`, in order.
+pub fn placeholders(arg: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        let name = &after[..end];
+        if !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            names.push(name);
+            rest = &after[end + 1..];
+        } else {
+            rest = after;
         }
     }
+    names
+}
+
+/// Replace the `{name}` placeholders of `args` with `values`.
+///
+/// Every placeholder needs a value, and every value must be used, so a
+/// typo in `--set` is reported instead of ignored.
+pub fn fill_placeholders(
+    args: &[String],
+    values: &[(String, String)],
+) -> Result<Vec<String>, ConfigError> {
+    let mut missing: Vec<&str> = Vec::new();
+    let mut used = vec![false; values.len()];
+    let filled = args
+        .iter()
+        .map(|arg| {
+            let mut out = arg.clone();
+            for name in placeholders(arg) {
+                match values.iter().position(|(key, _)| key == name) {
+                    Some(i) => {
+                        used[i] = true;
+                        out = out.replace(&format!("{{{name}}}"), &values[i].1);
+                    }
+                    None if !missing.contains(&name) => missing.push(name),
+                    None => {}
+                }
+            }
+            out
+        })
+        .collect();
+    if !missing.is_empty() {
+        let sets: Vec<String> = missing.iter().map(|m| format!("--set {m}=...")).collect();
+        return Err(ConfigError::Invalid(format!(
+            "missing value for {}",
+            sets.join(", ")
+        )));
+    }
+    if let Some(i) = used.iter().position(|u| !u) {
+        return Err(ConfigError::Invalid(format!(
+            "'{}' is not a placeholder of this preset",
+            values[i].0
+        )));
+    }
+    Ok(filled)
 }
 
 pub fn default_path() -> PathBuf {
     resolve_config_dir().join("config.toml")
 }
 
+/// Check the config file at `path` for everything [`ConfigStore::load`]
+/// would reject or silently skip: wrong types, unknown keys, unresolvable
+/// groups and invalid target options.
+///
+/// `check_format` and `check_preset` validate `defaults.format` and preset
+/// arguments, which only the CLI knows how to parse. Returns one message
+/// per problem; I/O and TOML syntax errors are returned as errors.
+pub fn validate(
+    path: &Path,
+    check_format: impl Fn(&str) -> Result<(), String>,
+    check_preset: impl Fn(&[String]) -> Result<(), String>,
+) -> Result<Vec<String>, ConfigError> {
+    let root: Value = fs::read_to_string(path)?.parse()?;
+    let mut problems = Vec::new();
+    let Some(sections) = root.as_table() else {
+        return Ok(problems);
+    };
+    validate_layer(sections, None, &check_format, &check_preset, &mut problems);
+    if let Some(profiles) = sections.get("profiles").and_then(Value::as_table) {
+        // Profile groups and presets may build on the top-level ones.
+        let mut base_sections = sections.clone();
+        base_sections.remove("profiles");
+        let base = parse_value(Value::Table(base_sections)).unwrap_or_default();
+        for (name, profile) in profiles {
+            let Some(profile) = profile.as_table() else {
+                problems.push(format!(
+                    "profiles.{name}: expected a table, found {}",
+                    profile.type_str()
+                ));
+                continue;
+            };
+            let mut found = Vec::new();
+            validate_layer(
+                profile,
+                Some(&base),
+                &check_format,
+                &check_preset,
+                &mut found,
+            );
+            problems.extend(found.into_iter().map(|p| format!("profiles.{name}.{p}")));
+        }
+    }
+    Ok(problems)
+}
+
+/// Problems of one layer of the file: the top level (`base` is `None`) or
+/// a profile, whose groups and presets resolve against `base` as well.
+fn validate_layer(
+    sections: &toml::map::Map<String, Value>,
+    base: Option<&ConfigData>,
+    check_format: &impl Fn(&str) -> Result<(), String>,
+    check_preset: &impl Fn(&[String]) -> Result<(), String>,
+    problems: &mut Vec<String>,
+) {
+    let layer_store = || {
+        let data = base.cloned().unwrap_or_default();
+        ConfigStore {
+            active: data.clone(),
+            data,
+            ..ConfigStore::empty()
+        }
+    };
+    for (section, value) in sections {
+        let Some(entries) = value.as_table() else {
+            problems.push(format!(
+                "{section}: expected a table, found {}",
+                value.type_str()
+            ));
+            continue;
+        };
+        match section.as_str() {
+            "defaults" => {
+                for (key, value) in entries {
+                    let problem = match key.as_str() {
+                        "timeout" => value
+                            .as_float()
+                            .or_else(|| value.as_integer().map(|i| i as f64))
+                            .filter(|t| t.is_finite() && *t > 0.0)
+                            .is_none()
+                            .then(|| "expected a positive number of seconds".to_string()),
+                        "format" => match value.as_str() {
+                            Some(format) => check_format(format).err(),
+                            None => Some(format!("expected a string, found {}", value.type_str())),
+                        },
+                        "ipv6_only" => (!value.is_bool())
+                            .then(|| format!("expected true or false, found {}", value.type_str())),
+                        "default_targets" => match default_targets(value) {
+                            Some(Some(targets)) if targets.is_empty() => {
+                                Some("expected at least one target".to_string())
+                            }
+                            Some(_) => None,
+                            None => Some(format!(
+                                "expected true, false or a list of targets, found {}",
+                                value.type_str()
+                            )),
+                        },
+                        _ => Some("unknown key".to_string()),
+                    };
+                    if let Some(problem) = problem {
+                        problems.push(format!("defaults.{key}: {problem}"));
+                    }
+                }
+            }
+            "presets" => {
+                let mut store = layer_store();
+                for (name, preset) in entries {
+                    let strings = |key: &str| match preset.get(key) {
+                        None => Some(Vec::new()),
+                        Some(Value::String(s)) if key == "extends" => Some(vec![s.clone()]),
+                        Some(Value::Array(items)) => items
+                            .iter()
+                            .map(|a| a.as_str().map(str::to_string))
+                            .collect::<Option<Vec<_>>>(),
+                        Some(_) => None,
+                    };
+                    match (strings("args"), strings("extends")) {
+                        (Some(args), Some(extends)) if !args.is_empty() || !extends.is_empty() => {
+                            store.add_preset(name.clone(), args, extends);
+                        }
+                        (_, None) => problems.push(format!(
+                            "presets.{name}.extends: expected a preset name or a list of them"
+                        )),
+                        _ => problems.push(format!(
+                            "presets.{name}: expected args = [\"...\"] with at least one argument"
+                        )),
+                    }
+                    if let Some(extra) = preset
+                        .as_table()
+                        .and_then(|t| t.keys().find(|k| !matches!(k.as_str(), "args" | "extends")))
+                    {
+                        problems.push(format!("presets.{name}.{extra}: unknown key"));
+                    }
+                }
+                let mut names: Vec<&String> = entries.keys().collect();
+                names.sort();
+                for name in names.into_iter().filter(|n| store.preset(n).is_some()) {
+                    match store.preset_args(name) {
+                        // Placeholders are only known at `preset run --set` time.
+                        Ok(args) if args.iter().any(|a| !placeholders(a).is_empty()) => {}
+                        Ok(args) => {
+                            if let Err(e) = check_preset(&args) {
+                                problems.push(format!("presets.{name}: {e}"));
+                            }
+                        }
+                        Err(e) => problems.push(format!("presets.{name}: {e}")),
+                    }
+                }
+            }
+            "groups" => {
+                let mut store = layer_store();
+                for (name, servers) in entries {
+                    let mut only = toml::map::Map::new();
+                    only.insert("groups".into(), {
+                        let mut group = toml::map::Map::new();
+                        group.insert(name.clone(), servers.clone());
+                        Value::Table(group)
+                    });
+                    match parse_value(Value::Table(only)) {
+                        Ok(data) => store.data.groups.extend(data.groups),
+                        Err(e) => problems.push(e.to_string()),
+                    }
+                }
+                store.refresh();
+                let mut names: Vec<&String> = entries.keys().collect();
+                names.sort();
+                for name in names
+                    .into_iter()
+                    .filter(|n| store.groups().contains_key(*n))
+                {
+                    if let Err(e) = store.expand_groups(vec![format!("@{name}")]) {
+                        problems.push(format!("groups.{name}: {e}"));
+                    }
+                }
+            }
+            "targets" => {
+                for (name, entry) in entries {
+                    match entry.as_table() {
+                        Some(table) => {
+                            if let Err(e) = parse_target_override(name, table) {
+                                problems.push(e.to_string());
+                            }
+                        }
+                        None => problems.push(format!("targets.\"{name}\" must be a table")),
+                    }
+                }
+            }
+            "thresholds" => {
+                for (key, value) in entries {
+                    let problem = match key.as_str() {
+                        "warning" | "critical" | "warning_rtt" | "critical_rtt" => {
+                            threshold_value(key, value).err().map(|e| e.to_string())
+                        }
+                        _ => Some(format!("thresholds.{key}: unknown key")),
+                    };
+                    problems.extend(problem);
+                }
+            }
+            // Checked by `validate`.
+            "profiles" if base.is_none() => {}
+            "profiles" => problems.push("profiles: profiles cannot be nested".to_string()),
+            other => problems.push(format!("{other}: unknown section")),
+        }
+    }
+}
+
 fn parse_value(root: Value) -> Result<ConfigData, ConfigError> {
     let mut data = ConfigData::default();
     if let Some(defaults) = root.get("defaults").and_then(|val| val.as_table()) {
@@ -174,29 +675,246 @@ fn parse_value(root: Value) -> Result<ConfigData, ConfigError> {
         if let Some(ipv6) = defaults.get("ipv6_only").and_then(Value::as_bool) {
             data.defaults.ipv6_only = Some(ipv6);
         }
+        if let Some(targets) = defaults.get("default_targets").and_then(default_targets) {
+            data.defaults.default_targets = targets.filter(|t| !t.is_empty());
+        }
     }
     if let Some(presets) = root.get("presets").and_then(|val| val.as_table()) {
         for (name, entry) in presets {
-            if let Some(table) = entry.as_table() {
-                if let Some(args) = table.get("args").and_then(Value::as_array) {
-                    let parsed_args: Vec<String> = args
-                        .iter()
-                        .filter_map(Value::as_str)
-                        .map(|s| s.to_string())
-                        .collect();
-                    if !parsed_args.is_empty() {
-                        data.presets
-                            .insert(name.to_string(), PresetRecord { args: parsed_args });
-                    }
-                }
+            let Some(table) = entry.as_table() else {
+                continue;
+            };
+            let parsed_args = string_list(table.get("args"));
+            let extends = string_list(table.get("extends"));
+            if !parsed_args.is_empty() || !extends.is_empty() {
+                data.presets.insert(
+                    name.to_string(),
+                    PresetRecord {
+                        args: parsed_args,
+                        extends,
+                    },
+                );
             }
         }
     }
+    if let Some(groups) = root.get("groups").and_then(|val| val.as_table()) {
+        for (name, entry) in groups {
+            let servers = entry
+                .as_array()
+                .and_then(|list| {
+                    list.iter()
+                        .map(|v| v.as_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| {
+                    ConfigError::Invalid(format!("groups.{name} must be a list of servers"))
+                })?;
+            data.groups.insert(name.to_string(), servers);
+        }
+    }
+    if let Some(targets) = root.get("targets").and_then(|val| val.as_table()) {
+        for (name, entry) in targets {
+            let table = entry.as_table().ok_or_else(|| {
+                ConfigError::Invalid(format!("targets.\"{name}\" must be a table"))
+            })?;
+            data.targets
+                .insert(name.to_string(), parse_target_override(name, table)?);
+        }
+    }
+    if let Some(thresholds) = root.get("thresholds").and_then(|val| val.as_table()) {
+        let t = &mut data.thresholds;
+        for (key, slot) in [
+            ("warning", &mut t.warning),
+            ("critical", &mut t.critical),
+            ("warning_rtt", &mut t.warning_rtt),
+            ("critical_rtt", &mut t.critical_rtt),
+        ] {
+            if let Some(value) = thresholds.get(key) {
+                *slot = Some(threshold_value(key, value)?);
+            }
+        }
+    }
+    if let Some(profiles) = root.get("profiles").and_then(|val| val.as_table()) {
+        for (name, entry) in profiles {
+            if entry.get("profiles").is_some() {
+                return Err(ConfigError::Invalid(format!(
+                    "profiles.{name}: profiles cannot be nested"
+                )));
+            }
+            let profile = parse_value(entry.clone())
+                .map_err(|e| ConfigError::Invalid(format!("profiles.{name}.{e}")))?;
+            data.profiles.insert(name.to_string(), profile);
+        }
+    }
     Ok(data)
 }
 
+/// `thresholds.<key>`: a number of milliseconds or a Nagios range string.
+fn threshold_value(key: &str, value: &Value) -> Result<Threshold, ConfigError> {
+    let raw = match value {
+        Value::String(s) => s.clone(),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        other => {
+            return Err(ConfigError::Invalid(format!(
+                "thresholds.{key}: expected milliseconds or a Nagios range, found {}",
+                other.type_str()
+            )));
+        }
+    };
+    raw.parse()
+        .map_err(|e| ConfigError::Invalid(format!("thresholds.{key}: {e}")))
+}
+
+/// Strings of a TOML string or array of strings; anything else is skipped.
+/// `default_targets`: `true` is pool.ntp.org, `false` is unset, otherwise
+/// a target or list of targets. `None` if the value has another type.
+fn default_targets(value: &Value) -> Option<Option<Vec<String>>> {
+    match value {
+        Value::Boolean(true) => Some(Some(vec![DEFAULT_POOL.to_string()])),
+        Value::Boolean(false) => Some(None),
+        Value::String(_) | Value::Array(_) => {
+            if let Value::Array(items) = value
+                && !items.iter().all(Value::is_str)
+            {
+                return None;
+            }
+            Some(Some(string_list(Some(value))))
+        }
+        _ => None,
+    }
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_target_override(
+    name: &str,
+    table: &toml::map::Map<String, Value>,
+) -> Result<TargetOverride, ConfigError> {
+    let invalid = |msg: &str| ConfigError::Invalid(format!("targets.\"{name}\": {msg}"));
+    let mut o = TargetOverride::default();
+    for (key, value) in table {
+        match key.as_str() {
+            "timeout" => {
+                let timeout = value
+                    .as_float()
+                    .or_else(|| value.as_integer().map(|i| i as f64))
+                    .filter(|t| t.is_finite() && *t > 0.0)
+                    .ok_or_else(|| invalid("timeout must be a positive number of seconds"))?;
+                o.timeout = Some(timeout);
+            }
+            "nts" => {
+                o.nts = Some(
+                    value
+                        .as_bool()
+                        .ok_or_else(|| invalid("nts must be true or false"))?,
+                );
+            }
+            "protocol" => {
+                let protocol = value
+                    .as_str()
+                    .map(str::to_ascii_lowercase)
+                    .filter(|p| matches!(p.as_str(), "ntp" | "nts" | "ptp"))
+                    .ok_or_else(|| invalid("protocol must be \"ntp\", \"nts\" or \"ptp\""))?;
+                o.protocol = Some(protocol);
+            }
+            "domain" => {
+                let domain = value
+                    .as_integer()
+                    .and_then(|d| u8::try_from(d).ok())
+                    .ok_or_else(|| invalid("domain must be a PTP domain number (0-255)"))?;
+                o.domain = Some(domain);
+            }
+            other => return Err(invalid(&format!("unknown option '{other}'"))),
+        }
+    }
+    if o.domain.is_some() && o.protocol.as_deref() != Some("ptp") {
+        return Err(invalid("domain requires protocol = \"ptp\""));
+    }
+    match (o.protocol.as_deref(), o.nts) {
+        (Some("nts"), Some(false)) | (Some("ntp" | "ptp"), Some(true)) => {
+            return Err(invalid("nts contradicts protocol"));
+        }
+        _ => {}
+    }
+    Ok(o)
+}
+
+/// Sections of `data`, with profiles as `[profiles.<name>]` tables.
+fn data_to_toml(data: &ConfigData) -> toml::map::Map<String, Value> {
+    let mut table = toml::map::Map::new();
+    if let Some(defaults_table) = defaults_to_toml(&data.defaults) {
+        table.insert("defaults".into(), Value::Table(defaults_table));
+    }
+    if !data.presets.is_empty() {
+        let mut presets = toml::map::Map::new();
+        for (name, preset) in &data.presets {
+            let value = Value::Array(
+                preset
+                    .args
+                    .iter()
+                    .map(|s| Value::String(s.clone()))
+                    .collect(),
+            );
+            let mut preset_table = toml::map::Map::new();
+            if !preset.extends.is_empty() {
+                let extends = preset.extends.iter().cloned().map(Value::String);
+                preset_table.insert("extends".into(), Value::Array(extends.collect()));
+            }
+            preset_table.insert("args".into(), value);
+            presets.insert(name.clone(), Value::Table(preset_table));
+        }
+        table.insert("presets".into(), Value::Table(presets));
+    }
+    if !data.groups.is_empty() {
+        let groups = data
+            .groups
+            .iter()
+            .map(|(name, servers)| {
+                let servers = servers.iter().map(|s| Value::String(s.clone())).collect();
+                (name.clone(), Value::Array(servers))
+            })
+            .collect();
+        table.insert("groups".into(), Value::Table(groups));
+    }
+    if !data.targets.is_empty() {
+        let targets = data
+            .targets
+            .iter()
+            .map(|(name, o)| (name.clone(), Value::Table(target_override_to_toml(o))))
+            .collect();
+        table.insert("targets".into(), Value::Table(targets));
+    }
+    if let Some(thresholds) = thresholds_to_toml(&data.thresholds) {
+        table.insert("thresholds".into(), Value::Table(thresholds));
+    }
+    if !data.profiles.is_empty() {
+        let profiles = data
+            .profiles
+            .iter()
+            .map(|(name, profile)| (name.clone(), Value::Table(data_to_toml(profile))))
+            .collect();
+        table.insert("profiles".into(), Value::Table(profiles));
+    }
+    table
+}
+
 fn defaults_to_toml(defaults: &Defaults) -> Option<toml::map::Map<String, Value>> {
-    if defaults.timeout.is_none() && defaults.format.is_none() && defaults.ipv6_only.is_none() {
+    if defaults.timeout.is_none()
+        && defaults.format.is_none()
+        && defaults.ipv6_only.is_none()
+        && defaults.default_targets.is_none()
+    {
         return None;
     }
     let mut table = toml::map::Map::new();
@@ -209,9 +927,48 @@ fn defaults_to_toml(defaults: &Defaults) -> Option<toml::map::Map<String, Value>
     if let Some(ipv6) = defaults.ipv6_only {
         table.insert("ipv6_only".into(), Value::Boolean(ipv6));
     }
+    if let Some(targets) = &defaults.default_targets {
+        table.insert(
+            "default_targets".into(),
+            Value::Array(targets.iter().cloned().map(Value::String).collect()),
+        );
+    }
     Some(table)
 }
 
+fn thresholds_to_toml(t: &Thresholds) -> Option<toml::map::Map<String, Value>> {
+    let table: toml::map::Map<String, Value> = [
+        ("warning", t.warning),
+        ("critical", t.critical),
+        ("warning_rtt", t.warning_rtt),
+        ("critical_rtt", t.critical_rtt),
+    ]
+    .into_iter()
+    .filter_map(|(key, threshold)| {
+        let value = threshold?.perfdata(TimeUnit::Ms);
+        Some((key.to_string(), Value::String(value)))
+    })
+    .collect();
+    (!table.is_empty()).then_some(table)
+}
+
+fn target_override_to_toml(o: &TargetOverride) -> toml::map::Map<String, Value> {
+    let mut table = toml::map::Map::new();
+    if let Some(timeout) = o.timeout {
+        table.insert("timeout".into(), Value::Float(timeout));
+    }
+    if let Some(nts) = o.nts {
+        table.insert("nts".into(), Value::Boolean(nts));
+    }
+    if let Some(protocol) = &o.protocol {
+        table.insert("protocol".into(), Value::String(protocol.clone()));
+    }
+    if let Some(domain) = o.domain {
+        table.insert("domain".into(), Value::Integer(domain.into()));
+    }
+    table
+}
+
 fn resolve_config_dir() -> PathBuf {
     if let Some(val) = env::var_os("RKIK_CONFIG_DIR") {
         let path = PathBuf::from(val);
@@ -227,3 +984,176 @@ fn resolve_config_dir() -> PathBuf {
     }
     PathBuf::from(".rkik")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(config: &str) -> ConfigStore {
+        let data = parse_value(config.parse().unwrap()).unwrap();
+        ConfigStore {
+            path: PathBuf::from("config.toml"),
+            active: data.clone(),
+            data,
+            profile: None,
+        }
+    }
+
+    #[test]
+    fn groups_expand_recursively() {
+        let config = store(
+            "[groups]\nprod = [\"ntp1.corp\", \"ntp2.corp\"]\nall = [\"@prod\", \"ntp2.corp\", \"lab.corp\"]\nloop = [\"@loop\"]\n",
+        );
+        let targets = vec!["@all".to_string(), "time.example".to_string()];
+        assert_eq!(
+            config.expand_groups(targets).unwrap(),
+            ["ntp1.corp", "ntp2.corp", "lab.corp", "time.example"]
+        );
+        assert!(config.expand_groups(vec!["@loop".into()]).is_err());
+        assert!(config.expand_groups(vec!["@missing".into()]).is_err());
+    }
+
+    #[test]
+    fn target_overrides_add_a_scheme() {
+        let config = store(
+            "[targets.\"gm1.lab\"]\nprotocol = \"ptp\"\ndomain = 24\ntimeout = 2\n\
+             [targets.\"ntp1.corp\"]\nnts = false\n",
+        );
+        let gm = config.target_override("GM1.lab").unwrap();
+        assert_eq!(gm.timeout, Some(2.0));
+        assert_eq!(gm.apply("gm1.lab"), "ptp://gm1.lab?domain=24");
+        let ntp1 = config.target_override("ntp1.corp").unwrap();
+        assert_eq!(ntp1.apply("ntp1.corp"), "ntp://ntp1.corp");
+        assert_eq!(ntp1.apply("nts://ntp1.corp"), "nts://ntp1.corp");
+        assert!(config.target_override("other.corp").is_none());
+
+        for bad in [
+            "[targets.a]\ndomain = 3\n",
+            "[targets.a]\nprotocol = \"ntp\"\nnts = true\n",
+            "[targets.a]\ntimeout = 0\n",
+            "[targets.a]\nport = 1\n",
+        ] {
+            assert!(parse_value(bad.parse().unwrap()).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let dir = std::env::temp_dir().join(format!("rkik-validate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            "[defaults]\ntimeout = \"5\"\nformat = \"xml\"\ncolour = true\n\
+             [presets.nightly]\nargs = [\"ntp\", \"--bogus\"]\n\
+             [groups]\nall = [\"@prod\"]\n\
+             [extra]\nx = 1\n",
+        )
+        .unwrap();
+        let problems = validate(
+            &path,
+            |f| {
+                (f == "text")
+                    .then_some(())
+                    .ok_or(format!("unknown format '{f}'"))
+            },
+            |args| Err(format!("unexpected argument '{}'", args[1])),
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            problems,
+            [
+                "defaults.colour: unknown key",
+                "defaults.format: unknown format 'xml'",
+                "defaults.timeout: expected a positive number of seconds",
+                "extra: unknown section",
+                "groups.all: unknown group '@prod'",
+                "presets.nightly: unexpected argument '--bogus'",
+            ]
+        );
+    }
+
+    #[test]
+    fn presets_extend_others_and_fill_placeholders() {
+        let config = store(
+            "[presets.base]\nargs = [\"ntp\", \"{target}\"]\n\
+             [presets.nightly]\nextends = \"base\"\nargs = [\"--count\", \"{count}\", \"-j\"]\n\
+             [presets.loop]\nextends = [\"loop\"]\n",
+        );
+        let args = config.preset_args("nightly").unwrap();
+        assert_eq!(args, ["ntp", "{target}", "--count", "{count}", "-j"]);
+        let set = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            fill_placeholders(&args, &set(&[("target", "ntp1"), ("count", "5")])).unwrap(),
+            ["ntp", "ntp1", "--count", "5", "-j"]
+        );
+        assert!(fill_placeholders(&args, &set(&[("target", "ntp1")])).is_err());
+        assert!(
+            fill_placeholders(&args, &set(&[("target", "a"), ("count", "1"), ("x", "y")])).is_err()
+        );
+        assert!(config.preset_args("loop").is_err());
+        assert_eq!(placeholders("ntp://{host}:{port}/{ }"), ["host", "port"]);
+    }
+
+    #[test]
+    fn groups_must_be_lists_of_strings() {
+        let parsed = parse_value("[groups]\nprod = \"ntp1.corp\"\n".parse().unwrap());
+        assert!(matches!(parsed, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn default_targets_accept_true_or_a_list() {
+        let pool = store("[defaults]\ndefault_targets = true\n");
+        assert_eq!(
+            pool.defaults().default_targets,
+            Some(vec![DEFAULT_POOL.to_string()])
+        );
+        let listed = store("[defaults]\ndefault_targets = [\"@lab\", \"time.cloudflare.com\"]\n");
+        assert_eq!(
+            listed.defaults().default_targets,
+            Some(vec!["@lab".to_string(), "time.cloudflare.com".to_string()])
+        );
+        assert!(
+            store("[defaults]\ndefault_targets = false\n")
+                .defaults()
+                .default_targets
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn profiles_overlay_the_top_level() {
+        let mut config = store(
+            "[defaults]\ntimeout = 1\nformat = \"text\"\n[thresholds]\nwarning = 50\n\
+             [groups]\nlab = [\"lab1\"]\n\
+             [profiles.prod.defaults]\ntimeout = 3\n[profiles.prod.thresholds]\ncritical = \"~:80\"\n\
+             [profiles.prod.groups]\nlab = [\"prod1\", \"prod2\"]\n",
+        );
+        assert!(config.select_profile("staging", false).is_err());
+        config.select_profile("prod", false).unwrap();
+        assert_eq!(config.defaults().timeout, Some(3.0));
+        assert_eq!(config.defaults().format.as_deref(), Some("text"));
+        assert_eq!(config.thresholds().warning.and_then(|t| t.end), Some(50.0));
+        assert_eq!(config.thresholds().critical.and_then(|t| t.end), Some(80.0));
+        assert_eq!(
+            config.expand_groups(vec!["@lab".into()]).unwrap(),
+            ["prod1", "prod2"]
+        );
+
+        // Updates go to the profile and survive a save.
+        config.update_format(Some("json".into()));
+        let saved = parse_value(Value::Table(data_to_toml(&config.data))).unwrap();
+        assert_eq!(saved.defaults.format.as_deref(), Some("text"));
+        assert_eq!(
+            saved.profiles["prod"].defaults.format.as_deref(),
+            Some("json")
+        );
+        assert_eq!(saved.thresholds, config.data.thresholds);
+    }
+}
@@ -0,0 +1,201 @@
+//! Unix control socket of `rkik daemon` and the `rkik ctl` client.
+//!
+//! A client connects, writes one TOML request (`command = "status"`, plus
+//! `server`, `interval`, … for `add-target`), shuts down its write half and
+//! reads the reply: `ok` or `error` on the first line, then the body.
+
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use toml::Value;
+
+use crate::daemon::{self, Daemon, Table};
+
+/// Largest request accepted on the socket.
+const MAX_REQUEST: u64 = 64 * 1024;
+/// Pause after a failed accept.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Bind the control socket, replacing a stale socket file left by a crashed daemon.
+pub async fn bind(path: &Path) -> Result<UnixListener, String> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(format!(
+                "another rkik daemon is already listening on {}",
+                path.display()
+            ));
+        }
+        std::fs::remove_file(path)
+            .map_err(|e| format!("cannot remove stale socket {}: {}", path.display(), e))?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("cannot create {}: {}", parent.display(), e))?;
+    }
+    // Only the daemon's user may change the target list. The socket is
+    // created inside a private directory, restricted, then moved into
+    // place, so no one can connect while it still has the umask's mode.
+    let fail = |what: &str, e: std::io::Error| format!("cannot {what}: {e}");
+    let staging = path.with_file_name(format!(".rkik-ctl-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .map_err(|e| fail(&format!("create {}", staging.display()), e))?;
+    let staged = staging.join("control.sock");
+    let bound = UnixListener::bind(&staged)
+        .map_err(|e| fail(&format!("bind control socket {}", path.display()), e))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| fail(&format!("restrict {}", path.display()), e))?;
+            std::fs::rename(&staged, path)
+                .map_err(|e| fail(&format!("move control socket to {}", path.display()), e))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_dir_all(&staging);
+    bound
+}
+
+/// Answer control requests until the task is aborted.
+pub async fn serve(listener: UnixListener, daemon: Arc<Daemon>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                // Out of descriptors and the like: give it time to clear
+                // instead of spinning.
+                tracing::warn!(error = %e, "control socket accept failed");
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+        let daemon = daemon.clone();
+        tokio::spawn(async move {
+            let (read, mut write) = stream.into_split();
+            let mut request = String::new();
+            let reply = match read.take(MAX_REQUEST).read_to_string(&mut request).await {
+                Ok(_) => handle(&daemon, &request),
+                Err(e) => Err(e.to_string()),
+            };
+            let response = match reply {
+                Ok(body) => format!("ok\n{body}"),
+                Err(e) => format!("error\n{e}\n"),
+            };
+            let _ = write.write_all(response.as_bytes()).await;
+            let _ = write.shutdown().await;
+        });
+    }
+}
+
+fn handle(daemon: &Arc<Daemon>, request: &str) -> Result<String, String> {
+    let table: Table = request
+        .parse()
+        .map_err(|e: toml::de::Error| format!("malformed request: {e}"))?;
+    let server = table.get("server").and_then(Value::as_str);
+    match table.get("command").and_then(Value::as_str) {
+        Some("status") => Ok(daemon.status()),
+        Some("stats") => daemon.stats(server),
+        Some("add-target") => {
+            let target = daemon::parse_target(&table, &daemon.defaults())?;
+            let name = target.server.clone();
+            daemon.add_target(target)?;
+            tracing::info!(server = %name, "target added");
+            Ok(format!("added {name}\n"))
+        }
+        Some("remove-target") => {
+            let name = server.ok_or("missing `server`")?;
+            daemon.remove_target(name)?;
+            tracing::info!(server = %name, "target removed");
+            Ok(format!("removed {name}\n"))
+        }
+        Some("reload") => {
+            let changes = daemon.reload_logged()?;
+            if changes.is_empty() {
+                Ok("no changes\n".into())
+            } else {
+                Ok(changes.iter().map(|c| format!("{c}\n")).collect())
+            }
+        }
+        Some(other) => Err(format!("unknown command '{other}'")),
+        None => Err("missing `command`".into()),
+    }
+}
+
+/// Send `request` to the daemon at `path` and return the reply body.
+pub async fn request(path: &Path, request: &Table) -> Result<String, String> {
+    let body = toml::to_string(request).map_err(|e| e.to_string())?;
+    let mut stream = UnixStream::connect(path).await.map_err(|e| {
+        format!(
+            "cannot reach rkik daemon at {}: {} (is it running? see --socket)",
+            path.display(),
+            e
+        )
+    })?;
+    stream
+        .write_all(body.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    stream.shutdown().await.map_err(|e| e.to_string())?;
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .await
+        .map_err(|e| e.to_string())?;
+    match reply.split_once('\n') {
+        Some(("ok", body)) => Ok(body.to_string()),
+        Some(("error", msg)) => Err(msg.trim_end().to_string()),
+        _ => Err("invalid reply from daemon".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::DaemonConfig;
+
+    #[tokio::test]
+    async fn add_and_remove_targets_over_the_socket() {
+        let path = std::env::temp_dir().join(format!("rkik-ctl-test-{}.sock", std::process::id()));
+        let cfg = DaemonConfig::parse(
+            "[daemon]\ninterval = 3600\ntimeout = 0.1\n[[target]]\nserver = \"unused\"\n",
+        )
+        .unwrap();
+        let daemon = Daemon::new(&cfg, None);
+        let listener = bind(&path).await.unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let server = tokio::spawn(serve(listener, daemon.clone()));
+
+        let call = |pairs: &[(&str, &str)]| {
+            let req: Table = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+                .collect();
+            let path = path.clone();
+            async move { request(&path, &req).await }
+        };
+        let add = [("command", "add-target"), ("server", "127.0.0.1:9")];
+        assert_eq!(call(&add).await.unwrap(), "added 127.0.0.1:9\n");
+        assert!(
+            call(&[("command", "status")])
+                .await
+                .unwrap()
+                .contains("127.0.0.1:9")
+        );
+        assert!(call(&add).await.unwrap_err().contains("already monitored"));
+        call(&[("command", "remove-target"), ("server", "127.0.0.1:9")])
+            .await
+            .unwrap();
+        let stats = call(&[("command", "stats"), ("server", "127.0.0.1:9")]).await;
+        assert!(stats.unwrap_err().contains("not monitored"));
+        assert!(call(&[("command", "reboot")]).await.is_err());
+        assert!(call(&[("command", "reload")]).await.is_err());
+
+        server.abort();
+        let _ = std::fs::remove_file(&path);
+    }
+}
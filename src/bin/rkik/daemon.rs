@@ -0,0 +1,1085 @@
+use std::fmt::Write as FmtWrite;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use rkik::fmt::prometheus::{self, TargetMetrics};
+use rkik::fmt::units::TimeUnit;
+use rkik::history::History;
+use rkik::services::pacing::Pacer;
+use rkik::{ProbeResult, QueryOptions, RkikError, query};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use toml::Value;
+
+use crate::alerts::{self, Alert, Alerts, Rule};
+#[cfg(unix)]
+use crate::control;
+#[cfg(all(feature = "dbus", target_os = "linux"))]
+use crate::dbus;
+use crate::exporter;
+use crate::logging::{self, LogTarget};
+use crate::plugin;
+use crate::systemd;
+use crate::threshold::Threshold;
+
+/// One monitored server of `rkik daemon`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetConfig {
+    pub server: String,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub ipv6: bool,
+    pub use_nts: bool,
+    pub nts_port: u16,
+    /// Offset thresholds (ms, absolute value of the rolling average).
+    pub warning: Option<Threshold>,
+    pub critical: Option<Threshold>,
+    /// RTT thresholds (ms, rolling average).
+    pub warning_rtt: Option<Threshold>,
+    pub critical_rtt: Option<Threshold>,
+}
+
+/// Settings loaded from the `rkik daemon --config` file.
+///
+/// ```toml
+/// [daemon]
+/// listen = "127.0.0.1:9123"   # optional Prometheus endpoint
+/// interval = 30               # default seconds between probes
+/// timeout = 5
+/// window = 20                 # samples kept for rolling stats
+/// log_target = "journald"     # stderr (default), syslog or journald
+/// control_socket = "/run/rkik/control.sock"
+/// dbus = "system"             # publish org.rkik.Monitor (Linux, feature `dbus`)
+///
+/// [[target]]
+/// server = "time.cloudflare.com"
+/// protocol = "nts"            # ntp (default) or nts
+/// interval = 10
+/// warning = 50                # offset thresholds, same syntax as --warning
+/// critical = 200
+///
+/// [[rule]]
+/// name = "offset"
+/// target = "*.lan"            # glob over target names (default "*")
+/// metric = "offset"           # offset, offset_max, rtt, jitter, stratum, failures
+/// op = ">"                    # >, >=, <, <=
+/// value = 100                 # fire above 100 ms...
+/// clear = 80                  # ...resolve below 80 ms
+/// for = "2m"                  # only once it held this long
+/// action = "webhook"          # log (default), webhook or mqtt
+/// url = "http://alerts.lan:8080/rkik"
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaemonConfig {
+    pub listen: Option<SocketAddr>,
+    /// Unix control socket used by `rkik ctl`.
+    pub control_socket: PathBuf,
+    pub window: usize,
+    pub log_target: LogTarget,
+    /// Publish `org.rkik.Monitor` on this bus.
+    pub dbus: Option<BusKind>,
+    pub defaults: TargetDefaults,
+    pub targets: Vec<TargetConfig>,
+    pub rules: Vec<Rule>,
+}
+
+/// D-Bus bus selected by `daemon.dbus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusKind {
+    System,
+    Session,
+}
+
+impl BusKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BusKind::System => "system",
+            BusKind::Session => "session",
+        }
+    }
+}
+
+/// `[daemon]` values inherited by targets that do not override them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetDefaults {
+    pub interval: f64,
+    pub timeout: f64,
+    pub ipv6: bool,
+}
+
+impl DaemonConfig {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        Self::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let root: Value = content
+            .parse()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+        let empty = toml::map::Map::new();
+        let daemon = match root.get("daemon") {
+            Some(v) => v.as_table().ok_or("[daemon] must be a table")?,
+            None => &empty,
+        };
+
+        let listen = match daemon.get("listen") {
+            Some(v) => Some(
+                v.as_str()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or("daemon.listen must be an address such as \"127.0.0.1:9123\"")?,
+            ),
+            None => None,
+        };
+        let control_socket = match daemon.get("control_socket") {
+            Some(v) => PathBuf::from(
+                v.as_str()
+                    .filter(|s| !s.is_empty())
+                    .ok_or("daemon.control_socket must be a path")?,
+            ),
+            None => default_control_socket(),
+        };
+        let dbus = match daemon.get("dbus").map(|v| v.as_str()) {
+            None => None,
+            Some(Some("system")) => Some(BusKind::System),
+            Some(Some("session")) => Some(BusKind::Session),
+            Some(_) => return Err("daemon.dbus must be \"system\" or \"session\"".into()),
+        };
+        let defaults = TargetDefaults {
+            interval: seconds(daemon, "interval", "daemon.")?.unwrap_or(30.0),
+            timeout: seconds(daemon, "timeout", "daemon.")?.unwrap_or(5.0),
+            ipv6: flag(daemon, "ipv6", "daemon.")?.unwrap_or(false),
+        };
+        let window = match daemon.get("window") {
+            Some(v) => v
+                .as_integer()
+                .filter(|n| *n > 0)
+                .ok_or("daemon.window must be a positive integer")? as usize,
+            None => 20,
+        };
+        let log_target = match daemon.get("log_target") {
+            Some(v) => v
+                .as_str()
+                .and_then(|s| LogTarget::from_str(s, true).ok())
+                .ok_or("daemon.log_target must be one of stderr, syslog, journald")?,
+            None => LogTarget::Stderr,
+        };
+
+        let entries = match root.get("target") {
+            Some(v) => v
+                .as_array()
+                .ok_or("targets are declared as [[target]] tables")?,
+            None => return Err("no [[target]] entries configured".into()),
+        };
+        let mut targets: Vec<TargetConfig> = Vec::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            let t = entry
+                .as_table()
+                .ok_or_else(|| format!("target #{} must be a table", i + 1))?;
+            let target =
+                parse_target(t, &defaults).map_err(|e| format!("target #{}: {}", i + 1, e))?;
+            if targets.iter().any(|o| o.server == target.server) {
+                return Err(format!("target '{}' is configured twice", target.server));
+            }
+            targets.push(target);
+        }
+
+        let rule_entries = match root.get("rule") {
+            Some(v) => v
+                .as_array()
+                .ok_or("rules are declared as [[rule]] tables")?,
+            None => &Vec::new(),
+        };
+        let mut rules: Vec<Rule> = Vec::with_capacity(rule_entries.len());
+        for (i, entry) in rule_entries.iter().enumerate() {
+            let t = entry
+                .as_table()
+                .ok_or_else(|| format!("rule #{} must be a table", i + 1))?;
+            let rule = alerts::parse_rule(t).map_err(|e| format!("rule #{}: {}", i + 1, e))?;
+            if rules.iter().any(|o| o.name == rule.name) {
+                return Err(format!("rule '{}' is configured twice", rule.name));
+            }
+            rules.push(rule);
+        }
+
+        Ok(Self {
+            listen,
+            control_socket,
+            window,
+            log_target,
+            dbus,
+            defaults,
+            targets,
+            rules,
+        })
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/rkik.sock`, or `rkik.sock` in the temp directory.
+pub fn default_control_socket() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(std::env::temp_dir)
+        .join("rkik.sock")
+}
+
+/// Build a target from a `[[target]]` table (also used by `rkik ctl add-target`).
+pub fn parse_target(t: &Table, defaults: &TargetDefaults) -> Result<TargetConfig, String> {
+    let server = t
+        .get("server")
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or("missing `server`")?
+        .to_string();
+    let ctx = format!("target '{}' ", server);
+    let key = |k: &str| format!("{ctx}{k}");
+    let use_nts = match t.get("protocol").map(|v| v.as_str()) {
+        None | Some(Some("ntp")) => false,
+        Some(Some("nts")) => true,
+        _ => return Err(format!("{} must be \"ntp\" or \"nts\"", key("protocol"))),
+    };
+    #[cfg(not(feature = "nts"))]
+    if use_nts {
+        return Err(format!(
+            "{}: NTS support is not compiled in (enable the `nts` feature)",
+            key("protocol")
+        ));
+    }
+    let nts_port = match t.get("nts_port") {
+        Some(v) => v
+            .as_integer()
+            .and_then(|n| u16::try_from(n).ok())
+            .ok_or_else(|| format!("{} must be a port number", key("nts_port")))?,
+        None => 4460,
+    };
+    Ok(TargetConfig {
+        interval: Duration::from_secs_f64(
+            seconds(t, "interval", &ctx)?.unwrap_or(defaults.interval),
+        ),
+        timeout: Duration::from_secs_f64(seconds(t, "timeout", &ctx)?.unwrap_or(defaults.timeout)),
+        ipv6: flag(t, "ipv6", &ctx)?.unwrap_or(defaults.ipv6),
+        use_nts,
+        nts_port,
+        warning: threshold(t, "warning", &ctx)?,
+        critical: threshold(t, "critical", &ctx)?,
+        warning_rtt: threshold(t, "warning_rtt", &ctx)?,
+        critical_rtt: threshold(t, "critical_rtt", &ctx)?,
+        server,
+    })
+}
+
+pub type Table = toml::map::Map<String, Value>;
+
+/// Positive number of seconds, or a duration string such as `"30s"`;
+/// `ctx` prefixes the field name in errors.
+pub fn seconds(table: &Table, field: &str, ctx: &str) -> Result<Option<f64>, String> {
+    match table.get(field) {
+        None => Ok(None),
+        Some(Value::String(s)) => crate::duration::parse_positive_seconds(s)
+            .map(Some)
+            .map_err(|e| format!("{ctx}{field}: {e}")),
+        Some(v) => v
+            .as_float()
+            .or_else(|| v.as_integer().map(|n| n as f64))
+            .filter(|s| s.is_finite() && *s > 0.0)
+            .map(Some)
+            .ok_or_else(|| format!("{ctx}{field} must be a positive number of seconds")),
+    }
+}
+
+fn flag(table: &Table, field: &str, ctx: &str) -> Result<Option<bool>, String> {
+    match table.get(field) {
+        None => Ok(None),
+        Some(v) => v
+            .as_bool()
+            .map(Some)
+            .ok_or_else(|| format!("{ctx}{field} must be true or false")),
+    }
+}
+
+/// Thresholds accept a number or a Nagios range string.
+fn threshold(table: &Table, field: &str, ctx: &str) -> Result<Option<Threshold>, String> {
+    let raw = match table.get(field) {
+        None => return Ok(None),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Integer(n)) => n.to_string(),
+        Some(Value::Float(f)) => f.to_string(),
+        Some(_) => {
+            return Err(format!("{ctx}{field} must be a number or range string"));
+        }
+    };
+    raw.parse()
+        .map(Some)
+        .map_err(|e| format!("{ctx}{field}: {e}"))
+}
+
+fn fmt_opt<T: std::fmt::Display>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_else(|| "none".into())
+}
+
+/// Human-readable list of the settings that differ between two versions of a target.
+fn target_diff(old: &TargetConfig, new: &TargetConfig) -> Vec<String> {
+    let secs = |d: Duration| format!("{}s", d.as_secs_f64());
+    let threshold = |t: Option<Threshold>| fmt_opt(t.map(|t| t.perfdata(TimeUnit::Ms)));
+    let protocol = |nts: bool| if nts { "nts" } else { "ntp" }.to_string();
+    let fields = [
+        ("interval", secs(old.interval), secs(new.interval)),
+        ("timeout", secs(old.timeout), secs(new.timeout)),
+        ("ipv6", old.ipv6.to_string(), new.ipv6.to_string()),
+        ("protocol", protocol(old.use_nts), protocol(new.use_nts)),
+        (
+            "nts_port",
+            old.nts_port.to_string(),
+            new.nts_port.to_string(),
+        ),
+        ("warning", threshold(old.warning), threshold(new.warning)),
+        ("critical", threshold(old.critical), threshold(new.critical)),
+        (
+            "warning_rtt",
+            threshold(old.warning_rtt),
+            threshold(new.warning_rtt),
+        ),
+        (
+            "critical_rtt",
+            threshold(old.critical_rtt),
+            threshold(new.critical_rtt),
+        ),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, a, b)| a != b)
+        .map(|(key, a, b)| format!("{key} {a} -> {b}"))
+        .collect()
+}
+
+/// Live state of one target: counters and check state. Its rolling window
+/// is kept in [`Daemon::history`].
+struct TargetState {
+    cfg: TargetConfig,
+    metrics: TargetMetrics,
+    /// Nagios-style state (0 OK, 1 WARNING, 2 CRITICAL, 3 UNKNOWN).
+    state: i32,
+    /// Probes failed since the last answer.
+    failures: u32,
+    /// Listed in the configuration file, rather than added over the control
+    /// socket: only these follow the file on [`Daemon::reload`].
+    from_file: bool,
+    task: Option<JoinHandle<()>>,
+}
+
+impl TargetState {
+    /// Record a probe outcome; returns whether the check state changed.
+    fn record(&mut self, res: Result<ProbeResult, RkikError>, history: &History) -> bool {
+        let cfg = &self.cfg;
+        let state = match res {
+            Ok(probe) => {
+                logging::probe_ok(&probe);
+                self.failures = 0;
+                history.push(&cfg.server, probe.clone());
+                self.metrics.record_success(probe);
+                history.stats(&cfg.server).map_or(0, |stats| {
+                    plugin::level(stats.offset_avg.abs(), cfg.warning, cfg.critical).max(
+                        plugin::level(stats.rtt_avg, cfg.warning_rtt, cfg.critical_rtt),
+                    )
+                })
+            }
+            Err(e) => {
+                logging::probe_failed(&cfg.server, &e);
+                self.failures += 1;
+                self.metrics.record_failure(e.kind());
+                3
+            }
+        };
+        if state == self.state {
+            return false;
+        }
+        let (from, to) = (plugin::state(self.state), plugin::state(state));
+        if state == 0 {
+            tracing::info!(server = %cfg.server, from, to, "check state changed");
+        } else {
+            tracing::warn!(server = %cfg.server, from, to, "check state changed");
+        }
+        self.state = state;
+        true
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            server: self.cfg.server.clone(),
+            offset_ms: self.metrics.last.as_ref().map(|p| p.offset_ms),
+            rtt_ms: self.metrics.last.as_ref().map(|p| p.rtt_ms),
+            state: self.state,
+        }
+    }
+}
+
+/// Latest measurement of one target, as exposed to other processes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub server: String,
+    pub offset_ms: Option<f64>,
+    pub rtt_ms: Option<f64>,
+    /// Nagios-style state (0 OK, 1 WARNING, 2 CRITICAL, 3 UNKNOWN).
+    pub state: i32,
+}
+
+/// Running daemon shared by the schedulers, the metrics server and the control socket.
+pub struct Daemon {
+    targets: Mutex<Vec<TargetState>>,
+    /// Rolling window of every target, sized once at startup.
+    history: History,
+    alerts: Mutex<Alerts>,
+    /// Configuration file re-read by [`Daemon::reload`].
+    path: Option<PathBuf>,
+    /// Settings currently applied.
+    config: Mutex<DaemonConfig>,
+}
+
+impl Daemon {
+    /// Empty daemon using the window size and target defaults of `cfg`.
+    pub fn new(cfg: &DaemonConfig, path: Option<PathBuf>) -> Arc<Self> {
+        Arc::new(Self {
+            targets: Mutex::new(Vec::with_capacity(cfg.targets.len())),
+            history: History::new(cfg.window),
+            alerts: Mutex::new(Alerts::new(cfg.rules.clone())),
+            path,
+            config: Mutex::new(cfg.clone()),
+        })
+    }
+
+    /// Defaults applied to targets added at runtime.
+    pub fn defaults(&self) -> TargetDefaults {
+        self.config
+            .lock()
+            .map(|c| c.defaults)
+            .unwrap_or(TargetDefaults {
+                interval: 30.0,
+                timeout: 5.0,
+                ipv6: false,
+            })
+    }
+
+    /// Start probing `cfg` on its own schedule, at runtime: a reload of the
+    /// configuration file leaves it alone.
+    pub fn add_target(self: &Arc<Self>, cfg: TargetConfig) -> Result<(), String> {
+        let mut targets = self.targets.lock().map_err(|e| e.to_string())?;
+        if targets.iter().any(|t| t.cfg.server == cfg.server) {
+            return Err(format!("target '{}' is already monitored", cfg.server));
+        }
+        check_watchdog(cfg.interval + cfg.timeout)?;
+        self.spawn_target(&mut targets, cfg, false);
+        Ok(())
+    }
+
+    /// Start probing a target of the configuration file.
+    fn add_configured(self: &Arc<Self>, cfg: TargetConfig) -> Result<(), String> {
+        let mut targets = self.targets.lock().map_err(|e| e.to_string())?;
+        if targets.iter().any(|t| t.cfg.server == cfg.server) {
+            return Err(format!("target '{}' is already monitored", cfg.server));
+        }
+        self.spawn_target(&mut targets, cfg, true);
+        Ok(())
+    }
+
+    fn spawn_target(
+        self: &Arc<Self>,
+        targets: &mut Vec<TargetState>,
+        cfg: TargetConfig,
+        from_file: bool,
+    ) {
+        let task = tokio::spawn(schedule(cfg.clone(), self.clone()));
+        targets.push(TargetState {
+            metrics: TargetMetrics::new(&cfg.server),
+            state: 0,
+            failures: 0,
+            from_file,
+            task: Some(task),
+            cfg,
+        });
+    }
+
+    /// Re-read the configuration file and apply target, threshold and interval
+    /// changes. Targets that stay keep their counters and rolling window;
+    /// targets added over the control socket are not the file's to remove.
+    ///
+    /// Returns one line per change; the running configuration is left untouched
+    /// when the file cannot be loaded, or when its probe cycles would outlast
+    /// the systemd watchdog.
+    pub fn reload(self: &Arc<Self>) -> Result<Vec<String>, String> {
+        let path = self
+            .path
+            .as_deref()
+            .ok_or("the daemon was not started from a configuration file")?;
+        let new = DaemonConfig::load(path)?;
+        let mut current = self.config.lock().map_err(|e| e.to_string())?;
+        let mut targets = self.targets.lock().map_err(|e| e.to_string())?;
+        let runtime = targets
+            .iter()
+            .filter(|t| !t.from_file && !new.targets.iter().any(|n| n.server == t.cfg.server))
+            .map(|t| &t.cfg);
+        let longest_cycle = new
+            .targets
+            .iter()
+            .chain(runtime)
+            .map(|t| t.interval + t.timeout)
+            .max()
+            .unwrap_or_default();
+        check_watchdog(longest_cycle)?;
+        let mut changes = Vec::new();
+
+        let restart_only = [
+            ("listen", fmt_opt(current.listen), fmt_opt(new.listen)),
+            (
+                "control_socket",
+                current.control_socket.display().to_string(),
+                new.control_socket.display().to_string(),
+            ),
+            ("window", current.window.to_string(), new.window.to_string()),
+            (
+                "log_target",
+                format!("{:?}", current.log_target).to_lowercase(),
+                format!("{:?}", new.log_target).to_lowercase(),
+            ),
+            (
+                "dbus",
+                fmt_opt(current.dbus.map(BusKind::as_str)),
+                fmt_opt(new.dbus.map(BusKind::as_str)),
+            ),
+        ];
+        for (key, old, value) in restart_only {
+            if old != value {
+                changes.push(format!(
+                    "daemon.{key}: {old} -> {value} (ignored until restart)"
+                ));
+            }
+        }
+
+        targets.retain_mut(|t| {
+            if !t.from_file || new.targets.iter().any(|n| n.server == t.cfg.server) {
+                return true;
+            }
+            if let Some(task) = t.task.take() {
+                task.abort();
+            }
+            self.forget(&t.cfg.server);
+            changes.push(format!("removed {}", t.cfg.server));
+            false
+        });
+        for cfg in &new.targets {
+            let Some(state) = targets.iter_mut().find(|t| t.cfg.server == cfg.server) else {
+                self.spawn_target(&mut targets, cfg.clone(), true);
+                changes.push(format!("added {}", cfg.server));
+                continue;
+            };
+            // Listed in the file now, a runtime target follows it from here on.
+            state.from_file = true;
+            let diff = target_diff(&state.cfg, cfg);
+            if diff.is_empty() {
+                continue;
+            }
+            // Thresholds are read on every probe; probe settings need a new scheduler.
+            let old = &state.cfg;
+            if (
+                old.interval,
+                old.timeout,
+                old.ipv6,
+                old.use_nts,
+                old.nts_port,
+            ) != (
+                cfg.interval,
+                cfg.timeout,
+                cfg.ipv6,
+                cfg.use_nts,
+                cfg.nts_port,
+            ) {
+                if let Some(task) = state.task.take() {
+                    task.abort();
+                }
+                state.task = Some(tokio::spawn(schedule(cfg.clone(), self.clone())));
+            }
+            state.cfg = cfg.clone();
+            changes.push(format!("updated {}: {}", cfg.server, diff.join(", ")));
+        }
+
+        let mut rule_changes = Vec::new();
+        for rule in &current.rules {
+            if !new.rules.iter().any(|r| r.name == rule.name) {
+                rule_changes.push(format!("removed rule {}", rule.name));
+            }
+        }
+        for rule in &new.rules {
+            match current.rules.iter().find(|r| r.name == rule.name) {
+                None => rule_changes.push(format!("added rule {}", rule.name)),
+                Some(old) if old != rule => {
+                    rule_changes.push(format!("updated rule {}", rule.name))
+                }
+                Some(_) => {}
+            }
+        }
+        if !rule_changes.is_empty() {
+            self.alerts
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .set_rules(new.rules.clone())
+                .into_iter()
+                .for_each(alerts::dispatch);
+            changes.extend(rule_changes);
+        }
+
+        let window = current.window;
+        *current = new;
+        // The window is sized once at startup.
+        current.window = window;
+        Ok(changes)
+    }
+
+    /// Stop probing `server` and drop its state.
+    pub fn remove_target(&self, server: &str) -> Result<(), String> {
+        let mut targets = self.targets.lock().map_err(|e| e.to_string())?;
+        let idx = targets
+            .iter()
+            .position(|t| t.cfg.server == server)
+            .ok_or_else(|| format!("target '{}' is not monitored", server))?;
+        if let Some(task) = targets.remove(idx).task {
+            task.abort();
+        }
+        self.forget(server);
+        Ok(())
+    }
+
+    /// Drop the window and rule states of a target no longer monitored,
+    /// resolving its firing alerts.
+    fn forget(&self, server: &str) {
+        self.history.remove(server);
+        self.alerts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .forget(server)
+            .into_iter()
+            .for_each(alerts::dispatch);
+    }
+
+    /// Apply the alerting rules to `target` after a probe.
+    fn evaluate_rules(&self, target: &TargetState) -> Vec<Alert> {
+        let server = &target.cfg.server;
+        let stratum = target.metrics.last.as_ref().map(|p| p.stratum);
+        let window = |span: Option<Duration>| alerts::Window {
+            stats: match span {
+                Some(span) => self.history.stats_within(server, span),
+                None => self.history.stats(server),
+            },
+            stratum,
+            failures: target.failures,
+        };
+        self.alerts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .evaluate(server, window, Instant::now())
+    }
+
+    /// [`Daemon::reload`], logging each change or the failure.
+    pub fn reload_logged(self: &Arc<Self>) -> Result<Vec<String>, String> {
+        let result = self.reload();
+        match &result {
+            Ok(changes) => {
+                for change in changes {
+                    tracing::info!(change = %change, "configuration change");
+                }
+                tracing::info!(changes = changes.len(), "configuration reloaded");
+            }
+            Err(e) => tracing::error!(error = %e, "configuration reload failed"),
+        }
+        result
+    }
+
+    /// Latest measurement of every target.
+    #[cfg(all(feature = "dbus", target_os = "linux"))]
+    pub fn snapshot(&self) -> Vec<Snapshot> {
+        self.targets
+            .lock()
+            .map(|t| t.iter().map(TargetState::snapshot).collect())
+            .unwrap_or_default()
+    }
+
+    /// One line per target with its state and last probe.
+    pub fn status(&self) -> String {
+        let Ok(targets) = self.targets.lock() else {
+            return String::new();
+        };
+        let mut out = format!(
+            "{:<30} {:<9} {:>12} {:>10} {:>7} {:>8} {:>8}\n",
+            "TARGET", "STATE", "OFFSET(ms)", "RTT(ms)", "STRATUM", "PROBES", "FAILED"
+        );
+        for t in targets.iter() {
+            let (offset, rtt, stratum) = match &t.metrics.last {
+                Some(p) => (
+                    format!("{:.3}", p.offset_ms),
+                    format!("{:.3}", p.rtt_ms),
+                    p.stratum.to_string(),
+                ),
+                None => ("-".into(), "-".into(), "-".into()),
+            };
+            let _ = writeln!(
+                out,
+                "{:<30} {:<9} {:>12} {:>10} {:>7} {:>8} {:>8}",
+                t.cfg.server,
+                plugin::state(t.state),
+                offset,
+                rtt,
+                stratum,
+                t.metrics.probes_total,
+                t.metrics.failures_total
+            );
+        }
+        out
+    }
+
+    /// Rolling-window statistics for every target, or only `server`.
+    pub fn stats(&self, server: Option<&str>) -> Result<String, String> {
+        let targets = self.targets.lock().map_err(|e| e.to_string())?;
+        if let Some(name) = server
+            && !targets.iter().any(|t| t.cfg.server == name)
+        {
+            return Err(format!("target '{}' is not monitored", name));
+        }
+        let mut out = String::new();
+        for t in targets
+            .iter()
+            .filter(|t| server.is_none_or(|name| t.cfg.server == name))
+        {
+            let _ = writeln!(out, "{}", t.cfg.server);
+            let _ = writeln!(
+                out,
+                "  samples:    {}/{}",
+                self.history.len(&t.cfg.server),
+                self.history.capacity()
+            );
+            let Some(st) = self.history.stats(&t.cfg.server) else {
+                continue;
+            };
+            let _ = writeln!(
+                out,
+                "  offset_ms:  avg {:.3}  min {:.3}  max {:.3}  stddev {:.3}  p95 {:.3}",
+                st.offset_avg, st.offset_min, st.offset_max, st.offset_stddev, st.offset_p95
+            );
+            let _ = writeln!(
+                out,
+                "  rtt_ms:     avg {:.3}  stddev {:.3}  p95 {:.3}",
+                st.rtt_avg, st.rtt_stddev, st.rtt_p95
+            );
+            if let Some(j) = st.jitter {
+                let _ = writeln!(out, "  jitter_ms:  {:.3}", j);
+            }
+            if let (Some(ppm), Some(r2)) = (st.drift_ppm, st.drift_r2) {
+                let _ = writeln!(out, "  drift_ppm:  {:+.3}  r2 {:.3}", ppm, r2);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Prometheus body: the exporter metrics plus rolling-window gauges.
+    fn render(&self) -> String {
+        let Ok(targets) = self.targets.lock() else {
+            return String::new();
+        };
+        render(&targets, &self.history)
+    }
+}
+
+fn render(targets: &[TargetState], history: &History) -> String {
+    let metrics: Vec<TargetMetrics> = targets.iter().map(|t| t.metrics.clone()).collect();
+    let mut out = prometheus::render(&metrics);
+    out.push_str(&prometheus::render_reuse(&rkik::adapters::reuse::stats()));
+
+    out.push_str(&prometheus::render_history(history));
+    let _ = writeln!(
+        out,
+        "# HELP rkik_check_state Threshold state (0 OK, 1 WARNING, 2 CRITICAL, 3 UNKNOWN)."
+    );
+    let _ = writeln!(out, "# TYPE rkik_check_state gauge");
+    for t in targets {
+        let _ = writeln!(
+            out,
+            "rkik_check_state{{target=\"{}\"}} {}",
+            label(&t.metrics.target),
+            t.state
+        );
+    }
+    out
+}
+
+fn label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Probe every target on its own schedule until Ctrl+C or SIGTERM.
+///
+/// SIGHUP re-reads `path` (see [`Daemon::reload`]).
+pub async fn run(cfg: DaemonConfig, path: PathBuf) -> Result<(), String> {
+    #[cfg(not(all(feature = "dbus", target_os = "linux")))]
+    if let Some(bus) = cfg.dbus {
+        return Err(format!(
+            "daemon.dbus = \"{}\" requires a Linux build with the `dbus` feature",
+            bus.as_str()
+        ));
+    }
+    let listener = match cfg.listen {
+        Some(addr) => Some(
+            TcpListener::bind(addr)
+                .await
+                .map_err(|e| format!("cannot listen on {}: {}", addr, e))?,
+        ),
+        None => None,
+    };
+    #[cfg(unix)]
+    let control = control::bind(&cfg.control_socket).await?;
+
+    let daemon = Daemon::new(&cfg, Some(path));
+    // Targets are probed for the life of the daemon: keep their sockets and NTS sessions.
+    let _reuse = rkik::adapters::reuse::keep_open();
+    let longest_cycle = cfg
+        .targets
+        .iter()
+        .map(|t| t.interval + t.timeout)
+        .max()
+        .unwrap_or_default();
+    #[cfg(all(feature = "dbus", target_os = "linux"))]
+    if let Some(bus) = cfg.dbus {
+        dbus::install(bus, daemon.clone()).await?;
+    }
+    for target in cfg.targets {
+        daemon.add_configured(target)?;
+    }
+    tracing::info!(
+        targets = daemon.targets.lock().map(|t| t.len()).unwrap_or(0),
+        listen = %cfg.listen.map(|a| a.to_string()).unwrap_or_default(),
+        control_socket = %cfg.control_socket.display(),
+        "rkik daemon started"
+    );
+
+    #[cfg(unix)]
+    let control_task = tokio::spawn(control::serve(control, daemon.clone()));
+    let watchdog = systemd::spawn_watchdog(longest_cycle);
+    systemd::notify(&format!(
+        "READY=1\nSTATUS=Monitoring {} targets",
+        daemon.targets.lock().map(|t| t.len()).unwrap_or(0)
+    ));
+
+    let mut hangup = systemd::Hangup::new();
+    loop {
+        tokio::select! {
+            Some(stream) = accept(listener.as_ref()) => {
+                let daemon = daemon.clone();
+                tokio::spawn(exporter::serve(stream, move || daemon.render()));
+            }
+            _ = hangup.recv() => {
+                systemd::notify("RELOADING=1");
+                let _ = daemon.reload_logged();
+                systemd::notify("READY=1");
+            }
+            _ = systemd::shutdown() => break,
+        }
+    }
+    systemd::notify("STOPPING=1");
+    if let Some(task) = watchdog {
+        task.abort();
+    }
+
+    #[cfg(unix)]
+    {
+        control_task.abort();
+        let _ = std::fs::remove_file(&cfg.control_socket);
+    }
+    if let Ok(mut targets) = daemon.targets.lock() {
+        for task in targets.iter_mut().filter_map(|t| t.task.take()) {
+            task.abort();
+        }
+    }
+    tracing::info!("rkik daemon stopped");
+    Ok(())
+}
+
+/// Refuse probe cycles as long as `longest_cycle` when they would let the
+/// systemd watchdog restart a working daemon.
+fn check_watchdog(longest_cycle: Duration) -> Result<(), String> {
+    match systemd::cycle_too_long(longest_cycle) {
+        Some(timeout) => Err(format!(
+            "a probe interval plus timeout of {:.1}s needs WatchdogSec above {:.1}s (it is {:.1}s)",
+            longest_cycle.as_secs_f64(),
+            2.0 * longest_cycle.as_secs_f64(),
+            timeout.as_secs_f64()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Next metrics connection; never resolves without a listener.
+async fn accept(listener: Option<&TcpListener>) -> Option<tokio::net::TcpStream> {
+    match listener {
+        Some(l) => l.accept().await.ok().map(|(stream, _)| stream),
+        None => std::future::pending().await,
+    }
+}
+
+/// Probe `target` every interval until it is removed from `daemon`,
+/// backing off when the server sends a RATE Kiss-o'-Death.
+async fn schedule(target: TargetConfig, daemon: Arc<Daemon>) {
+    let mut pacer = Pacer::new(target.interval, Duration::ZERO);
+    let mut ticker = tokio::time::interval(pacer.interval());
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let options = QueryOptions::new()
+        .timeout(target.timeout)
+        .ipv6_only(target.ipv6)
+        .nts(target.use_nts)
+        .nts_port(target.nts_port);
+    loop {
+        ticker.tick().await;
+        let res = query(&target.server, &options).await;
+        if pacer.observe(&res) {
+            let interval = pacer.interval();
+            tracing::warn!(
+                server = %target.server,
+                interval_s = interval.as_secs_f64(),
+                "rate limited by the server, slowing down"
+            );
+            ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        }
+        let Some((changed, snapshot, fired)) =
+            daemon.targets.lock().ok().and_then(|mut targets| {
+                let state = targets.iter_mut().find(|t| t.cfg.server == target.server)?;
+                let changed = state.record(res, &daemon.history);
+                Some((changed, state.snapshot(), daemon.evaluate_rules(state)))
+            })
+        else {
+            return;
+        };
+        fired.into_iter().for_each(alerts::dispatch);
+        systemd::progress();
+        #[cfg(all(feature = "dbus", target_os = "linux"))]
+        dbus::probed(&snapshot, changed).await;
+        #[cfg(not(all(feature = "dbus", target_os = "linux")))]
+        let _ = (changed, snapshot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_daemon_defaults_and_per_target_overrides() {
+        let cfg = DaemonConfig::parse(
+            r#"
+            [daemon]
+            listen = "127.0.0.1:9123"
+            interval = 30
+            window = 5
+
+            [[target]]
+            server = "time.example"
+            interval = 2.5
+            warning = 50
+            critical = "~:200"
+
+            [[target]]
+            server = "other.example"
+            ipv6 = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.listen, Some("127.0.0.1:9123".parse().unwrap()));
+        assert_eq!(cfg.window, 5);
+        assert_eq!(cfg.log_target, LogTarget::Stderr);
+        assert_eq!(cfg.targets[0].interval, Duration::from_millis(2500));
+        assert_eq!(cfg.targets[0].warning.unwrap().simple_bound(), Some(50.0));
+        assert!(!cfg.targets[0].critical.unwrap().simple);
+        assert_eq!(cfg.targets[1].interval, Duration::from_secs(30));
+        assert_eq!(cfg.targets[1].timeout, Duration::from_secs(5));
+        assert!(cfg.targets[1].ipv6);
+        assert_eq!(cfg.control_socket, default_control_socket());
+        assert_eq!(cfg.dbus, None);
+        let bus = DaemonConfig::parse("[daemon]\ndbus = \"session\"\n[[target]]\nserver = \"a\"\n");
+        assert_eq!(bus.unwrap().dbus, Some(BusKind::Session));
+
+        assert!(DaemonConfig::parse("[daemon]\ninterval = 1\n").is_err());
+        let err = DaemonConfig::parse("[[target]]\nserver = \"a\"\nprotocol = \"ptp\"\n");
+        assert!(err.unwrap_err().contains("protocol"));
+
+        let cfg = DaemonConfig::parse(
+            "[daemon]\ninterval = \"1m30s\"\n[[target]]\nserver = \"a\"\ntimeout = \"250ms\"\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.targets[0].interval, Duration::from_secs(90));
+        assert_eq!(cfg.targets[0].timeout, Duration::from_millis(250));
+        let err =
+            DaemonConfig::parse("[daemon]\ninterval = \"soon\"\n[[target]]\nserver = \"a\"\n");
+        assert!(err.unwrap_err().contains("interval"));
+
+        let rule = "[[rule]]\nname = \"rtt\"\nmetric = \"rtt\"\nvalue = 50\n";
+        let cfg = DaemonConfig::parse(&format!("[[target]]\nserver = \"a\"\n{rule}")).unwrap();
+        assert_eq!(cfg.rules[0].metric, alerts::Metric::Rtt);
+        let err = DaemonConfig::parse(&format!("[[target]]\nserver = \"a\"\n{rule}{rule}"));
+        assert!(err.unwrap_err().contains("rule 'rtt' is configured twice"));
+    }
+
+    #[tokio::test]
+    async fn reload_applies_diff_and_keeps_statistics() {
+        let path =
+            std::env::temp_dir().join(format!("rkik-reload-test-{}.toml", std::process::id()));
+        let write = |body: &str| std::fs::write(&path, body).unwrap();
+        write(
+            "[daemon]\ninterval = 3600\ntimeout = 0.1\n\
+             [[target]]\nserver = \"127.0.0.1:9\"\nwarning = 50\n\
+             [[target]]\nserver = \"127.0.0.2:9\"\n",
+        );
+        let cfg = DaemonConfig::load(&path).unwrap();
+        let daemon = Daemon::new(&cfg, Some(path.clone()));
+        for t in cfg.targets {
+            daemon.add_configured(t).unwrap();
+        }
+        daemon.targets.lock().unwrap()[0].metrics.probes_total = 7;
+        let runtime = parse_target(
+            &"server = \"127.0.0.4:9\"".parse().unwrap(),
+            &daemon.defaults(),
+        )
+        .unwrap();
+        daemon.add_target(runtime).unwrap();
+
+        write(
+            "[daemon]\ninterval = 3600\ntimeout = 0.1\nwindow = 5\n\
+             [[target]]\nserver = \"127.0.0.1:9\"\nwarning = 20\n\
+             [[target]]\nserver = \"127.0.0.3:9\"\n\
+             [[rule]]\nname = \"lost\"\nmetric = \"failures\"\nvalue = 3\n",
+        );
+        let changes = daemon.reload().unwrap();
+        assert_eq!(
+            changes,
+            [
+                "daemon.window: 20 -> 5 (ignored until restart)",
+                "removed 127.0.0.2:9",
+                "updated 127.0.0.1:9: warning 50 -> 20",
+                "added 127.0.0.3:9",
+                "added rule lost",
+            ]
+        );
+        let targets = daemon.targets.lock().unwrap();
+        assert_eq!(targets[0].metrics.probes_total, 7);
+        assert_eq!(targets[0].cfg.warning.unwrap().simple_bound(), Some(20.0));
+        // Added over the control socket: not the file's to remove.
+        assert_eq!(targets[1].cfg.server, "127.0.0.4:9");
+        drop(targets);
+
+        write("[daemon\n");
+        assert!(daemon.reload().is_err());
+        assert_eq!(daemon.targets.lock().unwrap().len(), 3);
+        let _ = std::fs::remove_file(&path);
+    }
+}
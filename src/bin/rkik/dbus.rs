@@ -0,0 +1,127 @@
+//! `org.rkik.Monitor` D-Bus interface of `rkik daemon` (Linux, feature `dbus`).
+//!
+//! Object `/org/rkik/Monitor` exposes the last offset/RTT and check state of
+//! every target as properties, emits `PropertiesChanged` after each probe and
+//! `ThresholdBreached` when a target enters WARNING, CRITICAL or UNKNOWN.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use zbus::object_server::SignalEmitter;
+use zbus::{Connection, connection, interface};
+
+use crate::daemon::{BusKind, Daemon, Snapshot};
+use crate::plugin;
+
+pub const NAME: &str = "org.rkik.Monitor";
+pub const PATH: &str = "/org/rkik/Monitor";
+
+static CONNECTION: OnceLock<Connection> = OnceLock::new();
+
+struct Monitor {
+    daemon: Arc<Daemon>,
+}
+
+#[interface(name = "org.rkik.Monitor")]
+impl Monitor {
+    /// Monitored servers, in configuration order.
+    #[zbus(property)]
+    fn targets(&self) -> Vec<String> {
+        self.daemon
+            .snapshot()
+            .into_iter()
+            .map(|s| s.server)
+            .collect()
+    }
+
+    /// Last offset per server (ms); servers without a successful probe are omitted.
+    #[zbus(property)]
+    fn offsets(&self) -> HashMap<String, f64> {
+        self.daemon
+            .snapshot()
+            .into_iter()
+            .filter_map(|s| Some((s.server, s.offset_ms?)))
+            .collect()
+    }
+
+    /// Last round-trip delay per server (ms).
+    #[zbus(property)]
+    fn rtts(&self) -> HashMap<String, f64> {
+        self.daemon
+            .snapshot()
+            .into_iter()
+            .filter_map(|s| Some((s.server, s.rtt_ms?)))
+            .collect()
+    }
+
+    /// Check state per server: OK, WARNING, CRITICAL or UNKNOWN.
+    #[zbus(property)]
+    fn states(&self) -> HashMap<String, String> {
+        self.daemon
+            .snapshot()
+            .into_iter()
+            .map(|s| (s.server, plugin::state(s.state).to_string()))
+            .collect()
+    }
+
+    /// A server entered WARNING, CRITICAL or UNKNOWN (offset/RTT are NaN if never measured).
+    #[zbus(signal)]
+    async fn threshold_breached(
+        emitter: &SignalEmitter<'_>,
+        server: &str,
+        state: &str,
+        offset_ms: f64,
+        rtt_ms: f64,
+    ) -> zbus::Result<()>;
+}
+
+/// Connect to `bus`, claim `org.rkik.Monitor` and serve the interface.
+pub async fn install(bus: BusKind, daemon: Arc<Daemon>) -> Result<(), String> {
+    let builder = match bus {
+        BusKind::System => connection::Builder::system(),
+        BusKind::Session => connection::Builder::session(),
+    };
+    let conn = builder
+        .and_then(|b| b.name(NAME))
+        .and_then(|b| b.serve_at(PATH, Monitor { daemon }))
+        .map_err(|e| format!("D-Bus setup failed: {e}"))?
+        .build()
+        .await
+        .map_err(|e| format!("cannot register {NAME} on the {} bus: {e}", bus.as_str()))?;
+    let _ = CONNECTION.set(conn);
+    Ok(())
+}
+
+/// Publish a new measurement; `changed` is true when the check state changed.
+pub async fn probed(snapshot: &Snapshot, changed: bool) {
+    let Some(conn) = CONNECTION.get() else {
+        return;
+    };
+    let Ok(iface) = conn.object_server().interface::<_, Monitor>(PATH).await else {
+        return;
+    };
+    let emitter = iface.signal_emitter();
+    let result = async {
+        let monitor = iface.get().await;
+        monitor.offsets_changed(emitter).await?;
+        monitor.rtts_changed(emitter).await?;
+        if changed {
+            monitor.states_changed(emitter).await?;
+            if snapshot.state != 0 {
+                Monitor::threshold_breached(
+                    emitter,
+                    &snapshot.server,
+                    plugin::state(snapshot.state),
+                    snapshot.offset_ms.unwrap_or(f64::NAN),
+                    snapshot.rtt_ms.unwrap_or(f64::NAN),
+                )
+                .await?;
+            }
+        }
+        zbus::Result::Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::warn!(error = %e, "D-Bus signal failed");
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+/// Parse a human-friendly duration into fractional seconds.
+///
+/// The grammar is one or more `<number><unit>` terms, added together:
+///
+/// | Unit | Meaning |
+/// |------|---------|
+/// | `us`, `µs` | microseconds |
+/// | `ms` | milliseconds |
+/// | `s` | seconds |
+/// | `m`, `min` | minutes |
+/// | `h` | hours |
+/// | `d` | days |
+///
+/// So `500ms`, `2s`, `1.5m` and `1h30m` are all valid. A bare number such as
+/// `0.5` is read as seconds, which keeps every flag that used to take raw
+/// seconds backward compatible.
+pub fn parse_seconds(s: &str) -> Result<f64, String> {
+    let input = s.trim();
+    if input.is_empty() {
+        return Err("empty duration (e.g. 500ms, 2s, 5m, 1h)".to_string());
+    }
+    if let Ok(v) = input.parse::<f64>() {
+        return check(s, v);
+    }
+
+    let mut total = 0.0;
+    let mut rest = input;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let (num, tail) = rest.split_at(split);
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, next) = tail.split_at(unit_len);
+        let value: f64 = num
+            .parse()
+            .map_err(|_| format!("'{s}' is not a duration (e.g. 500ms, 2s, 5m, 1h)"))?;
+        let scale = match unit {
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" | "min" => 60.0,
+            "h" => 3600.0,
+            "d" => 86_400.0,
+            "" => return Err(format!("missing unit after '{num}' in '{s}'")),
+            _ => {
+                return Err(format!(
+                    "unknown unit '{unit}' in '{s}' (use us, ms, s, m, h or d)"
+                ));
+            }
+        };
+        total += value * scale;
+        rest = next;
+    }
+    check(s, total)
+}
+
+/// Finite, non-negative and small enough for a [`Duration`].
+fn check(s: &str, v: f64) -> Result<f64, String> {
+    // `Duration::MAX` rounds up to 2^64 s, which `from_secs_f64` rejects.
+    if v.is_finite() && v >= 0.0 && v < Duration::MAX.as_secs_f64() {
+        Ok(v)
+    } else {
+        Err(format!(
+            "'{s}' is not a valid duration (e.g. 500ms, 2s, 5m, 1h)"
+        ))
+    }
+}
+
+/// Like [`parse_seconds`] but rejects zero, for budgets that must be positive.
+pub fn parse_positive_seconds(s: &str) -> Result<f64, String> {
+    match parse_seconds(s)? {
+        v if v > 0.0 => Ok(v),
+        _ => Err(format!(
+            "'{s}' is not a positive duration (e.g. 90s, 30m, 1h)"
+        )),
+    }
+}
+
+/// Like [`parse_seconds`] with an optional leading sign, for offsets.
+pub fn parse_signed_seconds(s: &str) -> Result<f64, String> {
+    let t = s.trim();
+    match t.strip_prefix('-') {
+        Some(rest) => parse_seconds(rest).map(|v| -v),
+        None => parse_seconds(t.strip_prefix('+').unwrap_or(t)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_numbers_are_seconds() {
+        assert_eq!(parse_seconds("5").unwrap(), 5.0);
+        assert_eq!(parse_seconds("0.25").unwrap(), 0.25);
+        assert_eq!(parse_seconds("0").unwrap(), 0.0);
+    }
+
+    #[test]
+    fn units_and_compound_terms() {
+        assert_eq!(parse_seconds("500ms").unwrap(), 0.5);
+        assert_eq!(parse_seconds("2s").unwrap(), 2.0);
+        assert_eq!(parse_seconds("5m").unwrap(), 300.0);
+        assert_eq!(parse_seconds("5min").unwrap(), 300.0);
+        assert_eq!(parse_seconds("1h").unwrap(), 3600.0);
+        assert_eq!(parse_seconds("1d").unwrap(), 86_400.0);
+        assert_eq!(parse_seconds("1h30m").unwrap(), 5400.0);
+        assert_eq!(parse_seconds("1m30s").unwrap(), 90.0);
+        assert!((parse_seconds("250us").unwrap() - 0.00025).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for bad in [
+            "",
+            "5x",
+            "ms",
+            "-1",
+            "-1s",
+            "1h30",
+            "1..5s",
+            "inf",
+            "NaN",
+            "1e300",
+            "99999999999999999999d",
+            "18446744073709551616",
+        ] {
+            assert!(parse_seconds(bad).is_err(), "{bad} should be rejected");
+        }
+        assert!(parse_positive_seconds("0s").is_err());
+        assert_eq!(parse_positive_seconds("90s").unwrap(), 90.0);
+        assert_eq!(parse_signed_seconds("-250ms").unwrap(), -0.25);
+        assert_eq!(parse_signed_seconds("+1s").unwrap(), 1.0);
+        assert!(parse_signed_seconds("--1s").is_err());
+        let longest = parse_seconds("100000000000000d").unwrap();
+        assert_eq!(
+            Duration::from_secs_f64(longest).as_secs(),
+            8_640_000_000_000_000_000
+        );
+    }
+}
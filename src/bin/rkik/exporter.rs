@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::join_all;
+use rkik::fmt::prometheus::{self, TargetMetrics};
+use rkik::history::History;
+use rkik::{QueryOptions, query};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::logging;
+use crate::systemd;
+
+/// Settings for `rkik exporter`.
+#[derive(Debug, Clone)]
+pub struct ExporterConfig {
+    pub listen: SocketAddr,
+    pub targets: Vec<String>,
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// Per-target timeouts from the configuration file, overriding `timeout`.
+    pub target_timeouts: HashMap<String, Duration>,
+    pub ipv6: bool,
+    pub use_nts: bool,
+    pub nts_port: u16,
+    /// Probes kept per target for the rolling-window gauges.
+    pub window: usize,
+}
+
+type SharedMetrics = Arc<Mutex<Vec<TargetMetrics>>>;
+
+/// Time a client gets to send its request headers.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Probe the configured targets on an interval and serve `/metrics` until Ctrl+C.
+pub async fn run(cfg: ExporterConfig) -> Result<(), String> {
+    let listener = TcpListener::bind(cfg.listen)
+        .await
+        .map_err(|e| format!("cannot listen on {}: {}", cfg.listen, e))?;
+    eprintln!(
+        "rkik exporter listening on http://{}/metrics ({} targets, every {:.1}s)",
+        cfg.listen,
+        cfg.targets.len(),
+        cfg.interval.as_secs_f64()
+    );
+
+    // Every round probes the same targets: keep their sockets and NTS sessions.
+    let _reuse = rkik::adapters::reuse::keep_open();
+    let metrics: SharedMetrics = Arc::new(Mutex::new(
+        cfg.targets.iter().map(TargetMetrics::new).collect(),
+    ));
+    let history = Arc::new(History::new(cfg.window));
+
+    let prober = tokio::spawn(probe_loop(cfg.clone(), metrics.clone(), history.clone()));
+    let slowest = cfg
+        .target_timeouts
+        .values()
+        .fold(cfg.timeout, |a, b| a.max(*b));
+    let watchdog = systemd::spawn_watchdog(cfg.interval + slowest);
+    systemd::notify(&format!(
+        "READY=1\nSTATUS=Serving metrics for {} targets",
+        cfg.targets.len()
+    ));
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                if let Ok((stream, _)) = accepted {
+                    let metrics = metrics.clone();
+                    let history = history.clone();
+                    tokio::spawn(serve(stream, move || {
+                        let mut body = metrics
+                            .lock()
+                            .map(|m| prometheus::render(&m))
+                            .unwrap_or_default();
+                        body.push_str(&prometheus::render_history(&history));
+                        body.push_str(&prometheus::render_reuse(&rkik::adapters::reuse::stats()));
+                        body
+                    }));
+                }
+            }
+            _ = systemd::shutdown() => break,
+        }
+    }
+    systemd::notify("STOPPING=1");
+    prober.abort();
+    if let Some(task) = watchdog {
+        task.abort();
+    }
+    Ok(())
+}
+
+async fn probe_loop(cfg: ExporterConfig, metrics: SharedMetrics, history: Arc<History>) {
+    let mut ticker = tokio::time::interval(cfg.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let probes = cfg.targets.iter().map(|t| {
+            let options = QueryOptions::new()
+                .timeout(cfg.target_timeouts.get(t).copied().unwrap_or(cfg.timeout))
+                .ipv6_only(cfg.ipv6)
+                .nts(cfg.use_nts)
+                .nts_port(cfg.nts_port);
+            async move { query(t, &options).await }
+        });
+        let results = join_all(probes).await;
+        let Ok(mut guard) = metrics.lock() else {
+            return;
+        };
+        for (slot, res) in guard.iter_mut().zip(results) {
+            match res {
+                Ok(probe) => {
+                    logging::probe_ok(&probe);
+                    history.push(&slot.target, probe.clone());
+                    slot.record_success(probe);
+                }
+                Err(e) => {
+                    logging::probe_failed(&slot.target, &e);
+                    slot.record_failure(e.kind());
+                }
+            }
+        }
+        drop(guard);
+        systemd::progress();
+    }
+}
+
+/// Answer one HTTP request, serving `render()` on `GET /metrics`.
+pub async fn serve<F: Fn() -> String>(mut stream: TcpStream, render: F) {
+    let mut buf = [0u8; 4096];
+    let mut len = 0;
+    // Read until the end of the request headers (or the buffer is full).
+    let read = async {
+        while len < buf.len() {
+            match stream.read(&mut buf[len..]).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    len += n;
+                    if buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+                        break;
+                    }
+                }
+            }
+        }
+    };
+    // A client that never finishes its request is dropped, not waited on.
+    if tokio::time::timeout(REQUEST_TIMEOUT, read).await.is_err() {
+        return;
+    }
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(),
+        ),
+        ("GET", "/") => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            "<html><head><title>rkik exporter</title></head><body>\
+             <h1>rkik exporter</h1><p><a href=\"/metrics\">Metrics</a></p></body></html>\n"
+                .to_string(),
+        ),
+        ("GET", _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,67 @@
+//! `rkik history`: read back the probes accumulated with `--store`.
+
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use rkik::adapters::store::ProbeStore;
+
+use crate::replay::{self, ReplayFormat};
+
+/// Parse `--since`: an age such as `90s`, `30m`, `24h`, `7d` or `2w`, or an
+/// RFC 3339 timestamp.
+pub fn parse_since(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(s) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: i64 = num.parse().map_err(|_| {
+        format!("invalid --since '{s}' (use e.g. 30m, 24h, 7d or an RFC 3339 time)")
+    })?;
+    let age = match unit {
+        "s" => Duration::try_seconds(n),
+        "m" => Duration::try_minutes(n),
+        "h" => Duration::try_hours(n),
+        "d" => Duration::try_days(n),
+        "w" => Duration::try_weeks(n),
+        _ => {
+            return Err(format!(
+                "unknown unit '{unit}' in --since '{s}' (use s, m, h, d or w)"
+            ));
+        }
+    }
+    .ok_or_else(|| format!("--since '{s}' is out of range"))?;
+    Ok(Utc::now() - age)
+}
+
+pub fn run(
+    store: &Path,
+    server: Option<&str>,
+    since: DateTime<Utc>,
+    format: ReplayFormat,
+    verbose: bool,
+    pretty: bool,
+    no_color: bool,
+) -> Result<(), String> {
+    let records = ProbeStore::open(store)
+        .and_then(|db| db.history(server, since))
+        .map_err(|e| format!("{}: {e}", store.display()))?;
+    replay::render(&records, "History", format, verbose, pretty, no_color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_accepts_ages_and_timestamps() {
+        let age = Utc::now() - parse_since("24h").unwrap();
+        assert!((age - Duration::hours(24)).num_seconds().abs() <= 1);
+        assert_eq!(
+            parse_since("2025-06-01T00:00:00Z").unwrap().to_rfc3339(),
+            "2025-06-01T00:00:00+00:00"
+        );
+        assert!(parse_since("24x").is_err());
+        assert!(parse_since("h").is_err());
+    }
+}
@@ -1,17 +1,53 @@
-use clap::{Parser, ValueEnum};
+use clap::{ArgAction, Parser, ValueEnum};
 use console::{Term, set_colors_enabled, style};
+use futures::StreamExt;
 #[cfg(feature = "sync")]
 use rkik::sync::{SyncError, get_sys_permissions, sync_from_probe};
 use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::pin::pin;
 use std::process;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use tokio::signal;
 
 use rkik::{
-    ProbeResult, RkikError, compare_many, fmt, query_one,
-    stats::{Stats, compute_stats},
+    CompareOutcome, HealthVerdict, Leap, ProbeResult, QueryOptions, RkikError,
+    adapters::{dhcp, kernel, ntp_client, resolver, reuse},
+    fmt,
+    fmt::fields::Field,
+    fmt::highlight::{self, Metric, Severity},
+    fmt::units::{TimeUnit, format_ms, set_display_unit},
+    services::clock_check::StepWatch,
+    services::compare::{CHRONYD_TARGET, LOCAL_TARGET, compare_with, rebase_offsets},
+    services::divergence::{DivergenceEvent, DivergenceTracker},
+    services::leap::{LeapTable, check_indicator, check_kernel},
+    services::monitor::{MonitorOptions, probe_stream},
+    services::pacing::{POOL_MIN_INTERVAL, Pacer, is_pool},
+    services::query::with_port,
+    services::recommend::{Candidate, DEFAULT_COUNT},
+    services::smear::{Smear, detect_smear},
+    stats::{Accumulator, DriftMatrix, Stats, compute_stats, filter_by_rtt},
 };
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+
+#[cfg(feature = "enrich")]
+use rkik::services::enrich;
+
+use crate::logging::{self, LogTarget};
+#[cfg(feature = "otel")]
+use crate::otel::{self, Endpoint};
+use crate::output_file::{self, RotatePolicy, RotatingFile};
+use crate::plugin::{self, PluginFormat};
+use crate::progress::Progress;
+use crate::recommend;
+#[cfg(feature = "json")]
+use crate::record;
+#[cfg(feature = "sqlite")]
+use crate::store;
+use crate::threshold::Threshold;
+#[cfg(feature = "json")]
+use crate::zabbix::{self, ZabbixServer};
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum OutputFormat {
@@ -20,6 +56,7 @@ pub enum OutputFormat {
     Simple,
     JsonShort,
     Csv,
+    Markdown,
 }
 
 impl OutputFormat {
@@ -30,6 +67,7 @@ impl OutputFormat {
             OutputFormat::Simple => "simple",
             OutputFormat::JsonShort => "json-short",
             OutputFormat::Csv => "csv",
+            OutputFormat::Markdown => "markdown",
         }
     }
 }
@@ -40,6 +78,32 @@ impl std::fmt::Display for OutputFormat {
     }
 }
 
+/// `--interval`: a fixed number of seconds, or `auto` to adapt it to the
+/// measured jitter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interval {
+    Seconds(f64),
+    Auto,
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interval::Seconds(s) => write!(f, "{}", s),
+            Interval::Auto => f.write_str("auto"),
+        }
+    }
+}
+
+pub fn parse_interval(s: &str) -> Result<Interval, String> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(Interval::Auto);
+    }
+    crate::duration::parse_seconds(s)
+        .map(Interval::Seconds)
+        .map_err(|e| format!("{e}, or 'auto'"))
+}
+
 #[derive(Parser, Debug, Clone)]
 #[command(name = "rkik")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
@@ -53,9 +117,76 @@ pub struct LegacyArgs {
     #[arg(short = 'C', long, num_args = 2..)]
     pub compare: Option<Vec<String>>,
 
-    /// Show detailed output
-    #[arg(short = 'v', long)]
-    pub verbose: bool,
+    /// Probe the target on each of these ports (e.g. 123,1123), compared side by side
+    #[arg(long, value_delimiter = ',', conflicts_with = "compare", value_parser = clap::value_parser!(u16).range(1..), value_name = "PORT,...")]
+    pub ports: Option<Vec<u16>>,
+
+    /// Express compare offsets relative to this server (one of the --compare list)
+    #[arg(long, requires = "compare", value_name = "SERVER")]
+    pub reference: Option<String>,
+
+    /// Add the local clock ("local", offset 0) to the compared servers
+    #[arg(long, requires = "compare")]
+    pub include_local: bool,
+
+    /// Print the matrix of pairwise offset differences between compared servers
+    #[arg(long, requires = "compare")]
+    pub matrix: bool,
+
+    /// Flag matrix pairs whose offsets differ by more than this (ms)
+    #[arg(long, requires = "matrix", value_name = "MS")]
+    pub max_drift: Option<f64>,
+
+    /// Exit with code 4 if a server's average offset exceeds this (ms)
+    #[arg(long, value_name = "MS")]
+    pub assert_max_offset: Option<f64>,
+
+    /// Exit with code 4 if compared servers' average offsets differ by more than this (ms)
+    #[arg(long, requires = "compare", value_name = "MS")]
+    pub assert_max_drift: Option<f64>,
+
+    /// Exit with code 4 if a server or the kernel advertises a leap second
+    /// state that contradicts the IERS leap second table
+    #[arg(long)]
+    pub assert_leap: bool,
+
+    /// IERS leap-seconds.list for --assert-leap and leap smear detection
+    #[arg(long, value_name = "PATH")]
+    pub leap_file: Option<PathBuf>,
+
+    /// Warn about compared servers known to smear leap seconds, not only
+    /// those caught smearing around a leap second
+    #[arg(long, requires = "compare")]
+    pub detect_smear: bool,
+
+    /// Query at most this many compared servers at once
+    #[arg(long, requires = "compare", default_value_t = 32, value_parser = clap::value_parser!(u16).range(1..), value_name = "N")]
+    pub concurrency: u16,
+
+    /// Wait at least this long between two queries of a comparison (ms)
+    #[arg(long, requires = "compare", value_name = "MS")]
+    pub stagger: Option<f64>,
+
+    /// Send every request of a comparison from one UDP socket instead of
+    /// one per server
+    #[arg(long, requires = "compare")]
+    pub shared_socket: bool,
+
+    /// Rank the compared servers by quality and print the best N (default 4)
+    #[arg(long, requires = "compare", value_name = "N", num_args = 0..=1, default_missing_value = "4", value_parser = clap::value_parser!(u16).range(1..))]
+    pub recommend: Option<u16>,
+
+    /// Also print the recommended servers as a chrony.conf or ntp.conf block
+    #[arg(long, requires = "compare", value_enum, value_name = "DIALECT")]
+    pub emit_config: Option<recommend::EmitConfig>,
+
+    /// Report servers drifting more than this far from the group (ms)
+    #[arg(long, requires = "compare", value_name = "MS")]
+    pub diverge_threshold: Option<f64>,
+
+    /// Show detailed output; -vv and -vvv add DNS, socket, packet and NTS diagnostics on stderr
+    #[arg(short = 'v', long, action = ArgAction::Count)]
+    pub verbose: u8,
 
     /// Output format: text or json
     #[arg(short = 'f', long, default_value = "text", value_enum)]
@@ -69,6 +200,14 @@ pub struct LegacyArgs {
     #[arg(short = 'S', long)]
     pub short: bool,
 
+    /// With --count or --infinite, print only the final statistics
+    #[arg(short = 'q', long)]
+    pub quiet: bool,
+
+    /// Print nothing at all; only the exit code tells the outcome
+    #[arg(long, conflicts_with_all = ["quiet", "verbose"])]
+    pub silent: bool,
+
     /// Pretty-print JSON
     #[arg(short = 'p', long)]
     pub pretty: bool,
@@ -77,14 +216,106 @@ pub struct LegacyArgs {
     #[arg(long = "no-color", alias = "nocolor")]
     pub no_color: bool,
 
+    /// Restrict json/csv/simple output to these columns (e.g. offset,rtt,stratum)
+    #[arg(long, value_delimiter = ',', value_name = "FIELDS")]
+    pub fields: Vec<Field>,
+
+    /// Unit for offsets and delays: s, ms, us, ns or auto
+    #[arg(long, default_value = "ms", value_name = "UNIT")]
+    pub unit: TimeUnit,
+
+    /// Write results to this file instead of stdout
+    #[arg(short = 'o', long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Rotate the output file by size (e.g. 10MB) or period (hourly, daily)
+    #[arg(long, requires = "output", value_name = "POLICY")]
+    pub rotate: Option<RotatePolicy>,
+
+    /// Number of rotated output files to keep
+    #[arg(long, requires = "output", default_value_t = 5, value_name = "N")]
+    pub rotate_keep: usize,
+
+    /// Push results to an OTLP/HTTP collector (e.g. http://localhost:4318)
+    #[cfg(feature = "otel")]
+    #[arg(long, value_name = "URL", value_parser = Endpoint::parse)]
+    pub otlp_endpoint: Option<Endpoint>,
+
+    /// Extra OTLP resource attribute (KEY=VALUE, repeatable)
+    #[cfg(feature = "otel")]
+    #[arg(long = "otlp-attribute", requires = "otlp_endpoint", value_name = "KEY=VALUE", value_parser = otel::parse_attribute)]
+    pub otlp_attributes: Vec<(String, String)>,
+
+    /// Push offset/rtt items to a Zabbix server or proxy (host[:port])
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "SERVER", value_parser = ZabbixServer::parse, requires = "zabbix_host")]
+    pub zabbix: Option<ZabbixServer>,
+
+    /// Monitored host name the Zabbix items belong to
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "HOST", requires = "zabbix")]
+    pub zabbix_host: Option<String>,
+
+    /// Emit structured probe logs to stderr, syslog or journald
+    #[arg(long, value_enum, value_name = "TARGET")]
+    pub log_target: Option<LogTarget>,
+
+    /// Write diagnostics (debug level, trace with -vvv) to this file instead of stderr
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Record every probe to this session file, for `rkik replay`
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+
+    /// Persist every probe to a history database (sqlite://PATH), for `rkik history`
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "URL", value_parser = crate::store::parse_url)]
+    pub store: Option<PathBuf>,
+
+    /// Capture the NTP packets exchanged to this pcap file
+    #[arg(long, value_name = "PATH")]
+    pub pcap: Option<PathBuf>,
+
     /// Use IPv6 resolution only
     #[arg(short = '6', long)]
     pub ipv6: bool,
 
-    /// Timeout in seconds
-    #[arg(long, default_value_t = 5.0)]
+    /// Timeout per request (e.g. 500ms, 2s; plain numbers are seconds)
+    #[arg(long, default_value_t = 5.0, value_name = "DURATION", value_parser = crate::duration::parse_seconds)]
     pub timeout: f64,
 
+    /// Discard samples whose RTT exceeds FACTOR times the lowest one before averaging
+    #[arg(long, value_name = "FACTOR", value_parser = parse_rtt_filter)]
+    pub rtt_filter: Option<f64>,
+
+    /// Re-resolve the host name on every probe instead of caching it for its DNS TTL
+    #[arg(long)]
+    pub no_dns_cache: bool,
+
+    /// Treat answers from unsynchronized servers (leap indicator 3, stratum 0 or 16, stale reference) as failures, with exit code 6
+    #[arg(long)]
+    pub require_sync: bool,
+
+    /// With --require-sync, the oldest reference accepted (default 1 day)
+    #[arg(long, value_name = "DURATION", requires = "require_sync", value_parser = crate::duration::parse_positive_seconds)]
+    pub max_reference_age: Option<f64>,
+
+    /// Per-target timeouts (s) from the configuration file, overriding `timeout`
+    #[arg(skip)]
+    pub target_timeouts: HashMap<String, f64>,
+
+    /// Only accept DNSSEC-validated answers when resolving host names
+    #[cfg(feature = "dnssec")]
+    #[arg(long)]
+    pub dnssec: bool,
+
+    /// MaxMind database (ASN, country or city) used to label responders in verbose output
+    #[cfg(feature = "enrich")]
+    #[arg(long, value_name = "PATH", requires = "verbose")]
+    pub geoip_db: Vec<PathBuf>,
+
     /// Enable one-shot system clock synchronization (requires root)
     #[cfg(feature = "sync")]
     #[arg(long)]
@@ -103,14 +334,38 @@ pub struct LegacyArgs {
     #[arg(short = '8', long)]
     pub infinite: bool,
 
-    /// Interval between queries in seconds (only with --infinite or --count)
-    #[arg(short = 'i', long, default_value_t = 1.0)]
-    pub interval: f64,
+    /// Interval between queries (e.g. 500ms, 2s, 5m), or `auto` to adapt it to the jitter (only with --infinite or --count)
+    #[arg(short = 'i', long, default_value_t = Interval::Seconds(1.0), value_name = "DURATION", value_parser = parse_interval)]
+    pub interval: Interval,
+
+    /// Lengthen each pause by a random share of the interval, up to this much (e.g. 10%)
+    #[arg(long, value_name = "PERCENT", value_parser = parse_jitter)]
+    pub interval_jitter: Option<f64>,
+
+    /// Never query a server more often than every DURATION, whatever --interval says
+    #[arg(long, value_name = "DURATION", value_parser = crate::duration::parse_seconds)]
+    pub min_interval: Option<f64>,
+
+    /// Allow intervals under 1 s against pool.ntp.org
+    #[arg(long)]
+    pub allow_fast_pool: bool,
 
     /// Specific count of requests
     #[arg(short = 'c', long, default_value_t = 1)]
     pub count: u32,
 
+    /// In repeated runs, give up with exit code 5 once a target fails this many times in a row
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_failures: Option<u32>,
+
+    /// Give up at the first failure of a repeated run (same as --max-failures 1)
+    #[arg(long, conflicts_with = "max_failures")]
+    pub fail_fast: bool,
+
+    /// Stop after this long (e.g. 90s, 30m, 1h); runs until then unless --count stops it first
+    #[arg(long, value_name = "DURATION", value_parser = crate::duration::parse_positive_seconds)]
+    pub duration: Option<f64>,
+
     /// Enable NTS (Network Time Security) authentication
     #[cfg(feature = "nts")]
     #[arg(long)]
@@ -125,13 +380,48 @@ pub struct LegacyArgs {
     #[arg(long)]
     pub plugin: bool,
 
-    /// Warning threshold in ms (requires --plugin)
-    #[arg(long, requires = "plugin", value_name = "MS")]
-    pub warning: Option<f64>,
+    /// Warning threshold in ms or Nagios range, e.g. 50 or @0:5 (requires --plugin or --highlight)
+    #[arg(long, allow_hyphen_values = true, value_name = "MS")]
+    pub warning: Option<Threshold>,
+
+    /// Critical threshold in ms or Nagios range (requires --plugin or --highlight)
+    #[arg(long, allow_hyphen_values = true, value_name = "MS")]
+    pub critical: Option<Threshold>,
 
-    /// Critical threshold in ms (requires --plugin)
-    #[arg(long, requires = "plugin", value_name = "MS")]
-    pub critical: Option<f64>,
+    /// Warning threshold on RTT in ms (requires --plugin or --highlight)
+    #[arg(long, allow_hyphen_values = true, value_name = "MS")]
+    pub warning_rtt: Option<Threshold>,
+
+    /// Critical threshold on RTT in ms (requires --plugin or --highlight)
+    #[arg(long, allow_hyphen_values = true, value_name = "MS")]
+    pub critical_rtt: Option<Threshold>,
+
+    /// Color offsets and RTTs in text output by --warning/--critical and --warning-rtt/--critical-rtt
+    #[arg(long)]
+    pub highlight: bool,
+
+    /// Warn when the server stratum is above this value (requires --plugin)
+    #[arg(long, requires = "plugin", value_name = "N")]
+    pub max_stratum: Option<u8>,
+
+    /// Warning threshold on offset jitter in ms, needs --count >= 2 (requires --plugin)
+    #[arg(
+        long,
+        requires = "plugin",
+        allow_hyphen_values = true,
+        value_name = "MS"
+    )]
+    pub warning_jitter: Option<Threshold>,
+
+    /// Plugin output dialect: nagios, checkmk or icinga2-json (requires --plugin)
+    #[arg(
+        long,
+        value_enum,
+        requires = "plugin",
+        default_value = "nagios",
+        value_name = "FORMAT"
+    )]
+    pub plugin_format: PluginFormat,
 }
 
 impl Default for LegacyArgs {
@@ -139,22 +429,75 @@ impl Default for LegacyArgs {
         Self {
             server: None,
             compare: None,
-            verbose: false,
+            ports: None,
+            reference: None,
+            include_local: false,
+            matrix: false,
+            max_drift: None,
+            assert_max_offset: None,
+            assert_max_drift: None,
+            assert_leap: false,
+            leap_file: None,
+            detect_smear: false,
+            concurrency: 32,
+            stagger: None,
+            shared_socket: false,
+            recommend: None,
+            emit_config: None,
+            diverge_threshold: None,
+            verbose: 0,
             format: OutputFormat::Text,
             json: false,
             short: false,
+            quiet: false,
+            silent: false,
             pretty: false,
             no_color: false,
+            fields: Vec::new(),
+            unit: TimeUnit::Ms,
+            output: None,
+            rotate: None,
+            rotate_keep: 5,
+            #[cfg(feature = "otel")]
+            otlp_endpoint: None,
+            #[cfg(feature = "otel")]
+            otlp_attributes: Vec::new(),
+            #[cfg(feature = "json")]
+            zabbix: None,
+            #[cfg(feature = "json")]
+            zabbix_host: None,
+            log_target: None,
+            log_file: None,
+            #[cfg(feature = "json")]
+            record: None,
+            #[cfg(feature = "sqlite")]
+            store: None,
+            pcap: None,
             ipv6: false,
             timeout: 5.0,
+            rtt_filter: None,
+            no_dns_cache: false,
+            require_sync: false,
+            max_reference_age: None,
+            target_timeouts: HashMap::new(),
+            #[cfg(feature = "dnssec")]
+            dnssec: false,
+            #[cfg(feature = "enrich")]
+            geoip_db: Vec::new(),
             #[cfg(feature = "sync")]
             sync: false,
             #[cfg(feature = "sync")]
             dry_run: false,
             target: None,
             infinite: false,
-            interval: 1.0,
+            interval: Interval::Seconds(1.0),
+            interval_jitter: None,
+            min_interval: None,
+            allow_fast_pool: false,
             count: 1,
+            duration: None,
+            max_failures: None,
+            fail_fast: false,
             #[cfg(feature = "nts")]
             nts: false,
             #[cfg(feature = "nts")]
@@ -162,6 +505,12 @@ impl Default for LegacyArgs {
             plugin: false,
             warning: None,
             critical: None,
+            warning_rtt: None,
+            critical_rtt: None,
+            max_stratum: None,
+            warning_jitter: None,
+            highlight: false,
+            plugin_format: PluginFormat::Nagios,
         }
     }
 }
@@ -182,35 +531,83 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
     let term = Term::stdout();
     let timeout = Duration::from_secs_f64(args.timeout);
 
-    // Validate thresholds for plugin mode
-    if args.plugin {
-        if let Some(w) = args.warning
-            && w < 0.0
-        {
-            term.write_line(&style("--warning must be non-negative").red().to_string())
+    let thresholds = [
+        ("--warning", args.warning),
+        ("--critical", args.critical),
+        ("--warning-rtt", args.warning_rtt),
+        ("--critical-rtt", args.critical_rtt),
+    ];
+    if !args.plugin
+        && !args.highlight
+        && let Some((flag, _)) = thresholds.iter().find(|(_, t)| t.is_some())
+    {
+        term.write_line(
+            &style(format!("{} requires --plugin or --highlight", flag))
+                .red()
+                .to_string(),
+        )
+        .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+    if args.highlight && thresholds.iter().all(|(_, t)| t.is_none()) {
+        term.write_line(
+            &style("--highlight needs --warning, --critical, --warning-rtt or --critical-rtt")
+                .red()
+                .to_string(),
+        )
+        .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+
+    // Validate thresholds for plugin mode and highlighting
+    if args.plugin || args.highlight {
+        for (flag, value) in [
+            ("--warning", args.warning),
+            ("--critical", args.critical),
+            ("--warning-rtt", args.warning_rtt),
+            ("--critical-rtt", args.critical_rtt),
+            ("--warning-jitter", args.warning_jitter),
+        ] {
+            if value
+                .and_then(|t| t.simple_bound())
+                .is_some_and(|v| v < 0.0)
+            {
+                term.write_line(
+                    &style(format!("{} must be non-negative", flag))
+                        .red()
+                        .to_string(),
+                )
                 .ok();
-            let _ = io::stdout().flush();
-            process::exit(2);
+                let _ = io::stdout().flush();
+                process::exit(2);
+            }
         }
-        if let Some(c) = args.critical
-            && c < 0.0
-        {
-            term.write_line(&style("--critical must be non-negative").red().to_string())
+        for (warn_flag, crit_flag, warning, critical) in [
+            ("--warning", "--critical", args.warning, args.critical),
+            (
+                "--warning-rtt",
+                "--critical-rtt",
+                args.warning_rtt,
+                args.critical_rtt,
+            ),
+        ] {
+            // Only plain upper bounds can be ordered; ranges are taken as given.
+            if let (Some(w), Some(c)) = (
+                warning.and_then(|t| t.simple_bound()),
+                critical.and_then(|t| t.simple_bound()),
+            ) && w >= c
+            {
+                term.write_line(
+                    &style(format!("{} must be less than {}", warn_flag, crit_flag))
+                        .red()
+                        .to_string(),
+                )
                 .ok();
-            let _ = io::stdout().flush();
-            process::exit(2);
-        }
-        if let (Some(w), Some(c)) = (args.warning, args.critical)
-            && w >= c
-        {
-            term.write_line(
-                &style("--warning must be less than --critical")
-                    .red()
-                    .to_string(),
-            )
-            .ok();
-            let _ = io::stdout().flush();
-            process::exit(2);
+                let _ = io::stdout().flush();
+                process::exit(2);
+            }
         }
     }
 
@@ -224,9 +621,30 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
         let _ = io::stdout().flush();
         process::exit(2);
     }
+    if args.fail_fast {
+        args.max_failures = Some(1);
+    }
+    // Without a sample budget, --duration runs open-ended until it is spent.
+    if args.duration.is_some() && args.count == 1 && !args.infinite {
+        if args.plugin {
+            plugin_conflict("duration (without --count)", &term);
+        }
+        #[cfg(feature = "sync")]
+        if args.sync {
+            term.write_line(
+                &style("--sync cannot be used with --duration without --count")
+                    .red()
+                    .to_string(),
+            )
+            .ok();
+            let _ = io::stdout().flush();
+            process::exit(2);
+        }
+        args.infinite = true;
+    }
     if (matches!(args.format, OutputFormat::Simple)
         || matches!(args.format, OutputFormat::JsonShort))
-        && args.verbose
+        && args.verbose > 0
     {
         term.write_line(
             &style("--verbose has no effect with short format")
@@ -235,7 +653,19 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
         )
         .ok();
     }
-    if args.interval != 1.0 && !args.infinite && args.count == 1 {
+    if !args.fields.is_empty() && matches!(args.format, OutputFormat::Text | OutputFormat::Markdown)
+    {
+        term.write_line(
+            &style(format!(
+                "--fields has no effect with {} format",
+                args.format
+            ))
+            .yellow()
+            .to_string(),
+        )
+        .ok();
+    }
+    if args.interval != Interval::Seconds(1.0) && !args.infinite && args.count == 1 {
         term.write_line(
             &style("--interval requires --infinite or --count")
                 .red()
@@ -257,12 +687,9 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
         process::exit(2);
     }
 
-    // refuse --plugin --compare, --verbose, --json, --pretty, --short, --format(except for text), --infinite
+    // refuse --plugin --verbose, --json, --pretty, --short, --format(except for text), --infinite
     if args.plugin {
-        if args.compare.is_some() {
-            plugin_conflict("compare", &term);
-        }
-        if args.verbose {
+        if args.verbose > 0 {
             plugin_conflict("verbose", &term);
         }
         if args.json {
@@ -280,6 +707,37 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
         if args.infinite {
             plugin_conflict("infinite", &term);
         }
+        if !args.fields.is_empty() {
+            plugin_conflict("fields", &term);
+        }
+        if args.output.is_some() {
+            plugin_conflict("output", &term);
+        }
+        if args.matrix {
+            plugin_conflict("matrix", &term);
+        }
+        if args.assert_max_offset.is_some() {
+            plugin_conflict("assert-max-offset", &term);
+        }
+        if args.assert_max_drift.is_some() {
+            plugin_conflict("assert-max-drift", &term);
+        }
+        if args.assert_leap {
+            plugin_conflict("assert-leap", &term);
+        }
+        if args.highlight {
+            plugin_conflict("highlight", &term);
+        }
+        if args.quiet {
+            plugin_conflict("quiet", &term);
+        }
+        if args.silent {
+            plugin_conflict("silent", &term);
+        }
+    }
+    SILENT.store(args.silent, Ordering::Relaxed);
+    if args.highlight {
+        install_highlight(&args);
     }
 
     // colors
@@ -287,10 +745,205 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
         || matches!(args.format, OutputFormat::Simple))
         && io::stdout().is_terminal()
         && std::env::var_os("NO_COLOR").is_none()
-        && !args.no_color;
+        && !args.no_color
+        && args.output.is_none();
     set_colors_enabled(want_color);
+    set_display_unit(args.unit);
+    resolver::set_cache_enabled(!args.no_dns_cache);
+    #[cfg(feature = "dnssec")]
+    resolver::set_require_dnssec(args.dnssec);
+    #[cfg(feature = "enrich")]
+    if let Err(e) = enrich::set_geoip_databases(&args.geoip_db) {
+        term.write_line(&style(format!("Error: {}", e)).red().to_string())
+            .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+
+    if let Some(path) = &args.output {
+        match RotatingFile::open(path, args.rotate, args.rotate_keep) {
+            Ok(file) => output_file::install(file),
+            Err(e) => {
+                term.write_line(
+                    &style(format!("Error: cannot open {}: {}", path.display(), e))
+                        .red()
+                        .to_string(),
+                )
+                .ok();
+                let _ = io::stdout().flush();
+                process::exit(2);
+            }
+        }
+    }
+
+    let diagnostics = logging::Diagnostics {
+        verbosity: args.verbose,
+        log_file: args.log_file.clone(),
+    };
+    if let Err(e) = logging::init(args.log_target, &diagnostics) {
+        term.write_line(&style(format!("Error: {}", e)).red().to_string())
+            .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+
+    #[cfg(feature = "json")]
+    if let Some(path) = &args.record
+        && let Err(e) = record::install(path)
+    {
+        term.write_line(
+            &style(format!("Error: cannot record to {}: {}", path.display(), e))
+                .red()
+                .to_string(),
+        )
+        .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.store
+        && let Err(e) = store::install(path)
+    {
+        term.write_line(&style(format!("Error: {}", e)).red().to_string())
+            .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+
+    if let Some(path) = &args.pcap
+        && let Err(e) = rkik::adapters::pcap::start_capture(path)
+    {
+        term.write_line(
+            &style(format!(
+                "Error: cannot capture to {}: {}",
+                path.display(),
+                e
+            ))
+            .red()
+            .to_string(),
+        )
+        .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+    #[cfg(feature = "nts")]
+    if args.pcap.is_some() && args.nts {
+        eprintln!("rkik: --pcap captures plain NTP only; NTS exchanges are not recorded");
+    }
+
+    #[cfg(feature = "otel")]
+    if let Some(endpoint) = &args.otlp_endpoint {
+        otel::install(endpoint.clone(), args.otlp_attributes.clone(), timeout);
+    }
+
+    #[cfg(feature = "json")]
+    if let (Some(server), Some(host)) = (&args.zabbix, &args.zabbix_host) {
+        zabbix::install(server.clone(), host.clone(), timeout);
+    }
+
+    let leap_table = match &args.leap_file {
+        Some(path) => match LeapTable::load(path) {
+            Ok(table) => Some(table),
+            Err(e) => {
+                term.write_line(&style(format!("Error: {}", e)).red().to_string())
+                    .ok();
+                let _ = io::stdout().flush();
+                process::exit(2);
+            }
+        },
+        None => args.assert_leap.then(LeapTable::current),
+    };
+
+    if args.include_local
+        && let Some(list) = args.compare.as_mut()
+        && !list.iter().any(|t| t == LOCAL_TARGET)
+    {
+        list.push(LOCAL_TARGET.to_string());
+    }
 
     // refuse --sync with --compare
+    if args.compare.is_none()
+        && args.server.is_none()
+        && args.target.is_none()
+        && let Some(dhcp) = dhcp::ntp_servers()
+    {
+        let note = format!(
+            "Using NTP servers from DHCP ({}): {}",
+            dhcp.source.display(),
+            dhcp.servers.join(", ")
+        );
+        match args.format {
+            OutputFormat::Text | OutputFormat::Simple if !args.plugin => {
+                emit_line(&term, &style(note).dim().to_string())
+            }
+            _ => eprintln!("{}", note),
+        }
+        let mut servers = dhcp.servers;
+        if servers.len() == 1 {
+            args.target = servers.pop();
+        } else {
+            args.compare = Some(servers);
+        }
+    }
+
+    if let Some(ports) = &args.ports {
+        match args
+            .server
+            .take()
+            .or_else(|| args.target.take())
+            .ok_or_else(|| "--ports needs a target".to_string())
+            .and_then(|target| on_ports(&target, ports))
+        {
+            Ok(mut targets) if targets.len() == 1 => args.target = targets.pop(),
+            Ok(targets) => args.compare = Some(targets),
+            Err(e) => {
+                term.write_line(&style(format!("Error: {}", e)).red().to_string())
+                    .ok();
+                let _ = io::stdout().flush();
+                process::exit(2);
+            }
+        }
+    }
+
+    if args.interval == Interval::Auto && args.compare.is_some() {
+        term.write_line(
+            &style("--interval auto cannot be used with --compare")
+                .red()
+                .to_string(),
+        )
+        .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+
+    // Public pool servers ask for polite clients: no sub-second polling
+    // unless explicitly forced.
+    let pool = args
+        .compare
+        .iter()
+        .flatten()
+        .chain(args.server.iter())
+        .chain(args.target.iter())
+        .find(|t| is_pool(t));
+    if let Some(pool) = pool
+        && (args.infinite || args.count > 1)
+        && pace(&args).base() < POOL_MIN_INTERVAL
+        && !args.allow_fast_pool
+    {
+        term.write_line(
+            &style(format!(
+                "Refusing to query {} more than once a second; raise --interval or pass --allow-fast-pool",
+                pool
+            ))
+            .red()
+            .to_string(),
+        )
+        .ok();
+        let _ = io::stdout().flush();
+        process::exit(2);
+    }
+
     #[cfg(feature = "sync")]
     if args.sync && args.compare.is_some() {
         term.write_line(
@@ -303,6 +956,9 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
         process::exit(2);
     }
 
+    // Repeated probes keep one socket, and one NTS session, per server.
+    let reuse_scope = (args.infinite || args.count > 1).then(reuse::keep_open);
+
     let exit_code = match (&args.compare, &args.server, &args.target) {
         (Some(list), _, _) => {
             #[cfg(feature = "nts")]
@@ -310,50 +966,185 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
             #[cfg(not(feature = "nts"))]
             let (use_nts, nts_port) = (false, 4460u16);
 
-            let mut all: HashMap<String, Vec<ProbeResult>> = HashMap::new();
+            let reference = match &args.reference {
+                Some(name) => match list.iter().position(|t| t == name) {
+                    Some(i) => Some(i),
+                    None => {
+                        term.write_line(
+                            &style(format!(
+                                "--reference {} is not one of the compared servers",
+                                name
+                            ))
+                            .red()
+                            .to_string(),
+                        )
+                        .ok();
+                        let _ = io::stdout().flush();
+                        process::exit(2);
+                    }
+                },
+                None => None,
+            };
+
+            let mut all: HashMap<String, Series> = HashMap::new();
+            let mut leaps: HashMap<String, Leap> = HashMap::new();
+            // Last stratum and NTS state of each server, for --recommend.
+            let mut strata: HashMap<String, (u8, bool)> = HashMap::new();
+            let mut divergence = args.diverge_threshold.map(DivergenceTracker::new);
+            let smear_table = leap_table.clone().unwrap_or_else(LeapTable::current);
+            let mut smear_reported: BTreeSet<String> = BTreeSet::new();
+            let mut health_reported: BTreeSet<String> = BTreeSet::new();
+            let mut failed: BTreeSet<String> = BTreeSet::new();
+            // Whether --require-sync refused a server.
+            let mut unsynced = false;
             let mut n = 0u32;
             let multi = args.count > 1 || args.infinite;
-            if multi && matches!(args.format, OutputFormat::Csv) {
-                println!("{}", fmt::csv::HEADER);
+            let mut pacer = pace(&args);
+            if multi {
+                report_pacing(&pacer, &args);
+            }
+            let started = Instant::now();
+            let steps = multi.then(StepWatch::spawn);
+            let mut slept = None;
+            let mut consecutive: HashMap<String, u32> = HashMap::new();
+            let mut gave_up = None;
+            if let Some(name) = &args.reference
+                && !args.plugin
+                && matches!(args.format, OutputFormat::Text)
+            {
+                emit_line(
+                    &term,
+                    &format!("Offsets relative to {}", style(name).green().bold()),
+                );
+            }
+            let quiet = multi && args.quiet;
+            if multi && matches!(args.format, OutputFormat::Csv) && !quiet {
+                emit_line(&term, &csv_header(&args.fields));
+            }
+            if multi && matches!(args.format, OutputFormat::Markdown) && !quiet {
+                emit_line(&term, fmt::markdown::HEADER);
             }
             loop {
-                match compare_many(list, args.ipv6, timeout, use_nts, nts_port).await {
-                    Ok(results) => {
-                        if multi {
+                let timeout_for = |target: &str| {
+                    args.target_timeouts
+                        .get(target)
+                        .map_or(timeout, |t| Duration::from_secs_f64(*t))
+                };
+                let options = QueryOptions::new()
+                    .ipv6_only(args.ipv6)
+                    .nts(use_nts)
+                    .nts_port(nts_port)
+                    .concurrency(args.concurrency.into())
+                    .stagger(Duration::from_secs_f64(
+                        args.stagger.unwrap_or(0.0) / 1000.0,
+                    ))
+                    .shared_socket(args.shared_socket)
+                    // Scanning ports, a single one answering is a result.
+                    .min_answers(if args.ports.is_some() { 1 } else { 2 });
+                let options = require_sync(options, &args);
+                let round = compare_with(list, &options, timeout_for)
+                    .await
+                    .and_then(|outcome| split_round(outcome, reference));
+                report_rejected(&args);
+                match round {
+                    Ok((mut results, failures, reference)) => {
+                        let step = steps.as_ref().and_then(StepWatch::take);
+                        report_clock_step(step, &args);
+                        results.iter_mut().for_each(|r| {
+                            r.pause_ms = slept;
+                            r.clock_step_ms = step;
+                        });
+                        // The local and chronyd pseudo-targets are not measurements worth exporting.
+                        let measured: Vec<ProbeResult> = results
+                            .iter()
+                            .filter(|r| {
+                                r.target.name != LOCAL_TARGET && r.target.name != CHRONYD_TARGET
+                            })
+                            .cloned()
+                            .collect();
+                        measured.iter().for_each(logging::probe_ok);
+                        #[cfg(feature = "json")]
+                        measured.iter().for_each(record::probe);
+                        #[cfg(feature = "sqlite")]
+                        measured.iter().for_each(store::probe);
+                        for (target, e) in &failures {
+                            logging::probe_failed(target, e);
+                            #[cfg(feature = "json")]
+                            record::failure(target, e);
+                            #[cfg(feature = "sqlite")]
+                            store::failure(target, e);
+                            failed.insert(target.clone());
+                            unsynced |= e.is_unsynchronized();
+                        }
+                        for r in &results {
+                            consecutive.remove(&r.target.name);
+                        }
+                        for (target, _) in &failures {
+                            *consecutive.entry(target.clone()).or_default() += 1;
+                        }
+                        if multi
+                            && let Some(max) = args.max_failures
+                            && let Some((target, _)) =
+                                failures.iter().find(|(t, _)| consecutive[t] >= max)
+                        {
+                            gave_up = Some((target.clone(), max));
+                        }
+                        if multi
+                            && let Some((target, _)) =
+                                failures.iter().find(|(_, e)| e.is_rate_limited())
+                        {
+                            pacer.rate_limited();
+                            report_backoff(target, &pacer, &args);
+                        }
+                        #[cfg(feature = "otel")]
+                        otel::push(&measured).await;
+                        #[cfg(feature = "json")]
+                        zabbix::send(&measured).await;
+                        // Exported telemetry keeps local-clock offsets; displays and checks are relative.
+                        if let Some(i) = reference {
+                            rebase_offsets(&mut results, i);
+                        }
+                        enrich_results(&mut results, &args).await;
+                        if args.plugin || quiet {
+                            // collected below, reported as a single plugin line or statistics
+                        } else if multi {
                             match args.format {
                                 OutputFormat::Text => {
-                                    if args.verbose {
+                                    if args.verbose > 0 {
                                         output(
                                             &term,
                                             &results,
                                             OutputFormat::Text,
                                             args.pretty,
                                             true,
+                                            &args.fields,
                                         );
                                     } else {
                                         let line = fmt::text::render_short_compare(&results);
-                                        term.write_line(&line).ok();
+                                        emit_line(&term, &line);
                                     }
                                 }
                                 OutputFormat::JsonShort => {
                                     for r in &results {
-                                        match fmt::json::probe_to_short_json(r) {
-                                            Ok(s) => println!("{}", s),
+                                        match short_json_line(r, &args.fields) {
+                                            Ok(s) => emit_line(&term, &s),
                                             Err(e) => eprintln!("error serializing: {}", e),
                                         }
                                     }
                                 }
-                                OutputFormat::Csv => match fmt::csv::rows(&results) {
-                                    Ok(s) => print!("{}", s),
+                                OutputFormat::Csv => match csv_rows(&results, &args.fields) {
+                                    Ok(s) => emit(&s),
                                     Err(e) => eprintln!("error serializing: {}", e),
                                 },
+                                OutputFormat::Markdown => emit(&fmt::markdown::rows(&results)),
                                 _ => {
                                     output(
                                         &term,
                                         &results,
                                         args.format.clone(),
                                         args.pretty,
-                                        args.verbose,
+                                        args.verbose > 0,
+                                        &args.fields,
                                     );
                                 }
                             }
@@ -363,52 +1154,107 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
                                 &results,
                                 args.format.clone(),
                                 args.pretty,
-                                args.verbose,
+                                args.verbose > 0,
+                                &args.fields,
                             );
                         }
+                        if !args.plugin {
+                            report_failures(&term, &failures, &args);
+                            report_smear(&results, &smear_table, &mut smear_reported, &args);
+                            report_health(&results, &mut health_reported, &args);
+                            if let Some(tracker) = divergence.as_mut() {
+                                report_divergence(&term, &tracker.observe(&measured), &args);
+                            }
+                        }
                         for r in results {
-                            all.entry(r.target.name.clone()).or_default().push(r);
+                            leaps.insert(r.target.name.clone(), r.leap);
+                            strata.insert(r.target.name.clone(), (r.stratum, r.authenticated));
+                            all.entry(r.target.name.clone())
+                                .or_insert_with(|| Series::new(&args))
+                                .push(r);
                         }
                     }
                     Err(e) => {
+                        logging::probe_failed(&list.join(","), &e);
+                        #[cfg(feature = "json")]
+                        record::failure(&list.join(","), &e);
+                        #[cfg(feature = "sqlite")]
+                        store::failure(&list.join(","), &e);
+                        if args.plugin {
+                            let report = plugin::failed(&args, "compare", &e);
+                            report.print(args.plugin_format);
+                            let _ = io::stdout().flush();
+                            process::exit(report.code);
+                        }
                         let code = handle_error(&term, e, args.format.clone(), args.pretty);
                         let _ = io::stdout().flush();
                         process::exit(code);
                     }
                 }
                 n += 1;
+                if let Some((target, max)) = &gave_up {
+                    report_give_up(target, *max, &args);
+                    break;
+                }
                 if !args.infinite && n >= args.count {
                     break;
                 }
+                let pause = pacer.pause();
+                if let Some(d) = args.duration
+                    && started.elapsed() + pause > Duration::from_secs_f64(d)
+                {
+                    break;
+                }
+                let slept_from = Instant::now();
                 if args.infinite {
-                    let sleep = tokio::time::sleep(Duration::from_secs_f64(args.interval));
+                    let sleep = tokio::time::sleep(pause);
                     tokio::select! {
                         _ = sleep => {},
                         _ = signal::ctrl_c() => { break; }
                     }
                 } else {
-                    tokio::time::sleep(Duration::from_secs_f64(args.interval)).await;
+                    tokio::time::sleep(pause).await;
                 }
+                slept = Some(slept_from.elapsed().as_secs_f64() * 1000.0);
             }
 
-            if all.values().map(|v| v.len()).sum::<usize>() > list.len() {
-                let mut stats_list: Vec<(String, Stats)> = all
+            let probed = all.values().map(Series::probed).sum::<usize>();
+            let mut stats_list: Vec<(String, Stats)> = all
+                .iter_mut()
+                .map(|(name, series)| (name.clone(), series.finish(args.rtt_filter)))
+                .collect();
+
+            if args.plugin {
+                let _ = io::stdout().flush();
+                let samples: HashMap<String, Vec<ProbeResult>> = all
                     .into_iter()
-                    .map(|(name, vals)| (name, compute_stats(&vals)))
+                    .map(|(name, series)| (name, series.into_samples()))
                     .collect();
-                stats_list.sort_by(|a, b| a.0.cmp(&b.0));
+                let failed: Vec<String> = failed.into_iter().collect();
+                let report = plugin::compare(&samples, &failed, &args);
+                report.print(args.plugin_format);
+                let _ = io::stdout().flush();
+                process::exit(report.code);
+            }
+
+            stats_list.sort_by(|a, b| a.0.cmp(&b.0));
+            if probed > list.len() {
                 match args.format {
                     OutputFormat::Json => {
                         match fmt::json::stats_list_to_json(&stats_list, args.pretty) {
-                            Ok(s) => println!("{}", s),
+                            Ok(s) => emit_line(&term, &s),
                             Err(e) => eprintln!("error serializing: {}", e),
                         }
                     }
                     OutputFormat::Csv => {}
+                    OutputFormat::Markdown => emit(&format!(
+                        "\n{}",
+                        fmt::markdown::stats_to_markdown(&stats_list)
+                    )),
                     _ => {
                         for (name, st) in &stats_list {
                             let line = fmt::text::render_stats(name, st);
-                            term.write_line(&line).ok();
+                            emit_line(&term, &line);
                         }
                         let min = stats_list
                             .iter()
@@ -419,23 +1265,69 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
                             .map(|(_, s)| s.offset_avg)
                             .fold(f64::NEG_INFINITY, f64::max);
                         let drift = max - min;
-                        let _ = term.write_line(&format!("Max avg drift: {:.3} ms", drift));
+                        emit_line(&term, &format!("Max avg drift: {}", format_ms(drift)));
                     }
                 }
             }
-            0
+            if args.matrix {
+                let offsets: Vec<(String, f64)> = stats_list
+                    .iter()
+                    .map(|(name, st)| (name.clone(), st.offset_avg))
+                    .collect();
+                let matrix = DriftMatrix::new(&offsets);
+                match args.format {
+                    OutputFormat::Json | OutputFormat::JsonShort => {
+                        match fmt::json::matrix_to_json(&matrix, args.max_drift, args.pretty) {
+                            Ok(s) => emit_line(&term, &s),
+                            Err(e) => eprintln!("error serializing: {}", e),
+                        }
+                    }
+                    _ => emit(&format!(
+                        "\n{}",
+                        fmt::text::render_matrix(&matrix, args.max_drift)
+                    )),
+                }
+            }
+            if args.recommend.is_some() || args.emit_config.is_some() {
+                let candidates: Vec<Candidate> = stats_list
+                    .iter()
+                    .filter(|(name, _)| name != LOCAL_TARGET && name != CHRONYD_TARGET)
+                    .filter_map(|(name, st)| {
+                        let (stratum, nts) = strata.get(name)?;
+                        Some(Candidate::from_stats(name, st, *stratum, *nts))
+                    })
+                    .collect();
+                let count = args.recommend.map_or(DEFAULT_COUNT, usize::from);
+                let json = matches!(args.format, OutputFormat::Json | OutputFormat::JsonShort);
+                match recommend::report(&candidates, count, args.emit_config, json, args.pretty) {
+                    Ok(s) => emit(&s),
+                    Err(e) => eprintln!("error serializing: {}", e),
+                }
+            }
+            let offsets: Vec<(String, f64)> = stats_list
+                .iter()
+                .map(|(name, st)| (name.clone(), st.offset_avg))
+                .collect();
+            let leaps: Vec<(String, Leap)> = leaps
+                .into_iter()
+                .filter(|(name, _)| name != LOCAL_TARGET)
+                .collect();
+            let code = check_assertions(&offsets, &leaps, leap_table.as_ref(), &args);
+            if gave_up.is_some() {
+                EXIT_GAVE_UP
+            } else if code == 0 && unsynced {
+                EXIT_UNSYNCHRONIZED
+            } else {
+                code
+            }
         }
         (_, Some(server), _) => {
-            query_loop(server, &args, &term, timeout).await;
-            0
-        }
-        (_, None, Some(pos)) => {
-            query_loop(pos, &args, &term, timeout).await;
-            0
+            query_loop(server, &args, &term, timeout, leap_table.as_ref()).await
         }
+        (_, None, Some(pos)) => query_loop(pos, &args, &term, timeout, leap_table.as_ref()).await,
         _ => {
             term.write_line(
-                &style("Error: Provide either a server, a positional argument, or --compare")
+                &style("Error: Provide either a server, a positional argument, or --compare (no NTP servers found in DHCP leases)")
                     .red()
                     .bold()
                     .to_string(),
@@ -445,13 +1337,34 @@ pub async fn run(mut args: LegacyArgs, _warn_legacy: bool) {
         }
     };
 
+    if reuse_scope.is_some() {
+        let reused = reuse::stats();
+        tracing::debug!(
+            sockets_opened = reused.sockets_opened,
+            sockets_reused = reused.sockets_reused,
+            nts_handshakes = reused.nts_handshakes,
+            nts_sessions_reused = reused.nts_sessions_reused,
+            "socket reuse"
+        );
+    }
+
     let _ = io::stdout().flush();
     process::exit(exit_code);
 }
 
-async fn query_loop(target: &str, args: &LegacyArgs, term: &Term, timeout: Duration) {
-    let mut all = Vec::new();
-    let mut n = 0u32;
+/// Returns the process exit code: 0, 4 when an `--assert-*` threshold is
+/// exceeded, or 5 when `--max-failures` gave up on the target.
+async fn query_loop(
+    target: &str,
+    args: &LegacyArgs,
+    term: &Term,
+    timeout: Duration,
+    leap_table: Option<&LeapTable>,
+) -> i32 {
+    let mut all = Series::new(args);
+    let mut name = String::new();
+    let mut leap = Leap::Normal;
+    let mut health_reported = BTreeSet::new();
 
     #[cfg(feature = "nts")]
     let (use_nts, nts_port) = (args.nts, args.nts_port);
@@ -459,48 +1372,130 @@ async fn query_loop(target: &str, args: &LegacyArgs, term: &Term, timeout: Durat
     let (use_nts, nts_port) = (false, 4460u16);
 
     let multi = args.count > 1 || args.infinite;
-    if multi && matches!(args.format, OutputFormat::Csv) && !args.plugin {
-        println!("{}", fmt::csv::HEADER);
+    // --quiet keeps only the final statistics of repeated runs.
+    let per_probe = !(args.plugin || (multi && args.quiet));
+    if multi && matches!(args.format, OutputFormat::Csv) && per_probe {
+        emit_line(term, &csv_header(&args.fields));
+    }
+    if multi && matches!(args.format, OutputFormat::Markdown) && per_probe {
+        emit_line(term, fmt::markdown::HEADER);
     }
 
+    let options = MonitorOptions::new()
+        .query(require_sync(
+            QueryOptions::new()
+                .timeout(timeout)
+                .ipv6_only(args.ipv6)
+                .nts(use_nts)
+                .nts_port(nts_port),
+            args,
+        ))
+        .min_interval(Duration::from_secs_f64(args.min_interval.unwrap_or(0.0)));
+    let options = match args.interval {
+        Interval::Seconds(s) => options.interval(Duration::from_secs_f64(s)),
+        Interval::Auto => options.auto_interval(),
+    };
+    // Mirrors the stream's own pacing, to tell the user about it.
+    let mut pacer = pace(args);
+    if multi {
+        report_pacing(&pacer, args);
+    }
+    let mut last_error = None;
+    // Exit code of the last failure already reported.
+    let mut failed_code = None;
+    let mut consecutive = 0u32;
+    let mut gave_up = false;
+    let options = if args.infinite {
+        options.infinite()
+    } else {
+        options.count(args.count)
+    };
+    let options = match args.duration {
+        Some(d) => options.duration(Duration::from_secs_f64(d)),
+        None => options,
+    };
+    let options = options.interval_jitter(args.interval_jitter.unwrap_or(0.0));
+    let mut probes = pin!(probe_stream(target, options));
+    let mut progress = Progress::new(args);
+    let steps = multi.then(StepWatch::spawn);
     loop {
-        match query_one(target, args.ipv6, timeout, use_nts, nts_port).await {
-            Ok(res) => {
+        if let Some(progress) = &progress {
+            progress.draw();
+        }
+        let next = if args.infinite {
+            tokio::select! {
+                next = probes.next() => next,
+                _ = signal::ctrl_c() => None,
+            }
+        } else {
+            probes.next().await
+        };
+        if let Some(progress) = progress.as_mut() {
+            progress.clear();
+            if let Some(next) = &next {
+                progress.tick(next.as_ref().ok().map(|r| r.offset_ms));
+            }
+        }
+        let Some(next) = next else {
+            break;
+        };
+        report_rejected(args);
+        match next {
+            Ok(mut res) => {
+                res.clock_step_ms = steps.as_ref().and_then(StepWatch::take);
+                report_clock_step(res.clock_step_ms, args);
+                enrich_results(std::slice::from_mut(&mut res), args).await;
+                logging::probe_ok(&res);
+                #[cfg(feature = "json")]
+                record::probe(&res);
+                #[cfg(feature = "sqlite")]
+                store::probe(&res);
+                #[cfg(feature = "otel")]
+                otel::push(std::slice::from_ref(&res)).await;
+                #[cfg(feature = "json")]
+                zabbix::send(std::slice::from_ref(&res)).await;
                 // In plugin mode we suppress the regular human-readable output and only
                 // collect results to produce the plugin line at the end.
-                if !args.plugin {
+                if per_probe {
                     if multi {
                         let format = args.format.clone();
                         match format {
                             OutputFormat::Text => {
-                                if args.verbose {
+                                if args.verbose > 0 {
                                     output(
                                         term,
                                         std::slice::from_ref(&res),
                                         OutputFormat::Text,
                                         args.pretty,
                                         true,
+                                        &args.fields,
                                     );
                                 } else {
                                     let line = fmt::text::render_short_probe(&res);
-                                    term.write_line(&line).ok();
+                                    emit_line(term, &line);
                                 }
                             }
-                            OutputFormat::JsonShort => match fmt::json::probe_to_short_json(&res) {
-                                Ok(s) => println!("{}", s),
-                                Err(e) => eprintln!("error serializing: {}", e),
-                            },
-                            OutputFormat::Csv => match fmt::csv::rows(std::slice::from_ref(&res)) {
-                                Ok(s) => print!("{}", s),
+                            OutputFormat::JsonShort => match short_json_line(&res, &args.fields) {
+                                Ok(s) => emit_line(term, &s),
                                 Err(e) => eprintln!("error serializing: {}", e),
                             },
+                            OutputFormat::Csv => {
+                                match csv_rows(std::slice::from_ref(&res), &args.fields) {
+                                    Ok(s) => emit(&s),
+                                    Err(e) => eprintln!("error serializing: {}", e),
+                                }
+                            }
+                            OutputFormat::Markdown => {
+                                emit(&fmt::markdown::rows(std::slice::from_ref(&res)))
+                            }
                             _ => {
                                 output(
                                     term,
                                     std::slice::from_ref(&res),
                                     format,
                                     args.pretty,
-                                    args.verbose,
+                                    args.verbose > 0,
+                                    &args.fields,
                                 );
                             }
                         }
@@ -510,99 +1505,112 @@ async fn query_loop(target: &str, args: &LegacyArgs, term: &Term, timeout: Durat
                             std::slice::from_ref(&res),
                             args.format.clone(),
                             args.pretty,
-                            args.verbose,
+                            args.verbose > 0,
+                            &args.fields,
                         );
                     }
                 }
+                if !args.plugin {
+                    report_health(std::slice::from_ref(&res), &mut health_reported, args);
+                }
+                if pacer.observe_offset(res.offset_ms) {
+                    report_pacing(&pacer, args);
+                }
+                consecutive = 0;
+                if name.is_empty() {
+                    name = res.target.name.clone();
+                }
+                leap = res.leap;
                 all.push(res);
             }
             Err(e) => {
+                logging::probe_failed(target, &e);
+                #[cfg(feature = "json")]
+                record::failure(target, &e);
+                #[cfg(feature = "sqlite")]
+                store::failure(target, &e);
                 if args.plugin {
-                    // Plugin mode: report UNKNOWN and exit with code 3
-                    emit_unknown(args.warning, args.critical);
+                    // Plugin mode: report UNKNOWN (CRITICAL for failed authentication)
+                    let report = plugin::failed(args, target, &e);
+                    report.print(args.plugin_format);
                     let _ = io::stdout().flush();
-                    process::exit(3);
+                    process::exit(report.code);
+                }
+                consecutive += 1;
+                if multi && let Some(max) = args.max_failures {
+                    if consecutive >= max {
+                        report_failures(term, &[(target.to_string(), e)], args);
+                        report_give_up(target, max, args);
+                        gave_up = true;
+                        break;
+                    }
+                    if !e.is_rate_limited() {
+                        failed_code = Some(e.exit_code());
+                        report_failures(term, &[(target.to_string(), e)], args);
+                        continue;
+                    }
+                }
+                // The stream backs off on its own; keep going at the slower pace.
+                if multi && e.is_rate_limited() {
+                    pacer.rate_limited();
+                    report_backoff(target, &pacer, args);
+                    last_error = Some(e);
+                    continue;
                 }
                 let code = handle_error(term, e, args.format.clone(), args.pretty);
                 let _ = io::stdout().flush();
                 process::exit(code);
             }
         }
-        n += 1;
-        if !args.infinite && n >= args.count {
-            break;
+    }
+
+    if all.probed() == 0 {
+        if gave_up {
+            return EXIT_GAVE_UP;
         }
-        if args.infinite {
-            let sleep = tokio::time::sleep(Duration::from_secs_f64(args.interval));
-            tokio::select! {
-                _ = sleep => {},
-                _ = signal::ctrl_c() => { break; }
-            }
-        } else {
-            tokio::time::sleep(Duration::from_secs_f64(args.interval)).await;
+        if let Some(e) = last_error {
+            return handle_error(term, e, args.format.clone(), args.pretty);
+        }
+        if let Some(code) = failed_code {
+            return code;
         }
     }
 
-    if all.len() > 1 && !args.plugin {
-        let stats = compute_stats(&all);
+    let probed = all.probed();
+    let stats = all.finish(args.rtt_filter);
+    #[cfg(feature = "sync")]
+    let rejected = stats.rejected;
+    let offsets = [(name.clone(), stats.offset_avg)];
+
+    if probed > 1 && !args.plugin {
         match args.format {
-            OutputFormat::Json => {
-                match fmt::json::stats_to_json(&all[0].target.name, &stats, args.pretty) {
-                    Ok(s) => println!("{}", s),
-                    Err(e) => eprintln!("error serializing: {}", e),
-                }
-            }
+            OutputFormat::Json => match fmt::json::stats_to_json(&name, &stats, args.pretty) {
+                Ok(s) => emit_line(term, &s),
+                Err(e) => eprintln!("error serializing: {}", e),
+            },
             OutputFormat::Csv => {}
+            OutputFormat::Markdown => emit(&format!(
+                "\n{}",
+                fmt::markdown::stats_to_markdown(&[(name, stats)])
+            )),
             _ => {
-                let line = fmt::text::render_stats(&all[0].target.name, &stats);
-                term.write_line(&line).ok();
+                let line = fmt::text::render_stats(&name, &stats);
+                emit_line(term, &line);
             }
         }
     }
 
     // Plugin mode: produce Centreon/Nagios compatible output and exit with proper code
     if args.plugin {
-        if all.is_empty() {
-            emit_unknown(args.warning, args.critical);
+        if all.samples().is_empty() {
+            plugin::unknown(args, target).print(args.plugin_format);
             let _ = io::stdout().flush();
             process::exit(3);
         }
 
-        let stats = compute_stats(&all);
-        let offset = stats.offset_avg;
-        let rtt = stats.rtt_avg;
-        let host = &all[0].target.name;
-        let ip = &all[0].target.ip;
-
-        let warn_str = args.warning.map(|v| v.to_string()).unwrap_or_default();
-        let crit_str = args.critical.map(|v| v.to_string()).unwrap_or_default();
-
-        let abs_offset = offset.abs();
-        let mut exit_code = 0i32;
-        if let Some(c) = args.critical
-            && abs_offset >= c
-        {
-            exit_code = 2;
-        }
-        if exit_code == 0
-            && let Some(w) = args.warning
-            && abs_offset >= w
-        {
-            exit_code = 1;
-        }
-
-        let state = match exit_code {
-            0 => "OK",
-            1 => "WARNING",
-            2 => "CRITICAL",
-            _ => "UNKNOWN",
-        };
-
-        println!(
-            "RKIK {} - offset {:.3}ms rtt {:.3}ms from {} ({}) | offset_ms={:.3}ms;{};{};0; rtt_ms={:.3}ms;;;0;",
-            state, offset, rtt, host, ip, offset, warn_str, crit_str, rtt
-        );
-
+        let report = plugin::single(all.samples(), args);
+        report.print(args.plugin_format);
+        let exit_code = report.code;
         let _ = io::stdout().flush();
         process::exit(exit_code);
     }
@@ -613,7 +1621,7 @@ async fn query_loop(target: &str, args: &LegacyArgs, term: &Term, timeout: Durat
         if !get_sys_permissions() || args.dry_run {
             no_sync = true;
         }
-        let probe = average_probe(&all);
+        let probe = average_probe(all.samples());
 
         match sync_from_probe(&probe, no_sync) {
             Ok(()) => {
@@ -625,10 +1633,15 @@ async fn query_loop(target: &str, args: &LegacyArgs, term: &Term, timeout: Durat
                 } else if args.count <= 1 {
                     let _ = term.write_line(&style("Sync applied").green().to_string());
                 } else {
+                    let filtered = if rejected > 0 {
+                        format!(" ({} of {} samples rejected by RTT)", rejected, probed)
+                    } else {
+                        String::new()
+                    };
                     let _ = term.write_line(
                         &style(format!(
-                            "Average offset Sync applied : {:.3} ms",
-                            probe.offset_ms
+                            "Average offset Sync applied : {:.3} ms{}",
+                            probe.offset_ms, filtered
                         ))
                         .green()
                         .to_string(),
@@ -659,67 +1672,147 @@ async fn query_loop(target: &str, args: &LegacyArgs, term: &Term, timeout: Durat
             }
         }
     }
+
+    let leaps = [(offsets[0].0.clone(), leap)];
+    let code = check_assertions(&offsets, &leaps, leap_table, args);
+    if gave_up { EXIT_GAVE_UP } else { code }
 }
 
-/// Emit a plugin-mode UNKNOWN status line with the provided thresholds
-fn emit_unknown(warning: Option<f64>, critical: Option<f64>) {
-    let warn_str = warning.map(|v| v.to_string()).unwrap_or_default();
-    let crit_str = critical.map(|v| v.to_string()).unwrap_or_default();
-    println!(
-        "RKIK UNKNOWN - request failed | offset_ms=;{};{};0; rtt_ms=;;;0;",
-        warn_str, crit_str
-    );
+/// Attach reverse DNS and GeoIP data to `results` when verbose output will show it.
+async fn enrich_results(results: &mut [ProbeResult], args: &LegacyArgs) {
+    #[cfg(feature = "enrich")]
+    if args.verbose > 0 && !args.plugin {
+        enrich::enrich(results).await;
+    }
+    #[cfg(not(feature = "enrich"))]
+    let _ = (results, args);
 }
 
-fn output(term: &Term, results: &[ProbeResult], fmt: OutputFormat, pretty: bool, verbose: bool) {
+fn output(
+    term: &Term,
+    results: &[ProbeResult],
+    fmt: OutputFormat,
+    pretty: bool,
+    verbose: bool,
+    fields: &[Field],
+) {
     match fmt {
         OutputFormat::Text => {
             if results.len() == 1 {
                 let s = fmt::text::render_probe(&results[0], verbose);
-                term.write_line(&s).ok();
+                emit_line(term, &s);
             } else {
                 let s = fmt::text::render_compare(results, verbose);
-                term.write_line(&s).ok();
+                emit_line(term, &s);
+            }
+        }
+        OutputFormat::Json => {
+            let encoded = if fields.is_empty() {
+                fmt::json::to_json(results, pretty, verbose)
+            } else {
+                fmt::json::to_json_fields(results, fields, pretty)
+            };
+            match encoded {
+                Ok(s) => emit_line(term, &s),
+                Err(e) => eprintln!("error serializing: {}", e),
+            }
+        }
+        OutputFormat::JsonShort => {
+            let encoded = if fields.is_empty() {
+                fmt::json::to_short_json(results, pretty)
+            } else {
+                fmt::json::to_short_json_fields(results, fields, pretty)
+            };
+            match encoded {
+                Ok(s) => emit_line(term, &s),
+                Err(e) => eprintln!("error serializing: {}", e),
             }
         }
-        OutputFormat::Json => match fmt::json::to_json(results, pretty, verbose) {
-            Ok(s) => println!("{}", s),
-            Err(e) => eprintln!("error serializing: {}", e),
-        },
-        OutputFormat::JsonShort => match fmt::json::to_short_json(results, pretty) {
-            Ok(s) => println!("{}", s),
-            Err(e) => eprintln!("error serializing: {}", e),
-        },
         OutputFormat::Simple => {
-            if results.len() == 1 {
-                let s = fmt::text::render_simple_probe(&results[0]);
-                term.write_line(&s).ok();
+            let s = if !fields.is_empty() {
+                fmt::text::render_fields_compare(results, fields)
+            } else if results.len() == 1 {
+                fmt::text::render_simple_probe(&results[0])
+            } else {
+                fmt::text::render_simple_compare(results)
+            };
+            emit_line(term, &s);
+        }
+        OutputFormat::Csv => {
+            let encoded = if fields.is_empty() {
+                fmt::csv::to_csv(results)
             } else {
-                let s = fmt::text::render_simple_compare(results);
-                term.write_line(&s).ok();
+                fmt::csv::to_csv_fields(results, fields)
+            };
+            match encoded {
+                Ok(s) => emit(&s),
+                Err(e) => eprintln!("error serializing: {}", e),
             }
         }
-        OutputFormat::Csv => match fmt::csv::to_csv(results) {
-            Ok(s) => print!("{}", s),
-            Err(e) => eprintln!("error serializing: {}", e),
-        },
+        OutputFormat::Markdown => emit(&fmt::markdown::to_markdown(results)),
+    }
+}
+
+/// Set by `--silent`: results, statistics and probe errors are dropped.
+static SILENT: AtomicBool = AtomicBool::new(false);
+
+/// Write a result line to the `--output` file when one is set, otherwise to the terminal.
+fn emit_line(term: &Term, s: &str) {
+    if SILENT.load(Ordering::Relaxed) {
+        return;
+    }
+    if !output_file::write(&format!("{s}\n")) {
+        term.write_line(s).ok();
+    }
+}
+
+/// Write raw result text (already newline-terminated) to the output file or stdout.
+fn emit(s: &str) {
+    if SILENT.load(Ordering::Relaxed) {
+        return;
+    }
+    if !output_file::write(s) {
+        print!("{s}");
+    }
+}
+
+fn csv_header(fields: &[Field]) -> String {
+    if fields.is_empty() {
+        fmt::csv::header()
+    } else {
+        fmt::csv::fields_header(fields)
+    }
+}
+
+fn csv_rows(results: &[ProbeResult], fields: &[Field]) -> Result<String, RkikError> {
+    if fields.is_empty() {
+        fmt::csv::rows(results)
+    } else {
+        fmt::csv::rows_fields(results, fields)
+    }
+}
+
+fn short_json_line(r: &ProbeResult, fields: &[Field]) -> Result<String, RkikError> {
+    if fields.is_empty() {
+        fmt::json::probe_to_short_json(r)
+    } else {
+        fmt::json::probe_to_fields_json(r, fields)
     }
 }
 
 fn handle_error(term: &Term, err: RkikError, fmt: OutputFormat, pretty: bool) -> i32 {
+    if SILENT.load(Ordering::Relaxed) {
+        return err.exit_code();
+    }
     match fmt {
         OutputFormat::Json | OutputFormat::JsonShort => {
             #[cfg(feature = "json")]
-            match err.to_json_string(pretty) {
-                Ok(s) => println!("{}", s),
-                Err(_) => {
-                    term.write_line(&style(format!("Error: {}", err)).red().to_string())
-                        .ok();
-                }
+            match fmt::json::error_to_json(&err, pretty) {
+                Ok(s) => eprintln!("{}", s),
+                Err(_) => eprintln!("Error: {}", err),
             }
             #[cfg(not(feature = "json"))]
-            term.write_line(&style(format!("Error: {}", err)).red().to_string())
-                .ok();
+            eprintln!("Error: {}", err);
         }
         _ => {
             term.write_line(&style(format!("Error: {}", err)).red().to_string())
@@ -727,16 +1820,403 @@ fn handle_error(term: &Term, err: RkikError, fmt: OutputFormat, pretty: bool) ->
         }
     }
 
-    if err.is_dns() {
-        2
-    } else if err.is_network_timeout() || err.is_nts() {
-        3
-    } else {
-        1
+    err.exit_code()
+}
+
+/// Parse `--interval-jitter`: a percentage (`10%`) or a fraction (`0.1`)
+/// of the interval, at most 100%.
+pub fn parse_jitter(s: &str) -> Result<f64, String> {
+    let value = match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => s.parse::<f64>(),
+    };
+    match value {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+        _ => Err(format!(
+            "'{s}' is not a share of the interval between 0% and 100%"
+        )),
     }
 }
 
+pub fn parse_rtt_filter(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v.is_finite() && v >= 1.0 => Ok(v),
+        _ => Err(format!("'{s}' is not a factor >= 1")),
+    }
+}
+
+/// Successful probes, failed targets and the position of the reference among the probes.
+type Round = (Vec<ProbeResult>, Vec<(String, RkikError)>, Option<usize>);
+
+/// Split a compare round into probes and failures, and locate the
+/// `--reference` among the probes. A failed reference fails the round:
+/// the other offsets cannot be expressed relative to it.
+fn split_round(outcome: CompareOutcome, reference: Option<usize>) -> Result<Round, RkikError> {
+    let position = match reference {
+        Some(i) if outcome.entries[i].1.is_err() => {
+            let (_, failed) = outcome.entries.into_iter().nth(i).expect("reference index");
+            return Err(failed.expect_err("reference failed"));
+        }
+        Some(i) => Some(
+            outcome.entries[..i]
+                .iter()
+                .filter(|(_, r)| r.is_ok())
+                .count(),
+        ),
+        None => None,
+    };
+    let (results, failures) = outcome.into_parts();
+    Ok((results, failures, position))
+}
+
+/// Show the servers that did not answer in a compare round that still succeeded.
+fn report_failures(term: &Term, failures: &[(String, RkikError)], args: &LegacyArgs) {
+    if args.silent {
+        return;
+    }
+    for (_, err) in failures {
+        match args.format {
+            OutputFormat::Json | OutputFormat::JsonShort => {
+                #[cfg(feature = "json")]
+                if let Ok(s) = fmt::json::error_to_json(err, args.pretty) {
+                    eprintln!("{}", s);
+                    continue;
+                }
+                eprintln!("Error: {}", err);
+            }
+            OutputFormat::Csv | OutputFormat::Markdown => eprintln!("Error: {}", err),
+            _ => emit_line(term, &style(format!("Error: {}", err)).red().to_string()),
+        }
+    }
+}
+
+/// `target` once per port of `--ports`, each probed as its own server.
+fn on_ports(target: &str, ports: &[u16]) -> Result<Vec<String>, String> {
+    if target == LOCAL_TARGET || target == CHRONYD_TARGET {
+        return Err(format!("'{}' has no port", target));
+    }
+    let mut targets = Vec::with_capacity(ports.len());
+    for &port in ports {
+        let target = with_port(target, port).map_err(|e| e.to_string())?;
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    Ok(targets)
+}
+
+/// Reference age `--require-sync` accepts without `--max-reference-age`.
+const DEFAULT_MAX_REFERENCE_AGE: Duration = Duration::from_secs(86_400);
+
+/// `options` refusing unsynchronized servers under `--require-sync`.
+fn require_sync(options: QueryOptions, args: &LegacyArgs) -> QueryOptions {
+    if !args.require_sync {
+        return options;
+    }
+    options.require_sync(
+        args.max_reference_age
+            .map_or(DEFAULT_MAX_REFERENCE_AGE, Duration::from_secs_f64),
+    )
+}
+
+/// Exit code of a repeated run abandoned by `--max-failures`.
+const EXIT_GAVE_UP: i32 = 5;
+/// A compare in which `--require-sync` refused a server.
+const EXIT_UNSYNCHRONIZED: i32 = 6;
+
+/// Tell on stderr that `target` failed `max` times in a row and the run
+/// stops.
+fn report_give_up(target: &str, max: u32, args: &LegacyArgs) {
+    if args.silent {
+        return;
+    }
+    let message = format!(
+        "giving up on {} after {} consecutive failure{}",
+        target,
+        max,
+        if max == 1 { "" } else { "s" }
+    );
+    match args.format {
+        OutputFormat::Text | OutputFormat::Simple => {
+            Term::stderr()
+                .write_line(&style(format!("Error: {}", message)).red().to_string())
+                .ok();
+        }
+        #[cfg(feature = "json")]
+        OutputFormat::Json | OutputFormat::JsonShort => eprintln!(
+            "{}",
+            serde_json::json!({
+                "schema_version": fmt::json::SCHEMA_VERSION,
+                "error": {"kind": "gave_up", "message": message, "target": target},
+            })
+        ),
+        _ => eprintln!("Error: {}", message),
+    }
+}
+
+/// The pacing `--interval`, `--min-interval` and `--interval-jitter` ask for.
+fn pace(args: &LegacyArgs) -> Pacer {
+    let min_interval = Duration::from_secs_f64(args.min_interval.unwrap_or(0.0));
+    match args.interval {
+        Interval::Seconds(s) => Pacer::new(Duration::from_secs_f64(s), min_interval),
+        Interval::Auto => Pacer::adaptive(min_interval),
+    }
+    .with_jitter(args.interval_jitter.unwrap_or(0.0))
+}
+
+/// Show the applied pacing on stderr in verbose mode.
+fn report_pacing(pacer: &Pacer, args: &LegacyArgs) {
+    if args.verbose == 0 || args.plugin {
+        return;
+    }
+    let note = format!(
+        "Pacing: one query every {:.1} s{}",
+        pacer.interval().as_secs_f64(),
+        if pacer.is_adaptive() {
+            " (adaptive)"
+        } else {
+            ""
+        }
+    );
+    Term::stderr()
+        .write_line(&style(note).dim().to_string())
+        .ok();
+}
+
+/// Warn on stderr that `target` asked us to slow down.
+fn report_backoff(target: &str, pacer: &Pacer, args: &LegacyArgs) {
+    if args.plugin || args.silent {
+        return;
+    }
+    let warning = format!(
+        "Warning: {} sent a RATE Kiss-o'-Death; slowing down to one query every {:.0} s",
+        target,
+        pacer.interval().as_secs_f64()
+    );
+    Term::stderr()
+        .write_line(&style(warning).yellow().to_string())
+        .ok();
+}
+
+/// Warn on stderr that the local clock was stepped since the previous
+/// sample of a repeated run.
+fn report_clock_step(step_ms: Option<f64>, args: &LegacyArgs) {
+    let Some(step_ms) = step_ms else {
+        return;
+    };
+    if args.plugin || args.silent {
+        return;
+    }
+    let warning = format!(
+        "Warning: the local clock was stepped by {} since the previous sample; this sample is left out of the statistics",
+        format_ms(step_ms)
+    );
+    Term::stderr()
+        .write_line(&style(warning).yellow().to_string())
+        .ok();
+}
+
+/// Warn on stderr in verbose mode about the replies the NTP client dropped
+/// as spoofed-looking since the last call.
+fn report_rejected(args: &LegacyArgs) {
+    let rejected = ntp_client::take_rejected();
+    if args.verbose == 0 || args.plugin {
+        return;
+    }
+    let stderr = Term::stderr();
+    for r in rejected {
+        let warning = format!(
+            "Warning: dropped a reply to {} from {}: {} (possible spoofing)",
+            r.server, r.from, r.reason
+        );
+        stderr.write_line(&style(warning).yellow().to_string()).ok();
+    }
+}
+
+/// Warn on stderr, once per server, about replies that fail the sanity
+/// checks; verbose output already shows the health of each probe.
+fn report_health(results: &[ProbeResult], reported: &mut BTreeSet<String>, args: &LegacyArgs) {
+    if args.verbose > 0 || args.silent {
+        return;
+    }
+    let stderr = Term::stderr();
+    for r in results {
+        if r.health.verdict == HealthVerdict::Ok || !reported.insert(r.target.name.clone()) {
+            continue;
+        }
+        let issues: Vec<String> = r.health.issues.iter().map(ToString::to_string).collect();
+        let warning = format!(
+            "Warning: {} looks {}: {}",
+            r.target.name,
+            r.health.verdict.as_str(),
+            issues.join(", ")
+        );
+        stderr.write_line(&style(warning).yellow().to_string()).ok();
+    }
+}
+
+/// Warn on stderr, once per server, about servers smearing a leap second and,
+/// with `--detect-smear`, about servers known to smear.
+fn report_smear(
+    results: &[ProbeResult],
+    table: &LeapTable,
+    reported: &mut BTreeSet<String>,
+    args: &LegacyArgs,
+) {
+    if args.silent {
+        return;
+    }
+    let stderr = Term::stderr();
+    for (name, smear) in detect_smear(results, table, chrono::Utc::now()) {
+        let warning = match smear {
+            Smear::Observed {
+                relative_ms,
+                expected_ms,
+            } => format!(
+                "Warning: {} looks leap-smeared: {} from the other servers, a 24 h smear predicts {}; do not compare it with them until the smear ends",
+                name,
+                format_ms(relative_ms),
+                format_ms(expected_ms)
+            ),
+            Smear::Known if args.detect_smear => format!(
+                "Warning: {} smears leap seconds; around one it drifts up to 500 ms from servers that step",
+                name
+            ),
+            Smear::Known => continue,
+        };
+        if reported.insert(name) {
+            stderr.write_line(&style(warning).yellow().to_string()).ok();
+        }
+    }
+}
+
+/// Print the events of `--diverge-threshold`: on stdout in the text and
+/// JSON formats, on stderr in the others so that rows stay parseable.
+fn report_divergence(term: &Term, events: &[DivergenceEvent], args: &LegacyArgs) {
+    if args.silent {
+        return;
+    }
+    for event in events {
+        match args.format {
+            OutputFormat::Text => emit_line(term, &fmt::text::render_divergence(event)),
+            #[cfg(feature = "json")]
+            OutputFormat::Json | OutputFormat::JsonShort => {
+                match fmt::json::divergence_to_json(event, args.pretty) {
+                    Ok(s) => emit_line(term, &s),
+                    Err(e) => eprintln!("error serializing: {}", e),
+                }
+            }
+            _ => {
+                Term::stderr()
+                    .write_line(&fmt::text::render_divergence(event))
+                    .ok();
+            }
+        }
+    }
+}
+
+/// Check `--assert-max-offset` and `--assert-max-drift` against the average
+/// offset of each server, and `--assert-leap` against the last leap indicator
+/// of each server and the kernel's leap state. Violations are printed on
+/// stderr so that stdout stays parseable; returns the exit code (4 if any
+/// assertion failed).
+fn check_assertions(
+    offsets: &[(String, f64)],
+    leaps: &[(String, Leap)],
+    leap_table: Option<&LeapTable>,
+    args: &LegacyArgs,
+) -> i32 {
+    let mut violations = Vec::new();
+    if let Some(limit) = args.assert_max_offset {
+        for (name, offset) in offsets {
+            if offset.abs() > limit {
+                violations.push(format!(
+                    "offset of {} is {} (limit {})",
+                    name,
+                    format_ms(*offset),
+                    format_ms(limit)
+                ));
+            }
+        }
+    }
+    if let Some(limit) = args.assert_max_drift
+        && offsets.len() > 1
+    {
+        let (mut lo, mut hi) = (&offsets[0], &offsets[0]);
+        for entry in offsets {
+            if entry.1 < lo.1 {
+                lo = entry;
+            }
+            if entry.1 > hi.1 {
+                hi = entry;
+            }
+        }
+        if hi.1 - lo.1 > limit {
+            violations.push(format!(
+                "{} and {} differ by {} (limit {})",
+                hi.0,
+                lo.0,
+                format_ms(hi.1 - lo.1),
+                format_ms(limit)
+            ));
+        }
+    }
+    let stderr = Term::stderr();
+    if let Some(table) = leap_table {
+        let now = chrono::Utc::now();
+        if !table.covers(now) {
+            stderr
+                .write_line(
+                    &style(format!(
+                        "Warning: leap second table expired on {}; only unsynchronised servers are flagged (pass --leap-file with a current leap-seconds.list)",
+                        table.expires().format("%Y-%m-%d")
+                    ))
+                    .yellow()
+                    .to_string(),
+                )
+                .ok();
+        }
+        for (name, leap) in leaps {
+            if let Some(problem) = check_indicator(*leap, table, now) {
+                violations.push(format!("{} {}", name, problem));
+            }
+        }
+        // Only readable on Linux with the sync feature.
+        if let Ok(clock) = kernel::read() {
+            for problem in check_kernel(&clock, table, now) {
+                violations.push(format!("kernel {}", problem));
+            }
+        }
+    }
+    for v in &violations {
+        stderr
+            .write_line(&style(format!("Assertion failed: {}", v)).red().to_string())
+            .ok();
+    }
+    if violations.is_empty() { 0 } else { 4 }
+}
+
 //--plugin checks
+/// Color text output by the plugin thresholds (`--highlight`).
+fn install_highlight(args: &LegacyArgs) {
+    let offset = (args.warning, args.critical);
+    let rtt = (args.warning_rtt, args.critical_rtt);
+    highlight::set_highlight(move |metric, value| {
+        let (warning, critical) = match metric {
+            Metric::Offset => offset,
+            Metric::Rtt => rtt,
+        };
+        if warning.is_none() && critical.is_none() {
+            return None;
+        }
+        Some(match plugin::level(value, warning, critical) {
+            2 => Severity::Critical,
+            1 => Severity::Warning,
+            _ => Severity::Ok,
+        })
+    });
+}
+
 fn plugin_conflict(flag: &str, term: &Term) {
     term.write_line(
         &style(format!("--plugin cannot be used with --{}", flag))
@@ -748,11 +2228,90 @@ fn plugin_conflict(flag: &str, term: &Term) {
     process::exit(2);
 }
 
+/// Probes of one server.
+///
+/// Bounded runs keep every sample: plugin checks, `--sync` and the two-pass
+/// `--rtt-filter` need them. `--infinite` runs only feed an [`Accumulator`]
+/// so memory stays constant however long they last.
+enum Series {
+    Samples(Vec<ProbeResult>),
+    Stream(Box<Accumulator>),
+}
+
+impl Series {
+    fn new(args: &LegacyArgs) -> Self {
+        if args.infinite {
+            Series::Stream(Box::new(
+                args.rtt_filter
+                    .map_or_else(Accumulator::new, Accumulator::with_rtt_filter),
+            ))
+        } else {
+            Series::Samples(Vec::new())
+        }
+    }
+
+    /// Samples taken across a step of the local clock are left out.
+    fn push(&mut self, res: ProbeResult) {
+        if res.clock_step_ms.is_some() {
+            return;
+        }
+        match self {
+            Series::Samples(v) => v.push(res),
+            Series::Stream(acc) => {
+                acc.push(&res);
+            }
+        }
+    }
+
+    /// Number of successful probes, including those the RTT filter rejects.
+    fn probed(&self) -> usize {
+        match self {
+            Series::Samples(v) => v.len(),
+            Series::Stream(acc) => acc.count() + acc.rejected(),
+        }
+    }
+
+    /// Apply the RTT filter (in place for kept samples) and compute statistics.
+    fn finish(&mut self, rtt_filter: Option<f64>) -> Stats {
+        match self {
+            Series::Samples(v) => {
+                let mut rejected = 0;
+                if let Some(factor) = rtt_filter {
+                    (*v, rejected) = filter_by_rtt(std::mem::take(v), factor);
+                }
+                let mut stats = compute_stats(v);
+                stats.rejected = rejected;
+                stats
+            }
+            Series::Stream(acc) => acc.stats(),
+        }
+    }
+
+    /// Retained samples; always empty under `--infinite`.
+    fn samples(&self) -> &[ProbeResult] {
+        match self {
+            Series::Samples(v) => v,
+            Series::Stream(_) => &[],
+        }
+    }
+
+    fn into_samples(self) -> Vec<ProbeResult> {
+        match self {
+            Series::Samples(v) => v,
+            Series::Stream(_) => Vec::new(),
+        }
+    }
+}
+
 #[cfg(feature = "sync")]
 fn average_probe(results: &[ProbeResult]) -> ProbeResult {
     let mut avg = results.last().cloned().unwrap();
     avg.offset_ms = results.iter().map(|r| r.offset_ms).sum::<f64>() / results.len() as f64;
     avg.rtt_ms = results.iter().map(|r| r.rtt_ms).sum::<f64>() / results.len() as f64;
+    avg.uncertainty_ms = results
+        .iter()
+        .filter_map(|r| Some(r.uncertainty_ms? + (avg.offset_ms - r.offset_ms).abs()))
+        .min_by(f64::total_cmp);
     if let Some(min_stratum) = results.iter().map(|r| r.stratum).min() {
         avg.stratum = min_stratum;
     }
@@ -0,0 +1,157 @@
+//! `rkik local`: what the resident time daemon believes, read over
+//! chronyd's command socket (the view `chronyc tracking` and `chronyc
+//! sources` give), ntpd's mode 6 (`ntpq -c rv` and `ntpq -p`) or, on
+//! Windows, the W32Time service.
+
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use console::set_colors_enabled;
+use rkik::RkikError;
+use rkik::adapters::chrony::{ChronyClient, Endpoint, Source, Tracking};
+use rkik::adapters::kernel;
+use rkik::adapters::ntpd::{NtpdClient, Peer, SystemVars};
+#[cfg(windows)]
+use rkik::adapters::w32time;
+
+/// Parse `--chronyd`: a UDP `HOST:PORT` address, else a Unix socket path.
+pub fn parse_endpoint(s: &str) -> Result<Endpoint, String> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(Endpoint::Udp(addr));
+    }
+    #[cfg(unix)]
+    {
+        Ok(Endpoint::Unix(s.into()))
+    }
+    #[cfg(not(unix))]
+    {
+        Err(format!("invalid chronyd address '{s}' (use HOST:PORT)"))
+    }
+}
+
+/// Which daemon `rkik local` asks.
+pub enum Daemon {
+    /// The platform's usual daemon, else ntpd (see [`auto`]).
+    Auto,
+    Chronyd(Endpoint),
+    Ntpd(SocketAddr),
+}
+
+enum Report {
+    Chronyd(Tracking, Vec<Source>),
+    Ntpd(SystemVars, Vec<Peer>),
+    #[cfg(windows)]
+    W32Time(rkik::adapters::w32time::W32TimeStatus),
+}
+
+async fn chronyd(client: ChronyClient) -> Result<Report, RkikError> {
+    Ok(Report::Chronyd(
+        client.tracking().await?,
+        client.sources().await?,
+    ))
+}
+
+async fn ntpd(client: NtpdClient) -> Result<Report, RkikError> {
+    Ok(Report::Ntpd(client.system().await?, client.peers().await?))
+}
+
+/// The platform's usual daemon (W32Time on Windows, chronyd elsewhere),
+/// else ntpd on 127.0.0.1:123.
+async fn auto(timeout: Duration) -> Result<Report, RkikError> {
+    #[cfg(windows)]
+    let (name, first) = ("w32time", w32time::status().map(Report::W32Time));
+    #[cfg(not(windows))]
+    let (name, first) = (
+        "chronyd",
+        chronyd(ChronyClient::new().timeout(timeout)).await,
+    );
+    match first {
+        Ok(report) => Ok(report),
+        Err(first_err) => ntpd(NtpdClient::new().timeout(timeout))
+            .await
+            .map_err(|ntpd_err| {
+                RkikError::Network(format!(
+                    "no local time daemon answered ({name}: {}; ntpd: {})",
+                    first_err.message(),
+                    ntpd_err.message()
+                ))
+            }),
+    }
+}
+
+pub async fn run(
+    daemon: Daemon,
+    timeout: Duration,
+    with_kernel: bool,
+    json: bool,
+    pretty: bool,
+    no_color: bool,
+) -> Result<(), String> {
+    set_colors_enabled(
+        !json
+            && !no_color
+            && std::io::stdout().is_terminal()
+            && std::env::var_os("NO_COLOR").is_none(),
+    );
+    let report = match daemon {
+        Daemon::Chronyd(endpoint) => {
+            chronyd(ChronyClient::with_endpoint(endpoint).timeout(timeout)).await
+        }
+        Daemon::Ntpd(addr) => ntpd(NtpdClient::with_addr(addr).timeout(timeout)).await,
+        Daemon::Auto => auto(timeout).await,
+    };
+    let kernel = if with_kernel {
+        Some(kernel::read().map_err(|e| e.to_string())?)
+    } else {
+        None
+    };
+    // The kernel section stands on its own when no daemon answers.
+    let report = match (report, &kernel) {
+        (Ok(report), _) => Some(report),
+        (Err(e), Some(_)) => {
+            eprintln!("Warning: {e}");
+            None
+        }
+        (Err(e), None) => return Err(e.to_string()),
+    };
+    if !json {
+        match &report {
+            Some(Report::Chronyd(t, s)) => print!("{}", rkik::fmt::text::render_chronyd(t, s)),
+            Some(Report::Ntpd(v, p)) => print!("{}", rkik::fmt::text::render_ntpd(v, p)),
+            #[cfg(windows)]
+            Some(Report::W32Time(w)) => print!("{}", rkik::fmt::text::render_w32time(w)),
+            None => {}
+        }
+        if let Some(k) = &kernel {
+            if report.is_some() {
+                println!();
+            }
+            print!("{}", rkik::fmt::text::render_kernel(k));
+        }
+        return Ok(());
+    }
+    #[cfg(feature = "json")]
+    {
+        let text = match (&report, &kernel) {
+            (Some(Report::Chronyd(t, s)), k) => {
+                rkik::fmt::json::chronyd_to_json(t, s, k.as_ref(), pretty)
+            }
+            (Some(Report::Ntpd(v, p)), k) => {
+                rkik::fmt::json::ntpd_to_json(v, p, k.as_ref(), pretty)
+            }
+            #[cfg(windows)]
+            (Some(Report::W32Time(w)), _) => rkik::fmt::json::w32time_to_json(w, pretty),
+            (None, Some(k)) => rkik::fmt::json::kernel_to_json(k, pretty),
+            (None, None) => unreachable!("a daemon failure without --kernel returned early"),
+        }
+        .map_err(|e| e.to_string())?;
+        println!("{text}");
+        Ok(())
+    }
+    #[cfg(not(feature = "json"))]
+    {
+        let _ = pretty;
+        Err("JSON output requires the json feature".into())
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt::Write as FmtWrite;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use rkik::{ProbeResult, RkikError};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::Registry;
+
+/// Where `--log-target` sends structured probe logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogTarget {
+    Stderr,
+    Syslog,
+    Journald,
+}
+
+/// Diagnostic tracing asked for with `-vv`/`-vvv` or `--log-file`.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    /// Number of `-v` flags.
+    pub verbosity: u8,
+    /// Write diagnostics to this file instead of stderr.
+    pub log_file: Option<PathBuf>,
+}
+
+impl Diagnostics {
+    /// Filter level for rkik's own events: `-vv` shows the DNS, socket and
+    /// NTS stages, `-vvv` every packet. `--log-file` alone captures the
+    /// former; a single `-v` only changes the human output.
+    pub fn level(&self) -> Option<LevelFilter> {
+        match self.verbosity {
+            0 | 1 if self.log_file.is_some() => Some(LevelFilter::DEBUG),
+            0 | 1 => None,
+            2 => Some(LevelFilter::DEBUG),
+            _ => Some(LevelFilter::TRACE),
+        }
+    }
+
+    fn on_stderr(&self) -> bool {
+        self.level().is_some() && self.log_file.is_none()
+    }
+
+    fn layer<S>(&self) -> Result<Option<Box<dyn Layer<S> + Send + Sync>>, String>
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        let Some(level) = self.level() else {
+            return Ok(None);
+        };
+        // Dependencies (TLS, DNS) stay at warnings; their debug output
+        // drowns rkik's own.
+        let filter = Targets::new()
+            .with_target("rkik", level)
+            .with_default(LevelFilter::WARN);
+        let layer = tracing_subscriber::fmt::layer().with_target(false);
+        Ok(Some(match &self.log_file {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("cannot open log file {}: {}", path.display(), e))?;
+                layer
+                    .with_writer(Mutex::new(file))
+                    .with_ansi(false)
+                    .with_filter(filter)
+                    .boxed()
+            }
+            None => layer
+                .with_writer(std::io::stderr)
+                .with_ansi(false)
+                .with_filter(filter)
+                .boxed(),
+        }))
+    }
+}
+
+/// Install the global tracing subscriber: structured probe logs for
+/// `target` and the diagnostics layer, whichever are asked for.
+pub fn init(target: Option<LogTarget>, diagnostics: &Diagnostics) -> Result<(), String> {
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+    if let Some(layer) = diagnostics.layer()? {
+        layers.push(layer);
+    }
+    match target {
+        None => {}
+        // The diagnostics layer already prints probe events on stderr.
+        Some(LogTarget::Stderr) if diagnostics.on_stderr() => {}
+        Some(LogTarget::Stderr) => layers.push(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(false)
+                .with_filter(LevelFilter::INFO)
+                .boxed(),
+        ),
+        #[cfg(unix)]
+        Some(LogTarget::Syslog) => layers.push(unix::SyslogLayer::connect()?.boxed()),
+        #[cfg(unix)]
+        Some(LogTarget::Journald) => layers.push(unix::JournaldLayer::connect()?.boxed()),
+        #[cfg(not(unix))]
+        Some(other) => {
+            return Err(format!(
+                "--log-target {:?} is only supported on Unix",
+                other
+            ));
+        }
+    }
+    if layers.is_empty() {
+        return Ok(());
+    }
+    tracing::subscriber::set_global_default(Registry::default().with(layers))
+        .map_err(|e| e.to_string())
+}
+
+/// Log a successful probe with structured fields.
+pub fn probe_ok(r: &ProbeResult) {
+    tracing::info!(
+        server = %r.target.name,
+        ip = %r.target.ip,
+        offset_ms = r.offset_ms,
+        rtt_ms = r.rtt_ms,
+        stratum = r.stratum,
+        authenticated = r.authenticated,
+        result = "ok",
+        "probe succeeded"
+    );
+}
+
+/// Log a failed probe with structured fields.
+pub fn probe_failed(server: &str, err: &RkikError) {
+    tracing::warn!(
+        server = %err.target().unwrap_or(server),
+        result = "error",
+        kind = err.kind(),
+        error = %err.message(),
+        "probe failed"
+    );
+}
+
+/// Collects the message and key/value fields of an event.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    pairs: Vec<(&'static str, String)>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.pairs.push((field.name(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.pairs.push((field.name(), format!("{:?}", value)));
+        }
+    }
+}
+
+impl Fields {
+    fn from_event(event: &Event<'_>) -> Self {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        fields
+    }
+
+    /// `message key=value ...` with values quoted when needed.
+    fn logfmt(&self) -> String {
+        let mut out = self.message.clone();
+        for (k, v) in &self.pairs {
+            if v.is_empty() || v.contains([' ', '"', '=']) {
+                let _ = write!(out, " {}=\"{}\"", k, v.replace('"', "\\\""));
+            } else {
+                let _ = write!(out, " {}={}", k, v);
+            }
+        }
+        out
+    }
+}
+
+/// Syslog severity for a tracing level.
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        _ => 7,
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    const SYSLOG_SOCKETS: [&str; 3] = ["/dev/log", "/var/run/syslog", "/var/run/log"];
+    const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+    /// LOG_DAEMON
+    const FACILITY: u8 = 3;
+
+    /// Sends RFC 3164 messages to the local syslog socket.
+    pub struct SyslogLayer {
+        socket: UnixDatagram,
+    }
+
+    impl SyslogLayer {
+        pub fn connect() -> Result<Self, String> {
+            let socket = UnixDatagram::unbound().map_err(|e| e.to_string())?;
+            for path in SYSLOG_SOCKETS {
+                if socket.connect(path).is_ok() {
+                    return Ok(Self { socket });
+                }
+            }
+            Err("no syslog socket found (tried /dev/log, /var/run/syslog, /var/run/log)".into())
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for SyslogLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let meta = event.metadata();
+            if *meta.level() > Level::INFO {
+                return;
+            }
+            let fields = Fields::from_event(event);
+            let line = format!(
+                "<{}>{} rkik[{}]: {}",
+                FACILITY * 8 + severity(meta.level()),
+                chrono::Local::now().format("%b %e %H:%M:%S"),
+                std::process::id(),
+                fields.logfmt()
+            );
+            let _ = self.socket.send(line.as_bytes());
+        }
+    }
+
+    /// Sends events to systemd-journald using its native protocol, one
+    /// journal field per tracing field (upper-cased).
+    pub struct JournaldLayer {
+        socket: UnixDatagram,
+    }
+
+    impl JournaldLayer {
+        pub fn connect() -> Result<Self, String> {
+            let socket = UnixDatagram::unbound().map_err(|e| e.to_string())?;
+            socket
+                .connect(JOURNALD_SOCKET)
+                .map_err(|e| format!("cannot reach journald at {}: {}", JOURNALD_SOCKET, e))?;
+            Ok(Self { socket })
+        }
+    }
+
+    fn put(buf: &mut Vec<u8>, key: &str, value: &str) {
+        buf.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            buf.push(b'\n');
+            buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            buf.push(b'=');
+        }
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    }
+
+    /// Journal field names are upper-case ASCII letters, digits and underscores.
+    fn journal_key(name: &str) -> String {
+        name.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>()
+            .trim_start_matches('_')
+            .to_string()
+    }
+
+    pub(super) fn journal_payload(level: &Level, fields: &Fields) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put(&mut buf, "MESSAGE", &fields.logfmt());
+        put(&mut buf, "PRIORITY", &severity(level).to_string());
+        put(&mut buf, "SYSLOG_IDENTIFIER", "rkik");
+        for (k, v) in &fields.pairs {
+            let key = journal_key(k);
+            if !key.is_empty() {
+                put(&mut buf, &key, v);
+            }
+        }
+        buf
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for JournaldLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let meta = event.metadata();
+            if *meta.level() > Level::INFO {
+                return;
+            }
+            let payload = journal_payload(meta.level(), &Fields::from_event(event));
+            let _ = self.socket.send(&payload);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_maps_to_diagnostic_levels() {
+        let level = |verbosity, file: Option<&str>| {
+            Diagnostics {
+                verbosity,
+                log_file: file.map(PathBuf::from),
+            }
+            .level()
+        };
+        assert_eq!(level(0, None), None);
+        assert_eq!(level(1, None), None);
+        assert_eq!(level(2, None), Some(LevelFilter::DEBUG));
+        assert_eq!(level(3, None), Some(LevelFilter::TRACE));
+        assert_eq!(level(0, Some("rkik.log")), Some(LevelFilter::DEBUG));
+        assert_eq!(level(3, Some("rkik.log")), Some(LevelFilter::TRACE));
+    }
+
+    #[test]
+    fn journal_payload_uppercases_fields_and_frames_multiline_values() {
+        let fields = Fields {
+            message: "probe failed".into(),
+            pairs: vec![
+                ("server", "time.example".into()),
+                ("error", "line1\nline2".into()),
+            ],
+        };
+        let payload = unix::journal_payload(&Level::WARN, &fields);
+        let text = String::from_utf8_lossy(&payload);
+        assert!(text.contains("PRIORITY=4\n"));
+        assert!(text.contains("SERVER=time.example\n"));
+        assert!(text.contains("SYSLOG_IDENTIFIER=rkik\n"));
+        let mut framed = b"ERROR\n".to_vec();
+        framed.extend_from_slice(&11u64.to_le_bytes());
+        framed.extend_from_slice(b"line1\nline2\n");
+        assert!(
+            payload
+                .windows(framed.len())
+                .any(|w| w == framed.as_slice())
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rkik::ProbeResult;
+use rkik::fmt::otlp::{self, RttHistogram};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Parsed `http://host:port/path` collector endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Endpoint {
+    /// Parse an OTLP/HTTP endpoint. A bare `host:port` gets `/v1/metrics` appended.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let rest = match s.split_once("://") {
+            Some(("http", rest)) => rest,
+            Some((scheme, _)) => {
+                return Err(format!(
+                    "unsupported OTLP scheme '{scheme}' (only http:// is supported; use a local collector for TLS)"
+                ));
+            }
+            None => s,
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        let parse_port = |p: &str| {
+            p.parse::<u16>()
+                .map_err(|_| format!("invalid port in OTLP endpoint '{s}'"))
+        };
+        let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+            let (host, tail) = v6
+                .split_once(']')
+                .ok_or_else(|| format!("unterminated IPv6 address in OTLP endpoint '{s}'"))?;
+            match tail.strip_prefix(':') {
+                Some(p) => (host, parse_port(p)?),
+                None => (host, 4318),
+            }
+        } else {
+            match authority.split_once(':') {
+                Some((host, p)) => (host, parse_port(p)?),
+                None => (authority, 4318),
+            }
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in OTLP endpoint '{s}'"));
+        }
+        let path = if path.is_empty() || path == "/" {
+            "/v1/metrics".to_string()
+        } else {
+            path.to_string()
+        };
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+
+    /// `host:port`, with IPv6 literals in brackets, as sent in `Host`.
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Parse a `KEY=VALUE` resource attribute.
+pub fn parse_attribute(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_string(), v.to_string())),
+        _ => Err(format!("invalid attribute '{s}' (expected KEY=VALUE)")),
+    }
+}
+
+struct Sink {
+    endpoint: Endpoint,
+    resource: Vec<(String, String)>,
+    histograms: BTreeMap<String, RttHistogram>,
+    timeout: Duration,
+}
+
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+/// Enable OTLP export for the rest of the process.
+pub fn install(endpoint: Endpoint, mut resource: Vec<(String, String)>, timeout: Duration) {
+    if !resource.iter().any(|(k, _)| k == "host.name") {
+        resource.insert(0, ("host.name".into(), hostname()));
+    }
+    if let Ok(mut guard) = SINK.lock() {
+        *guard = Some(Sink {
+            endpoint,
+            resource,
+            histograms: BTreeMap::new(),
+            timeout,
+        });
+    }
+}
+
+/// Push one round of results to the collector. Failures are reported on stderr
+/// and never abort the run.
+pub async fn push(results: &[ProbeResult]) {
+    let prepared = {
+        let Ok(mut guard) = SINK.lock() else {
+            return;
+        };
+        let Some(sink) = guard.as_mut() else {
+            return;
+        };
+        let now = chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64;
+        for r in results {
+            sink.histograms
+                .entry(r.target.name.clone())
+                .or_insert_with(|| RttHistogram::new(now))
+                .record(r.rtt_ms);
+        }
+        otlp::to_otlp_json(results, &sink.histograms, &sink.resource)
+            .map(|body| (sink.endpoint.clone(), body, sink.timeout))
+    };
+    let result = match prepared {
+        Ok((endpoint, body, timeout)) => {
+            match tokio::time::timeout(timeout, post(&endpoint, &body)).await {
+                Ok(r) => r,
+                Err(_) => Err("timed out".into()),
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = result {
+        eprintln!("warning: OTLP export failed: {}", e);
+    }
+}
+
+async fn post(endpoint: &Endpoint, body: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect((endpoint.host.as_str(), endpoint.port))
+        .await
+        .map_err(|e| format!("{}: {}", endpoint.authority(), e))?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        endpoint.path,
+        endpoint.authority(),
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .await
+        .map_err(|e| e.to_string())?;
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(format!("collector replied '{}'", status.trim())),
+    }
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_else(|| "unknown".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_defaults_port_and_path() {
+        assert_eq!(
+            Endpoint::parse("http://collector").unwrap(),
+            Endpoint {
+                host: "collector".into(),
+                port: 4318,
+                path: "/v1/metrics".into()
+            }
+        );
+        let ep = Endpoint::parse("localhost:9000/otlp/v1/metrics").unwrap();
+        assert_eq!((ep.port, ep.path.as_str()), (9000, "/otlp/v1/metrics"));
+        assert_eq!(Endpoint::parse("http://[::1]:4319").unwrap().host, "::1");
+        assert!(Endpoint::parse("https://collector").is_err());
+    }
+
+    #[test]
+    fn authority_brackets_ipv6_hosts() {
+        let authority = |s: &str| Endpoint::parse(s).unwrap().authority();
+        assert_eq!(authority("http://[::1]:4319"), "[::1]:4319");
+        assert_eq!(authority("collector"), "collector:4318");
+        assert_eq!(authority("127.0.0.1:9000"), "127.0.0.1:9000");
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use chrono::{Datelike, Local, Timelike};
+
+/// When to rotate the `--output` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotatePolicy {
+    /// Rotate once the file would exceed this many bytes.
+    Size(u64),
+    /// Rotate when the local hour changes.
+    Hourly,
+    /// Rotate when the local date changes.
+    Daily,
+}
+
+impl FromStr for RotatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw = s.trim().to_ascii_lowercase();
+        match raw.as_str() {
+            "hourly" => return Ok(RotatePolicy::Hourly),
+            "daily" => return Ok(RotatePolicy::Daily),
+            _ => {}
+        }
+        let split = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+        let (num, suffix) = raw.split_at(split);
+        let value: u64 = num
+            .parse()
+            .map_err(|_| format!("invalid rotation '{s}' (use e.g. 10MB, 512KB, hourly, daily)"))?;
+        let factor = match suffix.trim() {
+            "" | "b" => 1,
+            "k" | "kb" => 1024,
+            "m" | "mb" => 1024 * 1024,
+            "g" | "gb" => 1024 * 1024 * 1024,
+            other => return Err(format!("unknown size suffix '{other}' in '{s}'")),
+        };
+        if value == 0 {
+            return Err("rotation size must be greater than zero".into());
+        }
+        value
+            .checked_mul(factor)
+            .map(RotatePolicy::Size)
+            .ok_or_else(|| format!("rotation size '{s}' is too large"))
+    }
+}
+
+/// Append-only output file with optional size/time based rotation.
+///
+/// Rotated files are renamed `<path>.1`, `<path>.2`, ... (newest first) and
+/// at most `keep` of them are retained.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    period: i64,
+    policy: Option<RotatePolicy>,
+    keep: usize,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, policy: Option<RotatePolicy>, keep: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            written,
+            period: current_period(policy),
+            policy,
+            keep,
+        })
+    }
+
+    pub fn write_str(&mut self, s: &str) -> io::Result<()> {
+        if self.needs_rotation(s.len() as u64) {
+            self.rotate()?;
+        }
+        self.file.write_all(s.as_bytes())?;
+        self.written += s.len() as u64;
+        Ok(())
+    }
+
+    fn needs_rotation(&self, incoming: u64) -> bool {
+        match self.policy {
+            Some(RotatePolicy::Size(max)) => self.written > 0 && self.written + incoming > max,
+            Some(_) => current_period(self.policy) != self.period,
+            None => false,
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(backup_path(&self.path, self.keep));
+            for idx in (1..self.keep).rev() {
+                let from = backup_path(&self.path, idx);
+                if from.exists() {
+                    fs::rename(&from, backup_path(&self.path, idx + 1))?;
+                }
+            }
+            fs::rename(&self.path, backup_path(&self.path, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        self.period = current_period(self.policy);
+        Ok(())
+    }
+}
+
+fn backup_path(path: &Path, idx: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{idx}"));
+    PathBuf::from(name)
+}
+
+fn current_period(policy: Option<RotatePolicy>) -> i64 {
+    let now = Local::now();
+    let day = now.num_days_from_ce() as i64;
+    match policy {
+        Some(RotatePolicy::Hourly) => day * 24 + now.hour() as i64,
+        Some(RotatePolicy::Daily) => day,
+        _ => 0,
+    }
+}
+
+static SINK: Mutex<Option<RotatingFile>> = Mutex::new(None);
+
+/// Redirect result output to a file for the rest of the process.
+pub fn install(file: RotatingFile) {
+    if let Ok(mut guard) = SINK.lock() {
+        *guard = Some(file);
+    }
+}
+
+/// Write to the installed output file. Returns `false` when no file is installed.
+pub fn write(s: &str) -> bool {
+    let Ok(mut guard) = SINK.lock() else {
+        return false;
+    };
+    match guard.as_mut() {
+        Some(file) => {
+            if let Err(e) = file.write_str(s) {
+                eprintln!("error writing output file: {}", e);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_and_periods() {
+        assert_eq!(
+            "10MB".parse::<RotatePolicy>(),
+            Ok(RotatePolicy::Size(10 * 1024 * 1024))
+        );
+        assert_eq!(
+            "512k".parse::<RotatePolicy>(),
+            Ok(RotatePolicy::Size(512 * 1024))
+        );
+        assert_eq!("daily".parse::<RotatePolicy>(), Ok(RotatePolicy::Daily));
+        assert!("0MB".parse::<RotatePolicy>().is_err());
+        assert!("weekly".parse::<RotatePolicy>().is_err());
+        assert!("18014398509481984GB".parse::<RotatePolicy>().is_err());
+    }
+
+    #[test]
+    fn size_rotation_shifts_backups() {
+        let dir = std::env::temp_dir().join("rkik-output-rotation");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("out.log");
+        let mut file = RotatingFile::open(&path, Some(RotatePolicy::Size(8)), 2).unwrap();
+        for line in ["aaaaaa\n", "bbbbbb\n", "cccccc\n", "dddddd\n"] {
+            file.write_str(line).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "dddddd\n");
+        assert_eq!(
+            fs::read_to_string(backup_path(&path, 1)).unwrap(),
+            "cccccc\n"
+        );
+        assert_eq!(
+            fs::read_to_string(backup_path(&path, 2)).unwrap(),
+            "bbbbbb\n"
+        );
+        assert!(!backup_path(&path, 3).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
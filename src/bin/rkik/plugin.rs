@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use rkik::fmt::units::TimeUnit;
+use rkik::stats::{Stats, compute_stats};
+use rkik::{ProbeResult, RkikError};
+
+use crate::legacy::LegacyArgs;
+use crate::threshold::Threshold;
+
+/// Monitoring system dialect for `--plugin` output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PluginFormat {
+    /// Nagios/Centreon status line with perfdata
+    #[default]
+    Nagios,
+    /// check_mk local check line
+    Checkmk,
+    /// Icinga2 API process-check-result body
+    #[value(name = "icinga2-json")]
+    Icinga2Json,
+}
+
+/// One performance data metric.
+#[derive(Debug, Clone)]
+pub struct PerfItem {
+    label: String,
+    /// Formatted value without unit; empty when unknown.
+    value: String,
+    uom: &'static str,
+    warn: String,
+    crit: String,
+    min: &'static str,
+    max: &'static str,
+}
+
+impl PerfItem {
+    fn new(label: impl Into<String>, value: String, uom: &'static str) -> Self {
+        Self {
+            label: label.into(),
+            value,
+            uom,
+            warn: String::new(),
+            crit: String::new(),
+            min: "",
+            max: "",
+        }
+    }
+
+    fn thresholds(mut self, warn: String, crit: String) -> Self {
+        self.warn = warn;
+        self.crit = crit;
+        self
+    }
+
+    fn range(mut self, min: &'static str, max: &'static str) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    fn nagios(&self) -> String {
+        let label = if self
+            .label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.label.clone()
+        } else {
+            format!("'{}'", self.label.replace('\'', "''"))
+        };
+        let uom = if self.value.is_empty() { "" } else { self.uom };
+        format!(
+            "{}={}{};{};{};{};{}",
+            label, self.value, uom, self.warn, self.crit, self.min, self.max
+        )
+    }
+
+    /// check_mk metrics take bare numbers: no unit, no range thresholds.
+    fn checkmk(&self) -> String {
+        let name: String = self
+            .label
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let plain = |t: &str| {
+            if t.contains([':', '@']) {
+                String::new()
+            } else {
+                t.to_string()
+            }
+        };
+        format!(
+            "{}={};{};{};{};{}",
+            name,
+            self.value,
+            plain(&self.warn),
+            plain(&self.crit),
+            self.min,
+            self.max
+        )
+    }
+}
+
+/// Evaluated plugin result, renderable in any [`PluginFormat`].
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Exit code / state: 0 OK, 1 WARNING, 2 CRITICAL, 3 UNKNOWN.
+    pub code: i32,
+    service: String,
+    summary: String,
+    perf: Vec<PerfItem>,
+}
+
+impl Report {
+    fn status_line(&self) -> String {
+        format!("RKIK {} - {}", state(self.code), self.summary)
+    }
+
+    fn nagios_perf(&self) -> Vec<String> {
+        self.perf.iter().map(PerfItem::nagios).collect()
+    }
+
+    pub fn render(&self, format: PluginFormat) -> String {
+        match format {
+            PluginFormat::Nagios => {
+                format!("{} | {}", self.status_line(), self.nagios_perf().join(" "))
+            }
+            PluginFormat::Checkmk => {
+                // check_mk rejects metrics without a value; "-" means no metrics.
+                let metrics: Vec<String> = self
+                    .perf
+                    .iter()
+                    .filter(|p| !p.value.is_empty())
+                    .map(PerfItem::checkmk)
+                    .collect();
+                let perf = if metrics.is_empty() {
+                    "-".to_string()
+                } else {
+                    metrics.join("|")
+                };
+                format!(
+                    "{} \"rkik {}\" {} {}",
+                    self.code,
+                    self.service.replace('"', ""),
+                    perf,
+                    self.summary
+                )
+            }
+            PluginFormat::Icinga2Json => {
+                #[cfg(feature = "json")]
+                {
+                    serde_json::json!({
+                        "exit_status": self.code,
+                        "plugin_output": self.status_line(),
+                        "performance_data": self.nagios_perf(),
+                    })
+                    .to_string()
+                }
+                #[cfg(not(feature = "json"))]
+                {
+                    "icinga2-json output requires the json feature".to_string()
+                }
+            }
+        }
+    }
+
+    pub fn print(&self, format: PluginFormat) {
+        println!("{}", self.render(format));
+    }
+}
+
+/// Nagios level (0/1/2) of `value` against optional warning/critical thresholds.
+pub fn level(value: f64, warning: Option<Threshold>, critical: Option<Threshold>) -> i32 {
+    if critical.is_some_and(|c| c.alerts(value)) {
+        2
+    } else if warning.is_some_and(|w| w.alerts(value)) {
+        1
+    } else {
+        0
+    }
+}
+
+pub fn state(code: i32) -> &'static str {
+    match code {
+        0 => "OK",
+        1 => "WARNING",
+        2 => "CRITICAL",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Perfdata form of an optional threshold (empty when unset).
+fn perf_threshold(threshold: Option<Threshold>, unit: TimeUnit) -> String {
+    threshold.map(|t| t.perfdata(unit)).unwrap_or_default()
+}
+
+/// Nagios perfdata knows s/ms/us but has no ns UOM; ns values are emitted unitless.
+fn perfdata_uom(unit: TimeUnit) -> &'static str {
+    match unit {
+        TimeUnit::Ns => "",
+        other => other.as_str(),
+    }
+}
+
+/// RTT, stratum and jitter checks for the samples of one server.
+///
+/// `prefix` is prepended to metric names (empty for single-target checks).
+/// Stratum perfdata is only emitted when `--max-stratum` is set; jitter,
+/// offset stddev and RTT p95 perfdata need at least two samples.
+fn secondary_checks(
+    samples: &[ProbeResult],
+    prefix: &str,
+    args: &LegacyArgs,
+) -> (i32, Vec<PerfItem>) {
+    let unit = args.unit.fixed();
+    let u = unit.as_str();
+    let uom = perfdata_uom(unit);
+
+    let rtt = compute_stats(samples).rtt_avg;
+    let mut code = level(rtt, args.warning_rtt, args.critical_rtt);
+    let mut perf = vec![
+        PerfItem::new(format!("{prefix}rtt_{u}"), unit.format_value(rtt), uom)
+            .thresholds(
+                perf_threshold(args.warning_rtt, unit),
+                perf_threshold(args.critical_rtt, unit),
+            )
+            .range("0", ""),
+    ];
+
+    if let Some(max) = args.max_stratum {
+        let stratum = samples.iter().map(|r| r.stratum).max().unwrap_or(0);
+        if stratum > max {
+            code = code.max(1);
+        }
+        perf.push(
+            PerfItem::new(format!("{prefix}stratum"), stratum.to_string(), "")
+                .thresholds(max.to_string(), String::new())
+                .range("0", "16"),
+        );
+    }
+
+    if samples.len() >= 2 {
+        let st = compute_stats(samples);
+        if let Some(jitter) = st.jitter {
+            code = code.max(level(jitter, args.warning_jitter, None));
+            perf.push(
+                PerfItem::new(
+                    format!("{prefix}jitter_{u}"),
+                    unit.format_value(jitter),
+                    uom,
+                )
+                .thresholds(perf_threshold(args.warning_jitter, unit), String::new())
+                .range("0", ""),
+            );
+        }
+        perf.push(
+            PerfItem::new(
+                format!("{prefix}offset_stddev_{u}"),
+                unit.format_value(st.offset_stddev),
+                uom,
+            )
+            .range("0", ""),
+        );
+        perf.push(
+            PerfItem::new(
+                format!("{prefix}rtt_p95_{u}"),
+                unit.format_value(st.rtt_p95),
+                uom,
+            )
+            .range("0", ""),
+        );
+    }
+    (code, perf)
+}
+
+/// Evaluate the samples of a single target.
+pub fn single(all: &[ProbeResult], args: &LegacyArgs) -> Report {
+    let Some(first) = all.first() else {
+        return unknown(args, "");
+    };
+    let stats = compute_stats(all);
+    let unit = args.unit.fixed();
+    let u = unit.as_str();
+
+    let (extra_code, extra_perf) = secondary_checks(all, "", args);
+    let code = level(stats.offset_avg.abs(), args.warning, args.critical).max(extra_code);
+
+    let mut perf = vec![
+        PerfItem::new(
+            format!("offset_{u}"),
+            unit.format_value(stats.offset_avg),
+            perfdata_uom(unit),
+        )
+        .thresholds(
+            perf_threshold(args.warning, unit),
+            perf_threshold(args.critical, unit),
+        )
+        .range("0", ""),
+    ];
+    perf.extend(extra_perf);
+
+    Report {
+        code,
+        service: first.target.name.clone(),
+        summary: format!(
+            "offset {}{u} rtt {}{u} from {} ({})",
+            unit.format_value(stats.offset_avg),
+            unit.format_value(stats.rtt_avg),
+            first.target.name,
+            first.target.ip,
+        ),
+        perf,
+    }
+}
+
+/// `--compare`: the worst of the max pairwise drift and the largest per-server
+/// |offset| (both averaged over `--count`) decides the state.
+///
+/// Servers in `failed` did not answer at least one round: the state is at
+/// least WARNING and they are named in the summary.
+pub fn compare(
+    all: &HashMap<String, Vec<ProbeResult>>,
+    failed: &[String],
+    args: &LegacyArgs,
+) -> Report {
+    let mut servers: Vec<(&String, Stats)> = all
+        .iter()
+        .map(|(name, vals)| (name, compute_stats(vals)))
+        .collect();
+    if servers.is_empty() {
+        return unknown(args, "compare");
+    }
+    servers.sort_by(|a, b| a.0.cmp(b.0));
+
+    let min = servers
+        .iter()
+        .map(|(_, s)| s.offset_avg)
+        .fold(f64::INFINITY, f64::min);
+    let max = servers
+        .iter()
+        .map(|(_, s)| s.offset_avg)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let drift = max - min;
+    let (worst_name, worst) = servers
+        .iter()
+        .map(|(name, s)| (*name, s.offset_avg))
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .unwrap_or((servers[0].0, 0.0));
+
+    let mut code = level(drift, args.warning, args.critical).max(level(
+        worst.abs(),
+        args.warning,
+        args.critical,
+    ));
+
+    let unit = args.unit.fixed();
+    let u = unit.as_str();
+    let uom = perfdata_uom(unit);
+    let warn = perf_threshold(args.warning, unit);
+    let crit = perf_threshold(args.critical, unit);
+
+    let mut perf = vec![
+        PerfItem::new(format!("drift_{u}"), unit.format_value(drift), uom)
+            .thresholds(warn.clone(), crit.clone())
+            .range("0", ""),
+    ];
+    for (name, st) in &servers {
+        let (extra_code, extra_perf) = secondary_checks(&all[*name], &format!("{name}_"), args);
+        code = code.max(extra_code);
+        perf.push(
+            PerfItem::new(
+                format!("{name}_offset_{u}"),
+                unit.format_value(st.offset_avg),
+                uom,
+            )
+            .thresholds(warn.clone(), crit.clone()),
+        );
+        perf.extend(extra_perf);
+    }
+
+    let mut summary = format!(
+        "drift {}{u} across {} servers, worst offset {}{u} from {}",
+        unit.format_value(drift),
+        servers.len(),
+        unit.format_value(worst),
+        worst_name,
+    );
+    if !failed.is_empty() {
+        code = code.max(1);
+        summary.push_str(&format!(", no reply from {}", failed.join(", ")));
+    }
+
+    Report {
+        code,
+        service: "compare".into(),
+        summary,
+        perf,
+    }
+}
+
+/// Report for a request that failed with `err`: CRITICAL when the answer
+/// failed authentication, UNKNOWN otherwise (see [`RkikError::plugin_exit_code`]).
+pub fn failed(args: &LegacyArgs, service: &str, err: &RkikError) -> Report {
+    let mut report = unknown(args, service);
+    report.code = err.plugin_exit_code();
+    report.summary = format!("request failed: {}", err.kind());
+    report
+}
+
+/// UNKNOWN report for a failed request, keeping the configured thresholds in perfdata.
+pub fn unknown(args: &LegacyArgs, service: &str) -> Report {
+    let unit = args.unit.fixed();
+    let u = unit.as_str();
+    Report {
+        code: 3,
+        service: service.to_string(),
+        summary: "request failed".into(),
+        perf: vec![
+            PerfItem::new(format!("offset_{u}"), String::new(), "")
+                .thresholds(
+                    perf_threshold(args.warning, unit),
+                    perf_threshold(args.critical, unit),
+                )
+                .range("0", ""),
+            PerfItem::new(format!("rtt_{u}"), String::new(), "").range("0", ""),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(code: i32) -> Report {
+        Report {
+            code,
+            service: "time.example".into(),
+            summary: "offset 1.500ms rtt 9.000ms from time.example (192.0.2.1)".into(),
+            perf: vec![
+                PerfItem::new("offset_ms", "1.500".into(), "ms")
+                    .thresholds("@0:5".into(), "10".into())
+                    .range("0", ""),
+                PerfItem::new("a.b_rtt_ms", "9.000".into(), "ms").range("0", ""),
+            ],
+        }
+    }
+
+    #[test]
+    fn nagios_line_quotes_labels_with_special_characters() {
+        assert_eq!(
+            report(1).render(PluginFormat::Nagios),
+            "RKIK WARNING - offset 1.500ms rtt 9.000ms from time.example (192.0.2.1) \
+             | offset_ms=1.500ms;@0:5;10;0; 'a.b_rtt_ms'=9.000ms;;;0;"
+        );
+    }
+
+    #[test]
+    fn checkmk_line_drops_units_and_ranges() {
+        assert_eq!(
+            report(0).render(PluginFormat::Checkmk),
+            "0 \"rkik time.example\" offset_ms=1.500;;10;0;|a_b_rtt_ms=9.000;;;0; \
+             offset 1.500ms rtt 9.000ms from time.example (192.0.2.1)"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn icinga2_json_carries_exit_status_and_perfdata() {
+        let v: serde_json::Value =
+            serde_json::from_str(&report(2).render(PluginFormat::Icinga2Json)).unwrap();
+        assert_eq!(v["exit_status"], 2);
+        assert!(
+            v["plugin_output"]
+                .as_str()
+                .unwrap()
+                .starts_with("RKIK CRITICAL - ")
+        );
+        assert_eq!(v["performance_data"][0], "offset_ms=1.500ms;@0:5;10;0;");
+    }
+}
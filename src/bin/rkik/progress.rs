@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use console::Term;
+use rkik::fmt::units::format_ms;
+
+use crate::legacy::{LegacyArgs, OutputFormat};
+
+/// Runs shorter than this finish before a bar is worth reading.
+const MIN_COUNT: u32 = 10;
+/// Samples behind the rolling offset.
+const WINDOW: usize = 10;
+const BAR_WIDTH: usize = 24;
+
+/// One-line progress bar for long `--count` runs, drawn on stderr below
+/// the probes printed on stdout.
+///
+/// Only shown for text output to a terminal: piped output and machine
+/// formats are left untouched.
+pub struct Progress {
+    term: Term,
+    total: u32,
+    done: u32,
+    started: Instant,
+    offsets: VecDeque<f64>,
+}
+
+impl Progress {
+    pub fn new(args: &LegacyArgs) -> Option<Self> {
+        let term = Term::stderr();
+        let wanted = !args.infinite
+            && !args.plugin
+            && args.count >= MIN_COUNT
+            && matches!(args.format, OutputFormat::Text)
+            // -vv diagnostics would be torn apart by the redraws.
+            && (args.verbose < 2 || args.log_file.is_some())
+            && Term::stdout().is_term()
+            && term.is_term();
+        wanted.then(|| Self {
+            term,
+            total: args.count,
+            done: 0,
+            started: Instant::now(),
+            offsets: VecDeque::with_capacity(WINDOW),
+        })
+    }
+
+    /// Count one probe, with its offset when it succeeded.
+    pub fn tick(&mut self, offset_ms: Option<f64>) {
+        self.done = (self.done + 1).min(self.total);
+        if let Some(offset) = offset_ms {
+            if self.offsets.len() == WINDOW {
+                self.offsets.pop_front();
+            }
+            self.offsets.push_back(offset);
+        }
+    }
+
+    /// Erase the bar before anything else is printed.
+    pub fn clear(&self) {
+        self.term.clear_line().ok();
+    }
+
+    pub fn draw(&self) {
+        let rolling = (!self.offsets.is_empty())
+            .then(|| self.offsets.iter().sum::<f64>() / self.offsets.len() as f64);
+        let line = render(self.done, self.total, self.started.elapsed(), rolling);
+        self.term.clear_line().ok();
+        self.term.write_str(&line).ok();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// `[#####-------] 12/100  ETA 1m28s  offset +1.234 ms`
+fn render(done: u32, total: u32, elapsed: Duration, rolling_ms: Option<f64>) -> String {
+    let filled = BAR_WIDTH * done as usize / total.max(1) as usize;
+    let mut line = format!(
+        "[{}{}] {done}/{total}",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled)
+    );
+    if done > 0 && done < total {
+        let left = elapsed.as_secs_f64() / done as f64 * (total - done) as f64;
+        line.push_str(&format!("  ETA {}", human(left)));
+    }
+    if let Some(offset) = rolling_ms {
+        let sign = if offset >= 0.0 { "+" } else { "" };
+        line.push_str(&format!("  offset {sign}{}", format_ms(offset)));
+    }
+    line
+}
+
+fn human(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_bar_eta_and_rolling_offset() {
+        let line = render(25, 100, Duration::from_secs(25), Some(1.5));
+        assert_eq!(
+            line,
+            "[######------------------] 25/100  ETA 1m15s  offset +1.500 ms"
+        );
+        assert_eq!(
+            render(0, 100, Duration::ZERO, None),
+            format!("[{}] 0/100", "-".repeat(BAR_WIDTH))
+        );
+        assert!(
+            render(100, 100, Duration::from_secs(9), Some(-0.25))
+                .ends_with("100/100  offset -0.250 ms")
+        );
+        assert_eq!(human(7200.0), "2h00m");
+    }
+
+    #[test]
+    fn rolling_offset_covers_the_last_samples() {
+        let mut progress = Progress {
+            term: Term::stderr(),
+            total: 50,
+            done: 0,
+            started: Instant::now(),
+            offsets: VecDeque::new(),
+        };
+        for i in 0..20 {
+            progress.tick(Some(i as f64));
+        }
+        progress.tick(None);
+        assert_eq!(progress.done, 21);
+        assert_eq!(progress.offsets.len(), WINDOW);
+        assert_eq!(progress.offsets.front(), Some(&10.0));
+    }
+}
@@ -0,0 +1,51 @@
+//! `--recommend` and `--emit-config`: the best servers of a compare run or
+//! a survey, ranked by `rkik::services::recommend`.
+
+use clap::ValueEnum;
+use rkik::fmt;
+use rkik::services::recommend::{self, Candidate, ConfigDialect};
+
+/// Configuration file `--emit-config` writes a server block for.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmitConfig {
+    Chrony,
+    Ntp,
+}
+
+impl From<EmitConfig> for ConfigDialect {
+    fn from(emit: EmitConfig) -> Self {
+        match emit {
+            EmitConfig::Chrony => ConfigDialect::Chrony,
+            EmitConfig::Ntp => ConfigDialect::Ntp,
+        }
+    }
+}
+
+/// The `count` best `candidates`, followed by their server block with
+/// `--emit-config`, as text or as one JSON document.
+#[allow(unused_variables)]
+pub fn report(
+    candidates: &[Candidate],
+    count: usize,
+    emit: Option<EmitConfig>,
+    json: bool,
+    pretty: bool,
+) -> Result<String, String> {
+    let mut best = recommend::rank(candidates);
+    best.truncate(count.max(1));
+    let block = emit.map(|e| recommend::server_block(&best, e.into()));
+    if json {
+        #[cfg(feature = "json")]
+        return fmt::json::recommendation_to_json(&best, block.as_deref(), pretty)
+            .map(|s| s + "\n")
+            .map_err(|e| e.to_string());
+        #[cfg(not(feature = "json"))]
+        return Err("JSON output requires the json feature".into());
+    }
+    let mut out = format!("\n{}", fmt::text::render_recommendation(&best));
+    if let Some(block) = block {
+        out.push('\n');
+        out.push_str(&block);
+    }
+    Ok(out)
+}
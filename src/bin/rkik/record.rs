@@ -0,0 +1,41 @@
+//! `--record`: append every probe of the run to a session file.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rkik::adapters::session::{Record, SessionWriter};
+use rkik::{ProbeResult, RkikError};
+
+static SESSION: Mutex<Option<SessionWriter<BufWriter<File>>>> = Mutex::new(None);
+
+/// Record the rest of the process to a new session file at `path`.
+pub fn install(path: &Path) -> std::io::Result<()> {
+    let writer = SessionWriter::create(path)?;
+    if let Ok(mut guard) = SESSION.lock() {
+        *guard = Some(writer);
+    }
+    Ok(())
+}
+
+pub fn probe(r: &ProbeResult) {
+    write(&Record::Probe(Box::new(r.clone())));
+}
+
+pub fn failure(target: &str, err: &RkikError) {
+    write(&Record::failure(target, err));
+}
+
+/// A failed write is reported once and stops the recording; the run goes on.
+fn write(record: &Record) {
+    let Ok(mut guard) = SESSION.lock() else {
+        return;
+    };
+    if let Some(writer) = guard.as_mut()
+        && let Err(e) = writer.write(record)
+    {
+        eprintln!("rkik: recording stopped: {e}");
+        *guard = None;
+    }
+}
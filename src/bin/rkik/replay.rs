@@ -0,0 +1,412 @@
+//! `rkik replay`: render a session recorded with `--record`, offline.
+
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use clap::ValueEnum;
+use console::{set_colors_enabled, style};
+use rkik::ProbeResult;
+use rkik::adapters::session::{Record, open_session};
+use rkik::fmt;
+use rkik::fmt::units::format_ms;
+use rkik::stats::{Shift, Stats, compute_stats, welch_t_test};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReplayFormat {
+    /// Every probe and failure, as rkik printed them
+    Text,
+    /// The probes as one `rkik -j` document
+    Json,
+    /// Statistics per server, recomputed from the recorded probes
+    Report,
+}
+
+pub fn run(
+    path: &Path,
+    format: ReplayFormat,
+    verbose: bool,
+    pretty: bool,
+    no_color: bool,
+) -> Result<(), String> {
+    let records = open_session(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    render(&records, "Session", format, verbose, pretty, no_color)
+}
+
+/// Print `records` in `format`; shared with `rkik history`, whose report
+/// starts with `heading` instead of "Session".
+pub fn render(
+    records: &[Record],
+    heading: &str,
+    format: ReplayFormat,
+    verbose: bool,
+    pretty: bool,
+    no_color: bool,
+) -> Result<(), String> {
+    set_colors_enabled(
+        format != ReplayFormat::Json
+            && !no_color
+            && std::io::stdout().is_terminal()
+            && std::env::var_os("NO_COLOR").is_none(),
+    );
+    match format {
+        ReplayFormat::Text => {
+            for record in records {
+                match record {
+                    Record::Probe(r) if verbose => println!("{}", fmt::text::render_probe(r, true)),
+                    Record::Probe(r) => println!("{}", fmt::text::render_short_probe(r)),
+                    Record::Failure { .. } => println!("{}", style(failure_line(record)).red()),
+                }
+            }
+        }
+        ReplayFormat::Json => {
+            let probes: Vec<ProbeResult> = probes(records).cloned().collect();
+            let json = fmt::json::to_json(&probes, pretty, verbose).map_err(|e| e.to_string())?;
+            println!("{json}");
+            for record in records {
+                if matches!(record, Record::Failure { .. }) {
+                    eprintln!("{}", failure_line(record));
+                }
+            }
+        }
+        ReplayFormat::Report => print!("{}", report(records, heading)),
+    }
+    Ok(())
+}
+
+/// Significance level of the `--diff` shifts.
+const ALPHA: f64 = 0.05;
+
+/// Compare the offset and RTT distributions of every server between two
+/// sessions, flagging shifts significant under Welch's t-test.
+pub fn diff(
+    before: &Path,
+    after: &Path,
+    format: ReplayFormat,
+    pretty: bool,
+    no_color: bool,
+) -> Result<(), String> {
+    let load = |path: &Path| open_session(path).map_err(|e| format!("{}: {e}", path.display()));
+    let (old, new) = (load(before)?, load(after)?);
+    set_colors_enabled(
+        format != ReplayFormat::Json
+            && !no_color
+            && std::io::stdout().is_terminal()
+            && std::env::var_os("NO_COLOR").is_none(),
+    );
+    let rows = diff_rows(&old, &new);
+    if format == ReplayFormat::Json {
+        let servers: Vec<_> = rows.iter().map(DiffRow::to_json).collect();
+        let doc = serde_json::json!({
+            "before": before.display().to_string(),
+            "after": after.display().to_string(),
+            "alpha": ALPHA,
+            "servers": servers,
+        });
+        let text = if pretty {
+            serde_json::to_string_pretty(&doc)
+        } else {
+            serde_json::to_string(&doc)
+        };
+        println!("{}", text.map_err(|e| e.to_string())?);
+        return Ok(());
+    }
+    println!(
+        "Diff: {} probes in {}, {} in {} (significance {ALPHA})",
+        probes(&old).count(),
+        before.display(),
+        probes(&new).count(),
+        after.display()
+    );
+    for row in &rows {
+        print!("{}", row.render());
+    }
+    Ok(())
+}
+
+/// One server's samples in the two sessions.
+struct DiffRow<'a> {
+    name: &'a str,
+    before: Vec<&'a ProbeResult>,
+    after: Vec<&'a ProbeResult>,
+}
+
+impl DiffRow<'_> {
+    fn shift(&self, value: fn(&ProbeResult) -> f64) -> Option<Shift> {
+        let before: Vec<f64> = self.before.iter().map(|r| value(r)).collect();
+        let after: Vec<f64> = self.after.iter().map(|r| value(r)).collect();
+        welch_t_test(&before, &after)
+    }
+
+    fn render(&self) -> String {
+        let mut out = format!("\n{}\n", style(self.name).green().bold());
+        match (self.before.len(), self.after.len()) {
+            (0, _) => out.push_str("  only in the second session\n"),
+            (_, 0) => out.push_str("  only in the first session\n"),
+            (b, a) if b < 2 || a < 2 => out.push_str(&format!(
+                "  too few probes to compare ({b} before, {a} after)\n"
+            )),
+            _ => {
+                for (label, value) in [("offset", offset as fn(&ProbeResult) -> f64), ("rtt", rtt)]
+                {
+                    let Some(shift) = self.shift(value) else {
+                        continue;
+                    };
+                    let avg = |list: &[&ProbeResult]| {
+                        list.iter().map(|r| value(r)).sum::<f64>() / list.len() as f64
+                    };
+                    let line = format!(
+                        "  {label:<6} {} -> {}  shift {}{}  p={:.4}",
+                        format_ms(avg(&self.before)),
+                        format_ms(avg(&self.after)),
+                        if shift.delta >= 0.0 { "+" } else { "" },
+                        format_ms(shift.delta),
+                        shift.p
+                    );
+                    if shift.p < ALPHA {
+                        out.push_str(&format!(
+                            "{}\n",
+                            style(format!("{line}  significant")).yellow()
+                        ));
+                    } else {
+                        out.push_str(&format!("{line}\n"));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let metric = |value: fn(&ProbeResult) -> f64| {
+            self.shift(value).map(|s| {
+                serde_json::json!({
+                    "shift_ms": s.delta,
+                    "t": if s.t.is_finite() { Some(s.t) } else { None },
+                    "p": s.p,
+                    "significant": s.p < ALPHA,
+                })
+            })
+        };
+        serde_json::json!({
+            "name": self.name,
+            "before_count": self.before.len(),
+            "after_count": self.after.len(),
+            "offset": metric(offset),
+            "rtt": metric(rtt),
+        })
+    }
+}
+
+fn offset(r: &ProbeResult) -> f64 {
+    r.offset_ms
+}
+
+fn rtt(r: &ProbeResult) -> f64 {
+    r.rtt_ms
+}
+
+/// Servers of both sessions, in order of first appearance in `before`, then `after`.
+fn diff_rows<'a>(before: &'a [Record], after: &'a [Record]) -> Vec<DiffRow<'a>> {
+    let mut rows: Vec<DiffRow<'a>> = Vec::new();
+    for (second, records) in [(false, before), (true, after)] {
+        for r in probes(records) {
+            let name = r.target.name.as_str();
+            let row = match rows.iter().position(|row| row.name == name) {
+                Some(i) => &mut rows[i],
+                None => {
+                    rows.push(DiffRow {
+                        name,
+                        before: Vec::new(),
+                        after: Vec::new(),
+                    });
+                    rows.last_mut().unwrap()
+                }
+            };
+            if second {
+                row.after.push(r);
+            } else {
+                row.before.push(r);
+            }
+        }
+    }
+    rows
+}
+
+fn probes(records: &[Record]) -> impl Iterator<Item = &ProbeResult> {
+    records.iter().filter_map(|r| match r {
+        Record::Probe(p) => Some(p.as_ref()),
+        Record::Failure { .. } => None,
+    })
+}
+
+fn failure_line(record: &Record) -> String {
+    match record {
+        Record::Failure {
+            target,
+            kind,
+            message,
+            at,
+        } if message == kind => format!("Error: {} {target} - {kind}", at.to_rfc3339()),
+        Record::Failure {
+            target,
+            kind,
+            message,
+            at,
+        } => format!("Error: {} {target} - {kind}: {message}", at.to_rfc3339()),
+        Record::Probe(_) => String::new(),
+    }
+}
+
+/// Span of the records, then the `--count` statistics of every server in order of
+/// first appearance, its failures, and the spread of the averages.
+fn report(records: &[Record], heading: &str) -> String {
+    let mut order: Vec<&str> = Vec::new();
+    let mut samples: HashMap<&str, Vec<ProbeResult>> = HashMap::new();
+    let mut failures: HashMap<&str, usize> = HashMap::new();
+    for record in records {
+        let name = match record {
+            Record::Probe(p) => {
+                samples
+                    .entry(&p.target.name)
+                    .or_default()
+                    .push(p.as_ref().clone());
+                p.target.name.as_str()
+            }
+            Record::Failure { target, .. } => {
+                *failures.entry(target).or_default() += 1;
+                target.as_str()
+            }
+        };
+        if !order.contains(&name) {
+            order.push(name);
+        }
+    }
+
+    let mut out = String::new();
+    let times: Vec<_> = probes(records).map(|p| p.utc).collect();
+    if let (Some(first), Some(last)) = (times.iter().min(), times.iter().max()) {
+        out.push_str(&format!(
+            "{heading}: {} probes, {} failures, {} to {}\n",
+            times.len(),
+            records.len() - times.len(),
+            first.to_rfc3339(),
+            last.to_rfc3339()
+        ));
+    } else {
+        out.push_str(&format!(
+            "{heading}: no successful probes, {} failures\n",
+            records.len()
+        ));
+    }
+    let mut averages: Vec<f64> = Vec::new();
+    for name in order {
+        if let Some(list) = samples.get(name) {
+            let stats: Stats = compute_stats(list);
+            averages.push(stats.offset_avg);
+            out.push_str(&fmt::text::render_stats(name, &stats));
+            out.push('\n');
+        } else {
+            out.push_str(&format!("\n{}\n", style(name).green().bold()));
+        }
+        if let Some(&n) = failures.get(name) {
+            let ok = samples.get(name).map_or(0, Vec::len);
+            let availability = 100.0 * ok as f64 / (ok + n) as f64;
+            out.push_str(&format!(
+                "  {} (availability {availability:.1}%)\n",
+                style(format!("{n} failed")).red()
+            ));
+        }
+    }
+    if averages.len() > 1 {
+        let min = averages.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = averages.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        out.push_str(&format!("Max avg drift: {}\n", format_ms(max - min)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rkik::{Leap, RkikError, Target};
+
+    fn probe(name: &str, offset_ms: f64) -> ProbeResult {
+        let utc = chrono::Utc::now();
+        ProbeResult {
+            target: Target::new(name, "192.0.2.1".parse().unwrap(), 123),
+            offset_ms,
+            rtt_ms: 2.0,
+            stratum: 1,
+            ref_id: "GPS".into(),
+            utc,
+            local: utc.into(),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn diff_flags_shifted_servers() {
+        set_colors_enabled(false);
+        let session = |offsets: &[(&str, f64)]| -> Vec<Record> {
+            offsets
+                .iter()
+                .map(|&(name, ms)| Record::Probe(Box::new(probe(name, ms))))
+                .collect()
+        };
+        let before = session(&[("a", 1.0), ("a", 1.2), ("a", 0.9), ("b", 5.0), ("b", 5.1)]);
+        let after = session(&[
+            ("a", 3.0),
+            ("a", 3.1),
+            ("a", 2.9),
+            ("b", 5.1),
+            ("b", 5.0),
+            ("c", 1.0),
+        ]);
+        let rows = diff_rows(&before, &after);
+        let names: Vec<_> = rows.iter().map(|r| r.name).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(
+            rows[0].render().contains("significant"),
+            "{}",
+            rows[0].render()
+        );
+        assert!(
+            !rows[1].render().contains("significant"),
+            "{}",
+            rows[1].render()
+        );
+        assert!(rows[2].render().contains("only in the second session"));
+        assert_eq!(rows[0].to_json()["offset"]["significant"], true);
+    }
+
+    #[test]
+    fn report_groups_probes_and_failures_per_server() {
+        set_colors_enabled(false);
+        let records = vec![
+            Record::Probe(Box::new(probe("a", 1.0))),
+            Record::Probe(Box::new(probe("b", 4.0))),
+            Record::failure("b", &RkikError::Timeout),
+            Record::Probe(Box::new(probe("a", 3.0))),
+        ];
+        let text = report(&records, "Session");
+        assert!(text.starts_with("Session: 3 probes, 1 failures"), "{text}");
+        assert!(text.contains("a: avg 2.000 ms"), "{text}");
+        assert!(text.contains("1 failed (availability 50.0%)"), "{text}");
+        assert!(text.contains("Max avg drift: 2.000 ms"), "{text}");
+    }
+}
@@ -0,0 +1,91 @@
+//! `rkik serve`: the lab NTP responder of `rkik::services::serve`, until
+//! Ctrl+C or SIGTERM.
+
+use std::net::SocketAddr;
+
+use console::{Term, style};
+use rkik::fmt::units::format_ms;
+use rkik::services::serve::{self, ServeOptions};
+use tokio_util::sync::CancellationToken;
+
+use crate::systemd;
+
+/// Parse `--loss`: a percentage (`5%`) or a fraction (`0.05`) of requests.
+pub fn parse_loss(s: &str) -> Result<f64, String> {
+    let value = match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => s.parse::<f64>(),
+    };
+    match value {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+        _ => Err(format!(
+            "'{s}' is not a share of requests between 0% and 100%"
+        )),
+    }
+}
+
+pub async fn run(addr: SocketAddr, options: ServeOptions) -> Result<(), String> {
+    let socket = serve::bind(addr).await.map_err(|e| e.to_string())?;
+    let local = socket.local_addr().map_err(|e| e.to_string())?;
+    let term = Term::stderr();
+    let mut line = format!(
+        "Serving NTP on {} (stratum {}, offset {}",
+        local,
+        options.stratum,
+        format_ms(options.offset_ms)
+    );
+    if options.jitter_ms > 0.0 {
+        line.push_str(&format!(", jitter ±{}", format_ms(options.jitter_ms)));
+    }
+    if options.loss > 0.0 {
+        line.push_str(&format!(", loss {:.1}%", options.loss * 100.0));
+    }
+    line.push(')');
+    term.write_line(&style(line).cyan().to_string()).ok();
+    term.write_line(
+        &style("Lab use only: replies come from the local clock, shifted on purpose.")
+            .yellow()
+            .to_string(),
+    )
+    .ok();
+    if !local.ip().is_loopback() {
+        term.write_line(
+            &style(format!(
+                "Listening on {}: other machines can reach it, never point production clients at it.",
+                local.ip()
+            ))
+            .yellow()
+            .to_string(),
+        )
+        .ok();
+    }
+
+    let cancel = CancellationToken::new();
+    let server = tokio::spawn(serve::serve(socket, options, cancel.clone()));
+    systemd::notify("READY=1");
+    systemd::shutdown().await;
+    cancel.cancel();
+    let stats = server
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    term.write_line(&format!(
+        "{} requests, {} answered, {} dropped, {} ignored",
+        stats.requests, stats.answered, stats.dropped, stats.ignored
+    ))
+    .ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loss_is_a_share_of_requests() {
+        assert_eq!(parse_loss("5%").unwrap(), 0.05);
+        assert_eq!(parse_loss("0.5").unwrap(), 0.5);
+        assert!(parse_loss("150%").is_err());
+        assert!(parse_loss("-1").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! `--store`: persist every probe of the run to a history database.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rkik::adapters::session::Record;
+use rkik::adapters::store::ProbeStore;
+use rkik::{ProbeResult, RkikError};
+
+static STORE: Mutex<Option<ProbeStore>> = Mutex::new(None);
+
+/// Parse a `--store` location; only `sqlite://PATH` is supported.
+pub fn parse_url(s: &str) -> Result<PathBuf, String> {
+    match s.strip_prefix("sqlite://") {
+        Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Err(format!("unsupported store '{s}' (use sqlite://PATH)")),
+    }
+}
+
+/// Store the rest of the process in the database at `path`.
+pub fn install(path: &Path) -> Result<(), RkikError> {
+    let store = ProbeStore::open(path)?;
+    if let Ok(mut guard) = STORE.lock() {
+        *guard = Some(store);
+    }
+    Ok(())
+}
+
+pub fn probe(r: &ProbeResult) {
+    insert(&Record::Probe(Box::new(r.clone())));
+}
+
+pub fn failure(target: &str, err: &RkikError) {
+    insert(&Record::failure(target, err));
+}
+
+/// A failed insert is reported once and stops storing; the run goes on.
+fn insert(record: &Record) {
+    let Ok(mut guard) = STORE.lock() else {
+        return;
+    };
+    if let Some(store) = guard.as_ref()
+        && let Err(e) = store.insert(record)
+    {
+        eprintln!("rkik: storing stopped: {e}");
+        *guard = None;
+    }
+}
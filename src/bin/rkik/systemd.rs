@@ -0,0 +1,167 @@
+//! Service manager integration for `rkik daemon` and `rkik exporter`.
+//!
+//! Implements the `sd_notify` datagram protocol directly: `READY=1` once the
+//! listeners are bound, `WATCHDOG=1` while probe cycles keep completing, and
+//! `STOPPING=1` on shutdown. All calls are no-ops outside systemd.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+/// Set by [`progress`], cleared by each watchdog ping.
+static PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// Send `state` (e.g. `READY=1`) to `$NOTIFY_SOCKET`, if set.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Some(path) = std::env::var_os("NOTIFY_SOCKET")
+        && let Err(e) = unix::send(&path, state)
+    {
+        eprintln!("warning: sd_notify failed: {}", e);
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+/// Record that a probe cycle completed; the next watchdog ping is sent only after this.
+pub fn progress() {
+    PROGRESS.store(true, Ordering::Relaxed);
+}
+
+/// Watchdog timeout requested by the unit (`WatchdogSec=`), if it applies to this process.
+pub fn watchdog_timeout() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// The watchdog timeout, when probe cycles as long as `longest_cycle` would
+/// let it expire.
+///
+/// A ping goes out up to half the timeout after the cycle that allowed it,
+/// so cycles must complete within the other half.
+pub fn cycle_too_long(longest_cycle: Duration) -> Option<Duration> {
+    watchdog_timeout().filter(|timeout| longest_cycle >= *timeout / 2)
+}
+
+/// Ping the watchdog at half its timeout for as long as [`progress`] keeps being called.
+///
+/// A probe loop that stops completing cycles (hung resolver, deadlock) is
+/// then restarted by systemd. Failed probes still count as progress: an
+/// unreachable server is something to report, not a reason to restart.
+pub fn spawn_watchdog(longest_cycle: Duration) -> Option<JoinHandle<()>> {
+    let timeout = watchdog_timeout()?;
+    if cycle_too_long(longest_cycle).is_some() {
+        eprintln!(
+            "warning: WatchdogSec ({:.1}s) is less than twice the longest probe interval plus timeout ({:.1}s); systemd will restart rkik",
+            timeout.as_secs_f64(),
+            longest_cycle.as_secs_f64()
+        );
+    }
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(timeout / 2);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if PROGRESS.swap(false, Ordering::Relaxed) {
+                notify("WATCHDOG=1");
+            }
+        }
+    }))
+}
+
+/// Resolves on Ctrl+C, or SIGTERM on Unix (what service managers send).
+pub async fn shutdown() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        if let Ok(mut term) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// SIGHUP listener; never fires outside Unix.
+pub struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok(),
+        }
+    }
+
+    /// Wait for the next SIGHUP.
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = self.signal.as_mut() {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::unix::net::UnixDatagram;
+
+    pub fn send(path: &OsStr, state: &str) -> io::Result<()> {
+        let socket = UnixDatagram::unbound()?;
+        let bytes = path.as_encoded_bytes();
+        match bytes.strip_prefix(b"@") {
+            // Abstract namespace socket (Linux only).
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &addr)?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "abstract NOTIFY_SOCKET is only supported on Linux",
+                ));
+            }
+            None => {
+                socket.send_to(state.as_bytes(), path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn sends_state_to_notify_socket() {
+        let path = std::env::temp_dir().join(format!("rkik-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+        unix::send(path.as_os_str(), "READY=1\nSTATUS=ok").unwrap();
+        let mut buf = [0u8; 64];
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1\nSTATUS=ok");
+        let _ = std::fs::remove_file(&path);
+    }
+}
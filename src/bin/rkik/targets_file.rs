@@ -0,0 +1,59 @@
+//! Target lists read from a file or stdin (`--targets-file`).
+
+use std::io::Read;
+use std::path::Path;
+
+/// Read one target per line from `path`, or from stdin when it is `-`.
+///
+/// Blank lines and `#` comments (whole-line or trailing) are skipped; a list
+/// without any target is an error.
+pub fn read(path: &Path) -> Result<Vec<String>, String> {
+    let stdin = path == Path::new("-");
+    let text = if stdin {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("cannot read targets from stdin: {e}"))?;
+        text
+    } else {
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read targets file {}: {e}", path.display()))?
+    };
+    let targets = parse(&text);
+    if targets.is_empty() {
+        let source = if stdin {
+            "stdin".to_string()
+        } else {
+            path.display().to_string()
+        };
+        return Err(format!("no targets in {source}"));
+    }
+    Ok(targets)
+}
+
+fn parse(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| {
+            line.split_once('#')
+                .map_or(line, |(before, _)| before)
+                .trim()
+        })
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_blank_lines_and_comments() {
+        let text =
+            "# pool export\n0.pool.ntp.org\n\n  1.pool.ntp.org   # backup\n[2001:db8::1]:123\n";
+        assert_eq!(
+            parse(text),
+            ["0.pool.ntp.org", "1.pool.ntp.org", "[2001:db8::1]:123"]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use rkik::fmt::units::TimeUnit;
+
+/// Plugin threshold using the Nagios range grammar `[@][start:][end]`.
+///
+/// | Spec | Alert when |
+/// |------|------------|
+/// | `10` | value >= 10 (rkik's historical upper bound) |
+/// | `10:` | value < 10 |
+/// | `~:10` | value > 10 |
+/// | `10:20` | value < 10 or value > 20 |
+/// | `@10:20` | 10 <= value <= 20 |
+///
+/// Bounds are in milliseconds for time based checks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    /// Lower bound, `None` for negative infinity (`~`).
+    pub start: Option<f64>,
+    /// Upper bound, `None` for positive infinity.
+    pub end: Option<f64>,
+    /// Alert when the value is inside the range (`@` prefix).
+    pub inside: bool,
+    /// Plain number: keeps the `value >= end` semantics rkik used before ranges.
+    pub simple: bool,
+}
+
+impl Threshold {
+    /// Whether `value` triggers this threshold.
+    pub fn alerts(&self, value: f64) -> bool {
+        let start = self.start.unwrap_or(f64::NEG_INFINITY);
+        let end = self.end.unwrap_or(f64::INFINITY);
+        if self.simple {
+            value < start || value >= end
+        } else if self.inside {
+            value >= start && value <= end
+        } else {
+            value < start || value > end
+        }
+    }
+
+    /// Upper bound of a plain-number threshold.
+    pub fn simple_bound(&self) -> Option<f64> {
+        if self.simple { self.end } else { None }
+    }
+
+    /// Render for Nagios perfdata, converting bounds from milliseconds to `unit`.
+    pub fn perfdata(&self, unit: TimeUnit) -> String {
+        let end = self
+            .end
+            .map(|v| unit.from_ms(v).to_string())
+            .unwrap_or_default();
+        if self.simple {
+            return end;
+        }
+        let start = match self.start {
+            Some(v) => unit.from_ms(v).to_string(),
+            None => "~".into(),
+        };
+        format!("{}{}:{}", if self.inside { "@" } else { "" }, start, end)
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || format!("invalid threshold '{s}' (expected e.g. 10, 10:, ~:10, 10:20 or @10:20)");
+        let raw = s.trim();
+        let (inside, body) = match raw.strip_prefix('@') {
+            Some(rest) => (true, rest),
+            None => (false, raw),
+        };
+        let number = |p: &str| p.trim().parse::<f64>().ok().filter(|v| v.is_finite());
+
+        let Some((start, end)) = body.split_once(':') else {
+            let end = number(body).ok_or_else(invalid)?;
+            return Ok(Self {
+                start: Some(0.0),
+                end: Some(end),
+                inside,
+                simple: !inside,
+            });
+        };
+        let start = match start.trim() {
+            "~" => None,
+            "" => Some(0.0),
+            other => Some(number(other).ok_or_else(invalid)?),
+        };
+        let end = match end.trim() {
+            "" => None,
+            other => Some(number(other).ok_or_else(invalid)?),
+        };
+        if let (Some(a), Some(b)) = (start, end)
+            && a > b
+        {
+            return Err(format!(
+                "invalid threshold '{s}': start is greater than end"
+            ));
+        }
+        Ok(Self {
+            start,
+            end,
+            inside,
+            simple: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(s: &str) -> Threshold {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn plain_number_keeps_upper_bound_semantics() {
+        let th = t("50");
+        assert!(th.simple);
+        assert_eq!(th.simple_bound(), Some(50.0));
+        assert!(!th.alerts(49.9));
+        assert!(th.alerts(50.0));
+        assert_eq!(th.perfdata(TimeUnit::Us), "50000");
+    }
+
+    #[test]
+    fn nagios_ranges() {
+        assert!(t("10:").alerts(9.0) && !t("10:").alerts(100.0));
+        assert!(t("~:10").alerts(11.0) && !t("~:10").alerts(-100.0));
+        let r = t("10:20");
+        assert!(r.alerts(9.0) && r.alerts(21.0) && !r.alerts(10.0) && !r.alerts(20.0));
+        let at = t("@0:5");
+        assert!(at.alerts(0.0) && at.alerts(5.0) && !at.alerts(5.1));
+        assert_eq!(at.perfdata(TimeUnit::Ms), "@0:5");
+        assert_eq!(t("~:10").perfdata(TimeUnit::Ms), "~:10");
+        assert!("20:10".parse::<Threshold>().is_err());
+        assert!("abc".parse::<Threshold>().is_err());
+    }
+}
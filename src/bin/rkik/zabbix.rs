@@ -0,0 +1,233 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rkik::ProbeResult;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const DEFAULT_PORT: u16 = 10051;
+const HEADER: &[u8; 5] = b"ZBXD\x01";
+
+/// Zabbix server or proxy accepting trapper items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZabbixServer {
+    pub host: String,
+    pub port: u16,
+}
+
+impl ZabbixServer {
+    /// Parse `host`, `host:port` or `[v6]:port` (default port 10051).
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid Zabbix server '{s}' (expected host[:port])");
+        let (host, port) = if let Some(v6) = s.strip_prefix('[') {
+            let (host, tail) = v6.split_once(']').ok_or_else(invalid)?;
+            match tail.strip_prefix(':') {
+                Some(p) => (host, p.parse().map_err(|_| invalid())?),
+                None => (host, DEFAULT_PORT),
+            }
+        } else if s.matches(':').count() == 1 {
+            let (host, p) = s.split_once(':').ok_or_else(invalid)?;
+            (host, p.parse().map_err(|_| invalid())?)
+        } else {
+            (s, DEFAULT_PORT)
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+struct Sender {
+    server: ZabbixServer,
+    host: String,
+    timeout: Duration,
+}
+
+static SENDER: Mutex<Option<Sender>> = Mutex::new(None);
+
+/// Push trapper items to `server` for the rest of the process.
+pub fn install(server: ZabbixServer, host: String, timeout: Duration) {
+    if let Ok(mut guard) = SENDER.lock() {
+        *guard = Some(Sender {
+            server,
+            host,
+            timeout,
+        });
+    }
+}
+
+/// Build the `sender data` request for one round of results.
+///
+/// Each probe yields `rkik.offset[<target>]`, `rkik.rtt[<target>]` (milliseconds)
+/// and `rkik.stratum[<target>]`.
+fn request(host: &str, results: &[ProbeResult]) -> serde_json::Value {
+    let data: Vec<_> = results
+        .iter()
+        .flat_map(|r| {
+            let clock = r.utc.timestamp();
+            let ns = r.utc.timestamp_subsec_nanos();
+            let item = |key: &str, value: String| {
+                json!({
+                    "host": host,
+                    "key": format!("rkik.{}[{}]", key, item_param(&r.target.name)),
+                    "value": value,
+                    "clock": clock,
+                    "ns": ns,
+                })
+            };
+            [
+                item("offset", format!("{:.3}", r.offset_ms)),
+                item("rtt", format!("{:.3}", r.rtt_ms)),
+                item("stratum", r.stratum.to_string()),
+            ]
+        })
+        .collect();
+    json!({ "request": "sender data", "data": data })
+}
+
+/// Quote an item key parameter when it contains characters Zabbix treats specially.
+fn item_param(p: &str) -> String {
+    if p.contains([',', ']', '"', ' ']) || p.starts_with('[') {
+        format!("\"{}\"", p.replace('"', "\\\""))
+    } else {
+        p.to_string()
+    }
+}
+
+/// Frame a JSON payload with the Zabbix protocol header.
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(payload.len() + 13);
+    buf.extend_from_slice(HEADER);
+    buf.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    buf.extend_from_slice(payload);
+    buf
+}
+
+/// Send one round of results. Failures are reported on stderr and never abort the run.
+pub async fn send(results: &[ProbeResult]) {
+    let prepared = match SENDER.lock() {
+        Ok(guard) => guard.as_ref().map(|s| {
+            (
+                s.server.clone(),
+                request(&s.host, results).to_string(),
+                s.timeout,
+            )
+        }),
+        Err(_) => None,
+    };
+    let Some((server, body, timeout)) = prepared else {
+        return;
+    };
+    let result = match tokio::time::timeout(timeout, exchange(&server, body.as_bytes())).await {
+        Ok(r) => r,
+        Err(_) => Err("timed out".into()),
+    };
+    if let Err(e) = result {
+        eprintln!(
+            "warning: Zabbix send to {}:{} failed: {}",
+            server.host, server.port, e
+        );
+    }
+}
+
+async fn exchange(server: &ZabbixServer, payload: &[u8]) -> Result<(), String> {
+    let mut stream = TcpStream::connect((server.host.as_str(), server.port))
+        .await
+        .map_err(|e| e.to_string())?;
+    stream
+        .write_all(&frame(payload))
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.len() < 13 || &response[..4] != b"ZBXD" {
+        return Err("invalid response from server".into());
+    }
+    let reply: serde_json::Value =
+        serde_json::from_slice(&response[13..]).map_err(|e| e.to_string())?;
+    match reply["response"].as_str() {
+        Some("success") => {
+            let info = reply["info"].as_str().unwrap_or("");
+            // e.g. "processed: 2; failed: 1; total: 3; seconds spent: 0.000055"
+            match info
+                .split(';')
+                .find_map(|p| p.trim().strip_prefix("failed: "))
+            {
+                Some(failed) if failed != "0" => Err(format!(
+                    "{} item(s) rejected (check host name and trapper item keys)",
+                    failed
+                )),
+                _ => Ok(()),
+            }
+        }
+        _ => Err(format!("server replied {}", reply)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rkik::domain::ntp::{Leap, Target};
+
+    #[test]
+    fn parses_server_with_default_port() {
+        assert_eq!(
+            ZabbixServer::parse("zbx.example").unwrap(),
+            ZabbixServer {
+                host: "zbx.example".into(),
+                port: 10051
+            }
+        );
+        assert_eq!(ZabbixServer::parse("zbx:10052").unwrap().port, 10052);
+        assert_eq!(ZabbixServer::parse("[::1]:10051").unwrap().host, "::1");
+        assert!(ZabbixServer::parse("zbx:port").is_err());
+    }
+
+    #[test]
+    fn request_contains_keyed_items_and_frame_header() {
+        let utc = chrono::DateTime::from_timestamp(1_700_000_000, 5).unwrap();
+        let probe = ProbeResult {
+            target: Target::new("time.example", "192.0.2.1".parse().unwrap(), 123),
+            offset_ms: -1.25,
+            rtt_ms: 10.0,
+            stratum: 2,
+            ref_id: "GPS".into(),
+            utc,
+            local: chrono::DateTime::from(utc),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        };
+        let req = request("ntp-monitor", &[probe]);
+        assert_eq!(req["request"], "sender data");
+        assert_eq!(req["data"][0]["key"], "rkik.offset[time.example]");
+        assert_eq!(req["data"][0]["value"], "-1.250");
+        assert_eq!(req["data"][2]["key"], "rkik.stratum[time.example]");
+        assert_eq!(req["data"][1]["host"], "ntp-monitor");
+        assert_eq!(item_param("a b"), "\"a b\"");
+
+        let framed = frame(b"{}");
+        assert_eq!(&framed[..5], b"ZBXD\x01");
+        assert_eq!(u64::from_le_bytes(framed[5..13].try_into().unwrap()), 2);
+    }
+}
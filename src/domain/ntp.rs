@@ -2,23 +2,327 @@ use chrono::{DateTime, Local, Utc};
 use std::net::IpAddr;
 
 #[cfg(feature = "json")]
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "nts")]
 use crate::adapters::nts_client::{NtsKeData, NtsValidationOutcome};
 
 /// Target host resolved to an IP address.
 #[derive(Clone, Debug)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Target {
     pub name: String,
+    /// Address that was probed.
     pub ip: IpAddr,
+    /// UDP port that was probed (the NTS-KE port for `nts://` targets with one).
     pub port: u16,
+    /// Where `ip` came from.
+    #[cfg_attr(feature = "json", serde(default))]
+    pub resolution: Resolution,
+    /// Every address the name resolved to, `ip` included, in answer order.
+    #[cfg_attr(feature = "json", serde(default))]
+    pub addresses: Vec<IpAddr>,
+}
+
+impl Target {
+    /// A target probed at `ip`, with no record of how it was resolved.
+    pub fn new(name: impl Into<String>, ip: IpAddr, port: u16) -> Self {
+        Self {
+            name: name.into(),
+            ip,
+            port,
+            resolution: Resolution::Unknown,
+            addresses: vec![ip],
+        }
+    }
+
+    /// Address family of `ip`.
+    pub fn family(&self) -> AddressFamily {
+        AddressFamily::of(self.ip)
+    }
+
+    /// `ip:port`, with brackets around IPv6 addresses.
+    pub fn endpoint(&self) -> String {
+        std::net::SocketAddr::new(self.ip, self.port).to_string()
+    }
+}
+
+/// Address family of a probed address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    pub fn of(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(_) => AddressFamily::Ipv4,
+            IpAddr::V6(_) => AddressFamily::Ipv6,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AddressFamily::Ipv4 => "IPv4",
+            AddressFamily::Ipv6 => "IPv6",
+        }
+    }
+}
+
+/// How the address of a [`Target`] was obtained.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Resolution {
+    /// Not recorded, as in results read back from older records.
+    #[default]
+    Unknown,
+    /// The target was an IP address.
+    Literal,
+    /// The system resolver (`/etc/hosts`, nsswitch sources, DNS).
+    System,
+    /// rkik's own DNS lookup.
+    Dns,
+    /// An earlier DNS answer still within its TTL.
+    Cache,
+    /// No address lookup: mock targets, the local clock, chronyd.
+    Synthetic,
+}
+
+impl Resolution {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Resolution::Unknown => "unknown",
+            Resolution::Literal => "literal",
+            Resolution::System => "system",
+            Resolution::Dns => "dns",
+            Resolution::Cache => "cache",
+            Resolution::Synthetic => "synthetic",
+        }
+    }
+}
+
+/// Where the time of one plain NTP probe went.
+///
+/// T1 and T4 are read from the local clock, T2 and T3 from the server's;
+/// the round trip of the result is `(T4 - T1) - processing_ms`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Timing {
+    /// Resolving the name; close to 0 for IP literals and cached answers.
+    pub dns_ms: f64,
+    /// Binding and connecting the socket; close to 0 when an open one was
+    /// reused.
+    pub socket_setup_ms: f64,
+    /// Request sent (client clock).
+    pub t1: DateTime<Utc>,
+    /// Request received (server clock).
+    pub t2: DateTime<Utc>,
+    /// Reply sent (server clock).
+    pub t3: DateTime<Utc>,
+    /// Reply received (client clock).
+    pub t4: DateTime<Utc>,
+    /// Time the server held the request, `T3 - T2`.
+    pub processing_ms: f64,
+    /// T1 to T4 as NTP timestamps, without the rounding to nanoseconds.
+    #[cfg_attr(feature = "json", serde(default))]
+    pub raw: RawTimestamps,
+}
+
+/// The four timestamps of an exchange in the NTP 64-bit format: seconds
+/// since 1900 in the high 32 bits, a binary fraction in the low 32.
+///
+/// JSON writes them as `ntpq` does, `"e8f1a2b3.1c6a7ef9"`, which keeps
+/// every bit where a JSON number would round them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct RawTimestamps {
+    /// T1, the request's transmit timestamp as echoed by the reply.
+    #[cfg_attr(feature = "json", serde(with = "ntp_hex"))]
+    pub origin: u64,
+    /// T2, from the reply.
+    #[cfg_attr(feature = "json", serde(with = "ntp_hex"))]
+    pub receive: u64,
+    /// T3, from the reply.
+    #[cfg_attr(feature = "json", serde(with = "ntp_hex"))]
+    pub transmit: u64,
+    /// T4, the local clock when the reply arrived.
+    #[cfg_attr(feature = "json", serde(with = "ntp_hex"))]
+    pub destination: u64,
+}
+
+/// NTP timestamps as `seconds.fraction` in hexadecimal.
+#[cfg(feature = "json")]
+mod ntp_hex {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(ts: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:08x}.{:08x}", ts >> 32, ts & 0xffff_ffff))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let parse = |part: &str| u32::from_str_radix(part, 16).ok();
+        text.split_once('.')
+            .and_then(|(secs, frac)| Some(((parse(secs)? as u64) << 32) | parse(frac)? as u64))
+            .ok_or_else(|| D::Error::custom(format!("invalid NTP timestamp '{text}'")))
+    }
+}
+
+/// Network identity of the address that answered a probe.
+///
+/// Filled in by [`crate::services::enrich`] (feature `enrich`); each part is
+/// `None` when the lookup had no answer or no database was configured.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Origin {
+    /// PTR name of the address.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub reverse_dns: Option<String>,
+    /// Autonomous system number, from a MaxMind ASN database.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub asn: Option<u32>,
+    /// Organization the AS is registered to.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub as_org: Option<String>,
+    /// ISO 3166-1 country code, from a MaxMind country or city database.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub country: Option<String>,
+}
+
+/// Leap indicator of a server or daemon: the leap second it announces for
+/// the end of the current UTC day, or that it is not synchronised.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Leap {
+    #[default]
+    Normal,
+    InsertSecond,
+    DeleteSecond,
+    Unsynchronised,
+}
+
+impl Leap {
+    /// The two LI bits of an NTP header.
+    pub fn from_indicator(li: u8) -> Self {
+        match li & 0x3 {
+            0 => Leap::Normal,
+            1 => Leap::InsertSecond,
+            2 => Leap::DeleteSecond,
+            _ => Leap::Unsynchronised,
+        }
+    }
+}
+
+/// What the sanity checks on a server's reply make of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum HealthVerdict {
+    /// Nothing wrong, or nothing checked (NTS, local daemons).
+    #[default]
+    Ok,
+    /// Usable time from a server that looks neglected.
+    Suspect,
+    /// The reply cannot be trusted at all.
+    Bogus,
+}
+
+impl HealthVerdict {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthVerdict::Ok => "ok",
+            HealthVerdict::Suspect => "suspect",
+            HealthVerdict::Bogus => "bogus",
+        }
+    }
+}
+
+/// A sign of server misbehaviour found in a reply.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum HealthIssue {
+    /// The transmit timestamp falls in a year no working clock shows.
+    BogusYear { year: i32 },
+    /// Stratum 0 without a Kiss-o'-Death code.
+    StratumZero,
+    /// No reference timestamp: the server never set its clock.
+    NoReference,
+    /// The server last set its clock from its reference this long ago.
+    StaleReference { hours: f64 },
+    /// Root dispersion beyond what a synchronised server reports.
+    HighDispersion { ms: f64 },
+}
+
+impl HealthIssue {
+    /// How much the issue costs the server's credibility.
+    pub fn verdict(&self) -> HealthVerdict {
+        match self {
+            HealthIssue::BogusYear { .. } | HealthIssue::StratumZero => HealthVerdict::Bogus,
+            _ => HealthVerdict::Suspect,
+        }
+    }
+}
+
+impl std::fmt::Display for HealthIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthIssue::BogusYear { year } => write!(f, "time in the year {year}"),
+            HealthIssue::StratumZero => f.write_str("stratum 0 without a Kiss-o'-Death code"),
+            HealthIssue::NoReference => f.write_str("no reference timestamp"),
+            HealthIssue::StaleReference { hours } => {
+                write!(f, "reference last updated {hours:.0} h ago")
+            }
+            HealthIssue::HighDispersion { ms } => write!(f, "root dispersion {ms:.0} ms"),
+        }
+    }
+}
+
+/// Server health drawn from one reply: the issues found and their verdict.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Health {
+    pub verdict: HealthVerdict,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub issues: Vec<HealthIssue>,
+}
+
+impl Health {
+    pub fn from_issues(issues: Vec<HealthIssue>) -> Self {
+        Health {
+            verdict: issues
+                .iter()
+                .map(HealthIssue::verdict)
+                .max()
+                .unwrap_or_default(),
+            issues,
+        }
+    }
 }
 
 /// Result of probing an NTP server.
 #[derive(Clone, Debug)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ProbeResult {
     pub target: Target,
     pub offset_ms: f64,
@@ -29,6 +333,22 @@ pub struct ProbeResult {
     pub local: DateTime<Local>,
     pub timestamp: i64,      // Unix timestamp
     pub authenticated: bool, // Whether NTS authentication was used
+    pub dnssec: bool,        // Whether the target's address was DNSSEC-validated
+    #[cfg_attr(feature = "json", serde(default))]
+    pub leap: Leap, // Leap indicator the server answered with
+    #[cfg_attr(feature = "json", serde(default))]
+    pub health: Health, // Sanity checks on the reply (plain NTP only)
+    #[cfg_attr(feature = "json", serde(default))]
+    pub pause_ms: Option<f64>, // Pause actually slept before this probe, in repeated runs
+    pub origin: Option<Origin>, // Who answered, filled in by verbose enrichment
+    #[cfg_attr(feature = "json", serde(default))]
+    pub timing: Option<Timing>, // Where the time of the probe went (plain NTP only)
+    #[cfg_attr(feature = "json", serde(default))]
+    pub uncertainty_ms: Option<f64>, // Error bound on offset_ms, see Exchange::uncertainty_ms
+    #[cfg_attr(feature = "json", serde(default))]
+    pub clock_step_ms: Option<f64>, // Local clock step since the previous sample of a repeated run
+    #[cfg_attr(feature = "json", serde(default))]
+    pub reference_age_s: Option<f64>, // Time since the server last heard from its reference (plain NTP only)
     #[cfg(feature = "nts")]
     pub nts_ke_data: Option<NtsKeData>, // NTS-KE diagnostic data (only with nts feature)
     #[cfg(feature = "nts")]
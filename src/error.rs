@@ -5,6 +5,25 @@ use serde::{Serialize, Serializer};
 use thiserror::Error;
 
 /// Top-level error type for rkik library.
+///
+/// Each variant has a stable [`kind`](RkikError::kind) string and exit
+/// codes shared by every rkik command:
+///
+/// | Kind            | Retryable | Exit code | Plugin exit code |
+/// |-----------------|-----------|-----------|------------------|
+/// | `dns`           | no        | 2         | 3 (UNKNOWN)      |
+/// | `timeout`       | yes       | 3         | 3 (UNKNOWN)      |
+/// | `refused`       | no        | 3         | 3 (UNKNOWN)      |
+/// | `rate_limited`  | no        | 3         | 3 (UNKNOWN)      |
+/// | `kiss_of_death` | no        | 3         | 3 (UNKNOWN)      |
+/// | `nts`           | no        | 3         | 3 (UNKNOWN)      |
+/// | `auth_failed`   | no        | 3         | 2 (CRITICAL)     |
+/// | `unsynchronized`| no        | 6         | 2 (CRITICAL)     |
+/// | `network`       | yes       | 1         | 3 (UNKNOWN)      |
+/// | `io`            | yes       | 1         | 3 (UNKNOWN)      |
+/// | `protocol`      | no        | 1         | 3 (UNKNOWN)      |
+/// | `cancelled`     | no        | 1         | 3 (UNKNOWN)      |
+/// | `other`         | no        | 1         | 3 (UNKNOWN)      |
 #[derive(Error, Debug)]
 pub enum RkikError {
     /// DNS resolution failure.
@@ -13,18 +32,40 @@ pub enum RkikError {
     /// Network related error.
     #[error("network: {0}")]
     Network(String),
+    /// No answer within the timeout.
+    #[error("timeout")]
+    Timeout,
+    /// The server host rejected the request (ICMP port unreachable).
+    #[error("refused: {0}")]
+    Refused(String),
+    /// The server answered with a RATE Kiss-o'-Death: it asks to be queried less often.
+    #[error("rate limited: {0}")]
+    RateLimited(String),
+    /// The server answered with any other Kiss-o'-Death; the code (`DENY`, `RSTR`…) is kept.
+    #[error("kiss-o'-death: {0}")]
+    KissOfDeath(String),
     /// Protocol violation.
     #[error("protocol: {0}")]
     Protocol(String),
     /// NTS (Network Time Security) error.
     #[error("nts: {0}")]
     Nts(String),
+    /// The response failed authentication: tampered, replayed or unauthenticated after NTS-KE.
+    #[error("auth failed: {0}")]
+    AuthFailed(String),
+    /// The server answered but is not synchronised itself (see
+    /// [`QueryOptions::require_sync`](crate::services::query::QueryOptions::require_sync)).
+    #[error("unsynchronized: {0}")]
+    Unsynchronized(String),
     /// Underlying IO error.
     #[error(transparent)]
     Io(#[from] std::io::Error),
     /// Other error cases.
     #[error("other: {0}")]
     Other(String),
+    /// The probe was abandoned through its cancellation token.
+    #[error("cancelled")]
+    Cancelled,
     /// Error wrapper that carries target context (hostname/IP).
     #[error("{target} - {source}")]
     TargetContext {
@@ -60,10 +101,17 @@ impl RkikError {
         match self.root() {
             Self::Dns(_) => "dns",
             Self::Network(_) => "network",
+            Self::Timeout => "timeout",
+            Self::Refused(_) => "refused",
+            Self::RateLimited(_) => "rate_limited",
+            Self::KissOfDeath(_) => "kiss_of_death",
             Self::Protocol(_) => "protocol",
             Self::Nts(_) => "nts",
+            Self::AuthFailed(_) => "auth_failed",
+            Self::Unsynchronized(_) => "unsynchronized",
             Self::Io(_) => "io",
             Self::Other(_) => "other",
+            Self::Cancelled => "cancelled",
             Self::TargetContext { .. } => unreachable!("root() strips target wrappers"),
         }
     }
@@ -73,10 +121,17 @@ impl RkikError {
         match self.root() {
             Self::Dns(msg)
             | Self::Network(msg)
+            | Self::Refused(msg)
+            | Self::RateLimited(msg)
+            | Self::KissOfDeath(msg)
             | Self::Protocol(msg)
             | Self::Nts(msg)
+            | Self::AuthFailed(msg)
+            | Self::Unsynchronized(msg)
             | Self::Other(msg) => msg.clone(),
+            Self::Timeout => "timeout".into(),
             Self::Io(err) => err.to_string(),
+            Self::Cancelled => "cancelled".into(),
             Self::TargetContext { .. } => unreachable!("root() strips target wrappers"),
         }
     }
@@ -88,7 +143,29 @@ impl RkikError {
 
     /// True when the underlying error is a network timeout.
     pub fn is_network_timeout(&self) -> bool {
-        matches!(self.root(), Self::Network(msg) if msg == "timeout")
+        matches!(self.root(), Self::Timeout)
+    }
+
+    /// True when trying again at once may succeed: timeouts and transient
+    /// network or IO failures. Kiss-o'-Death replies, rate limiting
+    /// included, ask the client to back off and are not retryable.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.root(), Self::Timeout | Self::Network(_) | Self::Io(_))
+    }
+
+    /// True when the server answered with a RATE Kiss-o'-Death.
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self.root(), Self::RateLimited(_))
+    }
+
+    /// True when the probe was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.root(), Self::Cancelled)
+    }
+
+    /// True when the server answered but is not synchronised itself.
+    pub fn is_unsynchronized(&self) -> bool {
+        matches!(self.root(), Self::Unsynchronized(_))
     }
 
     /// True when the underlying error is NTS-related.
@@ -96,6 +173,32 @@ impl RkikError {
         matches!(self.root(), Self::Nts(_))
     }
 
+    /// Process exit code for a run that failed with this error (see the
+    /// table on [`RkikError`]).
+    pub fn exit_code(&self) -> i32 {
+        match self.root() {
+            Self::Dns(_) => 2,
+            Self::Timeout
+            | Self::Refused(_)
+            | Self::RateLimited(_)
+            | Self::KissOfDeath(_)
+            | Self::Nts(_)
+            | Self::AuthFailed(_) => 3,
+            Self::Unsynchronized(_) => 6,
+            _ => 1,
+        }
+    }
+
+    /// Monitoring plugin state for a check that failed with this error:
+    /// CRITICAL (2) when the answer failed authentication or came from an
+    /// unsynchronised server, UNKNOWN (3) otherwise.
+    pub fn plugin_exit_code(&self) -> i32 {
+        match self.root() {
+            Self::AuthFailed(_) | Self::Unsynchronized(_) => 2,
+            _ => 3,
+        }
+    }
+
     /// Serialize this error as JSON text.
     #[cfg(feature = "json")]
     pub fn to_json_string(&self, pretty: bool) -> Result<String, serde_json::Error> {
@@ -106,6 +209,14 @@ impl RkikError {
         }
     }
 
+    /// Error for a Kiss-o'-Death carrying the four-letter `code`.
+    pub(crate) fn kiss_of_death_code(code: &str) -> Self {
+        match code {
+            "RATE" => Self::RateLimited("RATE".into()),
+            code => Self::KissOfDeath(code.into()),
+        }
+    }
+
     fn root(&self) -> &Self {
         match self {
             Self::TargetContext { source, .. } => source.root(),
@@ -130,26 +241,41 @@ impl Serialize for RkikError {
     }
 }
 
-impl From<rsntp::SynchronizationError> for RkikError {
-    fn from(err: rsntp::SynchronizationError) -> Self {
-        match err {
-            rsntp::SynchronizationError::IOError(e) => RkikError::Network(e.to_string()),
-            rsntp::SynchronizationError::ProtocolError(e) => RkikError::Protocol(e.to_string()),
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::RkikError;
 
     #[test]
     fn with_target_wraps_display_and_preserves_kind() {
-        let err = RkikError::Network("timeout".into()).with_target("192.168.1.100");
-        assert_eq!(err.to_string(), "192.168.1.100 - network: timeout");
+        let err = RkikError::Network("unreachable".into()).with_target("192.168.1.100");
+        assert_eq!(err.to_string(), "192.168.1.100 - network: unreachable");
         assert_eq!(err.target(), Some("192.168.1.100"));
         assert_eq!(err.kind(), "network");
-        assert_eq!(err.message(), "timeout");
+        assert_eq!(err.message(), "unreachable");
+    }
+
+    #[test]
+    fn taxonomy_drives_retries_and_exit_codes() {
+        let timeout = RkikError::Timeout.with_target("192.0.2.1");
+        assert_eq!(timeout.kind(), "timeout");
+        assert!(timeout.is_network_timeout() && timeout.is_retryable());
+        assert_eq!((timeout.exit_code(), timeout.plugin_exit_code()), (3, 3));
+
+        let rate = RkikError::kiss_of_death_code("RATE");
+        assert_eq!(rate.kind(), "rate_limited");
+        assert!(!rate.is_retryable());
+        let deny = RkikError::kiss_of_death_code("DENY");
+        assert_eq!(deny.to_string(), "kiss-o'-death: DENY");
+
+        let refused = RkikError::Refused("connection refused".into());
+        assert_eq!(refused.kind(), "refused");
+
+        let auth = RkikError::AuthFailed("AEAD verification failed".into());
+        assert_eq!((auth.exit_code(), auth.plugin_exit_code()), (3, 2));
+        let unsynced = RkikError::Unsynchronized("stratum 16".into());
+        assert_eq!((unsynced.exit_code(), unsynced.plugin_exit_code()), (6, 2));
+        assert_eq!(RkikError::Dns("nxdomain".into()).exit_code(), 2);
+        assert_eq!(RkikError::Protocol("short".into()).exit_code(), 1);
     }
 
     #[cfg(feature = "json")]
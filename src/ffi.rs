@@ -0,0 +1,433 @@
+//! C bindings (feature = "ffi") for agents written in C, Go or Python.
+//!
+//! Build the shared library with `cargo build --release --features ffi`
+//! and include `include/rkik.h`. Every call blocks on an internal Tokio runtime.
+//! Results are opaque handles owned by the caller and released with their
+//! `_free` function; the strings they return stay valid until then.
+
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+
+use crate::domain::ntp::ProbeResult;
+use crate::error::RkikError;
+use crate::services::compare::compare;
+use crate::services::query::{QueryOptions, query};
+
+/// Probe settings, see [`QueryOptions`]. Start from [`rkik_options_default`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RkikOptions {
+    pub timeout_ms: u32,
+    pub ipv6_only: bool,
+    pub nts: bool,
+    pub nts_port: u16,
+    /// NTP port used when a target names none.
+    pub port: u16,
+    pub retries: u32,
+}
+
+impl From<&RkikOptions> for QueryOptions {
+    fn from(o: &RkikOptions) -> Self {
+        QueryOptions::new()
+            .timeout(Duration::from_millis(o.timeout_ms.into()))
+            .ipv6_only(o.ipv6_only)
+            .nts(o.nts)
+            .nts_port(o.nts_port)
+            .port(o.port)
+            .retries(o.retries)
+    }
+}
+
+/// Outcome of one probe: a measurement or an error.
+pub struct RkikResult {
+    outcome: Result<ProbeResult, RkikError>,
+    target: CString,
+    ip: CString,
+    ref_id: CString,
+    error: Option<CString>,
+    kind: Option<CString>,
+}
+
+impl RkikResult {
+    fn new(target: &str, outcome: Result<ProbeResult, RkikError>) -> Self {
+        let (ip, ref_id, error, kind) = match &outcome {
+            Ok(r) => (r.target.ip.to_string(), r.ref_id.clone(), None, None),
+            Err(e) => (
+                String::new(),
+                String::new(),
+                Some(c_string(e.to_string())),
+                Some(c_string(e.kind())),
+            ),
+        };
+        Self {
+            target: c_string(target),
+            ip: c_string(ip),
+            ref_id: c_string(ref_id),
+            error,
+            kind,
+            outcome,
+        }
+    }
+}
+
+/// Results of a comparison, one per target in input order.
+pub struct RkikCompare {
+    entries: Vec<RkikResult>,
+    error: Option<CString>,
+}
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("cannot start the rkik runtime")
+    })
+}
+
+/// Interior NUL bytes cannot cross the C boundary; they are dropped.
+fn c_string(s: impl Into<String>) -> CString {
+    let mut bytes = s.into().into_bytes();
+    bytes.retain(|&b| b != 0);
+    CString::new(bytes).unwrap_or_default()
+}
+
+unsafe fn options(options: *const RkikOptions) -> QueryOptions {
+    // SAFETY: the caller passes NULL or a valid `RkikOptions`.
+    match unsafe { options.as_ref() } {
+        Some(o) => o.into(),
+        None => QueryOptions::new(),
+    }
+}
+
+unsafe fn text<'a>(s: *const c_char) -> Result<&'a str, RkikError> {
+    if s.is_null() {
+        return Err(RkikError::Other("target is NULL".into()));
+    }
+    // SAFETY: the caller passes a NUL-terminated string.
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|_| RkikError::Other("target is not valid UTF-8".into()))
+}
+
+unsafe fn probe<'a>(r: *const RkikResult) -> Option<&'a ProbeResult> {
+    // SAFETY: the caller passes NULL or a live `RkikResult`.
+    unsafe { r.as_ref() }.and_then(|r| r.outcome.as_ref().ok())
+}
+
+/// Default probe settings: 5 s timeout, IPv4, plain NTP, ports 123 and 4460, no retry.
+#[unsafe(no_mangle)]
+pub extern "C" fn rkik_options_default() -> RkikOptions {
+    let o = QueryOptions::new();
+    RkikOptions {
+        timeout_ms: o.timeout.as_millis().try_into().unwrap_or(u32::MAX),
+        ipv6_only: o.ipv6_only,
+        nts: o.nts,
+        nts_port: o.nts_port,
+        port: o.port,
+        retries: o.retries,
+    }
+}
+
+/// Query one target (`host`, `host:port`, `nts://host`...).
+///
+/// Never returns NULL; failures are reported by [`rkik_result_error`].
+///
+/// # Safety
+///
+/// `target` must be a NUL-terminated string; `options` NULL or a valid
+/// pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_query(
+    target: *const c_char,
+    options: *const RkikOptions,
+) -> *mut RkikResult {
+    let opts = unsafe { self::options(options) };
+    let result = match unsafe { text(target) } {
+        Ok(t) => RkikResult::new(t, runtime().block_on(query(t, &opts))),
+        Err(e) => RkikResult::new("", Err(e)),
+    };
+    Box::into_raw(Box::new(result))
+}
+
+/// Query `count` targets concurrently.
+///
+/// Never returns NULL; when fewer than two targets answer the comparison
+/// has no entries and [`rkik_compare_error`] says why.
+///
+/// # Safety
+///
+/// `targets` must point to `count` NUL-terminated strings; `options` NULL
+/// or a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_compare(
+    targets: *const *const c_char,
+    count: usize,
+    options: *const RkikOptions,
+) -> *mut RkikCompare {
+    let opts = unsafe { self::options(options) };
+    let names: Result<Vec<String>, RkikError> = if targets.is_null() {
+        Err(RkikError::Other("targets is NULL".into()))
+    } else {
+        // SAFETY: the caller passes `count` string pointers.
+        unsafe { std::slice::from_raw_parts(targets, count) }
+            .iter()
+            .map(|&t| unsafe { text(t) }.map(str::to_string))
+            .collect()
+    };
+    let outcome = names.and_then(|names| runtime().block_on(compare(&names, &opts)));
+    let cmp = match outcome {
+        Ok(outcome) => RkikCompare {
+            entries: outcome
+                .entries
+                .into_iter()
+                .map(|(target, res)| RkikResult::new(&target, res))
+                .collect(),
+            error: None,
+        },
+        Err(e) => RkikCompare {
+            entries: Vec::new(),
+            error: Some(c_string(e.to_string())),
+        },
+    };
+    Box::into_raw(Box::new(cmp))
+}
+
+/// Number of entries of a comparison.
+///
+/// # Safety
+///
+/// `cmp` must be NULL or returned by [`rkik_compare`] and not yet freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_compare_len(cmp: *const RkikCompare) -> usize {
+    unsafe { cmp.as_ref() }.map_or(0, |c| c.entries.len())
+}
+
+/// Entry `index` of a comparison, borrowed: do not free it. NULL when out of range.
+///
+/// # Safety
+///
+/// `cmp` must be NULL or returned by [`rkik_compare`] and not yet freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_compare_get(
+    cmp: *const RkikCompare,
+    index: usize,
+) -> *const RkikResult {
+    unsafe { cmp.as_ref() }
+        .and_then(|c| c.entries.get(index))
+        .map_or(ptr::null(), |r| r as *const RkikResult)
+}
+
+/// Why the whole comparison failed, or NULL.
+///
+/// # Safety
+///
+/// `cmp` must be NULL or returned by [`rkik_compare`] and not yet freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_compare_error(cmp: *const RkikCompare) -> *const c_char {
+    unsafe { cmp.as_ref() }
+        .and_then(|c| c.error.as_ref())
+        .map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// Release a comparison and all its entries.
+///
+/// # Safety
+///
+/// `cmp` must be NULL or returned by [`rkik_compare`], and is invalid afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_compare_free(cmp: *mut RkikCompare) {
+    if !cmp.is_null() {
+        drop(unsafe { Box::from_raw(cmp) });
+    }
+}
+
+/// True when the probe succeeded.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_ok(r: *const RkikResult) -> bool {
+    unsafe { probe(r) }.is_some()
+}
+
+/// Error message of a failed probe (`"<target> - <kind>: <message>"`), or NULL.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_error(r: *const RkikResult) -> *const c_char {
+    unsafe { r.as_ref() }
+        .and_then(|r| r.error.as_ref())
+        .map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// Stable error kind of a failed probe (`"timeout"`, `"dns"`...), or NULL.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_error_kind(r: *const RkikResult) -> *const c_char {
+    unsafe { r.as_ref() }
+        .and_then(|r| r.kind.as_ref())
+        .map_or(ptr::null(), |k| k.as_ptr())
+}
+
+/// Exit code the rkik CLI would use for this failure, 0 on success.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_exit_code(r: *const RkikResult) -> i32 {
+    match unsafe { r.as_ref() }.map(|r| &r.outcome) {
+        Some(Ok(_)) => 0,
+        Some(Err(e)) => e.exit_code(),
+        None => 1,
+    }
+}
+
+/// Target as given to [`rkik_query`] or [`rkik_compare`].
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_target(r: *const RkikResult) -> *const c_char {
+    unsafe { r.as_ref() }.map_or(ptr::null(), |r| r.target.as_ptr())
+}
+
+/// Address that answered, empty on failure.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_ip(r: *const RkikResult) -> *const c_char {
+    unsafe { r.as_ref() }.map_or(ptr::null(), |r| r.ip.as_ptr())
+}
+
+/// Clock offset in ms, NaN on failure.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_offset_ms(r: *const RkikResult) -> f64 {
+    unsafe { probe(r) }.map_or(f64::NAN, |p| p.offset_ms)
+}
+
+/// Round-trip delay in ms, NaN on failure.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_rtt_ms(r: *const RkikResult) -> f64 {
+    unsafe { probe(r) }.map_or(f64::NAN, |p| p.rtt_ms)
+}
+
+/// Stratum of the server, 0 on failure.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_stratum(r: *const RkikResult) -> u8 {
+    unsafe { probe(r) }.map_or(0, |p| p.stratum)
+}
+
+/// Reference identifier, empty on failure.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_ref_id(r: *const RkikResult) -> *const c_char {
+    unsafe { r.as_ref() }.map_or(ptr::null(), |r| r.ref_id.as_ptr())
+}
+
+/// Unix time of the measurement, 0 on failure.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_timestamp(r: *const RkikResult) -> i64 {
+    unsafe { probe(r) }.map_or(0, |p| p.timestamp)
+}
+
+/// True when the answer was authenticated with NTS.
+///
+/// # Safety
+///
+/// `r` must be NULL or a live result.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_authenticated(r: *const RkikResult) -> bool {
+    unsafe { probe(r) }.is_some_and(|p| p.authenticated)
+}
+
+/// Release a result returned by [`rkik_query`]; entries of a comparison are
+/// released with it instead.
+///
+/// # Safety
+///
+/// `r` must be NULL or returned by [`rkik_query`], and is invalid afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rkik_result_free(r: *mut RkikResult) {
+    if !r.is_null() {
+        drop(unsafe { Box::from_raw(r) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::compare::LOCAL_TARGET;
+
+    #[test]
+    fn failed_query_reports_kind_and_exit_code() {
+        let mut opts = rkik_options_default();
+        assert_eq!((opts.timeout_ms, opts.port), (5000, 123));
+        opts.timeout_ms = 200;
+        unsafe {
+            let r = rkik_query(ptr::null(), &opts);
+            assert!(!rkik_result_ok(r));
+            assert_eq!(
+                CStr::from_ptr(rkik_result_error_kind(r)).to_str(),
+                Ok("other")
+            );
+            assert_eq!(rkik_result_exit_code(r), 1);
+            assert!(rkik_result_offset_ms(r).is_nan());
+            rkik_result_free(r);
+        }
+    }
+
+    #[test]
+    fn compare_exposes_entries_in_order() {
+        let local = CString::new(LOCAL_TARGET).unwrap();
+        let targets = [local.as_ptr(), local.as_ptr()];
+        unsafe {
+            let cmp = rkik_compare(targets.as_ptr(), targets.len(), ptr::null());
+            assert!(rkik_compare_error(cmp).is_null());
+            assert_eq!(rkik_compare_len(cmp), 2);
+            let first = rkik_compare_get(cmp, 0);
+            assert!(rkik_result_ok(first));
+            assert_eq!(rkik_result_offset_ms(first), 0.0);
+            assert_eq!(
+                CStr::from_ptr(rkik_result_ref_id(first)).to_str(),
+                Ok("LOCL")
+            );
+            assert!(rkik_compare_get(cmp, 2).is_null());
+            rkik_compare_free(cmp);
+        }
+    }
+}
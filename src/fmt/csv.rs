@@ -1,5 +1,7 @@
 use crate::domain::ntp::ProbeResult;
 use crate::error::RkikError;
+use crate::fmt::fields::Field;
+use crate::fmt::units::display_unit;
 use std::fmt::Write as FmtWrite;
 
 fn escape_csv(s: &str) -> String {
@@ -12,14 +14,25 @@ fn escape_csv(s: &str) -> String {
 
 pub const HEADER: &str = "target,stratum,offset_ms,delay_ms,timestamp";
 
+/// Header line for the default columns, using the current display unit.
+pub fn header() -> String {
+    let unit = display_unit().fixed();
+    format!("target,stratum,offset_{unit},delay_{unit},timestamp")
+}
+
 pub fn rows(results: &[ProbeResult]) -> Result<String, RkikError> {
+    let unit = display_unit();
     let mut out = String::new();
     for r in results {
         let target = escape_csv(&r.target.name);
         writeln!(
             &mut out,
-            "{},{},{:.3},{:.3},{}",
-            target, r.stratum, r.offset_ms, r.rtt_ms, r.timestamp
+            "{},{},{},{},{}",
+            target,
+            r.stratum,
+            unit.format_value(r.offset_ms),
+            unit.format_value(r.rtt_ms),
+            r.timestamp
         )
         .map_err(|e| RkikError::Other(e.to_string()))?;
     }
@@ -27,15 +40,44 @@ pub fn rows(results: &[ProbeResult]) -> Result<String, RkikError> {
 }
 
 pub fn to_csv(results: &[ProbeResult]) -> Result<String, RkikError> {
-    let mut out = format!("{}\n", HEADER);
+    let mut out = format!("{}\n", header());
     out.push_str(&rows(results)?);
     Ok(out)
 }
 
+/// Header line for a custom field selection.
+pub fn fields_header(fields: &[Field]) -> String {
+    fields
+        .iter()
+        .map(Field::column)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// CSV rows restricted to the selected fields (no header).
+pub fn rows_fields(results: &[ProbeResult], fields: &[Field]) -> Result<String, RkikError> {
+    let mut out = String::new();
+    for r in results {
+        let line = fields
+            .iter()
+            .map(|f| escape_csv(&f.value(r).to_string()))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(&mut out, "{}", line).map_err(|e| RkikError::Other(e.to_string()))?;
+    }
+    Ok(out)
+}
+
+pub fn to_csv_fields(results: &[ProbeResult], fields: &[Field]) -> Result<String, RkikError> {
+    let mut out = format!("{}\n", fields_header(fields));
+    out.push_str(&rows_fields(results, fields)?);
+    Ok(out)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::ntp::Target;
+    use crate::domain::ntp::{Leap, Target};
     use std::net::IpAddr;
 
     fn sample_probe(
@@ -48,11 +90,7 @@ mod tests {
         let utc = chrono::Utc::now();
         let local = chrono::DateTime::from(utc);
         ProbeResult {
-            target: Target {
-                name: name.into(),
-                ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
-                port: 123,
-            },
+            target: Target::new(name, "127.0.0.1".parse::<IpAddr>().unwrap(), 123),
             offset_ms,
             rtt_ms,
             stratum,
@@ -61,6 +99,15 @@ mod tests {
             local,
             timestamp,
             authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
             #[cfg(feature = "nts")]
             nts_ke_data: None,
             #[cfg(feature = "nts")]
@@ -99,4 +146,11 @@ mod tests {
             "target,stratum,offset_ms,delay_ms,timestamp\n\"server,with\"\"quotes\nand,commas\",3,0.000,0.000,0\n"
         );
     }
+
+    #[test]
+    fn selected_fields_drive_header_and_columns() {
+        let p = sample_probe("time.google.com", 1, 1.234, 15.678, 1680000000);
+        let csv = to_csv_fields(&[p], &[Field::Offset, Field::Name]).unwrap();
+        assert_eq!(csv, "offset_ms,name\n1.234,time.google.com\n");
+    }
 }
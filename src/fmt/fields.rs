@@ -0,0 +1,169 @@
+//! Column selection for machine-oriented outputs (`--fields`).
+
+use std::str::FromStr;
+
+use crate::domain::ntp::ProbeResult;
+use crate::error::RkikError;
+use crate::fmt::units::display_unit;
+
+/// A single column that can be selected for JSON, CSV or short output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Ip,
+    Port,
+    Offset,
+    Rtt,
+    Stratum,
+    RefId,
+    Utc,
+    Local,
+    Timestamp,
+    Authenticated,
+    Dnssec,
+}
+
+/// Value of a [`Field`] extracted from a probe.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Text(String),
+    Float(f64),
+    Int(i64),
+    Bool(bool),
+}
+
+impl Field {
+    /// Every supported field, in canonical order.
+    pub const ALL: [Field; 12] = [
+        Field::Name,
+        Field::Ip,
+        Field::Port,
+        Field::Offset,
+        Field::Rtt,
+        Field::Stratum,
+        Field::RefId,
+        Field::Utc,
+        Field::Local,
+        Field::Timestamp,
+        Field::Authenticated,
+        Field::Dnssec,
+    ];
+
+    /// Column key used in JSON objects and CSV headers.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Ip => "ip",
+            Field::Port => "port",
+            Field::Offset => "offset_ms",
+            Field::Rtt => "rtt_ms",
+            Field::Stratum => "stratum",
+            Field::RefId => "ref_id",
+            Field::Utc => "utc",
+            Field::Local => "local",
+            Field::Timestamp => "timestamp",
+            Field::Authenticated => "authenticated",
+            Field::Dnssec => "dnssec",
+        }
+    }
+
+    /// Column name including the current display unit (e.g. `offset_us`).
+    pub fn column(&self) -> String {
+        match self {
+            Field::Offset => format!("offset_{}", display_unit().fixed()),
+            Field::Rtt => format!("rtt_{}", display_unit().fixed()),
+            other => other.key().to_string(),
+        }
+    }
+
+    /// Extract this field from a probe result, converting durations to the display unit.
+    pub fn value(&self, r: &ProbeResult) -> FieldValue {
+        match self {
+            Field::Name => FieldValue::Text(r.target.name.clone()),
+            Field::Ip => FieldValue::Text(r.target.ip.to_string()),
+            Field::Port => FieldValue::Int(r.target.port as i64),
+            Field::Offset => FieldValue::Float(display_unit().from_ms(r.offset_ms)),
+            Field::Rtt => FieldValue::Float(display_unit().from_ms(r.rtt_ms)),
+            Field::Stratum => FieldValue::Int(r.stratum as i64),
+            Field::RefId => FieldValue::Text(r.ref_id.clone()),
+            Field::Utc => FieldValue::Text(r.utc.to_rfc3339()),
+            Field::Local => FieldValue::Text(r.local.format("%Y-%m-%d %H:%M:%S").to_string()),
+            Field::Timestamp => FieldValue::Int(r.timestamp),
+            Field::Authenticated => FieldValue::Bool(r.authenticated),
+            Field::Dnssec => FieldValue::Bool(r.dnssec),
+        }
+    }
+
+    /// Parse a comma separated list such as `offset,rtt,stratum`.
+    pub fn parse_list(s: &str) -> Result<Vec<Field>, RkikError> {
+        let fields = s
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(Field::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if fields.is_empty() {
+            return Err(RkikError::Other("empty field list".into()));
+        }
+        Ok(fields)
+    }
+}
+
+impl FromStr for Field {
+    type Err = RkikError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "name" | "target" | "server" => Ok(Field::Name),
+            "ip" | "addr" => Ok(Field::Ip),
+            "port" => Ok(Field::Port),
+            "offset" | "offset_ms" => Ok(Field::Offset),
+            "rtt" | "rtt_ms" | "delay" | "delay_ms" => Ok(Field::Rtt),
+            "stratum" => Ok(Field::Stratum),
+            "ref_id" | "refid" => Ok(Field::RefId),
+            "utc" => Ok(Field::Utc),
+            "local" => Ok(Field::Local),
+            "timestamp" | "ts" => Ok(Field::Timestamp),
+            "authenticated" | "auth" => Ok(Field::Authenticated),
+            "dnssec" => Ok(Field::Dnssec),
+            other => Err(RkikError::Other(format!(
+                "unknown field '{other}' (expected one of: {})",
+                Field::ALL
+                    .iter()
+                    .map(Field::key)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Text(s) => write!(f, "{s}"),
+            FieldValue::Float(v) => write!(f, "{v:.3}"),
+            FieldValue::Int(v) => write!(f, "{v}"),
+            FieldValue::Bool(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_list_accepts_aliases_and_keeps_order() {
+        let fields = Field::parse_list("rtt, offset,stratum").unwrap();
+        assert_eq!(fields, vec![Field::Rtt, Field::Offset, Field::Stratum]);
+        assert_eq!(Field::parse_list("delay").unwrap(), vec![Field::Rtt]);
+    }
+
+    #[test]
+    fn parse_list_rejects_unknown_field() {
+        let err = Field::parse_list("offset,bogus").unwrap_err();
+        assert!(err.to_string().contains("unknown field 'bogus'"));
+        assert!(Field::parse_list(" , ").is_err());
+    }
+}
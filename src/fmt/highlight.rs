@@ -0,0 +1,97 @@
+//! Threshold colouring of offsets and delays in text output (`--highlight`).
+//!
+//! Like the display unit, this is a process-wide setting: the CLI installs
+//! a classifier once and the text renderers colour values green, yellow or
+//! red by what it returns.
+
+use std::sync::RwLock;
+
+use console::{StyledObject, style};
+
+use crate::fmt::units::format_ms;
+
+/// Value being judged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Absolute clock offset, in ms.
+    Offset,
+    /// Round-trip delay, in ms.
+    Rtt,
+}
+
+/// Verdict of a classifier for one value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Critical,
+}
+
+type Classifier = Box<dyn Fn(Metric, f64) -> Option<Severity> + Send + Sync>;
+
+static CLASSIFIER: RwLock<Option<Classifier>> = RwLock::new(None);
+
+/// Colour offsets and delays by `classify`, which returns `None` for metrics
+/// it has no thresholds for. Offsets are passed as absolute values.
+pub fn set_highlight(classify: impl Fn(Metric, f64) -> Option<Severity> + Send + Sync + 'static) {
+    *CLASSIFIER.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(classify));
+}
+
+/// Severity of `value_ms`, when highlighting is on and covers `metric`.
+pub fn severity(metric: Metric, value_ms: f64) -> Option<Severity> {
+    let value = match metric {
+        Metric::Offset => value_ms.abs(),
+        Metric::Rtt => value_ms,
+    };
+    CLASSIFIER
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(|classify| classify(metric, value))
+}
+
+/// `text` coloured by severity, or by `fallback` when not highlighted.
+pub fn paint(
+    metric: Metric,
+    value_ms: f64,
+    text: String,
+    fallback: fn(StyledObject<String>) -> StyledObject<String>,
+) -> StyledObject<String> {
+    let styled = style(text);
+    match severity(metric, value_ms) {
+        Some(Severity::Ok) => styled.green(),
+        Some(Severity::Warning) => styled.yellow().bold(),
+        Some(Severity::Critical) => styled.red().bold(),
+        None => fallback(styled),
+    }
+}
+
+/// An offset in the display unit, yellow unless highlighted.
+pub fn offset(ms: f64) -> StyledObject<String> {
+    paint(Metric::Offset, ms, format_ms(ms), StyledObject::yellow)
+}
+
+/// A round-trip delay in the display unit, plain unless highlighted.
+pub fn rtt(ms: f64) -> StyledObject<String> {
+    paint(Metric::Rtt, ms, format_ms(ms), |s| s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifier_sees_absolute_offsets() {
+        set_highlight(|metric, value| match metric {
+            Metric::Offset if value >= 100.0 => Some(Severity::Critical),
+            Metric::Offset if value >= 10.0 => Some(Severity::Warning),
+            Metric::Offset => Some(Severity::Ok),
+            Metric::Rtt => None,
+        });
+        assert_eq!(severity(Metric::Offset, -150.0), Some(Severity::Critical));
+        assert_eq!(severity(Metric::Offset, 12.0), Some(Severity::Warning));
+        assert_eq!(severity(Metric::Offset, -0.5), Some(Severity::Ok));
+        assert_eq!(severity(Metric::Rtt, 500.0), None);
+        assert_eq!(offset(-150.0).to_string().trim(), "-150.000 ms");
+    }
+}
@@ -1,10 +1,44 @@
 use chrono::Utc;
 #[cfg(feature = "json")]
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "json")]
+use std::net::IpAddr;
 
+#[cfg(feature = "json")]
+use crate::adapters::chrony::{Source, Tracking};
+#[cfg(feature = "json")]
+use crate::adapters::http_date::HttpDateSample;
+#[cfg(feature = "json")]
+use crate::adapters::kernel::KernelClock;
+#[cfg(feature = "json")]
+use crate::adapters::ntpd::{Peer, SystemVars};
+#[cfg(feature = "json")]
+use crate::adapters::w32time::W32TimeStatus;
 use crate::domain::ntp::ProbeResult;
+#[cfg(feature = "json")]
+use crate::domain::ntp::{AddressFamily, Health, Leap, Origin, Resolution, Timing};
 use crate::error::RkikError;
-use crate::stats::Stats;
+use crate::fmt::fields::Field;
+#[cfg(feature = "json")]
+use crate::fmt::fields::FieldValue;
+#[cfg(feature = "json")]
+use crate::fmt::units::{TimeUnit, display_unit};
+#[cfg(feature = "json")]
+use crate::services::asymmetry::Asymmetry;
+#[cfg(feature = "json")]
+use crate::services::clock_check::ClockCheck;
+#[cfg(feature = "json")]
+use crate::services::diag::Diagnosis;
+use crate::services::discover::Advertised;
+#[cfg(feature = "json")]
+use crate::services::divergence::DivergenceEvent;
+#[cfg(all(feature = "json", feature = "nts"))]
+use crate::services::nts_ke::NtsKeReport;
+#[cfg(feature = "json")]
+use crate::services::recommend::Recommendation;
+#[cfg(feature = "json")]
+use crate::services::survey::Survey;
+use crate::stats::{DriftMatrix, Stats};
 
 #[cfg(all(feature = "json", feature = "nts"))]
 use crate::adapters::nts_client::{NtsKeData, NtsValidationOutcome};
@@ -12,14 +46,39 @@ use crate::adapters::nts_client::{NtsKeData, NtsValidationOutcome};
 // NtsValidationOutcome, NtsError, and NtsErrorKind already derive Serialize,
 // so we can serialize them directly without wrapper types.
 
+/// Version written as `schema_version` in every JSON document rkik emits.
+///
+/// Adding a field does not change it: readers must ignore keys they do not
+/// know. It is bumped when a field is removed or renamed, or when its type,
+/// unit or meaning changes. [`parse_run`] reads every version up to this
+/// one and rejects newer documents.
+pub const SCHEMA_VERSION: u8 = 1;
+
 #[cfg(feature = "json")]
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonProbe {
     pub name: String,
     pub ip: String,
     pub port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<AddressFamily>,
+    /// How `ip` was obtained (verbose).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<Resolution>,
+    /// Every address the name resolved to (verbose).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub addresses: Option<Vec<IpAddr>>,
     pub offset_ms: f64,
     pub rtt_ms: f64,
+    /// Display unit requested with `--unit` (omitted for milliseconds).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// `offset_ms` converted to `unit`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+    /// `rtt_ms` converted to `unit`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stratum: Option<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -29,6 +88,31 @@ pub struct JsonProbe {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<i64>,
     pub authenticated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dnssec: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leap: Option<Leap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<Health>,
+    /// Actual pause before this probe (ms), in `--count`/`--infinite` runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Origin>,
+    /// DNS, socket and server time of the probe, with T1 to T4 (verbose).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<Timing>,
+    /// Bound on the error of `offset_ms`: the true offset lies within
+    /// `offset_ms ± uncertainty_ms`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uncertainty_ms: Option<f64>,
+    /// Step of the local clock since the previous sample, which is left
+    /// out of the statistics (ms).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_step_ms: Option<f64>,
+    /// Seconds since the server last heard from its reference (verbose).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_age_s: Option<f64>,
     #[cfg(feature = "nts")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nts_ke_data: Option<NtsKeData>,
@@ -38,7 +122,7 @@ pub struct JsonProbe {
 }
 
 #[cfg(feature = "json")]
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRun {
     pub schema_version: u8,
     pub run_ts: String,
@@ -50,6 +134,8 @@ pub struct JsonRun {
 pub fn to_json(results: &[ProbeResult], pretty: bool, verbose: bool) -> Result<String, RkikError> {
     #[cfg(feature = "json")]
     {
+        let unit = display_unit().fixed();
+        let converted = unit != TimeUnit::Ms;
         let probes = results
             .iter()
             .map(|r| {
@@ -64,8 +150,14 @@ pub fn to_json(results: &[ProbeResult], pretty: bool, verbose: bool) -> Result<S
                     name: r.target.name.clone(),
                     ip: r.target.ip.to_string(),
                     port: r.target.port,
+                    family: Some(r.target.family()),
+                    resolution: verbose.then_some(r.target.resolution),
+                    addresses: verbose.then(|| r.target.addresses.clone()),
                     offset_ms: r.offset_ms,
                     rtt_ms: r.rtt_ms,
+                    unit: converted.then(|| unit.as_str().to_string()),
+                    offset: converted.then(|| unit.from_ms(r.offset_ms)),
+                    rtt: converted.then(|| unit.from_ms(r.rtt_ms)),
                     utc: r.utc.to_rfc3339(),
                     local: r.local.format("%Y-%m-%d %H:%M:%S").to_string(),
                     stratum: if verbose { Some(r.stratum) } else { None },
@@ -76,6 +168,19 @@ pub fn to_json(results: &[ProbeResult], pretty: bool, verbose: bool) -> Result<S
                     },
                     timestamp: if verbose { Some(r.timestamp) } else { None },
                     authenticated: r.authenticated,
+                    dnssec: if verbose { Some(r.dnssec) } else { None },
+                    leap: if verbose { Some(r.leap) } else { None },
+                    health: if verbose {
+                        Some(r.health.clone())
+                    } else {
+                        None
+                    },
+                    pause_ms: r.pause_ms,
+                    origin: if verbose { r.origin.clone() } else { None },
+                    timing: if verbose { r.timing } else { None },
+                    uncertainty_ms: r.uncertainty_ms,
+                    clock_step_ms: r.clock_step_ms,
+                    reference_age_s: if verbose { r.reference_age_s } else { None },
                     #[cfg(feature = "nts")]
                     nts_ke_data: if verbose { r.nts_ke_data.clone() } else { None },
                     #[cfg(feature = "nts")]
@@ -85,7 +190,7 @@ pub fn to_json(results: &[ProbeResult], pretty: bool, verbose: bool) -> Result<S
             .collect();
 
         let run = JsonRun {
-            schema_version: 1,
+            schema_version: SCHEMA_VERSION,
             run_ts: Utc::now().to_rfc3339(),
             results: probes,
         };
@@ -107,7 +212,7 @@ pub fn to_json(results: &[ProbeResult], pretty: bool, verbose: bool) -> Result<S
 }
 
 #[cfg(feature = "json")]
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonSimpleProbe {
     pub utc: String,
     pub name: String,
@@ -122,6 +227,70 @@ struct JsonSimpleRun {
     results: Vec<JsonSimpleProbe>,
 }
 
+/// Read back a document written by [`to_json`] (`rkik -j`).
+///
+/// Unknown keys are ignored; a `schema_version` newer than
+/// [`SCHEMA_VERSION`] is an error.
+#[cfg(feature = "json")]
+pub fn parse_run(text: &str) -> Result<JsonRun, RkikError> {
+    let run: JsonRun = serde_json::from_str(text).map_err(|e| RkikError::Other(e.to_string()))?;
+    if run.schema_version > SCHEMA_VERSION {
+        return Err(RkikError::Other(format!(
+            "unsupported schema_version {} (this rkik reads up to {SCHEMA_VERSION})",
+            run.schema_version
+        )));
+    }
+    Ok(run)
+}
+
+/// Rebuild the probe a JSON result came from.
+///
+/// Fields only written in verbose mode fall back to defaults (stratum 0,
+/// empty reference id, timestamp from `utc`); `local` is `utc` in the local
+/// time zone, the printed value having none.
+#[cfg(feature = "json")]
+impl TryFrom<JsonProbe> for ProbeResult {
+    type Error = RkikError;
+
+    fn try_from(p: JsonProbe) -> Result<Self, RkikError> {
+        let invalid = |what: &str, e: &dyn std::fmt::Display| {
+            RkikError::Other(format!("invalid {what} for '{}': {e}", p.name))
+        };
+        let ip = p.ip.parse().map_err(|e| invalid("ip", &e))?;
+        let utc = chrono::DateTime::parse_from_rfc3339(&p.utc)
+            .map_err(|e| invalid("utc", &e))?
+            .with_timezone(&Utc);
+        Ok(ProbeResult {
+            target: crate::domain::ntp::Target {
+                resolution: p.resolution.unwrap_or_default(),
+                addresses: p.addresses.unwrap_or_else(|| vec![ip]),
+                ..crate::domain::ntp::Target::new(p.name, ip, p.port)
+            },
+            offset_ms: p.offset_ms,
+            rtt_ms: p.rtt_ms,
+            stratum: p.stratum.unwrap_or_default(),
+            ref_id: p.ref_id.unwrap_or_default(),
+            utc,
+            local: utc.into(),
+            timestamp: p.timestamp.unwrap_or(utc.timestamp()),
+            authenticated: p.authenticated,
+            dnssec: p.dnssec.unwrap_or_default(),
+            leap: p.leap.unwrap_or_default(),
+            health: p.health.unwrap_or_default(),
+            pause_ms: p.pause_ms,
+            origin: p.origin,
+            timing: p.timing,
+            uncertainty_ms: p.uncertainty_ms,
+            clock_step_ms: p.clock_step_ms,
+            reference_age_s: p.reference_age_s,
+            #[cfg(feature = "nts")]
+            nts_ke_data: p.nts_ke_data,
+            #[cfg(feature = "nts")]
+            nts_validation: p.nts,
+        })
+    }
+}
+
 /// Serialize simple probe results (timestamp and IP only).
 #[allow(unused_variables)]
 pub fn simple_to_json(results: &[ProbeResult], pretty: bool) -> Result<String, RkikError> {
@@ -137,7 +306,7 @@ pub fn simple_to_json(results: &[ProbeResult], pretty: bool) -> Result<String, R
             .collect();
 
         let run = JsonSimpleRun {
-            schema_version: 1,
+            schema_version: SCHEMA_VERSION,
             run_ts: Utc::now().to_rfc3339(),
             results: probes,
         };
@@ -158,20 +327,21 @@ pub fn simple_to_json(results: &[ProbeResult], pretty: bool) -> Result<String, R
 }
 
 #[cfg(feature = "json")]
-#[derive(Serialize)]
-struct JsonStatsEntry {
-    name: String,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonStatsEntry {
+    pub name: String,
     #[serde(flatten)]
-    stats: Stats,
+    pub stats: Stats,
 }
 
+/// Statistics printed after a `--count` run.
 #[cfg(feature = "json")]
-#[derive(Serialize)]
-struct JsonStatsSummary {
-    schema_version: u8,
-    stats: Vec<JsonStatsEntry>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonStatsSummary {
+    pub schema_version: u8,
+    pub stats: Vec<JsonStatsEntry>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    max_avg_drift: Option<f64>,
+    pub max_avg_drift: Option<f64>,
 }
 
 /// Serialize statistics into JSON string.
@@ -202,7 +372,7 @@ pub fn stats_list_to_json(list: &[(String, Stats)], pretty: bool) -> Result<Stri
         };
 
         let summary = JsonStatsSummary {
-            schema_version: 1,
+            schema_version: SCHEMA_VERSION,
             stats,
             max_avg_drift: drift,
         };
@@ -238,6 +408,421 @@ pub fn stats_to_json(name: &str, stats: &Stats, pretty: bool) -> Result<String,
     }
 }
 
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonMatrix<'a> {
+    schema_version: u8,
+    matrix: &'a DriftMatrix,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_drift_ms: Option<f64>,
+    /// Server name pairs whose difference exceeds `max_drift_ms`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exceeding: Option<Vec<[&'a str; 2]>>,
+}
+
+/// Serialize a drift matrix, listing the pairs beyond `max_drift` (ms) when set.
+#[allow(unused_variables)]
+pub fn matrix_to_json(
+    matrix: &DriftMatrix,
+    max_drift: Option<f64>,
+    pretty: bool,
+) -> Result<String, RkikError> {
+    #[cfg(feature = "json")]
+    {
+        let exceeding = max_drift.map(|limit| {
+            matrix
+                .exceeding(limit)
+                .into_iter()
+                .map(|(i, j)| [matrix.servers[i].as_str(), matrix.servers[j].as_str()])
+                .collect()
+        });
+        let doc = JsonMatrix {
+            schema_version: SCHEMA_VERSION,
+            matrix,
+            max_drift_ms: max_drift,
+            exceeding,
+        };
+        let text = if pretty {
+            serde_json::to_string_pretty(&doc).map_err(|e| RkikError::Other(e.to_string()))?
+        } else {
+            serde_json::to_string(&doc).map_err(|e| RkikError::Other(e.to_string()))?
+        };
+        Ok(text)
+    }
+    #[cfg(not(feature = "json"))]
+    {
+        Err(RkikError::Other("json feature disabled".into()))
+    }
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonDiscovery<'a> {
+    schema_version: u8,
+    domain: &'a str,
+    servers: &'a [Advertised],
+}
+
+/// Serialize the servers advertised by `domain` (see `services::discover`).
+#[allow(unused_variables)]
+pub fn discovery_to_json(
+    domain: &str,
+    servers: &[Advertised],
+    pretty: bool,
+) -> Result<String, RkikError> {
+    #[cfg(feature = "json")]
+    {
+        let doc = JsonDiscovery {
+            schema_version: SCHEMA_VERSION,
+            domain,
+            servers,
+        };
+        let text = if pretty {
+            serde_json::to_string_pretty(&doc).map_err(|e| RkikError::Other(e.to_string()))?
+        } else {
+            serde_json::to_string(&doc).map_err(|e| RkikError::Other(e.to_string()))?
+        };
+        Ok(text)
+    }
+    #[cfg(not(feature = "json"))]
+    {
+        Err(RkikError::Other("json feature disabled".into()))
+    }
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonError<'a> {
+    schema_version: u8,
+    error: &'a RkikError,
+}
+
+/// Serialize a failure as `{"schema_version", "error": {"kind", "message", "target"}}`,
+/// the document the CLI prints on stderr in JSON mode.
+#[cfg(feature = "json")]
+pub fn error_to_json(err: &RkikError, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonError {
+        schema_version: SCHEMA_VERSION,
+        error: err,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonChronyd<'a> {
+    schema_version: u8,
+    daemon: &'static str,
+    tracking: &'a Tracking,
+    sources: &'a [Source],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kernel: Option<&'a KernelClock>,
+}
+
+/// Serialize the local chronyd's tracking report and sources (see
+/// `adapters::chrony`), with the kernel clock state if given.
+#[cfg(feature = "json")]
+pub fn chronyd_to_json(
+    tracking: &Tracking,
+    sources: &[Source],
+    kernel: Option<&KernelClock>,
+    pretty: bool,
+) -> Result<String, RkikError> {
+    let doc = JsonChronyd {
+        schema_version: SCHEMA_VERSION,
+        daemon: "chronyd",
+        tracking,
+        sources,
+        kernel,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonNtpd<'a> {
+    schema_version: u8,
+    daemon: &'static str,
+    system: &'a SystemVars,
+    peers: &'a [Peer],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kernel: Option<&'a KernelClock>,
+}
+
+/// Serialize the local ntpd's system variables and peers (see
+/// `adapters::ntpd`), with the kernel clock state if given.
+#[cfg(feature = "json")]
+pub fn ntpd_to_json(
+    system: &SystemVars,
+    peers: &[Peer],
+    kernel: Option<&KernelClock>,
+    pretty: bool,
+) -> Result<String, RkikError> {
+    let doc = JsonNtpd {
+        schema_version: SCHEMA_VERSION,
+        daemon: "ntpd",
+        system,
+        peers,
+        kernel,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonW32Time<'a> {
+    schema_version: u8,
+    daemon: &'static str,
+    status: &'a W32TimeStatus,
+}
+
+/// Serialize the Windows Time service status (see `adapters::w32time`).
+#[cfg(feature = "json")]
+pub fn w32time_to_json(status: &W32TimeStatus, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonW32Time {
+        schema_version: SCHEMA_VERSION,
+        daemon: "w32time",
+        status,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonKernel<'a> {
+    schema_version: u8,
+    kernel: &'a KernelClock,
+}
+
+/// Serialize the kernel clock state alone, when no time daemon answered.
+#[cfg(feature = "json")]
+pub fn kernel_to_json(kernel: &KernelClock, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonKernel {
+        schema_version: SCHEMA_VERSION,
+        kernel,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonHttpDate<'a> {
+    schema_version: u8,
+    source: &'static str,
+    #[serde(flatten)]
+    sample: &'a HttpDateSample,
+}
+
+/// Serialize an `rkik http` sample. `source` is always `"http_date"` so
+/// consumers can tell it apart from an NTP measurement.
+#[cfg(feature = "json")]
+pub fn http_date_to_json(sample: &HttpDateSample, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonHttpDate {
+        schema_version: SCHEMA_VERSION,
+        source: "http_date",
+        sample,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonSurvey<'a> {
+    schema_version: u8,
+    #[serde(flatten)]
+    survey: &'a Survey,
+}
+
+/// Serialize the ranked members of an `rkik survey`, best first.
+#[cfg(feature = "json")]
+pub fn survey_to_json(survey: &Survey, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonSurvey {
+        schema_version: SCHEMA_VERSION,
+        survey,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonRecommendation<'a> {
+    schema_version: u8,
+    recommended: &'a [Recommendation],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<&'a str>,
+}
+
+/// Serialize recommended servers, best first, with the server block of
+/// `--emit-config` when one was asked for.
+#[cfg(feature = "json")]
+pub fn recommendation_to_json(
+    recommended: &[Recommendation],
+    config: Option<&str>,
+    pretty: bool,
+) -> Result<String, RkikError> {
+    let doc = JsonRecommendation {
+        schema_version: SCHEMA_VERSION,
+        recommended,
+        config,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonAsymmetry<'a> {
+    schema_version: u8,
+    #[serde(flatten)]
+    asymmetry: &'a Asymmetry,
+}
+
+/// Serialize the paths and offset interval of `rkik asymmetry`.
+#[cfg(feature = "json")]
+pub fn asymmetry_to_json(asymmetry: &Asymmetry, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonAsymmetry {
+        schema_version: SCHEMA_VERSION,
+        asymmetry,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonClockCheck<'a> {
+    schema_version: u8,
+    #[serde(flatten)]
+    check: &'a ClockCheck,
+}
+
+/// Serialize the result of `rkik check-clock`.
+#[cfg(feature = "json")]
+pub fn clock_check_to_json(check: &ClockCheck, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonClockCheck {
+        schema_version: SCHEMA_VERSION,
+        check,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(all(feature = "json", feature = "nts"))]
+#[derive(Serialize)]
+struct JsonNtsKe<'a> {
+    schema_version: u8,
+    #[serde(flatten)]
+    report: &'a NtsKeReport,
+}
+
+/// Serialize the handshakes of `rkik nts-ke`.
+#[cfg(all(feature = "json", feature = "nts"))]
+pub fn nts_ke_to_json(report: &NtsKeReport, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonNtsKe {
+        schema_version: SCHEMA_VERSION,
+        report,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonDivergence<'a> {
+    schema_version: u8,
+    #[serde(flatten)]
+    event: &'a DivergenceEvent,
+}
+
+/// Serialize one divergence event of `--diverge-threshold`.
+#[cfg(feature = "json")]
+pub fn divergence_to_json(event: &DivergenceEvent, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonDivergence {
+        schema_version: SCHEMA_VERSION,
+        event,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonDiagnosis<'a> {
+    schema_version: u8,
+    #[serde(flatten)]
+    diagnosis: &'a Diagnosis,
+}
+
+/// Serialize the report of `rkik diag`.
+#[cfg(feature = "json")]
+pub fn diagnosis_to_json(diagnosis: &Diagnosis, pretty: bool) -> Result<String, RkikError> {
+    let doc = JsonDiagnosis {
+        schema_version: SCHEMA_VERSION,
+        diagnosis,
+    };
+    let text = if pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    };
+    text.map_err(|e| RkikError::Other(e.to_string()))
+}
+
 /// Serialize a single probe into a compact one-line JSON string (no envelope).
 pub fn probe_to_short_json(r: &ProbeResult) -> Result<String, RkikError> {
     #[cfg(feature = "json")]
@@ -283,6 +868,113 @@ pub fn to_short_json(results: &[ProbeResult], pretty: bool) -> Result<String, Rk
     }
 }
 
+/// A probe restricted to some fields, with its keys in the order requested
+/// (a `serde_json::Map` would sort them).
+#[cfg(feature = "json")]
+struct FieldObject<'a> {
+    probe: &'a ProbeResult,
+    fields: &'a [Field],
+}
+
+#[cfg(feature = "json")]
+impl Serialize for FieldObject<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.fields.len()))?;
+        for f in self.fields {
+            let key = f.column();
+            match f.value(self.probe) {
+                FieldValue::Text(s) => map.serialize_entry(&key, &s)?,
+                FieldValue::Float(v) => map.serialize_entry(&key, &v)?,
+                FieldValue::Int(v) => map.serialize_entry(&key, &v)?,
+                FieldValue::Bool(v) => map.serialize_entry(&key, &v)?,
+            }
+        }
+        map.end()
+    }
+}
+
+#[cfg(feature = "json")]
+fn field_object<'a>(probe: &'a ProbeResult, fields: &'a [Field]) -> FieldObject<'a> {
+    FieldObject { probe, fields }
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonFieldsRun<'a> {
+    schema_version: u8,
+    run_ts: String,
+    results: Vec<FieldObject<'a>>,
+}
+
+/// Serialize probe results restricted to the selected fields.
+#[allow(unused_variables)]
+pub fn to_json_fields(
+    results: &[ProbeResult],
+    fields: &[Field],
+    pretty: bool,
+) -> Result<String, RkikError> {
+    #[cfg(feature = "json")]
+    {
+        let run = JsonFieldsRun {
+            schema_version: SCHEMA_VERSION,
+            run_ts: Utc::now().to_rfc3339(),
+            results: results.iter().map(|r| field_object(r, fields)).collect(),
+        };
+        let text = if pretty {
+            serde_json::to_string_pretty(&run).map_err(|e| RkikError::Other(e.to_string()))?
+        } else {
+            serde_json::to_string(&run).map_err(|e| RkikError::Other(e.to_string()))?
+        };
+        Ok(text)
+    }
+    #[cfg(not(feature = "json"))]
+    {
+        Err(RkikError::Other("json feature disabled".into()))
+    }
+}
+
+/// Serialize a list of probes restricted to the selected fields as a JSON array (no envelope).
+#[allow(unused_variables)]
+pub fn to_short_json_fields(
+    results: &[ProbeResult],
+    fields: &[Field],
+    pretty: bool,
+) -> Result<String, RkikError> {
+    #[cfg(feature = "json")]
+    {
+        let items: Vec<_> = results.iter().map(|r| field_object(r, fields)).collect();
+        if pretty {
+            serde_json::to_string_pretty(&items)
+                .map_err(|e| RkikError::Other(format!("json encode: {}", e)))
+        } else {
+            serde_json::to_string(&items)
+                .map_err(|e| RkikError::Other(format!("json encode: {}", e)))
+        }
+    }
+    #[cfg(not(feature = "json"))]
+    {
+        Err(RkikError::Other("json feature disabled".into()))
+    }
+}
+
+/// Serialize a single probe restricted to the selected fields into a one-line JSON object.
+#[allow(unused_variables)]
+pub fn probe_to_fields_json(r: &ProbeResult, fields: &[Field]) -> Result<String, RkikError> {
+    #[cfg(feature = "json")]
+    {
+        serde_json::to_string(&field_object(r, fields))
+            .map_err(|e| RkikError::Other(format!("json encode: {}", e)))
+    }
+    #[cfg(not(feature = "json"))]
+    {
+        Err(RkikError::Other("json feature disabled".into()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -293,11 +985,7 @@ mod tests {
         let utc = chrono::Utc::now();
         let local: chrono::DateTime<chrono::Local> = chrono::DateTime::from(utc);
         ProbeResult {
-            target: Target {
-                name: "example".into(),
-                ip: "127.0.0.1".parse::<IpAddr>().unwrap(),
-                port: 123,
-            },
+            target: Target::new("example", "127.0.0.1".parse::<IpAddr>().unwrap(), 123),
             offset_ms: 0.0,
             rtt_ms: 0.5,
             stratum: 1,
@@ -306,6 +994,15 @@ mod tests {
             local,
             timestamp: 1,
             authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
             #[cfg(feature = "nts")]
             nts_ke_data: None,
             #[cfg(feature = "nts")]
@@ -327,4 +1024,92 @@ mod tests {
             "timestamp should appear when verbose: {json_verbose}"
         );
     }
+
+    #[test]
+    fn origin_only_in_verbose_output() {
+        let mut probe = sample_probe();
+        probe.origin = Some(Origin {
+            reverse_dns: Some("ntp1.example.net".into()),
+            asn: Some(64500),
+            ..Origin::default()
+        });
+        let json = to_json(std::slice::from_ref(&probe), false, true).unwrap();
+        assert!(
+            json.contains("\"origin\":{\"reverse_dns\":\"ntp1.example.net\",\"asn\":64500}"),
+            "{json}"
+        );
+        let json = to_json(std::slice::from_ref(&probe), false, false).unwrap();
+        assert!(!json.contains("origin"), "{json}");
+    }
+
+    #[test]
+    fn fields_restrict_json_keys_in_requested_order() {
+        let probe = sample_probe();
+        let json = to_json_fields(
+            std::slice::from_ref(&probe),
+            &[Field::Rtt, Field::Stratum],
+            false,
+        )
+        .unwrap();
+        assert!(
+            json.contains("\"results\":[{\"rtt_ms\":0.5,\"stratum\":1}]"),
+            "{json}"
+        );
+        assert!(!json.contains("offset_ms"), "{json}");
+    }
+
+    #[test]
+    fn fields_keep_a_non_alphabetical_order() {
+        let probe = sample_probe();
+        let json = probe_to_fields_json(&probe, &[Field::Stratum, Field::Rtt]).unwrap();
+        assert_eq!(json, "{\"stratum\":1,\"rtt_ms\":0.5}");
+        let json = to_short_json_fields(
+            std::slice::from_ref(&probe),
+            &[Field::Stratum, Field::Rtt],
+            false,
+        )
+        .unwrap();
+        assert_eq!(json, "[{\"stratum\":1,\"rtt_ms\":0.5}]");
+    }
+
+    #[test]
+    fn parse_run_round_trips_verbose_output() {
+        let mut probe = sample_probe();
+        probe.target.resolution = Resolution::Dns;
+        probe.target.addresses.push("2001:db8::7".parse().unwrap());
+        probe.timing = Some(Timing {
+            raw: crate::domain::ntp::RawTimestamps {
+                origin: 0xe8f1_a2b3_1c6a_7ef9,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let json = to_json(std::slice::from_ref(&probe), false, true).unwrap();
+        let run = parse_run(&json).unwrap();
+        assert_eq!(run.schema_version, SCHEMA_VERSION);
+        let back = ProbeResult::try_from(run.results[0].clone()).unwrap();
+        assert_eq!(back.target.name, "example");
+        assert_eq!(back.target.ip, probe.target.ip);
+        assert_eq!(back.target.resolution, Resolution::Dns);
+        assert_eq!(back.target.addresses, probe.target.addresses);
+        assert!(json.contains(r#""family":"ipv4""#), "{json}");
+        assert!(json.contains(r#""origin":"e8f1a2b3.1c6a7ef9""#), "{json}");
+        assert_eq!(back.timing, probe.timing);
+        assert_eq!((back.stratum, back.ref_id.as_str()), (1, "LOCL"));
+        assert_eq!(back.utc, probe.utc);
+
+        let newer = json.replacen("\"schema_version\":1", "\"schema_version\":99", 1);
+        assert!(parse_run(&newer).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn errors_are_wrapped_in_an_error_object() {
+        let err = RkikError::Timeout.with_target("time.example");
+        let doc: serde_json::Value =
+            serde_json::from_str(&error_to_json(&err, false).unwrap()).unwrap();
+        assert_eq!(doc["schema_version"], SCHEMA_VERSION);
+        assert_eq!(doc["error"]["kind"], "timeout");
+        assert_eq!(doc["error"]["target"], "time.example");
+    }
 }
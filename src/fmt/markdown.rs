@@ -0,0 +1,133 @@
+use crate::domain::ntp::ProbeResult;
+use crate::fmt::units::format_ms;
+use crate::stats::{Stats, compute_stats};
+
+/// Table header (title row and alignment row) for probe results.
+pub const HEADER: &str = "| Server | IP | Stratum | Offset | RTT | Auth |\n\
+                          |---|---|---:|---:|---:|:---:|";
+
+fn escape_md(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Table rows for probe results (no header).
+pub fn rows(results: &[ProbeResult]) -> String {
+    let mut out = String::new();
+    for r in results {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} |\n",
+            escape_md(&r.target.name),
+            r.target.ip,
+            r.stratum,
+            format_ms(r.offset_ms),
+            format_ms(r.rtt_ms),
+            if r.authenticated { "NTS" } else { "-" }
+        ));
+    }
+    out
+}
+
+/// Render probe results as a Markdown table followed by a summary line.
+pub fn to_markdown(results: &[ProbeResult]) -> String {
+    let mut out = format!("{}\n", HEADER);
+    out.push_str(&rows(results));
+    if results.is_empty() {
+        return out;
+    }
+
+    let stats = compute_stats(results);
+    out.push_str(&format!(
+        "\n**Summary:** {} server{} · offset avg {} (min {}, max {})",
+        results.len(),
+        if results.len() == 1 { "" } else { "s" },
+        format_ms(stats.offset_avg),
+        format_ms(stats.offset_min),
+        format_ms(stats.offset_max),
+    ));
+    if results.len() > 1 {
+        out.push_str(&format!(
+            " · max drift {}",
+            format_ms(stats.offset_max - stats.offset_min)
+        ));
+    }
+    out.push_str(&format!(" · rtt avg {}\n", format_ms(stats.rtt_avg)));
+    out
+}
+
+/// Render per-server statistics of a `--count`/`--infinite` run as a Markdown table.
+pub fn stats_to_markdown(list: &[(String, Stats)]) -> String {
+    let mut out = String::from(
+        "| Server | Samples | Offset avg | Offset min | Offset max | RTT avg |\n\
+         |---|---:|---:|---:|---:|---:|\n",
+    );
+    for (name, st) in list {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} |\n",
+            escape_md(name),
+            st.count,
+            format_ms(st.offset_avg),
+            format_ms(st.offset_min),
+            format_ms(st.offset_max),
+            format_ms(st.rtt_avg),
+        ));
+    }
+    if list.len() > 1 {
+        let min = list
+            .iter()
+            .map(|(_, s)| s.offset_avg)
+            .fold(f64::INFINITY, f64::min);
+        let max = list
+            .iter()
+            .map(|(_, s)| s.offset_avg)
+            .fold(f64::NEG_INFINITY, f64::max);
+        out.push_str(&format!("\n**Max avg drift:** {}\n", format_ms(max - min)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::{Leap, Target};
+    use std::net::IpAddr;
+
+    fn sample_probe(name: &str, offset_ms: f64) -> ProbeResult {
+        let utc = chrono::Utc::now();
+        ProbeResult {
+            target: Target::new(name, "127.0.0.1".parse::<IpAddr>().unwrap(), 123),
+            offset_ms,
+            rtt_ms: 10.0,
+            stratum: 2,
+            ref_id: "LOCL".into(),
+            utc,
+            local: chrono::DateTime::from(utc),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn table_has_row_per_server_and_drift_footer() {
+        let md = to_markdown(&[sample_probe("a|b", 1.0), sample_probe("c", 4.0)]);
+        let lines: Vec<&str> = md.lines().collect();
+        assert!(lines[0].starts_with("| Server |"));
+        assert_eq!(
+            lines[2],
+            "| a\\|b | 127.0.0.1 | 2 | 1.000 ms | 10.000 ms | - |"
+        );
+        assert!(md.contains("max drift 3.000 ms"), "{md}");
+    }
+}
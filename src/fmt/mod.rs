@@ -1,3 +1,10 @@
 pub mod csv;
+pub mod fields;
+pub mod highlight;
 pub mod json;
+pub mod markdown;
+#[cfg(feature = "otel")]
+pub mod otlp;
+pub mod prometheus;
 pub mod text;
+pub mod units;
@@ -0,0 +1,224 @@
+//! OTLP/HTTP JSON encoding of probe results (feature `otel`).
+//!
+//! Produces an `ExportMetricsServiceRequest` body suitable for a collector's
+//! `/v1/metrics` endpoint. Each target gets its own resource, so host and
+//! target attributes are available for grouping in the backend.
+
+use std::collections::BTreeMap;
+
+use serde_json::{Value, json};
+
+use crate::domain::ntp::ProbeResult;
+use crate::error::RkikError;
+
+/// Cumulative RTT histogram for one target, in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RttHistogram {
+    pub start_unix_nano: u64,
+    /// One count per bucket in [`RttHistogram::BOUNDS`], plus the overflow bucket.
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl RttHistogram {
+    /// Explicit bucket upper bounds, in seconds.
+    pub const BOUNDS: [f64; 9] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+    pub fn new(start_unix_nano: u64) -> Self {
+        Self {
+            start_unix_nano,
+            counts: vec![0; Self::BOUNDS.len() + 1],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, rtt_ms: f64) {
+        let secs = rtt_ms / 1000.0;
+        let idx = Self::BOUNDS
+            .iter()
+            .position(|b| secs <= *b)
+            .unwrap_or(Self::BOUNDS.len());
+        self.counts[idx] += 1;
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+fn attr(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn int_attr(key: &str, value: i64) -> Value {
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+fn unix_nano(r: &ProbeResult) -> String {
+    r.utc
+        .timestamp_nanos_opt()
+        .unwrap_or(r.timestamp * 1_000_000_000)
+        .to_string()
+}
+
+fn gauge(name: &str, unit: &str, description: &str, point: Value) -> Value {
+    json!({
+        "name": name,
+        "unit": unit,
+        "description": description,
+        "gauge": { "dataPoints": [point] }
+    })
+}
+
+/// Build the OTLP JSON request for one round of results.
+///
+/// `resource` attributes (e.g. `host.name`) are added to every target resource.
+/// `histograms` is keyed by target name; targets without an entry get no histogram.
+pub fn to_otlp_json(
+    results: &[ProbeResult],
+    histograms: &BTreeMap<String, RttHistogram>,
+    resource: &[(String, String)],
+) -> Result<String, RkikError> {
+    let resource_metrics: Vec<Value> = results
+        .iter()
+        .map(|r| {
+            let mut attrs = vec![
+                attr("service.name", "rkik"),
+                attr("service.version", env!("CARGO_PKG_VERSION")),
+                attr("server.address", &r.target.name),
+                int_attr("server.port", r.target.port as i64),
+            ];
+            attrs.extend(resource.iter().map(|(k, v)| attr(k, v)));
+
+            let ts = unix_nano(r);
+            let point = |value: Value| {
+                let mut p = json!({
+                    "timeUnixNano": ts,
+                    "attributes": [attr("network.peer.address", &r.target.ip.to_string())]
+                });
+                let key = if value.is_i64() { "asInt" } else { "asDouble" };
+                p[key] = value;
+                p
+            };
+
+            let mut metrics = vec![
+                gauge(
+                    "rkik.offset",
+                    "s",
+                    "Clock offset of the server relative to the local clock",
+                    point(json!(r.offset_ms / 1000.0)),
+                ),
+                gauge(
+                    "rkik.rtt",
+                    "s",
+                    "Round-trip delay of the probe",
+                    point(json!(r.rtt_ms / 1000.0)),
+                ),
+                gauge(
+                    "rkik.stratum",
+                    "1",
+                    "Stratum reported by the server",
+                    point(json!(r.stratum as i64)),
+                ),
+                gauge(
+                    "rkik.authenticated",
+                    "1",
+                    "Whether the probe was NTS authenticated",
+                    point(json!(r.authenticated as i64)),
+                ),
+            ];
+            if let Some(h) = histograms.get(&r.target.name) {
+                metrics.push(json!({
+                    "name": "rkik.rtt.histogram",
+                    "unit": "s",
+                    "description": "Distribution of probe round-trip delays",
+                    "histogram": {
+                        "aggregationTemporality": 2,
+                        "dataPoints": [{
+                            "startTimeUnixNano": h.start_unix_nano.to_string(),
+                            "timeUnixNano": ts,
+                            "count": h.count.to_string(),
+                            "sum": h.sum,
+                            "bucketCounts": h.counts.iter().map(u64::to_string).collect::<Vec<_>>(),
+                            "explicitBounds": RttHistogram::BOUNDS,
+                            "attributes": [attr("network.peer.address", &r.target.ip.to_string())]
+                        }]
+                    }
+                }));
+            }
+
+            json!({
+                "resource": { "attributes": attrs },
+                "scopeMetrics": [{
+                    "scope": { "name": "rkik", "version": env!("CARGO_PKG_VERSION") },
+                    "metrics": metrics
+                }]
+            })
+        })
+        .collect();
+
+    serde_json::to_string(&json!({ "resourceMetrics": resource_metrics }))
+        .map_err(|e| RkikError::Other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::{Leap, Target};
+
+    fn probe() -> ProbeResult {
+        let utc = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        ProbeResult {
+            target: Target::new("time.example", "192.0.2.1".parse().unwrap(), 123),
+            offset_ms: 2.0,
+            rtt_ms: 12.0,
+            stratum: 1,
+            ref_id: "GPS".into(),
+            utc,
+            local: chrono::DateTime::from(utc),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn encodes_resource_gauges_and_histogram() {
+        let mut h = RttHistogram::new(1);
+        h.record(12.0);
+        let mut hist = BTreeMap::new();
+        hist.insert("time.example".to_string(), h);
+        let body = to_otlp_json(
+            &[probe()],
+            &hist,
+            &[("host.name".into(), "probe-01".into())],
+        )
+        .unwrap();
+        let v: Value = serde_json::from_str(&body).unwrap();
+        let rm = &v["resourceMetrics"][0];
+        let attrs = rm["resource"]["attributes"].as_array().unwrap();
+        assert!(attrs.contains(&attr("host.name", "probe-01")));
+        assert!(attrs.contains(&attr("server.address", "time.example")));
+
+        let metrics = rm["scopeMetrics"][0]["metrics"].as_array().unwrap();
+        assert_eq!(metrics[0]["name"], "rkik.offset");
+        assert_eq!(metrics[0]["gauge"]["dataPoints"][0]["asDouble"], 0.002);
+        assert_eq!(metrics[2]["gauge"]["dataPoints"][0]["asInt"], 1);
+        let hp = &metrics[4]["histogram"]["dataPoints"][0];
+        assert_eq!(hp["count"], "1");
+        // 12 ms falls in the (0.01, 0.025] bucket
+        assert_eq!(hp["bucketCounts"][3], "1");
+    }
+}
@@ -0,0 +1,310 @@
+//! Prometheus text exposition format (version 0.0.4) for probe results.
+//!
+//! Durations are exported in seconds, following Prometheus base-unit conventions.
+
+use std::fmt::Write as FmtWrite;
+
+use crate::adapters::reuse::ReuseStats;
+use crate::domain::ntp::ProbeResult;
+use crate::history::History;
+use crate::stats::Stats;
+
+/// Latest state of one monitored target.
+#[derive(Debug, Clone, Default)]
+pub struct TargetMetrics {
+    pub target: String,
+    /// Last successful probe, if any.
+    pub last: Option<ProbeResult>,
+    /// Whether the most recent probe succeeded.
+    pub up: bool,
+    pub probes_total: u64,
+    pub failures_total: u64,
+    /// Error kind of the most recent failure (see [`crate::RkikError::kind`]).
+    pub last_error: Option<String>,
+}
+
+impl TargetMetrics {
+    pub fn new(target: impl Into<String>) -> Self {
+        Self {
+            target: target.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Record a successful probe.
+    pub fn record_success(&mut self, probe: ProbeResult) {
+        self.probes_total += 1;
+        self.up = true;
+        self.last = Some(probe);
+        self.last_error = None;
+    }
+
+    /// Record a failed probe with its error kind.
+    pub fn record_failure(&mut self, kind: &str) {
+        self.probes_total += 1;
+        self.failures_total += 1;
+        self.up = false;
+        self.last_error = Some(kind.to_string());
+    }
+}
+
+fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Render all targets as a Prometheus scrape body.
+pub fn render(targets: &[TargetMetrics]) -> String {
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "rkik_up",
+        "gauge",
+        "Whether the last probe of the target succeeded.",
+    );
+    for t in targets {
+        let _ = writeln!(
+            out,
+            "rkik_up{{target=\"{}\"}} {}",
+            escape_label(&t.target),
+            u8::from(t.up)
+        );
+    }
+
+    type Getter = fn(&ProbeResult) -> f64;
+    let gauges: [(&str, &str, Getter); 5] = [
+        (
+            "rkik_offset_seconds",
+            "Clock offset of the server relative to the local clock.",
+            |p| p.offset_ms / 1000.0,
+        ),
+        (
+            "rkik_rtt_seconds",
+            "Round-trip delay of the last successful probe.",
+            |p| p.rtt_ms / 1000.0,
+        ),
+        ("rkik_stratum", "Stratum reported by the server.", |p| {
+            p.stratum as f64
+        }),
+        (
+            "rkik_authenticated",
+            "Whether the last successful probe was NTS authenticated.",
+            |p| if p.authenticated { 1.0 } else { 0.0 },
+        ),
+        (
+            "rkik_last_success_timestamp_seconds",
+            "Unix time of the last successful probe.",
+            |p| p.utc.timestamp_millis() as f64 / 1000.0,
+        ),
+    ];
+    for (name, help, get) in gauges {
+        header(&mut out, name, "gauge", help);
+        for t in targets {
+            if let Some(p) = &t.last {
+                let _ = writeln!(
+                    out,
+                    "{name}{{target=\"{}\",ip=\"{}\"}} {}",
+                    escape_label(&t.target),
+                    p.target.ip,
+                    get(p)
+                );
+            }
+        }
+    }
+
+    header(
+        &mut out,
+        "rkik_probes_total",
+        "counter",
+        "Number of probes sent to the target.",
+    );
+    for t in targets {
+        let _ = writeln!(
+            out,
+            "rkik_probes_total{{target=\"{}\"}} {}",
+            escape_label(&t.target),
+            t.probes_total
+        );
+    }
+    header(
+        &mut out,
+        "rkik_probe_failures_total",
+        "counter",
+        "Number of failed probes.",
+    );
+    for t in targets {
+        let _ = writeln!(
+            out,
+            "rkik_probe_failures_total{{target=\"{}\"}} {}",
+            escape_label(&t.target),
+            t.failures_total
+        );
+    }
+    out
+}
+
+/// Render rolling statistics over the probes kept in `history`.
+pub fn render_history(history: &History) -> String {
+    let windows: Vec<(String, Stats)> = history
+        .targets()
+        .into_iter()
+        .filter_map(|t| history.stats(&t).map(|s| (t, s)))
+        .collect();
+    let mut out = String::new();
+    type Getter = fn(&Stats) -> Option<f64>;
+    let gauges: [(&str, &str, Getter); 5] = [
+        (
+            "rkik_window_offset_avg_seconds",
+            "Average offset over the rolling window.",
+            |s| Some(s.offset_avg / 1000.0),
+        ),
+        (
+            "rkik_window_offset_min_seconds",
+            "Minimum offset over the rolling window.",
+            |s| Some(s.offset_min / 1000.0),
+        ),
+        (
+            "rkik_window_offset_max_seconds",
+            "Maximum offset over the rolling window.",
+            |s| Some(s.offset_max / 1000.0),
+        ),
+        (
+            "rkik_window_rtt_avg_seconds",
+            "Average round-trip delay over the rolling window.",
+            |s| Some(s.rtt_avg / 1000.0),
+        ),
+        (
+            "rkik_window_jitter_seconds",
+            "RFC 5905 peer jitter over the rolling window.",
+            |s| s.jitter.map(|j| j / 1000.0),
+        ),
+    ];
+    for (name, help, get) in gauges {
+        header(&mut out, name, "gauge", help);
+        for (target, stats) in &windows {
+            if let Some(v) = get(stats) {
+                let _ = writeln!(out, "{name}{{target=\"{}\"}} {}", escape_label(target), v);
+            }
+        }
+    }
+    out
+}
+
+/// Render the socket and NTS session reuse counters (see
+/// [`adapters::reuse`](crate::adapters::reuse)).
+pub fn render_reuse(stats: &ReuseStats) -> String {
+    let mut out = String::new();
+    let counters = [
+        (
+            "rkik_sockets_opened_total",
+            "UDP sockets bound for NTP exchanges.",
+            stats.sockets_opened,
+        ),
+        (
+            "rkik_sockets_reused_total",
+            "NTP exchanges sent on a kept socket.",
+            stats.sockets_reused,
+        ),
+        (
+            "rkik_nts_handshakes_total",
+            "NTS-KE handshakes performed.",
+            stats.nts_handshakes,
+        ),
+        (
+            "rkik_nts_sessions_reused_total",
+            "NTS queries answered on a kept session.",
+            stats.nts_sessions_reused,
+        ),
+    ];
+    for (name, help, value) in counters {
+        header(&mut out, name, "counter", help);
+        let _ = writeln!(out, "{name} {value}");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::{Leap, Target};
+
+    fn probe(offset_ms: f64, rtt_ms: f64) -> ProbeResult {
+        let utc = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        ProbeResult {
+            target: Target::new("time.example", "192.0.2.1".parse().unwrap(), 123),
+            offset_ms,
+            rtt_ms,
+            stratum: 2,
+            ref_id: "GPS".into(),
+            utc,
+            local: chrono::DateTime::from(utc),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn renders_gauges_in_seconds_and_skips_unprobed_targets() {
+        let mut ok = TargetMetrics::new("time.example");
+        ok.record_success(probe(-1.5, 20.0));
+        let mut down = TargetMetrics::new("bad\"host");
+        down.record_failure("dns");
+
+        let body = render(&[ok, down]);
+        assert!(body.contains("rkik_up{target=\"time.example\"} 1"));
+        assert!(body.contains("rkik_up{target=\"bad\\\"host\"} 0"));
+        assert!(
+            body.contains("rkik_offset_seconds{target=\"time.example\",ip=\"192.0.2.1\"} -0.0015")
+        );
+        assert!(body.contains("rkik_rtt_seconds{target=\"time.example\",ip=\"192.0.2.1\"} 0.02"));
+        assert!(!body.contains("rkik_stratum{target=\"bad"));
+        assert!(body.contains("rkik_probe_failures_total{target=\"bad\\\"host\"} 1"));
+    }
+
+    #[test]
+    fn renders_reuse_counters() {
+        let body = render_reuse(&ReuseStats {
+            sockets_opened: 2,
+            sockets_reused: 40,
+            nts_handshakes: 1,
+            nts_sessions_reused: 9,
+        });
+        assert!(
+            body.contains(
+                "# TYPE rkik_sockets_reused_total counter\nrkik_sockets_reused_total 40\n"
+            )
+        );
+        assert!(body.contains("rkik_nts_sessions_reused_total 9\n"));
+    }
+
+    #[test]
+    fn renders_window_gauges_per_target() {
+        let history = History::new(5);
+        history.push("a", probe(1.0, 10.0));
+        history.push("a", probe(3.0, 30.0));
+        let body = render_history(&history);
+        assert!(body.contains("rkik_window_offset_avg_seconds{target=\"a\"} 0.002\n"));
+        assert!(body.contains("rkik_window_rtt_avg_seconds{target=\"a\"} 0.02\n"));
+        assert!(body.contains("rkik_window_jitter_seconds{target=\"a\"}"));
+    }
+}
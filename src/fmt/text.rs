@@ -1,6 +1,26 @@
-use crate::domain::ntp::ProbeResult;
-use crate::stats::Stats;
-use console::style;
+use crate::adapters::chrony::{Leap, Source, SourceState, Tracking};
+use crate::adapters::http_date::HttpDateSample;
+use crate::adapters::kernel::KernelClock;
+use crate::adapters::ntpd::{Peer, Selection, SystemVars};
+use crate::adapters::w32time::W32TimeStatus;
+use crate::domain::ntp::{
+    AddressFamily, Health, HealthVerdict, Origin, ProbeResult, Target, Timing,
+};
+use crate::fmt::fields::Field;
+use crate::fmt::highlight::{self, Metric};
+use crate::fmt::units::{display_unit, format_ms};
+use crate::services::asymmetry::Asymmetry;
+use crate::services::clock_check::ClockCheck;
+use crate::services::diag::{Diagnosis, Status};
+use crate::services::discover::Advertised;
+use crate::services::divergence::{DivergenceEvent, DivergenceKind};
+#[cfg(feature = "nts")]
+use crate::services::nts_ke::NtsKeReport;
+use crate::services::query::Scheme;
+use crate::services::recommend::Recommendation;
+use crate::services::survey::Survey;
+use crate::stats::{DriftMatrix, Stats};
+use console::{Alignment, StyledObject, pad_str, style};
 
 /// Render a probe result into human readable text with the legacy style.
 pub fn render_probe(r: &ProbeResult, verbose: bool) -> String {
@@ -41,8 +61,8 @@ pub fn render_probe(r: &ProbeResult, verbose: bool) -> String {
          {ip_lbl} {ip_val}:{port}\n\
          {utc_lbl} {utc_val}\n\
          {loc_lbl} {loc_val}\n\
-         {off_lbl} {off_val:.3} ms\n\
-         {rtt_lbl} {rtt_val:.3} ms",
+         {off_lbl} {off_val}\n\
+         {rtt_lbl} {rtt_val}",
         srv_lbl = style("Server:").cyan().bold(),
         srv_val = style(&r.target.name).green(),
         auth = auth_indicator,
@@ -54,14 +74,16 @@ pub fn render_probe(r: &ProbeResult, verbose: bool) -> String {
         loc_lbl = style("Local Time:").cyan().bold(),
         loc_val = style(r.local.format("%Y-%m-%d %H:%M:%S")).green(),
         off_lbl = style("Clock Offset:").cyan().bold(),
-        off_val = r.offset_ms,
+        off_val = highlight::paint(Metric::Offset, r.offset_ms, format_ms(r.offset_ms), |s| s)
+            .to_string()
+            + &uncertainty(r.uncertainty_ms),
         rtt_lbl = style("Round Trip Delay:").cyan().bold(),
-        rtt_val = r.rtt_ms,
+        rtt_val = highlight::rtt(r.rtt_ms),
     );
 
     if verbose {
         out.push_str(&format!(
-            "\n{str_lbl} {str_val}\n{ref_lbl} {ref_val}\n{str_ts}: {timestamp}\n{auth_lbl} {auth_val}",
+            "\n{str_lbl} {str_val}\n{ref_lbl} {ref_val}\n{str_ts}: {timestamp}\n{auth_lbl} {auth_val}\n{dnssec_lbl} {dnssec_val}",
             str_lbl = style("Stratum:").cyan().bold(),
             str_val = r.stratum,
             ref_lbl = style("Reference ID:").cyan().bold(),
@@ -73,8 +95,39 @@ pub fn render_probe(r: &ProbeResult, verbose: bool) -> String {
                 style("Yes (NTS)").green()
             } else {
                 style("No").yellow()
+            },
+            dnssec_lbl = style("DNSSEC:").cyan().bold(),
+            dnssec_val = if r.dnssec {
+                style("Validated").green()
+            } else {
+                style("Not validated").yellow()
             }
         ));
+        out.push_str(&format!(
+            "\n{} {}",
+            style("Resolved:").cyan().bold(),
+            resolution(&r.target)
+        ));
+        if let Some(timing) = &r.timing {
+            out.push_str(&format!(
+                "\n{} {}",
+                style("Timing:").cyan().bold(),
+                timing_breakdown(timing, r.rtt_ms)
+            ));
+        }
+        // NTS replies and the local pseudo-targets are not checked.
+        if !r.authenticated {
+            out.push_str(&format!(
+                "\n{} {}",
+                style("Server Health:").cyan().bold(),
+                health_status(&r.health)
+            ));
+        }
+        if let Some(origin) = &r.origin {
+            for (label, value) in origin_lines(origin) {
+                out.push_str(&format!("\n{} {}", style(label).cyan().bold(), value));
+            }
+        }
 
         // NTS-KE diagnostic information (verbose mode only)
         #[cfg(feature = "nts")]
@@ -156,26 +209,48 @@ pub fn render_probe(r: &ProbeResult, verbose: bool) -> String {
 
         // NTS validation error details (verbose mode only)
         #[cfg(feature = "nts")]
-        if let Some(ref validation) = r.nts_validation {
-            if let Some(ref error) = validation.error {
-                out.push_str(&format!(
-                    "\n\n{header}\n{kind_lbl} {kind_val}\n{msg_lbl} {msg_val}",
-                    header = style("=== NTS Validation Error ===")
-                        .red()
-                        .bold()
-                        .underlined(),
-                    kind_lbl = style("Error Kind:").red().bold(),
-                    kind_val = style(error.kind.as_str()).red(),
-                    msg_lbl = style("Message:").red().bold(),
-                    msg_val = style(&error.message).red(),
-                ));
-            }
+        if let Some(ref validation) = r.nts_validation
+            && let Some(ref error) = validation.error
+        {
+            out.push_str(&format!(
+                "\n\n{header}\n{kind_lbl} {kind_val}\n{msg_lbl} {msg_val}",
+                header = style("=== NTS Validation Error ===")
+                    .red()
+                    .bold()
+                    .underlined(),
+                kind_lbl = style("Error Kind:").red().bold(),
+                kind_val = style(error.kind.as_str()).red(),
+                msg_lbl = style("Message:").red().bold(),
+                msg_val = style(&error.message).red(),
+            ));
         }
     }
 
     out
 }
 
+/// Verbose "Reverse DNS:" and "Network:" lines for the parts of `origin` that are known.
+fn origin_lines(origin: &Origin) -> Vec<(&'static str, String)> {
+    let mut lines = Vec::new();
+    if let Some(name) = &origin.reverse_dns {
+        lines.push(("Reverse DNS:", name.clone()));
+    }
+    let mut network: Vec<String> = Vec::new();
+    if let Some(asn) = origin.asn {
+        network.push(format!("AS{asn}"));
+    }
+    if let Some(org) = &origin.as_org {
+        network.push(org.clone());
+    }
+    if let Some(country) = &origin.country {
+        network.push(format!("({country})"));
+    }
+    if !network.is_empty() {
+        lines.push(("Network:", network.join(" ")));
+    }
+    lines
+}
+
 /// Render comparison results line by line with the legacy style.
 pub fn render_compare(results: &[ProbeResult], verbose: bool) -> String {
     let mut out = String::new();
@@ -183,12 +258,12 @@ pub fn render_compare(results: &[ProbeResult], verbose: bool) -> String {
     // Header
     if results.len() == 2 {
         out.push_str(&format!(
-            "{} -  {}:{} and {}:{}\n",
+            "{} -  {} ({}) and {} ({})\n",
             style("Comparing").bold(),
             style(&results[0].target.name).green(),
-            style(&results[0].target.port).green(),
+            style(results[0].target.endpoint()).green(),
             style(&results[1].target.name).green(),
-            style(&results[1].target.port).green()
+            style(results[1].target.endpoint()).green()
         ));
     } else {
         out.push_str(&format!(
@@ -205,8 +280,11 @@ pub fn render_compare(results: &[ProbeResult], verbose: bool) -> String {
         } else {
             style(r.target.ip).blue()
         };
-        let ip_version = if r.target.ip.is_ipv6() { "v6" } else { "v4" };
-        let offset_style = style(format!("{:.3} ms", r.offset_ms)).yellow();
+        let ip_version = match r.target.family() {
+            AddressFamily::Ipv4 => "v4",
+            AddressFamily::Ipv6 => "v6",
+        };
+        let offset_style = highlight::offset(r.offset_ms);
 
         let nts_badge = if r.authenticated {
             format!(" {}", style("[NTS]").green().bold())
@@ -228,23 +306,24 @@ pub fn render_compare(results: &[ProbeResult], verbose: bool) -> String {
         };
 
         out.push_str(&format!(
-            "{}{} [{} {}]: {}\n",
+            "{}{} [{} {}]: {}{}\n",
             style(&r.target.name).green().bold(),
             nts_badge,
             ip_style,
             ip_version,
-            offset_style
+            offset_style,
+            uncertainty(r.uncertainty_ms)
         ));
 
         if verbose {
             out.push_str(&format!(
-                "  {} {}\n  {} {}\n  {} {:.3} ms\n  {} {}\n",
+                "  {} {}\n  {} {}\n  {} {}\n  {} {}\n",
                 style("Stratum:").cyan().bold(),
                 r.stratum,
                 style("Reference ID:").cyan().bold(),
                 r.ref_id,
                 style("Round Trip Delay:").cyan().bold(),
-                r.rtt_ms,
+                highlight::rtt(r.rtt_ms),
                 style("Authenticated:").cyan().bold(),
                 if r.authenticated {
                     style("Yes (NTS)").green()
@@ -252,6 +331,23 @@ pub fn render_compare(results: &[ProbeResult], verbose: bool) -> String {
                     style("No").yellow()
                 }
             ));
+            out.push_str(&format!(
+                "  {} {}\n",
+                style("Resolved:").cyan().bold(),
+                resolution(&r.target)
+            ));
+            if let Some(timing) = &r.timing {
+                out.push_str(&format!(
+                    "  {} {}\n",
+                    style("Timing:").cyan().bold(),
+                    timing_breakdown(timing, r.rtt_ms)
+                ));
+            }
+            if let Some(origin) = &r.origin {
+                for (label, value) in origin_lines(origin) {
+                    out.push_str(&format!("  {} {}\n", style(label).cyan().bold(), value));
+                }
+            }
 
             // NTS-KE diagnostics in compare mode
             #[cfg(feature = "nts")]
@@ -280,14 +376,15 @@ pub fn render_compare(results: &[ProbeResult], verbose: bool) -> String {
         .fold(f64::NEG_INFINITY, f64::max);
     let avg = results.iter().map(|r| r.offset_ms).sum::<f64>() / results.len() as f64;
     let diff = max - min;
+    let unit = display_unit().resolve(diff);
 
     out.push_str(&format!(
-        "{} {:.3} ms (min: {:.3}, max: {:.3}, avg: {:.3})\n",
+        "{} {} (min: {}, max: {}, avg: {})\n",
         style("Max drift:").cyan().bold(),
-        diff,
-        min,
-        max,
-        avg
+        unit.format_ms(diff),
+        unit.format_value(min),
+        unit.format_value(max),
+        unit.format_value(avg)
     ));
 
     out
@@ -299,7 +396,7 @@ pub fn render_short_probe(r: &ProbeResult) -> String {
         "{name}:{port} {offset}",
         name = style(&r.target.name).green(),
         port = r.target.port,
-        offset = style(format!("{:.3} ms", r.offset_ms)).yellow()
+        offset = highlight::offset(r.offset_ms)
     )
 }
 
@@ -312,7 +409,12 @@ pub fn render_short_compare(results: &[ProbeResult]) -> String {
                 "{name}:{port}:{off}",
                 name = style(&r.target.name).green(),
                 port = r.target.port,
-                off = style(format!("{:.3}", r.offset_ms)).yellow()
+                off = highlight::paint(
+                    Metric::Offset,
+                    r.offset_ms,
+                    display_unit().format_value(r.offset_ms),
+                    StyledObject::yellow
+                )
             )
         })
         .collect::<Vec<_>>()
@@ -321,33 +423,809 @@ pub fn render_short_compare(results: &[ProbeResult]) -> String {
 
 /// Render statistics for a set of probe results
 pub fn render_stats(name: &str, stats: &Stats) -> String {
-    fn fmt_ms(v: f64) -> String {
-        format!("{:.3} ms", v)
-    }
-
-    format!(
-        "\n{n}: {avg_lbl} {avg} ({min_lbl} {min}, {max_lbl} {max}) {rtt_lbl} {rtt} ({cnt} {rqst})",
+    let line = format!(
+        "\n{n}: {avg_lbl} {avg} ({min_lbl} {min}, {max_lbl} {max}) {rtt_lbl} {rtt} ({cnt} {rqst}{rej})",
         n = style(name).green().bold(),
         avg_lbl = style("avg").cyan().bold(),
-        avg = style(fmt_ms(stats.offset_avg)).green(),
+        avg = style(format_ms(stats.offset_avg)).green(),
         min_lbl = style("min").cyan().bold(),
-        min = style(fmt_ms(stats.offset_min)).green(),
+        min = style(format_ms(stats.offset_min)).green(),
         max_lbl = style("max").cyan().bold(),
-        max = style(fmt_ms(stats.offset_max)).green(),
+        max = style(format_ms(stats.offset_max)).green(),
         rtt_lbl = style("rtt").cyan().bold(),
-        rtt = style(fmt_ms(stats.rtt_avg)).green(),
-        cnt = style(stats.count).green(),
+        rtt = style(format_ms(stats.rtt_avg)).green(),
+        cnt = style(stats.count + stats.rejected).green(),
         rqst = style("requests").green(),
+        rej = if stats.rejected > 0 {
+            format!(
+                ", {} {}",
+                style(stats.rejected).yellow(),
+                style("rejected").yellow()
+            )
+        } else {
+            String::new()
+        },
+    );
+    if stats.count < 2 {
+        return line;
+    }
+    let jitter = stats
+        .jitter
+        .map(|j| {
+            format!(
+                " {} {}",
+                style("jitter").cyan().bold(),
+                style(format_ms(j)).green()
+            )
+        })
+        .unwrap_or_default();
+    let drift = match (stats.drift_ppm, stats.drift_r2) {
+        (Some(ppm), Some(r2)) => format!(
+            "\n  {} {} ({} {})",
+            style("drift ").cyan().bold(),
+            style(format!("{:+.3} ppm", ppm)).green(),
+            style("R²").cyan().bold(),
+            style(format!("{:.3}", r2)).green()
+        ),
+        _ => String::new(),
+    };
+    format!(
+        "{line}\n  {off_lbl} {sd_lbl} {osd}{jitter} {med_lbl} {omed}{ounc} {p95_lbl} {op95} {p99_lbl} {op99}\n  {rtt_lbl} {sd_lbl} {rsd} {med_lbl} {rmed} {p95_lbl} {rp95} {p99_lbl} {rp99}{drift}",
+        off_lbl = style("offset").cyan().bold(),
+        rtt_lbl = style("rtt   ").cyan().bold(),
+        sd_lbl = style("stddev").cyan().bold(),
+        med_lbl = style("median").cyan().bold(),
+        p95_lbl = style("p95").cyan().bold(),
+        p99_lbl = style("p99").cyan().bold(),
+        osd = style(format_ms(stats.offset_stddev)).green(),
+        omed = style(format_ms(stats.offset_median)).green(),
+        ounc = uncertainty(stats.uncertainty),
+        op95 = style(format_ms(stats.offset_p95)).green(),
+        op99 = style(format_ms(stats.offset_p99)).green(),
+        rsd = style(format_ms(stats.rtt_stddev)).green(),
+        rmed = style(format_ms(stats.rtt_median)).green(),
+        rp95 = style(format_ms(stats.rtt_p95)).green(),
+        rp99 = style(format_ms(stats.rtt_p99)).green(),
+    )
+}
+
+/// Render the pairwise drift matrix; cells beyond `max_drift` (ms) are flagged with `!`.
+pub fn render_matrix(m: &DriftMatrix, max_drift: Option<f64>) -> String {
+    let largest = m
+        .offsets_ms
+        .iter()
+        .flatten()
+        .fold(0.0f64, |acc, v| acc.max(v.abs()));
+    let unit = display_unit().resolve(largest);
+    let cells: Vec<Vec<String>> = m
+        .offsets_ms
+        .iter()
+        .map(|row| row.iter().map(|v| unit.format_value(*v)).collect())
+        .collect();
+    let name_width = m.servers.iter().map(|s| s.len()).max().unwrap_or(0);
+    let width = m
+        .servers
+        .iter()
+        .map(|s| s.len())
+        .chain(cells.iter().flatten().map(|c| c.len() + 1))
+        .max()
+        .unwrap_or(0);
+
+    let mut out = format!(
+        "{} ({})\n{:name_width$}",
+        style("Drift matrix").cyan().bold(),
+        format_args!("row - column, {}", unit.as_str()),
+        ""
+    );
+    for name in &m.servers {
+        out.push_str(&format!("  {:>width$}", style(name).green()));
+    }
+    out.push('\n');
+    for (i, row) in cells.iter().enumerate() {
+        out.push_str(&format!(
+            "{:name_width$}",
+            style(&m.servers[i]).green().bold()
+        ));
+        for (j, cell) in row.iter().enumerate() {
+            let over = max_drift.is_some_and(|limit| m.offsets_ms[i][j].abs() > limit);
+            let text = format!(
+                "{:>width$}",
+                format!("{}{}", cell, if over { "!" } else { " " })
+            );
+            if over {
+                out.push_str(&format!("  {}", style(text).red().bold()));
+            } else {
+                out.push_str(&format!("  {}", text));
+            }
+        }
+        out.push('\n');
+    }
+    if let Some(limit) = max_drift {
+        let pairs = m.exceeding(limit);
+        if pairs.is_empty() {
+            out.push_str(&format!(
+                "{} all pairs within {}\n",
+                style("OK:").green().bold(),
+                format_ms(limit)
+            ));
+        } else {
+            for (i, j) in pairs {
+                out.push_str(&format!(
+                    "{} {} vs {} differ by {} (> {})\n",
+                    style("Drift:").red().bold(),
+                    m.servers[i],
+                    m.servers[j],
+                    format_ms(m.offsets_ms[i][j].abs()),
+                    format_ms(limit)
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// Render the servers advertised by `domain` (or found on the local link), one per line.
+pub fn render_discovery(domain: &str, servers: &[Advertised]) -> String {
+    let mut out = format!(
+        "{} {} ({} servers)\n",
+        style("Discovered:").cyan().bold(),
+        domain,
+        servers.len()
+    );
+    for s in servers {
+        out.push_str(&format!(
+            "  {} {}:{}  priority {} weight {}{}\n",
+            match s.protocol {
+                Scheme::Nts => style("NTS").green().bold(),
+                Scheme::Ntp => style("NTP").cyan(),
+                Scheme::Ptp => style("PTP").magenta(),
+                Scheme::Mock => style("MOCK").dim(),
+            },
+            style(&s.host).green(),
+            s.port,
+            s.priority,
+            s.weight,
+            s.instance
+                .as_ref()
+                .map(|i| format!("  ({})", i))
+                .unwrap_or_default()
+        ));
+    }
+    out
+}
+
+/// Render the report of `rkik diag`: one line per check, then the verdict.
+pub fn render_diagnosis(d: &Diagnosis) -> String {
+    let status = |s: Status| match s {
+        Status::Pass => style("PASS").green().bold(),
+        Status::Warn => style("WARN").yellow().bold(),
+        Status::Fail => style("FAIL").red().bold(),
+        Status::Info => style("INFO").cyan(),
+        Status::Skip => style("SKIP").dim(),
+    };
+    let mut out = format!("{} {}\n", style("Diagnosis:").cyan().bold(), d.target);
+    for c in &d.checks {
+        let took = c
+            .duration_ms
+            .map(|ms| format!(" ({})", format_ms(ms)))
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "  {} {:<12} {}{}\n",
+            status(c.status),
+            c.name,
+            c.detail,
+            style(took).dim()
+        ));
+    }
+    if !d.path.is_empty() {
+        out.push_str(&format!("{}\n", style("Path:").cyan().bold()));
+        for hop in &d.path {
+            let (addr, rtt) = match (hop.address, hop.rtt_ms) {
+                (Some(ip), Some(ms)) => (style(ip.to_string()).green(), format_ms(ms)),
+                _ => (style("*".to_string()).dim(), String::new()),
+            };
+            out.push_str(&format!("  {:>2}  {:<39} {}\n", hop.ttl, addr, rtt));
+        }
+    }
+    out.push_str(&format!(
+        "{} {}\n",
+        style("Verdict:").cyan().bold(),
+        status(d.verdict)
+    ));
+    out
+}
+
+/// Render what the local chronyd believes: its tracking report, then its
+/// sources as `chronyc sources` lists them.
+pub fn render_chronyd(tracking: &Tracking, sources: &[Source]) -> String {
+    let mut out = format!(
+        "{dmn_lbl} chronyd\n\
+         {ref_lbl} {reference} ({ref_id}), stratum {stratum}\n\
+         {leap_lbl} {leap}\n\
+         {upd_lbl} {updated} (every {interval:.1} s)\n\
+         {off_lbl} {offset}\n\
+         {last_lbl} {last} (rms {rms})\n\
+         {freq_lbl} {freq:+.3} ppm (residual {resid:+.3}, skew {skew:.3})\n\
+         {root_lbl} delay {delay}, dispersion {disp}\n",
+        dmn_lbl = style("Daemon:").cyan().bold(),
+        ref_lbl = style("Reference:").cyan().bold(),
+        reference = style(&tracking.reference).green(),
+        ref_id = tracking.ref_id,
+        stratum = tracking.stratum,
+        leap_lbl = style("Leap Status:").cyan().bold(),
+        leap = leap_status(tracking.leap),
+        upd_lbl = style("Last Update:").cyan().bold(),
+        updated = style(tracking.ref_time.to_rfc2822()).green(),
+        interval = tracking.update_interval_s,
+        off_lbl = style("Clock Offset:").cyan().bold(),
+        offset = style(format_ms(tracking.offset_ms)).yellow(),
+        last_lbl = style("Last Offset:").cyan().bold(),
+        last = format_ms(tracking.last_offset_ms),
+        rms = format_ms(tracking.rms_offset_ms),
+        freq_lbl = style("Frequency:").cyan().bold(),
+        freq = tracking.freq_ppm,
+        resid = tracking.resid_freq_ppm,
+        skew = tracking.skew_ppm,
+        root_lbl = style("Root:").cyan().bold(),
+        delay = format_ms(tracking.root_delay_ms),
+        disp = format_ms(tracking.root_dispersion_ms),
+    );
+    out.push_str(&format!(
+        "{} {}\n",
+        style("Sources:").cyan().bold(),
+        sources.len()
+    ));
+    for s in sources {
+        let state = match s.state {
+            SourceState::Selected => style(s.state.symbol()).green().bold(),
+            SourceState::Falseticker => style(s.state.symbol()).red().bold(),
+            _ => style(s.state.symbol()).yellow(),
+        };
+        out.push_str(&format!(
+            "  {state} {address} stratum {stratum:>2} poll {poll:>3} reach {reach:03o} {last_rx:>5}s ago  {offset} ± {error}\n",
+            address = style(format!("{:<28}", s.address)).green(),
+            stratum = s.stratum,
+            poll = s.poll,
+            reach = s.reach,
+            last_rx = s.last_rx_s,
+            offset = style(format_ms(s.offset_ms)).yellow(),
+            error = format_ms(s.error_ms),
+        ));
+    }
+    out
+}
+
+/// Render what the local ntpd believes: its system variables, then its
+/// peers as `ntpq -p` lists them.
+pub fn render_ntpd(system: &SystemVars, peers: &[Peer]) -> String {
+    let selected = system
+        .sys_peer
+        .and_then(|id| peers.iter().find(|p| p.assoc_id == id))
+        .map_or("none".to_string(), |p| {
+            p.host.clone().unwrap_or_else(|| p.address.clone())
+        });
+    let mut out = format!(
+        "{dmn_lbl} {version}\n\
+         {ref_lbl} {ref_id}, stratum {stratum}\n\
+         {peer_lbl} {selected}\n\
+         {leap_lbl} {leap}\n\
+         {off_lbl} {offset} (jitter {jitter})\n\
+         {freq_lbl} {freq:+.3} ppm (wander {wander:.3})\n\
+         {root_lbl} delay {delay}, dispersion {disp}\n",
+        dmn_lbl = style("Daemon:").cyan().bold(),
+        version = system.version.as_deref().unwrap_or("ntpd"),
+        ref_lbl = style("Reference:").cyan().bold(),
+        ref_id = style(&system.ref_id).green(),
+        stratum = system.stratum,
+        peer_lbl = style("Selected Peer:").cyan().bold(),
+        selected = style(selected).green(),
+        leap_lbl = style("Leap Status:").cyan().bold(),
+        leap = leap_status(system.leap),
+        off_lbl = style("Clock Offset:").cyan().bold(),
+        offset = style(format_ms(system.offset_ms)).yellow(),
+        jitter = format_ms(system.jitter_ms),
+        freq_lbl = style("Frequency:").cyan().bold(),
+        freq = system.freq_ppm,
+        wander = system.wander_ppm,
+        root_lbl = style("Root:").cyan().bold(),
+        delay = format_ms(system.root_delay_ms),
+        disp = format_ms(system.root_dispersion_ms),
+    );
+    out.push_str(&format!(
+        "{} {}\n",
+        style("Peers:").cyan().bold(),
+        peers.len()
+    ));
+    for p in peers {
+        let tally = match p.selection {
+            Selection::SysPeer | Selection::PpsPeer => style(p.selection.symbol()).green().bold(),
+            Selection::Falseticker => style(p.selection.symbol()).red().bold(),
+            _ => style(p.selection.symbol()).yellow(),
+        };
+        out.push_str(&format!(
+            "  {tally} {address} refid {ref_id:<15} stratum {stratum:>2} poll {poll:>3} reach {reach:03o}  delay {delay}  {offset} ± {jitter}\n",
+            address = style(format!("{:<28}", p.host.as_deref().unwrap_or(&p.address))).green(),
+            ref_id = p.ref_id,
+            stratum = p.stratum,
+            poll = p.poll,
+            reach = p.reach,
+            delay = format_ms(p.delay_ms),
+            offset = style(format_ms(p.offset_ms)).yellow(),
+            jitter = format_ms(p.jitter_ms),
+        ));
+    }
+    out
+}
+
+/// Render what the Windows Time service reports, as `w32tm /query /status`
+/// does, with the system time adjustment.
+pub fn render_w32time(w: &W32TimeStatus) -> String {
+    let ms = |v: Option<f64>| v.map_or("-".to_string(), format_ms);
+    let mut out = format!(
+        "{dmn_lbl} W32Time\n\
+         {src_lbl} {source} ({ref_id}), stratum {stratum}\n\
+         {leap_lbl} {leap}\n\
+         {sync_lbl} {last_sync}{poll}\n\
+         {off_lbl} {offset}\n\
+         {root_lbl} delay {delay}, dispersion {disp}\n",
+        dmn_lbl = style("Daemon:").cyan().bold(),
+        src_lbl = style("Source:").cyan().bold(),
+        source = style(&w.source).green(),
+        ref_id = w.ref_id,
+        stratum = w.stratum,
+        leap_lbl = style("Leap Status:").cyan().bold(),
+        leap = leap_status(w.leap),
+        sync_lbl = style("Last Sync:").cyan().bold(),
+        last_sync = style(w.last_sync.as_deref().unwrap_or("never")).green(),
+        poll = w
+            .poll
+            .map(|p| format!(" (every {} s)", 1u64 << p.clamp(0, 62)))
+            .unwrap_or_default(),
+        off_lbl = style("Phase Offset:").cyan().bold(),
+        offset = style(ms(w.phase_offset_ms)).yellow(),
+        root_lbl = style("Root:").cyan().bold(),
+        delay = ms(w.root_delay_ms),
+        disp = ms(w.root_dispersion_ms),
+    );
+    if let Some(adj) = &w.adjustment {
+        out.push_str(&format!(
+            "{lbl} {freq:+.3} ppm ({adjustment} per {increment} x 100 ns{disabled})\n",
+            lbl = style("Frequency:").cyan().bold(),
+            freq = adj.freq_ppm(),
+            adjustment = adj.adjustment,
+            increment = adj.increment,
+            disabled = if adj.disabled {
+                ", adjustment disabled"
+            } else {
+                ""
+            },
+        ));
+    }
+    out
+}
+
+/// Render the kernel clock discipline state (see `adapters::kernel`).
+pub fn render_kernel(k: &KernelClock) -> String {
+    format!(
+        "{hdr}\n\
+         {state_lbl} {state}\n\
+         {off_lbl} {offset}\n\
+         {freq_lbl} {freq:+.3} ppm\n\
+         {err_lbl} estimated {est}, maximum {max}\n\
+         {flags_lbl} {flags}\n\
+         {tai_lbl} {tai} s\n",
+        hdr = style("Kernel Clock:").cyan().bold(),
+        state_lbl = style("  State:").cyan().bold(),
+        state = if k.synchronised {
+            style(format!("{} (synchronised)", k.state.as_str())).green()
+        } else {
+            style(format!("{} (unsynchronised)", k.state.as_str())).red()
+        },
+        off_lbl = style("  PLL Offset:").cyan().bold(),
+        offset = format_ms(k.offset_ms),
+        freq_lbl = style("  Frequency:").cyan().bold(),
+        freq = k.freq_ppm,
+        err_lbl = style("  Error:").cyan().bold(),
+        est = format_ms(k.est_error_ms),
+        max = format_ms(k.max_error_ms),
+        flags_lbl = style("  Flags:").cyan().bold(),
+        flags = if k.flags.is_empty() {
+            "none".to_string()
+        } else {
+            k.flags.join(" ")
+        },
+        tai_lbl = style("  TAI Offset:").cyan().bold(),
+        tai = k.tai_offset_s,
+    )
+}
+
+/// Render an `rkik http` sample. The accuracy bound is printed next to the
+/// offset because it is orders of magnitude wider than an NTP probe's.
+pub fn render_http_date(s: &HttpDateSample) -> String {
+    let ip = if s.ip.is_ipv6() {
+        format!("[{}]", s.ip)
+    } else {
+        s.ip.to_string()
+    };
+    format!(
+        "{url_lbl} {url} (HTTP {status})\n\
+         {ip_lbl} {ip}:{port}\n\
+         {date_lbl} {date}\n\
+         {off_lbl} {offset} {acc}\n\
+         {rtt_lbl} {rtt}\n\
+         {note}\n",
+        url_lbl = style("URL:").cyan().bold(),
+        url = style(&s.url).green(),
+        status = s.status,
+        ip_lbl = style("IP:").cyan().bold(),
+        ip = style(ip).green(),
+        port = style(s.port).green(),
+        date_lbl = style("Server Date:").cyan().bold(),
+        date = style(s.server_date.to_rfc2822()).green(),
+        off_lbl = style("Clock Offset:").cyan().bold(),
+        offset = highlight::offset(s.offset_ms),
+        acc = style(format!("(± {})", format_ms(s.accuracy_ms))).yellow(),
+        rtt_lbl = style("Round Trip Delay:").cyan().bold(),
+        rtt = highlight::rtt(s.rtt_ms),
+        note = style("Note: HTTP Date has a 1 s resolution; this is a coarse estimate, not an NTP measurement.").yellow(),
+    )
+}
+
+/// Render the ranked members of an `rkik survey`, one per line, best first.
+pub fn render_survey(survey: &Survey) -> String {
+    let answered = survey.members.iter().filter(|m| m.answered > 0).count();
+    let mut out = format!(
+        "{} {} ({} members, {} answered)\n",
+        style("Survey:").cyan().bold(),
+        style(&survey.pool).green(),
+        survey.members.len(),
+        answered
+    );
+    let width = survey
+        .members
+        .iter()
+        .map(|m| m.ip.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("ADDRESS".len());
+    out.push_str(&format!(
+        "  {:>4}  {:<width$}  {:>12}  {:>12}  {:>7}  {:<15}  {}\n",
+        "RANK", "ADDRESS", "OFFSET", "RTT", "STRATUM", "REF ID", "ASN"
+    ));
+    for (rank, m) in survey.members.iter().enumerate() {
+        let address = format!("{:<width$}", m.ip);
+        let (Some(offset), Some(rtt)) = (m.offset_ms, m.rtt_ms) else {
+            let error = m
+                .error
+                .as_ref()
+                .map_or("no answer".to_string(), ToString::to_string);
+            out.push_str(&format!(
+                "  {:>4}  {}  {}\n",
+                "-",
+                style(address).dim(),
+                style(error).red()
+            ));
+            continue;
+        };
+        let asn = m
+            .origin
+            .as_ref()
+            .and_then(|o| {
+                let asn = o.asn.map(|n| format!("AS{n}"));
+                match (asn, &o.as_org) {
+                    (Some(asn), Some(org)) => Some(format!("{asn} {org}")),
+                    (asn, _) => asn,
+                }
+            })
+            .unwrap_or_else(|| "-".into());
+        out.push_str(&format!(
+            "  {:>4}  {}  {}  {}  {:>7}  {:<15}  {}\n",
+            rank + 1,
+            style(address).green(),
+            pad_str(
+                &highlight::offset(offset).to_string(),
+                12,
+                Alignment::Right,
+                None
+            ),
+            pad_str(&highlight::rtt(rtt).to_string(), 12, Alignment::Right, None),
+            m.stratum.map_or("-".into(), |s| s.to_string()),
+            m.ref_id.as_deref().unwrap_or("-"),
+            asn
+        ));
+    }
+    out
+}
+
+/// Render the recommended servers, best first, with what their score is
+/// made of.
+pub fn render_recommendation(recommendations: &[Recommendation]) -> String {
+    let mut out = format!("{}\n", style("Recommended:").cyan().bold());
+    if recommendations.is_empty() {
+        out.push_str(&format!("  {}\n", style("no usable server").red()));
+    }
+    for (rank, r) in recommendations.iter().enumerate() {
+        out.push_str(&format!(
+            "  {}. {}  score {}  (offset {}, {} from consensus, rtt {}, jitter {}, stratum {}{})\n",
+            rank + 1,
+            style(&r.target).green().bold(),
+            style(format_ms(r.score)).yellow(),
+            highlight::offset(r.offset_ms),
+            format_ms(r.deviation_ms),
+            highlight::rtt(r.rtt_ms),
+            r.jitter_ms.map_or("-".into(), format_ms),
+            r.stratum,
+            if r.nts { ", NTS" } else { "" }
+        ));
+    }
+    out
+}
+
+/// Render the paths of `rkik asymmetry` and the interval they leave for
+/// the true offset.
+pub fn render_asymmetry(a: &Asymmetry) -> String {
+    let mut out = format!("{}\n", style("Paths:").cyan().bold());
+    for p in &a.paths {
+        let family = match p.family {
+            AddressFamily::Ipv4 => "v4",
+            AddressFamily::Ipv6 => "v6",
+        };
+        let path = format!("{} [{} {}]", p.target, p.ip, family);
+        match (p.offset_ms, p.rtt_ms, p.bounds()) {
+            (Some(offset), Some(rtt), Some((low, high))) => out.push_str(&format!(
+                "  {}: offset {}, rtt {}, true offset in [{}, {}]\n",
+                style(path).green().bold(),
+                highlight::offset(offset),
+                highlight::rtt(rtt),
+                format_ms(low),
+                format_ms(high)
+            )),
+            _ => out.push_str(&format!(
+                "  {}: {}\n",
+                style(path).dim(),
+                style(
+                    p.error
+                        .as_ref()
+                        .map_or("no answer".to_string(), ToString::to_string)
+                )
+                .red()
+            )),
+        }
+    }
+    for gap in &a.family_gaps {
+        out.push_str(&format!(
+            "{} {}: IPv6 {} from IPv4, path asymmetries differ by at least {}\n",
+            style("IPv4/IPv6:").cyan().bold(),
+            gap.target,
+            format_ms(gap.gap_ms),
+            format_ms(2.0 * gap.gap_ms.abs())
+        ));
+    }
+    out.push_str(&format!(
+        "{} {} between paths, asymmetries differ by at least {}\n",
+        style("Spread:").cyan().bold(),
+        format_ms(a.spread_ms),
+        format_ms(a.min_asymmetry_ms)
+    ));
+    if !a.consistent {
+        out.push_str(&format!(
+            "{}\n",
+            style(
+                "No offset fits every path: a server or a path is worse than its round trip allows"
+            )
+            .yellow()
+        ));
+    }
+    out.push_str(&format!(
+        "{} {} ± {} (between {} and {})\n",
+        style("True offset:").cyan().bold(),
+        highlight::offset(a.offset_ms),
+        format_ms(a.uncertainty_ms),
+        format_ms(a.low_ms),
+        format_ms(a.high_ms)
+    ));
+    out
+}
+
+/// Render the frequency error and realtime steps found by `rkik check-clock`.
+pub fn render_clock_check(c: &ClockCheck) -> String {
+    let seconds = |ms: f64| format!("{:.3} s", ms / 1000.0);
+    let mut out = format!(
+        "{} {}\n{} {} monotonic, {} server, {} realtime\n",
+        style("Server:").cyan().bold(),
+        style(&c.target).green(),
+        style("Elapsed:").cyan().bold(),
+        seconds(c.monotonic_ms),
+        seconds(c.server_ms),
+        seconds(c.realtime_ms)
+    );
+    let bound = c
+        .frequency_uncertainty_ppm
+        .map_or(String::new(), |u| format!(" ± {u:.3} ppm"));
+    let pace = if c.frequency_ppm >= 0.0 {
+        "fast"
+    } else {
+        "slow"
+    };
+    out.push_str(&format!(
+        "{} {}{} (local clock runs {})\n",
+        style("Frequency:").cyan().bold(),
+        style(format!("{:+.3} ppm", c.frequency_ppm)).green(),
+        bound,
+        pace
+    ));
+    out.push_str(&format!(
+        "{} {:+.3} ms against the monotonic clock\n",
+        style("Realtime:").cyan().bold(),
+        c.realtime_adjustment_ms
+    ));
+    if c.backward_steps.is_empty() {
+        out.push_str(&format!(
+            "{} {}\n",
+            style("Backward steps:").cyan().bold(),
+            style("none").green()
+        ));
+    }
+    for step in &c.backward_steps {
+        out.push_str(&format!(
+            "{} {} at {}\n",
+            style("Backward step:").cyan().bold(),
+            style(format_ms(step.step_ms)).red().bold(),
+            step.utc.format("%H:%M:%S%.3f")
+        ));
+    }
+    out
+}
+
+/// Render the handshakes of `rkik nts-ke`, one line each, then what
+/// resumption saved.
+#[cfg(feature = "nts")]
+pub fn render_nts_ke(r: &NtsKeReport) -> String {
+    let mut out = format!(
+        "{} {}\n",
+        style("Server:").cyan().bold(),
+        style(&r.target).green()
+    );
+    if let Some(h) = r.handshakes.first() {
+        let alpn = match h.alpn.as_deref() {
+            Some(alpn @ "ntske/1") => style(alpn.to_string()).green(),
+            Some(other) => style(other.to_string()).red(),
+            None => style("none".to_string()).red(),
+        };
+        out.push_str(&format!(
+            "{} {} {}, ALPN {}\n",
+            style("TLS:").cyan().bold(),
+            h.tls_version,
+            h.cipher_suite,
+            alpn
+        ));
+    }
+    for (i, h) in r.handshakes.iter().enumerate() {
+        let kind = match (h.resumed, h.hello_retry) {
+            (true, _) => style("resumed").green(),
+            (false, true) => style("full+HRR").yellow(),
+            (false, false) => style("full").yellow(),
+        };
+        out.push_str(&format!(
+            "{} {} {} {} (dns {}, tcp {}, tls {}, ke {}), {} cookies\n",
+            style(format!("#{}:", i + 1)).cyan().bold(),
+            h.ip,
+            kind,
+            style(format_ms(h.total_ms)).green(),
+            format_ms(h.dns_ms),
+            format_ms(h.tcp_ms),
+            format_ms(h.tls_ms),
+            format_ms(h.ke_ms),
+            h.cookie_count
+        ));
+    }
+    match (r.cold_ms, r.warm_ms) {
+        (Some(cold), Some(warm)) => out.push_str(&format!(
+            "{} {} cold, {} warm ({} saved by resumption)\n",
+            style("Handshake:").cyan().bold(),
+            format_ms(cold),
+            format_ms(warm),
+            format_ms(cold - warm)
+        )),
+        (Some(cold), None) if r.handshakes.len() > 1 => out.push_str(&format!(
+            "{} {} cold; {}\n",
+            style("Handshake:").cyan().bold(),
+            format_ms(cold),
+            style("the server never resumed a session").yellow()
+        )),
+        _ => {}
+    }
+    out
+}
+
+/// One line for a divergence event of `--diverge-threshold`.
+pub fn render_divergence(event: &DivergenceEvent) -> String {
+    let kind = match event.event {
+        DivergenceKind::Diverged => style("DIVERGED").yellow().bold(),
+        DivergenceKind::Recovered => style("RECOVERED").green().bold(),
+    };
+    let baseline = event
+        .baseline_ms
+        .map_or(String::new(), |b| format!(", usually {}", format_ms(b)));
+    format!(
+        "{} {} [{}]: offset {}, group {}, {} from the group (threshold {}{})",
+        kind,
+        style(&event.server).bold(),
+        event.utc.format("%H:%M:%S"),
+        highlight::offset(event.offset_ms),
+        format_ms(event.group_offset_ms),
+        format_ms(event.deviation_ms),
+        format_ms(event.threshold_ms),
+        baseline
     )
 }
 
+/// How the address of `target` was found and the other addresses it had.
+fn resolution(target: &Target) -> String {
+    let mut out = format!(
+        "{} via {}",
+        target.family().as_str(),
+        target.resolution.as_str()
+    );
+    let others: Vec<String> = target
+        .addresses
+        .iter()
+        .filter(|ip| **ip != target.ip)
+        .map(ToString::to_string)
+        .collect();
+    if !others.is_empty() {
+        out.push_str(&format!(" (also {})", style(others.join(", ")).dim()));
+    }
+    out
+}
+
+/// ` ± bound` after an offset, or nothing when the bound is unknown.
+fn uncertainty(bound_ms: Option<f64>) -> String {
+    bound_ms
+        .map(|b| format!(" {}", style(format!("± {}", format_ms(b))).dim()))
+        .unwrap_or_default()
+}
+
+/// Where the time of a probe went: DNS, socket, network and server.
+fn timing_breakdown(timing: &Timing, rtt_ms: f64) -> String {
+    format!(
+        "DNS {}, socket {}, network {}, server {}",
+        format_ms(timing.dns_ms),
+        format_ms(timing.socket_setup_ms),
+        highlight::rtt(rtt_ms),
+        format_ms(timing.processing_ms)
+    )
+}
+
+fn health_status(health: &Health) -> String {
+    let verdict = match health.verdict {
+        HealthVerdict::Ok => style("OK").green(),
+        HealthVerdict::Suspect => style("Suspect").yellow(),
+        HealthVerdict::Bogus => style("Bogus").red(),
+    };
+    if health.issues.is_empty() {
+        return verdict.to_string();
+    }
+    let issues: Vec<String> = health.issues.iter().map(ToString::to_string).collect();
+    format!("{} ({})", verdict, issues.join(", "))
+}
+
+fn leap_status(leap: Leap) -> console::StyledObject<&'static str> {
+    match leap {
+        Leap::Normal => style("Normal").green(),
+        Leap::InsertSecond => style("Insert second").yellow(),
+        Leap::DeleteSecond => style("Delete second").yellow(),
+        Leap::Unsynchronised => style("Not synchronised").red(),
+    }
+}
+
 /// Render a probe in simple mode (offset and IP only).
 pub fn render_simple_probe(r: &ProbeResult) -> String {
     format!(
         "{name}:{port} {offset}",
         name = style(&r.target.name).green(),
         port = style(&r.target.port).green(),
-        offset = style(format!("{:.3} ms", r.offset_ms)).yellow()
+        offset = highlight::offset(r.offset_ms)
     )
 }
 
@@ -359,3 +1237,25 @@ pub fn render_simple_compare(results: &[ProbeResult]) -> String {
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+/// Render a probe as space separated values of the selected fields.
+pub fn render_fields_probe(r: &ProbeResult, fields: &[Field]) -> String {
+    fields
+        .iter()
+        .map(|f| match f {
+            Field::Name => style(f.value(r)).green().to_string(),
+            Field::Offset => style(f.value(r)).yellow().to_string(),
+            _ => f.value(r).to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Render multiple probes in field-selection mode, one line each.
+pub fn render_fields_compare(results: &[ProbeResult], fields: &[Field]) -> String {
+    results
+        .iter()
+        .map(|r| render_fields_probe(r, fields))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
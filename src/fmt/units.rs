@@ -0,0 +1,167 @@
+//! Display unit for offsets and delays (`--unit`).
+//!
+//! Like `console::set_colors_enabled`, the unit is a process-wide setting:
+//! the CLI sets it once and every renderer picks it up.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::error::RkikError;
+
+/// Unit used to render offsets and round-trip delays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeUnit {
+    S,
+    #[default]
+    Ms,
+    Us,
+    Ns,
+    /// Pick the most readable unit for each value (text output only).
+    Auto,
+}
+
+static DISPLAY_UNIT: AtomicU8 = AtomicU8::new(1);
+
+/// Set the unit used by all renderers.
+pub fn set_display_unit(unit: TimeUnit) {
+    DISPLAY_UNIT.store(unit as u8, Ordering::Relaxed);
+}
+
+/// Unit currently used by renderers.
+pub fn display_unit() -> TimeUnit {
+    match DISPLAY_UNIT.load(Ordering::Relaxed) {
+        0 => TimeUnit::S,
+        2 => TimeUnit::Us,
+        3 => TimeUnit::Ns,
+        4 => TimeUnit::Auto,
+        _ => TimeUnit::Ms,
+    }
+}
+
+impl TimeUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeUnit::S => "s",
+            TimeUnit::Ms => "ms",
+            TimeUnit::Us => "us",
+            TimeUnit::Ns => "ns",
+            TimeUnit::Auto => "auto",
+        }
+    }
+
+    /// Concrete unit for a value expressed in milliseconds.
+    pub fn resolve(self, ms: f64) -> TimeUnit {
+        if self != TimeUnit::Auto {
+            return self;
+        }
+        let abs = ms.abs();
+        if abs >= 1000.0 {
+            TimeUnit::S
+        } else if abs >= 1.0 || abs == 0.0 {
+            TimeUnit::Ms
+        } else if abs >= 0.001 {
+            TimeUnit::Us
+        } else {
+            TimeUnit::Ns
+        }
+    }
+
+    /// Concrete unit for machine output, where every row must share one unit.
+    /// `Auto` falls back to milliseconds.
+    pub fn fixed(self) -> TimeUnit {
+        match self {
+            TimeUnit::Auto => TimeUnit::Ms,
+            other => other,
+        }
+    }
+
+    /// Convert a millisecond value into this unit.
+    pub fn from_ms(self, ms: f64) -> f64 {
+        match self.fixed() {
+            TimeUnit::S => ms / 1000.0,
+            TimeUnit::Us => ms * 1000.0,
+            TimeUnit::Ns => ms * 1_000_000.0,
+            _ => ms,
+        }
+    }
+
+    /// Convert a value in this unit back into milliseconds.
+    pub fn to_ms(self, value: f64) -> f64 {
+        value / self.from_ms(1.0)
+    }
+
+    fn precision(self) -> usize {
+        match self.fixed() {
+            TimeUnit::S => 6,
+            TimeUnit::Ns => 0,
+            _ => 3,
+        }
+    }
+
+    /// Format a millisecond value as `<number> <unit>`.
+    pub fn format_ms(self, ms: f64) -> String {
+        let unit = self.resolve(ms);
+        format!(
+            "{:.*} {}",
+            unit.precision(),
+            unit.from_ms(ms),
+            unit.as_str()
+        )
+    }
+
+    /// Format a millisecond value as a bare number in the fixed unit.
+    pub fn format_value(self, ms: f64) -> String {
+        let unit = self.fixed();
+        format!("{:.*}", unit.precision(), unit.from_ms(ms))
+    }
+}
+
+/// Format a millisecond value with the current display unit, e.g. `1.234 ms`.
+pub fn format_ms(ms: f64) -> String {
+    display_unit().format_ms(ms)
+}
+
+impl FromStr for TimeUnit {
+    type Err = RkikError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "s" | "sec" => Ok(TimeUnit::S),
+            "ms" => Ok(TimeUnit::Ms),
+            "us" | "µs" => Ok(TimeUnit::Us),
+            "ns" => Ok(TimeUnit::Ns),
+            "auto" => Ok(TimeUnit::Auto),
+            other => Err(RkikError::Other(format!(
+                "unknown unit '{other}' (expected s, ms, us, ns or auto)"
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for TimeUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_units_convert_from_milliseconds() {
+        assert_eq!(TimeUnit::Us.format_ms(1.5), "1500.000 us");
+        assert_eq!(TimeUnit::Ns.format_ms(-0.25), "-250000 ns");
+        assert_eq!(TimeUnit::S.format_ms(1234.5), "1.234500 s");
+        assert_eq!(TimeUnit::Ms.format_value(2.0), "2.000");
+    }
+
+    #[test]
+    fn auto_picks_readable_unit() {
+        assert_eq!(TimeUnit::Auto.format_ms(2500.0), "2.500000 s");
+        assert_eq!(TimeUnit::Auto.format_ms(12.0), "12.000 ms");
+        assert_eq!(TimeUnit::Auto.format_ms(0.042), "42.000 us");
+        assert_eq!(TimeUnit::Auto.format_ms(0.0005), "500 ns");
+        assert_eq!(TimeUnit::Auto.fixed(), TimeUnit::Ms);
+    }
+}
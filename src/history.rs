@@ -0,0 +1,275 @@
+//! Recent probes of each target, for rolling statistics.
+//!
+//! A [`History`] keeps the last `capacity` successful probes of every
+//! target it is given, oldest first, and drops the oldest once full. It
+//! locks internally, so one instance behind an `Arc` can be fed by the
+//! probing tasks and read by whatever reports on them (metrics endpoint,
+//! control socket, display). Statistics are computed on demand over the
+//! whole window or over its most recent part.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+
+use crate::domain::ntp::ProbeResult;
+use crate::stats::{Stats, compute_stats};
+
+/// Probes kept per target when nothing else is configured.
+pub const DEFAULT_CAPACITY: usize = 20;
+
+/// Fixed-capacity store of the recent probes of each target.
+#[derive(Debug)]
+pub struct History {
+    capacity: usize,
+    /// Targets in the order they were first recorded.
+    series: Mutex<Vec<(String, VecDeque<ProbeResult>)>>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl History {
+    /// Keep up to `capacity` probes per target (at least 1).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            series: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Record `probe` for `target`, dropping its oldest probe when full.
+    pub fn push(&self, target: &str, probe: ProbeResult) {
+        let mut series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        let samples = match series.iter().position(|(t, _)| t == target) {
+            Some(i) => &mut series[i].1,
+            None => {
+                series.push((target.to_string(), VecDeque::with_capacity(self.capacity)));
+                &mut series.last_mut().expect("just pushed").1
+            }
+        };
+        if samples.len() == self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(probe);
+    }
+
+    /// The probes of `target`, oldest first.
+    pub fn samples(&self, target: &str) -> Vec<ProbeResult> {
+        self.with(target, |s| s.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The probes of `target` taken within `span` of its latest one.
+    pub fn recent(&self, target: &str, span: Duration) -> Vec<ProbeResult> {
+        self.with(target, |s| {
+            let Some(latest) = s.back().map(|p| p.utc) else {
+                return Vec::new();
+            };
+            let span = chrono::TimeDelta::from_std(span).unwrap_or(chrono::TimeDelta::MAX);
+            s.iter()
+                .filter(|p| latest - p.utc <= span)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+
+    /// Number of probes kept for `target`.
+    pub fn len(&self, target: &str) -> usize {
+        self.with(target, VecDeque::len).unwrap_or(0)
+    }
+
+    /// Whether no probe is kept for any target.
+    pub fn is_empty(&self) -> bool {
+        self.series
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .all(|(_, s)| s.is_empty())
+    }
+
+    /// Targets with probes, in the order they were first recorded.
+    pub fn targets(&self) -> Vec<String> {
+        self.series
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(t, _)| t.clone())
+            .collect()
+    }
+
+    /// Statistics over the whole window of `target`, `None` without probes.
+    pub fn stats(&self, target: &str) -> Option<Stats> {
+        let samples = self.samples(target);
+        (!samples.is_empty()).then(|| compute_stats(&samples))
+    }
+
+    /// Statistics over the probes of `target` within `span` of its latest one.
+    pub fn stats_within(&self, target: &str, span: Duration) -> Option<Stats> {
+        let samples = self.recent(target, span);
+        (!samples.is_empty()).then(|| compute_stats(&samples))
+    }
+
+    /// Forget `target` and its probes.
+    pub fn remove(&self, target: &str) {
+        self.series
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(t, _)| t != target);
+    }
+
+    /// Forget every target.
+    pub fn clear(&self) {
+        self.series
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Copy of the whole store, e.g. to serialize it.
+    pub fn snapshot(&self) -> HistorySnapshot {
+        let series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        HistorySnapshot {
+            capacity: self.capacity,
+            targets: series
+                .iter()
+                .map(|(target, samples)| TargetHistory {
+                    target: target.clone(),
+                    samples: samples.iter().cloned().collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Store holding the probes of `snapshot`, keeping the most recent
+    /// ones of each target when there are more than its capacity.
+    pub fn restore(snapshot: HistorySnapshot) -> Self {
+        let history = Self::new(snapshot.capacity);
+        for t in snapshot.targets {
+            for probe in t.samples {
+                history.push(&t.target, probe);
+            }
+        }
+        history
+    }
+
+    fn with<T>(&self, target: &str, f: impl FnOnce(&VecDeque<ProbeResult>) -> T) -> Option<T> {
+        let series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        series.iter().find(|(t, _)| t == target).map(|(_, s)| f(s))
+    }
+}
+
+/// Contents of a [`History`] at one moment.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct HistorySnapshot {
+    pub capacity: usize,
+    pub targets: Vec<TargetHistory>,
+}
+
+/// The probes kept for one target, oldest first.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct TargetHistory {
+    pub target: String,
+    pub samples: Vec<ProbeResult>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::{Leap, Target};
+    use chrono::{TimeDelta, Utc};
+
+    fn probe(offset_ms: f64, age_s: i64) -> ProbeResult {
+        let utc = Utc::now() - TimeDelta::seconds(age_s);
+        ProbeResult {
+            target: Target::new("t", "192.0.2.1".parse().unwrap(), 123),
+            offset_ms,
+            rtt_ms: 10.0,
+            stratum: 2,
+            ref_id: "GPS".into(),
+            utc,
+            local: utc.into(),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn keeps_the_latest_probes_of_each_target() {
+        let history = History::new(2);
+        history.push("a", probe(1.0, 0));
+        history.push("b", probe(10.0, 0));
+        history.push("a", probe(2.0, 0));
+        history.push("a", probe(3.0, 0));
+        let offsets: Vec<f64> = history.samples("a").iter().map(|p| p.offset_ms).collect();
+        assert_eq!(offsets, [2.0, 3.0]);
+        assert_eq!(history.len("b"), 1);
+        assert_eq!(history.targets(), ["a", "b"]);
+        assert!(history.stats("c").is_none());
+
+        history.remove("a");
+        assert_eq!(history.targets(), ["b"]);
+    }
+
+    #[test]
+    fn windowed_stats_only_see_recent_probes() {
+        let history = History::new(10);
+        for (offset, age) in [(100.0, 300), (2.0, 20), (4.0, 0)] {
+            history.push("t", probe(offset, age));
+        }
+        let recent = history.stats_within("t", Duration::from_secs(60)).unwrap();
+        assert_eq!((recent.count, recent.offset_avg), (2, 3.0));
+        assert_eq!(history.stats("t").unwrap().count, 3);
+    }
+
+    #[test]
+    fn restore_keeps_the_most_recent_probes() {
+        let history = History::new(5);
+        for offset in [1.0, 2.0, 3.0] {
+            history.push("t", probe(offset, 0));
+        }
+        let mut snapshot = history.snapshot();
+        snapshot.capacity = 2;
+        let restored = History::restore(snapshot);
+        let offsets: Vec<f64> = restored.samples("t").iter().map(|p| p.offset_ms).collect();
+        assert_eq!(offsets, [2.0, 3.0]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn snapshots_round_trip_through_json() {
+        let history = History::new(4);
+        history.push("a", probe(1.5, 0));
+        history.push("b", probe(-2.0, 0));
+        let json = serde_json::to_string(&history.snapshot()).unwrap();
+        let restored = History::restore(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.capacity(), 4);
+        assert_eq!(restored.targets(), ["a", "b"]);
+        assert_eq!(restored.samples("b")[0].offset_ms, -2.0);
+    }
+}
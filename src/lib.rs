@@ -4,13 +4,24 @@ pub mod adapters;
 pub mod domain;
 mod error;
 pub mod fmt;
+pub mod history;
 pub mod services;
 pub mod stats;
 
-pub use domain::ntp::{ProbeResult, Target};
+pub use domain::ntp::{
+    AddressFamily, Health, HealthIssue, HealthVerdict, Leap, Origin, ProbeResult, RawTimestamps,
+    Resolution, Target, Timing,
+};
 pub use error::RkikError;
-pub use services::compare::compare_many;
-pub use services::query::query_one;
+#[allow(deprecated)]
+pub use services::compare::{CompareOutcome, compare, compare_many};
+pub use services::events::{ProbeEvent, ProbeObserver};
+pub use services::monitor::{MonitorOptions, probe_stream};
+#[allow(deprecated)]
+pub use services::query::{QueryOptions, query, query_one};
+pub use tokio_util::sync::CancellationToken;
 
+#[cfg(feature = "ffi")]
+pub mod ffi;
 #[cfg(feature = "sync")]
 pub mod sync;
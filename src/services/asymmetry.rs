@@ -0,0 +1,345 @@
+//! Bounds on the true offset from several network paths to one site.
+//!
+//! An NTP offset assumes the request and the reply took as long; when they
+//! did not, it is off by half the difference, and nothing in a single
+//! exchange tells by how much. What is known is that the error stays within
+//! half the round trip, so every path gives an interval that contains the
+//! true offset: `offset ± rtt / 2`. Several paths to the same site (its
+//! IPv4 and IPv6 addresses, or several servers in one rack) give several
+//! intervals, and the true offset lies where they overlap. Two paths whose
+//! offsets differ by `d` also prove their asymmetries differ by at least
+//! `2d`, since the servers agree and only the paths can tell them apart.
+//!
+//! Each path is probed a few times and judged by its fastest exchange, the
+//! one queueing delays distorted least.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use futures::future::join_all;
+
+use crate::adapters::resolver;
+use crate::domain::ntp::{AddressFamily, ProbeResult};
+use crate::error::RkikError;
+use crate::services::query::{QueryOptions, parse_target, query, split_scheme};
+
+/// How the paths are probed.
+#[derive(Debug, Clone)]
+pub struct AsymmetryOptions {
+    /// Probes of each path (default 4).
+    pub samples: u32,
+    /// Pause between two probes of a path (default 1 s).
+    pub interval: Duration,
+    /// Timeout, address family and port of the probes. Unless
+    /// `ipv6_only` is set, each name is probed over both families.
+    pub query: QueryOptions,
+}
+
+impl Default for AsymmetryOptions {
+    fn default() -> Self {
+        Self {
+            samples: 4,
+            interval: Duration::from_secs(1),
+            query: QueryOptions::default(),
+        }
+    }
+}
+
+impl AsymmetryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At least 1.
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn query(mut self, query: QueryOptions) -> Self {
+        self.query = query;
+        self
+    }
+}
+
+/// One address of a target and its fastest exchange.
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Path {
+    /// Target as given.
+    pub target: String,
+    pub ip: IpAddr,
+    pub family: AddressFamily,
+    /// Probes answered.
+    pub answered: u32,
+    /// Offset of the fastest exchange (ms).
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub offset_ms: Option<f64>,
+    /// Round trip of the fastest exchange (ms).
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub rtt_ms: Option<f64>,
+    /// Last failure, when no probe was answered.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub error: Option<RkikError>,
+}
+
+impl Path {
+    /// The interval the true offset lies in as seen over this path.
+    pub fn bounds(&self) -> Option<(f64, f64)> {
+        let (offset, rtt) = (self.offset_ms?, self.rtt_ms?);
+        Some((offset - rtt / 2.0, offset + rtt / 2.0))
+    }
+}
+
+/// Offsets of the IPv4 and IPv6 paths of one target.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct FamilyGap {
+    pub target: String,
+    pub ipv4_offset_ms: f64,
+    pub ipv6_offset_ms: f64,
+    /// IPv6 minus IPv4 (ms); the asymmetries of the two paths differ by
+    /// at least twice as much.
+    pub gap_ms: f64,
+}
+
+/// What the paths together say about the true offset.
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Asymmetry {
+    pub paths: Vec<Path>,
+    pub family_gaps: Vec<FamilyGap>,
+    /// Largest offset minus smallest offset among the answered paths (ms).
+    pub spread_ms: f64,
+    /// Smallest difference between two path asymmetries the spread proves
+    /// (ms).
+    pub min_asymmetry_ms: f64,
+    /// Whether one interval overlaps every path's. When not, a server or a
+    /// path is worse than its round trip allows, and the interval below is
+    /// the hull of all paths instead.
+    pub consistent: bool,
+    /// Lower bound of the true offset (ms).
+    pub low_ms: f64,
+    /// Upper bound of the true offset (ms).
+    pub high_ms: f64,
+    /// Middle of the interval (ms).
+    pub offset_ms: f64,
+    /// Half the width of the interval (ms).
+    pub uncertainty_ms: f64,
+}
+
+/// Combine the paths; `None` when none of them answered.
+pub fn estimate(paths: Vec<Path>) -> Option<Asymmetry> {
+    let bounds: Vec<(f64, f64)> = paths.iter().filter_map(Path::bounds).collect();
+    if bounds.is_empty() {
+        return None;
+    }
+    let low = bounds.iter().map(|b| b.0).fold(f64::NEG_INFINITY, f64::max);
+    let high = bounds.iter().map(|b| b.1).fold(f64::INFINITY, f64::min);
+    let consistent = low <= high;
+    let (low_ms, high_ms) = if consistent {
+        (low, high)
+    } else {
+        (
+            bounds.iter().map(|b| b.0).fold(f64::INFINITY, f64::min),
+            bounds.iter().map(|b| b.1).fold(f64::NEG_INFINITY, f64::max),
+        )
+    };
+    let offsets: Vec<f64> = paths.iter().filter_map(|p| p.offset_ms).collect();
+    let spread_ms = offsets.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+        - offsets.iter().copied().fold(f64::INFINITY, f64::min);
+    let mut family_gaps = Vec::new();
+    for v4 in paths.iter().filter(|p| p.family == AddressFamily::Ipv4) {
+        let v6 = paths
+            .iter()
+            .find(|p| p.target == v4.target && p.family == AddressFamily::Ipv6);
+        if let (Some(ipv4_offset_ms), Some(ipv6_offset_ms)) =
+            (v4.offset_ms, v6.and_then(|p| p.offset_ms))
+        {
+            family_gaps.push(FamilyGap {
+                target: v4.target.clone(),
+                ipv4_offset_ms,
+                ipv6_offset_ms,
+                gap_ms: ipv6_offset_ms - ipv4_offset_ms,
+            });
+        }
+    }
+    Some(Asymmetry {
+        paths,
+        family_gaps,
+        spread_ms,
+        min_asymmetry_ms: 2.0 * spread_ms,
+        consistent,
+        low_ms,
+        high_ms,
+        offset_ms: (low_ms + high_ms) / 2.0,
+        uncertainty_ms: (high_ms - low_ms) / 2.0,
+    })
+}
+
+/// Probe every path to `targets` and combine them.
+///
+/// Host names are probed on their first IPv4 and first IPv6 address, or
+/// only the IPv6 one with `ipv6_only`; IP addresses and targets with a
+/// scheme (`nts://`, `mock://`) are one path each. Fails with the last
+/// error when no path answered.
+pub async fn analyse(
+    targets: &[String],
+    options: &AsymmetryOptions,
+) -> Result<Asymmetry, RkikError> {
+    let mut endpoints = Vec::new();
+    let mut last_error = None;
+    for target in targets {
+        match paths_of(target, options).await {
+            Ok(paths) => endpoints.extend(paths.into_iter().map(|p| (target.clone(), p))),
+            Err(e) => last_error = Some(e.with_target(target)),
+        }
+    }
+    let paths = join_all(
+        endpoints
+            .iter()
+            .map(|(target, endpoint)| probe_path(target, endpoint, options)),
+    )
+    .await;
+    if paths.iter().all(|p| p.offset_ms.is_none()) {
+        let error = paths.into_iter().rev().find_map(|p| p.error).or(last_error);
+        return Err(error.unwrap_or_else(|| RkikError::Other("no target given".into())));
+    }
+    estimate(paths).ok_or_else(|| RkikError::Other("no path answered".into()))
+}
+
+/// How a path is queried: the target itself, or one of its addresses.
+enum Endpoint {
+    Direct,
+    Address(IpAddr, u16),
+}
+
+async fn paths_of(target: &str, options: &AsymmetryOptions) -> Result<Vec<Endpoint>, RkikError> {
+    let (scheme, rest) = split_scheme(target)?;
+    if scheme.is_some() {
+        return Ok(vec![Endpoint::Direct]);
+    }
+    let parsed = parse_target(rest)?;
+    let port = parsed.port.unwrap_or(options.query.port);
+    let ips = resolver::lookup_all(parsed.host).await?;
+    let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+    let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+    let chosen: Vec<Endpoint> = [ipv4.filter(|_| !options.query.ipv6_only), ipv6]
+        .into_iter()
+        .flatten()
+        .map(|ip| Endpoint::Address(*ip, port))
+        .collect();
+    if chosen.is_empty() {
+        return Err(RkikError::Dns(format!("no usable address for '{target}'")));
+    }
+    Ok(chosen)
+}
+
+async fn probe_path(target: &str, endpoint: &Endpoint, options: &AsymmetryOptions) -> Path {
+    let (query_target, query_options) = match endpoint {
+        Endpoint::Direct => (target.to_string(), options.query.clone()),
+        Endpoint::Address(ip, port) => (
+            std::net::SocketAddr::new(*ip, *port).to_string(),
+            options.query.clone().nts(false).ipv6_only(ip.is_ipv6()),
+        ),
+    };
+    let mut best: Option<ProbeResult> = None;
+    let mut answered = 0;
+    let mut error = None;
+    for sample in 0..options.samples {
+        if sample > 0 {
+            tokio::time::sleep(options.interval).await;
+        }
+        match query(&query_target, &query_options).await {
+            Ok(probe) => {
+                answered += 1;
+                if best.as_ref().is_none_or(|b| probe.rtt_ms < b.rtt_ms) {
+                    best = Some(probe);
+                }
+            }
+            Err(e) => error = Some(e.with_target(target)),
+        }
+    }
+    let ip = match (endpoint, &best) {
+        (Endpoint::Address(ip, _), _) => *ip,
+        (Endpoint::Direct, Some(b)) => b.target.ip,
+        (Endpoint::Direct, None) => std::net::Ipv4Addr::UNSPECIFIED.into(),
+    };
+    Path {
+        target: target.to_string(),
+        ip,
+        family: AddressFamily::of(ip),
+        answered,
+        offset_ms: best.as_ref().map(|b| b.offset_ms),
+        rtt_ms: best.as_ref().map(|b| b.rtt_ms),
+        error: if best.is_none() { error } else { None },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(target: &str, ip: &str, offset_ms: f64, rtt_ms: f64) -> Path {
+        let ip: IpAddr = ip.parse().unwrap();
+        Path {
+            target: target.into(),
+            ip,
+            family: AddressFamily::of(ip),
+            answered: 1,
+            offset_ms: Some(offset_ms),
+            rtt_ms: Some(rtt_ms),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn overlapping_paths_narrow_the_interval() {
+        let a = estimate(vec![
+            path("time.example", "192.0.2.1", 2.0, 10.0),
+            path("time.example", "2001:db8::1", -1.0, 4.0),
+            path("time2.example", "192.0.2.2", 0.0, 20.0),
+        ])
+        .unwrap();
+        assert!(a.consistent);
+        // [-3, 7] ∩ [-3, 1] ∩ [-10, 10]
+        assert_eq!((a.low_ms, a.high_ms), (-3.0, 1.0));
+        assert_eq!((a.offset_ms, a.uncertainty_ms), (-1.0, 2.0));
+        assert_eq!((a.spread_ms, a.min_asymmetry_ms), (3.0, 6.0));
+        assert_eq!(a.family_gaps.len(), 1);
+        assert_eq!(a.family_gaps[0].gap_ms, -3.0);
+    }
+
+    #[test]
+    fn disjoint_paths_are_inconsistent() {
+        let a = estimate(vec![
+            path("a", "192.0.2.1", 0.0, 2.0),
+            path("b", "192.0.2.2", 50.0, 2.0),
+        ])
+        .unwrap();
+        assert!(!a.consistent);
+        assert_eq!((a.low_ms, a.high_ms), (-1.0, 51.0));
+        assert!(a.family_gaps.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mock_targets_are_single_paths() {
+        let targets = [
+            "mock://a?offset=1ms&rtt=4ms".to_string(),
+            "mock://b?offset=2ms&rtt=2ms".to_string(),
+        ];
+        let options = AsymmetryOptions::new().samples(1).interval(Duration::ZERO);
+        let a = analyse(&targets, &options).await.unwrap();
+        assert_eq!(a.paths.len(), 2);
+        // [-1, 3] ∩ [1, 3]
+        assert_eq!((a.low_ms, a.high_ms), (1.0, 3.0));
+    }
+}
@@ -0,0 +1,268 @@
+//! Sanity check of the local clocks against a server.
+//!
+//! The server is probed twice, a window apart. Its clock advanced by the
+//! difference of the two answers, the local monotonic clock (`Instant`,
+//! `CLOCK_MONOTONIC` on Linux, never stepped) by what it measured in
+//! between: the ratio is the frequency error of the local oscillator. The
+//! bounds of the two offsets limit how well it is known, so a longer window
+//! gives a tighter figure.
+//!
+//! Meanwhile the realtime clock is read every [`POLL`] against the monotonic
+//! one. Both advance together unless something sets the time; whenever the
+//! realtime clock falls behind by more than [`STEP_TOLERANCE_MS`] between two
+//! readings, it was stepped backwards, which breaks timestamps and timeouts
+//! of anything running at that moment. [`StepWatch`] does the same in the
+//! background of longer runs, forward steps included, with the coarser
+//! [`WATCH_TOLERANCE_MS`]: a busy runtime can delay its readings by more
+//! than a millisecond, and a false step would drop a good sample.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::adapters::ntp_packet::millis_between;
+use crate::domain::ntp::ProbeResult;
+use crate::error::RkikError;
+use crate::services::query::{QueryOptions, query};
+
+/// Interval between two readings of the realtime clock.
+pub const POLL: Duration = Duration::from_millis(50);
+/// Realtime moving away from the monotonic clock by more than this between
+/// two readings is a step (ms).
+pub const STEP_TOLERANCE_MS: f64 = 1.0;
+/// Tolerance of [`StepWatch`] (ms), well above scheduling delays.
+pub const WATCH_TOLERANCE_MS: f64 = 100.0;
+
+/// How the clocks are checked.
+#[derive(Debug, Clone)]
+pub struct ClockCheckOptions {
+    /// Time between the two probes (default 10 s).
+    pub window: Duration,
+    pub query: QueryOptions,
+}
+
+impl Default for ClockCheckOptions {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(10),
+            query: QueryOptions::default(),
+        }
+    }
+}
+
+impl ClockCheckOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn query(mut self, query: QueryOptions) -> Self {
+        self.query = query;
+        self
+    }
+}
+
+/// The realtime clock jumping against the monotonic one.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct ClockStep {
+    /// Realtime clock right after the step.
+    pub utc: DateTime<Utc>,
+    /// Size of the step (ms), negative when the clock went back.
+    pub step_ms: f64,
+}
+
+/// What the local clocks did while the server's advanced.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct ClockCheck {
+    /// Target as given.
+    pub target: String,
+    /// Elapsed between the two answers, by the server's clock (ms).
+    pub server_ms: f64,
+    /// Elapsed by the local monotonic clock (ms).
+    pub monotonic_ms: f64,
+    /// Elapsed by the local realtime clock (ms).
+    pub realtime_ms: f64,
+    /// Frequency error of the local oscillator, positive when it runs fast.
+    pub frequency_ppm: f64,
+    /// Bound on `frequency_ppm`, from the uncertainty of both offsets.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub frequency_uncertainty_ppm: Option<f64>,
+    /// `realtime_ms - monotonic_ms`: what steps and slews of the realtime
+    /// clock added up to over the window.
+    pub realtime_adjustment_ms: f64,
+    pub backward_steps: Vec<ClockStep>,
+}
+
+impl ClockCheck {
+    /// From the two probes, the clocks read right after each, and the steps
+    /// seen in between.
+    fn from_probes(
+        target: &str,
+        (first, second): (&ProbeResult, &ProbeResult),
+        monotonic_ms: f64,
+        realtime_ms: f64,
+        backward_steps: Vec<ClockStep>,
+    ) -> Self {
+        let server_ms = millis_between(first.utc, second.utc);
+        let ppm = |ms: f64| ms / server_ms * 1e6;
+        Self {
+            target: target.to_string(),
+            server_ms,
+            monotonic_ms,
+            realtime_ms,
+            frequency_ppm: ppm(monotonic_ms - server_ms),
+            frequency_uncertainty_ppm: first
+                .uncertainty_ms
+                .zip(second.uncertainty_ms)
+                .map(|(a, b)| ppm(a + b)),
+            realtime_adjustment_ms: realtime_ms - monotonic_ms,
+            backward_steps,
+        }
+    }
+}
+
+/// Probe `target` twice, `options.window` apart, watching the realtime
+/// clock in between.
+pub async fn check(target: &str, options: &ClockCheckOptions) -> Result<ClockCheck, RkikError> {
+    let first = query(target, &options.query).await?;
+    let start = (Instant::now(), Utc::now());
+    let mut last = start;
+    let mut steps = Vec::new();
+    while let Some(left) = options.window.checked_sub(start.0.elapsed())
+        && !left.is_zero()
+    {
+        tokio::time::sleep(left.min(POLL)).await;
+        steps.extend(step_since(&mut last, STEP_TOLERANCE_MS));
+    }
+    let second = query(target, &options.query).await?;
+    steps.extend(step_since(&mut last, STEP_TOLERANCE_MS));
+    let (monotonic, realtime) = last;
+    steps.retain(|s| s.step_ms < 0.0);
+    Ok(ClockCheck::from_probes(
+        target,
+        (&first, &second),
+        (monotonic - start.0).as_secs_f64() * 1000.0,
+        millis_between(start.1, realtime),
+        steps,
+    ))
+}
+
+/// The step of the realtime clock since the `last` reading of both clocks,
+/// which becomes the current one, when it exceeds `tolerance_ms`.
+fn step_since(last: &mut (Instant, DateTime<Utc>), tolerance_ms: f64) -> Option<ClockStep> {
+    let now = (Instant::now(), Utc::now());
+    let step_ms = millis_between(last.1, now.1) - (now.0 - last.0).as_secs_f64() * 1000.0;
+    *last = now;
+    (step_ms.abs() > tolerance_ms).then_some(ClockStep {
+        utc: now.1,
+        step_ms,
+    })
+}
+
+/// Watches the realtime clock in the background, every [`POLL`], for as
+/// long as it is alive, reporting steps above [`WATCH_TOLERANCE_MS`].
+///
+/// Repeated runs take the steps at each sample: a sample measured across a
+/// step compares two readings of different clocks, and the samples around
+/// it no longer share a time base.
+pub struct StepWatch {
+    steps: Arc<Mutex<Vec<ClockStep>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl StepWatch {
+    /// Start watching; needs a Tokio runtime.
+    pub fn spawn() -> Self {
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let found = Arc::clone(&steps);
+        let task = tokio::spawn(async move {
+            let mut last = (Instant::now(), Utc::now());
+            loop {
+                tokio::time::sleep(POLL).await;
+                if let Some(step) = step_since(&mut last, WATCH_TOLERANCE_MS) {
+                    found.lock().unwrap_or_else(|e| e.into_inner()).push(step);
+                }
+            }
+        });
+        Self { steps, task }
+    }
+
+    /// Net step of the realtime clock since the last call (ms), `None` when
+    /// it did not move.
+    pub fn take(&self) -> Option<f64> {
+        let steps = std::mem::take(&mut *self.steps.lock().unwrap_or_else(|e| e.into_inner()));
+        (!steps.is_empty()).then(|| steps.iter().map(|s| s.step_ms).sum())
+    }
+}
+
+impl Drop for StepWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::{Leap, Target};
+    use chrono::TimeDelta;
+
+    fn probe(utc: DateTime<Utc>, uncertainty_ms: f64) -> ProbeResult {
+        ProbeResult {
+            target: Target::new("t", "192.0.2.1".parse().unwrap(), 123),
+            offset_ms: 0.0,
+            rtt_ms: 1.0,
+            stratum: 2,
+            ref_id: "GPS".into(),
+            utc,
+            local: utc.into(),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: Some(uncertainty_ms),
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn frequency_compares_monotonic_and_server_time() {
+        let t0 = Utc::now();
+        let first = probe(t0, 0.5);
+        let second = probe(t0 + TimeDelta::seconds(10), 1.5);
+        // 10 s of server time, 10.001 s locally: 100 ppm fast, and a
+        // realtime clock set 20 ms back on the way.
+        let check = ClockCheck::from_probes("t", (&first, &second), 10_001.0, 9_981.0, vec![]);
+        assert!((check.frequency_ppm - 100.0).abs() < 1e-6);
+        assert_eq!(check.frequency_uncertainty_ppm, Some(200.0));
+        assert_eq!(check.realtime_adjustment_ms, -20.0);
+    }
+
+    #[test]
+    fn steps_are_the_realtime_lag_behind_monotonic() {
+        // Last read 5 s ahead of now: the clock has since gone back by 5 s.
+        let mut last = (Instant::now(), Utc::now() + TimeDelta::seconds(5));
+        let step = step_since(&mut last, WATCH_TOLERANCE_MS).unwrap();
+        assert!((step.step_ms + 5000.0).abs() < 50.0, "{step:?}");
+        assert_eq!(step_since(&mut last, WATCH_TOLERANCE_MS), None);
+    }
+}
@@ -1,13 +1,142 @@
-use futures::future::join_all;
+use futures::{StreamExt, stream};
+use std::sync::Mutex;
 use std::time::Duration;
 
-use crate::domain::ntp::ProbeResult;
+use crate::domain::ntp::{Leap, ProbeResult, Resolution, Target};
 use crate::error::RkikError;
 use tracing::instrument;
 
-use super::query::query_one;
+use super::query::{QueryOptions, query_via};
+use crate::adapters::chrony::ChronyClient;
+use crate::adapters::demux::SharedSocket;
+use crate::adapters::transport::{NtpTransport, UdpTransport};
 
-/// Query many targets concurrently and return all successful [`ProbeResult`]s.
+/// Pseudo-target standing for this machine's own clock in [`compare`].
+pub const LOCAL_TARGET: &str = "local";
+
+/// The local clock as a probe: offset and delay 0 by definition, so compare
+/// tables show where the host sits relative to the servers.
+fn local_clock() -> ProbeResult {
+    let utc = chrono::Utc::now();
+    ProbeResult {
+        target: Target {
+            resolution: Resolution::Synthetic,
+            ..Target::new(LOCAL_TARGET, std::net::Ipv4Addr::LOCALHOST.into(), 0)
+        },
+        offset_ms: 0.0,
+        rtt_ms: 0.0,
+        stratum: 0,
+        ref_id: "LOCL".into(),
+        utc,
+        local: utc.into(),
+        timestamp: utc.timestamp(),
+        authenticated: false,
+        dnssec: false,
+        leap: Leap::Normal,
+        health: Default::default(),
+        pause_ms: None,
+        origin: None,
+        timing: None,
+        uncertainty_ms: None,
+        clock_step_ms: None,
+        reference_age_s: None,
+        #[cfg(feature = "nts")]
+        nts_ke_data: None,
+        #[cfg(feature = "nts")]
+        nts_validation: None,
+    }
+}
+
+/// Pseudo-target standing for the local chronyd's view of true time in [`compare`].
+pub const CHRONYD_TARGET: &str = "chronyd";
+
+/// chronyd's tracking report as a probe: its estimate of true time against
+/// the local clock, read over its command socket, so compare tables
+/// contrast what the daemon believes with rkik's own measurements.
+async fn chronyd_view(timeout: Duration) -> Result<ProbeResult, RkikError> {
+    let tracking = ChronyClient::new().timeout(timeout).tracking().await?;
+    let utc = chrono::Utc::now();
+    Ok(ProbeResult {
+        target: Target {
+            resolution: Resolution::Synthetic,
+            ..Target::new(CHRONYD_TARGET, std::net::Ipv4Addr::LOCALHOST.into(), 323)
+        },
+        offset_ms: tracking.offset_ms,
+        rtt_ms: 0.0,
+        stratum: u8::try_from(tracking.stratum).unwrap_or(u8::MAX),
+        ref_id: tracking.reference,
+        utc,
+        local: utc.into(),
+        timestamp: utc.timestamp(),
+        authenticated: false,
+        dnssec: false,
+        leap: tracking.leap,
+        health: Default::default(),
+        pause_ms: None,
+        origin: None,
+        timing: None,
+        uncertainty_ms: None,
+        clock_step_ms: None,
+        reference_age_s: None,
+        #[cfg(feature = "nts")]
+        nts_ke_data: None,
+        #[cfg(feature = "nts")]
+        nts_validation: None,
+    })
+}
+
+/// Per-target results of a [`compare`] round, in input order.
+#[derive(Debug)]
+pub struct CompareOutcome {
+    pub entries: Vec<(String, Result<ProbeResult, RkikError>)>,
+}
+
+impl CompareOutcome {
+    /// Successful probes.
+    pub fn results(&self) -> impl Iterator<Item = &ProbeResult> {
+        self.entries.iter().filter_map(|(_, r)| r.as_ref().ok())
+    }
+
+    /// Targets that failed, with their error.
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &RkikError)> {
+        self.entries
+            .iter()
+            .filter_map(|(t, r)| r.as_ref().err().map(|e| (t.as_str(), e)))
+    }
+
+    /// Split into successful probes and failed targets.
+    pub fn into_parts(self) -> (Vec<ProbeResult>, Vec<(String, RkikError)>) {
+        let mut results = Vec::new();
+        let mut failures = Vec::new();
+        for (target, r) in self.entries {
+            match r {
+                Ok(p) => results.push(p),
+                Err(e) => failures.push((target, e)),
+            }
+        }
+        (results, failures)
+    }
+}
+
+/// Query many targets concurrently.
+///
+/// At most [`QueryOptions::concurrency`] queries run at once, launched in
+/// target order and at least [`QueryOptions::stagger`] apart, so large
+/// lists do not flood the resolver and the network. A failing target does not abort the round: its error is kept in the
+/// returned [`CompareOutcome`]. The call only fails, with the first error,
+/// when fewer than [`QueryOptions::min_answers`] targets (two by default,
+/// or all of them if fewer are given) answered.
+/// [`LOCAL_TARGET`] is answered by the local clock without any network request,
+/// [`CHRONYD_TARGET`] by the local chronyd.
+#[instrument]
+pub async fn compare(
+    targets: &[String],
+    options: &QueryOptions,
+) -> Result<CompareOutcome, RkikError> {
+    compare_with(targets, options, |_| options.timeout).await
+}
+
+/// Query many targets concurrently.
 ///
 /// # Arguments
 ///
@@ -16,26 +145,254 @@ use super::query::query_one;
 /// * `timeout` - Timeout duration
 /// * `use_nts` - Whether to use NTS (Network Time Security) authentication
 /// * `nts_port` - NTS-KE port number
-#[instrument(skip(timeout))]
+#[deprecated(note = "use `compare` with `QueryOptions`")]
 pub async fn compare_many(
     targets: &[String],
     ipv6_only: bool,
     timeout: Duration,
     use_nts: bool,
     nts_port: u16,
-) -> Result<Vec<ProbeResult>, RkikError> {
-    let futures = targets
-        .iter()
-        .map(|target| async move {
-            query_one(target, ipv6_only, timeout, use_nts, nts_port)
-                .await
-                .map_err(|e| e.with_target(target))
+) -> Result<CompareOutcome, RkikError> {
+    let options = QueryOptions {
+        timeout,
+        ipv6_only,
+        nts: use_nts,
+        nts_port,
+        ..QueryOptions::default()
+    };
+    compare(targets, &options).await
+}
+
+/// Like [`compare`], with the timeout chosen per target by `timeout_for`
+/// (e.g. from per-target configuration).
+#[instrument(skip(timeout_for))]
+pub async fn compare_with(
+    targets: &[String],
+    options: &QueryOptions,
+    timeout_for: impl Fn(&str) -> Duration,
+) -> Result<CompareOutcome, RkikError> {
+    if options.shared_socket {
+        round(targets, options, timeout_for, &SharedSocket::new()).await
+    } else {
+        round(targets, options, timeout_for, &UdpTransport).await
+    }
+}
+
+/// Like [`compare`], with plain NTP exchanges going through `transport`.
+#[instrument(skip(transport))]
+pub async fn compare_via(
+    targets: &[String],
+    options: &QueryOptions,
+    transport: &impl NtpTransport,
+) -> Result<CompareOutcome, RkikError> {
+    round(targets, options, |_| options.timeout, transport).await
+}
+
+async fn round(
+    targets: &[String],
+    options: &QueryOptions,
+    timeout_for: impl Fn(&str) -> Duration,
+    transport: &impl NtpTransport,
+) -> Result<CompareOutcome, RkikError> {
+    // Launch slots handed out in target order, `stagger` apart.
+    let next_launch = Mutex::new(tokio::time::Instant::now());
+    let results: Vec<_> = stream::iter(targets)
+        .map(|target| {
+            let options = options.clone().timeout(timeout_for(target));
+            let next_launch = &next_launch;
+            async move {
+                if target == LOCAL_TARGET {
+                    return Ok(local_clock());
+                }
+                if target == CHRONYD_TARGET {
+                    return chronyd_view(options.timeout)
+                        .await
+                        .map_err(|e| e.with_target(target));
+                }
+                if !options.stagger.is_zero() {
+                    let at = {
+                        let mut next = next_launch.lock().unwrap_or_else(|e| e.into_inner());
+                        let at = (*next).max(tokio::time::Instant::now());
+                        *next = at + options.stagger;
+                        at
+                    };
+                    let pause = tokio::time::sleep_until(at);
+                    match &options.cancel {
+                        Some(token) => tokio::select! {
+                            _ = token.cancelled() => {
+                                return Err(RkikError::Cancelled.with_target(target));
+                            }
+                            _ = pause => {}
+                        },
+                        None => pause.await,
+                    }
+                }
+                query_via(target, &options, transport)
+                    .await
+                    .map_err(|e| e.with_target(target))
+            }
         })
-        .collect::<Vec<_>>();
-    let results = join_all(futures).await;
-    let mut out = Vec::new();
-    for res in results {
-        out.push(res?);
+        .buffered(options.concurrency.max(1))
+        .collect()
+        .await;
+    let outcome = CompareOutcome {
+        entries: targets.iter().cloned().zip(results).collect(),
+    };
+    if outcome.results().count() < targets.len().min(options.min_answers) {
+        let (_, failures) = outcome.into_parts();
+        return Err(failures
+            .into_iter()
+            .next()
+            .map(|(_, e)| e)
+            .unwrap_or_else(|| RkikError::Other("no targets to compare".into())));
+    }
+    Ok(outcome)
+}
+
+/// Express every offset relative to `results[reference]` instead of the local clock.
+///
+/// The reference ends up at 0 and the others show how far they are ahead of
+/// (positive) or behind it. Differences between servers are unchanged.
+pub fn rebase_offsets(results: &mut [ProbeResult], reference: usize) {
+    let Some(base) = results.get(reference).map(|r| r.offset_ms) else {
+        return;
+    };
+    for r in results.iter_mut() {
+        r.offset_ms -= base;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::Target;
+
+    fn probe(name: &str, offset_ms: f64) -> ProbeResult {
+        let utc = chrono::Utc::now();
+        ProbeResult {
+            target: Target::new(name, "127.0.0.1".parse().unwrap(), 123),
+            offset_ms,
+            rtt_ms: 1.0,
+            stratum: 1,
+            ref_id: "LOCL".into(),
+            utc,
+            local: chrono::DateTime::from(utc),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn rebase_offsets_zeroes_the_reference() {
+        let mut results = vec![probe("a", 3.0), probe("trusted", 1.0), probe("b", -2.0)];
+        rebase_offsets(&mut results, 1);
+        let offsets: Vec<f64> = results.iter().map(|r| r.offset_ms).collect();
+        assert_eq!(offsets, [2.0, 0.0, -3.0]);
+    }
+
+    #[test]
+    fn outcome_splits_probes_and_failures_in_order() {
+        let outcome = CompareOutcome {
+            entries: vec![
+                ("a".into(), Ok(probe("a", 1.0))),
+                ("down".into(), Err(RkikError::Timeout)),
+                ("b".into(), Ok(probe("b", 2.0))),
+            ],
+        };
+        assert_eq!(outcome.results().count(), 2);
+        assert_eq!(outcome.failures().next().unwrap().0, "down");
+        let (results, failures) = outcome.into_parts();
+        let names: Vec<&str> = results.iter().map(|r| r.target.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(failures.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn silent_server_is_kept_as_a_failure() {
+        use crate::adapters::transport::{MockTransport, NtpSample};
+
+        let sample = NtpSample {
+            offset_ms: -2.0,
+            rtt_ms: 1.0,
+            stratum: 2,
+            ref_id: "192.0.2.9".into(),
+            leap: Leap::Normal,
+            health: Default::default(),
+            utc: chrono::DateTime::UNIX_EPOCH,
+            timing: None,
+            uncertainty_ms: None,
+            reference_age_s: None,
+        };
+        let mock = MockTransport::new().with_sample("192.0.2.1:123".parse().unwrap(), sample);
+        let targets = vec![
+            "192.0.2.1".to_string(),
+            "192.0.2.2".to_string(),
+            LOCAL_TARGET.to_string(),
+        ];
+        let outcome = compare_via(&targets, &QueryOptions::new(), &mock)
+            .await
+            .unwrap();
+        let offsets: Vec<f64> = outcome.results().map(|r| r.offset_ms).collect();
+        assert_eq!(offsets, [-2.0, 0.0]);
+        let failures: Vec<&str> = outcome.failures().map(|(t, _)| t).collect();
+        assert_eq!(failures, ["192.0.2.2"]);
+    }
+
+    #[tokio::test]
+    async fn local_target_needs_no_network() {
+        let targets = vec![LOCAL_TARGET.to_string(), LOCAL_TARGET.to_string()];
+        let options = QueryOptions::new().timeout(Duration::from_millis(10));
+        let outcome = compare(&targets, &options).await.unwrap();
+        assert!(
+            outcome
+                .results()
+                .all(|r| r.offset_ms == 0.0 && r.ref_id == "LOCL")
+        );
+    }
+
+    #[tokio::test]
+    async fn stagger_spaces_launches_in_target_order() {
+        use crate::adapters::transport::{MockTransport, NtpSample};
+
+        let targets: Vec<String> = (1..=3).map(|i| format!("192.0.2.{i}")).collect();
+        let mut mock = MockTransport::new();
+        for target in &targets {
+            let sample = NtpSample {
+                offset_ms: 0.0,
+                rtt_ms: 1.0,
+                stratum: 1,
+                ref_id: "GPS".into(),
+                leap: Leap::Normal,
+                health: Default::default(),
+                utc: chrono::DateTime::UNIX_EPOCH,
+                timing: None,
+                uncertainty_ms: None,
+                reference_age_s: None,
+            };
+            mock = mock.with_sample(format!("{target}:123").parse().unwrap(), sample);
+        }
+        let options = QueryOptions::new()
+            .concurrency(1)
+            .stagger(Duration::from_millis(20));
+        let started = std::time::Instant::now();
+        let outcome = compare_via(&targets, &options, &mock).await.unwrap();
+        assert_eq!(outcome.results().count(), 3);
+        // Two gaps between three launches.
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        let requested: Vec<String> = mock.requests().iter().map(|a| a.ip().to_string()).collect();
+        assert_eq!(requested, targets);
     }
-    Ok(out)
 }
@@ -0,0 +1,834 @@
+//! End-to-end diagnosis of one target, step by step: name resolution, UDP
+//! reachability, validity of the reply, the stratum/refid chain, NTS
+//! support and rate limiting, then a verdict.
+//!
+//! Every step is reported as a [`Check`] so a support team can read where
+//! things go wrong; steps that need a reply are skipped when none came.
+
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use crate::adapters::ntp_client::{self, Exchange};
+use crate::adapters::ntp_packet::short_ms;
+#[cfg(feature = "nts")]
+use crate::adapters::nts_client;
+use crate::adapters::resolver;
+use crate::adapters::traceroute::{self, TraceHop, TraceOptions};
+use crate::domain::ntp::{HealthIssue, HealthVerdict};
+use crate::error::RkikError;
+use crate::services::query::{Scheme, parse_target, split_scheme};
+
+/// Largest root distance (root delay / 2 + root dispersion) RFC 5905
+/// clients accept from a server (MAXDIST, ms).
+const MAX_ROOT_DISTANCE_MS: f64 = 1500.0;
+/// Addresses of one name probed for reachability.
+const MAX_ADDRESSES: usize = 4;
+
+/// How [`diagnose`] probes.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DiagOptions {
+    /// Time allowed for each request (default 2 s).
+    pub timeout: Duration,
+    /// Only consider IPv6 addresses.
+    pub ipv6_only: bool,
+    /// NTP port used when the target does not name one (default 123).
+    pub port: u16,
+    /// NTS-KE port (default 4460).
+    pub nts_port: u16,
+    /// Back-to-back requests of the rate limit test; 0 skips it (default 4).
+    pub burst: u8,
+    /// Upstream servers followed through reference ids (default 3).
+    pub depth: u8,
+    /// Trace the UDP path to the server, up to this many hops; 0 (the
+    /// default) skips it.
+    pub max_hops: u8,
+}
+
+impl Default for DiagOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(2),
+            ipv6_only: false,
+            port: 123,
+            nts_port: 4460,
+            burst: 4,
+            depth: 3,
+            max_hops: 0,
+        }
+    }
+}
+
+impl DiagOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn ipv6_only(mut self, ipv6_only: bool) -> Self {
+        self.ipv6_only = ipv6_only;
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn nts_port(mut self, port: u16) -> Self {
+        self.nts_port = port;
+        self
+    }
+
+    pub fn burst(mut self, burst: u8) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    pub fn depth(mut self, depth: u8) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn max_hops(mut self, max_hops: u8) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+}
+
+/// Outcome of a step, from least to most serious.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(rename_all = "lowercase"))]
+pub enum Status {
+    /// Not run: it needs something an earlier step did not get.
+    Skip,
+    /// Nothing wrong, nothing required either (e.g. no NTS offered).
+    Info,
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Skip => "skip",
+            Status::Info => "info",
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        }
+    }
+}
+
+/// One step of a diagnosis.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Check {
+    /// `dns`, `reachability`, `response`, `chain`, `nts` or `rate_limit`.
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// Time the step took (ms).
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub duration_ms: Option<f64>,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            status,
+            detail: detail.into(),
+            duration_ms: None,
+        }
+    }
+
+    fn timed(mut self, started: Instant) -> Self {
+        self.duration_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+        self
+    }
+}
+
+/// A server on the way to the reference clock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Hop {
+    pub address: IpAddr,
+    pub stratum: u8,
+    pub ref_id: String,
+}
+
+/// Every step of [`diagnose`] and the verdict drawn from them.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Diagnosis {
+    pub target: String,
+    pub addresses: Vec<IpAddr>,
+    pub checks: Vec<Check>,
+    /// The target first, then each upstream server that answered.
+    pub chain: Vec<Hop>,
+    /// The UDP path to the server, when traced.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub path: Vec<TraceHop>,
+    /// The most serious status of the checks.
+    pub verdict: Status,
+}
+
+/// Diagnose `target` (`host`, `host:port`, `ntp://…` or `nts://…`).
+pub async fn diagnose(target: &str, options: &DiagOptions) -> Diagnosis {
+    let mut diagnosis = Diagnosis {
+        target: target.to_string(),
+        addresses: Vec::new(),
+        checks: Vec::new(),
+        chain: Vec::new(),
+        path: Vec::new(),
+        verdict: Status::Pass,
+    };
+    let parsed = split_scheme(target).and_then(|(scheme, rest)| {
+        let parsed = parse_target(rest)?;
+        Ok((scheme, parsed))
+    });
+    let (scheme, parsed) = match parsed {
+        Ok((Some(Scheme::Ptp), _)) => {
+            return finish(
+                diagnosis,
+                vec![Check::new(
+                    "dns",
+                    Status::Fail,
+                    "PTP is not supported by this build",
+                )],
+            );
+        }
+        Ok((Some(Scheme::Mock), _)) => {
+            return finish(
+                diagnosis,
+                vec![Check::new(
+                    "dns",
+                    Status::Skip,
+                    "mock:// targets have no network path to diagnose",
+                )],
+            );
+        }
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return finish(
+                diagnosis,
+                vec![Check::new("dns", Status::Fail, e.message())],
+            );
+        }
+    };
+    let port = parsed.port.unwrap_or(options.port);
+    let mut checks = Vec::new();
+
+    // 1. Name resolution.
+    let started = Instant::now();
+    let (dns, addresses) = resolve(parsed.host, options.ipv6_only).await;
+    checks.push(dns.timed(started));
+    if addresses.is_empty() {
+        return finish(diagnosis, checks);
+    }
+    let scope_id = match parsed.zone.map(resolver::scope_id).transpose() {
+        Ok(scope_id) => scope_id.unwrap_or(0),
+        Err(e) => {
+            checks.push(Check::new("reachability", Status::Fail, e.message()));
+            return finish(diagnosis, checks);
+        }
+    };
+    let socket = |ip: IpAddr| match ip {
+        IpAddr::V6(v6) => SocketAddr::V6(SocketAddrV6::new(v6, port, 0, scope_id)),
+        v4 => SocketAddr::new(v4, port),
+    };
+    diagnosis.addresses = addresses.clone();
+
+    // 2. UDP reachability of each address.
+    let started = Instant::now();
+    let mut answers = Vec::new();
+    for &ip in addresses.iter().take(MAX_ADDRESSES) {
+        let result = ntp_client::exchange(socket(ip), ip.is_ipv6(), options.timeout).await;
+        answers.push((ip, result));
+    }
+    checks.push(reachability(&answers, options.timeout).timed(started));
+    let exchange = answers.into_iter().find_map(|(_, r)| r.ok());
+
+    // The network path, to tell path problems from server problems.
+    if options.max_hops > 0 {
+        let started = Instant::now();
+        let addr = exchange
+            .as_ref()
+            .map_or_else(|| socket(addresses[0]), |ex| ex.server);
+        let trace_options = TraceOptions {
+            max_hops: options.max_hops,
+            timeout: options.timeout.min(Duration::from_secs(1)),
+        };
+        let check = match traceroute::trace(addr, trace_options).await {
+            Ok(hops) => {
+                let check = path(&hops, exchange.as_ref().map(Exchange::rtt_ms));
+                diagnosis.path = hops;
+                check
+            }
+            Err(e) => Check::new("path", Status::Skip, e.message()),
+        };
+        checks.push(check.timed(started));
+    }
+
+    let Some(exchange) = exchange else {
+        for name in ["response", "chain"] {
+            checks.push(Check::new(name, Status::Skip, "no reply to check"));
+        }
+        checks.push(nts(parsed.host, scheme, options).await);
+        checks.push(Check::new("rate_limit", Status::Skip, "no reply to check"));
+        return finish(diagnosis, checks);
+    };
+
+    // 3. What the reply says.
+    checks.push(response(&exchange));
+
+    // 4. Stratum and reference id chain.
+    let started = Instant::now();
+    let (chain, hops) = follow_chain(&exchange, options).await;
+    checks.push(chain.timed(started));
+    diagnosis.chain = hops;
+
+    // 5. NTS-KE.
+    checks.push(nts(parsed.host, scheme, options).await);
+
+    // 6. Behaviour under a burst.
+    let started = Instant::now();
+    checks.push(rate_limit(exchange.server, options).await.timed(started));
+
+    finish(diagnosis, checks)
+}
+
+fn finish(mut diagnosis: Diagnosis, checks: Vec<Check>) -> Diagnosis {
+    diagnosis.verdict = checks
+        .iter()
+        .map(|c| c.status)
+        .max()
+        .unwrap_or(Status::Pass)
+        .max(Status::Pass);
+    diagnosis.checks = checks;
+    diagnosis
+}
+
+async fn resolve(host: &str, ipv6_only: bool) -> (Check, Vec<IpAddr>) {
+    if host.parse::<IpAddr>().is_ok() {
+        let ip = host.parse().ok().into_iter().collect();
+        return (
+            Check::new("dns", Status::Pass, "address literal, no lookup"),
+            ip,
+        );
+    }
+    let started = Instant::now();
+    let addresses = match resolver::lookup_all(host).await {
+        Ok(addresses) => addresses,
+        Err(e) => return (Check::new("dns", Status::Fail, e.message()), Vec::new()),
+    };
+    let elapsed = started.elapsed();
+    let addresses: Vec<IpAddr> = addresses
+        .into_iter()
+        .filter(|ip| !ipv6_only || ip.is_ipv6())
+        .collect();
+    if addresses.is_empty() {
+        let what = if ipv6_only { "IPv6 address" } else { "address" };
+        return (
+            Check::new("dns", Status::Fail, format!("'{host}' has no {what}")),
+            addresses,
+        );
+    }
+    let (v4, v6) = addresses.iter().fold((0, 0), |(v4, v6), ip| {
+        if ip.is_ipv6() {
+            (v4, v6 + 1)
+        } else {
+            (v4 + 1, v6)
+        }
+    });
+    let list: Vec<String> = addresses.iter().map(IpAddr::to_string).collect();
+    let status = if elapsed > Duration::from_secs(1) {
+        Status::Warn
+    } else {
+        Status::Pass
+    };
+    let slow = if status == Status::Warn {
+        " (slow resolver)"
+    } else {
+        ""
+    };
+    (
+        Check::new(
+            "dns",
+            status,
+            format!("{v4} A, {v6} AAAA{slow}: {}", list.join(", ")),
+        ),
+        addresses,
+    )
+}
+
+fn reachability(answers: &[(IpAddr, Result<Exchange, RkikError>)], timeout: Duration) -> Check {
+    let hint = |e: &RkikError| match e {
+        RkikError::Timeout => format!(
+            "no answer within {:.1} s (UDP/123 filtered, or server down)",
+            timeout.as_secs_f64()
+        ),
+        RkikError::Refused(_) => "ICMP port unreachable: no NTP service listening".into(),
+        RkikError::RateLimited(_) | RkikError::KissOfDeath(_) => {
+            format!("answered with a Kiss-o'-Death ({})", e.message())
+        }
+        _ => e.message(),
+    };
+    let ok: Vec<&Exchange> = answers
+        .iter()
+        .filter_map(|(_, r)| r.as_ref().ok())
+        .collect();
+    let failed: Vec<String> = answers
+        .iter()
+        .filter_map(|(ip, r)| r.as_ref().err().map(|e| format!("{ip}: {}", hint(e))))
+        .collect();
+    let check = match (ok.first(), failed.is_empty()) {
+        (Some(first), true) => Check::new(
+            "reachability",
+            Status::Pass,
+            format!(
+                "{} of {} addresses answered, RTT {:.3} ms",
+                ok.len(),
+                answers.len(),
+                first.rtt_ms()
+            ),
+        ),
+        (Some(_), false) => Check::new(
+            "reachability",
+            Status::Warn,
+            format!(
+                "{} of {} addresses answered; {}",
+                ok.len(),
+                answers.len(),
+                failed.join("; ")
+            ),
+        ),
+        (None, _) => Check::new("reachability", Status::Fail, failed.join("; ")),
+    };
+    let dropped: u32 = ok.iter().map(|ex| ex.rejected).sum();
+    if dropped == 0 {
+        return check;
+    }
+    Check {
+        status: check.status.max(Status::Warn),
+        detail: format!(
+            "{}; dropped {dropped} spoofed-looking replies (origin timestamp or source mismatch)",
+            check.detail
+        ),
+        ..check
+    }
+}
+
+/// Where a trace ended, against the NTP round trip when there is one.
+fn path(hops: &[TraceHop], ntp_rtt_ms: Option<f64>) -> Check {
+    let last = hops.iter().rev().find(|h| h.address.is_some());
+    let Some(end) = hops.last().filter(|h| h.reached) else {
+        let last = match last {
+            Some(h) => format!(
+                "last answer from {} at hop {}",
+                h.address.map(|a| a.to_string()).unwrap_or_default(),
+                h.ttl
+            ),
+            None => "no router answered".into(),
+        };
+        return match ntp_rtt_ms {
+            // The server answers: the routers past that point only drop ICMP.
+            Some(_) => Check::new(
+                "path",
+                Status::Info,
+                format!("{last}; routers further on do not send ICMP"),
+            ),
+            None => Check::new(
+                "path",
+                Status::Warn,
+                format!("the path is lost after {} hops ({last})", hops.len()),
+            ),
+        };
+    };
+    let router = hops
+        .iter()
+        .rev()
+        .skip(1)
+        .find_map(|h| h.address.zip(h.rtt_ms));
+    let plural = if end.ttl == 1 { "" } else { "s" };
+    let mut detail = format!("{} hop{plural}", end.ttl);
+    if let Some((ip, ms)) = router {
+        detail.push_str(&format!("; last router {ip} at {ms:.3} ms"));
+    }
+    if let Some(rtt) = ntp_rtt_ms {
+        detail.push_str(&format!("; NTP round trip {rtt:.3} ms"));
+        if let Some((_, ms)) = router
+            && rtt > 2.0 * ms + 50.0
+        {
+            detail.push_str(" (time lost at the server, not on the path)");
+        }
+    }
+    Check::new("path", Status::Pass, detail)
+}
+
+/// Check the header of a reply against what RFC 5905 clients accept.
+fn response(ex: &Exchange) -> Check {
+    let reply = &ex.reply;
+    let root_distance = short_ms(reply.root_delay) / 2.0 + short_ms(reply.root_dispersion);
+    let mut fails = Vec::new();
+    let mut warns = Vec::new();
+    if reply.leap == 3 || reply.stratum >= 16 {
+        fails.push("the server says it is not synchronised".to_string());
+    }
+    if root_distance > MAX_ROOT_DISTANCE_MS {
+        fails.push(format!(
+            "root distance {root_distance:.1} ms exceeds {MAX_ROOT_DISTANCE_MS:.0} ms"
+        ));
+    }
+    if reply.receive.to_datetime() > reply.transmit.to_datetime() {
+        fails.push("receive timestamp is after the transmit timestamp".into());
+    }
+    if reply.version < 4 {
+        warns.push(format!("NTPv{} reply", reply.version));
+    }
+    for issue in ex.health().issues {
+        match issue.verdict() {
+            HealthVerdict::Bogus => fails.push(issue.to_string()),
+            // Already part of the root distance.
+            _ if matches!(issue, HealthIssue::HighDispersion { .. })
+                && root_distance > MAX_ROOT_DISTANCE_MS => {}
+            _ => warns.push(issue.to_string()),
+        }
+    }
+    if ex.offset_ms().abs() > 1000.0 {
+        warns.push(format!(
+            "{:+.1} ms from the local clock (check this host's clock too)",
+            ex.offset_ms()
+        ));
+    }
+    let summary = format!(
+        "NTPv{}, stratum {}, root distance {:.3} ms, offset {:+.3} ms",
+        reply.version,
+        reply.stratum,
+        root_distance,
+        ex.offset_ms()
+    );
+    let (status, problems) = if !fails.is_empty() {
+        fails.extend(warns);
+        (Status::Fail, fails)
+    } else if !warns.is_empty() {
+        (Status::Warn, warns)
+    } else {
+        (Status::Pass, Vec::new())
+    };
+    let detail = if problems.is_empty() {
+        summary
+    } else {
+        format!("{}; {summary}", problems.join("; "))
+    };
+    Check::new("response", status, detail)
+}
+
+/// Follow IPv4 reference ids upstream, as far as the servers answer.
+async fn follow_chain(first: &Exchange, options: &DiagOptions) -> (Check, Vec<Hop>) {
+    let mut hops = vec![Hop {
+        address: first.server.ip(),
+        stratum: first.reply.stratum,
+        ref_id: first.ref_id(),
+    }];
+    let mut note = None;
+    while let Some(last) = hops.last().cloned() {
+        if last.stratum <= 1 || hops.len() > options.depth as usize {
+            break;
+        }
+        if last.address.is_ipv6() {
+            note = Some("upstream of an IPv6 server is a hash, not followed".to_string());
+            break;
+        }
+        let Ok(upstream) = last.ref_id.parse::<IpAddr>() else {
+            break;
+        };
+        if hops.iter().any(|h| h.address == upstream) {
+            return (
+                Check::new(
+                    "chain",
+                    Status::Warn,
+                    format!("{} (reference loop)", render_chain(&hops)),
+                ),
+                hops,
+            );
+        }
+        match ntp_client::exchange(SocketAddr::new(upstream, 123), false, options.timeout).await {
+            Ok(ex) => hops.push(Hop {
+                address: upstream,
+                stratum: ex.reply.stratum,
+                ref_id: ex.ref_id(),
+            }),
+            Err(_) => {
+                note = Some(format!("upstream {upstream} does not answer from here"));
+                break;
+            }
+        }
+    }
+    let Some(last) = hops.last() else {
+        return (Check::new("chain", Status::Skip, "no reply"), hops);
+    };
+    let mut status = Status::Pass;
+    if last.stratum == 1 && last.ref_id.eq_ignore_ascii_case("LOCL") {
+        status = Status::Warn;
+        note = Some("stratum 1 on an undisciplined local clock".into());
+    } else if last.ref_id.starts_with("127.") {
+        status = Status::Warn;
+        note = Some("synchronised to a local clock".into());
+    }
+    let detail = match note {
+        Some(note) => format!("{}; {note}", render_chain(&hops)),
+        None => render_chain(&hops),
+    };
+    (Check::new("chain", status, detail), hops)
+}
+
+fn render_chain(hops: &[Hop]) -> String {
+    let mut parts: Vec<String> = hops
+        .iter()
+        .map(|h| format!("{} (stratum {})", h.address, h.stratum))
+        .collect();
+    if let Some(last) = hops.last()
+        && last.stratum == 1
+    {
+        parts.push(last.ref_id.clone());
+    }
+    parts.join(" -> ")
+}
+
+#[cfg(feature = "nts")]
+async fn nts(host: &str, scheme: Option<Scheme>, options: &DiagOptions) -> Check {
+    let started = Instant::now();
+    let required = scheme == Some(Scheme::Nts);
+    let check = match nts_client::query_nts(host, Some(options.nts_port), options.timeout).await {
+        Ok(result) => {
+            let detail = match &result.nts_ke_data {
+                Some(ke) => format!(
+                    "NTS-KE on port {}: {}, {} cookies, authenticated time",
+                    options.nts_port, ke.aead_algorithm, ke.cookie_count
+                ),
+                None => "authenticated time".into(),
+            };
+            Check::new("nts", Status::Pass, detail)
+        }
+        Err(e) => {
+            let message = e.message();
+            let unreachable = e.is_network_timeout()
+                || message.ends_with("[network]")
+                || message.ends_with("[timeout]");
+            let status = match (required, unreachable) {
+                (true, _) => Status::Fail,
+                (false, true) => Status::Info,
+                (false, false) => Status::Warn,
+            };
+            let detail = if unreachable && !required {
+                format!("no NTS-KE on port {}", options.nts_port)
+            } else {
+                message
+            };
+            Check::new("nts", status, detail)
+        }
+    };
+    check.timed(started)
+}
+
+#[cfg(not(feature = "nts"))]
+async fn nts(_host: &str, scheme: Option<Scheme>, _options: &DiagOptions) -> Check {
+    let status = if scheme == Some(Scheme::Nts) {
+        Status::Fail
+    } else {
+        Status::Skip
+    };
+    Check::new("nts", status, "NTS support not enabled in this build")
+}
+
+/// Send a burst of back-to-back requests and see how the server copes.
+async fn rate_limit(addr: SocketAddr, options: &DiagOptions) -> Check {
+    if options.burst == 0 {
+        return Check::new("rate_limit", Status::Skip, "disabled");
+    }
+    let mut answered = 0;
+    for n in 1..=options.burst {
+        match ntp_client::exchange(addr, addr.is_ipv6(), options.timeout).await {
+            Ok(_) => answered += 1,
+            Err(e @ (RkikError::RateLimited(_) | RkikError::KissOfDeath(_))) => {
+                return Check::new(
+                    "rate_limit",
+                    Status::Warn,
+                    format!(
+                        "Kiss-o'-Death {} after {n} back-to-back queries: poll no faster than the server allows",
+                        e.message()
+                    ),
+                );
+            }
+            Err(_) => {}
+        }
+    }
+    if answered == options.burst {
+        Check::new(
+            "rate_limit",
+            Status::Pass,
+            format!("answered all {answered} back-to-back queries"),
+        )
+    } else {
+        Check::new(
+            "rate_limit",
+            Status::Warn,
+            format!(
+                "answered {answered} of {} back-to-back queries: the rest were dropped silently",
+                options.burst
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::ntp_packet::{NtpPacket, NtpTimestamp};
+    use chrono::{TimeDelta, Utc};
+
+    fn exchange(reply: impl FnOnce(&mut NtpPacket)) -> Exchange {
+        let sent = Utc::now();
+        let request = NtpPacket::client(sent);
+        let mut packet = NtpPacket::client(sent);
+        packet.mode = 4;
+        packet.stratum = 2;
+        packet.ref_id = [192, 0, 2, 9];
+        packet.origin = request.transmit;
+        packet.reference = NtpTimestamp::from_datetime(sent - TimeDelta::seconds(30));
+        packet.receive = NtpTimestamp::from_datetime(sent + TimeDelta::milliseconds(1));
+        packet.transmit = NtpTimestamp::from_datetime(sent + TimeDelta::milliseconds(1));
+        packet.root_delay = 655; // 10 ms
+        reply(&mut packet);
+        Exchange {
+            local: "127.0.0.1:50000".parse().unwrap(),
+            server: "127.0.0.1:123".parse().unwrap(),
+            request,
+            reply: packet,
+            sent,
+            received: sent + TimeDelta::milliseconds(2),
+            rejected: 0,
+            setup_ms: 0.0,
+        }
+    }
+
+    #[test]
+    fn healthy_reply_passes() {
+        let check = response(&exchange(|_| {}));
+        assert_eq!(check.status, Status::Pass, "{}", check.detail);
+        assert!(check.detail.starts_with("NTPv4, stratum 2"));
+    }
+
+    #[test]
+    fn reply_problems_are_named() {
+        let unsynced = response(&exchange(|p| p.leap = 3));
+        assert_eq!(unsynced.status, Status::Fail);
+        assert!(unsynced.detail.contains("not synchronised"));
+
+        let far = response(&exchange(|p| p.root_dispersion = 2 << 16));
+        assert_eq!(far.status, Status::Fail);
+        assert!(far.detail.contains("root distance"));
+
+        let old = response(&exchange(|p| {
+            p.version = 3;
+            p.reference = NtpTimestamp::from_datetime(Utc::now() - TimeDelta::days(3));
+        }));
+        assert_eq!(old.status, Status::Warn);
+        assert!(old.detail.contains("NTPv3 reply"));
+        assert!(old.detail.contains("72 h ago"));
+    }
+
+    #[test]
+    fn reachability_summarises_each_address() {
+        let ok = exchange(|_| {});
+        let v4: IpAddr = "192.0.2.1".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        let partial = reachability(
+            &[(v4, Ok(ok)), (v6, Err(RkikError::Timeout))],
+            Duration::from_secs(2),
+        );
+        assert_eq!(partial.status, Status::Warn);
+        assert!(
+            partial
+                .detail
+                .contains("2001:db8::1: no answer within 2.0 s")
+        );
+
+        let refused = reachability(
+            &[(v4, Err(RkikError::Refused("refused".into())))],
+            Duration::from_secs(2),
+        );
+        assert_eq!(refused.status, Status::Fail);
+        assert!(refused.detail.contains("ICMP port unreachable"));
+    }
+
+    #[test]
+    fn path_end_is_explained() {
+        let hop = |ttl, address: Option<&str>, rtt_ms, reached| TraceHop {
+            ttl,
+            address: address.map(|a| a.parse().unwrap()),
+            rtt_ms,
+            reached,
+        };
+        let reached = [
+            hop(1, Some("192.0.2.1"), Some(1.0), false),
+            hop(2, None, None, false),
+            hop(3, Some("198.51.100.7"), Some(8.0), false),
+            hop(4, Some("203.0.113.5"), Some(9.0), true),
+        ];
+        let check = path(&reached, Some(120.0));
+        assert_eq!(check.status, Status::Pass);
+        assert_eq!(
+            check.detail,
+            "4 hops; last router 198.51.100.7 at 8.000 ms; NTP round trip 120.000 ms \
+             (time lost at the server, not on the path)"
+        );
+
+        let lost = &reached[..3];
+        assert_eq!(path(lost, Some(9.0)).status, Status::Info);
+        let check = path(lost, None);
+        assert_eq!(check.status, Status::Warn);
+        assert!(check.detail.contains("198.51.100.7 at hop 3"));
+    }
+
+    #[tokio::test]
+    async fn silent_target_fails_and_skips_the_rest() {
+        // Nothing listens on the loopback's discard port.
+        let options = DiagOptions::new()
+            .timeout(Duration::from_millis(200))
+            .burst(0);
+        let diagnosis = diagnose("127.0.0.1:9", &options).await;
+        assert_eq!(diagnosis.verdict, Status::Fail);
+        let names: Vec<&str> = diagnosis.checks.iter().map(|c| c.name).collect();
+        assert_eq!(
+            names,
+            [
+                "dns",
+                "reachability",
+                "response",
+                "chain",
+                "nts",
+                "rate_limit"
+            ]
+        );
+        assert_eq!(diagnosis.checks[1].status, Status::Fail);
+        assert_eq!(diagnosis.checks[2].status, Status::Skip);
+    }
+
+    #[tokio::test]
+    async fn ptp_targets_are_rejected() {
+        let diagnosis = diagnose("ptp://192.0.2.1", &DiagOptions::new()).await;
+        assert_eq!(diagnosis.verdict, Status::Fail);
+        assert_eq!(diagnosis.checks.len(), 1);
+    }
+}
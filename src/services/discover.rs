@@ -0,0 +1,159 @@
+//! DNS discovery of the time services a domain advertises, or that
+//! announce themselves on the local link via mDNS.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use std::time::Duration;
+
+use crate::adapters::mdns;
+use crate::adapters::resolver::{self, SrvRecord};
+use crate::error::RkikError;
+use crate::services::query::Scheme;
+
+/// SRV label of plain NTP servers.
+pub const NTP_SRV: &str = "_ntp._udp";
+/// SRV label of NTS-KE servers (NTS discovery draft); the port is the NTS-KE port.
+pub const NTS_SRV: &str = "_ntske._tcp";
+/// DNS-SD service types browsed by [`discover_local`].
+pub const LOCAL_SERVICES: [&str; 2] = ["_ntp._udp.local", "_ptp._udp.local"];
+
+/// A time server advertised in the DNS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Advertised {
+    /// Host name, or its address when announced over mDNS.
+    pub host: String,
+    /// Service port (the NTS-KE port for NTS).
+    pub port: u16,
+    pub protocol: Scheme,
+    pub priority: u16,
+    pub weight: u16,
+    /// DNS-SD instance name (mDNS only).
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub instance: Option<String>,
+}
+
+impl Advertised {
+    /// Target string selecting the advertised protocol, for [`query_one`](super::query::query_one).
+    pub fn target(&self) -> String {
+        let scheme = self.protocol.as_str();
+        if self.host.contains(':') {
+            format!("{scheme}://[{}]:{}", self.host, self.port)
+        } else {
+            format!("{scheme}://{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Look up the `_ntske._tcp` and `_ntp._udp` SRV records of `domain`.
+///
+/// NTS servers come first; within each protocol, records are ordered by
+/// priority, then by decreasing weight. Fails if neither lookup succeeds or
+/// the domain advertises no server at all.
+pub async fn discover(domain: &str) -> Result<Vec<Advertised>, RkikError> {
+    let domain = domain.trim_end_matches('.');
+    let (nts_name, ntp_name) = (
+        format!("{NTS_SRV}.{domain}."),
+        format!("{NTP_SRV}.{domain}."),
+    );
+    let (nts, ntp) = futures::join!(
+        resolver::lookup_srv(&nts_name),
+        resolver::lookup_srv(&ntp_name)
+    );
+    let (nts, ntp) = match (nts, ntp) {
+        (Err(e), Err(_)) => return Err(e.with_target(domain)),
+        (nts, ntp) => (nts.unwrap_or_default(), ntp.unwrap_or_default()),
+    };
+    let servers: Vec<Advertised> = ordered(nts, Scheme::Nts)
+        .chain(ordered(ntp, Scheme::Ntp))
+        .collect();
+    if servers.is_empty() {
+        return Err(RkikError::Dns(format!(
+            "no {NTS_SRV} or {NTP_SRV} SRV records for '{domain}'"
+        )));
+    }
+    Ok(servers)
+}
+
+/// Browse the local link for NTP and PTP time sources for `window`.
+///
+/// Hosts are reported by their first announced address (IPv4 first), so
+/// they can be probed without an mDNS-capable system resolver.
+pub async fn discover_local(window: Duration) -> Result<Vec<Advertised>, RkikError> {
+    let mut instances = mdns::browse(&LOCAL_SERVICES, window).await?;
+    instances.sort_by(|a, b| {
+        (&a.service, a.priority, b.weight, &a.name)
+            .cmp(&(&b.service, b.priority, a.weight, &b.name))
+    });
+    Ok(instances
+        .into_iter()
+        .map(|i| {
+            let protocol = if i.service.starts_with("_ptp.") {
+                Scheme::Ptp
+            } else {
+                Scheme::Ntp
+            };
+            let addr = i
+                .addrs
+                .iter()
+                .find(|a| a.is_ipv4())
+                .or(i.addrs.first())
+                .map(|a| a.to_string());
+            Advertised {
+                host: addr.unwrap_or(i.host),
+                port: i.port,
+                protocol,
+                priority: i.priority,
+                weight: i.weight,
+                instance: Some(i.name),
+            }
+        })
+        .collect())
+}
+
+fn ordered(mut records: Vec<SrvRecord>, protocol: Scheme) -> impl Iterator<Item = Advertised> {
+    records.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+    records.into_iter().map(move |r| Advertised {
+        host: r.host,
+        port: r.port,
+        protocol,
+        priority: r.priority,
+        weight: r.weight,
+        instance: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srv(host: &str, priority: u16, weight: u16) -> SrvRecord {
+        SrvRecord {
+            host: host.into(),
+            port: 123,
+            priority,
+            weight,
+        }
+    }
+
+    #[test]
+    fn orders_by_priority_then_weight() {
+        let records = vec![srv("c", 20, 5), srv("a", 10, 1), srv("b", 10, 50)];
+        let hosts: Vec<String> = ordered(records, Scheme::Ntp).map(|a| a.host).collect();
+        assert_eq!(hosts, ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn target_selects_the_advertised_protocol() {
+        let mut server = ordered(vec![srv("time.example", 0, 0)], Scheme::Nts)
+            .next()
+            .unwrap();
+        server.port = 4460;
+        assert_eq!(server.target(), "nts://time.example:4460");
+        server.protocol = Scheme::Ntp;
+        server.host = "2001:db8::1".into();
+        server.port = 123;
+        assert_eq!(server.target(), "ntp://[2001:db8::1]:123");
+    }
+}
@@ -0,0 +1,211 @@
+//! Divergence alerts for repeated compare rounds.
+//!
+//! Each round, every server's offset is measured against the group: the
+//! median offset of all servers in the same round, so with three servers
+//! or more one bad server cannot drag the reference along. A server whose
+//! deviation exceeds the threshold raises a [`DivergenceKind::Diverged`]
+//! event, and a [`DivergenceKind::Recovered`] one once it is back within
+//! [`RECOVERY_RATIO`] of the threshold; a server hovering around the
+//! threshold does not flap. Between events each server keeps a baseline,
+//! the moving average of its deviation, so an alert tells a sudden jump
+//! from a server that always sat apart.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::domain::ntp::ProbeResult;
+use crate::services::compare::{CHRONYD_TARGET, LOCAL_TARGET};
+
+/// Share of the threshold a diverged server must come back within.
+pub const RECOVERY_RATIO: f64 = 0.8;
+/// Weight of each new deviation in the baseline (RFC 5905's 1/8 averaging).
+const BASELINE_WEIGHT: f64 = 0.125;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "lowercase"))]
+pub enum DivergenceKind {
+    Diverged,
+    Recovered,
+}
+
+impl DivergenceKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DivergenceKind::Diverged => "diverged",
+            DivergenceKind::Recovered => "recovered",
+        }
+    }
+}
+
+/// A server crossing the divergence threshold, one way or the other.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct DivergenceEvent {
+    pub event: DivergenceKind,
+    pub server: String,
+    pub offset_ms: f64,
+    /// Median offset of all servers in the same round.
+    pub group_offset_ms: f64,
+    /// `offset_ms - group_offset_ms`.
+    pub deviation_ms: f64,
+    /// The server's usual deviation before this round; `None` for its
+    /// first round.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub baseline_ms: Option<f64>,
+    pub threshold_ms: f64,
+    pub utc: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Baseline {
+    deviation_ms: f64,
+    diverged: bool,
+}
+
+/// Per-server baselines across the rounds of a compare run.
+#[derive(Debug, Clone)]
+pub struct DivergenceTracker {
+    threshold_ms: f64,
+    baselines: HashMap<String, Baseline>,
+}
+
+impl DivergenceTracker {
+    /// Alert when a server is more than `threshold_ms` from the group.
+    pub fn new(threshold_ms: f64) -> Self {
+        Self {
+            threshold_ms: threshold_ms.abs(),
+            baselines: HashMap::new(),
+        }
+    }
+
+    /// Feed one round of results and return the events it caused.
+    ///
+    /// The local and chronyd pseudo-targets are ignored, and a round with
+    /// fewer than two servers has no group to diverge from.
+    pub fn observe(&mut self, results: &[ProbeResult]) -> Vec<DivergenceEvent> {
+        let servers: Vec<&ProbeResult> = results
+            .iter()
+            .filter(|r| r.target.name != LOCAL_TARGET && r.target.name != CHRONYD_TARGET)
+            .collect();
+        if servers.len() < 2 {
+            return Vec::new();
+        }
+        let group_offset_ms = median(servers.iter().map(|r| r.offset_ms).collect());
+        let mut events = Vec::new();
+        for r in servers {
+            let deviation_ms = r.offset_ms - group_offset_ms;
+            let previous = self.baselines.get(&r.target.name).copied();
+            let was_diverged = previous.is_some_and(|b| b.diverged);
+            let diverged = if was_diverged {
+                deviation_ms.abs() > self.threshold_ms * RECOVERY_RATIO
+            } else {
+                deviation_ms.abs() > self.threshold_ms
+            };
+            if diverged != was_diverged {
+                events.push(DivergenceEvent {
+                    event: if diverged {
+                        DivergenceKind::Diverged
+                    } else {
+                        DivergenceKind::Recovered
+                    },
+                    server: r.target.name.clone(),
+                    offset_ms: r.offset_ms,
+                    group_offset_ms,
+                    deviation_ms,
+                    baseline_ms: previous.map(|b| b.deviation_ms),
+                    threshold_ms: self.threshold_ms,
+                    utc: r.utc,
+                });
+            }
+            let baseline = previous.map_or(deviation_ms, |b| {
+                b.deviation_ms + BASELINE_WEIGHT * (deviation_ms - b.deviation_ms)
+            });
+            self.baselines.insert(
+                r.target.name.clone(),
+                Baseline {
+                    deviation_ms: baseline,
+                    diverged,
+                },
+            );
+        }
+        events
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    match n {
+        0 => 0.0,
+        _ if n.is_multiple_of(2) => (values[n / 2 - 1] + values[n / 2]) / 2.0,
+        _ => values[n / 2],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::{Leap, Target};
+
+    fn probe(name: &str, offset_ms: f64) -> ProbeResult {
+        let utc = Utc::now();
+        ProbeResult {
+            target: Target::new(name, "192.0.2.1".parse().unwrap(), 123),
+            offset_ms,
+            rtt_ms: 1.0,
+            stratum: 2,
+            ref_id: "GPS".into(),
+            utc,
+            local: utc.into(),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn events_fire_on_crossings_only() {
+        let mut tracker = DivergenceTracker::new(10.0);
+        let round = |c: f64| vec![probe("a", 0.0), probe("b", 1.0), probe("c", c)];
+        assert!(tracker.observe(&round(0.5)).is_empty());
+        let events = tracker.observe(&round(20.5));
+        assert_eq!(events.len(), 1);
+        let e = &events[0];
+        assert_eq!(
+            (e.event, e.server.as_str()),
+            (DivergenceKind::Diverged, "c")
+        );
+        assert_eq!((e.group_offset_ms, e.deviation_ms), (1.0, 19.5));
+        assert_eq!(e.baseline_ms, Some(0.0));
+        // Still apart: no new event. Within the threshold but not within
+        // the recovery ratio: none either.
+        assert!(tracker.observe(&round(25.0)).is_empty());
+        assert!(tracker.observe(&round(9.5)).is_empty());
+        let events = tracker.observe(&round(2.0));
+        assert_eq!(events[0].event, DivergenceKind::Recovered);
+    }
+
+    #[test]
+    fn a_lone_server_has_no_group() {
+        let mut tracker = DivergenceTracker::new(1.0);
+        let results = [probe("a", 100.0), probe(LOCAL_TARGET, 0.0)];
+        assert!(tracker.observe(&results).is_empty());
+    }
+}
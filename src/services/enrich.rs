@@ -0,0 +1,77 @@
+//! Identify who answered a probe: reverse DNS, and AS / country from
+//! optional MaxMind databases (see [`set_geoip_databases`]).
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use futures::future::join_all;
+
+use crate::adapters::geoip::GeoIpDb;
+use crate::adapters::resolver;
+use crate::domain::ntp::{Origin, ProbeResult};
+use crate::error::RkikError;
+
+static DATABASES: OnceLock<Vec<GeoIpDb>> = OnceLock::new();
+static CACHE: OnceLock<Mutex<HashMap<IpAddr, Origin>>> = OnceLock::new();
+
+/// Open the MaxMind databases consulted by [`enrich`] (ASN, country or city
+/// editions). Only the first call has an effect.
+pub fn set_geoip_databases(paths: &[PathBuf]) -> Result<(), RkikError> {
+    let dbs = paths
+        .iter()
+        .map(|p| GeoIpDb::open(p))
+        .collect::<Result<Vec<_>, _>>()?;
+    let _ = DATABASES.set(dbs);
+    Ok(())
+}
+
+/// Fill in [`ProbeResult::origin`] for each result.
+///
+/// Reverse lookups run concurrently; a failed lookup leaves its part empty
+/// rather than failing the probe. Origins are cached per address for the
+/// life of the process, so repeated rounds cost nothing.
+pub async fn enrich(results: &mut [ProbeResult]) {
+    let cache = CACHE.get_or_init(Default::default);
+    let mut missing: Vec<IpAddr> = Vec::new();
+    {
+        let cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        for r in results.iter() {
+            if !cache.contains_key(&r.target.ip) && !missing.contains(&r.target.ip) {
+                missing.push(r.target.ip);
+            }
+        }
+    }
+    let found = join_all(
+        missing
+            .into_iter()
+            .map(|ip| async move { (ip, origin(ip).await) }),
+    )
+    .await;
+
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    cache.extend(found);
+    for r in results.iter_mut() {
+        r.origin = cache
+            .get(&r.target.ip)
+            .filter(|o| **o != Origin::default())
+            .cloned();
+    }
+}
+
+async fn origin(ip: IpAddr) -> Origin {
+    let mut origin = Origin {
+        reverse_dns: resolver::reverse_lookup(ip).await.ok().flatten(),
+        ..Origin::default()
+    };
+    for db in DATABASES.get().into_iter().flatten() {
+        let Ok(record) = db.lookup(ip) else {
+            continue;
+        };
+        origin.asn = origin.asn.or(record.asn);
+        origin.as_org = origin.as_org.or(record.as_org);
+        origin.country = origin.country.or(record.country);
+    }
+    origin
+}
@@ -0,0 +1,216 @@
+//! Progress of a query, step by step.
+//!
+//! A [`ProbeObserver`] set with [`QueryOptions::observer`] is told about
+//! every stage of the queries run with those options, including the ones a
+//! compare round or a probe stream starts: [`ProbeEvent::Started`] for each
+//! attempt, then name resolution, request and reply, and finally
+//! [`ProbeEvent::Completed`] or [`ProbeEvent::Failed`] once per query.
+//! Displays can follow queries as they happen instead of waiting for the
+//! results.
+//!
+//! [`QueryOptions::observer`]: crate::services::query::QueryOptions::observer
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+use crate::domain::ntp::ProbeResult;
+use crate::error::RkikError;
+
+/// One step of a query. Every event names the target as given.
+#[derive(Debug, Clone)]
+pub enum ProbeEvent {
+    /// An attempt begins; `attempt` counts retries from 0.
+    Started { target: String, attempt: u32 },
+    /// The host name resolved to `ip` in `dns_ms`.
+    DnsResolved {
+        target: String,
+        ip: IpAddr,
+        dns_ms: f64,
+    },
+    /// The request is on its way to `addr` (for NTS, the key exchange and
+    /// the request).
+    Sent { target: String, addr: SocketAddr },
+    /// `addr` answered after `rtt_ms`.
+    Received {
+        target: String,
+        addr: SocketAddr,
+        rtt_ms: f64,
+    },
+    /// The query succeeded.
+    Completed(Box<ProbeResult>),
+    /// The query failed, after its last attempt.
+    Failed {
+        target: String,
+        /// [`RkikError::kind`] of the error.
+        kind: &'static str,
+        message: String,
+    },
+}
+
+impl ProbeEvent {
+    /// Target of the query the event belongs to.
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Started { target, .. }
+            | Self::DnsResolved { target, .. }
+            | Self::Sent { target, .. }
+            | Self::Received { target, .. }
+            | Self::Failed { target, .. } => target,
+            Self::Completed(result) => &result.target.name,
+        }
+    }
+
+    pub(crate) fn finished(target: &str, res: &Result<ProbeResult, RkikError>) -> Self {
+        match res {
+            Ok(result) => Self::Completed(Box::new(result.clone())),
+            Err(e) => Self::Failed {
+                target: target.to_string(),
+                kind: e.kind(),
+                message: e.message(),
+            },
+        }
+    }
+}
+
+/// Callback receiving [`ProbeEvent`]s; cheap to clone.
+///
+/// It runs on the task doing the query, possibly on several threads at
+/// once, and must return quickly.
+#[derive(Clone)]
+pub struct ProbeObserver(Arc<dyn Fn(&ProbeEvent) + Send + Sync>);
+
+impl ProbeObserver {
+    pub fn new(f: impl Fn(&ProbeEvent) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// An observer forwarding events to the returned receiver. Events
+    /// sent after the receiver is dropped are discarded.
+    pub fn channel() -> (Self, UnboundedReceiver<ProbeEvent>) {
+        let (tx, rx) = unbounded_channel();
+        let observer = Self::new(move |event| {
+            let _ = tx.send(event.clone());
+        });
+        (observer, rx)
+    }
+
+    /// An observer logging events at debug level with `tracing`.
+    pub fn tracing() -> Self {
+        Self::new(|event| match event {
+            ProbeEvent::Started { target, attempt } => {
+                tracing::debug!(%target, attempt, "probe started")
+            }
+            ProbeEvent::DnsResolved { target, ip, dns_ms } => {
+                tracing::debug!(%target, %ip, dns_ms, "name resolved")
+            }
+            ProbeEvent::Sent { target, addr } => tracing::debug!(%target, %addr, "request sent"),
+            ProbeEvent::Received {
+                target,
+                addr,
+                rtt_ms,
+            } => tracing::debug!(%target, %addr, rtt_ms, "reply received"),
+            ProbeEvent::Completed(result) => tracing::debug!(
+                target = %result.target.name,
+                offset_ms = result.offset_ms,
+                rtt_ms = result.rtt_ms,
+                "probe completed"
+            ),
+            ProbeEvent::Failed {
+                target,
+                kind,
+                message,
+            } => tracing::debug!(%target, kind, %message, "probe failed"),
+        })
+    }
+
+    pub fn notify(&self, event: &ProbeEvent) {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for ProbeObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProbeObserver")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::transport::{MockTransport, NtpSample};
+    use crate::domain::ntp::Leap;
+    use crate::services::query::{QueryOptions, query_via};
+
+    #[tokio::test]
+    async fn a_query_reports_each_step_in_order() {
+        let addr: SocketAddr = "192.0.2.1:123".parse().unwrap();
+        let mock = MockTransport::new();
+        mock.push(addr, Err(RkikError::Timeout));
+        mock.push(
+            addr,
+            Ok(NtpSample {
+                offset_ms: 1.5,
+                rtt_ms: 8.0,
+                stratum: 1,
+                ref_id: "GPS".into(),
+                leap: Leap::Normal,
+                health: Default::default(),
+                utc: chrono::DateTime::UNIX_EPOCH,
+                timing: None,
+                uncertainty_ms: None,
+                reference_age_s: None,
+            }),
+        );
+        let (observer, mut events) = ProbeObserver::channel();
+        let options = QueryOptions::new().retries(1).observer(observer);
+        query_via("192.0.2.1", &options, &mock).await.unwrap();
+
+        let mut steps = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            assert_eq!(event.target(), "192.0.2.1");
+            steps.push(match event {
+                ProbeEvent::Started { attempt, .. } => format!("started {attempt}"),
+                ProbeEvent::DnsResolved { ip, .. } => format!("resolved {ip}"),
+                ProbeEvent::Sent { .. } => "sent".into(),
+                ProbeEvent::Received { rtt_ms, .. } => format!("received {rtt_ms}"),
+                ProbeEvent::Completed(r) => format!("completed {}", r.offset_ms),
+                ProbeEvent::Failed { kind, .. } => format!("failed {kind}"),
+            });
+        }
+        assert_eq!(
+            steps,
+            [
+                "started 0",
+                "resolved 192.0.2.1",
+                "sent",
+                "started 1",
+                "resolved 192.0.2.1",
+                "sent",
+                "received 8",
+                "completed 1.5",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failed_query_ends_with_its_error() {
+        let (observer, mut events) = ProbeObserver::channel();
+        let options = QueryOptions::new().observer(observer);
+        let mock = MockTransport::new();
+        query_via("192.0.2.1", &options, &mock).await.unwrap_err();
+        let mut last = None;
+        while let Ok(event) = events.try_recv() {
+            last = Some(event);
+        }
+        assert!(matches!(
+            last,
+            Some(ProbeEvent::Failed {
+                kind: "timeout",
+                ..
+            })
+        ));
+    }
+}
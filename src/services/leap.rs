@@ -0,0 +1,412 @@
+//! Leap second sanity checks: compares the leap state servers and the
+//! kernel advertise with the IERS leap second table.
+//!
+//! Servers announce a leap second through the two LI bits of their replies
+//! during the month it ends (chronyd and some others only on its last day).
+//! The table says whether one is scheduled, and TAI−UTC at any date up to
+//! its expiry; past that date nothing can be verified.
+
+use std::fmt;
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, Utc};
+
+use crate::adapters::kernel::KernelClock;
+use crate::domain::ntp::Leap;
+use crate::error::RkikError;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch.
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+/// Where tzdata installs the IERS list.
+pub const SYSTEM_LIST: &str = "/usr/share/zoneinfo/leap-seconds.list";
+
+/// TAI−UTC from the first day of each month (IERS Bulletin C).
+const BUNDLED: [(i32, u32, i32); 28] = [
+    (1972, 1, 10),
+    (1972, 7, 11),
+    (1973, 1, 12),
+    (1974, 1, 13),
+    (1975, 1, 14),
+    (1976, 1, 15),
+    (1977, 1, 16),
+    (1978, 1, 17),
+    (1979, 1, 18),
+    (1980, 1, 19),
+    (1981, 7, 20),
+    (1982, 7, 21),
+    (1983, 7, 22),
+    (1985, 7, 23),
+    (1988, 1, 24),
+    (1990, 1, 25),
+    (1991, 1, 26),
+    (1992, 7, 27),
+    (1993, 7, 28),
+    (1994, 7, 29),
+    (1996, 1, 30),
+    (1997, 7, 31),
+    (1999, 1, 32),
+    (2006, 1, 33),
+    (2009, 1, 34),
+    (2012, 7, 35),
+    (2015, 7, 36),
+    (2017, 1, 37),
+];
+/// Expiry of the Bulletin C the bundled table was taken from.
+const BUNDLED_EXPIRES: (i32, u32, u32) = (2026, 6, 28);
+
+/// The leap seconds announced by the IERS and the date the announcement
+/// stops being valid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeapTable {
+    /// Instant each TAI−UTC value takes effect, in order.
+    changes: Vec<(DateTime<Utc>, i32)>,
+    expires: DateTime<Utc>,
+}
+
+impl LeapTable {
+    /// The table compiled into rkik.
+    pub fn bundled() -> Self {
+        let day = |y, m, d| {
+            NaiveDate::from_ymd_opt(y, m, d)
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|t| t.and_utc())
+                .unwrap_or_default()
+        };
+        let (y, m, d) = BUNDLED_EXPIRES;
+        LeapTable {
+            changes: BUNDLED
+                .iter()
+                .map(|&(y, m, offset)| (day(y, m, 1), offset))
+                .collect(),
+            expires: day(y, m, d),
+        }
+    }
+
+    /// The freshest of the bundled table and the system's
+    /// [`SYSTEM_LIST`], when one is installed.
+    pub fn current() -> Self {
+        let bundled = Self::bundled();
+        match Self::load(Path::new(SYSTEM_LIST)) {
+            Ok(system) if system.expires > bundled.expires => system,
+            _ => bundled,
+        }
+    }
+
+    /// Read an IETF/IERS `leap-seconds.list` file.
+    pub fn load(path: &Path) -> Result<Self, RkikError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| RkikError::Other(format!("cannot read {}: {}", path.display(), e)))?;
+        Self::parse(&text).map_err(|e| match e {
+            RkikError::Other(msg) => RkikError::Other(format!("{}: {}", path.display(), msg)),
+            e => e,
+        })
+    }
+
+    /// Parse the `leap-seconds.list` format: `#@` gives the expiry and each
+    /// data line an NTP timestamp and the TAI−UTC offset from then on.
+    pub fn parse(text: &str) -> Result<Self, RkikError> {
+        let ntp_time = |field: &str| {
+            field
+                .parse::<i64>()
+                .ok()
+                .and_then(|s| DateTime::from_timestamp(s - NTP_UNIX_OFFSET, 0))
+                .ok_or_else(|| RkikError::Other(format!("invalid timestamp '{field}'")))
+        };
+        let mut changes = Vec::new();
+        let mut expires = None;
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("#@") {
+                expires = Some(ntp_time(rest.trim())?);
+                continue;
+            }
+            let data = line.split('#').next().unwrap_or_default();
+            let mut fields = data.split_whitespace();
+            let (Some(at), Some(offset)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let offset = offset
+                .parse::<i32>()
+                .map_err(|_| RkikError::Other(format!("invalid offset '{offset}'")))?;
+            changes.push((ntp_time(at)?, offset));
+        }
+        let expires =
+            expires.ok_or_else(|| RkikError::Other("no expiration date (#@ line)".into()))?;
+        if changes.is_empty() {
+            return Err(RkikError::Other("no leap seconds listed".into()));
+        }
+        changes.sort_by_key(|&(at, _)| at);
+        Ok(LeapTable { changes, expires })
+    }
+
+    /// When the announcement stops covering the future.
+    pub fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+
+    /// Whether the table still says anything about `at`.
+    pub fn covers(&self, at: DateTime<Utc>) -> bool {
+        at < self.expires
+    }
+
+    /// TAI−UTC at `at`; `None` before 1972 or past the expiry.
+    pub fn tai_offset(&self, at: DateTime<Utc>) -> Option<i32> {
+        if !self.covers(at) {
+            return None;
+        }
+        self.changes
+            .iter()
+            .take_while(|&&(from, _)| from <= at)
+            .last()
+            .map(|&(_, offset)| offset)
+    }
+
+    /// The leap second due at the end of the month containing `at`;
+    /// `None` when the table expires before then.
+    pub fn scheduled(&self, at: DateTime<Utc>) -> Option<Leap> {
+        let end = month_end(at);
+        if end > self.expires {
+            return None;
+        }
+        let before = self.tai_offset(end - TimeDelta::seconds(1))?;
+        let after = self
+            .changes
+            .iter()
+            .take_while(|&&(from, _)| from <= end)
+            .last()
+            .map_or(before, |&(_, offset)| offset);
+        Some(match after - before {
+            d if d > 0 => Leap::InsertSecond,
+            d if d < 0 => Leap::DeleteSecond,
+            _ => Leap::Normal,
+        })
+    }
+}
+
+/// First instant of the month after the one containing `at`.
+pub(crate) fn month_end(at: DateTime<Utc>) -> DateTime<Utc> {
+    let (y, m) = match at.month() {
+        12 => (at.year() + 1, 1),
+        m => (at.year(), m + 1),
+    };
+    NaiveDate::from_ymd_opt(y, m, 1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map_or(at, |t| t.and_utc())
+}
+
+/// A leap state that disagrees with the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeapProblem {
+    /// LI = 3: the source says it is not synchronised.
+    Unsynchronised,
+    /// A leap second is announced but none is scheduled this month.
+    Stale(Leap),
+    /// An insertion is announced where the table has a deletion, or the reverse.
+    Wrong { announced: Leap, scheduled: Leap },
+    /// A leap second is due within a day and is not announced.
+    Missing(Leap),
+    /// The kernel's TAI offset differs from the table's.
+    TaiOffset { reported: i32, expected: i32 },
+}
+
+impl fmt::Display for LeapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeapProblem::Unsynchronised => write!(f, "leap indicator says not synchronised"),
+            LeapProblem::Stale(leap) => write!(
+                f,
+                "announces {} but no leap second is scheduled this month",
+                describe(*leap)
+            ),
+            LeapProblem::Wrong {
+                announced,
+                scheduled,
+            } => write!(
+                f,
+                "announces {} but {} is scheduled",
+                describe(*announced),
+                describe(*scheduled)
+            ),
+            LeapProblem::Missing(leap) => {
+                write!(f, "does not announce the {} due tonight", describe(*leap))
+            }
+            LeapProblem::TaiOffset { reported, expected } => {
+                write!(f, "TAI-UTC is {reported} s, the table says {expected} s")
+            }
+        }
+    }
+}
+
+fn describe(leap: Leap) -> &'static str {
+    match leap {
+        Leap::InsertSecond => "an inserted second",
+        Leap::DeleteSecond => "a deleted second",
+        Leap::Normal => "no leap second",
+        Leap::Unsynchronised => "no synchronisation",
+    }
+}
+
+/// Check the leap indicator a server answered with at `now`.
+pub fn check_indicator(leap: Leap, table: &LeapTable, now: DateTime<Utc>) -> Option<LeapProblem> {
+    if leap == Leap::Unsynchronised {
+        return Some(LeapProblem::Unsynchronised);
+    }
+    let scheduled = table.scheduled(now)?;
+    match (leap, scheduled) {
+        (Leap::Normal, Leap::Normal) => None,
+        (Leap::Normal, due) => {
+            (month_end(now) - now <= TimeDelta::days(1)).then_some(LeapProblem::Missing(due))
+        }
+        (announced, Leap::Normal) => Some(LeapProblem::Stale(announced)),
+        (announced, scheduled) if announced != scheduled => Some(LeapProblem::Wrong {
+            announced,
+            scheduled,
+        }),
+        _ => None,
+    }
+}
+
+/// Check the kernel's armed leap second and TAI offset at `now`.
+pub fn check_kernel(
+    clock: &KernelClock,
+    table: &LeapTable,
+    now: DateTime<Utc>,
+) -> Vec<LeapProblem> {
+    let mut problems = Vec::new();
+    // 0 means no daemon has set it.
+    if clock.tai_offset_s != 0
+        && let Some(expected) = table.tai_offset(now)
+        && clock.tai_offset_s != expected
+    {
+        problems.push(LeapProblem::TaiOffset {
+            reported: clock.tai_offset_s,
+            expected,
+        });
+    }
+    let armed = if clock.flags.contains(&"INS") {
+        Leap::InsertSecond
+    } else if clock.flags.contains(&"DEL") {
+        Leap::DeleteSecond
+    } else {
+        Leap::Normal
+    };
+    problems.extend(check_indicator(armed, table, now));
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::kernel::ClockState;
+
+    const LIST: &str = "#\tleap-seconds.list excerpt\n\
+        #$\t 3913697179\n\
+        #@\t3991593600\n\
+        3550089600\t35\t# 1 Jul 2012\n\
+        3644697600\t36\t# 1 Jul 2015\n\
+        3692217600\t37\t# 1 Jan 2017\n\
+        #h\t49db2447 571e5e1b 2f002a53 9c8da8e4 39b8e49e\n";
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn list_is_parsed() {
+        let table = LeapTable::parse(LIST).unwrap();
+        assert_eq!(table.expires(), at("2026-06-28T00:00:00Z"));
+        assert_eq!(table.tai_offset(at("2016-12-31T23:59:59Z")), Some(36));
+        assert_eq!(table.tai_offset(at("2017-01-01T00:00:00Z")), Some(37));
+        assert_eq!(table.tai_offset(at("2026-10-15T00:00:00Z")), None);
+        assert_eq!(table, {
+            let mut bundled = LeapTable::bundled();
+            bundled.changes.drain(..25);
+            bundled
+        });
+        assert!(LeapTable::parse("3692217600\t37\n").is_err());
+        assert!(LeapTable::parse("#@\t3991593600\n3692217600\tx\n").is_err());
+    }
+
+    #[test]
+    fn scheduled_leaps_follow_the_table() {
+        let table = LeapTable::bundled();
+        let dec_2016 = at("2016-12-10T12:00:00Z");
+        assert_eq!(table.scheduled(dec_2016), Some(Leap::InsertSecond));
+        assert_eq!(
+            table.scheduled(at("2016-11-30T12:00:00Z")),
+            Some(Leap::Normal)
+        );
+        // June 2026 ends after the bundled expiry.
+        assert_eq!(table.scheduled(at("2026-06-01T00:00:00Z")), None);
+        assert_eq!(
+            table.scheduled(at("2026-05-31T00:00:00Z")),
+            Some(Leap::Normal)
+        );
+    }
+
+    #[test]
+    fn indicators_are_checked() {
+        let table = LeapTable::bundled();
+        let dec_2016 = at("2016-12-10T12:00:00Z");
+        let eve = at("2016-12-31T12:00:00Z");
+        let quiet = at("2018-03-10T12:00:00Z");
+        assert_eq!(check_indicator(Leap::InsertSecond, &table, dec_2016), None);
+        // Announcing only on the last day is fine, not announcing then is not.
+        assert_eq!(check_indicator(Leap::Normal, &table, dec_2016), None);
+        assert_eq!(
+            check_indicator(Leap::Normal, &table, eve),
+            Some(LeapProblem::Missing(Leap::InsertSecond))
+        );
+        assert_eq!(
+            check_indicator(Leap::DeleteSecond, &table, eve),
+            Some(LeapProblem::Wrong {
+                announced: Leap::DeleteSecond,
+                scheduled: Leap::InsertSecond
+            })
+        );
+        assert_eq!(
+            check_indicator(Leap::InsertSecond, &table, quiet),
+            Some(LeapProblem::Stale(Leap::InsertSecond))
+        );
+        assert_eq!(
+            check_indicator(Leap::Unsynchronised, &table, quiet),
+            Some(LeapProblem::Unsynchronised)
+        );
+        // Past the expiry only LI = 3 can be judged.
+        let late = at("2026-10-15T00:00:00Z");
+        assert_eq!(check_indicator(Leap::InsertSecond, &table, late), None);
+    }
+
+    #[test]
+    fn kernel_offset_and_armed_leap_are_checked() {
+        let table = LeapTable::bundled();
+        let clock = KernelClock {
+            state: ClockState::InsertLeap,
+            synchronised: true,
+            freq_ppm: 0.0,
+            offset_ms: 0.0,
+            est_error_ms: 0.0,
+            max_error_ms: 0.0,
+            flags: vec!["PLL", "INS"],
+            tai_offset_s: 36,
+            time_constant: 2,
+        };
+        assert_eq!(
+            check_kernel(&clock, &table, at("2018-03-10T12:00:00Z")),
+            [
+                LeapProblem::TaiOffset {
+                    reported: 36,
+                    expected: 37
+                },
+                LeapProblem::Stale(Leap::InsertSecond)
+            ]
+        );
+        assert!(check_kernel(&clock, &table, at("2016-12-31T12:00:00Z")).is_empty());
+        let unset = KernelClock {
+            flags: vec!["PLL"],
+            tai_offset_s: 0,
+            ..clock
+        };
+        assert!(check_kernel(&unset, &table, at("2018-03-10T12:00:00Z")).is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+//! Synthetic `mock://` targets: deterministic results without network IO.
+//!
+//! A mock target is `mock://NAME[?PARAM=VALUE&...]`. Every probe of the
+//! same target string advances a per-process counter, so ramps and failure
+//! patterns replay identically from run to run:
+//!
+//! | Parameter | Default | Meaning |
+//! |-----------|---------|---------|
+//! | `offset`  | `0`     | Offset of the first probe |
+//! | `ramp`    | `0`     | Added to the offset at each further probe |
+//! | `jitter`  | `0`     | Pseudo-random spread (±), the same for a given name and probe |
+//! | `rtt`     | `1ms`   | Round trip delay reported |
+//! | `stratum` | `2`     | Stratum reported |
+//! | `leap`    | `0`     | Leap indicator (0–3) |
+//! | `fail`    | none    | Error kind returned: `timeout`, `refused`, `dns`, `network`, `rate`, `kod`, `auth` |
+//! | `every`   | `1`     | With `fail`: only every Nth probe fails |
+//! | `after`   | `0`     | With `fail`: the first N probes succeed |
+//!
+//! Durations are milliseconds unless suffixed with `us`, `ms` or `s`, and
+//! may be negative. Mock probes report the documentation address
+//! `192.0.2.1` (RFC 5737) and the reference id `MOCK`.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local, TimeDelta, Utc};
+
+use crate::domain::ntp::{Leap, ProbeResult, Resolution, Target};
+use crate::error::RkikError;
+
+/// Address reported by every mock probe.
+pub const MOCK_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+const PARAMETERS: [&str; 9] = [
+    "offset", "ramp", "jitter", "rtt", "stratum", "leap", "fail", "every", "after",
+];
+
+/// Probes made so far, per target string.
+static COUNTERS: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+/// What a `mock://` target answers.
+#[derive(Debug, Clone, PartialEq)]
+struct MockSpec {
+    offset_ms: f64,
+    ramp_ms: f64,
+    jitter_ms: f64,
+    rtt_ms: f64,
+    stratum: u8,
+    leap: u8,
+    fail: Option<FailKind>,
+    every: u64,
+    after: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FailKind {
+    Timeout,
+    Refused,
+    Dns,
+    Network,
+    Rate,
+    Kod,
+    Auth,
+}
+
+impl FailKind {
+    fn error(self, name: &str) -> RkikError {
+        match self {
+            FailKind::Timeout => RkikError::Timeout,
+            FailKind::Refused => RkikError::Refused(format!("mock target {name} refused")),
+            FailKind::Dns => RkikError::Dns(format!("mock target {name} does not resolve")),
+            FailKind::Network => RkikError::Network(format!("mock target {name} unreachable")),
+            FailKind::Rate => RkikError::RateLimited("RATE".into()),
+            FailKind::Kod => RkikError::KissOfDeath("DENY".into()),
+            FailKind::Auth => RkikError::AuthFailed(format!("mock target {name} failed")),
+        }
+    }
+}
+
+impl Default for MockSpec {
+    fn default() -> Self {
+        Self {
+            offset_ms: 0.0,
+            ramp_ms: 0.0,
+            jitter_ms: 0.0,
+            rtt_ms: 1.0,
+            stratum: 2,
+            leap: 0,
+            fail: None,
+            every: 1,
+            after: 0,
+        }
+    }
+}
+
+/// Split `NAME?params` (the part after `mock://`) into a name and a spec.
+fn parse(rest: &str) -> Result<(&str, MockSpec), RkikError> {
+    let (name, query) = rest.split_once('?').unwrap_or((rest, ""));
+    if name.is_empty() {
+        return Err(RkikError::Other(
+            "mock:// targets need a name (mock://NAME?offset=5ms)".into(),
+        ));
+    }
+    let mut spec = MockSpec::default();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let bad = || RkikError::Other(format!("invalid mock parameter '{pair}'"));
+        match key {
+            "offset" => spec.offset_ms = millis(value).ok_or_else(bad)?,
+            "ramp" => spec.ramp_ms = millis(value).ok_or_else(bad)?,
+            "jitter" => spec.jitter_ms = millis(value).ok_or_else(bad)?.abs(),
+            "rtt" => spec.rtt_ms = millis(value).filter(|v| *v >= 0.0).ok_or_else(bad)?,
+            "stratum" => spec.stratum = at_most(value, 16).ok_or_else(bad)?,
+            "leap" => spec.leap = at_most(value, 3).ok_or_else(bad)?,
+            "every" => {
+                spec.every = at_most(value, u64::MAX)
+                    .filter(|n| *n > 0)
+                    .ok_or_else(bad)?
+            }
+            "after" => spec.after = at_most(value, u64::MAX).ok_or_else(bad)?,
+            "fail" => {
+                spec.fail = Some(match value {
+                    "timeout" => FailKind::Timeout,
+                    "refused" => FailKind::Refused,
+                    "dns" => FailKind::Dns,
+                    "network" => FailKind::Network,
+                    "rate" => FailKind::Rate,
+                    "kod" => FailKind::Kod,
+                    "auth" => FailKind::Auth,
+                    _ => return Err(bad()),
+                })
+            }
+            _ => {
+                return Err(RkikError::Other(format!(
+                    "unknown mock parameter '{key}' (use {})",
+                    PARAMETERS.join(", ")
+                )));
+            }
+        }
+    }
+    Ok((name, spec))
+}
+
+fn at_most<T: std::str::FromStr + PartialOrd>(value: &str, max: T) -> Option<T> {
+    value.parse().ok().filter(|v| *v <= max)
+}
+
+/// `5`, `5ms`, `250us`, `-1.5s` as milliseconds.
+fn millis(value: &str) -> Option<f64> {
+    let (num, scale) = if let Some(n) = value.strip_suffix("us") {
+        (n, 1e-3)
+    } else if let Some(n) = value.strip_suffix("ms") {
+        (n, 1.0)
+    } else if let Some(n) = value.strip_suffix('s') {
+        (n, 1000.0)
+    } else {
+        (value, 1.0)
+    };
+    num.parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .map(|v| v * scale)
+}
+
+/// Answer a probe of `target`, whose scheme-less part is `rest`.
+pub(crate) fn probe(target: &str, rest: &str) -> Result<ProbeResult, RkikError> {
+    let (name, spec) = parse(rest).map_err(|e| e.with_target(target))?;
+    let n = next(target);
+    if let Some(kind) = spec.fail
+        && n >= spec.after
+        && (n + 1).is_multiple_of(spec.every)
+    {
+        return Err(kind.error(name).with_target(target));
+    }
+    let jitter = if spec.jitter_ms > 0.0 {
+        spec.jitter_ms * (2.0 * noise(name, n) - 1.0)
+    } else {
+        0.0
+    };
+    let offset_ms = spec.offset_ms + spec.ramp_ms * n as f64 + jitter;
+    let utc = Utc::now() + TimeDelta::microseconds((offset_ms * 1000.0).round() as i64);
+    let local: DateTime<Local> = DateTime::from(utc);
+    Ok(ProbeResult {
+        target: Target {
+            resolution: Resolution::Synthetic,
+            ..Target::new(target, MOCK_IP, 123)
+        },
+        offset_ms,
+        rtt_ms: spec.rtt_ms,
+        stratum: spec.stratum,
+        ref_id: "MOCK".into(),
+        utc,
+        local,
+        timestamp: utc.timestamp(),
+        authenticated: false,
+        dnssec: false,
+        leap: Leap::from_indicator(spec.leap),
+        health: Default::default(),
+        pause_ms: None,
+        origin: None,
+        timing: None,
+        // A synthetic server keeps perfect time: only the path is uncertain.
+        uncertainty_ms: Some(spec.rtt_ms / 2.0),
+        clock_step_ms: None,
+        reference_age_s: None,
+        #[cfg(feature = "nts")]
+        nts_ke_data: None,
+        #[cfg(feature = "nts")]
+        nts_validation: None,
+    })
+}
+
+/// Index of this probe of `target`, starting at 0.
+fn next(target: &str) -> u64 {
+    let mut guard = COUNTERS.lock().unwrap_or_else(|e| e.into_inner());
+    let counter = guard
+        .get_or_insert_with(HashMap::new)
+        .entry(target.to_string())
+        .or_insert(0);
+    let n = *counter;
+    *counter += 1;
+    n
+}
+
+/// Forget how many times each mock target was probed, so the next probes
+/// start over from the first one.
+pub fn reset() {
+    *COUNTERS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// A number in `[0, 1)` fixed by `name` and `n` (FNV-1a, then splitmix64).
+fn noise(name: &str, n: u64) -> f64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in name.bytes() {
+        h = (h ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    let mut z = h ^ n.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_and_failure_patterns_replay() {
+        let target = "mock://ramp?offset=1ms&ramp=0.5ms&fail=timeout&every=3";
+        let rest = target.strip_prefix("mock://").unwrap();
+        let first = probe(target, rest).unwrap();
+        assert_eq!(first.offset_ms, 1.0);
+        assert_eq!(first.target.ip, MOCK_IP);
+        assert_eq!(probe(target, rest).unwrap().offset_ms, 1.5);
+        assert!(probe(target, rest).unwrap_err().is_retryable());
+        assert_eq!(probe(target, rest).unwrap().offset_ms, 2.5);
+    }
+
+    #[test]
+    fn jitter_is_deterministic_and_bounded() {
+        for n in 0..100 {
+            let a = noise("a", n);
+            assert_eq!(a, noise("a", n));
+            assert!((0.0..1.0).contains(&a));
+        }
+        assert_ne!(noise("a", 1), noise("b", 1));
+    }
+
+    #[test]
+    fn parameters_are_validated() {
+        let (name, spec) =
+            parse("x?offset=-2s&rtt=250us&stratum=1&leap=3&fail=kod&after=2").unwrap();
+        assert_eq!(name, "x");
+        assert_eq!(spec.offset_ms, -2000.0);
+        assert_eq!(spec.rtt_ms, 0.25);
+        assert_eq!((spec.stratum, spec.leap, spec.after), (1, 3, 2));
+        assert_eq!(spec.fail, Some(FailKind::Kod));
+        for bad in [
+            "",
+            "?offset=1",
+            "x?offset=abc",
+            "x?stratum=17",
+            "x?every=0",
+            "x?fail=boom",
+            "x?colour=red",
+        ] {
+            assert!(parse(bad).is_err(), "{bad} should be rejected");
+        }
+    }
+}
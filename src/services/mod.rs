@@ -1,2 +1,20 @@
+pub mod asymmetry;
+pub mod clock_check;
 pub mod compare;
+pub mod diag;
+pub mod discover;
+pub mod divergence;
+#[cfg(feature = "enrich")]
+pub mod enrich;
+pub mod events;
+pub mod leap;
+pub mod mock;
+pub mod monitor;
+#[cfg(feature = "nts")]
+pub mod nts_ke;
+pub mod pacing;
 pub mod query;
+pub mod recommend;
+pub mod serve;
+pub mod smear;
+pub mod survey;
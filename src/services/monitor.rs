@@ -0,0 +1,285 @@
+//! Repeated probes of one target as a [`Stream`], the loop behind
+//! `rkik --count` and `--infinite`.
+//!
+//! The pause between probes never drops below
+//! [`MonitorOptions::min_interval`] and doubles after each RATE
+//! Kiss-o'-Death, see [`Pacer`].
+
+use std::time::Duration;
+
+use futures::Stream;
+use futures::stream;
+use tokio::time::Instant;
+
+use super::pacing::Pacer;
+use super::query::{QueryOptions, query_via};
+use crate::adapters::transport::{NtpTransport, UdpTransport};
+use crate::domain::ntp::ProbeResult;
+use crate::error::RkikError;
+
+/// Settings for [`probe_stream`]: how to query, how often and how many times.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MonitorOptions {
+    pub query: QueryOptions,
+    /// Pause between the end of one probe and the start of the next (default 1 s).
+    pub interval: Duration,
+    /// Number of probes, `None` to probe until the stream is dropped (default 1).
+    pub count: Option<u32>,
+    /// Floor under `interval` (default none).
+    pub min_interval: Duration,
+    /// Adapt the interval to the measured jitter instead of using
+    /// `interval` (default false).
+    pub auto_interval: bool,
+    /// Random share of the interval added to each pause, 0 to 1
+    /// (default 0).
+    pub interval_jitter: f64,
+    /// Wall-clock budget from the first probe: no probe starts once it
+    /// is spent (default none).
+    pub duration: Option<Duration>,
+}
+
+impl Default for MonitorOptions {
+    fn default() -> Self {
+        Self {
+            query: QueryOptions::default(),
+            interval: Duration::from_secs(1),
+            count: Some(1),
+            min_interval: Duration::ZERO,
+            auto_interval: false,
+            interval_jitter: 0.0,
+            duration: None,
+        }
+    }
+}
+
+impl MonitorOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn query(mut self, query: QueryOptions) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Lengthen the interval while offsets are stable and shorten it when
+    /// they move, see [`Pacer::adaptive`].
+    pub fn auto_interval(mut self) -> Self {
+        self.auto_interval = true;
+        self
+    }
+
+    pub fn count(mut self, count: u32) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// Lengthen each pause by up to `fraction` of the interval, at random;
+    /// see [`Pacer::with_jitter`].
+    pub fn interval_jitter(mut self, fraction: f64) -> Self {
+        self.interval_jitter = fraction;
+        self
+    }
+
+    /// Stop before a probe that would start more than `duration` after the
+    /// first one; combines with [`count`](Self::count) and
+    /// [`infinite`](Self::infinite).
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Probe until the stream is dropped.
+    pub fn infinite(mut self) -> Self {
+        self.count = None;
+        self
+    }
+}
+
+/// Probe `target` repeatedly, yielding each result as it arrives.
+///
+/// The first probe starts at once. The pause between probes is
+/// `interval` (or adapted to the jitter with `auto_interval`), raised to
+/// `min_interval`, lengthened by `interval_jitter` and backed off after a
+/// RATE Kiss-o'-Death; each result records the pause actually slept in
+/// `pause_ms`. A failed probe is yielded as an error and the stream goes
+/// on. The stream ends
+/// early when the
+/// [`QueryOptions::cancel`] token fires, abandoning a probe in flight or
+/// the pause before the next; dropping the stream stops it as well.
+pub fn probe_stream(
+    target: impl Into<String>,
+    options: MonitorOptions,
+) -> impl Stream<Item = Result<ProbeResult, RkikError>> {
+    probe_stream_via(target, options, UdpTransport)
+}
+
+/// Like [`probe_stream`], with plain NTP exchanges going through `transport`.
+pub fn probe_stream_via<T: NtpTransport>(
+    target: impl Into<String>,
+    options: MonitorOptions,
+    transport: T,
+) -> impl Stream<Item = Result<ProbeResult, RkikError>> {
+    let target = target.into();
+    let pacer = if options.auto_interval {
+        Pacer::adaptive(options.min_interval)
+    } else {
+        Pacer::new(options.interval, options.min_interval)
+    }
+    .with_jitter(options.interval_jitter);
+    // Probes done, start of the first one, pacing.
+    let state = (0u32, None, pacer, transport);
+    stream::unfold(state, move |(done, started, mut pacer, transport)| {
+        let target = target.clone();
+        let options = options.clone();
+        async move {
+            if options.count.is_some_and(|count| done >= count) || options.query.is_cancelled() {
+                return None;
+            }
+            let started = started.unwrap_or_else(Instant::now);
+            let pause = pacer.pause();
+            if let Some(budget) = options.duration
+                && done > 0
+                && started.elapsed() + pause > budget
+            {
+                return None;
+            }
+            let slept_from = Instant::now();
+            if done > 0 {
+                let pause = tokio::time::sleep(pause);
+                match &options.query.cancel {
+                    Some(token) => {
+                        tokio::select! {
+                            _ = token.cancelled() => return None,
+                            _ = pause => {}
+                        }
+                    }
+                    None => pause.await,
+                }
+            }
+            let slept = (done > 0).then(|| slept_from.elapsed().as_secs_f64() * 1000.0);
+            let mut res = query_via(&target, &options.query, &transport).await;
+            if matches!(&res, Err(e) if e.is_cancelled()) {
+                return None;
+            }
+            pacer.observe(&res);
+            if let Ok(probe) = &mut res {
+                probe.pause_ms = slept;
+                pacer.observe_offset(probe.offset_ms);
+            }
+            let state = (done.saturating_add(1), Some(started), pacer, transport);
+            Some((res, state))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::transport::{MockTransport, NtpSample};
+    use crate::domain::ntp::Leap;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn stream_stops_after_count_and_keeps_going_on_errors() {
+        let addr = "192.0.2.1:123".parse().unwrap();
+        let sample = NtpSample {
+            offset_ms: 0.5,
+            rtt_ms: 1.0,
+            stratum: 1,
+            ref_id: "PPS".into(),
+            leap: Leap::Normal,
+            health: Default::default(),
+            utc: chrono::DateTime::UNIX_EPOCH,
+            timing: None,
+            uncertainty_ms: None,
+            reference_age_s: None,
+        };
+        let mock = MockTransport::new().with_sample(addr, sample);
+        mock.push(addr, Err(RkikError::Timeout));
+
+        let options = MonitorOptions::new()
+            .interval(Duration::from_millis(10))
+            .count(3);
+        let started = std::time::Instant::now();
+        let results: Vec<_> = probe_stream_via("192.0.2.1", options, mock).collect().await;
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_err());
+        assert!(results[1..].iter().all(|r| r.is_ok()));
+        // Two pauses between three probes, recorded with each probe.
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        let pause_ms = results[1].as_ref().unwrap().pause_ms.unwrap();
+        assert!(pause_ms >= 10.0);
+    }
+
+    #[tokio::test]
+    async fn stream_stops_when_the_duration_is_spent() {
+        let addr = "192.0.2.1:123".parse().unwrap();
+        let sample = NtpSample {
+            offset_ms: 0.5,
+            rtt_ms: 1.0,
+            stratum: 1,
+            ref_id: "PPS".into(),
+            leap: Leap::Normal,
+            health: Default::default(),
+            utc: chrono::DateTime::UNIX_EPOCH,
+            timing: None,
+            uncertainty_ms: None,
+            reference_age_s: None,
+        };
+        let options = MonitorOptions::new()
+            .interval(Duration::from_millis(20))
+            .duration(Duration::from_millis(50))
+            .infinite();
+        let results: Vec<_> = probe_stream_via(
+            "192.0.2.1",
+            options,
+            MockTransport::new().with_sample(addr, sample),
+        )
+        .collect()
+        .await;
+        // Probes at 0, 20 and 40 ms; the next would start past the budget.
+        assert_eq!(results.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn cancelled_stream_ends_during_the_pause() {
+        let addr = "192.0.2.1:123".parse().unwrap();
+        let sample = NtpSample {
+            offset_ms: 0.5,
+            rtt_ms: 1.0,
+            stratum: 1,
+            ref_id: "PPS".into(),
+            leap: Leap::Normal,
+            health: Default::default(),
+            utc: chrono::DateTime::UNIX_EPOCH,
+            timing: None,
+            uncertainty_ms: None,
+            reference_age_s: None,
+        };
+        let token = tokio_util::sync::CancellationToken::new();
+        let options = MonitorOptions::new()
+            .query(QueryOptions::new().cancel_on(token.clone()))
+            .interval(Duration::from_secs(3600))
+            .infinite();
+        let mut probes = std::pin::pin!(probe_stream_via(
+            "192.0.2.1",
+            options,
+            MockTransport::new().with_sample(addr, sample),
+        ));
+        assert!(probes.next().await.unwrap().is_ok());
+        token.cancel();
+        assert!(probes.next().await.is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! Cold and warm NTS-KE handshakes against one server.
+//!
+//! The first handshake of a run is full; the following ones resume the TLS
+//! session from its ticket, as an NTS client renewing its keys would. The
+//! difference is what resumption saves on every key exchange after the
+//! first.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use std::time::Duration;
+
+use crate::adapters::nts_ke::{self, KeHandshake};
+use crate::error::RkikError;
+use crate::services::query::{Scheme, parse_target, split_scheme};
+
+/// How the handshakes are run.
+#[derive(Debug, Clone)]
+pub struct NtsKeOptions {
+    /// Handshakes in a row (default 2: one cold, one warm).
+    pub count: usize,
+    /// NTS-KE port when the target does not name one (default 4460).
+    pub port: u16,
+    /// Time allowed for each handshake (default 5 s).
+    pub timeout: Duration,
+    pub ipv6_only: bool,
+}
+
+impl Default for NtsKeOptions {
+    fn default() -> Self {
+        Self {
+            count: 2,
+            port: 4460,
+            timeout: Duration::from_secs(5),
+            ipv6_only: false,
+        }
+    }
+}
+
+impl NtsKeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At least 1.
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count.max(1);
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn ipv6_only(mut self, ipv6_only: bool) -> Self {
+        self.ipv6_only = ipv6_only;
+        self
+    }
+}
+
+/// The handshakes of a run, and what resumption changed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct NtsKeReport {
+    /// Target as given.
+    pub target: String,
+    pub handshakes: Vec<KeHandshake>,
+    /// Duration of the first full handshake (ms).
+    pub cold_ms: Option<f64>,
+    /// Median duration of the resumed handshakes (ms), `None` when the
+    /// server never resumed a session.
+    pub warm_ms: Option<f64>,
+}
+
+impl NtsKeReport {
+    fn new(target: &str, handshakes: Vec<KeHandshake>) -> Self {
+        let cold_ms = handshakes.iter().find(|h| !h.resumed).map(|h| h.total_ms);
+        let mut warm: Vec<f64> = handshakes
+            .iter()
+            .filter(|h| h.resumed)
+            .map(|h| h.total_ms)
+            .collect();
+        Self {
+            target: target.to_string(),
+            handshakes,
+            cold_ms,
+            warm_ms: median(&mut warm),
+        }
+    }
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    match n {
+        0 => None,
+        _ if n.is_multiple_of(2) => Some((values[n / 2 - 1] + values[n / 2]) / 2.0),
+        _ => Some(values[n / 2]),
+    }
+}
+
+/// Run `options.count` NTS-KE handshakes with `target` (`host`,
+/// `host:port` or `nts://host[:port]`), one after the other.
+pub async fn measure(target: &str, options: &NtsKeOptions) -> Result<NtsKeReport, RkikError> {
+    let (scheme, rest) = split_scheme(target)?;
+    if scheme.is_some_and(|s| s != Scheme::Nts) {
+        return Err(RkikError::Other(format!(
+            "'{target}' is not an NTS target (use host or nts://host)"
+        )));
+    }
+    let parsed = parse_target(rest)?;
+    if parsed.zone.is_some() {
+        return Err(RkikError::Other(
+            "NTS targets cannot carry a zone identifier".into(),
+        ));
+    }
+    let port = parsed.port.unwrap_or(options.port);
+    let mut handshakes = Vec::with_capacity(options.count);
+    for _ in 0..options.count {
+        handshakes
+            .push(nts_ke::handshake(parsed.host, port, options.timeout, options.ipv6_only).await?);
+    }
+    Ok(NtsKeReport::new(target, handshakes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(total_ms: f64, resumed: bool) -> KeHandshake {
+        KeHandshake {
+            ip: "192.0.2.1".parse().unwrap(),
+            port: 4460,
+            tls_version: "TLSv1_3".into(),
+            cipher_suite: "TLS13_AES_128_GCM_SHA256".into(),
+            alpn: Some("ntske/1".into()),
+            resumed,
+            hello_retry: false,
+            dns_ms: 0.0,
+            tcp_ms: 0.0,
+            tls_ms: 0.0,
+            ke_ms: 0.0,
+            total_ms,
+            aead_algorithm: Some(15),
+            cookie_count: 8,
+        }
+    }
+
+    #[test]
+    fn warm_is_the_median_of_resumed_handshakes() {
+        let report = NtsKeReport::new(
+            "t",
+            vec![
+                handshake(40.0, false),
+                handshake(25.0, true),
+                handshake(20.0, true),
+                handshake(30.0, true),
+            ],
+        );
+        assert_eq!((report.cold_ms, report.warm_ms), (Some(40.0), Some(25.0)));
+        let never = NtsKeReport::new("t", vec![handshake(40.0, false), handshake(41.0, false)]);
+        assert_eq!(never.warm_ms, None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Polling etiquette toward public servers: a floor under the interval
+//! between queries, and exponential backoff after a RATE Kiss-o'-Death
+//! (RFC 5905 7.4), so monitoring runs never hammer a server. The interval
+//! can also adapt to the measured jitter, as an NTP client's poll does.
+
+use std::time::Duration;
+
+use crate::error::RkikError;
+use crate::services::query::{parse_target, split_scheme};
+
+/// Shortest interval accepted against the NTP Pool unless forced.
+pub const POOL_MIN_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest pause backoff grows to.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(1024);
+/// Bounds of an adaptive interval: NTP's shortest burst spacing and its
+/// longest poll (2^10 s).
+pub const AUTO_MIN_INTERVAL: Duration = Duration::from_secs(1);
+pub const AUTO_MAX_INTERVAL: Duration = Duration::from_secs(1024);
+
+/// An offset step is unremarkable below this many times the jitter
+/// (RFC 5905 PGATE).
+const PGATE: f64 = 4.0;
+/// Stable samples needed to double an adaptive interval; a step beyond
+/// the gate counts for half as many against it, and halves the interval
+/// once the count reaches the same limit the other way.
+const ADAPT_LIMIT: i32 = 8;
+
+/// Whether `target` is an NTP Pool name (`pool.ntp.org`, `*.pool.ntp.org`).
+pub fn is_pool(target: &str) -> bool {
+    let Ok((_, rest)) = split_scheme(target) else {
+        return false;
+    };
+    let Ok(parsed) = parse_target(rest) else {
+        return false;
+    };
+    let host = parsed.host.trim_end_matches('.').to_ascii_lowercase();
+    host == "pool.ntp.org" || host.ends_with(".pool.ntp.org")
+}
+
+/// Pause between two queries to the same server(s).
+///
+/// Starts at the requested interval, raised to the minimum interval, and
+/// doubles after each RATE Kiss-o'-Death up to [`MAX_BACKOFF`]. The
+/// backoff is never undone: a server that asked once keeps being spared.
+///
+/// An [adaptive](Pacer::adaptive) pacer also follows the offsets it is
+/// fed: it lengthens the interval while they stay within the jitter and
+/// shortens it again when they start to move, between
+/// [`AUTO_MIN_INTERVAL`] and [`AUTO_MAX_INTERVAL`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pacer {
+    base: Duration,
+    /// Lowest interval allowed from now on, raised by backoff.
+    floor: Duration,
+    current: Duration,
+    adaptive: Option<Adaptive>,
+    /// Fraction of the interval added at random to each pause.
+    jitter: f64,
+}
+
+/// Poll adaptation state, after RFC 5905's `clock_adjust`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Adaptive {
+    last_offset_ms: Option<f64>,
+    jitter_ms: Option<f64>,
+    /// Stable samples minus the weight of unstable ones, since the last
+    /// change of interval.
+    score: i32,
+}
+
+impl Pacer {
+    pub fn new(interval: Duration, min_interval: Duration) -> Self {
+        let base = interval.max(min_interval);
+        Pacer {
+            base,
+            floor: base,
+            current: base,
+            adaptive: None,
+            jitter: 0.0,
+        }
+    }
+
+    /// A pacer starting at the shortest interval allowed and adapting to
+    /// the offsets passed to [`observe_offset`](Pacer::observe_offset).
+    pub fn adaptive(min_interval: Duration) -> Self {
+        Pacer {
+            adaptive: Some(Adaptive::default()),
+            ..Pacer::new(AUTO_MIN_INTERVAL, min_interval)
+        }
+    }
+
+    /// Lengthen each pause by a random share of the interval, up to
+    /// `fraction` (clamped to 0..=1), so hosts started together drift
+    /// apart. Pauses never get shorter than the interval.
+    pub fn with_jitter(mut self, fraction: f64) -> Self {
+        self.jitter = if fraction.is_finite() {
+            fraction.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self
+    }
+
+    /// The pause to sleep before the next query: the interval plus its
+    /// random jitter.
+    pub fn pause(&self) -> Duration {
+        self.current.mul_f64(1.0 + self.jitter * random_unit())
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        self.adaptive.is_some()
+    }
+
+    /// The pause to apply before the next query.
+    pub fn interval(&self) -> Duration {
+        self.current
+    }
+
+    /// The interval before any backoff or adaptation.
+    pub fn base(&self) -> Duration {
+        self.base
+    }
+
+    /// True once a Kiss-o'-Death slowed the pace.
+    pub fn is_backed_off(&self) -> bool {
+        self.floor > self.base
+    }
+
+    /// Slow down after a RATE Kiss-o'-Death; returns the new interval.
+    pub fn rate_limited(&mut self) -> Duration {
+        self.current = (self.current * 2)
+            .max(Duration::from_secs(2))
+            .min(MAX_BACKOFF.max(self.base));
+        self.floor = self.current;
+        self.current
+    }
+
+    /// Account for a query's result; true when it slowed the pace.
+    pub fn observe<T>(&mut self, result: &Result<T, RkikError>) -> bool {
+        match result {
+            Err(e) if e.is_rate_limited() => {
+                let before = self.current;
+                self.rate_limited() > before
+            }
+            _ => false,
+        }
+    }
+
+    /// Feed a measured offset to an adaptive pacer; true when the interval
+    /// changed. Fixed pacers ignore it.
+    pub fn observe_offset(&mut self, offset_ms: f64) -> bool {
+        let Some(state) = self.adaptive.as_mut() else {
+            return false;
+        };
+        let Some(last) = state.last_offset_ms.replace(offset_ms) else {
+            return false;
+        };
+        let step = (offset_ms - last).abs();
+        let jitter = state.jitter_ms.unwrap_or(step);
+        if step <= PGATE * jitter {
+            state.score += 1;
+        } else {
+            state.score -= ADAPT_LIMIT / 2;
+        }
+        // Exponential average of the steps, weight 1/4 as in RFC 5905.
+        state.jitter_ms = Some((jitter.powi(2) + (step.powi(2) - jitter.powi(2)) / 4.0).sqrt());
+
+        let before = self.current;
+        if state.score >= ADAPT_LIMIT {
+            state.score = 0;
+            self.current = (self.current * 2).min(AUTO_MAX_INTERVAL.max(self.floor));
+        } else if state.score <= -ADAPT_LIMIT {
+            state.score = 0;
+            self.current = (self.current / 2).max(self.floor);
+        }
+        self.current != before
+    }
+}
+
+/// A number in `[0, 1)`, random enough to spread schedules apart.
+pub(crate) fn random_unit() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut h = std::collections::hash_map::RandomState::new().build_hasher();
+    h.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos()),
+    );
+    (h.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_names_are_recognised() {
+        assert!(is_pool("pool.ntp.org"));
+        assert!(is_pool("0.fr.pool.ntp.org"));
+        assert!(is_pool("ntp://2.Pool.NTP.org.:123"));
+        assert!(!is_pool("time.google.com"));
+        assert!(!is_pool("pool.ntp.org.example"));
+    }
+
+    #[test]
+    fn rate_limits_double_the_interval() {
+        let mut pacer = Pacer::new(Duration::from_millis(200), Duration::from_secs(1));
+        assert_eq!(pacer.interval(), Duration::from_secs(1));
+        assert!(!pacer.observe(&Ok::<(), RkikError>(())));
+        let kod = Err::<(), _>(RkikError::RateLimited("RATE".into()));
+        assert!(pacer.observe(&kod));
+        assert_eq!(pacer.interval(), Duration::from_secs(2));
+        assert!(pacer.observe(&kod));
+        assert_eq!(pacer.interval(), Duration::from_secs(4));
+        // Other errors leave the pace alone.
+        assert!(!pacer.observe(&Err::<(), _>(RkikError::Timeout)));
+        assert!(pacer.is_backed_off());
+        for _ in 0..20 {
+            pacer.observe(&kod);
+        }
+        assert_eq!(pacer.interval(), MAX_BACKOFF);
+        assert!(!pacer.observe(&kod));
+        assert_eq!(pacer.base(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn adaptive_interval_follows_the_jitter() {
+        let mut pacer = Pacer::adaptive(Duration::ZERO);
+        assert_eq!(pacer.interval(), AUTO_MIN_INTERVAL);
+        // Steady offsets: one doubling per eight stable samples.
+        let mut changes = 0;
+        for i in 0..=24 {
+            if pacer.observe_offset(1.0 + (i % 2) as f64 * 0.01) {
+                changes += 1;
+            }
+        }
+        assert_eq!(changes, 3);
+        assert_eq!(pacer.interval(), Duration::from_secs(8));
+
+        // The offset starts moving: two steps beyond the gate halve it.
+        assert!(!pacer.observe_offset(5.0));
+        assert!(pacer.observe_offset(-5.0));
+        assert_eq!(pacer.interval(), Duration::from_secs(4));
+
+        // Fixed pacers ignore offsets.
+        let mut fixed = Pacer::new(Duration::from_secs(1), Duration::ZERO);
+        for _ in 0..20 {
+            assert!(!fixed.observe_offset(0.0));
+        }
+    }
+
+    #[test]
+    fn adaptation_stays_above_the_backoff() {
+        let mut pacer = Pacer::adaptive(Duration::from_secs(2));
+        assert_eq!(pacer.interval(), Duration::from_secs(2));
+        pacer.rate_limited();
+        assert_eq!(pacer.interval(), Duration::from_secs(4));
+        for offset in [0.0, 10.0, -10.0, 30.0, -30.0, 80.0, -80.0] {
+            pacer.observe_offset(offset);
+        }
+        assert_eq!(pacer.interval(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn jitter_only_lengthens_pauses() {
+        let pacer = Pacer::new(Duration::from_secs(10), Duration::ZERO).with_jitter(0.1);
+        let pauses: Vec<Duration> = (0..50).map(|_| pacer.pause()).collect();
+        assert!(
+            pauses
+                .iter()
+                .all(|p| (Duration::from_secs(10)..Duration::from_secs(11)).contains(p))
+        );
+        assert!(pauses.iter().any(|p| *p != pauses[0]));
+        let steady = Pacer::new(Duration::from_secs(10), Duration::ZERO);
+        assert_eq!(steady.pause(), Duration::from_secs(10));
+    }
+}
@@ -1,14 +1,19 @@
-use chrono::{DateTime, Local, Utc};
-use std::net::IpAddr;
+use chrono::{DateTime, Local};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "nts")]
 use crate::adapters::nts_client;
-use crate::adapters::{ntp_client, resolver};
-use crate::domain::ntp::{ProbeResult, Target};
+use crate::adapters::resolver;
+use crate::adapters::transport::{NtpTransport, UdpTransport};
+use crate::domain::ntp::{Leap, ProbeResult, Target, Timing};
 use crate::error::RkikError;
-use rsntp::ReferenceIdentifier;
+use crate::services::events::{ProbeEvent, ProbeObserver};
+use crate::services::mock;
+#[cfg(feature = "json")]
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
 use tracing::instrument;
 
 /// Parsed view of a target string.
@@ -17,6 +22,8 @@ pub struct ParsedTarget<'a> {
     pub host: &'a str,
     pub port: Option<u16>,
     pub is_ipv6_literal: bool,
+    /// Zone (scope) of a link-local IPv6 literal: `eth0` in `fe80::1%eth0`.
+    pub zone: Option<&'a str>,
 }
 /// Strict port parsing with range check (1..=65535).
 fn parse_port_strict(s: &str) -> Result<u16, RkikError> {
@@ -45,6 +52,8 @@ fn colon_count(s: &str) -> usize {
 /// - "[2001:db8::1]"
 /// - "[2001:db8::1]:123"
 /// - "2001:db8::1"              (bare IPv6, **no** port allowed)
+/// - "fe80::1%eth0"             (IPv6 with zone, bare or bracketed)
+/// - "[fe80::1%25eth0]:123"     (zone percent-encoded as in RFC 6874 URIs)
 ///
 /// Rules:
 /// - If it starts with '[', it must be bracketed IPv6, optional ":port".
@@ -64,7 +73,7 @@ pub fn parse_target(input: &str) -> Result<ParsedTarget<'_>, RkikError> {
         let Some(bracket_pos) = rest.find(']') else {
             return Err(RkikError::Other(format!("missing closing ']' in '{s}'")));
         };
-        let host = &rest[..bracket_pos]; // inside brackets (IPv6 literal)
+        let (host, zone) = split_zone(&rest[..bracket_pos], true)?; // inside brackets (IPv6 literal)
         let tail = &rest[bracket_pos + 1..]; // after ']'
 
         // Optional ":port" after the bracket
@@ -82,6 +91,7 @@ pub fn parse_target(input: &str) -> Result<ParsedTarget<'_>, RkikError> {
             host,
             port,
             is_ipv6_literal: true,
+            zone,
         });
     }
 
@@ -92,6 +102,7 @@ pub fn parse_target(input: &str) -> Result<ParsedTarget<'_>, RkikError> {
             host: s,
             port: None,
             is_ipv6_literal: false,
+            zone: None,
         }),
 
         // Exactly one colon: "host:port" (hostname or IPv4)
@@ -109,57 +120,438 @@ pub fn parse_target(input: &str) -> Result<ParsedTarget<'_>, RkikError> {
                 host,
                 port: Some(port),
                 is_ipv6_literal: false,
+                zone: None,
             })
         }
 
-        _ => Ok(ParsedTarget {
-            host: s,
-            port: None,
-            is_ipv6_literal: true,
-        }),
+        _ => {
+            let (host, zone) = split_zone(s, false)?;
+            Ok(ParsedTarget {
+                host,
+                port: None,
+                is_ipv6_literal: true,
+                zone,
+            })
+        }
     }
 }
 
-fn format_reference_id(reference_id: &ReferenceIdentifier) -> String {
-    reference_id.to_string()
+/// Split `fe80::1%eth0` into the address and its zone.
+///
+/// Inside brackets the `%` may be percent-encoded (`%25eth0`, RFC 6874).
+/// A zone is only accepted after a valid IPv6 address.
+fn split_zone(host: &str, bracketed: bool) -> Result<(&str, Option<&str>), RkikError> {
+    let Some((addr, zone)) = host.split_once('%') else {
+        return Ok((host, None));
+    };
+    let zone = match zone.strip_prefix("25") {
+        Some(unescaped) if bracketed && !unescaped.is_empty() => unescaped,
+        _ => zone,
+    };
+    if zone.is_empty() {
+        return Err(RkikError::Other(format!(
+            "empty zone identifier in '{host}'"
+        )));
+    }
+    if addr.parse::<Ipv6Addr>().is_err() {
+        return Err(RkikError::Other(format!(
+            "zone identifiers are only valid on IPv6 addresses: '{host}'"
+        )));
+    }
+    Ok((addr, Some(zone)))
+}
+
+/// Protocol requested by a `scheme://` target prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "lowercase"))]
+pub enum Scheme {
+    Ntp,
+    Nts,
+    Ptp,
+    /// Synthetic results without network IO (see [`services::mock`](super::mock)).
+    Mock,
+}
+
+impl Scheme {
+    /// URI scheme name (`ntp`, `nts`, `ptp` or `mock`).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Ntp => "ntp",
+            Scheme::Nts => "nts",
+            Scheme::Ptp => "ptp",
+            Scheme::Mock => "mock",
+        }
+    }
+}
+
+/// Split an optional `ntp://`, `nts://`, `ptp://` or `mock://` prefix off a target.
+///
+/// Lets a single compare run mix protocols (`ntp://a nts://b`). Query
+/// options (`?domain=24`, `?offset=5ms`) are only meaningful for PTP and
+/// mock targets and rejected otherwise.
+pub fn split_scheme(input: &str) -> Result<(Option<Scheme>, &str), RkikError> {
+    let Some((scheme, rest)) = input.trim().split_once("://") else {
+        return Ok((None, input));
+    };
+    let scheme = match scheme.to_ascii_lowercase().as_str() {
+        "ntp" => Scheme::Ntp,
+        "nts" => Scheme::Nts,
+        "ptp" => Scheme::Ptp,
+        "mock" => Scheme::Mock,
+        other => {
+            return Err(RkikError::Other(format!(
+                "unsupported scheme '{other}://' (use ntp://, nts://, ptp:// or mock://)"
+            )));
+        }
+    };
+    let rest = rest.trim_end_matches('/');
+    if !matches!(scheme, Scheme::Ptp | Scheme::Mock) && rest.contains('?') {
+        return Err(RkikError::Other(format!(
+            "query options are not supported for {}://",
+            input.split_once("://").map_or("", |(s, _)| s)
+        )));
+    }
+    Ok((Some(scheme), rest))
+}
+
+/// `target` on another port, keeping its `scheme://` prefix and zone:
+/// `time.example:1123`, `ntp://[fe80::1%25eth0]:1123`.
+///
+/// Probes a device on each port it may answer on. `mock://` targets have
+/// no port.
+pub fn with_port(target: &str, port: u16) -> Result<String, RkikError> {
+    let (scheme, rest) = split_scheme(target)?;
+    if scheme == Some(Scheme::Mock) {
+        return Err(RkikError::Other(format!("'{target}' has no port")));
+    }
+    let parsed = parse_target(rest)?;
+    let host = match parsed.zone {
+        Some(zone) => format!("[{}%25{zone}]", parsed.host),
+        None if parsed.is_ipv6_literal => format!("[{}]", parsed.host),
+        None => parsed.host.to_string(),
+    };
+    Ok(match scheme {
+        Some(scheme) => format!("{}://{host}:{port}", scheme.as_str()),
+        None => format!("{host}:{port}"),
+    })
+}
+
+/// Settings shared by [`query`] and [`compare`](super::compare::compare).
+///
+/// Start from [`QueryOptions::new`] (or `default()`) and override what differs:
+///
+/// ```
+/// use std::time::Duration;
+/// use rkik::QueryOptions;
+///
+/// let options = QueryOptions::new()
+///     .timeout(Duration::from_secs(2))
+///     .nts(true)
+///     .retries(1);
+/// assert_eq!(options.nts_port, 4460);
+/// ```
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct QueryOptions {
+    /// Time allowed for each attempt (default 5 s).
+    pub timeout: Duration,
+    /// Resolve names to IPv6 addresses only (default: prefer IPv4).
+    pub ipv6_only: bool,
+    /// Authenticate with NTS, unless the target has an `ntp://` prefix.
+    pub nts: bool,
+    /// NTS-KE port (default 4460).
+    pub nts_port: u16,
+    /// NTP port used when the target does not name one (default 123).
+    pub port: u16,
+    /// Further attempts after a [retryable](RkikError::is_retryable) failure (default 0).
+    pub retries: u32,
+    /// Abandon in-flight probes with [`RkikError::Cancelled`] once this is cancelled.
+    pub cancel: Option<CancellationToken>,
+    /// Told about each step of every query (default none).
+    pub observer: Option<ProbeObserver>,
+    /// Most targets a compare round queries at the same time (default 32).
+    pub concurrency: usize,
+    /// Minimum delay between two launches of a compare round (default none).
+    pub stagger: Duration,
+    /// Send all plain NTP requests of a compare round from one socket
+    /// ([`SharedSocket`](crate::adapters::demux::SharedSocket)) instead of
+    /// one per server (default false).
+    pub shared_socket: bool,
+    /// Answers a compare round needs to succeed, or every target when
+    /// fewer are given (default 2).
+    pub min_answers: usize,
+    /// Fail with [`RkikError::Unsynchronized`] when the server is not
+    /// synchronised itself, or last heard from its reference longer ago
+    /// than this (default: accept any answer). NTS answers are not checked.
+    pub require_sync: Option<Duration>,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            ipv6_only: false,
+            nts: false,
+            nts_port: 4460,
+            port: 123,
+            retries: 0,
+            cancel: None,
+            observer: None,
+            concurrency: 32,
+            stagger: Duration::ZERO,
+            shared_socket: false,
+            min_answers: 2,
+            require_sync: None,
+        }
+    }
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn ipv6_only(mut self, ipv6_only: bool) -> Self {
+        self.ipv6_only = ipv6_only;
+        self
+    }
+
+    pub fn nts(mut self, nts: bool) -> Self {
+        self.nts = nts;
+        self
+    }
+
+    pub fn nts_port(mut self, port: u16) -> Self {
+        self.nts_port = port;
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub fn observer(mut self, observer: ProbeObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// At least 1.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn stagger(mut self, stagger: Duration) -> Self {
+        self.stagger = stagger;
+        self
+    }
+
+    pub fn shared_socket(mut self, shared_socket: bool) -> Self {
+        self.shared_socket = shared_socket;
+        self
+    }
+
+    /// At least 1.
+    pub fn min_answers(mut self, min_answers: usize) -> Self {
+        self.min_answers = min_answers.max(1);
+        self
+    }
+
+    /// Refuse servers that are free-running or whose reference is older
+    /// than `max_reference_age`.
+    pub fn require_sync(mut self, max_reference_age: Duration) -> Self {
+        self.require_sync = Some(max_reference_age);
+        self
+    }
+
+    /// Whether the [`cancel`](Self::cancel) token has fired.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|t| t.is_cancelled())
+    }
+
+    /// Pass the event built by `event` to the [`observer`](Self::observer),
+    /// if any.
+    fn notify(&self, event: impl FnOnce() -> ProbeEvent) {
+        if let Some(observer) = &self.observer {
+            observer.notify(&event());
+        }
+    }
+}
+
+/// Query a single target and return a [`ProbeResult`].
+///
+/// `target` is a host name or IP address in any form accepted by
+/// [`parse_target`], optionally prefixed with `ntp://` or `nts://` to
+/// override [`QueryOptions::nts`] for this target, or a `mock://` target
+/// answered from [`services::mock`](super::mock) without network IO. Errors that are
+/// [retryable](RkikError::is_retryable) are retried
+/// [`QueryOptions::retries`] times; others are returned at once.
+/// Cancelling [`QueryOptions::cancel`] ends the query right away with
+/// [`RkikError::Cancelled`]; dropping the future does too.
+#[instrument]
+pub async fn query(target: &str, options: &QueryOptions) -> Result<ProbeResult, RkikError> {
+    query_via(target, options, &UdpTransport).await
+}
+
+/// Like [`query`], with plain NTP exchanges going through `transport`
+/// (NTS keeps its own client).
+#[instrument(skip(transport))]
+pub async fn query_via(
+    target: &str,
+    options: &QueryOptions,
+    transport: &impl NtpTransport,
+) -> Result<ProbeResult, RkikError> {
+    let attempts = async {
+        let mut attempt = 0;
+        loop {
+            options.notify(|| ProbeEvent::Started {
+                target: target.to_string(),
+                attempt,
+            });
+            match query_once(target, options, transport).await {
+                Err(e) if attempt < options.retries && e.is_retryable() => {
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    };
+    let res = match &options.cancel {
+        None => attempts.await,
+        Some(token) => tokio::select! {
+            biased;
+            _ = token.cancelled() => Err(RkikError::Cancelled.with_target(target)),
+            res = attempts => res,
+        },
+    };
+    options.notify(|| ProbeEvent::finished(target, &res));
+    res
 }
 
 /// Query a single target and return a [`ProbeResult`].
 ///
 /// # Arguments
 ///
-/// * `target` - The target server (hostname or IP address)
+/// * `target` - The target server (hostname or IP address), optionally prefixed
+///   with `ntp://` or `nts://` to override `use_nts` for this target
 /// * `ipv6` - Whether to use IPv6
 /// * `timeout` - Timeout duration
 /// * `use_nts` - Whether to use NTS (Network Time Security) authentication
 /// * `nts_port` - NTS-KE port number (typically 4460)
-#[instrument(skip(timeout))]
+#[deprecated(note = "use `query` with `QueryOptions`")]
 pub async fn query_one(
     target: &str,
-    mut ipv6: bool,
+    ipv6: bool,
     timeout: Duration,
     use_nts: bool,
     nts_port: u16,
 ) -> Result<ProbeResult, RkikError> {
+    let options = QueryOptions {
+        timeout,
+        ipv6_only: ipv6,
+        nts: use_nts,
+        nts_port,
+        ..QueryOptions::default()
+    };
+    query(target, &options).await
+}
+
+async fn query_once(
+    target: &str,
+    options: &QueryOptions,
+    transport: &impl NtpTransport,
+) -> Result<ProbeResult, RkikError> {
+    let timeout = options.timeout;
+    let mut ipv6 = options.ipv6_only;
+    let (scheme, host) = split_scheme(target).map_err(|e| e.with_target(target))?;
+    let use_nts = match scheme {
+        Some(Scheme::Ntp) => false,
+        Some(Scheme::Nts) => true,
+        Some(Scheme::Ptp) => {
+            return Err(
+                RkikError::Other("PTP is not supported by this build".into()).with_target(target),
+            );
+        }
+        Some(Scheme::Mock) => {
+            return mock::probe(target, host).and_then(|r| synchronised(r, options));
+        }
+        None => options.nts,
+    };
+
     // NTS branch
     #[cfg(feature = "nts")]
     if use_nts {
-        let parsed = parse_target(target).map_err(|e| e.with_target(target))?;
+        let parsed = parse_target(host).map_err(|e| e.with_target(target))?;
+        if parsed.zone.is_some() {
+            return Err(RkikError::Other(
+                "IPv6 zone identifiers are not supported with NTS".into(),
+            )
+            .with_target(target));
+        }
+        // In an nts:// URI the port is the NTS-KE port.
+        let nts_port = match scheme {
+            Some(Scheme::Nts) => parsed.port.unwrap_or(options.nts_port),
+            _ => options.nts_port,
+        };
+        // Resolve IP for display purposes (and, with DNSSEC required, to
+        // reject the name before the NTS-KE handshake)
+        let resolving = Instant::now();
+        let resolved = resolver::lookup(parsed.host, ipv6)
+            .await
+            .map_err(|e| e.with_target(target))?;
+        // The NTS client resolves the name again itself and cannot be
+        // pointed at an address, so its handshake would go to one the
+        // validation never saw.
+        if resolved.dnssec {
+            return Err(RkikError::Other(format!(
+                "DNSSEC cannot be enforced for NTS: the NTS-KE client resolves '{}' on its own",
+                parsed.host
+            ))
+            .with_target(target));
+        }
+        options.notify(|| ProbeEvent::DnsResolved {
+            target: target.to_string(),
+            ip: resolved.ip,
+            dns_ms: resolving.elapsed().as_secs_f64() * 1000.0,
+        });
+        let ke_addr = SocketAddr::new(resolved.ip, nts_port);
+        options.notify(|| ProbeEvent::Sent {
+            target: target.to_string(),
+            addr: ke_addr,
+        });
         let nts_result = nts_client::query_nts(parsed.host, Some(nts_port), timeout)
             .await
             .map_err(|e| e.with_target(target))?;
-
-        // Resolve IP for display purposes
-        let ip: IpAddr =
-            resolver::resolve_ip(parsed.host, ipv6).map_err(|e| e.with_target(target))?;
+        options.notify(|| ProbeEvent::Received {
+            target: target.to_string(),
+            addr: ke_addr,
+            rtt_ms: nts_result.rtt_ms,
+        });
         let local: DateTime<Local> = DateTime::from(nts_result.network_time);
         let timestamp = nts_result.network_time.timestamp();
 
         return Ok(ProbeResult {
             target: Target {
-                name: target.to_string(),
-                ip,
-                port: parsed.port.unwrap_or(123),
+                resolution: resolved.source,
+                addresses: resolved.addresses,
+                ..Target::new(target, resolved.ip, parsed.port.unwrap_or(options.port))
             },
             offset_ms: nts_result.offset_ms,
             rtt_ms: nts_result.rtt_ms,
@@ -169,6 +561,15 @@ pub async fn query_one(
             local,
             timestamp,
             authenticated: nts_result.authenticated,
+            dnssec: resolved.dnssec,
+            leap: Default::default(), // NTS library doesn't expose the leap indicator
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
             #[cfg(feature = "nts")]
             nts_ke_data: nts_result.nts_ke_data,
             #[cfg(feature = "nts")]
@@ -185,47 +586,269 @@ pub async fn query_one(
         .with_target(target));
     }
 
-    let parsed = parse_target(target).map_err(|e| e.with_target(target))?;
+    let parsed = parse_target(host).map_err(|e| e.with_target(target))?;
 
-    let ip: IpAddr = resolver::resolve_ip(parsed.host, ipv6).map_err(|e| e.with_target(target))?;
+    let resolving = Instant::now();
+    let resolved = resolver::lookup(parsed.host, ipv6)
+        .await
+        .map_err(|e| e.with_target(target))?;
+    let dns_ms = resolving.elapsed().as_secs_f64() * 1000.0;
+    let ip: IpAddr = resolved.ip;
+    options.notify(|| ProbeEvent::DnsResolved {
+        target: target.to_string(),
+        ip,
+        dns_ms,
+    });
 
-    let port: u16 = parsed.port.unwrap_or(123);
+    let port: u16 = parsed.port.unwrap_or(options.port);
     if parsed.is_ipv6_literal {
         ipv6 = true;
     }
-    let res = ntp_client::query(ip, ipv6, timeout, port)
+    let addr = match (ip, parsed.zone) {
+        (IpAddr::V6(v6), Some(zone)) => {
+            let scope_id = resolver::scope_id(zone).map_err(|e| e.with_target(target))?;
+            SocketAddr::V6(SocketAddrV6::new(v6, port, 0, scope_id))
+        }
+        _ => SocketAddr::new(ip, port),
+    };
+    options.notify(|| ProbeEvent::Sent {
+        target: target.to_string(),
+        addr,
+    });
+    let sample = transport
+        .query(addr, ipv6, timeout)
         .await
         .map_err(|e| e.with_target(target))?;
-
-    let utc: DateTime<Utc> = match res.datetime().try_into() {
-        Ok(dt) => dt,
-        Err(e) => return Err(RkikError::Other(e.to_string()).with_target(target)),
-    };
+    options.notify(|| ProbeEvent::Received {
+        target: target.to_string(),
+        addr,
+        rtt_ms: sample.rtt_ms,
+    });
+    let utc = sample.utc;
     let local: DateTime<Local> = DateTime::from(utc);
 
-    let offset_ms = res.clock_offset().as_secs_f64() * 1000.0;
-    let rtt_ms = res.round_trip_delay().as_secs_f64() * 1000.0;
-    let stratum = res.stratum();
-    let ref_id = format_reference_id(res.reference_identifier());
-    let timestamp = utc.timestamp();
-
-    Ok(ProbeResult {
+    let result = ProbeResult {
         target: Target {
-            name: target.to_string(),
-            ip,
-            port,
+            resolution: resolved.source,
+            addresses: resolved.addresses,
+            ..Target::new(target, ip, port)
         },
-        offset_ms,
-        rtt_ms,
-        stratum,
-        ref_id,
+        offset_ms: sample.offset_ms,
+        rtt_ms: sample.rtt_ms,
+        stratum: sample.stratum,
+        ref_id: sample.ref_id,
         utc,
         local,
-        timestamp,
+        timestamp: utc.timestamp(),
         authenticated: false, // Standard NTP is not authenticated
+        dnssec: resolved.dnssec,
+        leap: sample.leap,
+        health: sample.health,
+        pause_ms: None,
+        origin: None,
+        timing: sample.timing.map(|t| Timing { dns_ms, ..t }),
+        uncertainty_ms: sample.uncertainty_ms,
+        clock_step_ms: None,
+        reference_age_s: sample.reference_age_s,
         #[cfg(feature = "nts")]
         nts_ke_data: None, // No NTS-KE data for standard NTP queries
         #[cfg(feature = "nts")]
         nts_validation: None, // No NTS validation for standard NTP queries
-    })
+    };
+    synchronised(result, options)
+}
+
+/// `result`, unless [`QueryOptions::require_sync`] is set and the server
+/// is not synchronised: leap indicator 3 (alarm), stratum 0 or 16 and
+/// above, or a reference older than the limit.
+fn synchronised(result: ProbeResult, options: &QueryOptions) -> Result<ProbeResult, RkikError> {
+    let Some(max_age) = options.require_sync else {
+        return Ok(result);
+    };
+    let problem = if result.leap == Leap::Unsynchronised {
+        Some("leap indicator 3 (alarm)".to_string())
+    } else if result.stratum == 0 || result.stratum >= 16 {
+        Some(format!("stratum {}", result.stratum))
+    } else {
+        result
+            .reference_age_s
+            .filter(|age| *age > max_age.as_secs_f64())
+            .map(|age| format!("reference last updated {age:.0} s ago"))
+    };
+    match problem {
+        Some(problem) => Err(RkikError::Unsynchronized(problem).with_target(&result.target.name)),
+        None => Ok(result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_scheme_recognizes_protocol_prefixes() {
+        assert_eq!(
+            split_scheme("time.example").unwrap(),
+            (None, "time.example")
+        );
+        assert_eq!(
+            split_scheme("nts://time.example:4461").unwrap(),
+            (Some(Scheme::Nts), "time.example:4461")
+        );
+        assert_eq!(
+            split_scheme("NTP://[2001:db8::1]:123/").unwrap(),
+            (Some(Scheme::Ntp), "[2001:db8::1]:123")
+        );
+        assert_eq!(
+            split_scheme("ptp://gm.example?domain=24").unwrap(),
+            (Some(Scheme::Ptp), "gm.example?domain=24")
+        );
+        assert!(split_scheme("ntp://time.example?x=1").is_err());
+        assert!(split_scheme("http://time.example").is_err());
+    }
+
+    #[test]
+    fn query_options_builder_overrides_defaults() {
+        let options = QueryOptions::new()
+            .timeout(Duration::from_millis(1500))
+            .ipv6_only(true)
+            .port(1123)
+            .retries(2);
+        assert_eq!(options.timeout, Duration::from_millis(1500));
+        assert!(options.ipv6_only);
+        assert_eq!((options.port, options.retries), (1123, 2));
+        assert_eq!((options.nts, options.nts_port), (false, 4460));
+        assert!(options.cancel.is_none());
+    }
+
+    #[tokio::test]
+    async fn query_retries_network_failures() {
+        use crate::adapters::transport::{MockTransport, NtpSample};
+        use crate::domain::ntp::Leap;
+
+        let addr: SocketAddr = "192.0.2.1:1123".parse().unwrap();
+        let sample = NtpSample {
+            offset_ms: 1.5,
+            rtt_ms: 0.2,
+            stratum: 1,
+            ref_id: "GPS".into(),
+            leap: Leap::Normal,
+            health: Default::default(),
+            utc: DateTime::UNIX_EPOCH,
+            timing: None,
+            uncertainty_ms: None,
+            reference_age_s: None,
+        };
+        let mock = MockTransport::new().with_sample(addr, sample);
+        mock.push(addr, Err(RkikError::Timeout));
+
+        let options = QueryOptions::new().port(1123);
+        let err = query_via("192.0.2.1", &options, &mock).await.unwrap_err();
+        assert!(err.is_network_timeout());
+
+        let res = query_via("192.0.2.1", &options.retries(1), &mock)
+            .await
+            .unwrap();
+        assert_eq!((res.offset_ms, res.stratum), (1.5, 1));
+        assert_eq!(res.target.port, 1123);
+        assert_eq!(mock.requests(), [addr, addr]);
+    }
+
+    #[tokio::test]
+    async fn require_sync_refuses_free_running_servers() {
+        use crate::adapters::transport::{MockTransport, NtpSample};
+
+        let addr: SocketAddr = "192.0.2.1:123".parse().unwrap();
+        let sample = |stratum, reference_age_s| NtpSample {
+            offset_ms: 0.0,
+            rtt_ms: 1.0,
+            stratum,
+            ref_id: "GPS".into(),
+            leap: Leap::Normal,
+            health: Default::default(),
+            utc: DateTime::UNIX_EPOCH,
+            timing: None,
+            uncertainty_ms: None,
+            reference_age_s: Some(reference_age_s),
+        };
+        let mock = MockTransport::new();
+        mock.push(addr, Ok(sample(2, 60.0)));
+        mock.push(addr, Ok(sample(16, 60.0)));
+        mock.push(addr, Ok(sample(2, 7200.0)));
+
+        let options = QueryOptions::new().require_sync(Duration::from_secs(3600));
+        assert!(query_via("192.0.2.1", &options, &mock).await.is_ok());
+        for expected in ["stratum 16", "reference last updated 7200 s ago"] {
+            let err = query_via("192.0.2.1", &options, &mock).await.unwrap_err();
+            assert_eq!((err.kind(), err.exit_code()), ("unsynchronized", 6));
+            assert!(err.message().ends_with(expected), "{err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_token_abandons_a_probe_in_flight() {
+        use crate::adapters::transport::NtpSample;
+
+        struct Silent;
+        impl NtpTransport for Silent {
+            async fn query(
+                &self,
+                _: SocketAddr,
+                _: bool,
+                _: Duration,
+            ) -> Result<NtpSample, RkikError> {
+                std::future::pending().await
+            }
+        }
+
+        let token = CancellationToken::new();
+        let options = QueryOptions::new()
+            .timeout(Duration::from_secs(60))
+            .cancel_on(token.clone());
+        let canceller = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            token.cancel();
+        });
+        let started = Instant::now();
+        let err = query_via("192.0.2.1", &options, &Silent).await.unwrap_err();
+        assert!(err.is_cancelled());
+        assert_eq!(err.target(), Some("192.0.2.1"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        canceller.await.unwrap();
+    }
+
+    #[test]
+    fn parse_target_splits_ipv6_zones() {
+        let bare = parse_target("fe80::1%eth0").unwrap();
+        assert_eq!(
+            (bare.host, bare.port, bare.zone),
+            ("fe80::1", None, Some("eth0"))
+        );
+        let bracketed = parse_target("[fe80::1%25eth0]:1123").unwrap();
+        assert_eq!(
+            (bracketed.host, bracketed.port, bracketed.zone),
+            ("fe80::1", Some(1123), Some("eth0"))
+        );
+        assert_eq!(parse_target("[fe80::1%3]").unwrap().zone, Some("3"));
+        assert_eq!(parse_target("[2001:db8::1]").unwrap().zone, None);
+        assert!(parse_target("fe80::1%").is_err());
+        assert!(parse_target("[host.example%eth0]").is_err());
+    }
+
+    #[test]
+    fn with_port_replaces_the_port() {
+        assert_eq!(
+            with_port("time.example", 1123).unwrap(),
+            "time.example:1123"
+        );
+        assert_eq!(with_port("192.0.2.1:123", 1123).unwrap(), "192.0.2.1:1123");
+        assert_eq!(
+            with_port("2001:db8::1", 1123).unwrap(),
+            "[2001:db8::1]:1123"
+        );
+        let zoned = with_port("ntp://fe80::1%eth0", 1123).unwrap();
+        assert_eq!(zoned, "ntp://[fe80::1%25eth0]:1123");
+        assert_eq!(parse_target(&zoned[6..]).unwrap().zone, Some("eth0"));
+        assert!(with_port("mock://lab?offset=5ms", 1123).is_err());
+    }
 }
@@ -0,0 +1,227 @@
+//! Pick the servers worth configuring out of a compare run or a survey.
+//!
+//! Every candidate gets a score in milliseconds, lower being better: how
+//! far its time can be from the truth as seen from here. It adds up
+//!
+//! * half the round trip, the bound on the asymmetry error;
+//! * four times the jitter ([`JITTER_WEIGHT`]), as a client's selection
+//!   would count it against a noisy source;
+//! * the distance of its offset from the consensus, the median offset of
+//!   all candidates, so a server that disagrees with the others sinks;
+//! * [`STRATUM_PENALTY_MS`] per stratum below the first.
+//!
+//! [`server_block`] turns the best candidates into `server` lines for
+//! chrony.conf or ntp.conf.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use crate::services::query::{Scheme, parse_target, split_scheme};
+use crate::services::survey::SurveyMember;
+use crate::stats::Stats;
+
+/// Weight of the jitter in the score.
+pub const JITTER_WEIGHT: f64 = 4.0;
+/// Added to the score per stratum below the first (ms).
+pub const STRATUM_PENALTY_MS: f64 = 1.0;
+/// Servers recommended when no count is given, the minimum chrony and
+/// ntpd need to outvote one falseticker.
+pub const DEFAULT_COUNT: usize = 4;
+
+/// A server that answered, summarised over its probes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// Target as given (`host`, `host:port`, `nts://host`).
+    pub target: String,
+    pub offset_ms: f64,
+    pub rtt_ms: f64,
+    /// `None` after a single probe.
+    pub jitter_ms: Option<f64>,
+    /// 0 when unknown (NTS probes do not report it).
+    pub stratum: u8,
+    /// Whether the probes were authenticated with NTS.
+    pub nts: bool,
+}
+
+impl Candidate {
+    /// From the statistics of a compare run: median offset and round trip.
+    pub fn from_stats(target: &str, stats: &Stats, stratum: u8, nts: bool) -> Self {
+        Self {
+            target: target.to_string(),
+            offset_ms: stats.offset_median,
+            rtt_ms: stats.rtt_median,
+            jitter_ms: stats.jitter,
+            stratum,
+            nts,
+        }
+    }
+
+    /// From a survey member; `None` when it never answered.
+    pub fn from_member(member: &SurveyMember) -> Option<Self> {
+        Some(Self {
+            target: member.ip.to_string(),
+            offset_ms: member.offset_ms?,
+            rtt_ms: member.rtt_ms?,
+            jitter_ms: member.jitter_ms,
+            stratum: member.stratum.unwrap_or(0),
+            nts: false,
+        })
+    }
+}
+
+/// A candidate and how it scored, in [`rank`] order.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Recommendation {
+    pub target: String,
+    /// Lower is better (ms).
+    pub score: f64,
+    pub offset_ms: f64,
+    /// Offset minus the consensus offset (ms).
+    pub deviation_ms: f64,
+    pub rtt_ms: f64,
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub jitter_ms: Option<f64>,
+    pub stratum: u8,
+    pub nts: bool,
+}
+
+/// Score every usable candidate and sort them, best first.
+///
+/// Unsynchronised servers (stratum 16 and above) are left out.
+pub fn rank(candidates: &[Candidate]) -> Vec<Recommendation> {
+    let usable: Vec<&Candidate> = candidates.iter().filter(|c| c.stratum < 16).collect();
+    let consensus = median(usable.iter().map(|c| c.offset_ms).collect());
+    let mut ranked: Vec<Recommendation> = usable
+        .into_iter()
+        .map(|c| {
+            let deviation_ms = c.offset_ms - consensus;
+            let score = c.rtt_ms / 2.0
+                + JITTER_WEIGHT * c.jitter_ms.unwrap_or(0.0)
+                + deviation_ms.abs()
+                + STRATUM_PENALTY_MS * c.stratum.saturating_sub(1) as f64;
+            Recommendation {
+                target: c.target.clone(),
+                score,
+                offset_ms: c.offset_ms,
+                deviation_ms,
+                rtt_ms: c.rtt_ms,
+                jitter_ms: c.jitter_ms,
+                stratum: c.stratum,
+                nts: c.nts,
+            }
+        })
+        .collect();
+    ranked.sort_by(|a, b| a.score.total_cmp(&b.score));
+    ranked
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    if n.is_multiple_of(2) {
+        (values[n / 2 - 1] + values[n / 2]) / 2.0
+    } else {
+        values[n / 2]
+    }
+}
+
+/// Configuration file a [`server_block`] is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigDialect {
+    /// chrony.conf.
+    Chrony,
+    /// ntp.conf, for ntpd and NTPsec.
+    Ntp,
+}
+
+/// `server` lines for `recommendations`, with `iburst` for a quick first
+/// synchronisation. NTS servers get the `nts` option, which chrony and
+/// NTPsec understand; non-default ports are kept for chrony only. Mock
+/// and PTP targets are left out.
+pub fn server_block(recommendations: &[Recommendation], dialect: ConfigDialect) -> String {
+    let file = match dialect {
+        ConfigDialect::Chrony => "chrony.conf",
+        ConfigDialect::Ntp => "ntp.conf",
+    };
+    let mut out = format!("# {file}: servers recommended by rkik, best first\n");
+    for r in recommendations {
+        // Synthetic mock:// targets have nothing to configure.
+        let Ok((scheme @ (None | Some(Scheme::Ntp | Scheme::Nts)), rest)) = split_scheme(&r.target)
+        else {
+            continue;
+        };
+        let Ok(parsed) = parse_target(rest) else {
+            continue;
+        };
+        let nts = r.nts || scheme == Some(Scheme::Nts);
+        let mut line = format!("server {} iburst", parsed.host);
+        if nts {
+            line.push_str(" nts");
+        }
+        if dialect == ConfigDialect::Chrony
+            && let Some(port) = parsed.port
+        {
+            // In an nts:// target the port is the NTS-KE port.
+            match (nts, scheme) {
+                (true, Some(Scheme::Nts)) => line.push_str(&format!(" ntsport {port}")),
+                _ if port != 123 => line.push_str(&format!(" port {port}")),
+                _ => {}
+            }
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(target: &str, offset_ms: f64, rtt_ms: f64, stratum: u8) -> Candidate {
+        Candidate {
+            target: target.into(),
+            offset_ms,
+            rtt_ms,
+            jitter_ms: Some(0.1),
+            stratum,
+            nts: false,
+        }
+    }
+
+    #[test]
+    fn outliers_and_distant_servers_rank_last() {
+        let ranked = rank(&[
+            candidate("far", 0.5, 80.0, 1),
+            candidate("falseticker", 250.0, 2.0, 1),
+            candidate("near", 0.2, 2.0, 3),
+            candidate("close", 0.0, 4.0, 1),
+            candidate("unsynced", 0.0, 1.0, 16),
+        ]);
+        let order: Vec<&str> = ranked.iter().map(|r| r.target.as_str()).collect();
+        assert_eq!(order, ["close", "near", "far", "falseticker"]);
+        // Consensus: the median of the four usable offsets, 0.35 ms.
+        assert!((ranked[0].deviation_ms + 0.35).abs() < 1e-9);
+    }
+
+    #[test]
+    fn server_blocks_follow_the_dialect() {
+        let mut ranked = rank(&[
+            candidate("time.example", 0.0, 1.0, 1),
+            candidate("nts://nts.example:4461", 0.0, 2.0, 1),
+            candidate("192.0.2.1:1123", 0.0, 3.0, 1),
+        ]);
+        ranked[1].nts = true;
+        let chrony = server_block(&ranked, ConfigDialect::Chrony);
+        assert!(chrony.contains("server time.example iburst\n"));
+        assert!(chrony.contains("server nts.example iburst nts ntsport 4461\n"));
+        assert!(chrony.contains("server 192.0.2.1 iburst port 1123\n"));
+        let ntp = server_block(&ranked, ConfigDialect::Ntp);
+        assert!(ntp.starts_with("# ntp.conf"));
+        assert!(ntp.contains("server 192.0.2.1 iburst\n"));
+    }
+}
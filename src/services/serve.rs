@@ -0,0 +1,237 @@
+//! A small NTPv4 responder for labs and integration tests.
+//!
+//! It answers client requests from the local clock, shifted by a configured
+//! offset, with optional jitter and packet loss, so clients can be exercised
+//! against a known error without real infrastructure. It does not discipline
+//! its clock or follow upstream servers; never point production clients at it.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::net::UdpSocket;
+use tokio_util::sync::CancellationToken;
+
+use crate::adapters::ntp_packet::{MODE_CLIENT, MODE_SERVER, NtpPacket, NtpTimestamp};
+use crate::error::RkikError;
+use crate::services::pacing::random_unit;
+
+/// How the responder answers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServeOptions {
+    /// Added to the local clock in every reply, in milliseconds.
+    pub offset_ms: f64,
+    /// Each reply is shifted further by a uniform random amount in
+    /// `[-jitter_ms, +jitter_ms]`.
+    pub jitter_ms: f64,
+    /// Share of requests dropped without an answer, in `[0, 1]`.
+    pub loss: f64,
+    /// Stratum advertised in replies (default 2).
+    pub stratum: u8,
+    /// Leap indicator advertised in replies (default 0, no warning).
+    pub leap: u8,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        Self {
+            offset_ms: 0.0,
+            jitter_ms: 0.0,
+            loss: 0.0,
+            stratum: 2,
+            leap: 0,
+        }
+    }
+}
+
+impl ServeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn offset_ms(mut self, offset_ms: f64) -> Self {
+        self.offset_ms = offset_ms;
+        self
+    }
+
+    pub fn jitter_ms(mut self, jitter_ms: f64) -> Self {
+        self.jitter_ms = jitter_ms.abs();
+        self
+    }
+
+    pub fn loss(mut self, loss: f64) -> Self {
+        self.loss = loss.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn stratum(mut self, stratum: u8) -> Self {
+        self.stratum = stratum;
+        self
+    }
+
+    pub fn leap(mut self, leap: u8) -> Self {
+        self.leap = leap & 0x3;
+        self
+    }
+}
+
+/// What a [`serve`] run did, returned when it is cancelled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServeStats {
+    /// Valid client requests received.
+    pub requests: u64,
+    /// Requests answered.
+    pub answered: u64,
+    /// Requests dropped to simulate packet loss.
+    pub dropped: u64,
+    /// Datagrams that were not NTP client requests.
+    pub ignored: u64,
+}
+
+/// The reply to `request`, received at `receive` and sent at `transmit`
+/// (both already shifted by the simulated offset), or `None` when the
+/// datagram is not a client request.
+pub fn reply(
+    request: &[u8],
+    receive: DateTime<Utc>,
+    transmit: DateTime<Utc>,
+    options: &ServeOptions,
+) -> Option<NtpPacket> {
+    let request = NtpPacket::from_bytes(request).ok()?;
+    if request.mode != MODE_CLIENT || request.transmit.is_zero() {
+        return None;
+    }
+    // Stratum 1 names its pretend reference clock; above that the ref id is
+    // an upstream address, here the loopback one.
+    let ref_id = match options.stratum {
+        1 => *b"RKIK",
+        _ => [127, 0, 0, 1],
+    };
+    Some(NtpPacket {
+        leap: options.leap,
+        version: request.version,
+        mode: MODE_SERVER,
+        stratum: options.stratum,
+        poll: request.poll,
+        // About a microsecond, what a software clock can claim.
+        precision: -20,
+        // 1 ms to the reference, in NTP short format.
+        root_delay: 65_536 / 1000,
+        root_dispersion: 65_536 / 1000,
+        ref_id,
+        reference: NtpTimestamp::from_datetime(receive - TimeDelta::seconds(16)),
+        origin: request.transmit,
+        receive: NtpTimestamp::from_datetime(receive),
+        transmit: NtpTimestamp::from_datetime(transmit),
+    })
+}
+
+/// Answer NTP requests on `socket` until `cancel` fires.
+pub async fn serve(
+    socket: UdpSocket,
+    options: ServeOptions,
+    cancel: CancellationToken,
+) -> Result<ServeStats, RkikError> {
+    let mut stats = ServeStats::default();
+    let mut buf = [0u8; 1024];
+    loop {
+        let (len, from) = tokio::select! {
+            _ = cancel.cancelled() => return Ok(stats),
+            r = socket.recv_from(&mut buf) => match r {
+                Ok(r) => r,
+                // A previous reply bounced (ICMP port unreachable on some
+                // platforms); the socket itself is fine.
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => continue,
+                Err(e) => return Err(RkikError::Network(e.to_string())),
+            },
+        };
+        let shift = shift(&options);
+        let receive = Utc::now() + shift;
+        let Some(mut packet) = reply(&buf[..len], receive, receive, &options) else {
+            stats.ignored += 1;
+            tracing::trace!(%from, len, "not an NTP request");
+            continue;
+        };
+        stats.requests += 1;
+        if options.loss > 0.0 && random_unit() < options.loss {
+            stats.dropped += 1;
+            tracing::debug!(%from, "request dropped");
+            continue;
+        }
+        packet.transmit = NtpTimestamp::from_datetime(Utc::now() + shift);
+        match socket.send_to(&packet.to_bytes(), from).await {
+            Ok(_) => {
+                stats.answered += 1;
+                tracing::debug!(%from, shift_ms = shift.num_microseconds().unwrap_or(0) as f64 / 1000.0, "request answered");
+            }
+            Err(e) => tracing::debug!(%from, error = %e, "reply not sent"),
+        }
+    }
+}
+
+/// Offset plus this reply's jitter.
+fn shift(options: &ServeOptions) -> TimeDelta {
+    let jitter = if options.jitter_ms > 0.0 {
+        options.jitter_ms * (2.0 * random_unit() - 1.0)
+    } else {
+        0.0
+    };
+    let micros = ((options.offset_ms + jitter) * 1000.0).round() as i64;
+    TimeDelta::microseconds(micros)
+}
+
+/// Bind `addr` for [`serve`], with a readable error for the usual failures.
+pub async fn bind(addr: std::net::SocketAddr) -> Result<UdpSocket, RkikError> {
+    UdpSocket::bind(addr).await.map_err(|e| match e.kind() {
+        std::io::ErrorKind::PermissionDenied => RkikError::Other(format!(
+            "cannot bind {addr}: permission denied (ports below 1024 need privileges; try --port 1123)"
+        )),
+        _ => RkikError::Other(format!("cannot bind {addr}: {e}")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::query::{QueryOptions, query};
+
+    #[test]
+    fn replies_echo_the_request_and_ignore_other_modes() {
+        let now = Utc::now();
+        let request = NtpPacket::client(now);
+        let options = ServeOptions::new().stratum(1).leap(1);
+        let r = reply(&request.to_bytes(), now, now, &options).unwrap();
+        assert_eq!(r.mode, MODE_SERVER);
+        assert_eq!(r.origin, request.transmit);
+        assert_eq!(
+            (r.stratum, r.leap, r.ref_id_string(false).as_str()),
+            (1, 1, "RKIK")
+        );
+
+        let mut server = request.clone();
+        server.mode = MODE_SERVER;
+        assert!(reply(&server.to_bytes(), now, now, &options).is_none());
+        assert!(reply(&[0u8; 12], now, now, &options).is_none());
+    }
+
+    #[tokio::test]
+    async fn clients_see_the_injected_offset() {
+        let socket = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let cancel = CancellationToken::new();
+        let server = tokio::spawn(serve(
+            socket,
+            ServeOptions::new().offset_ms(250.0),
+            cancel.clone(),
+        ));
+        let result = query(&format!("127.0.0.1:{port}"), &QueryOptions::default())
+            .await
+            .unwrap();
+        assert!(
+            (result.offset_ms - 250.0).abs() < 50.0,
+            "{}",
+            result.offset_ms
+        );
+        assert_eq!(result.stratum, 2);
+        cancel.cancel();
+        let stats = server.await.unwrap().unwrap();
+        assert_eq!(stats.answered, 1);
+    }
+}
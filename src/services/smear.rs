@@ -0,0 +1,229 @@
+//! Detection of leap-smearing servers.
+//!
+//! Google and AWS do not step their clocks for a leap second: they smear it
+//! linearly over the 24 hours from noon to noon UTC around it, so their time
+//! drifts up to 500 ms from UTC and back. Mixed with servers that step, a
+//! smeared server skews every comparison during that window. Outside it the
+//! two agree and only the server's name gives it away.
+
+use chrono::{DateTime, Datelike, TimeDelta, Utc};
+
+use crate::domain::ntp::{Leap, ProbeResult};
+use crate::services::compare::{CHRONYD_TARGET, LOCAL_TARGET};
+use crate::services::leap::{LeapTable, month_end};
+use crate::services::query::{parse_target, split_scheme};
+
+/// Hosts documented to smear leap seconds.
+const KNOWN_SMEARING: [&str; 6] = [
+    "time.google.com",
+    "time1.google.com",
+    "time2.google.com",
+    "time3.google.com",
+    "time4.google.com",
+    "time.aws.com",
+];
+/// Amazon Time Sync Service, reachable from EC2 instances.
+const AWS_LINK_LOCAL: [&str; 2] = ["169.254.169.123", "fd00:ec2::123"];
+
+/// Whether `target` names a public server that smears leap seconds.
+pub fn is_known_smearing(target: &str) -> bool {
+    let Ok((_, rest)) = split_scheme(target) else {
+        return false;
+    };
+    let Ok(parsed) = parse_target(rest) else {
+        return false;
+    };
+    let host = parsed.host.trim_end_matches('.').to_ascii_lowercase();
+    KNOWN_SMEARING.contains(&host.as_str()) || AWS_LINK_LOCAL.contains(&host.as_str())
+}
+
+/// Offset of a 24 h noon-to-noon smear from UTC at `at` (ms, positive when
+/// the smeared clock is ahead); `None` outside a smear window or when the
+/// table cannot tell.
+pub fn smear_offset_ms(table: &LeapTable, at: DateTime<Utc>) -> Option<f64> {
+    let half = TimeDelta::hours(12);
+    let next = month_end(at);
+    let (boundary, leap) = if next - at <= half {
+        (next, table.scheduled(at)?)
+    } else {
+        let prev = at
+            .date_naive()
+            .with_day(1)
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map_or(at, |t| t.and_utc());
+        if at - prev >= half {
+            return None;
+        }
+        (prev, table.scheduled(prev - TimeDelta::seconds(1))?)
+    };
+    let sign = match leap {
+        Leap::InsertSecond => 1.0,
+        Leap::DeleteSecond => -1.0,
+        _ => return None,
+    };
+    // Fraction of the second smeared so far.
+    let done = (at - (boundary - half)).num_milliseconds() as f64 / 86_400_000.0;
+    // An inserted second: the smeared clock falls behind until UTC repeats a
+    // second at the boundary, then is ahead by what remains to smear.
+    Some(if at < boundary {
+        -sign * done * 1000.0
+    } else {
+        sign * (1.0 - done) * 1000.0
+    })
+}
+
+/// Why a server is flagged as smearing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smear {
+    /// Its offset from the other servers follows the smear curve.
+    Observed { relative_ms: f64, expected_ms: f64 },
+    /// Known to smear, with no smear window open to confirm it.
+    Known,
+}
+
+/// Servers in `results` that look smeared at `now`.
+///
+/// Offsets are taken relative to the median of the servers not known to
+/// smear; a server whose relative offset sits closer to the smear curve than
+/// to zero is flagged. With no window open, or nothing to compare against,
+/// only known smearing hosts are reported.
+pub fn detect_smear(
+    results: &[ProbeResult],
+    table: &LeapTable,
+    now: DateTime<Utc>,
+) -> Vec<(String, Smear)> {
+    let servers: Vec<&ProbeResult> = results
+        .iter()
+        .filter(|r| r.target.name != LOCAL_TARGET && r.target.name != CHRONYD_TARGET)
+        .collect();
+    let mut references: Vec<f64> = servers
+        .iter()
+        .filter(|r| !is_known_smearing(&r.target.name))
+        .map(|r| r.offset_ms)
+        .collect();
+    references.sort_by(f64::total_cmp);
+    let median = match references.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((references[n / 2 - 1] + references[n / 2]) / 2.0),
+        n => Some(references[n / 2]),
+    };
+    let expected = smear_offset_ms(table, now);
+    servers
+        .iter()
+        .filter_map(|r| {
+            let known = is_known_smearing(&r.target.name);
+            match (expected, median) {
+                (Some(expected_ms), Some(median)) => {
+                    let relative_ms = r.offset_ms - median;
+                    ((relative_ms - expected_ms).abs() < expected_ms.abs() / 2.0).then_some(
+                        Smear::Observed {
+                            relative_ms,
+                            expected_ms,
+                        },
+                    )
+                }
+                _ => known.then_some(Smear::Known),
+            }
+            .map(|smear| (r.target.name.clone(), smear))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ntp::Target;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn probe(name: &str, offset_ms: f64) -> ProbeResult {
+        let utc = Utc::now();
+        ProbeResult {
+            target: Target::new(name, "192.0.2.1".parse().unwrap(), 123),
+            offset_ms,
+            rtt_ms: 1.0,
+            stratum: 1,
+            ref_id: "GPS".into(),
+            utc,
+            local: utc.into(),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn known_hosts_are_recognised() {
+        assert!(is_known_smearing("time.google.com"));
+        assert!(is_known_smearing("ntp://Time2.Google.com:123"));
+        assert!(is_known_smearing("169.254.169.123"));
+        assert!(is_known_smearing("[fd00:ec2::123]:123"));
+        assert!(is_known_smearing("fd00:ec2::123"));
+        assert!(!is_known_smearing("pool.ntp.org"));
+        assert!(!is_known_smearing("time.google.com.evil.example"));
+    }
+
+    #[test]
+    fn smear_follows_the_noon_to_noon_curve() {
+        let table = LeapTable::bundled();
+        // The 2016-12-31 insertion.
+        assert_eq!(smear_offset_ms(&table, at("2016-12-31T11:00:00Z")), None);
+        assert_eq!(
+            smear_offset_ms(&table, at("2016-12-31T12:00:00Z")),
+            Some(-0.0)
+        );
+        assert_eq!(
+            smear_offset_ms(&table, at("2016-12-31T18:00:00Z")),
+            Some(-250.0)
+        );
+        assert_eq!(
+            smear_offset_ms(&table, at("2017-01-01T00:00:00Z")),
+            Some(500.0)
+        );
+        assert_eq!(
+            smear_offset_ms(&table, at("2017-01-01T06:00:00Z")),
+            Some(250.0)
+        );
+        assert_eq!(smear_offset_ms(&table, at("2017-01-01T12:00:00Z")), None);
+        // Month boundaries without a leap second.
+        assert_eq!(smear_offset_ms(&table, at("2018-01-01T00:00:00Z")), None);
+        assert_eq!(smear_offset_ms(&table, at("2018-06-30T23:00:00Z")), None);
+    }
+
+    #[test]
+    fn smeared_servers_are_told_from_stepping_ones() {
+        let table = LeapTable::bundled();
+        let results = [
+            probe("a.example", 1.0),
+            probe("b.example", -1.0),
+            probe("c.example", 0.5),
+            probe("time.google.com", -249.0),
+            probe("quiet.example", -240.0),
+        ];
+        let found = detect_smear(&results, &table, at("2016-12-31T18:00:00Z"));
+        let names: Vec<&str> = found.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["time.google.com", "quiet.example"]);
+        assert!(matches!(
+            found[0].1,
+            Smear::Observed { expected_ms, .. } if expected_ms == -250.0
+        ));
+
+        // Outside a window only the name is left.
+        let found = detect_smear(&results, &table, at("2018-03-10T12:00:00Z"));
+        assert_eq!(found, [("time.google.com".to_string(), Smear::Known)]);
+    }
+}
@@ -0,0 +1,365 @@
+//! Surveys of the NTP Pool: collect the members a pool name hands out,
+//! probe each of them a few times and rank them.
+//!
+//! The pool answers every DNS query with a few members picked at random,
+//! so [`expand`] asks repeatedly, also through the numbered names
+//! (`0.pool.ntp.org` to `3.pool.ntp.org`), and keeps the unique addresses.
+//! [`survey`] then probes each member [`SurveyOptions::samples`] times, at
+//! least [`POOL_MIN_INTERVAL`] apart, and ranks members by how far off
+//! they can be: the absolute median offset plus half the median round trip.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use futures::{StreamExt, stream};
+
+use crate::adapters::resolver;
+use crate::domain::ntp::{Origin, ProbeResult};
+use crate::error::RkikError;
+use crate::services::pacing::POOL_MIN_INTERVAL;
+use crate::services::query::{QueryOptions, query};
+use crate::stats::{compute_stats, jitter};
+
+/// How a survey is run.
+#[derive(Debug, Clone)]
+pub struct SurveyOptions {
+    /// Pool zone prefixed to the pool name (`europe` for
+    /// `europe.pool.ntp.org`).
+    pub zone: Option<String>,
+    /// DNS queries of each pool name (default 8).
+    pub rounds: u32,
+    /// Pause between two rounds of DNS queries (default none).
+    pub round_pause: Duration,
+    /// Probes of each member (default 3).
+    pub samples: u32,
+    /// Pause between two probes of a member, at least
+    /// [`POOL_MIN_INTERVAL`] (default 2 s).
+    pub interval: Duration,
+    /// Timeout, address family, port, concurrency and launch stagger of
+    /// the probes.
+    pub query: QueryOptions,
+    /// Look up who runs each member (reverse DNS, AS and country from the
+    /// MaxMind databases of `services::enrich`; feature `enrich`).
+    pub enrich: bool,
+}
+
+impl Default for SurveyOptions {
+    fn default() -> Self {
+        Self {
+            zone: None,
+            rounds: 8,
+            round_pause: Duration::ZERO,
+            samples: 3,
+            interval: Duration::from_secs(2),
+            query: QueryOptions::default(),
+            enrich: false,
+        }
+    }
+}
+
+impl SurveyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn zone(mut self, zone: impl Into<String>) -> Self {
+        self.zone = Some(zone.into());
+        self
+    }
+
+    /// At least 1.
+    pub fn rounds(mut self, rounds: u32) -> Self {
+        self.rounds = rounds.max(1);
+        self
+    }
+
+    pub fn round_pause(mut self, pause: Duration) -> Self {
+        self.round_pause = pause;
+        self
+    }
+
+    /// At least 1.
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Raised to [`POOL_MIN_INTERVAL`].
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(POOL_MIN_INTERVAL);
+        self
+    }
+
+    pub fn query(mut self, query: QueryOptions) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn enrich(mut self, enrich: bool) -> Self {
+        self.enrich = enrich;
+        self
+    }
+}
+
+/// One pool member and what its probes measured.
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct SurveyMember {
+    pub ip: IpAddr,
+    /// Probes answered, out of [`SurveyOptions::samples`].
+    pub answered: u32,
+    /// Median offset of the answered probes (ms).
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub offset_ms: Option<f64>,
+    /// Median round trip of the answered probes (ms).
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub rtt_ms: Option<f64>,
+    /// RFC 5905 jitter, with two answered probes or more (ms).
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub jitter_ms: Option<f64>,
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub stratum: Option<u8>,
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub ref_id: Option<String>,
+    /// Who runs the member, with [`SurveyOptions::enrich`].
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub origin: Option<Origin>,
+    /// Last failure, when no probe was answered.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub error: Option<RkikError>,
+}
+
+impl SurveyMember {
+    /// Worst-case error of the member's clock as seen from here: absolute
+    /// median offset plus half the median round trip. `None` for members
+    /// that never answered.
+    pub fn score(&self) -> Option<f64> {
+        Some(self.offset_ms?.abs() + self.rtt_ms? / 2.0)
+    }
+
+    fn from_probes(ip: IpAddr, probes: &[ProbeResult], error: Option<RkikError>) -> Self {
+        let Some(last) = probes.last() else {
+            return Self {
+                ip,
+                answered: 0,
+                offset_ms: None,
+                rtt_ms: None,
+                jitter_ms: None,
+                stratum: None,
+                ref_id: None,
+                origin: None,
+                error,
+            };
+        };
+        let stats = compute_stats(probes);
+        Self {
+            ip,
+            answered: probes.len() as u32,
+            offset_ms: Some(stats.offset_median),
+            rtt_ms: Some(stats.rtt_median),
+            jitter_ms: jitter(probes),
+            stratum: Some(last.stratum),
+            ref_id: Some(last.ref_id.clone()),
+            origin: last.origin.clone(),
+            error: None,
+        }
+    }
+}
+
+/// Members of a pool, best first.
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Survey {
+    /// Pool name queried, zone included.
+    pub pool: String,
+    /// Members ranked by [`SurveyMember::score`]; members that never
+    /// answered come last.
+    pub members: Vec<SurveyMember>,
+}
+
+/// `pool` within `zone`: `pool_name("pool.ntp.org", Some("europe"))` is
+/// `europe.pool.ntp.org`.
+pub fn pool_name(pool: &str, zone: Option<&str>) -> String {
+    let pool = pool.trim_end_matches('.');
+    match zone.map(|z| z.trim_matches('.')).filter(|z| !z.is_empty()) {
+        Some(zone) => format!("{zone}.{pool}"),
+        None => pool.to_string(),
+    }
+}
+
+/// Every unique address that `pool` and its numbered names resolve to over
+/// `rounds` rounds of queries, in the order first seen.
+///
+/// A round in which every query failed is not an error as long as another
+/// round found members; with no member at all, the last error is returned.
+pub async fn expand(pool: &str, rounds: u32, pause: Duration) -> Result<Vec<IpAddr>, RkikError> {
+    let names: Vec<String> = std::iter::once(pool.to_string())
+        .chain((0..4).map(|n| format!("{n}.{pool}")))
+        .collect();
+    let mut members: Vec<IpAddr> = Vec::new();
+    let mut last_error = None;
+    for round in 0..rounds.max(1) {
+        if round > 0 && !pause.is_zero() {
+            tokio::time::sleep(pause).await;
+        }
+        let answers =
+            futures::future::join_all(names.iter().map(|n| resolver::lookup_all(n))).await;
+        for answer in answers {
+            match answer {
+                Ok(ips) => {
+                    for ip in ips {
+                        if !members.contains(&ip) {
+                            members.push(ip);
+                        }
+                    }
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        tracing::debug!(pool, round, members = members.len(), "pool expanded");
+    }
+    match (members.is_empty(), last_error) {
+        (true, Some(e)) => Err(e),
+        (true, None) => Err(RkikError::Dns(format!("{pool} has no members"))),
+        _ => Ok(members),
+    }
+}
+
+/// Expand `pool` (see [`expand`]), probe every member and rank them.
+///
+/// Only IPv4 members are surveyed, or only IPv6 ones with
+/// [`QueryOptions::ipv6_only`]. Members are probed [`QueryOptions::concurrency`] at a time, launched
+/// at least [`QueryOptions::stagger`] apart. A member that answers with a
+/// Kiss-o'-Death is not probed again.
+pub async fn survey(pool: &str, options: &SurveyOptions) -> Result<Survey, RkikError> {
+    let pool = pool_name(pool, options.zone.as_deref());
+    let ipv6 = options.query.ipv6_only;
+    let ips: Vec<IpAddr> = expand(&pool, options.rounds, options.round_pause)
+        .await?
+        .into_iter()
+        .filter(|ip| ip.is_ipv6() == ipv6)
+        .collect();
+    if ips.is_empty() {
+        let family = if ipv6 { "IPv6" } else { "IPv4" };
+        return Err(RkikError::Dns(format!("{pool} has no {family} members")));
+    }
+    let stagger = options.query.stagger;
+    let mut members: Vec<SurveyMember> = stream::iter(ips.into_iter().enumerate())
+        .map(|(n, ip)| async move {
+            if !stagger.is_zero() {
+                tokio::time::sleep(stagger * n as u32).await;
+            }
+            probe_member(ip, options).await
+        })
+        .buffer_unordered(options.query.concurrency.max(1))
+        .collect()
+        .await;
+    rank(&mut members);
+    Ok(Survey { pool, members })
+}
+
+/// Sort by [`SurveyMember::score`], members that never answered last.
+fn rank(members: &mut [SurveyMember]) {
+    members.sort_by(|a, b| match (a.score(), b.score()) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.ip.cmp(&b.ip),
+    });
+}
+
+async fn probe_member(ip: IpAddr, options: &SurveyOptions) -> SurveyMember {
+    let target = match ip {
+        IpAddr::V6(v6) => format!("[{v6}]:{}", options.query.port),
+        IpAddr::V4(v4) => format!("{v4}:{}", options.query.port),
+    };
+    let query_options = options.query.clone().nts(false).ipv6_only(ip.is_ipv6());
+    let mut probes = Vec::new();
+    let mut error = None;
+    for sample in 0..options.samples {
+        if sample > 0 {
+            tokio::time::sleep(options.interval).await;
+        }
+        match query(&target, &query_options).await {
+            Ok(probe) => probes.push(probe),
+            Err(e) => {
+                let stop = matches!(e, RkikError::RateLimited(_) | RkikError::KissOfDeath(_));
+                error = Some(e);
+                if stop {
+                    break;
+                }
+            }
+        }
+    }
+    #[cfg(feature = "enrich")]
+    if options.enrich {
+        crate::services::enrich::enrich(&mut probes).await;
+    }
+    SurveyMember::from_probes(ip, &probes, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::serve::{ServeOptions, bind, serve};
+    use tokio_util::sync::CancellationToken;
+
+    #[test]
+    fn zones_prefix_the_pool_name() {
+        assert_eq!(
+            pool_name("pool.ntp.org.", Some("europe")),
+            "europe.pool.ntp.org"
+        );
+        assert_eq!(pool_name("pool.ntp.org", Some("")), "pool.ntp.org");
+        assert_eq!(pool_name("pool.ntp.org", None), "pool.ntp.org");
+    }
+
+    #[tokio::test]
+    async fn members_are_probed_on_the_configured_port() {
+        let socket = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let cancel = CancellationToken::new();
+        tokio::spawn(serve(
+            socket,
+            ServeOptions::new().offset_ms(40.0),
+            cancel.clone(),
+        ));
+        let options = SurveyOptions::new()
+            .samples(1)
+            .query(QueryOptions::new().port(port));
+        let member = probe_member("127.0.0.1".parse().unwrap(), &options).await;
+        cancel.cancel();
+        assert_eq!((member.answered, member.stratum), (1, Some(2)));
+        assert!((member.offset_ms.unwrap() - 40.0).abs() < 20.0);
+    }
+
+    #[test]
+    fn members_are_ranked_by_worst_case_error() {
+        let member = |last: u8, offset_ms: Option<f64>, rtt_ms: f64| SurveyMember {
+            ip: IpAddr::from([192, 0, 2, last]),
+            answered: offset_ms.is_some() as u32,
+            offset_ms,
+            rtt_ms: offset_ms.map(|_| rtt_ms),
+            jitter_ms: None,
+            stratum: None,
+            ref_id: None,
+            origin: None,
+            error: None,
+        };
+        let mut members = vec![
+            member(1, None, 0.0),
+            member(2, Some(-3.0), 2.0),
+            member(3, Some(1.0), 20.0),
+            member(4, Some(2.0), 1.0),
+        ];
+        rank(&mut members);
+        let order: Vec<IpAddr> = members.iter().map(|m| m.ip).collect();
+        assert_eq!(
+            order,
+            [4, 2, 3, 1].map(|last| IpAddr::from([192, 0, 2, last]))
+        );
+    }
+}
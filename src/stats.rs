@@ -1,15 +1,365 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
 use crate::domain::ntp::ProbeResult;
 #[cfg(feature = "json")]
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Stats {
     pub count: usize,
     pub offset_avg: f64,
     pub offset_min: f64,
     pub offset_max: f64,
+    /// Sample standard deviation of the offsets (0 with fewer than two samples).
+    pub offset_stddev: f64,
+    pub offset_median: f64,
+    pub offset_p95: f64,
+    pub offset_p99: f64,
     pub rtt_avg: f64,
+    pub rtt_stddev: f64,
+    pub rtt_median: f64,
+    pub rtt_p95: f64,
+    pub rtt_p99: f64,
+    /// RFC 5905 peer jitter, see [`jitter`]. `None` with fewer than two samples.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub jitter: Option<f64>,
+    /// Frequency error of the local clock in ppm (positive when it runs fast), see [`drift`].
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub drift_ppm: Option<f64>,
+    /// Coefficient of determination of the drift fit (1 = perfectly linear).
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub drift_r2: Option<f64>,
+    /// Bound on the error of `offset_median`: the tightest per-probe bound
+    /// plus that probe's distance from the median. `None` when no probe
+    /// carried one.
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub uncertainty: Option<f64>,
+    /// Samples discarded by [`filter_by_rtt`] before computing these statistics.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "is_zero"))]
+    pub rejected: usize,
+}
+
+#[cfg(feature = "json")]
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Values kept verbatim before a [`Quantiles`] switches to log buckets.
+const EXACT_LIMIT: usize = 4096;
+/// Relative accuracy of the log buckets (1%).
+const SKETCH_ALPHA: f64 = 0.01;
+/// Magnitudes below this (ms) share the zero bucket.
+const SKETCH_MIN: f64 = 1e-9;
+
+/// Percentile estimator with bounded memory.
+///
+/// Exact (nearest rank) for the first [`EXACT_LIMIT`] values, then a
+/// DDSketch-style histogram with logarithmic buckets: every quantile is
+/// within [`SKETCH_ALPHA`] relative error and the bucket count only grows
+/// with the range of magnitudes seen, not with the number of samples.
+#[derive(Debug, Clone, Default)]
+struct Quantiles {
+    exact: Vec<f64>,
+    sketch: Option<Sketch>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Sketch {
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zero: u64,
+    count: u64,
+}
+
+impl Sketch {
+    fn gamma() -> f64 {
+        (1.0 + SKETCH_ALPHA) / (1.0 - SKETCH_ALPHA)
+    }
+
+    fn insert(&mut self, v: f64) {
+        self.count += 1;
+        if v.abs() < SKETCH_MIN {
+            self.zero += 1;
+            return;
+        }
+        let index = (v.abs().ln() / Self::gamma().ln()).ceil() as i32;
+        let store = if v > 0.0 {
+            &mut self.positive
+        } else {
+            &mut self.negative
+        };
+        *store.entry(index).or_default() += 1;
+    }
+
+    /// Value of the bucket holding the `rank`-th smallest value (1-based).
+    fn at_rank(&self, rank: u64) -> f64 {
+        let gamma = Self::gamma();
+        let value = |index: i32| 2.0 * gamma.powi(index) / (gamma + 1.0);
+        let mut seen = 0;
+        for (&index, &n) in self.negative.iter().rev() {
+            seen += n;
+            if seen >= rank {
+                return -value(index);
+            }
+        }
+        seen += self.zero;
+        if seen >= rank {
+            return 0.0;
+        }
+        for (&index, &n) in &self.positive {
+            seen += n;
+            if seen >= rank {
+                return value(index);
+            }
+        }
+        0.0
+    }
+}
+
+impl Quantiles {
+    fn insert(&mut self, v: f64) {
+        if let Some(sketch) = self.sketch.as_mut() {
+            sketch.insert(v);
+            return;
+        }
+        self.exact.push(v);
+        if self.exact.len() > EXACT_LIMIT {
+            let mut sketch = Sketch::default();
+            self.exact.drain(..).for_each(|v| sketch.insert(v));
+            self.sketch = Some(sketch);
+        }
+    }
+
+    /// Median, p95 and p99 (nearest rank; the median of an even exact set is
+    /// the mean of the two middle values).
+    fn summary(&self) -> (f64, f64, f64) {
+        if let Some(sketch) = &self.sketch {
+            let n = sketch.count;
+            let rank = |p: f64| ((p / 100.0 * n as f64).ceil() as u64).clamp(1, n);
+            return (
+                sketch.at_rank(rank(50.0)),
+                sketch.at_rank(rank(95.0)),
+                sketch.at_rank(rank(99.0)),
+            );
+        }
+        let mut values = self.exact.clone();
+        let n = values.len();
+        if n == 0 {
+            return (0.0, 0.0, 0.0);
+        }
+        values.sort_by(f64::total_cmp);
+        let median = if n.is_multiple_of(2) {
+            (values[n / 2 - 1] + values[n / 2]) / 2.0
+        } else {
+            values[n / 2]
+        };
+        let rank = |p: f64| values[((p / 100.0 * n as f64).ceil() as usize).clamp(1, n) - 1];
+        (median, rank(95.0), rank(99.0))
+    }
+}
+
+/// Running mean, variance, extremes and percentiles of one quantity (Welford).
+#[derive(Debug, Clone)]
+struct Moments {
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+    quantiles: Quantiles,
+}
+
+impl Default for Moments {
+    fn default() -> Self {
+        Self {
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            quantiles: Quantiles::default(),
+        }
+    }
+}
+
+impl Moments {
+    /// Add the `n`-th value; returns the deviation from the previous mean.
+    fn push(&mut self, n: usize, v: f64) -> f64 {
+        let delta = v - self.mean;
+        self.mean += delta / n as f64;
+        self.m2 += delta * (v - self.mean);
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        self.quantiles.insert(v);
+        delta
+    }
+
+    fn stddev(&self, n: usize) -> f64 {
+        if n > 1 {
+            (self.m2 / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Incremental statistics over a stream of probes.
+///
+/// Memory stays constant however many samples are pushed, so `--infinite`
+/// runs can report statistics without keeping every [`ProbeResult`].
+/// [`compute_stats`] is the batch equivalent.
+#[derive(Debug, Clone, Default)]
+pub struct Accumulator {
+    count: usize,
+    offset: Moments,
+    rtt: Moments,
+    /// Offset and RTT of the lowest-delay sample (jitter reference).
+    best: Option<(f64, f64)>,
+    /// Offset and error bound of the sample with the tightest bound.
+    tightest: Option<(f64, f64)>,
+    /// Regression state: time origin, mean time, Σ(t-t̄)² and Σ(t-t̄)(θ-θ̄).
+    origin: Option<DateTime<Utc>>,
+    time_mean: f64,
+    time_m2: f64,
+    co_moment: f64,
+    rtt_filter: Option<f64>,
+    rejected: usize,
+}
+
+impl Accumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject samples whose RTT exceeds `factor` times the lowest RTT seen
+    /// so far; the streaming counterpart of [`filter_by_rtt`].
+    ///
+    /// Each sample is judged once, on arrival: early samples face the
+    /// minimum of the samples before them, and stay in when a lower RTT
+    /// shows up later, where the batch filter would drop them.
+    pub fn with_rtt_filter(factor: f64) -> Self {
+        Self {
+            rtt_filter: Some(factor),
+            ..Self::default()
+        }
+    }
+
+    /// Add a sample; returns false when the RTT filter rejected it.
+    pub fn push(&mut self, r: &ProbeResult) -> bool {
+        if let (Some(factor), Some((_, best_rtt))) = (self.rtt_filter, self.best)
+            && r.rtt_ms > rtt_limit(best_rtt, factor)
+        {
+            self.rejected += 1;
+            return false;
+        }
+        self.count += 1;
+        let n = self.count;
+        let origin = *self.origin.get_or_insert(r.utc);
+        let t = (r.utc - origin).num_microseconds().unwrap_or(0) as f64 / 1e6;
+        let dt = t - self.time_mean;
+        self.time_mean += dt / n as f64;
+        self.time_m2 += dt * (t - self.time_mean);
+        self.offset.push(n, r.offset_ms);
+        // Uses the updated offset mean, like the Welford M2 update.
+        self.co_moment += dt * (r.offset_ms - self.offset.mean);
+        self.rtt.push(n, r.rtt_ms);
+        if self.best.is_none_or(|(_, rtt)| r.rtt_ms < rtt) {
+            self.best = Some((r.offset_ms, r.rtt_ms));
+        }
+        if let Some(bound) = r.uncertainty_ms
+            && self.tightest.is_none_or(|(_, tightest)| bound < tightest)
+        {
+            self.tightest = Some((r.offset_ms, bound));
+        }
+        true
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Samples rejected by the RTT filter.
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+
+    pub fn stats(&self) -> Stats {
+        let n = self.count;
+        if n == 0 {
+            return Stats {
+                rejected: self.rejected,
+                ..compute_stats(&[])
+            };
+        }
+        let (offset_median, offset_p95, offset_p99) = self.offset.quantiles.summary();
+        let (rtt_median, rtt_p95, rtt_p99) = self.rtt.quantiles.summary();
+        let drift = self.drift();
+        Stats {
+            count: n,
+            offset_avg: self.offset.mean,
+            offset_min: self.offset.min,
+            offset_max: self.offset.max,
+            offset_stddev: self.offset.stddev(n),
+            offset_median,
+            offset_p95,
+            offset_p99,
+            rtt_avg: self.rtt.mean,
+            rtt_stddev: self.rtt.stddev(n),
+            rtt_median,
+            rtt_p95,
+            rtt_p99,
+            jitter: self.jitter(),
+            drift_ppm: drift.map(|d| d.ppm),
+            drift_r2: drift.map(|d| d.r2),
+            // The truth lies within that probe's bound, the median within
+            // its distance from the probe.
+            uncertainty: self
+                .tightest
+                .map(|(offset, bound)| bound + (offset_median - offset).abs()),
+            rejected: self.rejected,
+        }
+    }
+
+    fn jitter(&self) -> Option<f64> {
+        let (best, _) = self.best?;
+        if self.count < 2 {
+            return None;
+        }
+        // Σ(θ - θ₀)² = Σ(θ - θ̄)² + n(θ̄ - θ₀)²
+        let n = self.count as f64;
+        let sum_sq = self.offset.m2 + n * (self.offset.mean - best).powi(2);
+        Some((sum_sq / (n - 1.0)).sqrt())
+    }
+
+    fn drift(&self) -> Option<Drift> {
+        if self.count < 3 || self.time_m2 <= 0.0 {
+            return None;
+        }
+        let slope = self.co_moment / self.time_m2; // ms of offset per second
+        let r2 = if self.offset.m2 > 0.0 {
+            (self.co_moment.powi(2) / (self.time_m2 * self.offset.m2)).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        Some(Drift {
+            ppm: -slope * 1000.0,
+            r2,
+        })
+    }
+}
+
+impl<'a> FromIterator<&'a ProbeResult> for Accumulator {
+    fn from_iter<I: IntoIterator<Item = &'a ProbeResult>>(iter: I) -> Self {
+        let mut acc = Self::new();
+        iter.into_iter().for_each(|r| {
+            acc.push(r);
+        });
+        acc
+    }
 }
 
 pub fn compute_stats(results: &[ProbeResult]) -> Stats {
@@ -19,33 +369,229 @@ pub fn compute_stats(results: &[ProbeResult]) -> Stats {
             offset_avg: 0.0,
             offset_min: 0.0,
             offset_max: 0.0,
+            offset_stddev: 0.0,
+            offset_median: 0.0,
+            offset_p95: 0.0,
+            offset_p99: 0.0,
             rtt_avg: 0.0,
+            rtt_stddev: 0.0,
+            rtt_median: 0.0,
+            rtt_p95: 0.0,
+            rtt_p99: 0.0,
+            jitter: None,
+            drift_ppm: None,
+            drift_r2: None,
+            uncertainty: None,
+            rejected: 0,
         };
     }
+    results.iter().collect::<Accumulator>().stats()
+}
 
-    let count = results.len();
-    let offset_avg = results.iter().map(|r| r.offset_ms).sum::<f64>() / count as f64;
-    let offset_min = results
-        .iter()
-        .map(|r| r.offset_ms)
-        .fold(f64::INFINITY, f64::min);
-    let offset_max = results
+/// Linear fit of offset against time, see [`drift`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drift {
+    /// Frequency error of the local clock in ppm; positive when it runs fast.
+    pub ppm: f64,
+    /// Coefficient of determination of the fit.
+    pub r2: f64,
+}
+
+/// Estimate the local clock frequency error by least-squares regression of
+/// offset over server time.
+///
+/// A clock running fast by 1 ppm loses 1 µs of offset per second. Needs at
+/// least three samples spread over time; the R² tells whether the offsets
+/// actually follow a line or are dominated by network noise.
+pub fn drift(results: &[ProbeResult]) -> Option<Drift> {
+    results.iter().collect::<Accumulator>().drift()
+}
+
+/// Drop samples whose RTT exceeds `factor` times the lowest RTT of the set.
+///
+/// A long round trip usually means the request or reply was queued somewhere,
+/// which skews the offset by up to half the extra delay. Like chrony's sample
+/// filter, keeping only the low-delay samples gives a better offset estimate.
+/// Returns the kept samples (in order) and the number of rejected ones.
+pub fn filter_by_rtt(results: Vec<ProbeResult>, factor: f64) -> (Vec<ProbeResult>, usize) {
+    let Some(min) = results.iter().map(|r| r.rtt_ms).min_by(f64::total_cmp) else {
+        return (results, 0);
+    };
+    let limit = rtt_limit(min, factor);
+    let total = results.len();
+    let kept: Vec<ProbeResult> = results.into_iter().filter(|r| r.rtt_ms <= limit).collect();
+    let rejected = total - kept.len();
+    (kept, rejected)
+}
+
+/// Lowest RTT the filters scale by (ms): a reply within the same tick as its
+/// request measures 0, and would otherwise reject every slower sample.
+const RTT_FLOOR_MS: f64 = 0.1;
+
+/// Highest RTT kept by an RTT filter of `factor` over a minimum of `min_rtt`.
+fn rtt_limit(min_rtt: f64, factor: f64) -> f64 {
+    min_rtt.max(RTT_FLOOR_MS) * factor
+}
+
+/// Peer jitter as defined by the RFC 5905 clock filter: the RMS of the offset
+/// differences to the minimum-delay sample (ms). `None` with fewer than two samples.
+pub fn jitter(results: &[ProbeResult]) -> Option<f64> {
+    results.iter().collect::<Accumulator>().jitter()
+}
+
+/// Outcome of [`welch_t_test`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shift {
+    /// Mean of `after` minus mean of `before`.
+    pub delta: f64,
+    pub t: f64,
+    /// Welch–Satterthwaite degrees of freedom.
+    pub df: f64,
+    /// Two-sided p-value: the chance of a shift at least this large if the
+    /// means were in fact equal.
+    pub p: f64,
+}
+
+/// Welch's t-test for a change of mean between two samples with possibly
+/// different variances, e.g. the offsets of one server before and after a
+/// network change. `None` when either sample has fewer than two values.
+pub fn welch_t_test(before: &[f64], after: &[f64]) -> Option<Shift> {
+    fn mean_var(v: &[f64]) -> (f64, f64) {
+        let n = v.len() as f64;
+        let mean = v.iter().sum::<f64>() / n;
+        let var = v.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        (mean, var)
+    }
+    if before.len() < 2 || after.len() < 2 {
+        return None;
+    }
+    let (m1, v1) = mean_var(before);
+    let (m2, v2) = mean_var(after);
+    let (s1, s2) = (v1 / before.len() as f64, v2 / after.len() as f64);
+    let delta = m2 - m1;
+    let se2 = s1 + s2;
+    if se2 == 0.0 {
+        // Both samples constant: any difference is certain.
+        let p = if delta == 0.0 { 1.0 } else { 0.0 };
+        let t = if delta == 0.0 {
+            0.0
+        } else {
+            delta.signum() * f64::INFINITY
+        };
+        return Some(Shift {
+            delta,
+            t,
+            df: f64::INFINITY,
+            p,
+        });
+    }
+    let t = delta / se2.sqrt();
+    let df = se2.powi(2)
+        / (s1.powi(2) / (before.len() as f64 - 1.0) + s2.powi(2) / (after.len() as f64 - 1.0));
+    let p = incomplete_beta(df / 2.0, 0.5, df / (df + t * t));
+    Some(Shift { delta, t, df, p })
+}
+
+/// Regularized incomplete beta function I_x(a, b), by continued fraction.
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x > (a + 1.0) / (a + b + 2.0) {
+        return 1.0 - incomplete_beta(b, a, 1.0 - x);
+    }
+    // Lentz's algorithm.
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut f = d;
+    for m in 1..300 {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            f *= c * d;
+        }
+        if (c * d - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    front * f / a
+}
+
+/// Natural log of the gamma function (Lanczos approximation).
+fn ln_gamma(x: f64) -> f64 {
+    const G: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = G
         .iter()
-        .map(|r| r.offset_ms)
-        .fold(f64::NEG_INFINITY, f64::max);
-    let rtt_avg = results.iter().map(|r| r.rtt_ms).sum::<f64>() / count as f64;
-    Stats {
-        count,
-        offset_avg,
-        offset_min,
-        offset_max,
-        rtt_avg,
+        .enumerate()
+        .fold(1.000_000_000_190_015, |acc, (i, g)| {
+            acc + g / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Pairwise offset differences between servers.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct DriftMatrix {
+    pub servers: Vec<String>,
+    /// `offsets_ms[i][j]` is the offset of server `i` minus that of server `j`.
+    pub offsets_ms: Vec<Vec<f64>>,
+}
+
+impl DriftMatrix {
+    pub fn new(offsets: &[(String, f64)]) -> Self {
+        Self {
+            servers: offsets.iter().map(|(name, _)| name.clone()).collect(),
+            offsets_ms: offsets
+                .iter()
+                .map(|(_, a)| offsets.iter().map(|(_, b)| a - b).collect())
+                .collect(),
+        }
+    }
+
+    /// Index pairs `(i, j)`, `i < j`, whose absolute difference exceeds `limit` ms.
+    pub fn exceeding(&self, limit: f64) -> Vec<(usize, usize)> {
+        let n = self.servers.len();
+        (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .filter(|&(i, j)| self.offsets_ms[i][j].abs() > limit)
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::ntp::Leap;
 
     #[test]
     fn compute_stats_empty_results() {
@@ -59,4 +605,163 @@ mod tests {
         assert_eq!(stats.offset_max, 0.0);
         assert_eq!(stats.rtt_avg, 0.0);
     }
+
+    fn probe(offset_ms: f64, rtt_ms: f64) -> ProbeResult {
+        let utc = chrono::Utc::now();
+        ProbeResult {
+            target: crate::domain::ntp::Target::new("t", "127.0.0.1".parse().unwrap(), 123),
+            offset_ms,
+            rtt_ms,
+            stratum: 1,
+            ref_id: "LOCL".into(),
+            utc,
+            local: chrono::DateTime::from(utc),
+            timestamp: utc.timestamp(),
+            authenticated: false,
+            dnssec: false,
+            leap: Leap::Normal,
+            health: Default::default(),
+            pause_ms: None,
+            origin: None,
+            timing: None,
+            uncertainty_ms: None,
+            clock_step_ms: None,
+            reference_age_s: None,
+            #[cfg(feature = "nts")]
+            nts_ke_data: None,
+            #[cfg(feature = "nts")]
+            nts_validation: None,
+        }
+    }
+
+    #[test]
+    fn jitter_is_rms_against_min_delay_sample() {
+        assert_eq!(jitter(&[probe(1.0, 1.0)]), None);
+        // equal delays: the first sample is the reference -> sqrt((0 + 9 + 1) / 2)
+        let j = jitter(&[probe(1.0, 1.0), probe(4.0, 1.0), probe(0.0, 1.0)]).unwrap();
+        assert!((j - 5f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn uncertainty_bounds_the_median() {
+        assert_eq!(compute_stats(&[probe(1.0, 1.0)]).uncertainty, None);
+        let bounded = |offset_ms, bound| ProbeResult {
+            uncertainty_ms: Some(bound),
+            clock_step_ms: None,
+            reference_age_s: None,
+            ..probe(offset_ms, 1.0)
+        };
+        // Median 2.0; the tightest probe (0.5 ms) sits 1 ms away from it.
+        let stats = compute_stats(&[bounded(1.0, 0.5), bounded(2.0, 4.0), bounded(3.0, 2.0)]);
+        assert_eq!(stats.uncertainty, Some(1.5));
+    }
+
+    #[test]
+    fn quantiles_are_exact_nearest_rank_for_small_sets() {
+        let mut q = Quantiles::default();
+        (1..=20).for_each(|v| q.insert(f64::from(v)));
+        assert_eq!(q.summary(), (10.5, 19.0, 20.0));
+        let mut one = Quantiles::default();
+        one.insert(3.0);
+        assert_eq!(one.summary(), (3.0, 3.0, 3.0));
+    }
+
+    #[test]
+    fn quantiles_switch_to_bounded_sketch() {
+        let mut q = Quantiles::default();
+        // -5000..5000 ms in 0.1 ms steps
+        (-50_000..50_000).for_each(|v| q.insert(f64::from(v) / 10.0));
+        let sketch = q.sketch.as_ref().unwrap();
+        assert!(q.exact.is_empty());
+        assert!(sketch.positive.len() + sketch.negative.len() < 2000);
+        let (median, p95, p99) = q.summary();
+        assert!(median.abs() < 0.2);
+        assert!((p95 - 4500.0).abs() < 4500.0 * SKETCH_ALPHA);
+        assert!((p99 - 4900.0).abs() < 4900.0 * SKETCH_ALPHA);
+    }
+
+    #[test]
+    fn accumulator_matches_batch_moments() {
+        let samples: Vec<ProbeResult> = (1..=20)
+            .map(|v| probe(f64::from(v), 10.0 + f64::from(v % 3)))
+            .collect();
+        let stats = compute_stats(&samples);
+        assert_eq!(stats.count, 20);
+        assert_eq!(stats.offset_avg, 10.5);
+        assert!((stats.offset_stddev - 35f64.sqrt()).abs() < 1e-12);
+        assert_eq!((stats.offset_min, stats.offset_max), (1.0, 20.0));
+        assert_eq!(stats.offset_median, 10.5);
+        assert_eq!(stats.rtt_p99, 12.0);
+
+        let mut acc = Accumulator::with_rtt_filter(1.1);
+        samples.iter().for_each(|r| {
+            acc.push(r);
+        });
+        // rtt 12 passes against the first floor (11), then fails once rtt 10 is seen
+        assert_eq!(acc.stats().rejected, 6);
+        assert_eq!(acc.count(), 14);
+    }
+
+    #[test]
+    fn rtt_filters_survive_a_zero_rtt_sample() {
+        let samples = vec![probe(1.0, 0.0), probe(2.0, 0.15), probe(3.0, 5.0)];
+        let mut acc = Accumulator::with_rtt_filter(2.0);
+        let kept: Vec<bool> = samples.iter().map(|r| acc.push(r)).collect();
+        assert_eq!(kept, [true, true, false]);
+        let (kept, rejected) = filter_by_rtt(samples, 2.0);
+        assert_eq!((kept.len(), rejected), (2, 1));
+    }
+
+    #[test]
+    fn drift_is_negated_offset_slope_in_ppm() {
+        // offset grows by 0.05 ms per second: the local clock is 50 ppm slow
+        let start = chrono::Utc::now();
+        let samples: Vec<ProbeResult> = (0..5)
+            .map(|i| {
+                let mut p = probe(1.0 + 0.05 * f64::from(i), 1.0);
+                p.utc = start + chrono::Duration::seconds(i64::from(i));
+                p
+            })
+            .collect();
+        let d = drift(&samples).unwrap();
+        assert!((d.ppm + 50.0).abs() < 1e-9);
+        assert!((d.r2 - 1.0).abs() < 1e-12);
+        assert_eq!(drift(&samples[..2]), None);
+    }
+
+    #[test]
+    fn filter_by_rtt_drops_slow_samples() {
+        let samples = vec![probe(1.0, 10.0), probe(9.0, 45.0), probe(2.0, 12.0)];
+        let (kept, rejected) = filter_by_rtt(samples, 2.0);
+        assert_eq!(rejected, 1);
+        let offsets: Vec<f64> = kept.iter().map(|r| r.offset_ms).collect();
+        assert_eq!(offsets, [1.0, 2.0]);
+        assert_eq!(filter_by_rtt(Vec::new(), 2.0).1, 0);
+    }
+
+    #[test]
+    fn drift_matrix_is_antisymmetric_and_flags_pairs() {
+        let m = DriftMatrix::new(&[("a".into(), 1.0), ("b".into(), 4.0), ("c".into(), 1.5)]);
+        assert_eq!(m.offsets_ms[0], [0.0, -3.0, -0.5]);
+        assert_eq!(m.offsets_ms[1][0], 3.0);
+        assert_eq!(m.exceeding(1.0), [(0, 1), (1, 2)]);
+        assert!(m.exceeding(5.0).is_empty());
+    }
+
+    #[test]
+    fn welch_t_test_flags_real_shifts_only() {
+        let before = [1.0, 1.2, 0.9, 1.1, 1.0, 0.8, 1.2, 1.0];
+        let same = [1.1, 0.9, 1.0, 1.2, 0.9, 1.0, 1.1, 1.0];
+        let moved = [2.0, 2.2, 1.9, 2.1, 2.0, 1.8, 2.2, 2.0];
+        let noise = welch_t_test(&before, &same).unwrap();
+        assert!(noise.p > 0.5, "{noise:?}");
+        let shift = welch_t_test(&before, &moved).unwrap();
+        assert!(
+            (shift.delta - 1.0).abs() < 1e-9 && shift.p < 1e-6,
+            "{shift:?}"
+        );
+        // t = 2.0 with 10 degrees of freedom: p = 0.0734 (two-sided).
+        assert!((incomplete_beta(5.0, 0.5, 10.0 / 14.0) - 0.0734).abs() < 1e-4);
+        assert!(welch_t_test(&[1.0], &moved).is_none());
+    }
 }
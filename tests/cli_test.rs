@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use predicates::prelude::PredicateBooleanExt;
 use predicates::str::contains;
 #[cfg(feature = "network-tests")]
 use rkik::adapters::resolver::resolve_ip;
@@ -85,8 +86,879 @@ fn test_preset_add_and_list() {
         .stdout(contains("nightly"));
 }
 
+#[test]
+fn test_bare_rkik_uses_default_targets() {
+    let dir = config_test_dir("default-targets");
+    let mut set = Command::cargo_bin("rkik").unwrap();
+    set.env("RKIK_CONFIG_DIR", dir.to_string_lossy().as_ref())
+        .args(["config", "set", "default-targets", "@missing"])
+        .assert()
+        .success();
+
+    // The configured group is expanded instead of printing help.
+    let mut bare = Command::cargo_bin("rkik").unwrap();
+    bare.env("RKIK_CONFIG_DIR", dir.to_string_lossy().as_ref())
+        .assert()
+        .failure()
+        .stderr(contains("unknown group '@missing'"));
+}
+
+#[test]
+fn test_profile_selects_config_layer() {
+    let dir = config_test_dir("profile");
+    let rkik = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rkik").unwrap();
+        cmd.env("RKIK_CONFIG_DIR", dir.to_string_lossy().as_ref())
+            .env_remove("RKIK_PROFILE")
+            .args(args);
+        cmd
+    };
+    rkik(&["--profile", "prod", "config", "set", "timeout", "3"])
+        .assert()
+        .success();
+    rkik(&["config", "get", "timeout"])
+        .assert()
+        .success()
+        .stdout(contains("<unset>"));
+    rkik(&["config", "get", "timeout"])
+        .env("RKIK_PROFILE", "prod")
+        .assert()
+        .success()
+        .stdout(contains("3.000"));
+    rkik(&["--profile", "staging", "config", "list"])
+        .assert()
+        .failure()
+        .stderr(contains("unknown profile 'staging'"));
+}
+
 fn config_test_dir(name: &str) -> PathBuf {
     let path = std::env::temp_dir().join(format!("rkik-test-{name}"));
     let _ = fs::remove_dir_all(&path);
     path
 }
+
+#[test]
+fn test_fields_rejects_unknown_column() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["--fields", "offset,bogus", "127.0.0.1"])
+        .assert()
+        .failure()
+        .stderr(contains("unknown field 'bogus'"));
+}
+
+#[test]
+fn test_unit_rejects_unknown_value() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "--unit", "fortnights", "127.0.0.1"])
+        .assert()
+        .failure()
+        .stderr(contains("unknown unit 'fortnights'"));
+}
+
+#[test]
+fn test_exporter_requires_targets() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.arg("exporter")
+        .assert()
+        .failure()
+        .stderr(contains("--targets"));
+}
+
+#[test]
+fn test_daemon_reports_missing_config() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["daemon", "--config", "does-not-exist.toml"])
+        .assert()
+        .failure()
+        .stderr(contains("does-not-exist.toml"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ctl_reports_unreachable_daemon() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ctl", "--socket", "/nonexistent/rkik.sock", "status"])
+        .assert()
+        .failure()
+        .stderr(contains("cannot reach rkik daemon"));
+}
+
+#[test]
+fn test_plugin_compare_reports_unknown_on_failure() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "compare",
+        "nonexistent.invalid",
+        "other.invalid",
+        "--plugin",
+        "--timeout",
+        "1",
+    ])
+    .assert()
+    .code(3)
+    .stdout(contains("RKIK UNKNOWN"));
+}
+
+#[test]
+fn test_rtt_filter_rejects_factor_below_one() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "time.google.com", "--rtt-filter", "0.5"])
+        .assert()
+        .failure()
+        .stderr(contains("not a factor >= 1"));
+}
+
+#[test]
+fn test_legacy_reference_must_be_compared() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["-C", "a.invalid", "b.invalid", "--reference", "c.invalid"])
+        .assert()
+        .code(2)
+        .stdout(contains("is not one of the compared servers"));
+}
+
+#[test]
+fn test_assertions_conflict_with_plugin() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "a.invalid", "--plugin", "--assert-max-offset", "5"])
+        .assert()
+        .code(2)
+        .stdout(contains("--plugin cannot be used with --assert-max-offset"));
+}
+
+#[test]
+fn test_unreadable_leap_file_is_rejected() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "ntp",
+        "a.invalid",
+        "--assert-leap",
+        "--leap-file",
+        "/nonexistent/leap-seconds.list",
+    ])
+    .assert()
+    .code(2)
+    .stdout(contains("cannot read /nonexistent/leap-seconds.list"));
+}
+
+#[test]
+fn test_diag_reports_an_unreachable_target() {
+    // Nothing listens on the loopback's discard port.
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "diag",
+        "127.0.0.1:9",
+        "--timeout",
+        "0.5",
+        "--burst",
+        "0",
+        "--no-color",
+    ])
+    .assert()
+    .code(1)
+    .stdout(contains("FAIL reachability"))
+    .stdout(contains("SKIP response"))
+    .stdout(contains("Verdict: FAIL"));
+}
+
+#[test]
+fn test_fast_polling_of_the_pool_is_refused() {
+    // Refused before any query is sent.
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["0.pool.ntp.org", "-c", "3", "-i", "0.2", "--no-color"])
+        .assert()
+        .code(2)
+        .stdout(contains("--allow-fast-pool"));
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "ntp",
+        "pool.ntp.org",
+        "-c",
+        "3",
+        "-i",
+        "0.2",
+        "--min-interval=-1",
+    ])
+    .assert()
+    .code(2)
+    .stderr(contains("--min-interval"));
+}
+
+#[test]
+fn test_time_flags_accept_human_durations() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1:9", "--timeout", "300ms", "--no-color"])
+        .assert()
+        .code(3);
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1", "--timeout", "5x"])
+        .assert()
+        .code(2)
+        .stderr(contains("unknown unit 'x'"));
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1", "-c", "2", "-i", "1h30"])
+        .assert()
+        .code(2)
+        .stderr(contains("missing unit"));
+}
+
+#[test]
+fn test_repeated_verbose_flags_trace_to_stderr_or_log_file() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "ntp",
+        "127.0.0.1:9",
+        "-vv",
+        "--timeout",
+        "300ms",
+        "--no-color",
+    ])
+    .assert()
+    .code(3)
+    .stderr(contains("UDP socket connected"))
+    .stdout(contains("UDP socket connected").not());
+
+    let log = std::env::temp_dir().join(format!("rkik-log-file-{}.log", std::process::id()));
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "ntp",
+        "127.0.0.1:9",
+        "--timeout",
+        "300ms",
+        "--no-color",
+        "--log-file",
+    ])
+    .arg(&log)
+    .assert()
+    .code(3)
+    .stderr(contains("UDP socket connected").not());
+    let captured = std::fs::read_to_string(&log).unwrap();
+    let _ = std::fs::remove_file(&log);
+    assert!(captured.contains("UDP socket connected"));
+}
+
+#[test]
+fn test_highlight_needs_thresholds() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1", "--warning", "50"])
+        .assert()
+        .code(2)
+        .stdout(contains("--warning requires --plugin or --highlight"));
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1", "--highlight"])
+        .assert()
+        .code(2)
+        .stdout(contains("--highlight needs --warning"));
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "ntp",
+        "127.0.0.1",
+        "--highlight",
+        "--critical",
+        "5",
+        "--plugin",
+    ])
+    .assert()
+    .code(2)
+    .stdout(contains("--plugin cannot be used with --highlight"));
+}
+
+#[test]
+fn test_quiet_keeps_errors_and_silent_drops_everything() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1:9", "-c", "2", "-q", "--timeout", "300ms"])
+        .args(["--no-color"])
+        .assert()
+        .code(3)
+        .stdout(contains("Error:"));
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1:9", "--silent", "--timeout", "300ms"])
+        .assert()
+        .code(3)
+        .stdout("")
+        .stderr("");
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1", "--silent", "-v"])
+        .assert()
+        .code(2)
+        .stderr(contains("cannot be used with"));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_json_errors_go_to_stderr() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1:9", "--timeout", "300ms", "-j"])
+        .assert()
+        .code(3)
+        .stdout("")
+        .stderr(contains(r#""error":{"kind":"refused""#));
+}
+
+#[test]
+fn test_http_reports_refused_connections() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["http", "http://127.0.0.1:9", "--timeout", "1s"])
+        .assert()
+        .code(3)
+        .stderr(contains("refused"));
+
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["http", "ftp://127.0.0.1"])
+        .assert()
+        .failure()
+        .stderr(contains("unsupported scheme"));
+}
+
+#[test]
+fn test_survey_lists_silent_members_last() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "survey",
+            "127.0.0.1",
+            "--rounds",
+            "1",
+            "--samples",
+            "1",
+            "--timeout",
+            "500ms",
+            "--no-color",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Survey: 127.0.0.1 (1 members, 0 answered)"))
+        .stdout(contains("refused"));
+
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["survey", "--samples", "0"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_compare_recommends_servers_and_emits_config() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "compare",
+            "mock://a?offset=1ms&rtt=2ms",
+            "mock://b?offset=1.2ms&rtt=30ms",
+            "mock://c?offset=400ms&rtt=1ms",
+            "--recommend",
+            "2",
+            "--emit-config",
+            "chrony",
+            "--no-color",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("1. mock://a"))
+        .stdout(contains("2. mock://b"))
+        .stdout(contains("3. mock://c").not())
+        .stdout(contains("# chrony.conf"))
+        .stdout(contains("server a").not());
+}
+
+#[test]
+fn test_compare_reports_divergence_events() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "compare",
+            "mock://a",
+            "mock://b",
+            "mock://c?ramp=10ms",
+            "-c",
+            "4",
+            "-i",
+            "0",
+            "--diverge-threshold",
+            "15",
+            "-f",
+            "json-short",
+        ])
+        .assert()
+        .success()
+        .stdout(contains(
+            r#""event":"diverged","server":"mock://c?ramp=10ms""#,
+        ))
+        .stdout(contains(r#""deviation_ms":20.0"#))
+        .stdout(contains("recovered").not());
+}
+
+#[test]
+fn test_asymmetry_bounds_the_offset_across_paths() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "asymmetry",
+            "mock://a?offset=1ms&rtt=4ms",
+            "mock://b?offset=2ms&rtt=2ms",
+            "-c",
+            "1",
+            "--no-color",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("asymmetries differ by at least 2.000 ms"))
+        .stdout(contains("True offset: 2.000 ms ± 1.000 ms"));
+}
+
+#[test]
+fn test_offset_is_reported_with_its_uncertainty() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["mock://a?offset=1ms&rtt=4ms", "--no-color"])
+        .assert()
+        .success()
+        .stdout(contains("Clock Offset: 1.000 ms ± 2.000 ms"));
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["mock://a?rtt=4ms", "-j"])
+        .assert()
+        .success()
+        .stdout(contains("\"uncertainty_ms\":2.0"));
+}
+
+#[test]
+fn test_check_clock_reports_frequency_and_steps() {
+    // The mock server gains 10 ms between the probes, 1 s apart: the local
+    // clock looks 1% slow.
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "check-clock",
+            "mock://a?ramp=10ms",
+            "--window",
+            "1s",
+            "--no-color",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("(local clock runs slow)"))
+        .stdout(contains("Backward steps: none"));
+}
+
+#[test]
+fn test_require_sync_refuses_unsynchronized_servers() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["mock://a?leap=3", "--require-sync"])
+        .assert()
+        .code(6)
+        .stdout(contains("unsynchronized: leap indicator 3"));
+    // A compare carries on without the server, then exits with the same code.
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "compare",
+            "mock://a",
+            "mock://b",
+            "mock://c?stratum=16",
+            "--require-sync",
+            "--no-color",
+        ])
+        .assert()
+        .code(6)
+        .stdout(contains("mock://b"))
+        .stdout(contains("unsynchronized: stratum 16"));
+}
+
+#[test]
+fn test_ports_probes_each_port_of_the_target() {
+    let mut server = std::process::Command::new(assert_cmd::cargo::cargo_bin("rkik"))
+        .args(["serve", "--bind", "127.0.0.1", "--port", "11931"])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let out = Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "ntp",
+            "127.0.0.1",
+            "--ports",
+            "11931,11932",
+            "--timeout",
+            "1s",
+            "--no-color",
+        ])
+        .output()
+        .unwrap();
+    server.kill().ok();
+    server.wait().ok();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{stdout}");
+    assert!(stdout.contains("Server: 127.0.0.1:11931"), "{stdout}");
+    assert!(stdout.contains("Error: 127.0.0.1:11932"), "{stdout}");
+
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["ntp", "mock://lab", "--ports", "123,1123"])
+        .assert()
+        .code(2)
+        .stdout(contains("has no port"));
+}
+
+#[cfg(feature = "nts")]
+#[test]
+fn test_nts_ke_reports_an_unreachable_server() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["nts-ke", "127.0.0.1", "--nts-port", "1", "--no-color"])
+        .assert()
+        .failure()
+        .stderr(contains("Error: 127.0.0.1 - refused"));
+}
+
+#[test]
+fn test_serve_answers_with_the_injected_offset() {
+    let mut server = std::process::Command::new(assert_cmd::cargo::cargo_bin("rkik"))
+        .args([
+            "serve",
+            "--bind",
+            "127.0.0.1",
+            "--port",
+            "11923",
+            "--offset",
+            "3s",
+            "--stratum",
+            "4",
+        ])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let out = Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "ntp",
+            "127.0.0.1:11923",
+            "-v",
+            "--timeout",
+            "2s",
+            "--no-color",
+        ])
+        .output()
+        .unwrap();
+    server.kill().ok();
+    server.wait().ok();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{stdout}");
+    let offset: f64 = stdout
+        .lines()
+        .find_map(|l| l.strip_prefix("Clock Offset: "))
+        .and_then(|v| v.split(" ms").next()?.parse().ok())
+        .unwrap();
+    assert!((offset - 3000.0).abs() < 100.0, "{stdout}");
+    assert!(stdout.contains(" ms ± "), "{stdout}");
+    assert!(stdout.contains("Stratum: 4"), "{stdout}");
+}
+
+#[test]
+fn test_mock_targets_run_offline() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "ntp",
+            "mock://lab?offset=12.5ms&stratum=3",
+            "-v",
+            "--no-color",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Clock Offset: 12.500 ms"))
+        .stdout(contains("Stratum: 3"));
+
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "ntp",
+            "mock://lab?fail=timeout&after=1",
+            "-c",
+            "2",
+            "-i",
+            "0",
+        ])
+        .assert()
+        .code(3);
+
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["ntp", "mock://lab?colour=red"])
+        .assert()
+        .failure()
+        .stdout(contains("unknown mock parameter 'colour'"));
+}
+
+#[test]
+fn test_auto_interval_is_validated() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1", "-c", "3", "-i", "fast"])
+        .assert()
+        .code(2)
+        .stderr(contains("'auto'"));
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["compare", "127.0.0.1", "127.0.0.2", "-c", "3", "-i", "auto"])
+        .assert()
+        .code(2)
+        .stdout(contains("--interval auto cannot be used with --compare"));
+}
+
+#[test]
+fn test_duration_is_validated() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1", "--duration", "5x"])
+        .assert()
+        .code(2)
+        .stderr(contains("unknown unit 'x'"));
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["127.0.0.1", "--duration", "1h", "--plugin"])
+        .assert()
+        .code(2)
+        .stdout(contains("--plugin cannot be used with --duration"));
+}
+
+#[test]
+fn test_interval_jitter_is_a_share_of_the_interval() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["ntp", "127.0.0.1", "-c", "2", "--interval-jitter", "150%"])
+        .assert()
+        .code(2)
+        .stderr(contains("between 0% and 100%"));
+}
+
+#[test]
+fn test_max_failures_gives_up_with_exit_code_5() {
+    // Nothing listens on the loopback's discard port.
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "ntp",
+        "127.0.0.1:9",
+        "-c",
+        "10",
+        "-i",
+        "0.1",
+        "--timeout",
+        "0.5",
+        "--max-failures",
+        "2",
+    ])
+    .assert()
+    .code(5)
+    .stderr(contains(
+        "giving up on 127.0.0.1:9 after 2 consecutive failures",
+    ));
+
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args([
+        "127.0.0.1:9",
+        "--infinite",
+        "--timeout",
+        "0.5",
+        "--fail-fast",
+    ])
+    .assert()
+    .code(5);
+}
+
+#[test]
+fn test_targets_file_without_targets_is_rejected() {
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.args(["compare", "--targets-file", "-"])
+        .write_stdin("# nothing here\n\n")
+        .assert()
+        .failure()
+        .stderr(contains("no targets in stdin"));
+}
+
+#[test]
+fn test_compare_rejects_unknown_group() {
+    let dir = config_test_dir("groups");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("config.toml"),
+        "[groups]\nprod = [\"ntp1.invalid\", \"ntp2.invalid\"]\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.env("RKIK_CONFIG_DIR", dir.to_string_lossy().as_ref())
+        .args(["compare", "@staging"])
+        .assert()
+        .failure()
+        .stderr(contains("unknown group '@staging'"));
+}
+
+#[test]
+fn test_config_validate_reports_problems() {
+    let dir = config_test_dir("validate");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("config.toml"),
+        "[defaults]\ntimeout = -1\n[presets.broken]\nargs = [\"compare\", \"--no-such-flag\"]\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("rkik").unwrap();
+    cmd.env("RKIK_CONFIG_DIR", dir.to_string_lossy().as_ref())
+        .args(["config", "validate"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "defaults.timeout: expected a positive number of seconds",
+        ))
+        .stderr(contains(
+            "presets.broken: unexpected argument '--no-such-flag'",
+        ));
+}
+
+#[test]
+fn test_preset_run_requires_placeholder_values() {
+    let dir = config_test_dir("preset-placeholders");
+    let env = dir.to_string_lossy().to_string();
+    let mut add = Command::cargo_bin("rkik").unwrap();
+    add.env("RKIK_CONFIG_DIR", &env)
+        .args(["preset", "add", "base", "--", "ntp", "{target}"])
+        .assert()
+        .success();
+    let mut add = Command::cargo_bin("rkik").unwrap();
+    add.env("RKIK_CONFIG_DIR", &env)
+        .args([
+            "preset",
+            "add",
+            "nightly",
+            "--extends",
+            "base",
+            "--",
+            "-c",
+            "5",
+        ])
+        .assert()
+        .success();
+
+    let mut run = Command::cargo_bin("rkik").unwrap();
+    run.env("RKIK_CONFIG_DIR", &env)
+        .args(["preset", "run", "nightly"])
+        .assert()
+        .failure()
+        .stderr(contains("missing value for --set target=..."));
+}
+
+#[test]
+fn test_record_and_replay_session() {
+    let dir = config_test_dir("replay");
+    fs::create_dir_all(&dir).unwrap();
+    let session = dir.join("run.rkik");
+    // Nothing listens on port 9 of the loopback: the failure is recorded.
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["ntp", "127.0.0.1:9", "--timeout", "0.5", "--record"])
+        .arg(&session)
+        .assert()
+        .failure();
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .arg("replay")
+        .arg(&session)
+        .assert()
+        .success()
+        .stdout(contains("127.0.0.1:9"));
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["replay", "--format", "report"])
+        .arg(&session)
+        .assert()
+        .success()
+        .stdout(contains("Session: no successful probes, 1 failures"));
+
+    fs::write(&session, "not a session").unwrap();
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .arg("replay")
+        .arg(&session)
+        .assert()
+        .failure()
+        .stderr(contains("not a valid rkik session"));
+}
+
+#[test]
+fn test_pcap_captures_the_request() {
+    let dir = config_test_dir("pcap");
+    fs::create_dir_all(&dir).unwrap();
+    let capture = dir.join("ntp.pcap");
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["ntp", "127.0.0.1:9", "--timeout", "0.5", "--pcap"])
+        .arg(&capture)
+        .assert()
+        .failure();
+    // pcap header, then one record: 16-byte header, IPv4, UDP, 48-byte NTP.
+    let bytes = fs::read(&capture).unwrap();
+    assert_eq!(&bytes[..4], &0xa1b2_3c4du32.to_le_bytes());
+    assert_eq!(bytes.len(), 24 + 16 + 20 + 8 + 48);
+    assert_eq!(bytes[24 + 16 + 20 + 8], 0x23);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_store_and_history_stats() {
+    let dir = config_test_dir("history");
+    fs::create_dir_all(&dir).unwrap();
+    let url = format!("sqlite://{}", dir.join("history.db").display());
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["ntp", "127.0.0.1:9", "--timeout", "0.5", "--store", &url])
+        .assert()
+        .failure();
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["history", "127.0.0.1:9", "--stats", "--store", &url])
+        .assert()
+        .success()
+        .stdout(contains("1 failed (availability 0.0%)"));
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["history", "--store", "postgres://db"])
+        .assert()
+        .failure()
+        .stderr(contains("use sqlite://PATH"));
+}
+
+#[test]
+fn test_local_reports_unreachable_chronyd() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args(["local", "--chronyd", "/nonexistent/chronyd.sock"])
+        .assert()
+        .failure()
+        .stderr(contains("chronyd not reachable"));
+}
+
+#[cfg(all(feature = "sync", target_os = "linux"))]
+#[test]
+fn test_local_kernel_section_without_daemon() {
+    Command::cargo_bin("rkik")
+        .unwrap()
+        .args([
+            "local",
+            "--kernel",
+            "--chronyd",
+            "/nonexistent/chronyd.sock",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Kernel Clock:"))
+        .stderr(contains("Warning:"));
+}
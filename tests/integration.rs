@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 #[tokio::test]
+#[allow(deprecated)]
 async fn test_query_invalid_host() {
     let err = rkik::query_one(
         "no.such.domain.example",
@@ -14,3 +15,37 @@ async fn test_query_invalid_host() {
     assert!(err.is_dns());
     assert_eq!(err.target(), Some("no.such.domain.example"));
 }
+
+#[tokio::test]
+async fn test_query_options_invalid_host() {
+    let options = rkik::QueryOptions::new()
+        .timeout(Duration::from_secs(1))
+        .retries(2);
+    let err = rkik::query("no.such.domain.example", &options)
+        .await
+        .expect_err("expected error");
+    // DNS failures are not retried.
+    assert!(err.is_dns());
+    assert_eq!(err.target(), Some("no.such.domain.example"));
+}
+
+#[tokio::test]
+async fn test_mock_targets_need_no_network() {
+    let options = rkik::QueryOptions::new();
+    let target = "mock://integration?offset=-3ms&ramp=2ms&stratum=1";
+    let first = rkik::query(target, &options).await.unwrap();
+    let second = rkik::query(target, &options).await.unwrap();
+    assert_eq!((first.offset_ms, second.offset_ms), (-3.0, -1.0));
+    assert_eq!((first.stratum, first.ref_id.as_str()), (1, "MOCK"));
+
+    let targets = vec![
+        "mock://integration-a?offset=1ms".to_string(),
+        "mock://integration-b?fail=refused".to_string(),
+        "mock://integration-c?offset=2ms".to_string(),
+    ];
+    let outcome = rkik::compare(&targets, &options).await.unwrap();
+    assert_eq!(outcome.results().count(), 2);
+    let (name, err) = outcome.failures().next().unwrap();
+    assert_eq!(name, "mock://integration-b?fail=refused");
+    assert_eq!(err.kind(), "refused");
+}